@@ -10,9 +10,16 @@ const CHUNK_SIZE: usize = 1024;
 struct Input {
     pub name: String,
     pub length: usize,
+    pub data: Vec<u8>,
     pub chunks: Vec<Vec<u8>>,
 }
 
+impl Input {
+    pub fn chunks_of(&self, chunk_size: usize) -> Vec<Vec<u8>> {
+        self.data.chunks(chunk_size).map(|c| c.to_owned()).collect()
+    }
+}
+
 impl Debug for Input {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "{}", self.name)
@@ -38,6 +45,7 @@ lazy_static! {
                     name: path.file_name().unwrap().to_string_lossy().to_string(),
                     length: data.len(),
                     chunks: data.chunks(CHUNK_SIZE).map(|c| c.to_owned()).collect(),
+                    data,
                 }
             })
             .collect()
@@ -102,7 +110,10 @@ criterion_group!(
     benches,
     cases::parsing::group,
     cases::rewriting::group,
-    cases::selector_matching::group
+    cases::selector_matching::group,
+    cases::configurations::group,
+    cases::selector_count::group,
+    cases::chunk_size::group
 );
 
 criterion_main!(benches);
@@ -0,0 +1,41 @@
+use criterion::*;
+use lol_html::*;
+
+const CHUNK_SIZES: &[usize] = &[64, 256, 1024, 4096, 16 * 1024];
+
+fn settings() -> Settings<'static, 'static> {
+    Settings {
+        element_content_handlers: vec![
+            element!("a[href]", noop_handler!()),
+            element!("img", noop_handler!()),
+            text!("title", noop_handler!()),
+        ],
+        ..Settings::default()
+    }
+}
+
+pub fn group(c: &mut Criterion) {
+    let mut g = c.benchmark_group("Chunk size");
+
+    for input in crate::INPUTS.iter() {
+        g.throughput(Throughput::Bytes(input.length as u64));
+
+        for &chunk_size in CHUNK_SIZES {
+            g.bench_with_input(
+                BenchmarkId::new(format!("{} bytes", chunk_size), &input.name),
+                &input.chunks_of(chunk_size),
+                create_runner!(settings()),
+            );
+        }
+
+        // NOTE: the whole input in a single chunk, i.e. the rewriter never buffers
+        // content between the chunks.
+        g.bench_with_input(
+            BenchmarkId::new("Single chunk", &input.name),
+            &vec![input.data.clone()],
+            create_runner!(settings()),
+        );
+    }
+
+    g.finish();
+}
@@ -0,0 +1,65 @@
+use lol_html::html_content::*;
+use lol_html::*;
+
+define_group!(
+    "Configurations",
+    [
+        ("Parse only", Settings::default()),
+        (
+            "Element handlers only",
+            Settings {
+                element_content_handlers: vec![element!("a[href]", |el| {
+                    el.set_attribute("rel", "noopener").unwrap();
+
+                    Ok(())
+                })],
+                ..Settings::default()
+            }
+        ),
+        (
+            "Element and document handlers",
+            // NOTE: the document handler disables the rewriter specialisation
+            // for element handlers, but doesn't capture any content by itself.
+            Settings {
+                element_content_handlers: vec![element!("a[href]", |el| {
+                    el.set_attribute("rel", "noopener").unwrap();
+
+                    Ok(())
+                })],
+                document_content_handlers: vec![end!(|end| {
+                    end.append("<!-- end -->", ContentType::Html);
+
+                    Ok(())
+                })],
+                ..Settings::default()
+            }
+        ),
+        (
+            "Output coalescing",
+            Settings {
+                element_content_handlers: vec![element!("a[href]", |el| {
+                    el.set_attribute("rel", "noopener").unwrap();
+
+                    Ok(())
+                })],
+                output_buffer_watermark: Some(16 * 1024),
+                ..Settings::default()
+            }
+        ),
+        (
+            "Parsing buffer compaction watermark",
+            Settings {
+                element_content_handlers: vec![element!("a[href]", |el| {
+                    el.set_attribute("rel", "noopener").unwrap();
+
+                    Ok(())
+                })],
+                memory_settings: MemorySettings {
+                    parsing_buffer_compaction_watermark: 16 * 1024,
+                    ..MemorySettings::default()
+                },
+                ..Settings::default()
+            }
+        )
+    ]
+);
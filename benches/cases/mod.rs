@@ -1,3 +1,6 @@
+pub mod chunk_size;
+pub mod configurations;
 pub mod parsing;
 pub mod rewriting;
+pub mod selector_count;
 pub mod selector_matching;
@@ -0,0 +1,40 @@
+use criterion::*;
+use lol_html::*;
+
+const SELECTOR_COUNTS: &[usize] = &[1, 10, 100];
+
+fn settings(selector_count: usize) -> Settings<'static, 'static> {
+    // NOTE: mix of the selector kinds that are commonly used in the rewriting rules,
+    // only a few of them actually match anything in the inputs.
+    let element_content_handlers = (0..selector_count)
+        .map(|i| match i % 4 {
+            0 => element!(format!("div.c{} a[href]", i), noop_handler!()),
+            1 => element!(format!("#id{}", i), noop_handler!()),
+            2 => element!(format!("[data-n=\"{}\"]", i), noop_handler!()),
+            _ => element!(format!("ul > li:nth-child({}) > a", i), noop_handler!()),
+        })
+        .collect();
+
+    Settings {
+        element_content_handlers,
+        ..Settings::default()
+    }
+}
+
+pub fn group(c: &mut Criterion) {
+    let mut g = c.benchmark_group("Selector count");
+
+    for input in crate::INPUTS.iter() {
+        g.throughput(Throughput::Bytes(input.length as u64));
+
+        for &selector_count in SELECTOR_COUNTS {
+            g.bench_with_input(
+                BenchmarkId::new(format!("{} selectors", selector_count), &input.name),
+                &input.chunks,
+                create_runner!(settings(selector_count)),
+            );
+        }
+    }
+
+    g.finish();
+}
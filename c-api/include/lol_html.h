@@ -59,6 +59,17 @@ typedef struct {
     size_t len;
 } lol_html_text_chunk_content_t;
 
+// A type of parsed text. Defines the parsing context of the text chunk,
+// e.g. text of `<script>` elements has LOL_HTML_TEXT_TYPE_SCRIPT_DATA type.
+typedef enum {
+    LOL_HTML_TEXT_TYPE_PLAINTEXT,
+    LOL_HTML_TEXT_TYPE_RCDATA,
+    LOL_HTML_TEXT_TYPE_RAWTEXT,
+    LOL_HTML_TEXT_TYPE_SCRIPT_DATA,
+    LOL_HTML_TEXT_TYPE_DATA,
+    LOL_HTML_TEXT_TYPE_CDATA_SECTION
+} lol_html_text_type_t;
+
 // Utilities
 //---------------------------------------------------------------------
 
@@ -131,6 +142,21 @@ void lol_html_selector_free(lol_html_selector_t *selector);
 
 // Rewriter builder
 //---------------------------------------------------------------------
+//
+// Thread safety
+//
+// A builder can be frozen with `lol_html_rewriter_builder_freeze` once
+// it's configured. Frozen builder is immutable: functions that modify
+// the builder abort the thread if they're called with a frozen builder.
+// It's safe to call `lol_html_rewriter_build` with the same frozen builder
+// from multiple threads simultaneously, so multi-threaded applications can
+// share a single builder between all the workers. A builder that is not
+// frozen should be used by a single thread at a time.
+//
+// Note that handlers of the rewriters constructed on different threads get
+// the same user data pointers, so the user data should be safe to access
+// concurrently. The builder should be freed only after all the threads are
+// done constructing rewriters from it.
 
 // Adds document-level content handlers to the builder.
 //
@@ -195,6 +221,41 @@ int lol_html_rewriter_builder_add_element_content_handlers(
     void *text_handler_user_data
 );
 
+// Makes rewriters constructed from the builder buffer the whole text content
+// of `<script>` and `<style>` elements and deliver it to text handlers as a
+// single chunk, so the content can be matched and transformed as a whole.
+//
+// The buffered text counts towards the memory limit of the rewriter.
+// Disabled by default.
+void lol_html_rewriter_builder_buffer_script_and_style_text(
+    lol_html_rewriter_builder_t *builder,
+    bool enable
+);
+
+// Sets the character encoding of the output of rewriters constructed from
+// the builder, e.g. "utf-8" to produce UTF-8 output regardless of the
+// document's charset. By default, the output has the same encoding as
+// the input.
+//
+// The encoding label is validated on the rewriter construction, so
+// `lol_html_rewriter_build` returns NULL for unknown or non-ASCII-compatible
+// encodings.
+//
+// Returns 0 in case of success and -1 otherwise. The actual error message
+// can be obtained using `lol_html_take_last_error` function.
+int lol_html_rewriter_builder_set_output_encoding(
+    lol_html_rewriter_builder_t *builder,
+    const char *encoding,
+    size_t encoding_len
+);
+
+// Freezes the builder, making it immutable and safe to share between
+// threads for the concurrent construction of rewriters.
+void lol_html_rewriter_builder_freeze(lol_html_rewriter_builder_t *builder);
+
+// Returns `true` if the builder has been frozen.
+bool lol_html_rewriter_builder_is_frozen(const lol_html_rewriter_builder_t *builder);
+
 // Frees the memory held by the builder.
 //
 // Note that builder can be freed before any rewriters constructed from
@@ -217,11 +278,23 @@ typedef struct {
     // `lol_html_rewriter_write` and `lol_html_rewriter_end` will return an error
     // if this limit is exceeded.
     size_t max_allowed_memory_usage;
+    // Number of already parsed bytes that can be kept at the beginning of
+    // the parsing buffer before the unparsed bytes are moved to its beginning.
+    //
+    // Can be set to 0. In this case the unparsed bytes are moved on each
+    // written chunk.
+    size_t parsing_buffer_compaction_watermark;
+    // Capacity of the parsing buffer above which it's shrunk back to the
+    // preallocated size once it's no longer in use.
+    //
+    // Can be set to 0. In this case the buffer is never shrunk.
+    size_t parsing_buffer_shrink_watermark;
 } lol_html_memory_settings_t;
 
 // Builds HTML-rewriter out of the provided builder. Can be called
 // multiple times to construct different rewriters from the same
-// builder.
+// builder. Can be called concurrently from multiple threads if the
+// builder is frozen.
 //
 // `output_sink` receives a zero-length chunk on the end of the output.
 //
@@ -234,7 +307,7 @@ typedef struct {
 //
 // In case of an error the function returns a NULL pointer.
 lol_html_rewriter_t *lol_html_rewriter_build(
-    lol_html_rewriter_builder_t *builder,
+    const lol_html_rewriter_builder_t *builder,
     const char *encoding,
     size_t encoding_len,
     lol_html_memory_settings_t memory_settings,
@@ -286,6 +359,53 @@ lol_html_str_t *lol_html_doctype_public_id_get(const lol_html_doctype_t *doctype
 // Returns NULL if the doctype doesn't have a SYSTEM identifier.
 lol_html_str_t *lol_html_doctype_system_id_get(const lol_html_doctype_t *doctype);
 
+// Sets doctype's name.
+//
+// Name should be a valid non-empty UTF8-string without whitespace and `>`
+// characters.
+//
+// Returns 0 in case of success and -1 otherwise. The actual error message
+// can be obtained using `lol_html_take_last_error` function.
+int lol_html_doctype_name_set(
+    lol_html_doctype_t *doctype,
+    const char *name,
+    size_t name_len
+);
+
+// Sets doctype's PUBLIC identifier. The identifier is removed if NULL is
+// passed as `public_id`.
+//
+// Identifier should be a valid UTF8-string that doesn't contain the `>`
+// character or both `"` and `'` characters.
+//
+// Returns 0 in case of success and -1 otherwise. The actual error message
+// can be obtained using `lol_html_take_last_error` function.
+int lol_html_doctype_public_id_set(
+    lol_html_doctype_t *doctype,
+    const char *public_id,
+    size_t public_id_len
+);
+
+// Sets doctype's SYSTEM identifier. The identifier is removed if NULL is
+// passed as `system_id`.
+//
+// Identifier should be a valid UTF8-string that doesn't contain the `>`
+// character or both `"` and `'` characters.
+//
+// Returns 0 in case of success and -1 otherwise. The actual error message
+// can be obtained using `lol_html_take_last_error` function.
+int lol_html_doctype_system_id_set(
+    lol_html_doctype_t *doctype,
+    const char *system_id,
+    size_t system_id_len
+);
+
+// Removes the doctype.
+void lol_html_doctype_remove(lol_html_doctype_t *doctype);
+
+// Returns `true` if the doctype has been removed.
+bool lol_html_doctype_is_removed(const lol_html_doctype_t *doctype);
+
 // Attaches custom user data to the doctype.
 //
 // The same doctype can be passed to multiple handlers if it has been
@@ -393,6 +513,9 @@ lol_html_text_chunk_content_t lol_html_text_chunk_content_get(
 // Returns `true` if the chunk is last in the current text node.
 bool lol_html_text_chunk_is_last_in_text_node(const lol_html_text_chunk_t *chunk);
 
+// Returns the type of the text chunk's content.
+lol_html_text_type_t lol_html_text_chunk_text_type(const lol_html_text_chunk_t *chunk);
+
 // Inserts the content string before the text chunk either as raw text or as HTML.
 //
 // Content should be a valid UTF8-string.
@@ -5,7 +5,7 @@ use std::slice::Iter;
 pub extern "C" fn lol_html_element_tag_name_get(element: *const Element) -> Str {
     let element = to_ref!(element);
 
-    Str::new(element.tag_name())
+    Str::new(element.tag_name().to_string())
 }
 
 #[no_mangle]
@@ -64,14 +64,14 @@ pub extern "C" fn lol_html_attributes_iterator_free(iterator: *mut Iter<Attribut
 pub extern "C" fn lol_html_attribute_name_get(attribute: *const Attribute) -> Str {
     let attribute = to_ref!(attribute);
 
-    Str::new(attribute.name())
+    Str::new(attribute.name().to_string())
 }
 
 #[no_mangle]
 pub extern "C" fn lol_html_attribute_value_get(attribute: *const Attribute) -> Str {
     let attribute = to_ref!(attribute);
 
-    Str::new(attribute.value())
+    Str::new(attribute.value().to_string())
 }
 
 #[no_mangle]
@@ -32,7 +32,7 @@ impl OutputSink for ExternOutputSink {
 
 #[no_mangle]
 pub extern "C" fn lol_html_rewriter_build(
-    builder: *mut HtmlRewriterBuilder,
+    builder: *const HtmlRewriterBuilder,
     encoding: *const c_char,
     encoding_len: size_t,
     memory_settings: MemorySettings,
@@ -46,9 +46,19 @@ pub extern "C" fn lol_html_rewriter_build(
     let settings = Settings {
         element_content_handlers: handlers.element,
         document_content_handlers: handlers.document,
+        token_transformers: vec![],
+        runtime_handlers: None,
+        max_lookahead: Some(64 * 1024),
         encoding: unwrap_or_ret_null! { to_str!(encoding, encoding_len) },
+        output_encoding: builder.output_encoding.as_deref(),
         memory_settings,
         strict,
+        buffer_script_and_style_text: builder.buffer_script_and_style_text,
+        xhtml_output: false,
+        custom_void_elements: vec![],
+        provenance_comments: false,
+        output_buffer_watermark: None,
+        observability_hooks: ObservabilityHooks::default(),
     };
 
     let output_sink = ExternOutputSink::new(output_sink, output_sink_user_data);
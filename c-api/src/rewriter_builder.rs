@@ -93,13 +93,23 @@ pub struct SafeContentHandlers<'b> {
     pub element: Vec<(Cow<'b, Selector>, ElementContentHandlers<'b>)>,
 }
 
+// NOTE: building of a rewriter only reads the builder, so once the builder is frozen it can be
+// shared between threads to build rewriters concurrently.
 #[derive(Default)]
 pub struct HtmlRewriterBuilder {
+    frozen: bool,
     document_content_handlers: Vec<ExternDocumentContentHandlers>,
     element_content_handlers: Vec<(&'static Selector, ExternElementContentHandlers)>,
+    pub buffer_script_and_style_text: bool,
+    pub output_encoding: Option<String>,
 }
 
 impl HtmlRewriterBuilder {
+    #[inline]
+    fn assert_not_frozen(&self) {
+        assert!(!self.frozen, "Attempt to modify a frozen builder.");
+    }
+
     pub fn get_safe_handlers(&self) -> SafeContentHandlers {
         SafeContentHandlers {
             document: self
@@ -135,6 +145,8 @@ pub extern "C" fn lol_html_rewriter_builder_add_document_content_handlers(
 ) {
     let builder = to_ref_mut!(builder);
 
+    builder.assert_not_frozen();
+
     let handlers = ExternDocumentContentHandlers {
         doctype: ExternHandler::new(doctype_handler, doctype_handler_user_data),
         comments: ExternHandler::new(comments_handler, comments_handler_user_data),
@@ -159,6 +171,8 @@ pub extern "C" fn lol_html_rewriter_builder_add_element_content_handlers(
     let selector = to_ref!(selector);
     let builder = to_ref_mut!(builder);
 
+    builder.assert_not_frozen();
+
     let handlers = ExternElementContentHandlers {
         element: ExternHandler::new(element_handler, element_handler_user_data),
         comments: ExternHandler::new(comments_handler, comments_handler_user_data),
@@ -170,6 +184,44 @@ pub extern "C" fn lol_html_rewriter_builder_add_element_content_handlers(
     0
 }
 
+#[no_mangle]
+pub extern "C" fn lol_html_rewriter_builder_buffer_script_and_style_text(
+    builder: *mut HtmlRewriterBuilder,
+    enable: bool,
+) {
+    let builder = to_ref_mut!(builder);
+
+    builder.assert_not_frozen();
+    builder.buffer_script_and_style_text = enable;
+}
+
+#[no_mangle]
+pub extern "C" fn lol_html_rewriter_builder_set_output_encoding(
+    builder: *mut HtmlRewriterBuilder,
+    encoding: *const c_char,
+    encoding_len: size_t,
+) -> c_int {
+    let builder = to_ref_mut!(builder);
+
+    builder.assert_not_frozen();
+
+    let encoding = unwrap_or_ret_err_code! { to_str!(encoding, encoding_len) };
+
+    builder.output_encoding = Some(encoding.to_string());
+
+    0
+}
+
+#[no_mangle]
+pub extern "C" fn lol_html_rewriter_builder_freeze(builder: *mut HtmlRewriterBuilder) {
+    to_ref_mut!(builder).frozen = true;
+}
+
+#[no_mangle]
+pub extern "C" fn lol_html_rewriter_builder_is_frozen(builder: *const HtmlRewriterBuilder) -> bool {
+    to_ref!(builder).frozen
+}
+
 #[no_mangle]
 pub extern "C" fn lol_html_rewriter_builder_free(builder: *mut HtmlRewriterBuilder) {
     drop(to_box!(builder));
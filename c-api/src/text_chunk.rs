@@ -17,6 +17,29 @@ impl TextChunkContent {
     }
 }
 
+#[repr(C)]
+pub enum CTextType {
+    PlainText,
+    RCData,
+    RawText,
+    ScriptData,
+    Data,
+    CDataSection,
+}
+
+impl From<TextType> for CTextType {
+    fn from(text_type: TextType) -> Self {
+        match text_type {
+            TextType::PlainText => CTextType::PlainText,
+            TextType::RCData => CTextType::RCData,
+            TextType::RawText => CTextType::RawText,
+            TextType::ScriptData => CTextType::ScriptData,
+            TextType::Data => CTextType::Data,
+            TextType::CDataSection => CTextType::CDataSection,
+        }
+    }
+}
+
 #[no_mangle]
 pub extern "C" fn lol_html_text_chunk_content_get(chunk: *mut TextChunk) -> TextChunkContent {
     TextChunkContent::new(to_ref!(chunk))
@@ -27,6 +50,11 @@ pub extern "C" fn lol_html_text_chunk_is_last_in_text_node(chunk: *mut TextChunk
     to_ref!(chunk).last_in_text_node()
 }
 
+#[no_mangle]
+pub extern "C" fn lol_html_text_chunk_text_type(chunk: *const TextChunk) -> CTextType {
+    to_ref!(chunk).text_type().into()
+}
+
 #[no_mangle]
 pub extern "C" fn lol_html_text_chunk_before(
     chunk: *mut TextChunk,
@@ -10,11 +10,13 @@
 int run_tests() {
     subtest("Unsupported selector", test_unsupported_selector);
     subtest("Non-ASCII encoding", test_non_ascii_encoding);
+    subtest("Output encoding", test_output_encoding);
     subtest("Doctype API", test_doctype_api);
     subtest("Comment API", test_comment_api);
     subtest("Text chunk API", test_text_chunk_api);
     subtest("Element API", element_api_test);
     subtest("Document end API", document_end_api_test);
     subtest("Memory limiting", test_memory_limiting);
+    subtest("Frozen builder", test_frozen_builder);
     return done_testing();
 }
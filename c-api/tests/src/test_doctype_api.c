@@ -109,6 +109,106 @@ static void test_get_user_data(void *user_data) {
     );
 }
 
+//-------------------------------------------------------------------------
+EXPECT_OUTPUT(
+    modify_doctype_output_sink,
+    "<!DOCTYPE html PUBLIC \"-//W3C//DTD HTML 4.01//EN\">",
+    &EXPECTED_USER_DATA,
+    sizeof(EXPECTED_USER_DATA)
+)
+
+static lol_html_rewriter_directive_t modify_doctype(
+    lol_html_doctype_t *doctype,
+    void *user_data
+) {
+    UNUSED(user_data);
+
+    const char *name = "html";
+    const char *public_id = "-//W3C//DTD HTML 4.01//EN";
+    const char *invalid_name = "foo bar";
+
+    note("Set name");
+    ok(!lol_html_doctype_name_set(doctype, name, strlen(name)));
+
+    note("Set invalid name");
+    ok(lol_html_doctype_name_set(doctype, invalid_name, strlen(invalid_name)) == -1);
+
+    lol_html_str_t *msg = lol_html_take_last_error();
+
+    str_eq(msg, "Doctype name should be a non-empty string without whitespace and `>` characters.");
+
+    lol_html_str_free(*msg);
+
+    note("Set identifiers");
+    ok(!lol_html_doctype_public_id_set(doctype, public_id, strlen(public_id)));
+    ok(!lol_html_doctype_system_id_set(doctype, NULL, 0));
+
+    return LOL_HTML_CONTINUE;
+}
+
+static void test_modify_doctype(void *user_data) {
+    lol_html_rewriter_builder_t *builder = lol_html_rewriter_builder_new();
+
+    lol_html_rewriter_builder_add_document_content_handlers(
+        builder,
+        &modify_doctype,
+        NULL,
+        NULL,
+        NULL,
+        NULL,
+        NULL,
+        NULL,
+        NULL
+    );
+
+    run_rewriter(
+        builder,
+        "<!DOCTYPE math SYSTEM \"http://www.w3.org/Math/DTD/mathml1/mathml.dtd\">",
+        modify_doctype_output_sink,
+        user_data
+    );
+}
+
+//-------------------------------------------------------------------------
+EXPECT_OUTPUT(
+    remove_doctype_output_sink,
+    "<p>Hi</p>",
+    &EXPECTED_USER_DATA,
+    sizeof(EXPECTED_USER_DATA)
+)
+
+static lol_html_rewriter_directive_t remove_doctype(
+    lol_html_doctype_t *doctype,
+    void *user_data
+) {
+    UNUSED(user_data);
+
+    note("Remove");
+    ok(!lol_html_doctype_is_removed(doctype));
+    lol_html_doctype_remove(doctype);
+    ok(lol_html_doctype_is_removed(doctype));
+
+    return LOL_HTML_CONTINUE;
+}
+
+static void test_remove_doctype(void *user_data) {
+    lol_html_rewriter_builder_t *builder = lol_html_rewriter_builder_new();
+
+    lol_html_rewriter_builder_add_document_content_handlers(
+        builder,
+        &remove_doctype,
+        NULL,
+        NULL,
+        NULL,
+        NULL,
+        NULL,
+        NULL,
+        NULL
+    );
+
+    run_rewriter(builder, "<!DOCTYPE html><p>Hi</p>", remove_doctype_output_sink, user_data);
+}
+
 //-------------------------------------------------------------------------
 static lol_html_rewriter_directive_t stop_rewriting(
     lol_html_doctype_t *doctype,
@@ -145,5 +245,7 @@ void test_doctype_api() {
 
     test_get_doctype_fields(&user_data);
     test_get_user_data(&user_data);
+    test_modify_doctype(&user_data);
+    test_remove_doctype(&user_data);
     test_stop(&user_data);
 }
@@ -0,0 +1,122 @@
+#include <pthread.h>
+#include <string.h>
+
+#include "../../include/lol_html.h"
+#include "deps/picotest/picotest.h"
+#include "tests.h"
+#include "test_util.h"
+
+#define THREAD_COUNT 4
+#define OUTPUT_CAPACITY 64
+
+typedef struct {
+    const lol_html_rewriter_builder_t *builder;
+    char output[OUTPUT_CAPACITY];
+    size_t output_len;
+    int err;
+} worker_t;
+
+static void worker_output_sink(const char *chunk, size_t chunk_len, void *user_data) {
+    worker_t *worker = (worker_t*)user_data;
+
+    if (worker->output_len + chunk_len > OUTPUT_CAPACITY) {
+        worker->err = 1;
+    } else {
+        memcpy(worker->output + worker->output_len, chunk, chunk_len);
+        worker->output_len += chunk_len;
+    }
+}
+
+static lol_html_rewriter_directive_t set_class(
+    lol_html_element_t *element,
+    void *user_data
+) {
+    UNUSED(user_data);
+
+    const char *name = "class";
+    const char *value = "shared";
+
+    lol_html_element_set_attribute(element, name, strlen(name), value, strlen(value));
+
+    return LOL_HTML_CONTINUE;
+}
+
+static void *run_worker(void *arg) {
+    worker_t *worker = (worker_t*)arg;
+    const char *encoding = "UTF-8";
+    const char *html = "<div>Hi</div>";
+
+    lol_html_rewriter_t *rewriter = lol_html_rewriter_build(
+        worker->builder,
+        encoding,
+        strlen(encoding),
+        (lol_html_memory_settings_t) {
+            .preallocated_parsing_buffer_size = 0,
+            .max_allowed_memory_usage = MAX_MEMORY
+        },
+        &worker_output_sink,
+        worker,
+        true
+    );
+
+    if (rewriter == NULL) {
+        worker->err = 1;
+    } else {
+        worker->err |= lol_html_rewriter_write(rewriter, html, strlen(html));
+        worker->err |= lol_html_rewriter_end(rewriter);
+
+        lol_html_rewriter_free(rewriter);
+    }
+
+    return NULL;
+}
+
+void test_frozen_builder() {
+    const char *selector_str = "div";
+    const char *expected = "<div class=\"shared\">Hi</div>";
+
+    lol_html_selector_t *selector = lol_html_selector_parse(
+        selector_str,
+        strlen(selector_str)
+    );
+
+    lol_html_rewriter_builder_t *builder = lol_html_rewriter_builder_new();
+
+    int err = lol_html_rewriter_builder_add_element_content_handlers(
+        builder,
+        selector,
+        &set_class,
+        NULL,
+        NULL,
+        NULL,
+        NULL,
+        NULL
+    );
+
+    ok(!err);
+
+    note("Freeze");
+    ok(!lol_html_rewriter_builder_is_frozen(builder));
+    lol_html_rewriter_builder_freeze(builder);
+    ok(lol_html_rewriter_builder_is_frozen(builder));
+
+    note("Concurrent construction of rewriters");
+
+    pthread_t threads[THREAD_COUNT];
+    worker_t workers[THREAD_COUNT];
+
+    for (int i = 0; i < THREAD_COUNT; i++) {
+        workers[i] = (worker_t) { .builder = builder, .output_len = 0, .err = 0 };
+        ok(!pthread_create(&threads[i], NULL, &run_worker, &workers[i]));
+    }
+
+    for (int i = 0; i < THREAD_COUNT; i++) {
+        ok(!pthread_join(threads[i], NULL));
+        ok(!workers[i].err);
+        ok(workers[i].output_len == strlen(expected));
+        ok(!memcmp(workers[i].output, expected, workers[i].output_len));
+    }
+
+    lol_html_rewriter_builder_free(builder);
+    lol_html_selector_free(selector);
+}
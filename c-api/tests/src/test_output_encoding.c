@@ -0,0 +1,95 @@
+#include <string.h>
+
+#include "../../include/lol_html.h"
+#include "deps/picotest/picotest.h"
+#include "tests.h"
+#include "test_util.h"
+
+static int EXPECTED_USER_DATA = 42;
+
+//-------------------------------------------------------------------------
+// NOTE: "Привет" in windows-1251 and UTF-8 respectively.
+EXPECT_OUTPUT(
+    transcoded_output_sink,
+    "<p>\xD0\x9F\xD1\x80\xD0\xB8\xD0\xB2\xD0\xB5\xD1\x82</p>",
+    &EXPECTED_USER_DATA,
+    sizeof(EXPECTED_USER_DATA)
+);
+
+static void test_transcoding(void *user_data) {
+    const char *encoding = "windows-1251";
+    const char *output_encoding = "utf-8";
+    const char *html = "<p>\xCF\xF0\xE8\xE2\xE5\xF2</p>";
+
+    lol_html_rewriter_builder_t *builder = lol_html_rewriter_builder_new();
+
+    ok(!lol_html_rewriter_builder_set_output_encoding(
+        builder,
+        output_encoding,
+        strlen(output_encoding)
+    ));
+
+    lol_html_rewriter_t *rewriter = lol_html_rewriter_build(
+        builder,
+        encoding,
+        strlen(encoding),
+        (lol_html_memory_settings_t) {
+            .preallocated_parsing_buffer_size = 0,
+            .max_allowed_memory_usage = MAX_MEMORY
+        },
+        transcoded_output_sink,
+        user_data,
+        true
+    );
+
+    lol_html_rewriter_builder_free(builder);
+
+    ok(rewriter != NULL);
+    ok(!lol_html_rewriter_write(rewriter, html, strlen(html)));
+    ok(!lol_html_rewriter_end(rewriter));
+
+    lol_html_rewriter_free(rewriter);
+}
+
+static void test_unknown_output_encoding() {
+    const char *encoding = "UTF-8";
+    const char *output_encoding = "hey-yo";
+
+    lol_html_rewriter_builder_t *builder = lol_html_rewriter_builder_new();
+
+    ok(!lol_html_rewriter_builder_set_output_encoding(
+        builder,
+        output_encoding,
+        strlen(output_encoding)
+    ));
+
+    lol_html_rewriter_t *rewriter = lol_html_rewriter_build(
+        builder,
+        encoding,
+        strlen(encoding),
+        (lol_html_memory_settings_t) {
+            .preallocated_parsing_buffer_size = 0,
+            .max_allowed_memory_usage = MAX_MEMORY
+        },
+        &output_sink_stub,
+        NULL,
+        true
+    );
+
+    lol_html_rewriter_builder_free(builder);
+
+    ok(rewriter == NULL);
+
+    lol_html_str_t *msg = lol_html_take_last_error();
+
+    str_eq(msg, "Unknown character encoding has been provided.");
+
+    lol_html_str_free(*msg);
+}
+
+void test_output_encoding() {
+    int user_data = 42;
+
+    test_transcoding(&user_data);
+    test_unknown_output_encoding();
+}
@@ -230,6 +230,68 @@ static void test_remove_chunk(void *user_data) {
     run_rewriter(builder, "<span>0_0</span>", remove_chunk_output_sink, user_data);
 }
 
+//-------------------------------------------------------------------------
+EXPECT_OUTPUT(
+    buffered_script_text_output_sink,
+    "<script>foo()</script>",
+    &EXPECTED_USER_DATA,
+    sizeof(EXPECTED_USER_DATA)
+);
+
+static lol_html_rewriter_directive_t check_buffered_script_text(
+    lol_html_text_chunk_t *chunk,
+    void *user_data
+) {
+    UNUSED(user_data);
+    lol_html_text_chunk_content_t content = lol_html_text_chunk_content_get(chunk);
+
+    note("Text type");
+    ok(lol_html_text_chunk_text_type(chunk) == LOL_HTML_TEXT_TYPE_SCRIPT_DATA);
+
+    if (content.len > 0) {
+        note("Buffered script text");
+        str_eq(&content, "foo()");
+        ok(lol_html_text_chunk_is_last_in_text_node(chunk));
+    }
+
+    return LOL_HTML_CONTINUE;
+}
+
+static void test_buffered_script_text(lol_html_selector_t *selector, void *user_data) {
+    lol_html_rewriter_builder_t *builder = lol_html_rewriter_builder_new();
+
+    int err = lol_html_rewriter_builder_add_element_content_handlers(
+        builder,
+        selector,
+        NULL,
+        NULL,
+        NULL,
+        NULL,
+        &check_buffered_script_text,
+        NULL
+    );
+
+    ok(!err);
+
+    lol_html_rewriter_builder_buffer_script_and_style_text(builder, true);
+
+    lol_html_rewriter_t *rewriter = create_rewriter(
+        builder,
+        buffered_script_text_output_sink,
+        user_data,
+        MAX_MEMORY
+    );
+
+    const char *chunk1 = "<script>fo";
+    const char *chunk2 = "o()</script>";
+
+    ok(!lol_html_rewriter_write(rewriter, chunk1, strlen(chunk1)));
+    ok(!lol_html_rewriter_write(rewriter, chunk2, strlen(chunk2)));
+    ok(!lol_html_rewriter_end(rewriter));
+
+    lol_html_rewriter_free(rewriter);
+}
+
 //-------------------------------------------------------------------------
 static lol_html_rewriter_directive_t stop_rewriting(
     lol_html_text_chunk_t *chunk,
@@ -294,6 +356,7 @@ void test_text_chunk_api() {
     test_replace_chunk(selector, &user_data);
     test_insert_after_chunk(&user_data);
     test_remove_chunk(&user_data);
+    test_buffered_script_text(selector, &user_data);
 
     test_stop_with_selector(selector, &user_data);
     test_stop(&user_data);
@@ -5,11 +5,13 @@
 
 void test_unsupported_selector();
 void test_non_ascii_encoding();
+void test_output_encoding();
 void test_doctype_api();
 void test_comment_api();
 void test_text_chunk_api();
 void element_api_test();
 void document_end_api_test();
 void test_memory_limiting();
+void test_frozen_builder();
 
 #endif // TESTS_H
@@ -203,6 +203,8 @@ fn run_c_api_rewriter_iter(data: &[u8], encoding: &str) -> () {
             lol_html_memory_settings_t {
                 preallocated_parsing_buffer_size: 0,
                 max_allowed_memory_usage: std::usize::MAX,
+                parsing_buffer_compaction_watermark: 0,
+                parsing_buffer_shrink_watermark: 0,
             },
             Some(empty_handler),
             output_data_ptr,
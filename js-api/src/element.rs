@@ -12,8 +12,8 @@ pub struct Attribute {
 impl From<&NativeAttribute<'_>> for Attribute {
     fn from(native: &NativeAttribute) -> Self {
         Attribute {
-            name: native.name(),
-            value: native.value(),
+            name: native.name().to_string(),
+            value: native.value().to_string(),
         }
     }
 }
@@ -28,7 +28,7 @@ impl_mutations!(Element);
 impl Element {
     #[wasm_bindgen(method, getter=tagName)]
     pub fn tag_name(&self) -> JsResult<String> {
-        self.0.get().map(|e| e.tag_name())
+        self.0.get().map(|e| e.tag_name().to_string())
     }
 
     #[wasm_bindgen(method, setter=tagName)]
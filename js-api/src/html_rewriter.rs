@@ -1,9 +1,8 @@
 use super::*;
 use js_sys::{Function as JsFunction, Uint8Array};
-use lol_html::{
-    DocumentContentHandlers, ElementContentHandlers, HtmlRewriter as NativeHTMLRewriter,
-    OutputSink, Selector,
-};
+use lol_html::{DocumentContentHandlers, HtmlRewriter as NativeHTMLRewriter, OutputSink};
+#[cfg(feature = "element_handlers")]
+use lol_html::{ElementContentHandlers, Selector};
 
 struct JsOutputSink(JsFunction);
 
@@ -26,6 +25,7 @@ impl OutputSink for JsOutputSink {
 
 #[wasm_bindgen]
 pub struct HTMLRewriterBuilder {
+    #[cfg(feature = "element_handlers")]
     element_content_handlers: (Selector, ElementContentHandlers<'static>),
     document_content_handlers: DocumentContentHandlers<'static>,
 }
@@ -0,0 +1,18 @@
+use super::*;
+use lol_html::html_content::Comment as NativeComment;
+
+#[napi]
+pub struct Comment {
+    inner: NativeRefWrap<NativeComment<'static>>,
+}
+
+impl_from_native!(NativeComment --> Comment);
+impl_mutations!(Comment);
+
+#[napi]
+impl Comment {
+    #[napi(getter)]
+    pub fn text(&self) -> Result<String> {
+        self.inner.get().map(|c| c.text())
+    }
+}
@@ -0,0 +1,27 @@
+use super::*;
+use lol_html::html_content::Doctype as NativeDoctype;
+
+#[napi]
+pub struct Doctype {
+    inner: NativeRefWrap<NativeDoctype<'static>>,
+}
+
+impl_from_native!(NativeDoctype --> Doctype);
+
+#[napi]
+impl Doctype {
+    #[napi(getter)]
+    pub fn name(&self) -> Result<Option<String>> {
+        self.inner.get().map(|d| d.name())
+    }
+
+    #[napi(getter)]
+    pub fn public_id(&self) -> Result<Option<String>> {
+        self.inner.get().map(|d| d.public_id())
+    }
+
+    #[napi(getter)]
+    pub fn system_id(&self) -> Result<Option<String>> {
+        self.inner.get().map(|d| d.system_id())
+    }
+}
@@ -0,0 +1,23 @@
+use super::*;
+use lol_html::html_content::DocumentEnd as NativeDocumentEnd;
+
+#[napi]
+pub struct DocumentEnd {
+    inner: NativeRefWrap<NativeDocumentEnd<'static>>,
+}
+
+impl_from_native!(NativeDocumentEnd --> DocumentEnd);
+
+#[napi]
+impl DocumentEnd {
+    #[napi]
+    pub fn append(
+        &mut self,
+        content: String,
+        content_type: Option<ContentTypeOptions>,
+    ) -> Result<()> {
+        self.inner
+            .get_mut()
+            .map(|e| e.append(&content, content_type.into_native()))
+    }
+}
@@ -0,0 +1,111 @@
+use super::*;
+use lol_html::html_content::{Attribute as NativeAttribute, Element as NativeElement};
+
+#[napi(object)]
+pub struct Attribute {
+    pub name: String,
+    pub value: String,
+}
+
+impl From<&NativeAttribute<'_>> for Attribute {
+    fn from(native: &NativeAttribute) -> Self {
+        Attribute {
+            name: native.name().to_string(),
+            value: native.value().to_string(),
+        }
+    }
+}
+
+#[napi]
+pub struct Element {
+    inner: NativeRefWrap<NativeElement<'static, 'static>>,
+}
+
+impl_from_native!(NativeElement --> Element);
+impl_mutations!(Element);
+
+#[napi]
+impl Element {
+    #[napi(getter, js_name = "tagName")]
+    pub fn tag_name(&self) -> Result<String> {
+        self.inner.get().map(|e| e.tag_name().into())
+    }
+
+    #[napi(setter, js_name = "tagName")]
+    pub fn set_tag_name(&mut self, name: String) -> Result<()> {
+        self.inner.get_mut()?.set_tag_name(&name).into_napi_result()
+    }
+
+    #[napi(getter, js_name = "namespaceURI")]
+    pub fn namespace_uri(&self) -> Result<String> {
+        self.inner.get().map(|e| e.namespace_uri().into())
+    }
+
+    #[napi(getter)]
+    pub fn attributes(&self) -> Result<Vec<Attribute>> {
+        self.inner
+            .get()
+            .map(|e| e.attributes().iter().map(Attribute::from).collect())
+    }
+
+    #[napi]
+    pub fn get_attribute(&self, name: String) -> Result<Option<String>> {
+        self.inner.get().map(|e| e.get_attribute(&name))
+    }
+
+    #[napi]
+    pub fn has_attribute(&self, name: String) -> Result<bool> {
+        self.inner.get().map(|e| e.has_attribute(&name))
+    }
+
+    #[napi]
+    pub fn set_attribute(&mut self, name: String, value: String) -> Result<()> {
+        self.inner
+            .get_mut()?
+            .set_attribute(&name, &value)
+            .into_napi_result()
+    }
+
+    #[napi]
+    pub fn remove_attribute(&mut self, name: String) -> Result<()> {
+        self.inner.get_mut().map(|e| e.remove_attribute(&name))
+    }
+
+    #[napi]
+    pub fn prepend(
+        &mut self,
+        content: String,
+        content_type: Option<ContentTypeOptions>,
+    ) -> Result<()> {
+        self.inner
+            .get_mut()
+            .map(|e| e.prepend(&content, content_type.into_native()))
+    }
+
+    #[napi]
+    pub fn append(
+        &mut self,
+        content: String,
+        content_type: Option<ContentTypeOptions>,
+    ) -> Result<()> {
+        self.inner
+            .get_mut()
+            .map(|e| e.append(&content, content_type.into_native()))
+    }
+
+    #[napi]
+    pub fn set_inner_content(
+        &mut self,
+        content: String,
+        content_type: Option<ContentTypeOptions>,
+    ) -> Result<()> {
+        self.inner
+            .get_mut()
+            .map(|e| e.set_inner_content(&content, content_type.into_native()))
+    }
+
+    #[napi]
+    pub fn remove_and_keep_content(&mut self) -> Result<()> {
+        self.inner.get_mut().map(|e| e.remove_and_keep_content())
+    }
+}
@@ -0,0 +1,336 @@
+use super::comment::Comment;
+use super::doctype::Doctype;
+use super::document_end::DocumentEnd;
+use super::element::Element;
+use super::text_chunk::TextChunk;
+use super::*;
+use lol_html::errors::RewritingError;
+use lol_html::html_content::{
+    Comment as NativeComment, Doctype as NativeDoctype, DocumentEnd as NativeDocumentEnd,
+    Element as NativeElement, TextChunk as NativeTextChunk,
+};
+use lol_html::{
+    DocumentContentHandlers, ElementContentHandlers, HtmlRewriter as NativeHtmlRewriter,
+    OutputSink, Selector, Settings,
+};
+use napi::bindgen_prelude::*;
+use napi::{Env, JsFunction, JsObject, NapiRaw, Ref};
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::error::Error as StdError;
+
+type HandlerResult = std::result::Result<(), Box<dyn StdError + Send + Sync>>;
+
+// NOTE: references to the JS functions must be released explicitly, so we keep all of them
+// in one place and release them once the rewriter is garbage collected.
+#[derive(Default)]
+struct FunctionRefs(RefCell<Vec<Ref<()>>>);
+
+struct JsCallback {
+    env: Env,
+    refs: Rc<FunctionRefs>,
+    idx: usize,
+}
+
+impl JsCallback {
+    fn new(env: Env, refs: &Rc<FunctionRefs>, function: JsFunction) -> Result<Self> {
+        let mut functions = refs.0.borrow_mut();
+
+        functions.push(env.create_reference(function)?);
+
+        Ok(JsCallback {
+            env,
+            refs: Rc::clone(refs),
+            idx: functions.len() - 1,
+        })
+    }
+
+    fn call<V: NapiRaw>(&self, arg: V) -> Result<()> {
+        let function: JsFunction = {
+            let functions = self.refs.0.borrow();
+
+            self.env.get_reference_value(&functions[self.idx])?
+        };
+
+        function.call(None, &[arg]).map(|_| ())
+    }
+
+    fn call_with_instance<T: 'static>(&self, instance: Result<ClassInstance<T>>) -> HandlerResult {
+        let object = instance.map_err(|e| e.reason)?.as_object(self.env);
+
+        self.call(object).map_err(|e| e.reason.into())
+    }
+}
+
+macro_rules! handler {
+    ($callback:ident, $Native:ident --> $JsTy:ident) => {
+        move |unit: &mut $Native| {
+            let (unit, _anchor) = $JsTy::from_native(unit);
+
+            $callback.call_with_instance(unit.into_instance($callback.env))
+        }
+    };
+}
+
+struct JsOutputSink {
+    callback: JsCallback,
+    error: Rc<RefCell<Option<Error>>>,
+}
+
+impl OutputSink for JsOutputSink {
+    fn handle_chunk(&mut self, chunk: &[u8]) {
+        let mut error = self.error.borrow_mut();
+
+        if chunk.is_empty() || error.is_some() {
+            return;
+        }
+
+        let env = self.callback.env;
+
+        if let Err(e) = env
+            .create_buffer_with_data(chunk.to_vec())
+            .and_then(|buffer| self.callback.call(buffer.into_raw()))
+        {
+            *error = Some(e);
+        }
+    }
+}
+
+struct Builder {
+    element_content_handlers: Vec<(Selector, ElementContentHandlers<'static>)>,
+    document_content_handlers: Vec<DocumentContentHandlers<'static>>,
+    output_sink: JsOutputSink,
+}
+
+impl Builder {
+    fn build(self) -> Result<NativeHtmlRewriter<'static, JsOutputSink>> {
+        let element_content_handlers = self
+            .element_content_handlers
+            .into_iter()
+            .map(|(selector, handlers)| (Cow::Owned(selector), handlers))
+            .collect();
+
+        NativeHtmlRewriter::try_new(
+            Settings {
+                element_content_handlers,
+                document_content_handlers: self.document_content_handlers,
+                ..Settings::default()
+            },
+            self.output_sink,
+        )
+        .into_napi_result()
+    }
+}
+
+// NOTE: the rewriter is constructed on the first write, so handlers can be added after
+// the construction of the JS object.
+enum State {
+    Building(Builder),
+    Rewriting(NativeHtmlRewriter<'static, JsOutputSink>),
+    Ended,
+}
+
+/// A streaming HTML rewriter.
+///
+/// Handlers are invoked synchronously during the `write` and `end` calls and the output is
+/// passed to the output sink function as it becomes available.
+#[napi(js_name = "HTMLRewriter", custom_finalize)]
+pub struct HtmlRewriter {
+    env: Env,
+    refs: Rc<FunctionRefs>,
+    output_error: Rc<RefCell<Option<Error>>>,
+    state: State,
+    busy: bool,
+}
+
+#[napi]
+impl HtmlRewriter {
+    #[napi(constructor)]
+    pub fn new(env: Env, output_sink: JsFunction) -> Result<Self> {
+        let refs = Rc::new(FunctionRefs::default());
+        let output_error = Rc::new(RefCell::new(None));
+
+        let output_sink = JsOutputSink {
+            callback: JsCallback::new(env, &refs, output_sink)?,
+            error: Rc::clone(&output_error),
+        };
+
+        Ok(HtmlRewriter {
+            env,
+            refs,
+            output_error,
+            state: State::Building(Builder {
+                element_content_handlers: Vec::new(),
+                document_content_handlers: Vec::new(),
+                output_sink,
+            }),
+            busy: false,
+        })
+    }
+
+    #[napi]
+    pub fn on(&mut self, selector: String, handlers: JsObject) -> Result<()> {
+        let selector: Selector = selector.parse().into_napi_result()?;
+        let mut native = ElementContentHandlers::default();
+
+        if let Some(function) = handlers.get::<_, JsFunction>("element")? {
+            let callback = JsCallback::new(self.env, &self.refs, function)?;
+
+            native = native.element(handler!(callback, NativeElement --> Element));
+        }
+
+        if let Some(function) = handlers.get::<_, JsFunction>("comments")? {
+            let callback = JsCallback::new(self.env, &self.refs, function)?;
+
+            native = native.comments(handler!(callback, NativeComment --> Comment));
+        }
+
+        if let Some(function) = handlers.get::<_, JsFunction>("text")? {
+            let callback = JsCallback::new(self.env, &self.refs, function)?;
+
+            native = native.text(handler!(callback, NativeTextChunk --> TextChunk));
+        }
+
+        self.builder()?
+            .element_content_handlers
+            .push((selector, native));
+
+        Ok(())
+    }
+
+    #[napi]
+    pub fn on_document(&mut self, handlers: JsObject) -> Result<()> {
+        let mut native = DocumentContentHandlers::default();
+
+        if let Some(function) = handlers.get::<_, JsFunction>("doctype")? {
+            let callback = JsCallback::new(self.env, &self.refs, function)?;
+
+            native = native.doctype(handler!(callback, NativeDoctype --> Doctype));
+        }
+
+        if let Some(function) = handlers.get::<_, JsFunction>("comments")? {
+            let callback = JsCallback::new(self.env, &self.refs, function)?;
+
+            native = native.comments(handler!(callback, NativeComment --> Comment));
+        }
+
+        if let Some(function) = handlers.get::<_, JsFunction>("text")? {
+            let callback = JsCallback::new(self.env, &self.refs, function)?;
+
+            native = native.text(handler!(callback, NativeTextChunk --> TextChunk));
+        }
+
+        if let Some(function) = handlers.get::<_, JsFunction>("end")? {
+            let callback = JsCallback::new(self.env, &self.refs, function)?;
+
+            native = native.end(handler!(callback, NativeDocumentEnd --> DocumentEnd));
+        }
+
+        self.builder()?.document_content_handlers.push(native);
+
+        Ok(())
+    }
+
+    #[napi]
+    pub fn write(&mut self, chunk: Buffer) -> Result<()> {
+        self.enter()?;
+
+        let result = match self.rewriter() {
+            Ok(rewriter) => rewriter.write(&chunk),
+            Err(e) => {
+                self.busy = false;
+
+                return Err(e);
+            }
+        };
+
+        self.leave(result)
+    }
+
+    #[napi]
+    pub fn end(&mut self) -> Result<()> {
+        self.enter()?;
+
+        if let Err(e) = self.rewriter() {
+            self.busy = false;
+
+            return Err(e);
+        }
+
+        let result = match mem::replace(&mut self.state, State::Ended) {
+            State::Rewriting(rewriter) => rewriter.end(),
+            _ => unreachable!("Rewriter should be constructed at this point"),
+        };
+
+        self.leave(result)
+    }
+
+    fn builder(&mut self) -> Result<&mut Builder> {
+        match self.state {
+            State::Building(ref mut builder) => Ok(builder),
+            _ => Err(Error::from_reason(
+                "Handlers can't be added after the rewriting has started.",
+            )),
+        }
+    }
+
+    fn rewriter(&mut self) -> Result<&mut NativeHtmlRewriter<'static, JsOutputSink>> {
+        if let State::Building(_) = self.state {
+            match mem::replace(&mut self.state, State::Ended) {
+                State::Building(builder) => self.state = State::Rewriting(builder.build()?),
+                _ => unreachable!(),
+            }
+        }
+
+        match self.state {
+            State::Rewriting(ref mut rewriter) => Ok(rewriter),
+            _ => Err(Error::from_reason("The rewriter has already ended.")),
+        }
+    }
+
+    // NOTE: handlers are invoked while we hold a mutable reference to the rewriter, so they
+    // must not use the rewriter.
+    fn enter(&mut self) -> Result<()> {
+        if self.busy {
+            Err(Error::from_reason(
+                "The rewriter can't be used from its own handlers.",
+            ))
+        } else {
+            self.busy = true;
+
+            Ok(())
+        }
+    }
+
+    fn leave(&mut self, result: std::result::Result<(), RewritingError>) -> Result<()> {
+        let output_error = self.output_error.borrow_mut().take();
+
+        self.busy = false;
+
+        // NOTE: the rewriter can't be used after an error.
+        if let Some(e) = output_error {
+            self.state = State::Ended;
+
+            return Err(e);
+        }
+
+        result.map_err(|e| {
+            self.state = State::Ended;
+
+            Error::from_reason(e.to_string())
+        })
+    }
+}
+
+impl ObjectFinalize for HtmlRewriter {
+    fn finalize(self, env: Env) -> Result<()> {
+        // NOTE: drop the handlers before their function references are released.
+        drop(self.state);
+
+        for function in self.refs.0.borrow_mut().drain(..) {
+            function.unref(env)?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,179 @@
+//! Native Node.js bindings for the rewriter.
+//!
+//! Unlike the WASM bindings, the addon runs the rewriter natively, so the input chunks are
+//! passed to the rewriter without being copied to the WASM memory and the handlers are invoked
+//! synchronously while the chunks are written.
+
+#[macro_use]
+extern crate napi_derive;
+
+use lol_html::html_content::ContentType as NativeContentType;
+use napi::{Error, Result};
+use std::cell::Cell;
+use std::marker::PhantomData;
+use std::mem;
+use std::rc::Rc;
+
+struct Anchor<'r> {
+    poisoned: Rc<Cell<bool>>,
+    lifetime: PhantomData<&'r mut ()>,
+}
+
+impl<'r> Anchor<'r> {
+    pub fn new(poisoned: Rc<Cell<bool>>) -> Self {
+        Anchor {
+            poisoned,
+            lifetime: PhantomData,
+        }
+    }
+}
+
+impl Drop for Anchor<'_> {
+    fn drop(&mut self) {
+        self.poisoned.replace(true);
+    }
+}
+
+// NOTE: JS classes can't have lifetimes, so, as in the WASM bindings, we erase the lifetime
+// information from the inner reference and provide an anchor object that keeps track of
+// the lifetime in the runtime.
+//
+// When anchor goes out of scope, wrapper becomes poisoned and any attempt to get inner
+// object results in exception.
+struct NativeRefWrap<R> {
+    inner_ptr: *mut R,
+    poisoned: Rc<Cell<bool>>,
+}
+
+impl<R> NativeRefWrap<R> {
+    pub fn wrap<I>(inner: &mut I) -> (Self, Anchor) {
+        let wrap = NativeRefWrap {
+            inner_ptr: unsafe { mem::transmute(inner) },
+            poisoned: Rc::new(Cell::new(false)),
+        };
+
+        let anchor = Anchor::new(Rc::clone(&wrap.poisoned));
+
+        (wrap, anchor)
+    }
+
+    fn assert_not_poisoned(&self) -> Result<()> {
+        if self.poisoned.get() {
+            Err(Error::from_reason(
+                "The object has been freed and can't be used anymore.",
+            ))
+        } else {
+            Ok(())
+        }
+    }
+
+    pub fn get(&self) -> Result<&R> {
+        self.assert_not_poisoned()?;
+
+        Ok(unsafe { self.inner_ptr.as_ref() }.unwrap())
+    }
+
+    pub fn get_mut(&mut self) -> Result<&mut R> {
+        self.assert_not_poisoned()?;
+
+        Ok(unsafe { self.inner_ptr.as_mut() }.unwrap())
+    }
+}
+
+trait IntoNapiResult<T> {
+    fn into_napi_result(self) -> Result<T>;
+}
+
+impl<T, E: ToString> IntoNapiResult<T> for std::result::Result<T, E> {
+    #[inline]
+    fn into_napi_result(self) -> Result<T> {
+        self.map_err(|e| Error::from_reason(e.to_string()))
+    }
+}
+
+#[napi(object)]
+pub struct ContentTypeOptions {
+    pub html: Option<bool>,
+}
+
+trait IntoNative<T> {
+    fn into_native(self) -> T;
+}
+
+impl IntoNative<NativeContentType> for Option<ContentTypeOptions> {
+    fn into_native(self) -> NativeContentType {
+        match self.and_then(|opts| opts.html) {
+            Some(true) => NativeContentType::Html,
+            _ => NativeContentType::Text,
+        }
+    }
+}
+
+macro_rules! impl_mutations {
+    ($Ty:ident) => {
+        #[napi]
+        impl $Ty {
+            #[napi]
+            pub fn before(
+                &mut self,
+                content: String,
+                content_type: Option<ContentTypeOptions>,
+            ) -> Result<()> {
+                self.inner
+                    .get_mut()
+                    .map(|o| o.before(&content, content_type.into_native()))
+            }
+
+            #[napi]
+            pub fn after(
+                &mut self,
+                content: String,
+                content_type: Option<ContentTypeOptions>,
+            ) -> Result<()> {
+                self.inner
+                    .get_mut()
+                    .map(|o| o.after(&content, content_type.into_native()))
+            }
+
+            #[napi]
+            pub fn replace(
+                &mut self,
+                content: String,
+                content_type: Option<ContentTypeOptions>,
+            ) -> Result<()> {
+                self.inner
+                    .get_mut()
+                    .map(|o| o.replace(&content, content_type.into_native()))
+            }
+
+            #[napi]
+            pub fn remove(&mut self) -> Result<()> {
+                self.inner.get_mut().map(|o| o.remove())
+            }
+
+            #[napi(getter)]
+            pub fn removed(&self) -> Result<bool> {
+                self.inner.get().map(|o| o.removed())
+            }
+        }
+    };
+}
+
+macro_rules! impl_from_native {
+    ($Ty:ident --> $JsTy:ident) => {
+        impl $JsTy {
+            pub(crate) fn from_native<'r>(inner: &'r mut $Ty) -> (Self, Anchor<'r>) {
+                let (inner, anchor) = NativeRefWrap::wrap(inner);
+
+                ($JsTy { inner }, anchor)
+            }
+        }
+    };
+}
+
+mod comment;
+mod doctype;
+mod document_end;
+mod element;
+mod html_rewriter;
+mod text_chunk;
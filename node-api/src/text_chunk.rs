@@ -0,0 +1,23 @@
+use super::*;
+use lol_html::html_content::TextChunk as NativeTextChunk;
+
+#[napi]
+pub struct TextChunk {
+    inner: NativeRefWrap<NativeTextChunk<'static>>,
+}
+
+impl_from_native!(NativeTextChunk --> TextChunk);
+impl_mutations!(TextChunk);
+
+#[napi]
+impl TextChunk {
+    #[napi(getter)]
+    pub fn text(&self) -> Result<String> {
+        self.inner.get().map(|c| c.as_str().into())
+    }
+
+    #[napi(getter)]
+    pub fn last_in_text_node(&self) -> Result<bool> {
+        self.inner.get().map(|c| c.last_in_text_node())
+    }
+}
@@ -0,0 +1,3 @@
+#!/bin/sh
+
+cargo test --features=differential_test --test differential -- "$@"
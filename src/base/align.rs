@@ -1,3 +1,5 @@
+use smallvec::{Array, SmallVec};
+
 pub trait Align {
     fn align(&mut self, offset: usize);
 }
@@ -11,6 +13,18 @@ impl<T: Align> Align for Vec<T> {
     }
 }
 
+impl<A: Array> Align for SmallVec<A>
+where
+    A::Item: Align,
+{
+    #[inline]
+    fn align(&mut self, offset: usize) {
+        for item in self.iter_mut() {
+            item.align(offset);
+        }
+    }
+}
+
 impl<T: Align> Align for Option<T> {
     #[inline]
     fn align(&mut self, offset: usize) {
@@ -49,6 +49,19 @@ impl<'b> Bytes<'b> {
         Bytes(Cow::Owned(self.0.into_owned()))
     }
 
+    /// Replaces the content with a copy of the `bytes`, reusing the allocated buffer if the
+    /// content is owned.
+    #[inline]
+    pub fn assign(&mut self, bytes: &[u8]) {
+        match self.0 {
+            Cow::Owned(ref mut owned) => {
+                owned.clear();
+                owned.extend_from_slice(bytes);
+            }
+            Cow::Borrowed(_) => self.0 = Cow::Owned(bytes.to_vec()),
+        }
+    }
+
     #[inline]
     pub fn slice(&self, range: Range) -> Bytes {
         self.0[range.start..range.end].into()
@@ -97,7 +110,11 @@ macro_rules! impl_replace_byte {
 
 impl<'b> Bytes<'b> {
     #[inline]
-    pub fn replace_byte(&self, (needle, repl): (u8, &[u8]), output_handler: &mut dyn FnMut(&[u8])) {
+    pub fn replace_byte(
+        &self,
+        (needle, repl): (u8, &[u8]),
+        output_handler: &mut impl FnMut(&[u8]),
+    ) {
         macro_rules! impls {
             (@find $tail:ident) => {
                 memchr(needle, $tail)
@@ -117,7 +134,7 @@ impl<'b> Bytes<'b> {
         (needle1, repl1): (u8, &[u8]),
         (needle2, repl2): (u8, &[u8]),
         (needle3, repl3): (u8, &[u8]),
-        output_handler: &mut dyn FnMut(&[u8]),
+        output_handler: &mut impl FnMut(&[u8]),
     ) {
         macro_rules! impls {
             (@find $tail:ident) => {
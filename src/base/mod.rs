@@ -6,5 +6,7 @@ mod bytes;
 mod range;
 
 pub use self::align::Align;
-pub use self::bytes::{Bytes, HasReplacementsError};
+pub use self::bytes::Bytes;
+#[cfg(feature = "element_handlers")]
+pub use self::bytes::HasReplacementsError;
 pub use self::range::Range;
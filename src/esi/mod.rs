@@ -0,0 +1,427 @@
+//! [Edge Side Includes] (ESI) processing.
+//!
+//! Requires the `esi` feature.
+//!
+//! [Edge Side Includes]: https://www.w3.org/TR/esi-lang
+
+use crate::html_content::{ContentType, UserData};
+use crate::rewriter::{HtmlRewriter, RewritingError, Settings};
+use crate::transform_stream::OutputSink;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::error::Error;
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+use thiserror::Error;
+
+/// The result of a fetch of an included resource.
+pub type FetchResult = Result<String, Box<dyn Error + Send + Sync>>;
+
+/// A future produced by the fetch callback of the [`EsiProcessor`] that resolves to the
+/// content of an included resource.
+///
+/// [`EsiProcessor`]: struct.EsiProcessor.html
+pub type FetchFuture = Pin<Box<dyn Future<Output = FetchResult>>>;
+
+/// An error that can be returned by the [`EsiProcessor`].
+///
+/// [`EsiProcessor`]: struct.EsiProcessor.html
+#[derive(Error, Debug)]
+pub enum EsiError {
+    /// See [`RewritingError`].
+    ///
+    /// [`RewritingError`]: ../errors/enum.RewritingError.html
+    #[error("{0}")]
+    Rewriting(#[from] RewritingError),
+
+    /// An error that occurred while fetching the resource of an `<esi:include>` element that
+    /// doesn't have the `onerror="continue"` attribute.
+    #[error("Failed to fetch the included resource `{src}`: {error}")]
+    Fetch {
+        /// The URL of the resource.
+        src: String,
+        /// The error returned by the fetch callback.
+        error: Box<dyn Error + Send + Sync>,
+    },
+}
+
+#[derive(Error, Debug, PartialEq, Copy, Clone)]
+#[error("The `<esi:include>` element doesn't have the `src` attribute.")]
+struct MissingIncludeSrcError;
+
+// NOTE: attached to the includes in the content of `<esi:remove>` elements.
+struct RemovedInclude;
+
+struct Include {
+    src: String,
+    alt: Option<String>,
+    continue_on_error: bool,
+    response: FetchFuture,
+}
+
+enum Segment {
+    Content(Vec<u8>),
+    Include(Include),
+}
+
+struct EsiState<'f> {
+    fetch: Box<dyn FnMut(&str) -> FetchFuture + 'f>,
+    segments: VecDeque<Segment>,
+}
+
+struct SegmentSink<'f>(Rc<RefCell<EsiState<'f>>>);
+
+impl OutputSink for SegmentSink<'_> {
+    fn handle_chunk(&mut self, chunk: &[u8]) {
+        let mut state = self.0.borrow_mut();
+
+        if let Some(Segment::Content(content)) = state.segments.back_mut() {
+            content.extend_from_slice(chunk);
+        } else if !chunk.is_empty() {
+            state.segments.push_back(Segment::Content(chunk.to_vec()));
+        }
+    }
+}
+
+/// A streaming processor of [Edge Side Includes].
+///
+/// The processor recognizes the following ESI constructs:
+///  * `<esi:include src="..." alt="..." onerror="continue"/>` elements are replaced with the
+///    content of the resource produced by the fetch callback. If the fetch of the `src` resource
+///    fails, the `alt` resource is fetched instead. If the fetch of both fails, the element is
+///    removed if it has the `onerror="continue"` attribute, otherwise the processing fails with
+///    an [`EsiError::Fetch`] error.
+///  * `<esi:remove>` elements are removed along with their content.
+///  * `<!--esi ... -->` comments are replaced with their content.
+///
+/// Markup is parsed synchronously by the [`write`] method, while the included resources are
+/// awaited in the document order by the [`flush`] and [`end`] methods, which write the output
+/// that precedes the first pending include to the output sink. The fetch callback is called
+/// as soon as an include is found, so it can start the request eagerly (e.g. by spawning a
+/// task) to fetch the resources concurrently.
+///
+/// # Note
+/// ESI constructs in the content of `<!--esi ... -->` comments and in the included resources
+/// are not processed.
+///
+/// # Example
+/// ```
+/// use lol_html::esi::{EsiProcessor, FetchFuture, FetchResult};
+/// # use std::future::Future;
+/// # use std::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
+/// #
+/// # fn block_on<F: Future>(f: F) -> F::Output {
+/// #     fn noop_raw_waker() -> RawWaker {
+/// #         fn clone(_: *const ()) -> RawWaker { noop_raw_waker() }
+/// #         fn noop(_: *const ()) {}
+/// #         static VTABLE: RawWakerVTable = RawWakerVTable::new(clone, noop, noop, noop);
+/// #         RawWaker::new(std::ptr::null(), &VTABLE)
+/// #     }
+/// #     let waker = unsafe { Waker::from_raw(noop_raw_waker()) };
+/// #     let mut f = Box::pin(f);
+/// #     loop {
+/// #         if let Poll::Ready(v) = f.as_mut().poll(&mut Context::from_waker(&waker)) {
+/// #             return v;
+/// #         }
+/// #     }
+/// # }
+///
+/// let mut output = vec![];
+///
+/// let mut processor = EsiProcessor::new(|src| -> FetchFuture {
+///     let result: FetchResult = Ok(format!("<nav>{}</nav>", src));
+///
+///     Box::pin(async move { result })
+/// });
+///
+/// processor.write(br#"<body><esi:include src="/nav"/><esi:remove>No ESI</esi:remove>"#).unwrap();
+/// processor.write(b"<!--esi <p>ESI</p>--></body>").unwrap();
+///
+/// block_on(processor.end(|c: &[u8]| output.extend_from_slice(c))).unwrap();
+///
+/// assert_eq!(
+///     String::from_utf8(output).unwrap(),
+///     "<body><nav>/nav</nav> <p>ESI</p></body>"
+/// );
+/// ```
+///
+/// [Edge Side Includes]: https://www.w3.org/TR/esi-lang
+/// [`EsiError::Fetch`]: enum.EsiError.html#variant.Fetch
+/// [`write`]: #method.write
+/// [`flush`]: #method.flush
+/// [`end`]: #method.end
+pub struct EsiProcessor<'f> {
+    rewriter: HtmlRewriter<'f, SegmentSink<'f>>,
+    state: Rc<RefCell<EsiState<'f>>>,
+}
+
+impl<'f> EsiProcessor<'f> {
+    /// Constructs a new processor that fetches included resources with the `fetch` callback.
+    pub fn new(fetch: impl FnMut(&str) -> FetchFuture + 'f) -> Self {
+        let state = Rc::new(RefCell::new(EsiState {
+            fetch: Box::new(fetch),
+            segments: VecDeque::default(),
+        }));
+
+        let include_state = Rc::clone(&state);
+
+        // NOTE: never panics because encoding is always "utf-8".
+        let rewriter = HtmlRewriter::try_new(
+            Settings {
+                element_content_handlers: vec![
+                    element!(r"esi\:remove esi\:include", |el| {
+                        el.set_user_data(RemovedInclude);
+                        Ok(())
+                    }),
+                    element!(r"esi\:include", move |el| {
+                        if el.user_data().is::<RemovedInclude>() {
+                            return Ok(());
+                        }
+
+                        let src = el.get_attribute("src").ok_or(MissingIncludeSrcError)?;
+                        let mut state = include_state.borrow_mut();
+                        let response = (state.fetch)(&src);
+
+                        // NOTE: the include element is usually self-closing, which is ignored
+                        // for HTML elements, so we keep the content to not remove the markup
+                        // that follows it.
+                        el.remove_and_keep_content();
+
+                        state.segments.push_back(Segment::Include(Include {
+                            alt: el.get_attribute("alt"),
+                            continue_on_error: el.get_attribute("onerror").as_deref()
+                                == Some("continue"),
+                            src,
+                            response,
+                        }));
+
+                        Ok(())
+                    }),
+                    element!(r"esi\:remove", |el| {
+                        el.remove();
+                        Ok(())
+                    }),
+                ],
+                document_content_handlers: vec![doc_comments!(|c| {
+                    let text = c.text();
+
+                    if text.starts_with("esi") && text[3..].starts_with(char::is_whitespace) {
+                        c.replace(&text[3..], ContentType::Html);
+                    }
+
+                    Ok(())
+                })],
+                ..Settings::default()
+            },
+            SegmentSink(Rc::clone(&state)),
+        )
+        .unwrap();
+
+        EsiProcessor { rewriter, state }
+    }
+
+    /// Writes a chunk of input data to the processor.
+    ///
+    /// # Panics
+    ///  * In the same cases as [`HtmlRewriter::write`].
+    ///
+    /// [`HtmlRewriter::write`]: ../struct.HtmlRewriter.html#method.write
+    #[inline]
+    pub fn write(&mut self, data: &[u8]) -> Result<(), RewritingError> {
+        self.rewriter.write(data)
+    }
+
+    async fn fetch_include(&self, include: Include) -> Result<Option<String>, EsiError> {
+        let Include {
+            src,
+            alt,
+            continue_on_error,
+            response,
+        } = include;
+
+        let mut result = response.await;
+
+        if result.is_err() {
+            if let Some(alt) = alt {
+                let response = (self.state.borrow_mut().fetch)(&alt);
+
+                result = response.await;
+            }
+        }
+
+        match result {
+            Ok(content) => Ok(Some(content)),
+            Err(_) if continue_on_error => Ok(None),
+            Err(error) => Err(EsiError::Fetch { src, error }),
+        }
+    }
+
+    /// Awaits the included resources found so far and writes the output to the `output_sink`.
+    pub async fn flush(&mut self, output_sink: &mut impl OutputSink) -> Result<(), EsiError> {
+        loop {
+            // NOTE: the state must not be borrowed across the await points, since the fetch
+            // callback is invoked by the rewriter during the processing.
+            let segment = self.state.borrow_mut().segments.pop_front();
+
+            match segment {
+                Some(Segment::Content(content)) => output_sink.handle_chunk(&content),
+                Some(Segment::Include(include)) => {
+                    if let Some(content) = self.fetch_include(include).await? {
+                        if !content.is_empty() {
+                            output_sink.handle_chunk(content.as_bytes());
+                        }
+                    }
+                }
+                None => return Ok(()),
+            }
+        }
+    }
+
+    /// Finalizes the processing, awaits all the included resources and writes the output to the
+    /// `output_sink`.
+    ///
+    /// # Panics
+    ///  * In the same cases as [`HtmlRewriter::end`].
+    ///
+    /// [`HtmlRewriter::end`]: ../struct.HtmlRewriter.html#method.end
+    pub async fn end(mut self, mut output_sink: impl OutputSink) -> Result<(), EsiError> {
+        self.rewriter.end()?;
+        self.flush(&mut output_sink).await?;
+
+        output_sink.handle_chunk(&[]);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::future::ready;
+    use std::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
+
+    fn block_on<F: Future>(f: F) -> F::Output {
+        fn noop_raw_waker() -> RawWaker {
+            fn clone(_: *const ()) -> RawWaker {
+                noop_raw_waker()
+            }
+
+            fn noop(_: *const ()) {}
+
+            static VTABLE: RawWakerVTable = RawWakerVTable::new(clone, noop, noop, noop);
+
+            RawWaker::new(std::ptr::null(), &VTABLE)
+        }
+
+        let waker = unsafe { Waker::from_raw(noop_raw_waker()) };
+        let mut f = Box::pin(f);
+
+        loop {
+            if let Poll::Ready(v) = f.as_mut().poll(&mut Context::from_waker(&waker)) {
+                return v;
+            }
+        }
+    }
+
+    fn fetch(src: &str) -> FetchFuture {
+        let result: FetchResult = match src {
+            "/fail" => Err("Not found".into()),
+            _ => Ok(format!("[{}]", src)),
+        };
+
+        Box::pin(ready(result))
+    }
+
+    fn process(chunks: &[&str]) -> Result<String, EsiError> {
+        let mut output = vec![];
+        let mut processor = EsiProcessor::new(fetch);
+
+        for chunk in chunks {
+            processor.write(chunk.as_bytes())?;
+        }
+
+        block_on(processor.end(|c: &[u8]| output.extend_from_slice(c)))?;
+
+        Ok(String::from_utf8(output).unwrap())
+    }
+
+    #[test]
+    fn include() {
+        assert_eq!(
+            process(&[
+                r#"<div><esi:include src="/a"/><p>foo</p><esi:in"#,
+                r#"clude src="/b"></esi:include></div>"#
+            ])
+            .unwrap(),
+            "<div>[/a]<p>foo</p>[/b]</div>"
+        );
+    }
+
+    #[test]
+    fn include_alt() {
+        assert_eq!(
+            process(&[r#"<esi:include src="/fail" alt="/alt"/>"#]).unwrap(),
+            "[/alt]"
+        );
+    }
+
+    #[test]
+    fn include_error() {
+        assert_eq!(
+            process(&[r#"foo<esi:include src="/fail" onerror="continue"/>bar"#]).unwrap(),
+            "foobar"
+        );
+
+        match process(&[r#"<esi:include src="/fail" alt="/fail"/>"#]).unwrap_err() {
+            EsiError::Fetch { src, error } => {
+                assert_eq!(src, "/fail");
+                assert_eq!(error.to_string(), "Not found");
+            }
+            err => panic!("Unexpected error: {}", err),
+        }
+
+        match process(&["<esi:include/>"]).unwrap_err() {
+            EsiError::Rewriting(RewritingError::ContentHandlerError(err)) => {
+                assert_eq!(
+                    *err.downcast::<MissingIncludeSrcError>().unwrap(),
+                    MissingIncludeSrcError
+                );
+            }
+            err => panic!("Unexpected error: {}", err),
+        }
+    }
+
+    #[test]
+    fn remove_and_comments() {
+        assert_eq!(
+            process(&[
+                r#"<esi:remove><esi:include src="/a"/></esi:remove>"#,
+                "<!--esi<b>foo</b>--><!--esi <b>bar</b>--><!--esimate-->"
+            ])
+            .unwrap(),
+            "<!--esi<b>foo</b>--> <b>bar</b><!--esimate-->"
+        );
+    }
+
+    #[test]
+    fn flush() {
+        let mut output = vec![];
+        let mut processor = EsiProcessor::new(fetch);
+
+        processor
+            .write(br#"<p>foo</p><esi:include src="/a"/>"#)
+            .unwrap();
+        block_on(processor.flush(&mut |c: &[u8]| output.extend_from_slice(c))).unwrap();
+
+        assert_eq!(String::from_utf8(output.clone()).unwrap(), "<p>foo</p>[/a]");
+
+        processor.write(b"<p>bar</p>").unwrap();
+        block_on(processor.end(|c: &[u8]| output.extend_from_slice(c))).unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "<p>foo</p>[/a]<p>bar</p>"
+        );
+    }
+}
@@ -0,0 +1,52 @@
+// NOTE: https://html.spec.whatwg.org/multipage/custom-elements.html#valid-custom-element-name
+const RESERVED_NAMES: &[&str] = &[
+    "annotation-xml",
+    "color-profile",
+    "font-face",
+    "font-face-src",
+    "font-face-uri",
+    "font-face-format",
+    "font-face-name",
+    "missing-glyph",
+];
+
+#[inline]
+fn is_pcen_char(ch: char) -> bool {
+    matches!(ch,
+        '-' | '.' | '0'..='9' | '_' | 'a'..='z' | '\u{B7}'
+        | '\u{C0}'..='\u{D6}' | '\u{D8}'..='\u{F6}' | '\u{F8}'..='\u{37D}'
+        | '\u{37F}'..='\u{1FFF}' | '\u{200C}'..='\u{200D}' | '\u{203F}'..='\u{2040}'
+        | '\u{2070}'..='\u{218F}' | '\u{2C00}'..='\u{2FEF}' | '\u{3001}'..='\u{D7FF}'
+        | '\u{F900}'..='\u{FDCF}' | '\u{FDF0}'..='\u{FFFD}' | '\u{10000}'..='\u{EFFFF}'
+    )
+}
+
+/// Returns `true` if the `name` is a valid name of a custom element, e.g. `fancy-button`.
+pub fn is_valid_custom_element_name(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_lowercase())
+        && name.contains('-')
+        && name.chars().all(is_pcen_char)
+        && !RESERVED_NAMES.contains(&name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn custom_element_names() {
+        assert!(is_valid_custom_element_name("fancy-button"));
+        assert!(is_valid_custom_element_name("x-"));
+        assert!(is_valid_custom_element_name("math-α"));
+        assert!(is_valid_custom_element_name("my-el.v2_beta"));
+
+        assert!(!is_valid_custom_element_name("button"));
+        assert!(!is_valid_custom_element_name("-button"));
+        assert!(!is_valid_custom_element_name("1-button"));
+        assert!(!is_valid_custom_element_name("Fancy-button"));
+        assert!(!is_valid_custom_element_name("fancy-Button"));
+        assert!(!is_valid_custom_element_name("fancy button-"));
+        assert!(!is_valid_custom_element_name("font-face"));
+        assert!(!is_valid_custom_element_name(""));
+    }
+}
@@ -0,0 +1,408 @@
+use std::borrow::Cow;
+
+// NOTE: named character references defined by HTML 4.01 and `&apos;`. These cover the
+// overwhelming majority of the references used in the wild, while the full HTML5 table
+// contains more than two thousand entries. Sorted by name for the binary search.
+static NAMED_CHAR_REFS: &[(&str, char)] = &[
+    ("AElig", '\u{C6}'),
+    ("Aacute", '\u{C1}'),
+    ("Acirc", '\u{C2}'),
+    ("Agrave", '\u{C0}'),
+    ("Alpha", '\u{391}'),
+    ("Aring", '\u{C5}'),
+    ("Atilde", '\u{C3}'),
+    ("Auml", '\u{C4}'),
+    ("Beta", '\u{392}'),
+    ("Ccedil", '\u{C7}'),
+    ("Chi", '\u{3A7}'),
+    ("Dagger", '\u{2021}'),
+    ("Delta", '\u{394}'),
+    ("ETH", '\u{D0}'),
+    ("Eacute", '\u{C9}'),
+    ("Ecirc", '\u{CA}'),
+    ("Egrave", '\u{C8}'),
+    ("Epsilon", '\u{395}'),
+    ("Eta", '\u{397}'),
+    ("Euml", '\u{CB}'),
+    ("Gamma", '\u{393}'),
+    ("Iacute", '\u{CD}'),
+    ("Icirc", '\u{CE}'),
+    ("Igrave", '\u{CC}'),
+    ("Iota", '\u{399}'),
+    ("Iuml", '\u{CF}'),
+    ("Kappa", '\u{39A}'),
+    ("Lambda", '\u{39B}'),
+    ("Mu", '\u{39C}'),
+    ("Ntilde", '\u{D1}'),
+    ("Nu", '\u{39D}'),
+    ("OElig", '\u{152}'),
+    ("Oacute", '\u{D3}'),
+    ("Ocirc", '\u{D4}'),
+    ("Ograve", '\u{D2}'),
+    ("Omega", '\u{3A9}'),
+    ("Omicron", '\u{39F}'),
+    ("Oslash", '\u{D8}'),
+    ("Otilde", '\u{D5}'),
+    ("Ouml", '\u{D6}'),
+    ("Phi", '\u{3A6}'),
+    ("Pi", '\u{3A0}'),
+    ("Prime", '\u{2033}'),
+    ("Psi", '\u{3A8}'),
+    ("Rho", '\u{3A1}'),
+    ("Scaron", '\u{160}'),
+    ("Sigma", '\u{3A3}'),
+    ("THORN", '\u{DE}'),
+    ("Tau", '\u{3A4}'),
+    ("Theta", '\u{398}'),
+    ("Uacute", '\u{DA}'),
+    ("Ucirc", '\u{DB}'),
+    ("Ugrave", '\u{D9}'),
+    ("Upsilon", '\u{3A5}'),
+    ("Uuml", '\u{DC}'),
+    ("Xi", '\u{39E}'),
+    ("Yacute", '\u{DD}'),
+    ("Yuml", '\u{178}'),
+    ("Zeta", '\u{396}'),
+    ("aacute", '\u{E1}'),
+    ("acirc", '\u{E2}'),
+    ("acute", '\u{B4}'),
+    ("aelig", '\u{E6}'),
+    ("agrave", '\u{E0}'),
+    ("alefsym", '\u{2135}'),
+    ("alpha", '\u{3B1}'),
+    ("amp", '\u{26}'),
+    ("and", '\u{2227}'),
+    ("ang", '\u{2220}'),
+    ("apos", '\u{27}'),
+    ("aring", '\u{E5}'),
+    ("asymp", '\u{2248}'),
+    ("atilde", '\u{E3}'),
+    ("auml", '\u{E4}'),
+    ("bdquo", '\u{201E}'),
+    ("beta", '\u{3B2}'),
+    ("brvbar", '\u{A6}'),
+    ("bull", '\u{2022}'),
+    ("cap", '\u{2229}'),
+    ("ccedil", '\u{E7}'),
+    ("cedil", '\u{B8}'),
+    ("cent", '\u{A2}'),
+    ("chi", '\u{3C7}'),
+    ("circ", '\u{2C6}'),
+    ("clubs", '\u{2663}'),
+    ("cong", '\u{2245}'),
+    ("copy", '\u{A9}'),
+    ("crarr", '\u{21B5}'),
+    ("cup", '\u{222A}'),
+    ("curren", '\u{A4}'),
+    ("dArr", '\u{21D3}'),
+    ("dagger", '\u{2020}'),
+    ("darr", '\u{2193}'),
+    ("deg", '\u{B0}'),
+    ("delta", '\u{3B4}'),
+    ("diams", '\u{2666}'),
+    ("divide", '\u{F7}'),
+    ("eacute", '\u{E9}'),
+    ("ecirc", '\u{EA}'),
+    ("egrave", '\u{E8}'),
+    ("empty", '\u{2205}'),
+    ("emsp", '\u{2003}'),
+    ("ensp", '\u{2002}'),
+    ("epsilon", '\u{3B5}'),
+    ("equiv", '\u{2261}'),
+    ("eta", '\u{3B7}'),
+    ("eth", '\u{F0}'),
+    ("euml", '\u{EB}'),
+    ("euro", '\u{20AC}'),
+    ("exist", '\u{2203}'),
+    ("fnof", '\u{192}'),
+    ("forall", '\u{2200}'),
+    ("frac12", '\u{BD}'),
+    ("frac14", '\u{BC}'),
+    ("frac34", '\u{BE}'),
+    ("frasl", '\u{2044}'),
+    ("gamma", '\u{3B3}'),
+    ("ge", '\u{2265}'),
+    ("gt", '\u{3E}'),
+    ("hArr", '\u{21D4}'),
+    ("harr", '\u{2194}'),
+    ("hearts", '\u{2665}'),
+    ("hellip", '\u{2026}'),
+    ("iacute", '\u{ED}'),
+    ("icirc", '\u{EE}'),
+    ("iexcl", '\u{A1}'),
+    ("igrave", '\u{EC}'),
+    ("image", '\u{2111}'),
+    ("infin", '\u{221E}'),
+    ("int", '\u{222B}'),
+    ("iota", '\u{3B9}'),
+    ("iquest", '\u{BF}'),
+    ("isin", '\u{2208}'),
+    ("iuml", '\u{EF}'),
+    ("kappa", '\u{3BA}'),
+    ("lArr", '\u{21D0}'),
+    ("lambda", '\u{3BB}'),
+    ("lang", '\u{2329}'),
+    ("laquo", '\u{AB}'),
+    ("larr", '\u{2190}'),
+    ("lceil", '\u{2308}'),
+    ("ldquo", '\u{201C}'),
+    ("le", '\u{2264}'),
+    ("lfloor", '\u{230A}'),
+    ("lowast", '\u{2217}'),
+    ("loz", '\u{25CA}'),
+    ("lrm", '\u{200E}'),
+    ("lsaquo", '\u{2039}'),
+    ("lsquo", '\u{2018}'),
+    ("lt", '\u{3C}'),
+    ("macr", '\u{AF}'),
+    ("mdash", '\u{2014}'),
+    ("micro", '\u{B5}'),
+    ("middot", '\u{B7}'),
+    ("minus", '\u{2212}'),
+    ("mu", '\u{3BC}'),
+    ("nabla", '\u{2207}'),
+    ("nbsp", '\u{A0}'),
+    ("ndash", '\u{2013}'),
+    ("ne", '\u{2260}'),
+    ("ni", '\u{220B}'),
+    ("not", '\u{AC}'),
+    ("notin", '\u{2209}'),
+    ("nsub", '\u{2284}'),
+    ("ntilde", '\u{F1}'),
+    ("nu", '\u{3BD}'),
+    ("oacute", '\u{F3}'),
+    ("ocirc", '\u{F4}'),
+    ("oelig", '\u{153}'),
+    ("ograve", '\u{F2}'),
+    ("oline", '\u{203E}'),
+    ("omega", '\u{3C9}'),
+    ("omicron", '\u{3BF}'),
+    ("oplus", '\u{2295}'),
+    ("or", '\u{2228}'),
+    ("ordf", '\u{AA}'),
+    ("ordm", '\u{BA}'),
+    ("oslash", '\u{F8}'),
+    ("otilde", '\u{F5}'),
+    ("otimes", '\u{2297}'),
+    ("ouml", '\u{F6}'),
+    ("para", '\u{B6}'),
+    ("part", '\u{2202}'),
+    ("permil", '\u{2030}'),
+    ("perp", '\u{22A5}'),
+    ("phi", '\u{3C6}'),
+    ("pi", '\u{3C0}'),
+    ("piv", '\u{3D6}'),
+    ("plusmn", '\u{B1}'),
+    ("pound", '\u{A3}'),
+    ("prime", '\u{2032}'),
+    ("prod", '\u{220F}'),
+    ("prop", '\u{221D}'),
+    ("psi", '\u{3C8}'),
+    ("quot", '\u{22}'),
+    ("rArr", '\u{21D2}'),
+    ("radic", '\u{221A}'),
+    ("rang", '\u{232A}'),
+    ("raquo", '\u{BB}'),
+    ("rarr", '\u{2192}'),
+    ("rceil", '\u{2309}'),
+    ("rdquo", '\u{201D}'),
+    ("real", '\u{211C}'),
+    ("reg", '\u{AE}'),
+    ("rfloor", '\u{230B}'),
+    ("rho", '\u{3C1}'),
+    ("rlm", '\u{200F}'),
+    ("rsaquo", '\u{203A}'),
+    ("rsquo", '\u{2019}'),
+    ("sbquo", '\u{201A}'),
+    ("scaron", '\u{161}'),
+    ("sdot", '\u{22C5}'),
+    ("sect", '\u{A7}'),
+    ("shy", '\u{AD}'),
+    ("sigma", '\u{3C3}'),
+    ("sigmaf", '\u{3C2}'),
+    ("sim", '\u{223C}'),
+    ("spades", '\u{2660}'),
+    ("sub", '\u{2282}'),
+    ("sube", '\u{2286}'),
+    ("sum", '\u{2211}'),
+    ("sup", '\u{2283}'),
+    ("sup1", '\u{B9}'),
+    ("sup2", '\u{B2}'),
+    ("sup3", '\u{B3}'),
+    ("supe", '\u{2287}'),
+    ("szlig", '\u{DF}'),
+    ("tau", '\u{3C4}'),
+    ("there4", '\u{2234}'),
+    ("theta", '\u{3B8}'),
+    ("thetasym", '\u{3D1}'),
+    ("thinsp", '\u{2009}'),
+    ("thorn", '\u{FE}'),
+    ("tilde", '\u{2DC}'),
+    ("times", '\u{D7}'),
+    ("trade", '\u{2122}'),
+    ("uArr", '\u{21D1}'),
+    ("uacute", '\u{FA}'),
+    ("uarr", '\u{2191}'),
+    ("ucirc", '\u{FB}'),
+    ("ugrave", '\u{F9}'),
+    ("uml", '\u{A8}'),
+    ("upsih", '\u{3D2}'),
+    ("upsilon", '\u{3C5}'),
+    ("uuml", '\u{FC}'),
+    ("weierp", '\u{2118}'),
+    ("xi", '\u{3BE}'),
+    ("yacute", '\u{FD}'),
+    ("yen", '\u{A5}'),
+    ("yuml", '\u{FF}'),
+    ("zeta", '\u{3B6}'),
+    ("zwj", '\u{200D}'),
+    ("zwnj", '\u{200C}'),
+];
+
+// NOTE: numeric character references in the 0x80..=0x9F range are interpreted as
+// windows-1252 characters, as defined by the HTML parsing specification.
+static C1_CONTROL_REPLACEMENTS: [char; 32] = [
+    '\u{20AC}', '\u{81}', '\u{201A}', '\u{192}', '\u{201E}', '\u{2026}', '\u{2020}', '\u{2021}',
+    '\u{2C6}', '\u{2030}', '\u{160}', '\u{2039}', '\u{152}', '\u{8D}', '\u{17D}', '\u{8F}',
+    '\u{90}', '\u{2018}', '\u{2019}', '\u{201C}', '\u{201D}', '\u{2022}', '\u{2013}', '\u{2014}',
+    '\u{2DC}', '\u{2122}', '\u{161}', '\u{203A}', '\u{153}', '\u{9D}', '\u{17E}', '\u{178}',
+];
+
+#[inline]
+fn numeric_char_ref_to_char(code_point: u32) -> char {
+    match code_point {
+        0x80..=0x9F => C1_CONTROL_REPLACEMENTS[(code_point - 0x80) as usize],
+        0 => '\u{FFFD}',
+        _ => std::char::from_u32(code_point).unwrap_or('\u{FFFD}'),
+    }
+}
+
+/// Decodes the character reference at the beginning of the `input` that follows the `&`
+/// character. Returns the decoded character and the length of the reference in the `input`.
+fn decode_char_ref(input: &str) -> Option<(char, usize)> {
+    let bytes = input.as_bytes();
+
+    if bytes.first() == Some(&b'#') {
+        let (radix, digits_start) = match bytes.get(1) {
+            Some(b'x') | Some(b'X') => (16, 2),
+            _ => (10, 1),
+        };
+
+        let digits_len = input[digits_start..]
+            .find(|c: char| !c.is_digit(radix))
+            .unwrap_or(input.len() - digits_start);
+
+        if digits_len == 0 {
+            return None;
+        }
+
+        let mut len = digits_start + digits_len;
+
+        // NOTE: out of range code points are replaced with U+FFFD, so we can saturate.
+        let code_point = input[digits_start..len].chars().fold(0u32, |cp, c| {
+            cp.saturating_mul(radix)
+                .saturating_add(c.to_digit(radix).unwrap_or(0))
+        });
+
+        // NOTE: the semicolon is optional for numeric references.
+        if bytes.get(len) == Some(&b';') {
+            len += 1;
+        }
+
+        Some((numeric_char_ref_to_char(code_point), len))
+    } else {
+        let name_len = input
+            .find(|c: char| !c.is_ascii_alphanumeric())
+            .unwrap_or(input.len());
+
+        if bytes.get(name_len) != Some(&b';') {
+            return None;
+        }
+
+        let name = &input[..name_len];
+
+        NAMED_CHAR_REFS
+            .binary_search_by_key(&name, |&(n, _)| n)
+            .ok()
+            .map(|idx| (NAMED_CHAR_REFS[idx].1, name_len + 1))
+    }
+}
+
+/// Decodes character references in the `text`.
+///
+/// Numeric references and the named references defined by HTML 4.01 are supported. Named
+/// references without a trailing semicolon and unknown references are left intact.
+pub fn decode_char_refs(text: &str) -> Cow<str> {
+    let mut amp_pos = match text.find('&') {
+        Some(pos) => pos,
+        None => return Cow::Borrowed(text),
+    };
+
+    let mut decoded = String::with_capacity(text.len());
+    let mut last_pos = 0;
+
+    loop {
+        decoded.push_str(&text[last_pos..amp_pos]);
+
+        let ref_start = amp_pos + 1;
+
+        match decode_char_ref(&text[ref_start..]) {
+            Some((ch, len)) => {
+                decoded.push(ch);
+                last_pos = ref_start + len;
+            }
+            None => {
+                decoded.push('&');
+                last_pos = ref_start;
+            }
+        }
+
+        match text[last_pos..].find('&') {
+            Some(pos) => amp_pos = last_pos + pos,
+            None => break,
+        }
+    }
+
+    decoded.push_str(&text[last_pos..]);
+
+    Cow::Owned(decoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn named_char_refs_are_sorted() {
+        assert!(NAMED_CHAR_REFS.windows(2).all(|w| w[0].0 < w[1].0));
+    }
+
+    #[test]
+    fn decode() {
+        assert_eq!(decode_char_refs("foo bar"), "foo bar");
+        assert_eq!(
+            decode_char_refs("&lt;a&gt; &amp;&amp; &quot;b&apos;"),
+            "<a> && \"b'"
+        );
+        assert_eq!(
+            decode_char_refs("&copy;&nbsp;&Eacute;&eacute;"),
+            "\u{A9}\u{A0}\u{C9}\u{E9}"
+        );
+        assert_eq!(
+            decode_char_refs("&#65;&#x42;&#X43&#x1F600;"),
+            "ABC\u{1F600}"
+        );
+        assert_eq!(
+            decode_char_refs("&#128;&#0;&#xD800;&#99999999999;"),
+            "\u{20AC}\u{FFFD}\u{FFFD}\u{FFFD}"
+        );
+    }
+
+    #[test]
+    fn invalid_char_refs() {
+        assert_eq!(
+            decode_char_refs("a & b &amp c &foo; &#; &#x;&"),
+            "a & b &amp c &foo; &#; &#x;&"
+        );
+    }
+}
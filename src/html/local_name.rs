@@ -1,6 +1,11 @@
 use super::Tag;
-use crate::base::{Bytes, HasReplacementsError, Range};
+#[cfg(feature = "element_handlers")]
+use crate::base::HasReplacementsError;
+use crate::base::{Bytes, Range};
+#[cfg(feature = "element_handlers")]
 use encoding_rs::Encoding;
+use std::hash::{Hash, Hasher};
+use std::mem;
 
 // NOTE: All standard tag names contain only ASCII alpha characters
 // and digits from 1 to 6 (in numbered header tags, i.e. <h1> - <h6>).
@@ -70,6 +75,29 @@ impl LocalNameHash {
             };
         }
     }
+
+    /// Decodes the lowercased name the hash was produced from.
+    #[cfg(feature = "element_handlers")]
+    #[inline]
+    pub fn decode(&self) -> Option<String> {
+        let mut h = self.0?;
+        let mut name = Vec::new();
+
+        // NOTE: names can't start with a digit, so the leftmost character is never
+        // encoded as zero and we can stop once there are no more bits set in the hash.
+        while h != 0 {
+            name.push(match (h & 0x1F) as u8 {
+                ch @ 0..=5 => b'1' + ch,
+                ch => b'a' + ch - 6,
+            });
+
+            h >>= 5;
+        }
+
+        name.reverse();
+
+        String::from_utf8(name).ok()
+    }
 }
 
 impl From<&str> for LocalNameHash {
@@ -97,8 +125,12 @@ impl PartialEq<Tag> for LocalNameHash {
 
 /// LocalName is used for the comparison of tag names.
 /// In the majority of cases it will be represented as a hash, however for long
-/// non-standard tag names it fallsback to the Name representation.
-#[derive(Clone, Debug, Eq, Hash)]
+/// non-standard tag names (e.g. custom element names that contain hyphens, dots,
+/// underscores or non-ASCII characters) it fallsback to the Name representation.
+///
+/// Both representations are obtained from the bytes of the name in the same way,
+/// so a name is always represented the same regardless of where it comes from.
+#[derive(Clone, Debug, Eq)]
 pub enum LocalName<'i> {
     Hash(LocalNameHash),
     Bytes(Bytes<'i>),
@@ -114,6 +146,7 @@ impl<'i> LocalName<'i> {
         }
     }
 
+    #[cfg(feature = "element_handlers")]
     #[inline]
     pub fn into_owned(self) -> LocalName<'static> {
         match self {
@@ -122,6 +155,7 @@ impl<'i> LocalName<'i> {
         }
     }
 
+    #[cfg(feature = "element_handlers")]
     #[inline]
     pub fn from_str_without_replacements<'s>(
         string: &'s str,
@@ -135,6 +169,16 @@ impl<'i> LocalName<'i> {
             Ok(LocalName::Hash(hash))
         }
     }
+
+    /// Decodes the ASCII-lowercased name.
+    #[cfg(feature = "element_handlers")]
+    #[inline]
+    pub fn as_lowercase_string(&self, encoding: &'static Encoding) -> String {
+        match self {
+            LocalName::Hash(h) => h.decode().unwrap_or_default(),
+            LocalName::Bytes(b) => b.as_lowercase_string(encoding),
+        }
+    }
 }
 
 impl PartialEq<Tag> for LocalName<'_> {
@@ -160,6 +204,22 @@ impl PartialEq<LocalName<'_>> for LocalName<'_> {
     }
 }
 
+// NOTE: names in the byte representation are compared ASCII case-insensitively, so they
+// should be hashed the same way to be usable as keys of hash maps.
+impl Hash for LocalName<'_> {
+    #[inline]
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        mem::discriminant(self).hash(state);
+
+        match self {
+            LocalName::Hash(h) => h.hash(state),
+            LocalName::Bytes(b) => b
+                .iter()
+                .for_each(|b| state.write_u8(b.to_ascii_lowercase())),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -169,6 +229,16 @@ mod tests {
         assert_eq!(LocalNameHash::from("div"), LocalNameHash(Some(9691u64)));
     }
 
+    #[test]
+    fn decode() {
+        ["div", "h1", "h6", "blockquote", "a"]
+            .iter()
+            .for_each(|&name| assert_eq!(LocalNameHash::from(name).decode().unwrap(), name));
+
+        assert_eq!(LocalNameHash::from("DiV").decode().unwrap(), "div");
+        assert_eq!(LocalNameHash::from("my-element").decode(), None);
+    }
+
     #[test]
     fn hash_invalidation_for_non_ascii_chars() {
         assert!(LocalNameHash::from("div@&").is_empty());
@@ -178,4 +248,39 @@ mod tests {
     fn hash_invalidation_for_long_values() {
         assert!(LocalNameHash::from("aaaaaaaaaaaaaa").is_empty());
     }
+
+    #[test]
+    fn custom_element_names() {
+        use encoding_rs::UTF_8;
+        use std::collections::hash_map::DefaultHasher;
+
+        let local_name = |name| LocalName::from_str_without_replacements(name, UTF_8).unwrap();
+
+        let hash = |name: &LocalName| {
+            let mut hasher = DefaultHasher::new();
+
+            name.hash(&mut hasher);
+            hasher.finish()
+        };
+
+        [
+            ("my-element", "MY-Element"),
+            ("x-foo.bar_baz", "X-Foo.Bar_Baz"),
+            ("math-\u{3b1}", "MATH-\u{3b1}"),
+            ("longcustomname", "LongCustomName"),
+        ]
+        .iter()
+        .for_each(|&(name, other_case_name)| {
+            let name = local_name(name);
+            let other_case_name = local_name(other_case_name);
+
+            assert!(matches!(name, LocalName::Bytes(_)));
+            assert_eq!(name, other_case_name);
+            assert_eq!(hash(&name), hash(&other_case_name));
+        });
+
+        // NOTE: only ASCII characters are case-insensitive in tag names.
+        assert_ne!(local_name("x-\u{e9}"), local_name("x-\u{c9}"));
+        assert_ne!(local_name("my-element"), local_name("my-elements"));
+    }
 }
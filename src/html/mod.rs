@@ -1,10 +1,14 @@
 #[macro_use]
 mod tag;
 
+mod custom_elements;
+mod entities;
 mod local_name;
 mod namespace;
 mod text_type;
 
+pub use self::custom_elements::is_valid_custom_element_name;
+pub use self::entities::decode_char_refs;
 pub use self::local_name::{LocalName, LocalNameHash};
 pub use self::namespace::Namespace;
 pub use self::tag::*;
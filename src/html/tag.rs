@@ -1,7 +1,9 @@
 macro_rules! declare_tags {
     ($($name:ident = $val:expr),+) => {
+        // NOTE: some of the tags are only used by the selector matching VM.
         #[repr(u64)]
         #[derive(Debug, Copy, Clone)]
+        #[cfg_attr(not(feature = "element_handlers"), allow(dead_code))]
         pub enum Tag {
             $($name = $val),+
         }
@@ -26,6 +26,9 @@ mod html;
 #[macro_use]
 mod rewriter;
 
+#[cfg(feature = "esi")]
+pub mod esi;
+
 mod memory;
 mod parser;
 mod rewritable_units;
@@ -34,34 +37,81 @@ mod transform_stream;
 use cfg_if::cfg_if;
 
 pub use self::rewriter::{
-    rewrite_str, DocumentContentHandlers, ElementContentHandlers, HtmlRewriter, MemorySettings,
-    RewriteStrSettings, Settings,
+    html_to_text, rewrite_str, DocumentBoundary, DocumentBoundaryDetector, DocumentContentHandlers,
+    DocumentDelimiter, ElementContentHandlers, HtmlRewriter, MemorySettings, MergedHandlers,
+    MultiDocumentRewriter, ObservabilityHooks, ProgressEvent, Recording, RewriteStrSettings,
+    Settings, SlotRewriter, StructureValidator, TextExtractor, TokenRecorder, TokenTransformer,
+    ValidationIssue, ValidationReport, WhitespaceSensitiveElements,
+};
+
+#[cfg(feature = "element_handlers")]
+pub use self::rewriter::{
+    custom_element_definitions, detect_selector_match, extract_metadata, prefixed_attributes,
+    truncate_content, AuditedUrl, CustomElementDefinition, LinkAuditor, Metadata,
+    MetadataCollector, MetadataLink, RuntimeHandlers, RuntimeHandlersHandle, SelectorDetector,
+};
+
+pub use self::rewriter::TwoPassRewriter;
+#[cfg(feature = "element_handlers")]
+pub use self::selectors_vm::{
+    AttributeCaseSensitivity, AttributeOperator, PseudoClass, PseudoClassElement,
+    PseudoClassPredicate, PseudoElementKind, Selector, SelectorBuilder, SelectorCache,
+    SelectorCombinator, SelectorLimits, SelectorPart, SelectorTemplate,
+};
+pub use self::transform_stream::{
+    BodyEvent, BodyFraming, ContentLengthSink, OutputSink, Patch, PatchSink,
 };
-pub use self::selectors_vm::Selector;
-pub use self::transform_stream::OutputSink;
 
 /// The errors that can be produced by the crate's API.
 pub mod errors {
     pub use super::memory::MemoryLimitExceededError;
     pub use super::parser::ParsingAmbiguityError;
-    pub use super::rewritable_units::{AttributeNameError, CommentTextError, TagNameError};
-    pub use super::rewriter::{EncodingError, RewritingError};
-    pub use super::selectors_vm::SelectorError;
+    pub use super::rewritable_units::{
+        AttributeNameError, ClassNameError, CommentTextError, DoctypeError, StylePropertyError,
+        TagNameError,
+    };
+    pub use super::rewriter::{
+        EncodingError, MultiDocumentRewritingError, ReplayError, RewritingError,
+    };
+
+    pub use super::rewriter::TwoPassRewritingError;
+    #[cfg(feature = "element_handlers")]
+    pub use super::selectors_vm::{
+        SelectorBindingError, SelectorDiagnostic, SelectorError, XPathError,
+    };
+    #[cfg(feature = "element_handlers")]
+    pub use super::rewriter::RuntimeHandlersError;
 }
 
 /// HTML content descriptors that can be produced and modified by a rewriter.
 pub mod html_content {
     pub use super::rewritable_units::{
-        Attribute, Comment, ContentType, Doctype, DocumentEnd, Element, TextChunk, UserData,
+        Attribute, Comment, ContentType, Doctype, DocumentEnd, Element, ElementNode, EndTag, Node,
+        Style, Subtree, TextChunk, TokenKind, UserData,
     };
 
+    #[cfg(feature = "markdown")]
+    pub use super::rewritable_units::markdown_to_html;
+
     pub use super::html::TextType;
 }
 
-#[cfg(any(test, feature = "integration_test"))]
+/// Utilities for testing the output of the rewriter.
+///
+/// Available with the `test_support` feature, so that handler sets can be checked against golden
+/// outputs and for the independence from the input chunk boundaries in the test suites of the
+/// dependent crates.
+#[cfg(any(test, feature = "integration_test", feature = "test_support"))]
 pub mod test_utils {
     use encoding_rs::*;
 
+    mod chunked;
+    mod golden;
+
+    pub use self::chunked::{assert_chunk_boundary_safe, for_each_chunked_input, ChunkedInput};
+    pub use self::golden::{assert_golden_output, golden_fixtures, GoldenFixture};
+
+    /// The encodings the rewriter can operate in.
     pub static ASCII_COMPATIBLE_ENCODINGS: [&Encoding; 36] = [
         BIG5,
         EUC_JP,
@@ -101,6 +151,7 @@ pub mod test_utils {
         X_USER_DEFINED,
     ];
 
+    /// A collector for the rewriter output that checks that the output is properly finalized.
     pub struct Output {
         bytes: Vec<u8>,
         encoding: &'static Encoding,
@@ -108,6 +159,7 @@ pub mod test_utils {
     }
 
     impl Output {
+        /// Creates a new collector for the output in the `encoding`.
         pub fn new(encoding: &'static Encoding) -> Self {
             Output {
                 bytes: Vec::default(),
@@ -116,6 +168,7 @@ pub mod test_utils {
             }
         }
 
+        /// Appends a chunk of the output.
         pub fn push(&mut self, chunk: &[u8]) {
             if chunk.is_empty() {
                 self.finalizing_chunk_received = true;
@@ -162,6 +215,7 @@ cfg_if! {
         pub use self::memory::MemoryLimiter;
         pub use self::html::{LocalName, LocalNameHash, Tag, Namespace};
     } else {
+        #[cfg(feature = "element_handlers")]
         mod selectors_vm;
     }
 }
@@ -1,12 +1,17 @@
 use super::{MemoryLimitExceededError, SharedMemoryLimiter};
 use safemem::copy_over;
 
-/// Preallocated region of memory that can grow and never deallocates during the lifetime of
-/// the limiter.
+/// Preallocated region of memory that can grow and, unless the shrink watermark is set, never
+/// deallocates during the lifetime of the limiter.
 #[derive(Debug)]
 pub struct Arena {
     limiter: SharedMemoryLimiter,
     data: Vec<u8>,
+    start: usize,
+    preallocated_size: usize,
+    reserved_size: usize,
+    compaction_watermark: usize,
+    shrink_watermark: usize,
 }
 
 impl Arena {
@@ -16,19 +21,47 @@ impl Arena {
         Arena {
             limiter,
             data: Vec::with_capacity(preallocated_size),
+            start: 0,
+            preallocated_size,
+            reserved_size: preallocated_size,
+            compaction_watermark: 0,
+            shrink_watermark: 0,
         }
     }
 
+    /// Sets the number of shifted out bytes that the arena can hold before it moves the
+    /// remaining bytes to the beginning of the allocated memory.
+    #[inline]
+    pub fn with_compaction_watermark(mut self, watermark: usize) -> Self {
+        self.compaction_watermark = watermark;
+        self
+    }
+
+    /// Sets the capacity of the arena above which it's shrunk back to the preallocated size
+    /// once it's reset. Zero disables shrinking.
+    #[inline]
+    pub fn with_shrink_watermark(mut self, watermark: usize) -> Self {
+        self.shrink_watermark = watermark;
+        self
+    }
+
     pub fn append(&mut self, slice: &[u8]) -> Result<(), MemoryLimitExceededError> {
-        let new_len = self.data.len() + slice.len();
+        let mut new_len = self.data.len() + slice.len();
         let capacity = self.data.capacity();
 
+        if new_len > capacity && self.start > 0 {
+            // NOTE: reuse the space occupied by the shifted out bytes before growing.
+            self.compact();
+            new_len = self.data.len() + slice.len();
+        }
+
         if new_len > capacity {
             let additional = new_len - capacity;
 
             // NOTE: approximate usage, as `Vec::reserve_exact` doesn't
             // give guarantees about exact capacity value :).
             self.limiter.borrow_mut().increase_usage(additional)?;
+            self.reserved_size += additional;
 
             // NOTE: with wicely choosen preallocated size this branch should be
             // executed quite rarely. We can't afford to use double capacity
@@ -44,18 +77,46 @@ impl Arena {
 
     pub fn init_with(&mut self, slice: &[u8]) -> Result<(), MemoryLimitExceededError> {
         self.data.clear();
+        self.start = 0;
         self.append(slice)
     }
 
     pub fn shift(&mut self, byte_count: usize) {
-        let remainder_len = self.data.len() - byte_count;
+        self.start += byte_count;
 
-        copy_over(&mut self.data, byte_count, 0, remainder_len);
-        self.data.truncate(remainder_len);
+        if self.start >= self.compaction_watermark {
+            self.compact();
+        }
+    }
+
+    /// Discards the content of the arena and, if its capacity has grown beyond the shrink
+    /// watermark, releases the memory allocated in excess of the preallocated size.
+    pub fn reset(&mut self) {
+        self.data.clear();
+        self.start = 0;
+
+        if self.shrink_watermark > 0 && self.data.capacity() > self.shrink_watermark {
+            self.data = Vec::with_capacity(self.preallocated_size);
+
+            self.limiter
+                .borrow_mut()
+                .decrease_usage(self.reserved_size - self.preallocated_size);
+
+            self.reserved_size = self.preallocated_size;
+        }
     }
 
     pub fn bytes(&self) -> &[u8] {
-        &self.data
+        &self.data[self.start..]
+    }
+
+    #[inline]
+    fn compact(&mut self) {
+        let remainder_len = self.data.len() - self.start;
+
+        copy_over(&mut self.data, self.start, 0, remainder_len);
+        self.data.truncate(remainder_len);
+        self.start = 0;
     }
 }
 
@@ -136,4 +197,54 @@ mod tests {
         assert_eq!(arena.bytes(), &[2, 3, 4, 5]);
         assert_eq!(limiter.borrow().current_usage(), 5);
     }
+
+    #[test]
+    fn compaction_watermark() {
+        let limiter = MemoryLimiter::new_shared(10);
+        let mut arena = Arena::new(Rc::clone(&limiter), 0).with_compaction_watermark(3);
+
+        arena.append(&[0, 1, 2, 3]).unwrap();
+        arena.shift(2);
+        assert_eq!(arena.bytes(), &[2, 3]);
+        assert_eq!(arena.start, 2);
+
+        arena.shift(1);
+        assert_eq!(arena.bytes(), &[3]);
+        assert_eq!(arena.start, 0);
+
+        arena.append(&[4, 5]).unwrap();
+        arena.shift(1);
+        assert_eq!(arena.bytes(), &[4, 5]);
+        assert_eq!(limiter.borrow().current_usage(), 4);
+
+        // NOTE: shifted out bytes are discarded before the arena grows.
+        arena.append(&[6, 7, 8]).unwrap();
+        assert_eq!(arena.bytes(), &[4, 5, 6, 7, 8]);
+        assert_eq!(arena.start, 0);
+        assert_eq!(limiter.borrow().current_usage(), 5);
+    }
+
+    #[test]
+    fn reset() {
+        let limiter = MemoryLimiter::new_shared(10);
+        let mut arena = Arena::new(Rc::clone(&limiter), 2);
+
+        arena.append(&[0, 1, 2, 3, 4]).unwrap();
+        arena.reset();
+        assert_eq!(arena.bytes(), &[]);
+        assert_eq!(limiter.borrow().current_usage(), 5);
+
+        let mut arena = Arena::new(Rc::clone(&limiter), 2).with_shrink_watermark(4);
+
+        arena.append(&[0, 1, 2, 3]).unwrap();
+        arena.reset();
+        assert_eq!(limiter.borrow().current_usage(), 9);
+
+        arena.append(&[0, 1, 2, 3, 4]).unwrap();
+        assert_eq!(limiter.borrow().current_usage(), 10);
+
+        arena.reset();
+        assert_eq!(arena.bytes(), &[]);
+        assert_eq!(limiter.borrow().current_usage(), 7);
+    }
 }
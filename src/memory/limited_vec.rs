@@ -20,6 +20,15 @@ impl<T> LimitedVec<T> {
         }
     }
 
+    /// Creates a vector with the preallocated `capacity`. Only the pushed elements are
+    /// counted against the memory limit.
+    pub fn with_capacity(limiter: SharedMemoryLimiter, capacity: usize) -> Self {
+        LimitedVec {
+            vec: Vec::with_capacity(capacity),
+            limiter,
+        }
+    }
+
     pub fn push(&mut self, element: T) -> Result<(), MemoryLimitExceededError> {
         self.limiter.borrow_mut().increase_usage(size_of::<T>())?;
         self.vec.push(element);
@@ -44,6 +53,12 @@ impl<T> LimitedVec<T> {
         self.vec.last_mut()
     }
 
+    /// Returns an iterator that allows modifying each element.
+    #[inline]
+    pub fn iter_mut(&mut self) -> std::slice::IterMut<T> {
+        self.vec.iter_mut()
+    }
+
     /// Creates a draining iterator that removes the specified range in the
     /// vector and yields the removed items.
     pub fn drain<R>(&mut self, range: R) -> Drain<T>
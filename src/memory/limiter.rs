@@ -26,6 +26,11 @@ impl MemoryLimiter {
         }))
     }
 
+    #[inline]
+    pub fn set_max(&mut self, max: usize) {
+        self.max = max;
+    }
+
     #[cfg(test)]
     pub fn current_usage(&self) -> usize {
         self.current_usage
@@ -80,6 +85,22 @@ mod tests {
         assert_eq!(err, MemoryLimitExceededError);
     }
 
+    #[test]
+    fn set_max() {
+        let limiter = MemoryLimiter::new_shared(10);
+        let mut limiter = limiter.borrow_mut();
+
+        limiter.increase_usage(8).unwrap();
+        limiter.set_max(20);
+        limiter.increase_usage(8).unwrap();
+
+        limiter.set_max(10);
+
+        let err = limiter.increase_usage(1).unwrap_err();
+
+        assert_eq!(err, MemoryLimitExceededError);
+    }
+
     #[test]
     #[should_panic(
         expected = "Total preallocated memory size should be less than `MemorySettings::max_allowed_memory_usage`."
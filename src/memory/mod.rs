@@ -1,7 +1,10 @@
 mod arena;
+#[cfg(feature = "element_handlers")]
 mod limited_vec;
 mod limiter;
+pub mod profile;
 
 pub use arena::Arena;
+#[cfg(feature = "element_handlers")]
 pub use limited_vec::LimitedVec;
 pub use limiter::{MemoryLimitExceededError, MemoryLimiter, SharedMemoryLimiter};
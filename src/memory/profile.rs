@@ -0,0 +1,40 @@
+//! Initial sizes of the internal buffers.
+//!
+//! The sizes are selected at compile time with the `profile_tiny` and `profile_server` features.
+//! The tiny profile minimises memory that is allocated on rewriter instantiation, e.g. for
+//! memory-constrained embedded deployments, while the server profile preallocates more to avoid
+//! reallocations on large documents. If none of the features is enabled, the default profile
+//! is used.
+//!
+//! The features are additive, as any of the crates in the dependency graph may enable them. If
+//! both are enabled, the tiny profile takes precedence, so the memory constraints of
+//! the deployment are not lifted by a dependency that prefers the server profile.
+
+use cfg_if::cfg_if;
+
+cfg_if! {
+    if #[cfg(feature = "profile_tiny")] {
+        pub const NS_STACK_CAPACITY: usize = 16;
+        pub const INLINE_ATTRIBUTE_CAPACITY: usize = 4;
+        pub const TEXT_DECODER_BUFFER_SIZE: usize = 256;
+        #[cfg(feature = "element_handlers")]
+        pub const SELECTOR_STACK_CAPACITY: usize = 0;
+    } else if #[cfg(feature = "profile_server")] {
+        pub const NS_STACK_CAPACITY: usize = 1024;
+        pub const INLINE_ATTRIBUTE_CAPACITY: usize = 16;
+        pub const TEXT_DECODER_BUFFER_SIZE: usize = 4096;
+        #[cfg(feature = "element_handlers")]
+        pub const SELECTOR_STACK_CAPACITY: usize = 64;
+    } else {
+        /// Initial capacity of the tree builder simulator's namespace stack.
+        pub const NS_STACK_CAPACITY: usize = 256;
+        /// The number of start tag attributes that are stored without a heap allocation, both
+        /// by the lexer and by the start tags passed to the content handlers.
+        pub const INLINE_ATTRIBUTE_CAPACITY: usize = 8;
+        /// Size of the buffer that is used to decode text chunks.
+        pub const TEXT_DECODER_BUFFER_SIZE: usize = 1024;
+        /// Initial capacity of the selector matching VM's stack of open elements.
+        #[cfg(feature = "element_handlers")]
+        pub const SELECTOR_STACK_CAPACITY: usize = 0;
+    }
+}
@@ -62,6 +62,10 @@ impl<S: LexemeSink> StateMachineActions for Lexer<S> {
             .try_get_tree_builder_feedback(&token)
             .map_err(ActionError::from)?;
 
+        if let StartTag { .. } = token {
+            self.attr_buffer.borrow_mut().finish_section(self.pos());
+        }
+
         let mut lexeme = self.create_lexeme_with_raw_inclusive(input, token);
 
         // NOTE: exit from any non-initial text parsing mode always happens on tag emission
@@ -122,8 +126,6 @@ impl<S: LexemeSink> StateMachineActions for Lexer<S> {
 
     #[inline]
     fn create_start_tag(&mut self, _input: &[u8]) {
-        self.attr_buffer.borrow_mut().clear();
-
         self.current_tag_token = Some(StartTag {
             name: Range::default(),
             name_hash: LocalNameHash::new(),
@@ -216,9 +218,14 @@ impl<S: LexemeSink> StateMachineActions for Lexer<S> {
     #[inline]
     fn finish_tag_name(&mut self, _input: &[u8]) -> ActionResult {
         match self.current_tag_token {
-            Some(StartTag { ref mut name, .. }) | Some(EndTag { ref mut name, .. }) => {
-                *name = get_token_part_range!(self)
+            Some(StartTag { ref mut name, .. }) => {
+                *name = get_token_part_range!(self);
+
+                // NOTE: attributes are split into outlines only if they are requested,
+                // so we just need to remember where they start.
+                self.attr_buffer.borrow_mut().start_section(self.pos());
             }
+            Some(EndTag { ref mut name, .. }) => *name = get_token_part_range!(self),
             _ => unreachable!("Tag should exist at this point"),
         }
 
@@ -251,53 +258,12 @@ impl<S: LexemeSink> StateMachineActions for Lexer<S> {
         }
     }
 
-    #[inline]
-    fn start_attr(&mut self, input: &[u8]) {
-        // NOTE: create attribute only if we are parsing a start tag
-        if let Some(StartTag { .. }) = self.current_tag_token {
-            self.current_attr = Some(AttributeOutline::default());
-
-            self.start_token_part(input);
-        }
-    }
-
-    #[inline]
-    fn finish_attr_name(&mut self, _input: &[u8]) {
-        if let Some(AttributeOutline {
-            ref mut name,
-            ref mut raw_range,
-            ..
-        }) = self.current_attr
-        {
-            *name = get_token_part_range!(self);
-            *raw_range = *name;
-        }
-    }
-
-    #[inline]
-    fn finish_attr_value(&mut self, input: &[u8]) {
-        if let Some(AttributeOutline {
-            ref mut value,
-            ref mut raw_range,
-            ..
-        }) = self.current_attr
-        {
-            *value = get_token_part_range!(self);
-
-            // NOTE: include closing quote into the raw value if it's present
-            raw_range.end = match input.get(self.next_pos - 1).copied() {
-                Some(ch) if ch == self.closing_quote => value.end + 1,
-                _ => value.end,
-            };
-        }
-    }
-
-    #[inline]
-    fn finish_attr(&mut self, _input: &[u8]) {
-        if let Some(attr) = self.current_attr.take() {
-            self.attr_buffer.borrow_mut().push(attr);
-        }
-    }
-
-    noop_action!(mark_tag_start, unmark_tag_start);
+    noop_action!(
+        start_attr,
+        finish_attr_name,
+        finish_attr_value,
+        finish_attr,
+        mark_tag_start,
+        unmark_tag_start
+    );
 }
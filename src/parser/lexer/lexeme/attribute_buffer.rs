@@ -0,0 +1,204 @@
+use super::AttributeOutline;
+use crate::base::{Align, Range};
+use crate::memory::profile;
+use smallvec::SmallVec;
+
+type AttributeOutlines = SmallVec<[AttributeOutline; profile::INLINE_ATTRIBUTE_CAPACITY]>;
+
+#[derive(Copy, Clone)]
+enum State {
+    BeforeName,
+    Name,
+    AfterName,
+    BeforeValue,
+    QuotedValue(u8),
+    AfterQuotedValue,
+    UnquotedValue,
+    SelfClosing,
+}
+
+#[inline]
+fn is_whitespace(ch: u8) -> bool {
+    ch == b' ' || ch == b'\n' || ch == b'\r' || ch == b'\t' || ch == b'\x0C'
+}
+
+/// Splits the attribute section of a start tag (the bytes between the tag name and the closing
+/// `>`) into attribute outlines. Mirrors the attribute states of the lexer's state machine.
+fn parse_attributes(input: &[u8], section: Range, outlines: &mut AttributeOutlines) {
+    let mut state = State::BeforeName;
+    let mut attr = AttributeOutline::default();
+    let mut part_start = section.start;
+    let mut pos = section.start;
+
+    macro_rules! start_attr {
+        () => {{
+            attr = AttributeOutline::default();
+            part_start = pos;
+        }};
+    }
+
+    macro_rules! finish_attr_name {
+        () => {{
+            attr.name = Range {
+                start: part_start,
+                end: pos,
+            };
+
+            attr.raw_range = attr.name;
+        }};
+    }
+
+    macro_rules! finish_attr_value {
+        ($raw_end:expr) => {{
+            attr.value = Range {
+                start: part_start,
+                end: pos,
+            };
+
+            attr.raw_range.end = $raw_end;
+        }};
+    }
+
+    while pos < section.end {
+        let ch = input[pos];
+
+        state = match state {
+            State::BeforeName if is_whitespace(ch) => State::BeforeName,
+            State::BeforeName if ch == b'/' => State::SelfClosing,
+            State::BeforeName => {
+                start_attr!();
+                State::Name
+            }
+
+            State::Name if is_whitespace(ch) => {
+                finish_attr_name!();
+                State::AfterName
+            }
+            State::Name if ch == b'/' => {
+                finish_attr_name!();
+                outlines.push(attr);
+                State::SelfClosing
+            }
+            State::Name if ch == b'=' => {
+                finish_attr_name!();
+                State::BeforeValue
+            }
+            State::Name => State::Name,
+
+            State::AfterName if is_whitespace(ch) => State::AfterName,
+            State::AfterName if ch == b'/' => {
+                outlines.push(attr);
+                State::SelfClosing
+            }
+            State::AfterName if ch == b'=' => State::BeforeValue,
+            State::AfterName => {
+                outlines.push(attr);
+                start_attr!();
+                State::Name
+            }
+
+            State::BeforeValue if is_whitespace(ch) => State::BeforeValue,
+            State::BeforeValue if ch == b'"' || ch == b'\'' => {
+                part_start = pos + 1;
+                State::QuotedValue(ch)
+            }
+            State::BeforeValue => {
+                part_start = pos;
+                State::UnquotedValue
+            }
+
+            State::QuotedValue(quote) if ch == quote => {
+                // NOTE: include closing quote into the raw value.
+                finish_attr_value!(pos + 1);
+                outlines.push(attr);
+                State::AfterQuotedValue
+            }
+            State::QuotedValue(quote) => State::QuotedValue(quote),
+
+            State::AfterQuotedValue if is_whitespace(ch) => State::BeforeName,
+            State::AfterQuotedValue if ch == b'/' => State::SelfClosing,
+
+            // NOTE: reconsume the character in the before attribute name state.
+            State::AfterQuotedValue | State::SelfClosing => {
+                state = State::BeforeName;
+                continue;
+            }
+
+            State::UnquotedValue if is_whitespace(ch) => {
+                finish_attr_value!(pos);
+                outlines.push(attr);
+                State::BeforeName
+            }
+            State::UnquotedValue => State::UnquotedValue,
+        };
+
+        pos += 1;
+    }
+
+    // NOTE: we've reached the `>` character that closes the tag.
+    match state {
+        State::Name => {
+            finish_attr_name!();
+            outlines.push(attr);
+        }
+        State::AfterName | State::BeforeValue => outlines.push(attr),
+        State::UnquotedValue => {
+            finish_attr_value!(pos);
+            outlines.push(attr);
+        }
+        _ => (),
+    }
+}
+
+/// Attributes of the start tag that are split into outlines only once they are requested.
+///
+/// The lexer only records the range of the attribute section of the tag, so start tags
+/// whose attributes are not inspected by the tree builder simulator, selectors or content
+/// handlers skip attribute processing entirely.
+///
+/// Outlines of the tags with up to `INLINE_ATTRIBUTE_CAPACITY` attributes are stored inline.
+/// The buffer is reused for all the tags, so it's only spilled to the heap once a tag with more
+/// attributes is encountered.
+#[derive(Debug, Default)]
+pub struct AttributeBuffer {
+    section: Range,
+    outlines: AttributeOutlines,
+    parsed: bool,
+}
+
+impl AttributeBuffer {
+    #[inline]
+    pub fn start_section(&mut self, start: usize) {
+        self.section = Range { start, end: start };
+        self.parsed = false;
+    }
+
+    #[inline]
+    pub fn finish_section(&mut self, end: usize) {
+        self.section.end = end;
+    }
+
+    /// Returns outlines of the attributes, splitting the attribute section of the `input`
+    /// on the first call.
+    #[inline]
+    pub fn outlines(&mut self, input: &[u8]) -> &[AttributeOutline] {
+        if !self.parsed {
+            self.outlines.clear();
+            parse_attributes(input, self.section, &mut self.outlines);
+            self.parsed = true;
+        }
+
+        &self.outlines
+    }
+}
+
+impl Align for AttributeBuffer {
+    #[inline]
+    fn align(&mut self, offset: usize) {
+        self.section.align(offset);
+
+        if self.parsed {
+            self.outlines.align(offset);
+        }
+    }
+}
@@ -1,8 +1,10 @@
+mod attribute_buffer;
 mod token_outline;
 
 use crate::base::{Bytes, Range};
 use std::fmt::{self, Debug, Write};
 
+pub use self::attribute_buffer::AttributeBuffer;
 pub use self::token_outline::*;
 
 pub struct Lexeme<'i, T> {
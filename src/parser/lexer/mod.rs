@@ -7,7 +7,7 @@ mod lexeme;
 use crate::base::{Align, Range};
 use crate::html::{LocalNameHash, Namespace, TextType};
 use crate::parser::state_machine::{
-    ActionError, ActionResult, FeedbackDirective, StateMachine, StateResult,
+    ActionError, ActionResult, FeedbackDirective, State, StateMachine,
 };
 use crate::parser::{
     ParserDirective, ParsingAmbiguityError, TreeBuilderFeedback, TreeBuilderSimulator,
@@ -18,8 +18,6 @@ use std::rc::Rc;
 
 pub use self::lexeme::*;
 
-const DEFAULT_ATTR_BUFFER_CAPACITY: usize = 256;
-
 pub trait LexemeSink {
     fn handle_tag(&mut self, lexeme: &TagLexeme) -> Result<ParserDirective, RewritingError>;
     fn handle_non_tag_content(
@@ -28,8 +26,7 @@ pub trait LexemeSink {
     ) -> Result<(), RewritingError>;
 }
 
-pub type State<S> = fn(&mut Lexer<S>, &[u8]) -> StateResult;
-pub type SharedAttributeBuffer = Rc<RefCell<Vec<AttributeOutline>>>;
+pub type SharedAttributeBuffer = Rc<RefCell<AttributeBuffer>>;
 
 pub struct Lexer<S: LexemeSink> {
     next_pos: usize,
@@ -39,10 +36,9 @@ pub struct Lexer<S: LexemeSink> {
     is_state_enter: bool,
     cdata_allowed: bool,
     lexeme_sink: S,
-    state: State<S>,
+    state: State,
     current_tag_token: Option<TagTokenOutline>,
     current_non_tag_content_token: Option<NonTagContentTokenOutline>,
-    current_attr: Option<AttributeOutline>,
     last_start_tag_name_hash: LocalNameHash,
     closing_quote: u8,
     attr_buffer: SharedAttributeBuffer,
@@ -61,15 +57,12 @@ impl<S: LexemeSink> Lexer<S> {
             is_state_enter: true,
             cdata_allowed: false,
             lexeme_sink,
-            state: Lexer::data_state,
+            state: State::data_state,
             current_tag_token: None,
             current_non_tag_content_token: None,
-            current_attr: None,
             last_start_tag_name_hash: LocalNameHash::default(),
             closing_quote: b'"',
-            attr_buffer: Rc::new(RefCell::new(Vec::with_capacity(
-                DEFAULT_ATTR_BUFFER_CAPACITY,
-            ))),
+            attr_buffer: Rc::new(RefCell::new(AttributeBuffer::default())),
             tree_builder_simulator,
             last_text_type: TextType::Data,
             feedback_directive: FeedbackDirective::None,
@@ -176,12 +169,12 @@ impl<S: LexemeSink> StateMachine for Lexer<S> {
     impl_common_input_cursor_methods!();
 
     #[inline]
-    fn set_state(&mut self, state: State<S>) {
+    fn set_state(&mut self, state: State) {
         self.state = state;
     }
 
     #[inline]
-    fn state(&self) -> State<S> {
+    fn state(&self) -> State {
         self.state
     }
 
@@ -194,7 +187,6 @@ impl<S: LexemeSink> StateMachine for Lexer<S> {
         self.token_part_start.align(self.lexeme_start);
         self.current_tag_token.align(self.lexeme_start);
         self.current_non_tag_content_token.align(self.lexeme_start);
-        self.current_attr.align(self.lexeme_start);
 
         self.lexeme_start = 0;
     }
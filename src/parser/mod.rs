@@ -15,9 +15,11 @@ use cfg_if::cfg_if;
 use std::cell::RefCell;
 use std::rc::Rc;
 
+#[cfg(feature = "element_handlers")]
+pub use self::lexer::AttributeOutline;
 pub use self::lexer::{
-    AttributeOutline, Lexeme, LexemeSink, NonTagContentLexeme, NonTagContentTokenOutline,
-    SharedAttributeBuffer, TagLexeme, TagTokenOutline,
+    Lexeme, LexemeSink, NonTagContentLexeme, NonTagContentTokenOutline, SharedAttributeBuffer,
+    TagLexeme, TagTokenOutline,
 };
 pub use self::tag_scanner::TagHintSink;
 pub use self::tree_builder_simulator::ParsingAmbiguityError;
@@ -71,6 +71,8 @@ pub type ActionResult = Result<(), ActionError>;
 pub type StateResult = Result<(), ParsingTermination>;
 pub type ParseResult = Result<Never, ParsingTermination>;
 
+with_state_groups!(define_state_enum);
+
 pub trait StateMachineActions {
     fn emit_eof(&mut self, input: &[u8]) -> ActionResult;
     fn emit_text(&mut self, input: &[u8]) -> ActionResult;
@@ -120,10 +122,10 @@ pub trait StateMachineConditions {
 }
 
 pub trait StateMachine: StateMachineActions + StateMachineConditions {
-    define_states!();
+    with_state_groups!(define_states);
 
-    fn state(&self) -> fn(&mut Self, &[u8]) -> StateResult;
-    fn set_state(&mut self, state: fn(&mut Self, &[u8]) -> StateResult);
+    fn state(&self) -> State;
+    fn set_state(&mut self, state: State);
 
     fn is_state_enter(&self) -> bool;
     fn set_is_state_enter(&mut self, val: bool);
@@ -147,6 +149,7 @@ pub trait StateMachine: StateMachineActions + StateMachineConditions {
     fn consume_ch(&mut self, input: &[u8]) -> Option<u8>;
     fn unconsume_ch(&mut self);
     fn consume_several(&mut self, count: usize);
+    fn skip_until(&mut self, input: &[u8], search: impl FnOnce(&[u8]) -> Option<usize>);
     fn lookahead(&self, input: &[u8], offset: usize) -> Option<u8>;
     fn pos(&self) -> usize;
     fn set_pos(&mut self, pos: usize);
@@ -157,7 +160,7 @@ pub trait StateMachine: StateMachineActions + StateMachineConditions {
         self.set_is_last_input(last);
 
         loop {
-            self.state()(self, input)?;
+            dispatch_state(self, input)?;
         }
     }
 
@@ -220,7 +223,7 @@ pub trait StateMachine: StateMachineActions + StateMachineConditions {
     }
 
     #[inline]
-    fn switch_state(&mut self, state: fn(&mut Self, &[u8]) -> StateResult) {
+    fn switch_state(&mut self, state: State) {
         self.set_state(state);
         self.set_is_state_enter(true);
     }
@@ -232,14 +235,14 @@ pub trait StateMachine: StateMachineActions + StateMachineConditions {
     }
 
     #[inline]
-    fn next_text_parsing_state(&self) -> fn(&mut Self, &[u8]) -> StateResult {
+    fn next_text_parsing_state(&self) -> State {
         match self.last_text_type() {
-            TextType::Data => Self::data_state,
-            TextType::PlainText => Self::plaintext_state,
-            TextType::RCData => Self::rcdata_state,
-            TextType::RawText => Self::rawtext_state,
-            TextType::ScriptData => Self::script_data_state,
-            TextType::CDataSection => Self::cdata_section_state,
+            TextType::Data => State::data_state,
+            TextType::PlainText => State::plaintext_state,
+            TextType::RCData => State::rcdata_state,
+            TextType::RawText => State::rawtext_state,
+            TextType::ScriptData => State::script_data_state,
+            TextType::CDataSection => State::cdata_section_state,
         }
     }
 }
@@ -340,6 +343,17 @@ macro_rules! impl_common_input_cursor_methods {
             trace!(@chars "consume several");
         }
 
+        #[inline]
+        fn skip_until(&mut self, input: &[u8], search: impl FnOnce(&[u8]) -> Option<usize>) {
+            let rest = input.get(self.next_pos..).unwrap_or_default();
+
+            // NOTE: if there is no match, we skip to the end of the input,
+            // so the next consumed character will be the end of the chunk.
+            self.next_pos += search(rest).unwrap_or_else(|| rest.len());
+
+            trace!(@chars "skip");
+        }
+
         #[inline]
         #[allow(clippy::let_and_return)]
         fn lookahead(&self, input: &[u8], offset: usize) -> Option<u8> {
@@ -1,6 +1,6 @@
 define_state_group!(comment_states_group = {
 
-    bogus_comment_state {
+    bogus_comment_state skip_to [ b'>' ] {
         b'>' => ( mark_comment_text_end; emit_current_token?; --> data_state )
         eof  => ( mark_comment_text_end; emit_current_token_and_eof?; )
         _    => ()
@@ -13,7 +13,7 @@ define_state_group!(comment_states_group = {
         _    => ( reconsume in comment_state )
     }
 
-    comment_state {
+    comment_state skip_to [ b'<', b'-' ] {
         b'<' => ( --> comment_less_than_sign_state )
         b'-' => ( mark_comment_text_end; --> comment_end_dash_state )
         eof  => ( mark_comment_text_end; emit_current_token_and_eof?; )
@@ -1,6 +1,6 @@
 define_state_group!(cdata_section_states_group = {
 
-    cdata_section_state {
+    cdata_section_state skip_to [ b']' ] {
         b']' => ( emit_text?; --> cdata_section_bracket_state )
         eoc  => ( emit_text?; )
         eof  => ( emit_text?; emit_eof?; )
@@ -1,6 +1,6 @@
 define_state_group!(rawtext_states_group = {
 
-    rawtext_state {
+    rawtext_state skip_to [ b'<' ] {
         b'<' => ( emit_text?; mark_tag_start; --> rawtext_less_than_sign_state )
         eoc  => ( emit_text?; )
         eof  => ( emit_text?; emit_eof?; )
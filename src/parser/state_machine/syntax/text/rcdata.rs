@@ -1,6 +1,6 @@
 define_state_group!(rcdata_states_group = {
 
-    rcdata_state {
+    rcdata_state skip_to [ b'<' ] {
         b'<' => ( emit_text?; mark_tag_start; --> rcdata_less_than_sign_state )
         eoc  => ( emit_text?; )
         eof  => ( emit_text?; emit_eof?; )
@@ -8,7 +8,7 @@ define_state_group!(script_data_double_escaped_states_group = {
         _          => ( reconsume in script_data_escaped_state )
     }
 
-    script_data_double_escaped_state {
+    script_data_double_escaped_state skip_to [ b'-', b'<' ] {
         [ "--" ] => ( --> script_data_double_escaped_dash_dash_state )
         b'<'     => ( emit_text?; --> script_data_double_escaped_less_than_sign_state )
         eof      => ( emit_text?; emit_eof?; )
@@ -6,7 +6,7 @@ mod double_escaped;
 
 define_state_group!(script_data_states_group = {
 
-    script_data_state {
+    script_data_state skip_to [ b'<' ] {
         b'<' => ( emit_text?; mark_tag_start; --> script_data_less_than_sign_state )
         eoc  => ( emit_text?; )
         eof  => ( emit_text?; emit_eof?; )
@@ -13,7 +13,7 @@ macro_rules! action {
     };
 
     ( @state_transition | $self:tt, $input:ident | > - -> $state:ident) => {
-        $self.switch_state(Self::$state);
+        $self.switch_state(State::$state);
         return Ok(());
     };
 
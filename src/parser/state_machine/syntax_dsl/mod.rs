@@ -13,12 +13,21 @@ mod state;
 #[macro_use]
 mod arm_pattern;
 
+#[macro_use]
+mod state_enum;
+
 macro_rules! define_state_group {
     ( $name:ident = { $($states:tt)+ } ) => {
         macro_rules! $name {
             () => {
                 state!($($states)+);
             };
+
+            // NOTE: passes the state definitions of the group to the given macro, this is used
+            // to collect names of all the states for the state enum.
+            (@with_states $callback:ident $args:tt) => {
+                $callback!($args $($states)+);
+            };
         }
     };
 }
@@ -1,6 +1,6 @@
 macro_rules! state {
     (
-        $name:ident $(<-- ( $($enter_actions:tt)* ))* {
+        $name:ident $(<-- ( $($enter_actions:tt)* ))* $(skip_to [ $($skip_chs:expr),+ ])* {
             $($arms:tt)*
         }
 
@@ -10,6 +10,8 @@ macro_rules! state {
             // NOTE: clippy complains about some states that break the loop in each match arm
             #[allow(clippy::never_loop)]
             loop {
+                $( skip_to!(self, input, $($skip_chs),+); )*
+
                 let ch = self.consume_ch(input);
 
                 state_body!(|[self, input, ch]|> [$($arms)*], [$($($enter_actions)*)*]);
@@ -22,3 +24,21 @@ macro_rules! state {
     // NOTE: end of the state list
     () => ();
 }
+
+// NOTE: skips all the characters up to the first occurrence of any of the given characters
+// in the input. Used by the states where all the other characters are handled by the
+// `_ => ()` arm, so we can look for the closing sequence candidates with a vectorised
+// search instead of running the state body for each character.
+macro_rules! skip_to {
+    ($self:tt, $input:ident, $ch:expr) => {
+        $self.skip_until($input, |input| memchr::memchr($ch, input));
+    };
+
+    ($self:tt, $input:ident, $ch1:expr, $ch2:expr) => {
+        $self.skip_until($input, |input| memchr::memchr2($ch1, $ch2, input));
+    };
+
+    ($self:tt, $input:ident, $ch1:expr, $ch2:expr, $ch3:expr) => {
+        $self.skip_until($input, |input| memchr::memchr3($ch1, $ch2, $ch3, input));
+    };
+}
@@ -0,0 +1,48 @@
+macro_rules! state_enum {
+    // NOTE: all the state groups have been processed
+    ( [$($names:ident)*] [] ) => {
+        /// Identifier of the state machine state.
+        ///
+        /// Each variant corresponds to the state method with the same name. Dispatching on the
+        /// dense enum instead of calling through a function pointer allows the compiler to
+        /// inline state bodies into the parsing loop.
+        #[allow(non_camel_case_types)]
+        #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+        pub enum State {
+            $($names),*
+        }
+
+        #[inline]
+        fn dispatch_state<M: StateMachine + ?Sized>(
+            state_machine: &mut M,
+            input: &[u8],
+        ) -> StateResult {
+            match state_machine.state() {
+                $(State::$names => state_machine.$names(input)),*
+            }
+        }
+    };
+
+    ( $names:tt [$group:ident $($groups:ident)*] ) => {
+        $group!(@with_states collect_state_names ($names [$($groups)*]));
+    };
+}
+
+macro_rules! collect_state_names {
+    // NOTE: end of the state list of the group
+    ( ($names:tt $groups:tt) ) => {
+        state_enum!($names $groups);
+    };
+
+    (
+        ([$($names:ident)*] $groups:tt)
+
+        $name:ident $(<-- ( $($enter_actions:tt)* ))* $(skip_to [ $($skip_chs:expr),+ ])* {
+            $($arms:tt)*
+        }
+
+        $($rest:tt)*
+    ) => {
+        collect_state_names!(([$($names)* $name] $groups) $($rest)*);
+    };
+}
@@ -4,7 +4,7 @@ mod conditions;
 
 use crate::base::{Align, Bytes, Range};
 use crate::html::{LocalName, LocalNameHash, Namespace, TextType};
-use crate::parser::state_machine::{FeedbackDirective, StateMachine, StateResult};
+use crate::parser::state_machine::{FeedbackDirective, State, StateMachine};
 use crate::parser::{
     ParserDirective, ParsingAmbiguityError, TreeBuilderFeedback, TreeBuilderSimulator,
 };
@@ -22,8 +22,6 @@ pub trait TagHintSink {
     fn handle_end_tag_hint(&mut self, name: LocalName) -> Result<ParserDirective, RewritingError>;
 }
 
-pub type State<S> = fn(&mut TagScanner<S>, &[u8]) -> StateResult;
-
 /// Tag scanner skips the majority of lexer operations and, thus,
 /// is faster. It also has much less requirements for buffering which makes it more
 /// prone to bailouts caused by buffer exhaustion (actually it buffers only tag names).
@@ -47,7 +45,7 @@ pub struct TagScanner<S: TagHintSink> {
     is_state_enter: bool,
     cdata_allowed: bool,
     tag_hint_sink: S,
-    state: State<S>,
+    state: State,
     closing_quote: u8,
     tree_builder_simulator: Rc<RefCell<TreeBuilderSimulator>>,
     pending_text_type_change: Option<TextType>,
@@ -71,7 +69,7 @@ impl<S: TagHintSink> TagScanner<S> {
             is_state_enter: true,
             cdata_allowed: false,
             tag_hint_sink,
-            state: TagScanner::data_state,
+            state: State::data_state,
             closing_quote: b'"',
             tree_builder_simulator,
             pending_text_type_change: None,
@@ -146,12 +144,12 @@ impl<S: TagHintSink> StateMachine for TagScanner<S> {
     impl_common_input_cursor_methods!();
 
     #[inline]
-    fn set_state(&mut self, state: State<S>) {
+    fn set_state(&mut self, state: State) {
         self.state = state;
     }
 
     #[inline]
-    fn state(&self) -> State<S> {
+    fn state(&self) -> State {
         self.state
     }
 
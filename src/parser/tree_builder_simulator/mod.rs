@@ -16,13 +16,12 @@ mod ambiguity_guard;
 use self::ambiguity_guard::AmbiguityGuard;
 use crate::base::Bytes;
 use crate::html::{LocalNameHash, Namespace, Tag, TextType};
+use crate::memory::profile;
 use crate::parser::{TagLexeme, TagTokenOutline};
 use TagTokenOutline::*;
 
 pub use self::ambiguity_guard::ParsingAmbiguityError;
 
-const DEFAULT_NS_STACK_CAPACITY: usize = 256;
-
 #[must_use]
 pub enum TreeBuilderFeedback {
     SwitchTextType(TextType),
@@ -119,7 +118,7 @@ pub struct TreeBuilderSimulator {
 impl TreeBuilderSimulator {
     pub fn new(strict: bool) -> Self {
         let mut simulator = TreeBuilderSimulator {
-            ns_stack: Vec::with_capacity(DEFAULT_NS_STACK_CAPACITY),
+            ns_stack: Vec::with_capacity(profile::NS_STACK_CAPACITY),
             current_ns: Namespace::Html,
             ambiguity_guard: AmbiguityGuard::default(),
             strict,
@@ -249,7 +248,7 @@ impl TreeBuilderSimulator {
             // to decide on foreign context exit
             return request_lexeme(|this, lexeme| {
                 expect_tag!(lexeme, StartTag { ref attributes, .. } => {
-                    for attr in attributes.borrow().iter() {
+                    for attr in attributes.borrow_mut().outlines(lexeme.input()) {
                         let name = lexeme.part(attr.name);
 
                         if eq_case_insensitive(&name, b"color")
@@ -278,7 +277,7 @@ impl TreeBuilderSimulator {
                     let name = lexeme.part(name);
 
                     if !self_closing && eq_case_insensitive(&name, b"annotation-xml") {
-                        for attr in attributes.borrow().iter() {
+                        for attr in attributes.borrow_mut().outlines(lexeme.input()) {
                             let name = lexeme.part(attr.name);
                             let value = lexeme.part(attr.value);
 
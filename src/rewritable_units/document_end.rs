@@ -1,7 +1,7 @@
-use super::mutations::content_to_bytes;
-use super::ContentType;
+use super::{ContentType, Mutations};
 
 use encoding_rs::Encoding;
+use std::rc::Rc;
 
 use crate::transform_stream::OutputSink;
 
@@ -12,14 +12,16 @@ use crate::transform_stream::OutputSink;
 /// the final chunk.
 pub struct DocumentEnd<'a> {
     output_sink: &'a mut dyn OutputSink,
-    encoding: &'static Encoding,
+    // NOTE: the appended content is buffered until all the handlers have been invoked, so
+    // the content of the `HtmlReprocessed` type can be run through the content handlers.
+    pub(crate) mutations: Mutations,
 }
 
 impl<'a> DocumentEnd<'a> {
     pub(crate) fn new(output_sink: &'a mut dyn OutputSink, encoding: &'static Encoding) -> Self {
         DocumentEnd {
             output_sink,
-            encoding,
+            mutations: Mutations::new(encoding),
         }
     }
 
@@ -49,9 +51,22 @@ impl<'a> DocumentEnd<'a> {
     /// ```
     #[inline]
     pub fn append(&mut self, content: &str, content_type: ContentType) {
-        content_to_bytes(content, content_type, self.encoding, &mut |c: &[u8]| {
-            self.output_sink.handle_chunk(c)
-        });
+        self.mutations.before(content, content_type);
+    }
+
+    /// Sets the label of the content handler the subsequent insertions are attributed to.
+    #[inline]
+    pub(crate) fn set_provenance(&mut self, provenance: Option<Rc<str>>) {
+        self.mutations.set_provenance(provenance);
+    }
+
+    /// Outputs the appended content.
+    #[inline]
+    pub(crate) fn flush(self) {
+        if !self.mutations.content_before.is_empty() {
+            self.output_sink
+                .handle_chunk(&self.mutations.content_before);
+        }
     }
 }
 
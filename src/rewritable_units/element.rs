@@ -1,9 +1,17 @@
-use super::{Attribute, AttributeNameError, ContentType, EndTag, Mutations, StartTag};
+use super::mutations::content_to_bytes;
+use super::{
+    Attribute, AttributeNameError, ContentType, EndTag, Mutations, StartTag, Style, Subtree,
+};
 use crate::base::Bytes;
+use crate::html::{is_valid_custom_element_name, Namespace};
+use crate::memory::{MemoryLimitExceededError, SharedMemoryLimiter};
 use crate::rewriter::EndTagHandler;
 use encoding_rs::Encoding;
 use std::any::Any;
+use std::cell::RefCell;
+use std::error::Error;
 use std::fmt::{self, Debug};
+use std::rc::Rc;
 use thiserror::Error;
 
 /// An error that occurs when invalid value is provided for the tag name.
@@ -30,6 +38,147 @@ pub enum TagNameError {
     UnencodableCharacter,
 }
 
+/// An error that occurs when invalid value is provided for the class name.
+#[derive(Error, Debug, PartialEq, Copy, Clone)]
+pub enum ClassNameError {
+    /// The provided value is empty.
+    #[error("Class name can't be empty.")]
+    Empty,
+
+    /// The provided value contains an ASCII whitespace character, which separates the class
+    /// names in the `class` attribute.
+    #[error("Class name can't contain whitespace characters.")]
+    ContainsWhitespace,
+}
+
+#[inline]
+fn validate_class_name(name: &str) -> Result<(), ClassNameError> {
+    if name.is_empty() {
+        Err(ClassNameError::Empty)
+    } else if name.bytes().any(|b| b.is_ascii_whitespace()) {
+        Err(ClassNameError::ContainsWhitespace)
+    } else {
+        Ok(())
+    }
+}
+
+/// Splits the value of the `class` attribute into the class names, skipping the duplicates.
+fn unique_class_names(value: &str) -> Vec<&str> {
+    let mut names = Vec::new();
+
+    for name in value.split_ascii_whitespace() {
+        if !names.contains(&name) {
+            names.push(name);
+        }
+    }
+
+    names
+}
+
+pub(crate) fn validate_tag_name(name: &str) -> Result<(), TagNameError> {
+    match name.chars().next() {
+        Some(ch) if !ch.is_ascii_alphabetic() => Err(TagNameError::InvalidFirstCharacter),
+        Some(_) => match name
+            .chars()
+            .find(|&ch| matches!(ch, ' ' | '\n' | '\r' | '\t' | '\x0C' | '/' | '>'))
+        {
+            Some(ch) => Err(TagNameError::ForbiddenCharacter(ch)),
+            None => Ok(()),
+        },
+        None => Err(TagNameError::Empty),
+    }
+}
+
+pub(crate) fn tag_name_bytes_from_str(
+    name: &str,
+    encoding: &'static Encoding,
+) -> Result<Bytes<'static>, TagNameError> {
+    validate_tag_name(name)?;
+
+    // NOTE: if character can't be represented in the given
+    // encoding then encoding_rs replaces it with a numeric
+    // character reference. Character references are not
+    // supported in tag names, so we need to bail.
+    match Bytes::from_str_without_replacements(name, encoding) {
+        Ok(name) => Ok(name.into_owned()),
+        Err(_) => Err(TagNameError::UnencodableCharacter),
+    }
+}
+
+type AccumulatedTextHandler = Box<dyn FnOnce(&str) -> Result<(), Box<dyn Error + Send + Sync>>>;
+type SubtreeHandler = Box<dyn FnOnce(&mut Subtree) -> Result<(), Box<dyn Error + Send + Sync>>>;
+type EndTagHook = Box<dyn FnOnce()>;
+type UserEndTagHandler = Box<dyn FnOnce(&mut EndTag) -> Result<(), Box<dyn Error + Send + Sync>>>;
+
+/// A buffer that collects decoded text content of an element up to a certain length.
+#[derive(Clone)]
+pub(crate) struct TextAccumulator {
+    text: Rc<RefCell<String>>,
+    limit: usize,
+    memory_limiter: Option<SharedMemoryLimiter>,
+    memory_usage: usize,
+}
+
+impl TextAccumulator {
+    #[inline]
+    fn new(limit: usize) -> Self {
+        TextAccumulator {
+            text: Rc::new(RefCell::new(String::new())),
+            limit,
+            memory_limiter: None,
+            memory_usage: 0,
+        }
+    }
+
+    /// Makes the accumulator count the collected text against the `memory_limiter`. The memory
+    /// is released once the accumulator is dropped.
+    #[inline]
+    pub fn with_memory_limiter(mut self, memory_limiter: SharedMemoryLimiter) -> Self {
+        self.memory_limiter = Some(memory_limiter);
+        self
+    }
+
+    pub fn push(&mut self, chunk: &str) -> Result<(), MemoryLimitExceededError> {
+        let mut text = self.text.borrow_mut();
+        let remaining = self.limit - text.len();
+
+        let chunk = if chunk.len() <= remaining {
+            chunk
+        } else {
+            let mut end = remaining;
+
+            while !chunk.is_char_boundary(end) {
+                end -= 1;
+            }
+
+            // NOTE: once the limit is reached we shouldn't take any more text, even if
+            // a consequent chunk is short enough to fit the remaining capacity.
+            self.limit = text.len() + end;
+
+            &chunk[..end]
+        };
+
+        if let Some(ref memory_limiter) = self.memory_limiter {
+            memory_limiter.borrow_mut().increase_usage(chunk.len())?;
+            self.memory_usage += chunk.len();
+        }
+
+        text.push_str(chunk);
+
+        Ok(())
+    }
+}
+
+impl Drop for TextAccumulator {
+    fn drop(&mut self) {
+        if let Some(ref memory_limiter) = self.memory_limiter {
+            memory_limiter
+                .borrow_mut()
+                .decrease_usage(self.memory_usage);
+        }
+    }
+}
+
 /// An HTML element rewritable unit.
 ///
 /// Exposes API for examination and modification of a parsed HTML element.
@@ -40,6 +189,15 @@ pub struct Element<'r, 't> {
     can_have_content: bool,
     should_remove_content: bool,
     encoding: &'static Encoding,
+    text_accumulator: Option<(TextAccumulator, AccumulatedTextHandler)>,
+    buffered_subtree: Option<(Rc<RefCell<Subtree>>, SubtreeHandler)>,
+    end_tag_hooks: Vec<EndTagHook>,
+    // NOTE: the handlers are stored with the label of the content handler that registered them.
+    end_tag_handlers: Vec<(UserEndTagHandler, Option<Rc<str>>)>,
+    matched_selector_index: Option<usize>,
+    #[cfg(feature = "element_handlers")]
+    reprocessed: bool,
+    provenance: Option<Rc<str>>,
     user_data: Box<dyn Any>,
 }
 
@@ -54,59 +212,89 @@ impl<'r, 't> Element<'r, 't> {
             can_have_content,
             should_remove_content: false,
             encoding,
+            text_accumulator: None,
+            buffered_subtree: None,
+            end_tag_hooks: Vec::new(),
+            end_tag_handlers: Vec::new(),
+            matched_selector_index: None,
+            #[cfg(feature = "element_handlers")]
+            reprocessed: false,
+            provenance: None,
             user_data: Box::new(()),
         }
     }
 
-    fn tag_name_bytes_from_str(&self, name: &str) -> Result<Bytes<'static>, TagNameError> {
-        match name.chars().next() {
-            Some(ch) if !ch.is_ascii_alphabetic() => Err(TagNameError::InvalidFirstCharacter),
-            Some(_) => {
-                if let Some(ch) = name
-                    .chars()
-                    .find(|&ch| matches!(ch, ' ' | '\n' | '\r' | '\t' | '\x0C' | '/' | '>'))
-                {
-                    Err(TagNameError::ForbiddenCharacter(ch))
-                } else {
-                    // NOTE: if character can't be represented in the given
-                    // encoding then encoding_rs replaces it with a numeric
-                    // character reference. Character references are not
-                    // supported in tag names, so we need to bail.
-                    match Bytes::from_str_without_replacements(name, self.encoding) {
-                        Ok(name) => Ok(name.into_owned()),
-                        Err(_) => Err(TagNameError::UnencodableCharacter),
-                    }
-                }
-            }
-            None => Err(TagNameError::Empty),
-        }
-    }
-
     #[inline]
     fn remove_content(&mut self) {
-        self.start_tag.mutations.content_after.clear();
-        self.end_tag_mutations_mut().content_before.clear();
+        self.start_tag.mutations.clear_content_after();
+        self.end_tag_mutations_mut().clear_content_before();
         self.should_remove_content = true;
+
+        // NOTE: the content is replaced, so there is nothing to buffer anymore.
+        self.buffered_subtree = None;
     }
 
     #[inline]
     fn end_tag_mutations_mut(&mut self) -> &mut Mutations {
         let encoding = self.encoding;
 
-        self.end_tag_mutations
-            .get_or_insert_with(|| Mutations::new(encoding))
+        let provenance = &self.provenance;
+
+        self.end_tag_mutations.get_or_insert_with(|| {
+            let mut mutations = Mutations::new(encoding);
+
+            mutations.set_provenance(provenance.clone());
+            mutations
+        })
     }
 
     /// Returns the tag name of the element.
+    ///
+    /// The name is decoded on the first access and cached afterwards.
     #[inline]
-    pub fn tag_name(&self) -> String {
+    pub fn tag_name(&self) -> &str {
         self.start_tag.name()
     }
 
-    /// Sets the tag name of the element.
+    /// Sets the tag name of the element, e.g. to turn `<b>` into `<strong>`.
+    ///
+    /// The end tag of the element, if the element has one in the document, is renamed as well.
+    /// The name is validated against the HTML grammar and the [`encoding`] of the document.
+    ///
+    /// [`encoding`]: ../struct.Settings.html#structfield.encoding
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use lol_html::{rewrite_str, element, RewriteStrSettings};
+    ///
+    /// let html = rewrite_str(
+    ///     r#"<p><b>Bold</b> and <font color=red>red</font></p>"#,
+    ///     RewriteStrSettings {
+    ///         element_content_handlers: vec![
+    ///             element!("b", |el| {
+    ///                 el.set_tag_name("strong")?;
+    ///
+    ///                 Ok(())
+    ///             }),
+    ///             element!("font", |el| {
+    ///                 el.set_tag_name("span")?;
+    ///
+    ///                 Ok(())
+    ///             })
+    ///         ],
+    ///         ..RewriteStrSettings::default()
+    ///     }
+    /// ).unwrap();
+    ///
+    /// assert_eq!(
+    ///     html,
+    ///     r#"<p><strong>Bold</strong> and <span color=red>red</span></p>"#
+    /// );
+    /// ```
     #[inline]
     pub fn set_tag_name(&mut self, name: &str) -> Result<(), TagNameError> {
-        let name = self.tag_name_bytes_from_str(name)?;
+        let name = tag_name_bytes_from_str(name, self.encoding)?;
 
         if self.can_have_content {
             self.modified_end_tag_name = Some(name.clone());
@@ -125,6 +313,51 @@ impl<'r, 't> Element<'r, 't> {
         self.start_tag.namespace_uri()
     }
 
+    /// Returns the index of the selector in the selector list of the handler that matched
+    /// the element.
+    ///
+    /// If the element is matched by several selectors of the list, the index of the first one is
+    /// returned. This allows a single handler registered for a selector list to branch on the
+    /// matched selector without re-testing the element.
+    ///
+    /// # Example
+    /// ```
+    /// use lol_html::{rewrite_str, element, RewriteStrSettings};
+    ///
+    /// let html = rewrite_str(
+    ///     r#"<img src="a.png"><script src="a.js"></script>"#,
+    ///     RewriteStrSettings {
+    ///         element_content_handlers: vec![
+    ///             element!("img[src], script[src]", |el| {
+    ///                 let kind = match el.matched_selector_index() {
+    ///                     Some(0) => "image",
+    ///                     _ => "script",
+    ///                 };
+    ///
+    ///                 el.set_attribute("data-kind", kind)?;
+    ///
+    ///                 Ok(())
+    ///             })
+    ///         ],
+    ///         ..RewriteStrSettings::default()
+    ///     }
+    /// ).unwrap();
+    ///
+    /// assert_eq!(
+    ///     html,
+    ///     r#"<img src="a.png" data-kind="image"><script src="a.js" data-kind="script"></script>"#
+    /// );
+    /// ```
+    #[inline]
+    pub fn matched_selector_index(&self) -> Option<usize> {
+        self.matched_selector_index
+    }
+
+    #[inline]
+    pub(crate) fn set_matched_selector_index(&mut self, index: Option<usize>) {
+        self.matched_selector_index = index;
+    }
+
     /// Returns an immutable collection of element's attributes.
     #[inline]
     pub fn attributes(&self) -> &[Attribute<'t>] {
@@ -140,7 +373,7 @@ impl<'r, 't> Element<'r, 't> {
 
         self.attributes().iter().find_map(|attr| {
             if attr.name() == name {
-                Some(attr.value())
+                Some(attr.value().to_string())
             } else {
                 None
             }
@@ -155,6 +388,50 @@ impl<'r, 't> Element<'r, 't> {
         self.attributes().iter().any(|attr| attr.name() == name)
     }
 
+    /// Returns the name of the [customized built-in element] specified by the `is` attribute
+    /// of the element, e.g. `fancy-button` for `<button is="fancy-button">`.
+    ///
+    /// Returns `None` if the element doesn't have the `is` attribute, if the value of the
+    /// attribute is not a [valid custom element name] or if the element is not a built-in HTML
+    /// element.
+    ///
+    /// # Example
+    /// ```
+    /// use lol_html::{rewrite_str, element, RewriteStrSettings};
+    ///
+    /// let mut names = vec![];
+    ///
+    /// rewrite_str(
+    ///     r#"<button is="fancy-button"></button><p is="Fancy"></p><x-el is="x-y"></x-el>"#,
+    ///     RewriteStrSettings {
+    ///         element_content_handlers: vec![
+    ///             element!("[is]", |el| {
+    ///                 names.push(el.is_attribute());
+    ///
+    ///                 Ok(())
+    ///             })
+    ///         ],
+    ///         ..RewriteStrSettings::default()
+    ///     }
+    /// ).unwrap();
+    ///
+    /// assert_eq!(names, [Some("fancy-button".to_string()), None, None]);
+    /// ```
+    ///
+    /// [customized built-in element]: https://html.spec.whatwg.org/multipage/custom-elements.html#customized-built-in-element
+    /// [valid custom element name]: https://html.spec.whatwg.org/multipage/custom-elements.html#valid-custom-element-name
+    #[inline]
+    pub fn is_attribute(&self) -> Option<String> {
+        if self.namespace_uri() != Namespace::Html.uri()
+            || is_valid_custom_element_name(self.tag_name())
+        {
+            return None;
+        }
+
+        self.get_attribute("is")
+            .filter(|name| is_valid_custom_element_name(name))
+    }
+
     /// Sets `value` of element's attribute with `name`.
     ///
     /// If element doesn't have an attribute with the `name`, method adds new attribute
@@ -170,6 +447,193 @@ impl<'r, 't> Element<'r, 't> {
         self.start_tag.remove_attribute(name);
     }
 
+    /// Retains only the attributes for which `f` returns `true`, visiting the attributes in
+    /// the order they appear in the start tag.
+    ///
+    /// `f` receives a mutable reference to each attribute, so the values of the retained
+    /// attributes can be rewritten in the same pass with [`Attribute::set_value`].
+    ///
+    /// [`Attribute::set_value`]: struct.Attribute.html#method.set_value
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use lol_html::{rewrite_str, element, RewriteStrSettings};
+    ///
+    /// let html = rewrite_str(
+    ///     r#"<a href="/foo" onclick="steal()" data-x="1" TITLE="Foo">Foo</a>"#,
+    ///     RewriteStrSettings {
+    ///         element_content_handlers: vec![
+    ///             element!("a", |el| {
+    ///                 el.retain_attributes(|attr| match attr.name() {
+    ///                     "href" => {
+    ///                         let href = format!("https://example.com{}", attr.value());
+    ///
+    ///                         attr.set_value(&href);
+    ///
+    ///                         true
+    ///                     }
+    ///                     name => ["title", "rel"].contains(&name),
+    ///                 });
+    ///
+    ///                 Ok(())
+    ///             })
+    ///         ],
+    ///         ..RewriteStrSettings::default()
+    ///     }
+    /// ).unwrap();
+    ///
+    /// assert_eq!(html, r#"<a href="https://example.com/foo" TITLE="Foo">Foo</a>"#);
+    /// ```
+    #[inline]
+    pub fn retain_attributes(&mut self, f: impl FnMut(&mut Attribute<'t>) -> bool) {
+        self.start_tag.retain_attributes(f);
+    }
+
+    /// Returns `true` if the `class` attribute of the element contains `name`.
+    ///
+    /// The value of the attribute is split on ASCII whitespace and class names are compared
+    /// case-sensitively.
+    #[inline]
+    pub fn has_class(&self, name: &str) -> bool {
+        self.attributes().iter().any(|attr| {
+            attr.name() == "class" && attr.value().split_ascii_whitespace().any(|n| n == name)
+        })
+    }
+
+    /// Adds `name` to the `class` attribute of the element, adding the attribute if it's not
+    /// present.
+    ///
+    /// Similarly to [`DOMTokenList`], the modified attribute value is normalized: class names
+    /// are separated with a single space and duplicates are removed. The attribute is left
+    /// untouched if it already contains `name`.
+    ///
+    /// [`DOMTokenList`]: https://developer.mozilla.org/en-US/docs/Web/API/DOMTokenList
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use lol_html::{rewrite_str, element, RewriteStrSettings};
+    ///
+    /// let html = rewrite_str(
+    ///     r#"<p class=" foo  bar foo"></p><p class="active"></p><p></p>"#,
+    ///     RewriteStrSettings {
+    ///         element_content_handlers: vec![
+    ///             element!("p", |el| {
+    ///                 el.add_class("active")?;
+    ///
+    ///                 Ok(())
+    ///             })
+    ///         ],
+    ///         ..RewriteStrSettings::default()
+    ///     }
+    /// ).unwrap();
+    ///
+    /// assert_eq!(
+    ///     html,
+    ///     r#"<p class="foo bar active"></p><p class="active"></p><p class="active"></p>"#
+    /// );
+    /// ```
+    pub fn add_class(&mut self, name: &str) -> Result<(), ClassNameError> {
+        validate_class_name(name)?;
+
+        if !self.has_class(name) {
+            let value = self.get_attribute("class").unwrap_or_default();
+            let mut names = unique_class_names(&value);
+
+            names.push(name);
+            self.set_class_attribute(&names);
+        }
+
+        Ok(())
+    }
+
+    /// Removes `name` from the `class` attribute of the element.
+    ///
+    /// The modified attribute value is normalized the same way as in [`add_class`]. The attribute
+    /// is kept, even if it becomes empty, and left untouched if it doesn't contain `name`.
+    ///
+    /// [`add_class`]: #method.add_class
+    pub fn remove_class(&mut self, name: &str) -> Result<(), ClassNameError> {
+        validate_class_name(name)?;
+
+        if self.has_class(name) {
+            let value = self.get_attribute("class").unwrap_or_default();
+            let mut names = unique_class_names(&value);
+
+            names.retain(|&n| n != name);
+            self.set_class_attribute(&names);
+        }
+
+        Ok(())
+    }
+
+    /// Removes `name` from the `class` attribute of the element if it's present, otherwise adds
+    /// it. Returns `true` if the element has the class after the call.
+    ///
+    /// See [`add_class`] and [`remove_class`] for the details.
+    ///
+    /// [`add_class`]: #method.add_class
+    /// [`remove_class`]: #method.remove_class
+    pub fn toggle_class(&mut self, name: &str) -> Result<bool, ClassNameError> {
+        if self.has_class(name) {
+            self.remove_class(name)?;
+
+            Ok(false)
+        } else {
+            self.add_class(name)?;
+
+            Ok(true)
+        }
+    }
+
+    #[inline]
+    fn set_class_attribute(&mut self, names: &[&str]) {
+        self.set_attribute("class", &names.join(" "))
+            .expect("The `class` attribute name should be valid in any encoding.");
+    }
+
+    /// Returns the parsed `style` attribute of the element that provides access to individual
+    /// CSS declarations of the attribute.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use lol_html::{rewrite_str, element, RewriteStrSettings};
+    ///
+    /// let html = rewrite_str(
+    ///     r#"<div style="position: fixed; color: red">Hi</div><p>Hey</p>"#,
+    ///     RewriteStrSettings {
+    ///         element_content_handlers: vec![
+    ///             element!("div", |el| {
+    ///                 let mut style = el.style();
+    ///
+    ///                 if style.get_property("position") == Some("fixed") {
+    ///                     style.remove_property("position");
+    ///                 }
+    ///
+    ///                 Ok(())
+    ///             }),
+    ///             element!("p", |el| {
+    ///                 el.style().set_property("display", "none")?;
+    ///
+    ///                 Ok(())
+    ///             })
+    ///         ],
+    ///         ..RewriteStrSettings::default()
+    ///     }
+    /// ).unwrap();
+    ///
+    /// assert_eq!(
+    ///     html,
+    ///     r#"<div style="color: red">Hi</div><p style="display: none">Hey</p>"#
+    /// );
+    /// ```
+    #[inline]
+    pub fn style(&mut self) -> Style<'_, 'r, 't> {
+        Style::new(self)
+    }
+
     /// Inserts `content` before the element.
     ///
     /// Consequent calls to the method append `content` to the previously inserted content.
@@ -446,31 +910,363 @@ impl<'r, 't> Element<'r, 't> {
         }
     }
 
-    /// Returns `true` if the element has been removed or replaced with some content.
+    /// Accumulates decoded text content of the element, up to `limit` bytes, and invokes
+    /// `handler` with it once the element's end tag is reached.
+    ///
+    /// Text of the descendant elements is accumulated as well. Text that exceeds the `limit` is
+    /// dropped, so the handler receives at most `limit` bytes of text. Accumulated text is
+    /// counted against the [`max_allowed_memory_usage`] limit. The handler is not invoked if
+    /// the element doesn't have an end tag in the document.
+    ///
+    /// Consequent calls to the method overwrite the previously set handler.
+    /// A call to the method doesn't make any effect if the element is an [empty element].
+    ///
+    /// [`max_allowed_memory_usage`]: ../struct.MemorySettings.html#structfield.max_allowed_memory_usage
+    /// [empty element]: https://developer.mozilla.org/en-US/docs/Glossary/Empty_element
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use lol_html::{rewrite_str, element, RewriteStrSettings};
+    /// use std::cell::RefCell;
+    /// use std::rc::Rc;
+    ///
+    /// let headline = Rc::new(RefCell::new(String::new()));
+    ///
+    /// rewrite_str(
+    ///     r#"<h1>Hello <b>world</b>, how are you?</h1>"#,
+    ///     RewriteStrSettings {
+    ///         element_content_handlers: vec![
+    ///             element!("h1", |el| {
+    ///                 let headline = Rc::clone(&headline);
+    ///
+    ///                 el.accumulate_text(10, move |text| {
+    ///                     headline.borrow_mut().push_str(text);
+    ///
+    ///                     Ok(())
+    ///                 });
+    ///
+    ///                 Ok(())
+    ///             })
+    ///         ],
+    ///         ..RewriteStrSettings::default()
+    ///     }
+    /// ).unwrap();
+    ///
+    /// assert_eq!(*headline.borrow(), "Hello world");
+    /// ```
+    #[inline]
+    pub fn accumulate_text(
+        &mut self,
+        limit: usize,
+        handler: impl FnOnce(&str) -> Result<(), Box<dyn Error + Send + Sync>> + 'static,
+    ) {
+        if self.can_have_content {
+            self.text_accumulator = Some((TextAccumulator::new(limit), Box::new(handler)));
+        }
+    }
+
+    /// Invokes `handler` with the decoded text content of the element and its descendants once
+    /// the element's end tag is reached.
+    ///
+    /// This is a shorthand for [`accumulate_text`] without the length limit, so the text is
+    /// bounded by the [`max_allowed_memory_usage`] limit only. Use [`accumulate_text`] when
+    /// only a prefix of the text is needed.
+    ///
+    /// [`accumulate_text`]: #method.accumulate_text
+    /// [`max_allowed_memory_usage`]: ../struct.MemorySettings.html#structfield.max_allowed_memory_usage
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use lol_html::{rewrite_str, element, RewriteStrSettings};
+    /// use std::cell::RefCell;
+    /// use std::rc::Rc;
+    ///
+    /// let title = Rc::new(RefCell::new(String::new()));
+    ///
+    /// rewrite_str(
+    ///     r#"<head><title>Tom and Jerry</title></head><h1>Welcome</h1>"#,
+    ///     RewriteStrSettings {
+    ///         element_content_handlers: vec![
+    ///             element!("title", |el| {
+    ///                 let title = Rc::clone(&title);
+    ///
+    ///                 el.on_inner_text(move |text| {
+    ///                     *title.borrow_mut() = text.to_owned();
+    ///
+    ///                     Ok(())
+    ///                 });
+    ///
+    ///                 Ok(())
+    ///             })
+    ///         ],
+    ///         ..RewriteStrSettings::default()
+    ///     }
+    /// ).unwrap();
+    ///
+    /// assert_eq!(*title.borrow(), "Tom and Jerry");
+    /// ```
+    #[inline]
+    pub fn on_inner_text(
+        &mut self,
+        handler: impl FnOnce(&str) -> Result<(), Box<dyn Error + Send + Sync>> + 'static,
+    ) {
+        self.accumulate_text(usize::MAX, handler);
+    }
+
+    /// Buffers inner content of the element as a tree of nodes and invokes `handler` with it
+    /// once the element's end tag is reached. The tree, with all the modifications made by the
+    /// `handler`, is then serialized and written to the output in place of the original content.
+    ///
+    /// The tree reflects the content the way it would be written to the output after all other
+    /// content handlers have processed it: removed elements, text and comments are not in the
+    /// tree, while content inserted by the handlers, including buffered subtrees of
+    /// the descendant elements, is represented by [`Node::Html`] nodes.
+    ///
+    /// Buffered content is counted against the [`max_allowed_memory_usage`] limit. The handler
+    /// is not invoked if the element doesn't have an end tag in the document. Consequent calls
+    /// to the method overwrite the previously set handler. A call to the method doesn't make
+    /// any effect if the element is an [empty element].
+    ///
+    /// [`Node::Html`]: enum.Node.html#variant.Html
+    /// [`max_allowed_memory_usage`]: ../struct.MemorySettings.html#structfield.max_allowed_memory_usage
+    /// [empty element]: https://developer.mozilla.org/en-US/docs/Glossary/Empty_element
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use lol_html::{rewrite_str, element, RewriteStrSettings};
+    /// use lol_html::html_content::Node;
+    ///
+    /// let html = rewrite_str(
+    ///     r#"<ul><li>Foo</li><li class="ad">Buy!</li><li>Bar</li></ul>"#,
+    ///     RewriteStrSettings {
+    ///         element_content_handlers: vec![
+    ///             element!("ul", |el| {
+    ///                 el.buffer_subtree(|subtree| {
+    ///                     subtree.children_mut().retain(|node| match node {
+    ///                         Node::Element(el) => el.get_attribute("class") != Some("ad"),
+    ///                         _ => true,
+    ///                     });
+    ///
+    ///                     subtree.children_mut().reverse();
+    ///
+    ///                     Ok(())
+    ///                 });
+    ///
+    ///                 Ok(())
+    ///             })
+    ///         ],
+    ///         ..RewriteStrSettings::default()
+    ///     }
+    /// ).unwrap();
+    ///
+    /// assert_eq!(html, r#"<ul><li>Bar</li><li>Foo</li></ul>"#);
+    /// ```
+    #[inline]
+    pub fn buffer_subtree(
+        &mut self,
+        handler: impl FnOnce(&mut Subtree) -> Result<(), Box<dyn Error + Send + Sync>> + 'static,
+    ) {
+        if self.can_have_content {
+            self.buffered_subtree =
+                Some((Rc::new(RefCell::new(Subtree::default())), Box::new(handler)));
+        }
+    }
+
+    /// Registers a `handler` that is invoked once the element's end tag is reached, e.g. to
+    /// insert content right before `</body>`.
+    ///
+    /// The handler is invoked after the modifications of the end tag made by the other APIs of
+    /// the element, e.g. [`append`] or [`set_tag_name`], have been applied, so it observes and can
+    /// override them. Consequent calls to the method add handlers that are invoked in the order
+    /// they were registered. The handlers are not invoked if the element doesn't have an end tag
+    /// in the document. A call to the method doesn't make any effect if the element is
+    /// an [empty element].
+    ///
+    /// [`append`]: #method.append
+    /// [`set_tag_name`]: #method.set_tag_name
+    /// [empty element]: https://developer.mozilla.org/en-US/docs/Glossary/Empty_element
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use lol_html::{rewrite_str, element, RewriteStrSettings};
+    /// use lol_html::html_content::ContentType;
+    ///
+    /// let html = rewrite_str(
+    ///     r#"<body><div>Hello</div></body>"#,
+    ///     RewriteStrSettings {
+    ///         element_content_handlers: vec![
+    ///             element!("body", |el| {
+    ///                 el.on_end_tag(|end| {
+    ///                     end.before("<script src=app.js></script>", ContentType::Html);
+    ///
+    ///                     Ok(())
+    ///                 });
+    ///
+    ///                 Ok(())
+    ///             })
+    ///         ],
+    ///         ..RewriteStrSettings::default()
+    ///     }
+    /// ).unwrap();
+    ///
+    /// assert_eq!(html, r#"<body><div>Hello</div><script src=app.js></script></body>"#);
+    /// ```
+    #[inline]
+    pub fn on_end_tag(
+        &mut self,
+        handler: impl FnOnce(&mut EndTag) -> Result<(), Box<dyn Error + Send + Sync>> + 'static,
+    ) {
+        if self.can_have_content {
+            self.end_tag_handlers
+                .push((Box::new(handler), self.provenance.clone()));
+        }
+    }
+
+    /// Returns `true` if the element has been removed or replaced with some content.
+    #[inline]
+    pub fn removed(&self) -> bool {
+        self.start_tag.mutations.removed()
+    }
+
+    /// Returns `true` if the element can have inner content, i.e. it's not an [empty element]
+    /// or a self-closing element in foreign content.
+    ///
+    /// The methods that modify inner content of the element, like [`prepend`], [`append`] and
+    /// [`set_inner_content`], don't make any effect if the element can't have content.
+    ///
+    /// [empty element]: https://developer.mozilla.org/en-US/docs/Glossary/Empty_element
+    /// [`prepend`]: #method.prepend
+    /// [`append`]: #method.append
+    /// [`set_inner_content`]: #method.set_inner_content
+    #[inline]
+    pub fn can_have_content(&self) -> bool {
+        self.can_have_content
+    }
+
+    /// Returns the offset of the element's start tag in the input.
+    #[cfg(feature = "element_handlers")]
+    #[inline]
+    pub(crate) fn source_offset(&self) -> usize {
+        self.start_tag.source_offset()
+    }
+
+    /// Returns `true` if the element is a part of the content inserted by the content handlers
+    /// that is run through them, see [`ContentType::HtmlReprocessed`].
+    #[cfg(feature = "element_handlers")]
+    #[inline]
+    pub(crate) fn is_reprocessed(&self) -> bool {
+        self.reprocessed
+    }
+
+    #[cfg(feature = "element_handlers")]
+    #[inline]
+    pub(crate) fn set_reprocessed(&mut self, reprocessed: bool) {
+        self.reprocessed = reprocessed;
+    }
+
+    /// Sets the label of the content handler the subsequent insertions are attributed to.
+    #[cfg(feature = "element_handlers")]
+    #[inline]
+    pub(crate) fn set_provenance(&mut self, provenance: Option<Rc<str>>) {
+        self.start_tag.mutations.set_provenance(provenance.clone());
+
+        if let Some(ref mut mutations) = self.end_tag_mutations {
+            mutations.set_provenance(provenance.clone());
+        }
+
+        self.provenance = provenance;
+    }
+
+    #[cfg(feature = "element_handlers")]
+    #[inline]
+    pub(crate) fn set_self_closing(&mut self, self_closing: bool) {
+        self.start_tag.set_self_closing(self_closing);
+    }
+
+    /// Adds a hook that is invoked once the element's end tag is reached. Unlike the public
+    /// end tag related APIs, the hooks don't overwrite each other.
+    #[cfg(feature = "element_handlers")]
+    #[inline]
+    pub(crate) fn add_end_tag_hook(&mut self, hook: impl FnOnce() + 'static) {
+        if self.can_have_content {
+            self.end_tag_hooks.push(Box::new(hook));
+        }
+    }
+
+    #[inline]
+    pub(crate) fn should_remove_content(&self) -> bool {
+        self.should_remove_content
+    }
+
     #[inline]
-    pub fn removed(&self) -> bool {
-        self.start_tag.mutations.removed()
+    pub(crate) fn text_accumulator(&self) -> Option<TextAccumulator> {
+        self.text_accumulator.as_ref().map(|(a, _)| a.clone())
     }
 
     #[inline]
-    pub(crate) fn should_remove_content(&self) -> bool {
-        self.should_remove_content
+    pub(crate) fn buffered_subtree(&self) -> Option<Rc<RefCell<Subtree>>> {
+        self.buffered_subtree.as_ref().map(|(s, _)| Rc::clone(s))
     }
 
     pub(crate) fn into_end_tag_handler(self) -> Option<EndTagHandler<'static>> {
         let end_tag_mutations = self.end_tag_mutations;
         let modified_end_tag_name = self.modified_end_tag_name;
+        let text_accumulator = self.text_accumulator;
+        let buffered_subtree = self.buffered_subtree;
+        let end_tag_hooks = self.end_tag_hooks;
+        let end_tag_handlers = self.end_tag_handlers;
+        let encoding = self.encoding;
 
-        if end_tag_mutations.is_some() || modified_end_tag_name.is_some() {
+        if end_tag_mutations.is_some()
+            || modified_end_tag_name.is_some()
+            || text_accumulator.is_some()
+            || buffered_subtree.is_some()
+            || !end_tag_hooks.is_empty()
+            || !end_tag_handlers.is_empty()
+        {
             Some(Box::new(move |end_tag: &mut EndTag| {
+                end_tag_hooks.into_iter().for_each(|hook| hook());
+
                 if let Some(name) = modified_end_tag_name {
-                    end_tag.set_name(name);
+                    end_tag.set_name_bytes(name);
                 }
 
                 if let Some(mutations) = end_tag_mutations {
                     end_tag.mutations = mutations;
                 }
 
+                if let Some((accumulator, handler)) = text_accumulator {
+                    handler(&accumulator.text.borrow())?;
+                }
+
+                if let Some((subtree, handler)) = buffered_subtree {
+                    let mut subtree = subtree.borrow_mut();
+
+                    handler(&mut subtree)?;
+
+                    if !end_tag.mutations.removed() {
+                        let mut content = Vec::default();
+
+                        content_to_bytes(
+                            &subtree.to_html(),
+                            ContentType::Html,
+                            encoding,
+                            &mut |c| content.extend_from_slice(c),
+                        );
+
+                        end_tag.mutations.prepend_content_before(&content);
+                    }
+                }
+
+                for (handler, provenance) in end_tag_handlers {
+                    end_tag.set_provenance(provenance);
+                    handler(end_tag)?;
+                }
+
                 Ok(())
             }))
         } else {
@@ -497,6 +1293,8 @@ mod tests {
     use crate::rewritable_units::test_utils::*;
     use crate::*;
     use encoding_rs::{Encoding, EUC_JP, UTF_8};
+    use std::cell::RefCell;
+    use std::rc::Rc;
 
     fn rewrite_element(
         html: &[u8],
@@ -569,6 +1367,49 @@ mod tests {
         });
     }
 
+    #[test]
+    fn custom_element_names() {
+        [
+            ("<My-Element>", "my-element", "my-element"),
+            ("<x-foo.bar_baz>", r"x-foo\.bar_baz", "x-foo.bar_baz"),
+            ("<math-\u{3b1}>", "math-\u{3b1}", "math-\u{3b1}"),
+            ("<LongCustomName>", "LONGCUSTOMNAME", "longcustomname"),
+        ]
+        .iter()
+        .for_each(|&(html, selector, expected_name)| {
+            rewrite_element(html.as_bytes(), UTF_8, selector, |el| {
+                assert_eq!(el.tag_name(), expected_name);
+            });
+        });
+    }
+
+    #[test]
+    fn custom_element_names_nth_of_type() {
+        let output = rewrite_html(
+            b"<div><My-El></My-El><my-el></my-el><MY-EL></MY-EL></div>",
+            UTF_8,
+            vec![element!("my-el:nth-of-type(2n+1)", |el| {
+                el.set_attribute("odd", "")?;
+                Ok(())
+            })],
+            vec![],
+        );
+
+        assert_eq!(
+            output,
+            r#"<div><My-El odd=""></My-El><my-el></my-el><MY-EL odd=""></MY-EL></div>"#
+        );
+    }
+
+    #[test]
+    fn set_custom_element_tag_name() {
+        let output = rewrite_element(b"<div>Hi</div>", UTF_8, "div", |el| {
+            el.set_tag_name("my-el.v2_\u{f1}").unwrap();
+        });
+
+        assert_eq!(output, "<my-el.v2_\u{f1}>Hi</my-el.v2_\u{f1}>");
+    }
+
     #[test]
     fn namespace_uri() {
         rewrite_element(b"<script></script>", UTF_8, "script", |el| {
@@ -767,6 +1608,122 @@ mod tests {
         }
     }
 
+    #[test]
+    fn retain_attrs() {
+        for (html, enc) in encoded("<Foo FooѦ1=Bar1 onClick=x FooѤ2='Bar2'>") {
+            let output = rewrite_element(&html, enc, "foo", |el| {
+                el.retain_attributes(|attr| {
+                    if attr.name() == "fooѤ2" {
+                        attr.set_value("Bar\"Ѥ");
+                    }
+
+                    !attr.name().starts_with("on")
+                });
+
+                assert_eq!(el.attributes().len(), 2, "Encoding: {}", enc.name());
+
+                assert_eq!(
+                    el.get_attribute("onclick"),
+                    None,
+                    "Encoding: {}",
+                    enc.name()
+                );
+
+                assert_eq!(
+                    el.get_attribute("fooѤ2").unwrap(),
+                    "Bar\"Ѥ",
+                    "Encoding: {}",
+                    enc.name()
+                );
+            });
+
+            assert_eq!(output, "<Foo FooѦ1=Bar1 FooѤ2=\"Bar&quot;Ѥ\">");
+        }
+    }
+
+    #[test]
+    fn retain_all_attrs() {
+        let output = rewrite_element(b"<div A = 'b'  c>", UTF_8, "div", |el| {
+            el.retain_attributes(|_| true);
+        });
+
+        assert_eq!(output, "<div A = 'b'  c>");
+    }
+
+    #[test]
+    fn has_class() {
+        rewrite_element(b"<div class='\tfoo\x0Cbar  Baz\n'>", UTF_8, "div", |el| {
+            assert!(el.has_class("foo"));
+            assert!(el.has_class("bar"));
+            assert!(el.has_class("Baz"));
+            assert!(!el.has_class("baz"));
+            assert!(!el.has_class("foo bar"));
+            assert!(!el.has_class(""));
+        });
+
+        rewrite_element(b"<div>", UTF_8, "div", |el| {
+            assert!(!el.has_class("foo"));
+        });
+    }
+
+    #[test]
+    fn add_and_remove_class() {
+        for (html, enc) in encoded("<div class=' fooѦ bar fooѦ'>") {
+            let output = rewrite_element(&html, enc, "div", |el| {
+                el.add_class("bar").unwrap();
+
+                assert_eq!(
+                    el.get_attribute("class").unwrap(),
+                    " fooѦ bar fooѦ",
+                    "Encoding: {}",
+                    enc.name()
+                );
+
+                el.add_class("Ѧ").unwrap();
+                el.remove_class("bar").unwrap();
+                el.remove_class("qux").unwrap();
+            });
+
+            assert_eq!(output, "<div class=\"fooѦ Ѧ\">");
+        }
+
+        let output = rewrite_element(b"<div class=foo>", UTF_8, "div", |el| {
+            el.remove_class("foo").unwrap();
+        });
+
+        assert_eq!(output, "<div class=\"\">");
+    }
+
+    #[test]
+    fn toggle_class() {
+        let output = rewrite_element(b"<div>", UTF_8, "div", |el| {
+            assert!(el.toggle_class("foo").unwrap());
+            assert!(el.toggle_class("bar").unwrap());
+            assert!(!el.toggle_class("foo").unwrap());
+        });
+
+        assert_eq!(output, "<div class=\"bar\">");
+    }
+
+    #[test]
+    fn invalid_class_name() {
+        rewrite_element(b"<div class=foo>", UTF_8, "div", |el| {
+            assert_eq!(el.add_class("").unwrap_err(), ClassNameError::Empty);
+
+            assert_eq!(
+                el.remove_class("foo bar").unwrap_err(),
+                ClassNameError::ContainsWhitespace
+            );
+
+            assert_eq!(
+                el.toggle_class("foo\tbar").unwrap_err(),
+                ClassNameError::ContainsWhitespace
+            );
+
+            assert!(el.has_class("foo"));
+        });
+    }
+
     #[test]
     fn insert_content_before() {
         for (html, enc) in encoded("<div><span>ĥi</span></div>") {
@@ -840,6 +1797,22 @@ mod tests {
         }
     }
 
+    #[test]
+    fn extend_set_content() {
+        for (html, enc) in encoded("<div><span>Hi<b>RemoveŴ</b></span></div>") {
+            let output = rewrite_element(&html, enc, "span", |el| {
+                el.set_inner_content("<imgŵ>", ContentType::Html);
+                el.prepend("<prepended>", ContentType::Html);
+                el.append("<appended>", ContentType::Html);
+            });
+
+            assert_eq!(
+                output,
+                "<div><span><prepended><imgŵ><appended></span></div>"
+            );
+        }
+    }
+
     #[test]
     fn replace() {
         for (html, enc) in
@@ -947,6 +1920,8 @@ mod tests {
             el.prepend("<!--prepend-->", ContentType::Html);
             el.set_inner_content("<!--set_inner_content-->", ContentType::Html);
             el.set_tag_name("img-foo").unwrap();
+
+            assert!(!el.can_have_content());
         });
 
         assert_eq!(output, "<img-foo><!--after--><span>Hi</span></img>");
@@ -957,6 +1932,8 @@ mod tests {
         let output = rewrite_element(b"<svg><foo/>Hi</foo></svg>", UTF_8, "foo", |el| {
             el.after("<!--after-->", ContentType::Html);
             el.set_tag_name("bar").unwrap();
+
+            assert!(!el.can_have_content());
         });
 
         assert_eq!(output, "<svg><bar/><!--after-->Hi</foo></svg>");
@@ -975,6 +1952,273 @@ mod tests {
         });
     }
 
+    #[test]
+    fn accumulate_text() {
+        let accumulated = Rc::new(RefCell::new(Vec::default()));
+
+        let output = rewrite_element(
+            "<div>Hey <b>there</b><div>ça va?</div> world</div>".as_bytes(),
+            UTF_8,
+            "div",
+            |el| {
+                let accumulated = Rc::clone(&accumulated);
+
+                el.accumulate_text(10, move |text| {
+                    accumulated.borrow_mut().push(text.to_owned());
+                    Ok(())
+                });
+
+                el.set_tag_name("p").unwrap();
+            },
+        );
+
+        assert_eq!(output, "<p>Hey <b>there</b><p>ça va?</p> world</p>");
+
+        // NOTE: the multi-byte character can't be split, so the outer text stops before it.
+        assert_eq!(*accumulated.borrow(), ["ça va?", "Hey there"]);
+    }
+
+    #[test]
+    fn inner_text() {
+        let text = Rc::new(RefCell::new(String::new()));
+        let long_text = "a".repeat(2048);
+
+        let output = rewrite_element(
+            format!("<h1>Foo <i>{}</i> bar</h1>", long_text).as_bytes(),
+            UTF_8,
+            "h1",
+            |el| {
+                let text = Rc::clone(&text);
+
+                el.on_inner_text(move |t| {
+                    *text.borrow_mut() = t.to_owned();
+                    Ok(())
+                });
+            },
+        );
+
+        assert_eq!(output, format!("<h1>Foo <i>{}</i> bar</h1>", long_text));
+        assert_eq!(*text.borrow(), format!("Foo {} bar", long_text));
+    }
+
+    #[test]
+    fn accumulate_text_for_void_element() {
+        rewrite_element(b"<img>Hi", UTF_8, "img", |el| {
+            el.accumulate_text(10, |_| panic!("Handler shouldn't be called."));
+        });
+    }
+
+    #[test]
+    fn buffer_subtree() {
+        let output = rewrite_element(
+            b"<section>Hey <b class=x>there</b><!-- c --><br><p>unclosed</section>",
+            UTF_8,
+            "section",
+            |el| {
+                el.prepend("[prepend]", ContentType::Text);
+                el.append("[append]", ContentType::Text);
+
+                el.buffer_subtree(|subtree| {
+                    assert_eq!(subtree.text(), "Hey thereunclosed");
+
+                    if let Some(Node::Element(b)) = subtree.children_mut().get_mut(1) {
+                        assert_eq!(b.tag_name(), "b");
+
+                        b.set_attribute("class", "y")?;
+                    }
+
+                    subtree.children_mut().push(Node::text("<!>"));
+
+                    Ok(())
+                });
+            },
+        );
+
+        assert_eq!(
+            output,
+            concat!(
+                "<section>[prepend]Hey ",
+                r#"<b class="y">there</b><!-- c --><br><p>unclosed</p>&lt;!&gt;"#,
+                "[append]</section>"
+            )
+        );
+    }
+
+    #[test]
+    fn buffer_subtree_reflects_modifications_of_other_handlers() {
+        let output = rewrite_html(
+            b"<div><span>1</span><span>2</span></div>",
+            UTF_8,
+            vec![
+                element!("div", |el| {
+                    el.buffer_subtree(|subtree| {
+                        subtree.children_mut().reverse();
+
+                        Ok(())
+                    });
+
+                    Ok(())
+                }),
+                element!("span", |el| {
+                    el.set_attribute("class", "num").unwrap();
+
+                    Ok(())
+                }),
+            ],
+            vec![],
+        );
+
+        assert_eq!(
+            output,
+            r#"<div><span class="num">2</span><span class="num">1</span></div>"#
+        );
+    }
+
+    #[test]
+    fn buffer_subtree_reflects_removed_and_inserted_content() {
+        let output = rewrite_html(
+            b"<div><p>1</p><b>2</b><i>3</i><!--c-->5<section><s>6</s></section></div>",
+            UTF_8,
+            vec![
+                element!("div", |el| {
+                    el.buffer_subtree(|subtree| {
+                        assert_eq!(subtree.text(), "25");
+                        assert_eq!(
+                            subtree.to_html(),
+                            "<hr>2<i>three</i>!5<section><s>7</s></section>"
+                        );
+
+                        subtree.children_mut().reverse();
+
+                        Ok(())
+                    });
+
+                    Ok(())
+                }),
+                element!("p", |el| {
+                    el.remove();
+                    el.before("<hr>", ContentType::Html);
+
+                    Ok(())
+                }),
+                element!("b", |el| {
+                    el.remove_and_keep_content();
+
+                    Ok(())
+                }),
+                element!("i", |el| {
+                    el.set_inner_content("three", ContentType::Text);
+                    el.after("!", ContentType::Text);
+
+                    Ok(())
+                }),
+                element!("section", |el| {
+                    el.buffer_subtree(|subtree| {
+                        subtree.children_mut().clear();
+
+                        Ok(())
+                    });
+
+                    el.append("<s>7</s>", ContentType::Html);
+
+                    Ok(())
+                }),
+                comments!("div", |c| {
+                    c.remove();
+
+                    Ok(())
+                }),
+            ],
+            vec![],
+        );
+
+        assert_eq!(
+            output,
+            "<div><section><s>7</s></section>5!<i>three</i>2<hr></div>"
+        );
+    }
+
+    #[test]
+    fn buffer_subtree_with_replaced_content() {
+        let output = rewrite_element(b"<div>Hi</div>", UTF_8, "div", |el| {
+            el.buffer_subtree(|_| panic!("Handler shouldn't be called."));
+            el.set_inner_content("Bye", ContentType::Text);
+        });
+
+        assert_eq!(output, "<div>Bye</div>");
+    }
+
+    #[test]
+    fn buffer_subtree_for_void_element() {
+        let output = rewrite_element(b"<img>Hi", UTF_8, "img", |el| {
+            el.buffer_subtree(|_| panic!("Handler shouldn't be called."));
+        });
+
+        assert_eq!(output, "<img>Hi");
+    }
+
+    #[test]
+    fn end_tag_handlers() {
+        let output = rewrite_element(
+            b"<div><p>Hello</p></DIV><div>Bye</div>",
+            UTF_8,
+            "div",
+            |el| {
+                el.append("[append]", ContentType::Text);
+                el.set_tag_name("section").unwrap();
+
+                el.on_end_tag(|end| {
+                    assert_eq!(end.name(), "section");
+
+                    end.before("[before]", ContentType::Text);
+                    end.after("<hr>", ContentType::Html);
+
+                    Ok(())
+                });
+
+                el.on_end_tag(|end| {
+                    end.set_name("article")?;
+
+                    Ok(())
+                });
+            },
+        );
+
+        assert_eq!(
+            output,
+            concat!(
+                "<section><p>Hello</p>[append][before]</article><hr>",
+                "<section>Bye[append][before]</article><hr>"
+            )
+        );
+    }
+
+    #[test]
+    fn remove_end_tag() {
+        let output = rewrite_element(b"<div>Hi</div>", UTF_8, "div", |el| {
+            el.on_end_tag(|end| {
+                end.remove();
+                end.after("!", ContentType::Text);
+
+                assert!(end.removed());
+                assert_eq!(end.set_name("1"), Err(TagNameError::InvalidFirstCharacter));
+
+                Ok(())
+            });
+        });
+
+        assert_eq!(output, "<div>Hi!");
+    }
+
+    #[test]
+    fn end_tag_handlers_for_void_element() {
+        let output = rewrite_element(b"<img>Hi", UTF_8, "img", |el| {
+            el.on_end_tag(|_| panic!("Handler shouldn't be called."));
+        });
+
+        assert_eq!(output, "<img>Hi");
+    }
+
     mod serialization {
         use super::*;
 
@@ -0,0 +1,157 @@
+use pulldown_cmark::{html, Event, Parser, Tag};
+
+const LINK_SCHEMES: &[&str] = &["http", "https", "mailto"];
+const IMAGE_SCHEMES: &[&str] = &["http", "https"];
+
+/// Returns `true` if the `url` is relative or has one of the `allowed_schemes`.
+fn has_allowed_scheme(url: &str, allowed_schemes: &[&str]) -> bool {
+    // NOTE: browsers ignore leading C0 controls and spaces, as well as tabs and newlines
+    // anywhere in the URL, e.g. `java\tscript:` is a `javascript:` URL.
+    let url = url
+        .trim_start_matches(|c: char| c <= ' ')
+        .chars()
+        .filter(|&c| !matches!(c, '\t' | '\n' | '\r'))
+        .collect::<String>();
+
+    match url.find(|c| matches!(c, ':' | '/' | '?' | '#')) {
+        Some(idx) if url[idx..].starts_with(':') => allowed_schemes
+            .iter()
+            .any(|scheme| url[..idx].eq_ignore_ascii_case(scheme)),
+        _ => true,
+    }
+}
+
+#[inline]
+fn is_allowed_destination(tag: &Tag) -> bool {
+    match tag {
+        Tag::Link(_, url, _) => has_allowed_scheme(url, LINK_SCHEMES),
+        Tag::Image(_, url, _) => has_allowed_scheme(url, IMAGE_SCHEMES),
+        _ => true,
+    }
+}
+
+/// Renders `markdown` to HTML that can be inserted into the document with the
+/// [`ContentType::Html`] content type.
+///
+/// Markdown is treated as untrusted input:
+/// * raw HTML in it is HTML-escaped in the same way as the [`ContentType::Text`] content;
+/// * links are rendered only if their destination is a relative URL or a URL with
+///   the `http`, `https` or `mailto` scheme, otherwise only the text of the link is rendered;
+/// * images are rendered only if their source is a relative URL or a URL with the `http` or
+///   `https` scheme, otherwise only the alternative text of the image is rendered.
+///
+/// Requires the `markdown` feature.
+///
+/// # Example
+///
+/// ```
+/// use lol_html::{element, rewrite_str, RewriteStrSettings};
+/// use lol_html::html_content::{markdown_to_html, ContentType};
+///
+/// let html = rewrite_str(
+///     r#"<div id="comment"></div>"#,
+///     RewriteStrSettings {
+///         element_content_handlers: vec![element!("#comment", |el| {
+///             el.set_inner_content(
+///                 &markdown_to_html("*Hi*, [click me](javascript:alert(1)) <script>"),
+///                 ContentType::Html,
+///             );
+///
+///             Ok(())
+///         })],
+///         ..RewriteStrSettings::default()
+///     },
+/// )
+/// .unwrap();
+///
+/// assert_eq!(
+///     html,
+///     "<div id=\"comment\"><p><em>Hi</em>, click me &lt;script&gt;</p>\n</div>"
+/// );
+/// ```
+///
+/// [`ContentType::Html`]: enum.ContentType.html#variant.Html
+/// [`ContentType::Text`]: enum.ContentType.html#variant.Text
+pub fn markdown_to_html(markdown: &str) -> String {
+    let mut output = String::with_capacity(markdown.len() * 3 / 2);
+
+    let events = Parser::new(markdown).filter_map(|event| match event {
+        Event::Html(html) => Some(Event::Text(html)),
+        Event::Start(ref tag) | Event::End(ref tag) if !is_allowed_destination(tag) => None,
+        event => Some(event),
+    });
+
+    html::push_html(&mut output, events);
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::html_content::ContentType;
+    use crate::rewritable_units::test_utils::*;
+    use crate::*;
+
+    #[test]
+    fn append_markdown() {
+        for (html, enc) in encoded("<div><span>ĥi</span></div>") {
+            let output = rewrite_html(
+                &html,
+                enc,
+                vec![element!("span", |el| {
+                    el.append(&markdown_to_html("*Ĥey* <script>"), ContentType::Html);
+
+                    Ok(())
+                })],
+                vec![],
+            );
+
+            assert_eq!(
+                output,
+                "<div><span>ĥi<p><em>Ĥey</em> &lt;script&gt;</p>\n</span></div>"
+            );
+        }
+    }
+
+    #[test]
+    fn link_and_image_destinations() {
+        let html = markdown_to_html(concat!(
+            "[a](https://example.com) [b](/path) [c](mailto:a@example.com) ",
+            "[d](javascript:alert(1)) [e](JavaScript&#58;alert(1)) <vbscript:x> ",
+            "![f](data:image/png;base64,AA) ![g](img.png)"
+        ));
+
+        assert_eq!(
+            html,
+            concat!(
+                r#"<p><a href="https://example.com">a</a> <a href="/path">b</a> "#,
+                r#"<a href="mailto:a@example.com">c</a> d e vbscript:x "#,
+                r#"f <img src="img.png" alt="g" /></p>"#,
+                "\n"
+            )
+        );
+    }
+
+    #[test]
+    fn url_schemes() {
+        [
+            ("HTTPS://example.com", true),
+            ("mailto:a@example.com", true),
+            ("path/to:file", true),
+            ("?a:b", true),
+            ("#a:b", true),
+            ("data:text/html,a", false),
+            ("java\tscript:a", false),
+            (" javascript:a", false),
+            ("\u{1}javascript:a", false),
+            ("java\nscript:a", false),
+        ]
+        .iter()
+        .for_each(|&(url, allowed)| {
+            assert_eq!(has_allowed_scheme(url, LINK_SCHEMES), allowed, "{:?}", url);
+        });
+
+        assert!(!has_allowed_scheme("mailto:a@example.com", IMAGE_SCHEMES));
+    }
+}
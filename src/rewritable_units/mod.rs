@@ -2,9 +2,15 @@ use std::any::Any;
 
 pub use self::document_end::*;
 pub use self::element::*;
+#[cfg(feature = "markdown")]
+pub use self::markdown::markdown_to_html;
 pub use self::mutations::{ContentType, Mutations};
+pub use self::style::{Style, StylePropertyError};
+pub use self::subtree::{ElementNode, Node, Subtree};
 pub use self::tokens::*;
 
+pub(crate) use self::subtree::SubtreeBuilder;
+
 /// Data that can be attached to a rewritable unit by a user and shared between content handler
 /// invocations.
 ///
@@ -83,6 +89,10 @@ mod mutations;
 
 mod document_end;
 mod element;
+#[cfg(feature = "markdown")]
+mod markdown;
+mod style;
+mod subtree;
 mod tokens;
 
 #[cfg(test)]
@@ -1,10 +1,29 @@
 use crate::base::Bytes;
 use encoding_rs::Encoding;
+use std::cmp::Reverse;
+use std::ops::Range;
+use std::rc::Rc;
 
 /// The type of inserted content.
 pub enum ContentType {
     /// HTML content type. The rewriter will insert the content as is.
     Html,
+    /// HTML content type. The rewriter will run the content through the content handlers
+    /// before insertion, so the inserted elements, text and comments are rewritten by the same
+    /// rules as the content of the document.
+    ///
+    /// The content is parsed as a standalone fragment, so it can't close the elements of the
+    /// document and the elements it leaves unclosed don't affect the rest of the document.
+    /// Selectors that refer to the ancestors of the inserted elements take into account the
+    /// elements of the document that are open when the token the content is inserted at is
+    /// rewritten, e.g. the content prepended to an element is matched as a child of it.
+    ///
+    /// The content inserted by the content handlers while they process the inserted content is
+    /// run through them as well, up to a nesting depth of 16, after which the rewriting fails
+    /// with [`RewritingError::ReprocessingDepthExceeded`].
+    ///
+    /// [`RewritingError::ReprocessingDepthExceeded`]: ../errors/enum.RewritingError.html#variant.ReprocessingDepthExceeded
+    HtmlReprocessed,
     /// Text content type. The rewriter will HTML-escape the content before insertion:
     ///     - `<` will be replaced with `&lt;`
     ///     - `>` will be replaced with `&gt;`
@@ -17,13 +36,13 @@ pub(super) fn content_to_bytes(
     content: &str,
     content_type: ContentType,
     encoding: &'static Encoding,
-    mut output_handler: &mut dyn FnMut(&[u8]),
+    mut output_handler: &mut impl FnMut(&[u8]),
 ) {
-    let bytes = Bytes::from_str(content, encoding);
-
     match content_type {
-        ContentType::Html => output_handler(&bytes),
-        ContentType::Text => bytes.replace_byte3(
+        ContentType::Html | ContentType::HtmlReprocessed => {
+            output_handler(&Bytes::from_str(content, encoding))
+        }
+        ContentType::Text => Bytes::from_str(content, encoding).replace_byte3(
             (b'<', b"&lt;"),
             (b'>', b"&gt;"),
             (b'&', b"&amp;"),
@@ -32,12 +51,63 @@ pub(super) fn content_to_bytes(
     }
 }
 
+/// Same as `content_to_bytes`, but wraps the content in the provenance comments if
+/// the `provenance` label is specified. Returns the range of the serialized content without
+/// the provenance comments.
+#[inline]
+fn content_to_bytes_with_provenance(
+    content: &str,
+    content_type: ContentType,
+    encoding: &'static Encoding,
+    provenance: Option<&str>,
+    output_handler: &mut impl FnMut(&[u8]),
+) -> Range<usize> {
+    let mut len = 0;
+
+    let mut content_handler = |c: &[u8]| {
+        len += c.len();
+        output_handler(c);
+    };
+
+    match provenance {
+        Some(label) => {
+            let start = format!("<!--lolhtml:{}-->", label);
+            let end = format!("<!--/lolhtml:{}-->", label);
+            let start = Bytes::from_str(&start, encoding);
+            let end = Bytes::from_str(&end, encoding);
+
+            content_handler(&start);
+            content_to_bytes(content, content_type, encoding, &mut content_handler);
+            content_handler(&end);
+
+            start.len()..len - end.len()
+        }
+        None => {
+            content_to_bytes(content, content_type, encoding, &mut content_handler);
+
+            0..len
+        }
+    }
+}
+
+/// The part of the token's mutations the inserted content belongs to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum InsertionPoint {
+    Before,
+    Replacement,
+    After,
+}
+
 pub struct Mutations {
     pub content_before: Vec<u8>,
     pub replacement: Vec<u8>,
     pub content_after: Vec<u8>,
     pub removed: bool,
+    /// The ranges of the inserted content that should be run through the content handlers
+    /// before the output, see [`ContentType::HtmlReprocessed`].
+    reprocessed_content: Vec<(InsertionPoint, Range<usize>)>,
     encoding: &'static Encoding,
+    provenance: Option<Rc<str>>,
 }
 
 impl Mutations {
@@ -48,40 +118,167 @@ impl Mutations {
             replacement: Vec::default(),
             content_after: Vec::default(),
             removed: false,
+            reprocessed_content: Vec::default(),
             encoding,
+            provenance: None,
+        }
+    }
+
+    #[inline]
+    pub fn encoding(&self) -> &'static Encoding {
+        self.encoding
+    }
+
+    /// Sets the label of the content handler the subsequent insertions are attributed to.
+    #[inline]
+    pub fn set_provenance(&mut self, provenance: Option<Rc<str>>) {
+        self.provenance = provenance;
+    }
+
+    #[inline]
+    fn add_reprocessed_content(&mut self, point: InsertionPoint, range: Range<usize>) {
+        if !range.is_empty() {
+            self.reprocessed_content.push((point, range));
+        }
+    }
+
+    /// Shifts the ranges of the reprocessed content at the insertion `point` by `len` bytes
+    /// that have been inserted at the start of the content.
+    #[inline]
+    fn shift_reprocessed_content(&mut self, point: InsertionPoint, len: usize) {
+        for (p, range) in self.reprocessed_content.iter_mut() {
+            if *p == point {
+                *range = range.start + len..range.end + len;
+            }
         }
     }
 
     #[inline]
     pub fn before(&mut self, content: &str, content_type: ContentType) {
-        content_to_bytes(content, content_type, self.encoding, &mut |c| {
-            self.content_before.extend_from_slice(c);
-        });
+        let provenance = self.provenance.clone();
+        let start = self.content_before.len();
+        let is_reprocessed = matches!(content_type, ContentType::HtmlReprocessed);
+
+        let range = content_to_bytes_with_provenance(
+            content,
+            content_type,
+            self.encoding,
+            provenance.as_deref(),
+            &mut |c| self.content_before.extend_from_slice(c),
+        );
+
+        if is_reprocessed {
+            self.add_reprocessed_content(
+                InsertionPoint::Before,
+                range.start + start..range.end + start,
+            );
+        }
     }
 
     #[inline]
     pub fn after(&mut self, content: &str, content_type: ContentType) {
+        let provenance = self.provenance.clone();
+        let is_reprocessed = matches!(content_type, ContentType::HtmlReprocessed);
         let mut pos = 0;
 
-        content_to_bytes(content, content_type, self.encoding, &mut |c| {
-            self.content_after.splice(pos..pos, c.iter().cloned());
+        let range = content_to_bytes_with_provenance(
+            content,
+            content_type,
+            self.encoding,
+            provenance.as_deref(),
+            &mut |c| {
+                self.content_after.splice(pos..pos, c.iter().cloned());
+
+                pos += c.len();
+            },
+        );
 
-            pos += c.len();
-        });
+        self.shift_reprocessed_content(InsertionPoint::After, pos);
+
+        if is_reprocessed {
+            self.add_reprocessed_content(InsertionPoint::After, range);
+        }
     }
 
     #[inline]
     pub fn replace(&mut self, content: &str, content_type: ContentType) {
         let mut replacement = Vec::default();
+        let is_reprocessed = matches!(content_type, ContentType::HtmlReprocessed);
 
-        content_to_bytes(content, content_type, self.encoding, &mut |c| {
-            replacement.extend_from_slice(c);
-        });
+        let range = content_to_bytes_with_provenance(
+            content,
+            content_type,
+            self.encoding,
+            self.provenance.as_deref(),
+            &mut |c| replacement.extend_from_slice(c),
+        );
 
         self.replacement = replacement;
+        self.reprocessed_content
+            .retain(|(p, _)| *p != InsertionPoint::Replacement);
+
+        if is_reprocessed {
+            self.add_reprocessed_content(InsertionPoint::Replacement, range);
+        }
+
         self.remove();
     }
 
+    /// Removes the content inserted before the token.
+    #[inline]
+    pub fn clear_content_before(&mut self) {
+        self.content_before.clear();
+        self.reprocessed_content
+            .retain(|(p, _)| *p != InsertionPoint::Before);
+    }
+
+    /// Removes the content inserted after the token.
+    #[inline]
+    pub fn clear_content_after(&mut self) {
+        self.content_after.clear();
+        self.reprocessed_content
+            .retain(|(p, _)| *p != InsertionPoint::After);
+    }
+
+    /// Inserts serialized `content` in front of the content inserted before the token.
+    #[inline]
+    pub fn prepend_content_before(&mut self, content: &[u8]) {
+        self.content_before.splice(0..0, content.iter().cloned());
+        self.shift_reprocessed_content(InsertionPoint::Before, content.len());
+    }
+
+    #[inline]
+    pub fn has_reprocessed_content(&self) -> bool {
+        !self.reprocessed_content.is_empty()
+    }
+
+    /// Replaces each part of the inserted content that should be reprocessed with the output
+    /// of `reprocess` for it.
+    pub fn reprocess_content<E>(
+        &mut self,
+        mut reprocess: impl FnMut(&[u8]) -> Result<Vec<u8>, E>,
+    ) -> Result<(), E> {
+        let mut reprocessed_content = std::mem::take(&mut self.reprocessed_content);
+
+        // NOTE: the content is replaced starting from the end, so the ranges of
+        // the preceding content remain valid.
+        reprocessed_content.sort_by_key(|(point, range)| (*point, Reverse(range.start)));
+
+        for (point, range) in reprocessed_content {
+            let content = match point {
+                InsertionPoint::Before => &mut self.content_before,
+                InsertionPoint::Replacement => &mut self.replacement,
+                InsertionPoint::After => &mut self.content_after,
+            };
+
+            let output = reprocess(&content[range.clone()])?;
+
+            content.splice(range, output);
+        }
+
+        Ok(())
+    }
+
     #[inline]
     pub fn remove(&mut self) {
         self.removed = true;
@@ -91,4 +288,9 @@ impl Mutations {
     pub fn removed(&self) -> bool {
         self.removed
     }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        !self.removed && self.content_before.is_empty() && self.content_after.is_empty()
+    }
 }
@@ -0,0 +1,370 @@
+use super::Element;
+use thiserror::Error;
+
+/// An error that occurs when invalid property is provided to [`Style`].
+///
+/// [`Style`]: struct.Style.html
+#[derive(Error, Debug, PartialEq, Copy, Clone)]
+pub enum StylePropertyError {
+    /// The provided property name is empty.
+    #[error("Property name can't be empty.")]
+    EmptyName,
+
+    /// The provided property name contains a character that is forbidden in property names
+    /// (e.g. `':'`).
+    #[error("`{0}` character is forbidden in the property name")]
+    ForbiddenCharacter(char),
+
+    /// The provided property value contains a semicolon that would end the declaration or
+    /// has unbalanced quotes, brackets, comments or escapes.
+    #[error("Property value should be a single well-formed declaration value.")]
+    InvalidValue,
+}
+
+#[inline]
+fn validate_property_name(name: &str) -> Result<(), StylePropertyError> {
+    if name.is_empty() {
+        Err(StylePropertyError::EmptyName)
+    } else if let Some(ch) = name.chars().find(|&ch| {
+        ch.is_ascii_whitespace()
+            || matches!(
+                ch,
+                ':' | ';' | '!' | '"' | '\'' | '(' | ')' | '[' | ']' | '{' | '}' | '\\' | '/'
+            )
+    }) {
+        Err(StylePropertyError::ForbiddenCharacter(ch))
+    } else {
+        Ok(())
+    }
+}
+
+/// Property names are ASCII case-insensitive, except for the names of custom properties.
+#[inline]
+fn normalize_property_name(name: &str) -> String {
+    if name.starts_with("--") {
+        name.to_string()
+    } else {
+        name.to_ascii_lowercase()
+    }
+}
+
+/// Splits the value of the `style` attribute on the semicolons that are not enclosed in strings
+/// or brackets, stripping the comments. Also returns `false` if there are unterminated strings,
+/// comments, brackets or escapes in the value.
+fn split_declarations(style: &str) -> (Vec<String>, bool) {
+    let mut declarations = Vec::new();
+    let mut current = String::new();
+    let mut quote = None;
+    let mut depth = 0usize;
+    let mut balanced = true;
+    let mut chars = style.chars().peekable();
+
+    while let Some(ch) = chars.next() {
+        match ch {
+            '\\' => {
+                current.push(ch);
+
+                match chars.next() {
+                    Some(ch) => current.push(ch),
+                    None => balanced = false,
+                }
+            }
+            _ if quote == Some(ch) => {
+                current.push(ch);
+                quote = None;
+            }
+            _ if quote.is_some() => current.push(ch),
+            '"' | '\'' => {
+                current.push(ch);
+                quote = Some(ch);
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+
+                let mut prev = None;
+
+                loop {
+                    match chars.next() {
+                        Some('/') if prev == Some('*') => break,
+                        Some(ch) => prev = Some(ch),
+                        None => {
+                            balanced = false;
+                            break;
+                        }
+                    }
+                }
+            }
+            '(' | '[' | '{' => {
+                current.push(ch);
+                depth += 1;
+            }
+            ')' | ']' | '}' => {
+                current.push(ch);
+
+                if depth == 0 {
+                    balanced = false;
+                } else {
+                    depth -= 1;
+                }
+            }
+            ';' if depth == 0 => declarations.push(std::mem::take(&mut current)),
+            _ => current.push(ch),
+        }
+    }
+
+    declarations.push(current);
+
+    (declarations, balanced && quote.is_none() && depth == 0)
+}
+
+fn parse_declarations(style: &str) -> Vec<(String, String)> {
+    split_declarations(style)
+        .0
+        .iter()
+        .filter_map(|declaration| {
+            let colon = declaration.find(':')?;
+            let name = declaration[..colon].trim_matches(|c: char| c.is_ascii_whitespace());
+            let value = declaration[colon + 1..].trim_matches(|c: char| c.is_ascii_whitespace());
+
+            // NOTE: invalid declarations are dropped, the same way browsers do.
+            if validate_property_name(name).is_err() || value.is_empty() {
+                None
+            } else {
+                Some((normalize_property_name(name), value.to_string()))
+            }
+        })
+        .collect()
+}
+
+/// A parsed `style` attribute of an [`Element`].
+///
+/// Provides access to the individual declarations of the attribute. Each modification is
+/// immediately serialized back into the `style` attribute of the element, in which case
+/// comments and invalid declarations are dropped from the attribute value. The attribute is left
+/// untouched if it's not modified.
+///
+/// Values are stored verbatim, so a value of an important declaration includes the `!important`
+/// suffix.
+///
+/// [`Element`]: struct.Element.html
+pub struct Style<'e, 'r, 't> {
+    element: &'e mut Element<'r, 't>,
+    declarations: Vec<(String, String)>,
+}
+
+impl<'e, 'r, 't> Style<'e, 'r, 't> {
+    pub(super) fn new(element: &'e mut Element<'r, 't>) -> Self {
+        let declarations = element
+            .get_attribute("style")
+            .map(|style| parse_declarations(&style))
+            .unwrap_or_default();
+
+        Style {
+            element,
+            declarations,
+        }
+    }
+
+    /// Returns the value of the property with `name`.
+    ///
+    /// If the property is declared multiple times the value of the last declaration is returned,
+    /// as it's the one that takes effect.
+    #[inline]
+    pub fn get_property(&self, name: &str) -> Option<&str> {
+        let name = normalize_property_name(name);
+
+        self.declarations
+            .iter()
+            .rev()
+            .find(|(n, _)| *n == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Sets `value` of the property with `name`, adding a declaration for the property if it's
+    /// not present. Setting an empty value removes the property.
+    pub fn set_property(&mut self, name: &str, value: &str) -> Result<(), StylePropertyError> {
+        validate_property_name(name)?;
+
+        let value = value.trim_matches(|c: char| c.is_ascii_whitespace());
+
+        if value.is_empty() {
+            self.remove_property(name);
+
+            return Ok(());
+        }
+
+        let (declarations, balanced) = split_declarations(value);
+
+        if !balanced || declarations.len() != 1 {
+            return Err(StylePropertyError::InvalidValue);
+        }
+
+        let name = normalize_property_name(name);
+
+        match self.declarations.iter().rposition(|(n, _)| *n == name) {
+            Some(idx) => {
+                // NOTE: the previous declarations of the property don't have any effect,
+                // so we don't preserve them.
+                let shift = self.declarations[..idx]
+                    .iter()
+                    .filter(|(n, _)| *n == name)
+                    .count();
+
+                self.declarations.retain(|(n, _)| *n != name);
+                self.declarations
+                    .insert(idx - shift, (name, value.to_string()));
+            }
+            None => self.declarations.push((name, value.to_string())),
+        }
+
+        self.update_attribute();
+
+        Ok(())
+    }
+
+    /// Removes all the declarations of the property with `name` if it is present.
+    pub fn remove_property(&mut self, name: &str) {
+        let name = normalize_property_name(name);
+        let len = self.declarations.len();
+
+        self.declarations.retain(|(n, _)| *n != name);
+
+        if self.declarations.len() != len {
+            self.update_attribute();
+        }
+    }
+
+    fn update_attribute(&mut self) {
+        let style = self
+            .declarations
+            .iter()
+            .map(|(name, value)| format!("{}: {}", name, value))
+            .collect::<Vec<_>>()
+            .join("; ");
+
+        self.element
+            .set_attribute("style", &style)
+            .expect("The `style` attribute name should be valid in any encoding.");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rewritable_units::test_utils::*;
+    use crate::*;
+    use encoding_rs::UTF_8;
+
+    fn rewrite_style(html: &str, mut handler: impl FnMut(&mut Style)) -> String {
+        rewrite_html(
+            html.as_bytes(),
+            UTF_8,
+            vec![element!("div", |el| {
+                handler(&mut el.style());
+                Ok(())
+            })],
+            vec![],
+        )
+    }
+
+    #[test]
+    fn parse() {
+        let html = concat!(
+            r#"<div style=" COLOR :red;background: url('a;b.png') ; /* width: 1px; */"#,
+            r#"content: ';\'';--Foo: {a;b}; invalid; :x; margin:;"#,
+            r#"color: blue !important">"#
+        );
+
+        let output = rewrite_style(html, |style| {
+            assert_eq!(style.get_property("color"), Some("blue !important"));
+            assert_eq!(style.get_property("Background"), Some("url('a;b.png')"));
+            assert_eq!(style.get_property("width"), None);
+            assert_eq!(style.get_property("content"), Some(r#"';\''"#));
+            assert_eq!(style.get_property("--Foo"), Some("{a;b}"));
+            assert_eq!(style.get_property("--foo"), None);
+            assert_eq!(style.get_property("margin"), None);
+            assert_eq!(style.get_property("invalid"), None);
+        });
+
+        assert_eq!(output, html);
+    }
+
+    #[test]
+    fn set_property() {
+        let output = rewrite_style(
+            r#"<div style="color: red; display: block; Color: green /* c */">"#,
+            |style| {
+                style.set_property("COLOR", " blue ").unwrap();
+                style.set_property("position", "fixed !important").unwrap();
+
+                assert_eq!(style.get_property("color"), Some("blue"));
+                assert_eq!(style.get_property("position"), Some("fixed !important"));
+            },
+        );
+
+        assert_eq!(
+            output,
+            r#"<div style="display: block; color: blue; position: fixed !important">"#
+        );
+
+        let output = rewrite_style("<div>", |style| {
+            style.set_property("display", "none").unwrap();
+        });
+
+        assert_eq!(output, r#"<div style="display: none">"#);
+    }
+
+    #[test]
+    fn remove_property() {
+        let output = rewrite_style(
+            r#"<div style="position: fixed; top: 0; POSITION: absolute">"#,
+            |style| {
+                style.remove_property("Position");
+                style.set_property("top", "").unwrap();
+
+                assert_eq!(style.get_property("position"), None);
+            },
+        );
+
+        assert_eq!(output, r#"<div style="">"#);
+
+        let output = rewrite_style(r#"<div style="top:0">"#, |style| {
+            style.remove_property("position");
+        });
+
+        assert_eq!(output, r#"<div style="top:0">"#);
+    }
+
+    #[test]
+    fn invalid_property() {
+        let output = rewrite_style(r#"<div style="top: 0">"#, |style| {
+            assert_eq!(
+                style.set_property("", "0").unwrap_err(),
+                StylePropertyError::EmptyName
+            );
+
+            assert_eq!(
+                style.set_property("to p", "0").unwrap_err(),
+                StylePropertyError::ForbiddenCharacter(' ')
+            );
+
+            assert_eq!(
+                style.set_property("top:", "0").unwrap_err(),
+                StylePropertyError::ForbiddenCharacter(':')
+            );
+
+            ["0; position: fixed", "url(a", "'a", "0 /* c", "a)", "a\\"]
+                .iter()
+                .for_each(|value| {
+                    assert_eq!(
+                        style.set_property("left", value).unwrap_err(),
+                        StylePropertyError::InvalidValue,
+                        "{}",
+                        value
+                    );
+                });
+        });
+
+        assert_eq!(output, r#"<div style="top: 0">"#);
+    }
+}
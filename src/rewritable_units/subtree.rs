@@ -0,0 +1,602 @@
+use super::{
+    validate_tag_name, AttributeNameError, EndTag, Mutations, StartTag, TagNameError, Token,
+};
+use crate::memory::{MemoryLimitExceededError, SharedMemoryLimiter};
+use encoding_rs::Encoding;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+const HTML_NAMESPACE_URI: &str = "http://www.w3.org/1999/xhtml";
+
+#[inline]
+fn is_void_element(tag_name: &str) -> bool {
+    // NOTE: https://html.spec.whatwg.org/multipage/syntax.html#void-elements
+    matches!(
+        tag_name,
+        "area"
+            | "base"
+            | "basefont"
+            | "bgsound"
+            | "br"
+            | "col"
+            | "embed"
+            | "frame"
+            | "hr"
+            | "image"
+            | "img"
+            | "input"
+            | "keygen"
+            | "link"
+            | "meta"
+            | "param"
+            | "source"
+            | "track"
+            | "wbr"
+    )
+}
+
+#[inline]
+fn validate_attribute_name(name: &str) -> Result<(), AttributeNameError> {
+    if name.is_empty() {
+        Err(AttributeNameError::Empty)
+    } else if let Some(ch) = name.chars().find(|&ch| {
+        matches!(
+            ch,
+            ' ' | '\n' | '\r' | '\t' | '\x0C' | '/' | '>' | '=' | '"' | '\'' | '<'
+        )
+    }) {
+        Err(AttributeNameError::ForbiddenCharacter(ch))
+    } else {
+        Ok(())
+    }
+}
+
+fn push_text(nodes: &mut Vec<Node>, text: &str) {
+    match nodes.last_mut() {
+        Some(Node::Text(last)) => last.push_str(text),
+        _ => nodes.push(Node::Text(text.to_string())),
+    }
+}
+
+fn collect_text(nodes: &[Node], output: &mut String) {
+    for node in nodes {
+        match node {
+            Node::Element(element) => collect_text(&element.children, output),
+            Node::Text(text) => output.push_str(text),
+            Node::Comment(_) | Node::Html(_) => (),
+        }
+    }
+}
+
+fn serialize_nodes(nodes: &[Node], output: &mut String) {
+    for node in nodes {
+        match node {
+            Node::Element(element) => element.serialize(output),
+            Node::Text(text) | Node::Html(text) => output.push_str(text),
+            Node::Comment(text) => {
+                output.push_str("<!--");
+                output.push_str(text);
+                output.push_str("-->");
+            }
+        }
+    }
+}
+
+/// A node of a buffered [`Subtree`].
+///
+/// [`Subtree`]: struct.Subtree.html
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Node {
+    /// An element.
+    Element(ElementNode),
+    /// Text in its source form, i.e. with HTML character references not decoded.
+    Text(String),
+    /// A comment text.
+    Comment(String),
+    /// Content inserted by the content handlers, e.g. with [`Element::before`], in its serialized
+    /// form. Its text is not included in the text of the subtree.
+    ///
+    /// [`Element::before`]: struct.Element.html#method.before
+    Html(String),
+}
+
+impl Node {
+    /// Creates a text node from the given text, escaping HTML special characters in it.
+    pub fn text(text: &str) -> Self {
+        Node::Text(
+            text.replace('&', "&amp;")
+                .replace('<', "&lt;")
+                .replace('>', "&gt;"),
+        )
+    }
+}
+
+/// An element of a buffered [`Subtree`].
+///
+/// [`Subtree`]: struct.Subtree.html
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ElementNode {
+    tag_name: String,
+    attributes: Vec<(String, String)>,
+    self_closing: bool,
+    void: bool,
+    children: Vec<Node>,
+}
+
+impl ElementNode {
+    /// Creates an element without attributes and children.
+    ///
+    /// The caller is responsible for providing a valid tag name.
+    pub fn new(tag_name: &str) -> Self {
+        let tag_name = tag_name.to_ascii_lowercase();
+
+        ElementNode {
+            void: is_void_element(&tag_name),
+            tag_name,
+            attributes: Vec::default(),
+            self_closing: false,
+            children: Vec::default(),
+        }
+    }
+
+    /// Returns the tag name of the element.
+    #[inline]
+    pub fn tag_name(&self) -> &str {
+        &self.tag_name
+    }
+
+    /// Sets the tag name of the element.
+    ///
+    /// The element is serialized without the end tag and the children if it's renamed to
+    /// a [void element].
+    ///
+    /// [void element]: https://html.spec.whatwg.org/multipage/syntax.html#void-elements
+    pub fn set_tag_name(&mut self, name: &str) -> Result<(), TagNameError> {
+        validate_tag_name(name)?;
+
+        self.tag_name = name.to_ascii_lowercase();
+        self.void = is_void_element(&self.tag_name);
+
+        Ok(())
+    }
+
+    /// Returns the attributes of the element as name-value pairs.
+    ///
+    /// Values are in their source form, i.e. with HTML character references not decoded.
+    #[inline]
+    pub fn attributes(&self) -> &[(String, String)] {
+        &self.attributes
+    }
+
+    /// Returns the value of the attribute with the `name`.
+    ///
+    /// The value is in its source form, i.e. with HTML character references not decoded.
+    #[inline]
+    pub fn get_attribute(&self, name: &str) -> Option<&str> {
+        let name = name.to_ascii_lowercase();
+
+        self.attributes
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Returns `true` if the element has an attribute with the `name`.
+    #[inline]
+    pub fn has_attribute(&self, name: &str) -> bool {
+        self.get_attribute(name).is_some()
+    }
+
+    /// Sets `value` of the element's attribute with the `name`.
+    ///
+    /// If the element doesn't have an attribute with the `name`, the method adds a new attribute
+    /// to the element with the `name` and the `value`.
+    pub fn set_attribute(&mut self, name: &str, value: &str) -> Result<(), AttributeNameError> {
+        validate_attribute_name(name)?;
+
+        let name = name.to_ascii_lowercase();
+
+        match self.attributes.iter_mut().find(|(n, _)| *n == name) {
+            Some((_, v)) => *v = value.to_string(),
+            None => self.attributes.push((name, value.to_string())),
+        }
+
+        Ok(())
+    }
+
+    /// Removes the element's attribute with the `name`.
+    #[inline]
+    pub fn remove_attribute(&mut self, name: &str) {
+        let name = name.to_ascii_lowercase();
+
+        self.attributes.retain(|(n, _)| *n != name);
+    }
+
+    /// Returns the child nodes of the element.
+    #[inline]
+    pub fn children(&self) -> &[Node] {
+        &self.children
+    }
+
+    /// Returns the mutable child nodes of the element.
+    #[inline]
+    pub fn children_mut(&mut self) -> &mut Vec<Node> {
+        &mut self.children
+    }
+
+    /// Returns the concatenated text of all the descendant text nodes of the element.
+    #[inline]
+    pub fn text(&self) -> String {
+        let mut text = String::new();
+
+        collect_text(&self.children, &mut text);
+
+        text
+    }
+
+    fn serialize(&self, output: &mut String) {
+        output.push('<');
+        output.push_str(&self.tag_name);
+
+        for (name, value) in &self.attributes {
+            output.push(' ');
+            output.push_str(name);
+            output.push_str("=\"");
+            output.push_str(&value.replace('"', "&quot;"));
+            output.push('"');
+        }
+
+        if self.self_closing && self.children.is_empty() {
+            output.push_str(" />");
+        } else if self.void {
+            output.push('>');
+        } else {
+            output.push('>');
+            serialize_nodes(&self.children, output);
+            output.push_str("</");
+            output.push_str(&self.tag_name);
+            output.push('>');
+        }
+    }
+}
+
+/// Buffered inner content of an element.
+///
+/// See [`Element::buffer_subtree`] for the details.
+///
+/// [`Element::buffer_subtree`]: struct.Element.html#method.buffer_subtree
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Subtree {
+    children: Vec<Node>,
+}
+
+impl Subtree {
+    /// Returns the top level nodes of the subtree.
+    #[inline]
+    pub fn children(&self) -> &[Node] {
+        &self.children
+    }
+
+    /// Returns the mutable top level nodes of the subtree.
+    #[inline]
+    pub fn children_mut(&mut self) -> &mut Vec<Node> {
+        &mut self.children
+    }
+
+    /// Returns the concatenated text of all the text nodes of the subtree.
+    #[inline]
+    pub fn text(&self) -> String {
+        let mut text = String::new();
+
+        collect_text(&self.children, &mut text);
+
+        text
+    }
+
+    /// Serializes the subtree to HTML.
+    #[inline]
+    pub fn to_html(&self) -> String {
+        let mut html = String::new();
+
+        serialize_nodes(&self.children, &mut html);
+
+        html
+    }
+}
+
+/// Builds a [`Subtree`] from the tokens of an element's content.
+pub(crate) struct SubtreeBuilder {
+    subtree: Rc<RefCell<Subtree>>,
+    open_elements: Vec<ElementNode>,
+    children: Vec<Node>,
+    memory_limiter: SharedMemoryLimiter,
+    memory_usage: usize,
+    custom_void_elements: Rc<[String]>,
+}
+
+impl SubtreeBuilder {
+    pub fn new(
+        subtree: Rc<RefCell<Subtree>>,
+        memory_limiter: SharedMemoryLimiter,
+        custom_void_elements: Rc<[String]>,
+    ) -> Self {
+        SubtreeBuilder {
+            subtree,
+            open_elements: Vec::default(),
+            children: Vec::default(),
+            memory_limiter,
+            memory_usage: 0,
+            custom_void_elements,
+        }
+    }
+
+    #[inline]
+    fn current_children(&mut self) -> &mut Vec<Node> {
+        match self.open_elements.last_mut() {
+            Some(element) => &mut element.children,
+            None => &mut self.children,
+        }
+    }
+
+    fn close_current_element(&mut self) {
+        if let Some(element) = self.open_elements.pop() {
+            self.current_children().push(Node::Element(element));
+        }
+    }
+
+    fn track_memory(&mut self, byte_count: usize) -> Result<(), MemoryLimitExceededError> {
+        self.memory_limiter
+            .borrow_mut()
+            .increase_usage(byte_count)?;
+        self.memory_usage += byte_count;
+
+        Ok(())
+    }
+
+    /// Adds the content inserted by the handlers as a node of the current element.
+    fn add_inserted_content(
+        &mut self,
+        content: &[u8],
+        encoding: &'static Encoding,
+    ) -> Result<(), MemoryLimitExceededError> {
+        if !content.is_empty() {
+            let html = encoding.decode_without_bom_handling(content).0.into_owned();
+
+            self.track_memory(html.len())?;
+            self.current_children().push(Node::Html(html));
+        }
+
+        Ok(())
+    }
+
+    fn add_start_tag(&mut self, start_tag: &StartTag) -> Result<(), MemoryLimitExceededError> {
+        let tag_name = start_tag.name().to_string();
+
+        let attributes = start_tag
+            .attributes()
+            .iter()
+            .map(|a| (a.name().to_string(), a.value().to_string()))
+            .collect::<Vec<_>>();
+
+        self.track_memory(
+            tag_name.len()
+                + attributes
+                    .iter()
+                    .map(|(n, v)| n.len() + v.len())
+                    .sum::<usize>(),
+        )?;
+
+        let is_html_element = start_tag.namespace_uri() == HTML_NAMESPACE_URI;
+
+        let void = is_html_element
+            && (is_void_element(&tag_name) || self.custom_void_elements.contains(&tag_name));
+
+        let element = ElementNode {
+            tag_name,
+            attributes,
+            self_closing: start_tag.self_closing() && !is_html_element,
+            void,
+            children: Vec::default(),
+        };
+
+        // NOTE: void elements and self-closing foreign elements can't have content.
+        if element.self_closing || element.void {
+            self.current_children().push(Node::Element(element));
+        } else {
+            self.open_elements.push(element);
+        }
+
+        Ok(())
+    }
+
+    fn add_end_tag(&mut self, end_tag: &EndTag) {
+        let tag_name = end_tag.name();
+
+        // NOTE: end tags without a matching open element are ignored, while elements
+        // that are not closed explicitly are closed by the end tag of their ancestor.
+        if let Some(idx) = self
+            .open_elements
+            .iter()
+            .rposition(|e| e.tag_name == tag_name)
+        {
+            while self.open_elements.len() > idx {
+                self.close_current_element();
+            }
+        }
+    }
+
+    /// Adds the token to the subtree the same way it's written to the output, i.e. with
+    /// the modifications made by the handlers.
+    pub fn add_token(&mut self, token: &Token) -> Result<(), MemoryLimitExceededError> {
+        let Mutations {
+            content_before,
+            replacement,
+            content_after,
+            removed,
+            ..
+        } = token.mutations();
+
+        let encoding = token.mutations().encoding();
+
+        self.add_inserted_content(content_before, encoding)?;
+
+        if *removed {
+            self.add_inserted_content(replacement, encoding)?;
+        } else {
+            match token {
+                Token::StartTag(start_tag) => self.add_start_tag(start_tag)?,
+                Token::EndTag(end_tag) => self.add_end_tag(end_tag),
+                Token::TextChunk(text) => {
+                    let text = text.as_str();
+
+                    self.track_memory(text.len())?;
+                    push_text(self.current_children(), text);
+                }
+                Token::Comment(comment) => {
+                    let text = comment.text();
+
+                    self.track_memory(text.len())?;
+                    self.current_children().push(Node::Comment(text));
+                }
+                Token::Doctype(_) => (),
+            }
+        }
+
+        // NOTE: content inserted after a start tag is the first content of the element.
+        self.add_inserted_content(content_after, encoding)
+    }
+}
+
+impl Drop for SubtreeBuilder {
+    fn drop(&mut self) {
+        while !self.open_elements.is_empty() {
+            self.close_current_element();
+        }
+
+        self.subtree.borrow_mut().children = std::mem::take(&mut self.children);
+
+        self.memory_limiter
+            .borrow_mut()
+            .decrease_usage(self.memory_usage);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn element(tag_name: &str, attributes: &[(&str, &str)], children: Vec<Node>) -> Node {
+        let mut element = ElementNode::new(tag_name);
+
+        for (name, value) in attributes {
+            element.set_attribute(name, value).unwrap();
+        }
+
+        *element.children_mut() = children;
+
+        Node::Element(element)
+    }
+
+    #[test]
+    fn serialization() {
+        let subtree = Subtree {
+            children: vec![
+                Node::text("1 < 2 "),
+                element(
+                    "a",
+                    &[("href", "/foo?a=1&amp;b=2"), ("title", r#"Say "hi""#)],
+                    vec![Node::Text("Foo".into())],
+                ),
+                element("br", &[], vec![]),
+                Node::Comment(" hey ".into()),
+            ],
+        };
+
+        assert_eq!(
+            subtree.to_html(),
+            concat!(
+                "1 &lt; 2 ",
+                r#"<a href="/foo?a=1&amp;b=2" title="Say &quot;hi&quot;">Foo</a>"#,
+                "<br><!-- hey -->"
+            )
+        );
+    }
+
+    #[test]
+    fn attributes() {
+        let mut element = ElementNode::new("DIV");
+
+        element.set_attribute("Foo", "1").unwrap();
+        element.set_attribute("bar", "2").unwrap();
+        element.set_attribute("foo", "3").unwrap();
+
+        assert_eq!(element.tag_name(), "div");
+        assert_eq!(element.get_attribute("FOO"), Some("3"));
+        assert!(element.has_attribute("bar"));
+
+        element.remove_attribute("bar");
+
+        assert!(!element.has_attribute("bar"));
+        assert_eq!(
+            element.attributes(),
+            &[("foo".to_string(), "3".to_string())]
+        );
+
+        assert_eq!(
+            element.set_attribute("", "1"),
+            Err(AttributeNameError::Empty)
+        );
+
+        assert_eq!(
+            element.set_attribute("foo>", "1"),
+            Err(AttributeNameError::ForbiddenCharacter('>'))
+        );
+    }
+
+    #[test]
+    fn tag_name() {
+        let mut element = ElementNode::new("B");
+
+        *element.children_mut() = vec![Node::text("Bold")];
+
+        element.set_tag_name("Strong").unwrap();
+
+        assert_eq!(element.tag_name(), "strong");
+        let to_html = |element: &ElementNode| {
+            Subtree {
+                children: vec![Node::Element(element.clone())],
+            }
+            .to_html()
+        };
+
+        assert_eq!(to_html(&element), "<strong>Bold</strong>");
+
+        element.set_tag_name("br").unwrap();
+
+        assert_eq!(to_html(&element), "<br>");
+
+        assert_eq!(element.set_tag_name(""), Err(TagNameError::Empty));
+        assert_eq!(
+            element.set_tag_name("1a"),
+            Err(TagNameError::InvalidFirstCharacter)
+        );
+        assert_eq!(
+            element.set_tag_name("a/b"),
+            Err(TagNameError::ForbiddenCharacter('/'))
+        );
+        assert_eq!(element.tag_name(), "br");
+    }
+
+    #[test]
+    fn text() {
+        let subtree = Subtree {
+            children: vec![
+                Node::Text("Hello ".into()),
+                element("b", &[], vec![Node::Text("world".into())]),
+                Node::Comment("!".into()),
+            ],
+        };
+
+        assert_eq!(subtree.text(), "Hello world");
+    }
+}
@@ -1,10 +1,13 @@
 use crate::base::Bytes;
+use crate::memory::profile;
 use crate::parser::SharedAttributeBuffer;
 use crate::rewritable_units::Serialize;
 use encoding_rs::Encoding;
 use lazycell::LazyCell;
+use smallvec::SmallVec;
 use std::fmt::{self, Debug};
 use std::ops::Deref;
+use std::ops::Range;
 use thiserror::Error;
 
 /// An error that occurs when invalid value is provided for the attribute name.
@@ -29,22 +32,35 @@ pub enum AttributeNameError {
 
 /// An attribute of an [`Element`].
 ///
-/// This is an immutable representation of an attribute. To modify element's attributes use
-/// approriate [`Element`]'s methods.
+/// To add or remove element's attributes use approriate [`Element`]'s methods. The value of
+/// the attribute can be modified in place with [`Element::retain_attributes`].
 ///
 /// [`Element`]: struct.Element.html
+/// [`Element::retain_attributes`]: struct.Element.html#method.retain_attributes
 pub struct Attribute<'i> {
     name: Bytes<'i>,
+    decoded_name: LazyCell<String>,
     value: Bytes<'i>,
+    decoded_value: LazyCell<String>,
+    value_byte_range: Option<Range<usize>>,
     raw: Option<Bytes<'i>>,
     encoding: &'static Encoding,
 }
 
 impl<'i> Attribute<'i> {
-    fn new(name: Bytes<'i>, value: Bytes<'i>, raw: Bytes<'i>, encoding: &'static Encoding) -> Self {
+    fn new(
+        name: Bytes<'i>,
+        value: Bytes<'i>,
+        value_byte_range: Range<usize>,
+        raw: Bytes<'i>,
+        encoding: &'static Encoding,
+    ) -> Self {
         Attribute {
             name,
+            decoded_name: LazyCell::new(),
             value,
+            decoded_value: LazyCell::new(),
+            value_byte_range: Some(value_byte_range),
             raw: Some(raw),
             encoding,
         }
@@ -82,34 +98,75 @@ impl<'i> Attribute<'i> {
     ) -> Result<Self, AttributeNameError> {
         Ok(Attribute {
             name: Attribute::name_from_str(name, encoding)?,
+            decoded_name: LazyCell::new(),
             value: Bytes::from_str(value, encoding).into_owned(),
+            decoded_value: LazyCell::new(),
+            value_byte_range: None,
             raw: None,
             encoding,
         })
     }
 
     /// Returns the name of the attribute.
+    ///
+    /// The name is decoded on the first access and cached afterwards.
     #[inline]
-    pub fn name(&self) -> String {
-        self.name.as_lowercase_string(self.encoding)
+    pub fn name(&self) -> &str {
+        self.decoded_name
+            .borrow_with(|| self.name.as_lowercase_string(self.encoding))
     }
 
     /// Returns the value of the attribute.
+    ///
+    /// The value is decoded on the first access and cached afterwards.
+    #[inline]
+    pub fn value(&self) -> &str {
+        self.decoded_value
+            .borrow_with(|| self.value.as_string(self.encoding))
+    }
+
+    /// Returns the range of the input bytes occupied by the value of the attribute, excluding
+    /// the quotes.
+    ///
+    /// Returns `None` if the attribute was added or its value was modified by a content handler.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use lol_html::{rewrite_str, element, RewriteStrSettings};
+    ///
+    /// rewrite_str(
+    ///     r#"<div><a href="/foo">Foo</a></div>"#,
+    ///     RewriteStrSettings {
+    ///         element_content_handlers: vec![
+    ///             element!("a", |el| {
+    ///                 assert_eq!(el.attributes()[0].value_byte_range(), Some(14..18));
+    ///
+    ///                 Ok(())
+    ///             })
+    ///         ],
+    ///         ..RewriteStrSettings::default()
+    ///     }
+    /// ).unwrap();
+    /// ```
     #[inline]
-    pub fn value(&self) -> String {
-        self.value.as_string(self.encoding)
+    pub fn value_byte_range(&self) -> Option<Range<usize>> {
+        self.value_byte_range.clone()
     }
 
+    /// Sets the value of the attribute.
     #[inline]
-    fn set_value(&mut self, value: &str) {
-        self.value = Bytes::from_str(value, self.encoding).into_owned();
+    pub fn set_value(&mut self, value: &str) {
+        self.value.assign(&Bytes::from_str(value, self.encoding));
+        self.decoded_value = LazyCell::new();
+        self.value_byte_range = None;
         self.raw = None;
     }
 }
 
 impl Serialize for Attribute<'_> {
     #[inline]
-    fn to_bytes(&self, output_handler: &mut dyn FnMut(&[u8])) {
+    fn to_bytes(&self, output_handler: &mut impl FnMut(&[u8])) {
         match self.raw.as_ref() {
             Some(raw) => output_handler(raw),
             None => {
@@ -131,10 +188,15 @@ impl Debug for Attribute<'_> {
     }
 }
 
+// NOTE: the vast majority of tags have just a few attributes, so we store them inline to avoid
+// heap allocation for each tag that is exposed to the content handlers.
+type AttributeList<'i> = SmallVec<[Attribute<'i>; profile::INLINE_ATTRIBUTE_CAPACITY]>;
+
 pub struct Attributes<'i> {
     input: &'i Bytes<'i>,
+    input_offset: usize,
     attribute_buffer: SharedAttributeBuffer,
-    items: LazyCell<Vec<Attribute<'i>>>,
+    items: LazyCell<AttributeList<'i>>,
     encoding: &'static Encoding,
 }
 
@@ -146,12 +208,20 @@ impl<'i> Attributes<'i> {
     ) -> Self {
         Attributes {
             input,
+            input_offset: 0,
             attribute_buffer,
             items: LazyCell::default(),
             encoding,
         }
     }
 
+    /// Sets the offset of the input chunk in the whole input, so byte ranges of the attribute
+    /// values can be reported relative to the beginning of the input.
+    #[inline]
+    pub(super) fn set_input_offset(&mut self, input_offset: usize) {
+        self.input_offset = input_offset;
+    }
+
     pub fn set_attribute(
         &mut self,
         name: &str,
@@ -159,7 +229,7 @@ impl<'i> Attributes<'i> {
         encoding: &'static Encoding,
     ) -> Result<(), AttributeNameError> {
         let name = name.to_ascii_lowercase();
-        let items = self.as_mut_vec();
+        let items = self.as_mut_list();
 
         match items.iter_mut().find(|attr| attr.name() == name.as_str()) {
             Some(attr) => attr.set_value(value),
@@ -173,7 +243,7 @@ impl<'i> Attributes<'i> {
 
     pub fn remove_attribute(&mut self, name: &str) -> bool {
         let name = name.to_ascii_lowercase();
-        let items = self.as_mut_vec();
+        let items = self.as_mut_list();
         let mut i = 0;
 
         while i < items.len() {
@@ -188,14 +258,36 @@ impl<'i> Attributes<'i> {
         false
     }
 
-    fn init_items(&self) -> Vec<Attribute<'i>> {
+    /// Retains only the attributes for which `f` returns `true`. Returns `true` if any of
+    /// the attributes have been removed or modified.
+    pub fn retain(&mut self, mut f: impl FnMut(&mut Attribute<'i>) -> bool) -> bool {
+        let items = self.as_mut_list();
+        let len = items.len();
+        let mut modified = false;
+
+        items.retain(|attr| {
+            let had_raw = attr.raw.is_some();
+            let retain = f(attr);
+
+            // NOTE: attributes lose their raw representation once their value is modified.
+            modified |= had_raw && attr.raw.is_none();
+
+            retain
+        });
+
+        modified || items.len() != len
+    }
+
+    fn init_items(&self) -> AttributeList<'i> {
         self.attribute_buffer
-            .borrow()
+            .borrow_mut()
+            .outlines(self.input)
             .iter()
             .map(|a| {
                 Attribute::new(
                     self.input.slice(a.name),
                     self.input.slice(a.value),
+                    self.input_offset + a.value.start..self.input_offset + a.value.end,
                     self.input.slice(a.raw_range),
                     self.encoding,
                 )
@@ -204,7 +296,7 @@ impl<'i> Attributes<'i> {
     }
 
     #[inline]
-    fn as_mut_vec(&mut self) -> &mut Vec<Attribute<'i>> {
+    fn as_mut_list(&mut self) -> &mut AttributeList<'i> {
         // NOTE: we can't use borrow_mut_with here as we'll need
         // because `self` is a mutable reference and we'll have
         // two mutable references by passing it to the initializer
@@ -237,7 +329,7 @@ impl<'i> Deref for Attributes<'i> {
 
 impl Serialize for Attributes<'_> {
     #[inline]
-    fn to_bytes(&self, output_handler: &mut dyn FnMut(&[u8])) {
+    fn to_bytes(&self, output_handler: &mut impl FnMut(&[u8])) {
         if !self.is_empty() {
             let last = self.len() - 1;
 
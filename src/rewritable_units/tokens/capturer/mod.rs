@@ -3,6 +3,7 @@ mod to_token;
 
 use self::text_decoder::TextDecoder;
 use super::*;
+use crate::memory::SharedMemoryLimiter;
 use crate::parser::Lexeme;
 use crate::rewriter::RewritingError;
 use bitflags::bitflags;
@@ -35,10 +36,15 @@ pub struct TokenCapturer {
 }
 
 impl TokenCapturer {
-    pub fn new(capture_flags: TokenCaptureFlags, encoding: &'static Encoding) -> Self {
+    pub fn new(
+        capture_flags: TokenCaptureFlags,
+        encoding: &'static Encoding,
+        memory_limiter: SharedMemoryLimiter,
+        buffer_script_and_style_text: bool,
+    ) -> Self {
         TokenCapturer {
             encoding,
-            text_decoder: TextDecoder::new(encoding),
+            text_decoder: TextDecoder::new(encoding, memory_limiter, buffer_script_and_style_text),
             capture_flags,
         }
     }
@@ -48,6 +54,13 @@ impl TokenCapturer {
         !self.capture_flags.is_empty()
     }
 
+    /// Returns `true` if lexemes can't produce any tokens, i.e. there are no captures and
+    /// no text is pending to be flushed. Such lexemes can be passed through as raw input.
+    #[inline]
+    pub fn is_idle(&self) -> bool {
+        self.capture_flags.is_empty() && !self.text_decoder.has_pending()
+    }
+
     #[inline]
     pub fn set_capture_flags(&mut self, flags: TokenCaptureFlags) {
         self.capture_flags = flags;
@@ -64,13 +77,19 @@ impl TokenCapturer {
     pub fn feed<'i, T>(
         &mut self,
         lexeme: &Lexeme<'i, T>,
+        input_offset: usize,
         mut event_handler: impl FnMut(TokenCapturerEvent) -> Result<(), RewritingError>,
     ) -> Result<(), RewritingError>
     where
         Lexeme<'i, T>: ToToken,
     {
         match lexeme.to_token(&mut self.capture_flags, self.encoding) {
-            ToTokenResult::Token(token) => {
+            ToTokenResult::Token(mut token) => {
+                if let Token::StartTag(ref mut start_tag) = *token {
+                    start_tag
+                        .set_input_offset(input_offset, input_offset + lexeme.raw_range().start);
+                }
+
                 self.flush_pending_text(&mut event_handler)?;
                 event_handler(TokenCapturerEvent::LexemeConsumed)?;
                 event_handler(TokenCapturerEvent::TokenProduced(token))
@@ -79,8 +98,12 @@ impl TokenCapturer {
                 if self.capture_flags.contains(TokenCaptureFlags::TEXT) {
                     event_handler(TokenCapturerEvent::LexemeConsumed)?;
 
-                    self.text_decoder
-                        .feed_text(&lexeme.raw(), text_type, &mut event_handler)?;
+                    self.text_decoder.feed_text(
+                        &lexeme.raw(),
+                        input_offset + lexeme.raw_range().start,
+                        text_type,
+                        &mut event_handler,
+                    )?;
                 }
 
                 Ok(())
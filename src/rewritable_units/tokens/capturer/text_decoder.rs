@@ -1,55 +1,167 @@
 use super::*;
 use crate::html::TextType;
+use crate::memory::{profile, SharedMemoryLimiter};
 use crate::rewriter::RewritingError;
-use encoding_rs::{CoderResult, Decoder, Encoding};
+use encoding_rs::{CoderResult, Decoder, Encoding, UTF_8};
 
 // NOTE: this can't be refactored into method, because we hold a mutable reference for `self`
 // during the decoding loop in `feed_text`.
 macro_rules! emit {
     ($self:tt, $text:expr, $last:ident, $event_handler:ident) => {{
-        let token = TextChunk::new_token($text, $self.last_text_type, $last, $self.encoding);
+        let raw_end = $self.consumed_raw_end - $self.pending_text.len();
+
+        let token = TextChunk::new_token(
+            $text,
+            $self.last_text_type,
+            $last,
+            $self.emitted_raw_end..raw_end,
+            $self.encoding,
+        );
+
+        $self.emitted_raw_end = raw_end;
 
         $event_handler(TokenCapturerEvent::TokenProduced(Box::new(token)))
     }};
 }
 
+// NOTE: the longest named character reference (`&CounterClockwiseContourIntegral;`)
+// is 33 characters long, including the leading `&` and the trailing `;`.
+const MAX_CHAR_REF_LEN: usize = 33;
+
+// NOTE: returns the start of a trailing `&...` sequence that can be a beginning
+// of a character reference which is split across input chunks.
+#[inline]
+fn incomplete_char_ref_start(text: &str) -> Option<usize> {
+    let bytes = text.as_bytes();
+    let tail_start = bytes.len().saturating_sub(MAX_CHAR_REF_LEN);
+    let pos = tail_start + bytes[tail_start..].iter().rposition(|&b| b == b'&')?;
+
+    if bytes[pos + 1..]
+        .iter()
+        .all(|&b| b.is_ascii_alphanumeric() || b == b'#')
+    {
+        Some(pos)
+    } else {
+        None
+    }
+}
+
+// NOTE: returns the length of the text that can be emitted, the rest of the text is
+// moved to the `pending_text`.
+#[inline]
+fn hold_back_incomplete_char_ref(
+    text: &str,
+    text_type: TextType,
+    last: bool,
+    pending_text: &mut String,
+) -> usize {
+    // NOTE: we never split a text chunk in the middle of a character reference,
+    // so the tail of the text that might be a beginning of a character reference
+    // is kept until we get more input or the text node ends.
+    if !last && text_type.allows_html_entities() {
+        if let Some(pos) = incomplete_char_ref_start(text) {
+            pending_text.push_str(&text[pos..]);
+
+            return pos;
+        }
+    }
+
+    text.len()
+}
+
+#[inline]
+fn buffer_whole_text(
+    text: &str,
+    pending_text: &mut String,
+    memory_limiter: &SharedMemoryLimiter,
+) -> Result<(), RewritingError> {
+    memory_limiter
+        .borrow_mut()
+        .increase_usage(text.len())
+        .map_err(RewritingError::MemoryLimitExceeded)?;
+
+    pending_text.push_str(text);
+
+    Ok(())
+}
+
 pub struct TextDecoder {
     encoding: &'static Encoding,
+    memory_limiter: SharedMemoryLimiter,
+    buffer_script_and_style_text: bool,
     pending_text_streaming_decoder: Option<Decoder>,
+    has_pending_borrowed_text: bool,
+    emitted_raw_end: usize,
+    consumed_raw_end: usize,
     text_buffer: String,
+    pending_text: String,
     last_text_type: TextType,
 }
 
 impl TextDecoder {
-    pub fn new(encoding: &'static Encoding) -> Self {
+    pub fn new(
+        encoding: &'static Encoding,
+        memory_limiter: SharedMemoryLimiter,
+        buffer_script_and_style_text: bool,
+    ) -> Self {
         TextDecoder {
             encoding,
+            memory_limiter,
+            buffer_script_and_style_text,
             pending_text_streaming_decoder: None,
-            // TODO make adjustable
-            text_buffer: String::from_utf8(vec![0u8; 1024]).unwrap(),
+            has_pending_borrowed_text: false,
+            emitted_raw_end: 0,
+            consumed_raw_end: 0,
+            text_buffer: String::from_utf8(vec![0u8; profile::TEXT_DECODER_BUFFER_SIZE]).unwrap(),
+            pending_text: String::new(),
             last_text_type: TextType::Data,
         }
     }
 
+    #[inline]
+    pub fn has_pending(&self) -> bool {
+        self.pending_text_streaming_decoder.is_some() || self.has_pending_borrowed_text
+    }
+
     #[inline]
     pub fn flush_pending(
         &mut self,
         event_handler: CapturerEventHandler,
     ) -> Result<(), RewritingError> {
-        if self.pending_text_streaming_decoder.is_some() {
-            self.decode_with_streaming_decoder(&[], true, event_handler)?;
+        if self.has_pending() {
+            if self.should_buffer_whole_text() {
+                self.memory_limiter
+                    .borrow_mut()
+                    .decrease_usage(self.pending_text.len());
+            }
+
+            self.decode_with_streaming_decoder(&[], self.consumed_raw_end, true, event_handler)?;
             self.pending_text_streaming_decoder = None;
+            self.has_pending_borrowed_text = false;
         }
         Ok(())
     }
 
+    // NOTE: `<script>` and `<style>` content can't be transformed at arbitrary
+    // split points, so on request we buffer it and emit it as a single chunk.
+    #[inline]
+    fn should_buffer_whole_text(&self) -> bool {
+        self.buffer_script_and_style_text
+            && matches!(
+                self.last_text_type,
+                TextType::ScriptData | TextType::RawText
+            )
+    }
+
     fn decode_with_streaming_decoder(
         &mut self,
         raw: &[u8],
+        raw_start: usize,
         last: bool,
         event_handler: CapturerEventHandler,
     ) -> Result<(), RewritingError> {
         let encoding = self.encoding;
+        let should_buffer_whole_text = self.should_buffer_whole_text();
         let buffer = self.text_buffer.as_mut_str();
 
         let decoder = self
@@ -61,8 +173,35 @@ impl TextDecoder {
         loop {
             let (status, read, written, ..) = decoder.decode_to_str(&raw[consumed..], buffer, last);
 
-            if written > 0 || last {
-                emit!(self, &buffer[..written], last, event_handler)?;
+            self.consumed_raw_end = raw_start + consumed + read;
+
+            if should_buffer_whole_text && !last {
+                buffer_whole_text(
+                    &buffer[..written],
+                    &mut self.pending_text,
+                    &self.memory_limiter,
+                )?;
+            } else if written > 0 || last {
+                let mut text = &buffer[..written];
+                let joined;
+
+                if !self.pending_text.is_empty() {
+                    self.pending_text.push_str(text);
+                    joined = std::mem::take(&mut self.pending_text);
+                    text = &joined;
+                }
+
+                // NOTE: multi-byte sequences are taken care of by the streaming decoder.
+                let emitted_len = hold_back_incomplete_char_ref(
+                    text,
+                    self.last_text_type,
+                    last,
+                    &mut self.pending_text,
+                );
+
+                if emitted_len > 0 || last {
+                    emit!(self, &text[..emitted_len], last, event_handler)?;
+                }
             }
 
             if let CoderResult::InputEmpty = status {
@@ -79,10 +218,102 @@ impl TextDecoder {
     pub fn feed_text(
         &mut self,
         raw: &[u8],
+        raw_start: usize,
         text_type: TextType,
         event_handler: CapturerEventHandler,
     ) -> Result<(), RewritingError> {
         self.last_text_type = text_type;
-        self.decode_with_streaming_decoder(raw, false, event_handler)
+
+        if self.pending_text_streaming_decoder.is_none() && !self.has_pending_borrowed_text {
+            self.emitted_raw_end = raw_start;
+        }
+
+        // NOTE: fast path - if the text is valid UTF-8 and we haven't started decoding
+        // the text node with the streaming decoder, we can borrow the text from the input.
+        if self.encoding == UTF_8 && self.pending_text_streaming_decoder.is_none() {
+            if let Ok(text) = std::str::from_utf8(raw) {
+                self.consumed_raw_end = raw_start + raw.len();
+
+                return self.emit_borrowed_text(text, event_handler);
+            }
+        }
+
+        self.decode_with_streaming_decoder(raw, raw_start, false, event_handler)
+    }
+
+    fn emit_borrowed_text(
+        &mut self,
+        text: &str,
+        event_handler: CapturerEventHandler,
+    ) -> Result<(), RewritingError> {
+        self.has_pending_borrowed_text = true;
+
+        if self.should_buffer_whole_text() {
+            return buffer_whole_text(text, &mut self.pending_text, &self.memory_limiter);
+        }
+
+        let joined;
+
+        let text = if self.pending_text.is_empty() {
+            text
+        } else {
+            self.pending_text.push_str(text);
+            joined = std::mem::take(&mut self.pending_text);
+            &joined
+        };
+
+        let emitted_len =
+            hold_back_incomplete_char_ref(text, self.last_text_type, false, &mut self.pending_text);
+
+        if emitted_len > 0 {
+            emit!(self, &text[..emitted_len], false, event_handler)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::MemoryLimiter;
+
+    #[test]
+    fn incomplete_char_ref() {
+        assert_eq!(incomplete_char_ref_start("foo"), None);
+        assert_eq!(incomplete_char_ref_start("foo &"), Some(4));
+        assert_eq!(incomplete_char_ref_start("foo &am"), Some(4));
+        assert_eq!(incomplete_char_ref_start("foo &#x3"), Some(4));
+        assert_eq!(incomplete_char_ref_start("foo &amp;"), None);
+        assert_eq!(incomplete_char_ref_start("foo & bar"), None);
+        assert_eq!(
+            incomplete_char_ref_start(&format!("&{}", "a".repeat(40))),
+            None
+        );
+    }
+
+    #[test]
+    fn utf8_text_is_borrowed_from_input() {
+        let raw = "Hello, мир".as_bytes();
+        let mut decoder = TextDecoder::new(UTF_8, MemoryLimiter::new_shared(2048), false);
+        let mut text_ptrs = Vec::default();
+
+        let mut handler = |event: TokenCapturerEvent| -> Result<(), RewritingError> {
+            if let TokenCapturerEvent::TokenProduced(token) = event {
+                if let Token::TextChunk(chunk) = *token {
+                    text_ptrs.push(chunk.as_str().as_ptr());
+                }
+            }
+
+            Ok(())
+        };
+
+        decoder
+            .feed_text(raw, 0, TextType::Data, &mut handler)
+            .unwrap();
+        decoder.flush_pending(&mut handler).unwrap();
+
+        assert_eq!(text_ptrs.len(), 2);
+        assert_eq!(text_ptrs[0], raw.as_ptr());
     }
 }
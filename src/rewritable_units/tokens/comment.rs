@@ -3,6 +3,7 @@ use crate::base::Bytes;
 use encoding_rs::Encoding;
 use std::any::Any;
 use std::fmt::{self, Debug};
+use std::rc::Rc;
 use thiserror::Error;
 
 /// An error that occurs when invalid value is provided for the HTML comment text.
@@ -26,7 +27,7 @@ pub struct Comment<'i> {
     text: Bytes<'i>,
     raw: Option<Bytes<'i>>,
     encoding: &'static Encoding,
-    mutations: Mutations,
+    pub(crate) mutations: Mutations,
     user_data: Box<dyn Any>,
 }
 
@@ -181,13 +182,24 @@ impl<'i> Comment<'i> {
         self.mutations.removed()
     }
 
+    /// Sets the label of the content handler the subsequent insertions are attributed to.
+    #[inline]
+    pub(crate) fn set_provenance(&mut self, provenance: Option<Rc<str>>) {
+        self.mutations.set_provenance(provenance);
+    }
+
     #[inline]
     fn raw(&self) -> Option<&Bytes> {
         self.raw.as_ref()
     }
 
     #[inline]
-    fn serialize_from_parts(&self, output_handler: &mut dyn FnMut(&[u8])) {
+    fn serializes_to_source(&self) -> bool {
+        self.raw.is_some()
+    }
+
+    #[inline]
+    fn serialize_from_parts(&self, output_handler: &mut impl FnMut(&[u8])) {
         output_handler(b"<!--");
         output_handler(&self.text);
         output_handler(b"-->");
@@ -1,20 +1,54 @@
+use super::{Mutations, Token};
 use crate::base::Bytes;
-use crate::rewritable_units::{Serialize, Token};
 use encoding_rs::Encoding;
 use std::any::Any;
 use std::fmt::{self, Debug};
+use thiserror::Error;
+
+/// An error that occurs when invalid value is provided for the doctype name or identifiers.
+#[derive(Error, Debug, PartialEq, Copy, Clone)]
+pub enum DoctypeError {
+    /// The provided value is empty or contains whitespace or the `>` character.
+    #[error("Doctype name should be a non-empty string without whitespace and `>` characters.")]
+    InvalidName,
+
+    /// The provided value contains the `>` character or both `"` and `'` characters.
+    #[error("Doctype identifier shouldn't contain `>` or both `\"` and `'` characters.")]
+    InvalidIdentifier,
+
+    /// The provided value contains a character that can't be represented in the document's [`encoding`].
+    ///
+    /// [`encoding`]: ../struct.Settings.html#structfield.encoding
+    #[error("Doctype contains a character that can't be represented in the document's character encoding.")]
+    UnencodableCharacter,
+}
+
+#[inline]
+fn is_whitespace(ch: char) -> bool {
+    ch == ' ' || ch == '\n' || ch == '\r' || ch == '\t' || ch == '\x0C'
+}
+
+#[inline]
+fn serialize_identifier(id: &Bytes, output_handler: &mut impl FnMut(&[u8])) {
+    let quote: &[u8] = if id.contains(&b'"') { b"'" } else { b"\"" };
+
+    output_handler(b" ");
+    output_handler(quote);
+    output_handler(id);
+    output_handler(quote);
+}
 
 /// A [document type declaration] preamble.
 ///
-/// Note that unlike other HTML content, `Doctype` can't be modified and should be used only for
-/// the examination purposes.
+/// Exposes API for examination and modification of a parsed doctype. A modified doctype is
+/// serialized in the canonical form, e.g. `<!DOCTYPE html PUBLIC "public id" "system id">`.
 ///
 /// # Example
 /// ```
 /// use lol_html::{rewrite_str, doctype, RewriteStrSettings};
 ///
-/// rewrite_str(
-///     r#"<!DOCTYPE html PUBLIC "-//W3C//DTD XHTML 1.0 Transitional//EN" "DTD/xhtml1-transitional.dtd""#,
+/// let html = rewrite_str(
+///     r#"<!DOCTYPE html PUBLIC "-//W3C//DTD XHTML 1.0 Transitional//EN" "DTD/xhtml1-transitional.dtd">"#,
 ///     RewriteStrSettings {
 ///         document_content_handlers: vec![
 ///             doctype!(|d| {
@@ -22,12 +56,17 @@ use std::fmt::{self, Debug};
 ///                 assert_eq!(d.public_id(), Some("-//W3C//DTD XHTML 1.0 Transitional//EN".into()));
 ///                 assert_eq!(d.system_id(), Some("DTD/xhtml1-transitional.dtd".into()));
 ///
+///                 d.set_public_id(None)?;
+///                 d.set_system_id(None)?;
+///
 ///                 Ok(())
 ///             })
 ///         ],
 ///         ..RewriteStrSettings::default()
 ///     }
 /// ).unwrap();
+///
+/// assert_eq!(html, "<!DOCTYPE html>");
 /// ```
 ///
 /// [document type declaration]: https://developer.mozilla.org/en-US/docs/Glossary/Doctype
@@ -36,8 +75,9 @@ pub struct Doctype<'i> {
     public_id: Option<Bytes<'i>>,
     system_id: Option<Bytes<'i>>,
     force_quirks: bool,
-    raw: Bytes<'i>,
+    raw: Option<Bytes<'i>>,
     encoding: &'static Encoding,
+    pub(crate) mutations: Mutations,
     user_data: Box<dyn Any>,
 }
 
@@ -55,8 +95,9 @@ impl<'i> Doctype<'i> {
             public_id,
             system_id,
             force_quirks,
-            raw,
+            raw: Some(raw),
             encoding,
+            mutations: Mutations::new(encoding),
             user_data: Box::new(()),
         })
     }
@@ -69,34 +110,132 @@ impl<'i> Doctype<'i> {
             .map(|n| n.as_lowercase_string(self.encoding))
     }
 
+    /// Sets the name of the doctype.
+    #[inline]
+    pub fn set_name(&mut self, name: &str) -> Result<(), DoctypeError> {
+        if name.is_empty() || name.chars().any(|ch| is_whitespace(ch) || ch == '>') {
+            Err(DoctypeError::InvalidName)
+        } else {
+            self.name = Some(self.encode(name)?);
+            self.raw = None;
+
+            Ok(())
+        }
+    }
+
     /// The public identifier of the doctype.
     #[inline]
     pub fn public_id(&self) -> Option<String> {
         self.public_id.as_ref().map(|i| i.as_string(self.encoding))
     }
 
+    /// Sets the public identifier of the doctype. The identifier is removed if `None` is provided.
+    #[inline]
+    pub fn set_public_id(&mut self, public_id: Option<&str>) -> Result<(), DoctypeError> {
+        self.public_id = self.encode_identifier(public_id)?;
+        self.raw = None;
+
+        Ok(())
+    }
+
     /// The system identifier of the doctype.
     #[inline]
     pub fn system_id(&self) -> Option<String> {
         self.system_id.as_ref().map(|i| i.as_string(self.encoding))
     }
 
+    /// Sets the system identifier of the doctype. The identifier is removed if `None` is provided.
+    #[inline]
+    pub fn set_system_id(&mut self, system_id: Option<&str>) -> Result<(), DoctypeError> {
+        self.system_id = self.encode_identifier(system_id)?;
+        self.raw = None;
+
+        Ok(())
+    }
+
+    /// Removes the doctype.
+    #[inline]
+    pub fn remove(&mut self) {
+        self.mutations.remove();
+    }
+
+    /// Returns `true` if the doctype has been removed.
+    #[inline]
+    pub fn removed(&self) -> bool {
+        self.mutations.removed()
+    }
+
     #[inline]
     #[cfg(feature = "integration_test")]
     pub fn force_quirks(&self) -> bool {
         self.force_quirks
     }
-}
 
-impl_user_data!(Doctype<'_>);
+    #[inline]
+    fn encode(&self, value: &str) -> Result<Bytes<'static>, DoctypeError> {
+        // NOTE: character references are not supported in doctypes, so we need to bail
+        // if a character can't be represented in the document's encoding.
+        Bytes::from_str_without_replacements(value, self.encoding)
+            .map(Bytes::into_owned)
+            .map_err(|_| DoctypeError::UnencodableCharacter)
+    }
+
+    #[inline]
+    fn encode_identifier(
+        &self,
+        identifier: Option<&str>,
+    ) -> Result<Option<Bytes<'static>>, DoctypeError> {
+        match identifier {
+            Some(id) if id.contains('>') || (id.contains('"') && id.contains('\'')) => {
+                Err(DoctypeError::InvalidIdentifier)
+            }
+            Some(id) => self.encode(id).map(Some),
+            None => Ok(None),
+        }
+    }
 
-impl Serialize for Doctype<'_> {
     #[inline]
-    fn to_bytes(&self, output_handler: &mut dyn FnMut(&[u8])) {
-        output_handler(&self.raw);
+    fn raw(&self) -> Option<&Bytes> {
+        self.raw.as_ref()
+    }
+
+    #[inline]
+    fn serializes_to_source(&self) -> bool {
+        self.raw.is_some()
+    }
+
+    #[inline]
+    fn serialize_from_parts(&self, output_handler: &mut impl FnMut(&[u8])) {
+        output_handler(b"<!DOCTYPE");
+
+        if let Some(ref name) = self.name {
+            output_handler(b" ");
+            output_handler(name);
+        }
+
+        match (&self.public_id, &self.system_id) {
+            (Some(public_id), system_id) => {
+                output_handler(b" PUBLIC");
+                serialize_identifier(public_id, output_handler);
+
+                if let Some(system_id) = system_id {
+                    serialize_identifier(system_id, output_handler);
+                }
+            }
+            (None, Some(system_id)) => {
+                output_handler(b" SYSTEM");
+                serialize_identifier(system_id, output_handler);
+            }
+            (None, None) => (),
+        }
+
+        output_handler(b">");
     }
 }
 
+impl_serialize!(Doctype);
+impl_user_data!(Doctype<'_>);
+
 impl Debug for Doctype<'_> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         f.debug_struct("Doctype")
@@ -110,6 +249,7 @@ impl Debug for Doctype<'_> {
 
 #[cfg(test)]
 mod tests {
+    use crate::errors::*;
     use crate::html_content::*;
     use crate::rewritable_units::test_utils::*;
     use crate::*;
@@ -159,4 +299,64 @@ mod tests {
             assert_eq!(output, r#"<!DOCTYPE html SYSTEM "Ĥey">"#);
         }
     }
+
+    #[test]
+    fn modification() {
+        for (html, enc) in encoded(r#"<!doctype foo SYSTEM "Ĥey">"#) {
+            let output = rewrite_doctype(&html, enc, |d| {
+                d.set_name("html").unwrap();
+                d.set_public_id(Some(r#"-//"Ĥey"//EN"#)).unwrap();
+
+                assert_eq!(d.name(), Some("html".into()));
+                assert_eq!(d.public_id(), Some(r#"-//"Ĥey"//EN"#.into()));
+                assert_eq!(d.system_id(), Some("Ĥey".into()));
+            });
+
+            assert_eq!(output, r#"<!DOCTYPE html PUBLIC '-//"Ĥey"//EN' "Ĥey">"#);
+
+            let output = rewrite_doctype(&html, enc, |d| {
+                d.set_system_id(None).unwrap();
+            });
+
+            assert_eq!(output, "<!DOCTYPE foo>");
+        }
+    }
+
+    #[test]
+    fn invalid_values() {
+        rewrite_doctype(b"<!DOCTYPE html>", UTF_8, |d| {
+            assert_eq!(d.set_name(""), Err(DoctypeError::InvalidName));
+            assert_eq!(d.set_name("foo bar"), Err(DoctypeError::InvalidName));
+            assert_eq!(d.set_name("foo>"), Err(DoctypeError::InvalidName));
+
+            assert_eq!(
+                d.set_public_id(Some("foo>")),
+                Err(DoctypeError::InvalidIdentifier)
+            );
+
+            assert_eq!(
+                d.set_system_id(Some(r#"'foo""#)),
+                Err(DoctypeError::InvalidIdentifier)
+            );
+        });
+
+        let enc = Encoding::for_label(b"windows-1251").unwrap();
+
+        rewrite_doctype(b"<!DOCTYPE html>", enc, |d| {
+            assert_eq!(d.set_name("Ĥey"), Err(DoctypeError::UnencodableCharacter));
+        });
+    }
+
+    #[test]
+    fn removal() {
+        let output = rewrite_doctype(b"<!DOCTYPE html><p>Hi</p>", UTF_8, |d| {
+            assert!(!d.removed());
+
+            d.remove();
+
+            assert!(d.removed());
+        });
+
+        assert_eq!(output, "<p>Hi</p>");
+    }
 }
@@ -1,13 +1,23 @@
 use super::{Mutations, Token};
 use crate::base::Bytes;
+use crate::rewritable_units::{tag_name_bytes_from_str, TagNameError};
 use encoding_rs::Encoding;
+use lazycell::LazyCell;
 use std::fmt::{self, Debug};
+use std::rc::Rc;
 
+/// An HTML end tag rewritable unit.
+///
+/// Exposes API for examination and modification of the end tag of an element. Passed to
+/// the handlers registered with [`Element::on_end_tag`].
+///
+/// [`Element::on_end_tag`]: struct.Element.html#method.on_end_tag
 pub struct EndTag<'i> {
     name: Bytes<'i>,
+    decoded_name: LazyCell<String>,
     raw: Option<Bytes<'i>>,
     encoding: &'static Encoding,
-    pub mutations: Mutations,
+    pub(crate) mutations: Mutations,
 }
 
 impl<'i> EndTag<'i> {
@@ -18,30 +28,139 @@ impl<'i> EndTag<'i> {
     ) -> Token<'i> {
         Token::EndTag(EndTag {
             name,
+            decoded_name: LazyCell::new(),
             raw: Some(raw),
             encoding,
             mutations: Mutations::new(encoding),
         })
     }
 
+    /// Returns the name of the tag, converted to lowercase.
     #[inline]
-    pub fn name(&self) -> String {
-        self.name.as_lowercase_string(self.encoding)
+    pub fn name(&self) -> &str {
+        self.decoded_name
+            .borrow_with(|| self.name.as_lowercase_string(self.encoding))
     }
 
+    /// Sets the name of the tag.
     #[inline]
-    pub fn set_name(&mut self, name: Bytes<'static>) {
+    pub fn set_name(&mut self, name: &str) -> Result<(), TagNameError> {
+        let name = tag_name_bytes_from_str(name, self.encoding)?;
+
+        self.set_name_bytes(name);
+
+        Ok(())
+    }
+
+    #[inline]
+    pub(crate) fn set_name_bytes(&mut self, name: Bytes<'static>) {
         self.name = name;
+        self.decoded_name = LazyCell::new();
         self.raw = None;
     }
 
+    /// Inserts `content` before the end tag.
+    ///
+    /// Consequent calls to the method append `content` to the previously inserted content.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use lol_html::{rewrite_str, element, RewriteStrSettings};
+    /// use lol_html::html_content::ContentType;
+    ///
+    /// let html = rewrite_str(
+    ///     r#"<div>Hello</div>"#,
+    ///     RewriteStrSettings {
+    ///         element_content_handlers: vec![
+    ///             element!("div", |el| {
+    ///                 el.on_end_tag(|end| {
+    ///                     end.before("<b>", ContentType::Html);
+    ///                     end.before("world", ContentType::Text);
+    ///
+    ///                     Ok(())
+    ///                 });
+    ///
+    ///                 Ok(())
+    ///             })
+    ///         ],
+    ///         ..RewriteStrSettings::default()
+    ///     }
+    /// ).unwrap();
+    ///
+    /// assert_eq!(html, r#"<div>Hello<b>world</div>"#);
+    /// ```
+    #[inline]
+    pub fn before(&mut self, content: &str, content_type: crate::rewritable_units::ContentType) {
+        self.mutations.before(content, content_type);
+    }
+
+    /// Inserts `content` after the end tag.
+    ///
+    /// Consequent calls to the method prepend `content` to the previously inserted content.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use lol_html::{rewrite_str, element, RewriteStrSettings};
+    /// use lol_html::html_content::ContentType;
+    ///
+    /// let html = rewrite_str(
+    ///     r#"<div>Hello</div>"#,
+    ///     RewriteStrSettings {
+    ///         element_content_handlers: vec![
+    ///             element!("div", |el| {
+    ///                 el.on_end_tag(|end| {
+    ///                     end.after("Bar", ContentType::Text);
+    ///                     end.after("Qux", ContentType::Text);
+    ///
+    ///                     Ok(())
+    ///                 });
+    ///
+    ///                 Ok(())
+    ///             })
+    ///         ],
+    ///         ..RewriteStrSettings::default()
+    ///     }
+    /// ).unwrap();
+    ///
+    /// assert_eq!(html, r#"<div>Hello</div>QuxBar"#);
+    /// ```
+    #[inline]
+    pub fn after(&mut self, content: &str, content_type: crate::rewritable_units::ContentType) {
+        self.mutations.after(content, content_type);
+    }
+
+    /// Removes the end tag, keeping the content inserted before and after it.
+    #[inline]
+    pub fn remove(&mut self) {
+        self.mutations.remove();
+    }
+
+    /// Returns `true` if the end tag has been removed.
+    #[inline]
+    pub fn removed(&self) -> bool {
+        self.mutations.removed()
+    }
+
+    /// Sets the label of the content handler the subsequent insertions are attributed to.
+    #[inline]
+    pub(crate) fn set_provenance(&mut self, provenance: Option<Rc<str>>) {
+        self.mutations.set_provenance(provenance);
+    }
+
     #[inline]
     fn raw(&self) -> Option<&Bytes> {
         self.raw.as_ref()
     }
 
     #[inline]
-    fn serialize_from_parts(&self, output_handler: &mut dyn FnMut(&[u8])) {
+    fn serializes_to_source(&self) -> bool {
+        self.raw.is_some()
+    }
+
+    #[inline]
+    fn serialize_from_parts(&self, output_handler: &mut impl FnMut(&[u8])) {
         output_handler(b"</");
         output_handler(&self.name);
         output_handler(b">");
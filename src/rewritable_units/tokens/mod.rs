@@ -8,14 +8,20 @@ pub use self::attributes::{Attribute, AttributeNameError};
 pub use self::capturer::*;
 
 pub trait Serialize {
-    fn to_bytes(&self, output_handler: &mut dyn FnMut(&[u8]));
+    fn to_bytes(&self, output_handler: &mut impl FnMut(&[u8]));
+
+    /// Returns `true` if the serialized content is identical to its source in the input.
+    #[inline]
+    fn is_verbatim(&self) -> bool {
+        false
+    }
 }
 
 macro_rules! impl_serialize {
     ($Token:ident) => {
         impl crate::rewritable_units::Serialize for $Token<'_> {
             #[inline]
-            fn to_bytes(&self, output_handler: &mut dyn FnMut(&[u8])) {
+            fn to_bytes(&self, output_handler: &mut impl FnMut(&[u8])) {
                 let Mutations {
                     content_before,
                     replacement,
@@ -41,6 +47,11 @@ macro_rules! impl_serialize {
                     output_handler(content_after);
                 }
             }
+
+            #[inline]
+            fn is_verbatim(&self) -> bool {
+                self.mutations.is_empty() && self.serializes_to_source()
+            }
         }
     };
 }
@@ -52,11 +63,26 @@ mod start_tag;
 mod text_chunk;
 
 pub use self::comment::{Comment, CommentTextError};
-pub use self::doctype::Doctype;
+pub use self::doctype::{Doctype, DoctypeError};
 pub use self::end_tag::EndTag;
 pub use self::start_tag::StartTag;
 pub use self::text_chunk::TextChunk;
 
+/// The kind of a token produced by the rewriter.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum TokenKind {
+    /// A chunk of text.
+    Text,
+    /// An HTML comment.
+    Comment,
+    /// A start tag of an element.
+    StartTag,
+    /// An end tag of an element.
+    EndTag,
+    /// A document type declaration.
+    Doctype,
+}
+
 #[derive(Debug)]
 pub enum Token<'i> {
     TextChunk(TextChunk<'i>),
@@ -66,9 +92,44 @@ pub enum Token<'i> {
     Doctype(Doctype<'i>),
 }
 
+impl Token<'_> {
+    #[inline]
+    pub fn kind(&self) -> TokenKind {
+        match self {
+            Token::TextChunk(_) => TokenKind::Text,
+            Token::Comment(_) => TokenKind::Comment,
+            Token::StartTag(_) => TokenKind::StartTag,
+            Token::EndTag(_) => TokenKind::EndTag,
+            Token::Doctype(_) => TokenKind::Doctype,
+        }
+    }
+
+    #[inline]
+    pub(crate) fn mutations(&self) -> &Mutations {
+        match self {
+            Token::TextChunk(t) => &t.mutations,
+            Token::Comment(t) => &t.mutations,
+            Token::StartTag(t) => &t.mutations,
+            Token::EndTag(t) => &t.mutations,
+            Token::Doctype(t) => &t.mutations,
+        }
+    }
+
+    #[inline]
+    pub(crate) fn mutations_mut(&mut self) -> &mut Mutations {
+        match self {
+            Token::TextChunk(t) => &mut t.mutations,
+            Token::Comment(t) => &mut t.mutations,
+            Token::StartTag(t) => &mut t.mutations,
+            Token::EndTag(t) => &mut t.mutations,
+            Token::Doctype(t) => &mut t.mutations,
+        }
+    }
+}
+
 impl Serialize for Token<'_> {
     #[inline]
-    fn to_bytes(&self, output_handler: &mut dyn FnMut(&[u8])) {
+    fn to_bytes(&self, output_handler: &mut impl FnMut(&[u8])) {
         match self {
             Token::TextChunk(t) => t.to_bytes(output_handler),
             Token::Comment(t) => t.to_bytes(output_handler),
@@ -77,4 +138,15 @@ impl Serialize for Token<'_> {
             Token::Doctype(t) => t.to_bytes(output_handler),
         }
     }
+
+    #[inline]
+    fn is_verbatim(&self) -> bool {
+        match self {
+            Token::TextChunk(t) => t.is_verbatim(),
+            Token::Comment(t) => t.is_verbatim(),
+            Token::StartTag(t) => t.is_verbatim(),
+            Token::EndTag(t) => t.is_verbatim(),
+            Token::Doctype(t) => t.is_verbatim(),
+        }
+    }
 }
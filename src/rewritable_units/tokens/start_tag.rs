@@ -3,15 +3,18 @@ use super::{Mutations, Serialize, Token};
 use crate::base::Bytes;
 use crate::html::Namespace;
 use encoding_rs::Encoding;
+use lazycell::LazyCell;
 use std::fmt::{self, Debug};
 
 pub struct StartTag<'i> {
     name: Bytes<'i>,
+    decoded_name: LazyCell<String>,
     attributes: Attributes<'i>,
     ns: Namespace,
     self_closing: bool,
     raw: Option<Bytes<'i>>,
     encoding: &'static Encoding,
+    source_offset: usize,
     pub mutations: Mutations,
 }
 
@@ -26,28 +29,47 @@ impl<'i> StartTag<'i> {
     ) -> Token<'i> {
         Token::StartTag(StartTag {
             name,
+            decoded_name: LazyCell::new(),
             attributes,
             ns,
             self_closing,
             raw: Some(raw),
             encoding,
+            source_offset: 0,
             mutations: Mutations::new(encoding),
         })
     }
 
+    /// Sets the offset of the input chunk in the whole input and the offset of the tag
+    /// in the whole input.
+    #[inline]
+    pub(super) fn set_input_offset(&mut self, input_offset: usize, source_offset: usize) {
+        self.attributes.set_input_offset(input_offset);
+        self.source_offset = source_offset;
+    }
+
+    /// Returns the offset of the tag in the input.
+    #[cfg(feature = "element_handlers")]
+    #[inline]
+    pub fn source_offset(&self) -> usize {
+        self.source_offset
+    }
+
     #[inline]
     pub fn encoding(&self) -> &'static Encoding {
         self.encoding
     }
 
     #[inline]
-    pub fn name(&self) -> String {
-        self.name.as_lowercase_string(self.encoding)
+    pub fn name(&self) -> &str {
+        self.decoded_name
+            .borrow_with(|| self.name.as_lowercase_string(self.encoding))
     }
 
     #[inline]
     pub fn set_name(&mut self, name: Bytes<'static>) {
         self.name = name;
+        self.decoded_name = LazyCell::new();
         self.raw = None;
     }
 
@@ -77,18 +99,38 @@ impl<'i> StartTag<'i> {
     }
 
     #[inline]
-    #[cfg(any(test, feature = "integration_test"))]
+    pub fn retain_attributes(&mut self, f: impl FnMut(&mut Attribute<'i>) -> bool) {
+        if self.attributes.retain(f) {
+            self.raw = None;
+        }
+    }
+
+    #[inline]
     pub fn self_closing(&self) -> bool {
         self.self_closing
     }
 
+    #[cfg(feature = "element_handlers")]
+    #[inline]
+    pub fn set_self_closing(&mut self, self_closing: bool) {
+        if self.self_closing != self_closing {
+            self.self_closing = self_closing;
+            self.raw = None;
+        }
+    }
+
     #[inline]
     fn raw(&self) -> Option<&Bytes> {
         self.raw.as_ref()
     }
 
     #[inline]
-    fn serialize_from_parts(&self, output_handler: &mut dyn FnMut(&[u8])) {
+    fn serializes_to_source(&self) -> bool {
+        self.raw.is_some()
+    }
+
+    #[inline]
+    fn serialize_from_parts(&self, output_handler: &mut impl FnMut(&[u8])) {
         output_handler(b"<");
         output_handler(&self.name);
 
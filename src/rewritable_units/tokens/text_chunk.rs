@@ -1,10 +1,12 @@
 use super::{Mutations, Token};
 use crate::base::Bytes;
 use crate::html::TextType;
-use encoding_rs::Encoding;
+use encoding_rs::{Encoding, UTF_8};
 use std::any::Any;
 use std::borrow::Cow;
 use std::fmt::{self, Debug};
+use std::ops::Range;
+use std::rc::Rc;
 
 /// An HTML text node chunk.
 ///
@@ -18,6 +20,11 @@ use std::fmt::{self, Debug};
 ///
 /// Note that the last chunk in a text node can have empty textual content.
 ///
+/// The rewriter guarantees that a text chunk never ends in the middle of a multi-byte character
+/// sequence or, for the text types that allow them, in the middle of a [character reference]. The
+/// few trailing bytes of such an incomplete sequence are buffered and delivered in the beginning of
+/// the next chunk of the same text node (e.g. `&am` and `p;` are never split between two chunks).
+///
 /// # Example
 /// ```
 /// use lol_html::{HtmlRewriter, Settings, text};
@@ -53,12 +60,14 @@ use std::fmt::{self, Debug};
 /// ```
 ///
 /// [`last_in_text_node`]: #method.last_in_text_node
+/// [character reference]: https://developer.mozilla.org/en-US/docs/Glossary/Entity
 pub struct TextChunk<'i> {
     text: Cow<'i, str>,
     text_type: TextType,
     last_in_text_node: bool,
+    byte_range: Range<usize>,
     encoding: &'static Encoding,
-    mutations: Mutations,
+    pub(crate) mutations: Mutations,
     user_data: Box<dyn Any>,
 }
 
@@ -67,12 +76,14 @@ impl<'i> TextChunk<'i> {
         text: &'i str,
         text_type: TextType,
         last_in_text_node: bool,
+        byte_range: Range<usize>,
         encoding: &'static Encoding,
     ) -> Token<'i> {
         Token::TextChunk(TextChunk {
             text: text.into(),
             text_type,
             last_in_text_node,
+            byte_range,
             encoding,
             mutations: Mutations::new(encoding),
             user_data: Box::new(()),
@@ -80,6 +91,9 @@ impl<'i> TextChunk<'i> {
     }
 
     /// Returns the textual content of the chunk.
+    ///
+    /// For UTF-8 documents the text is borrowed directly from the input chunk whenever it's
+    /// possible, i.e. no decoding or copying takes place.
     #[inline]
     pub fn as_str(&self) -> &str {
         &*self.text
@@ -131,6 +145,41 @@ impl<'i> TextChunk<'i> {
         self.last_in_text_node
     }
 
+    /// Returns the range of byte offsets in the input document that the chunk has been produced
+    /// from.
+    ///
+    /// Offsets are counted from the beginning of the input, across all the chunks written to the
+    /// rewriter. Note that the range can differ in length from the textual content of the chunk,
+    /// e.g. for documents in encodings other than UTF-8. If a multi-byte character is split
+    /// between input chunks, its bytes are included in the range of the chunk that precedes the
+    /// chunk containing the character.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use lol_html::{rewrite_str, text, RewriteStrSettings};
+    ///
+    /// rewrite_str(
+    ///     r#"<div>Hello</div>"#,
+    ///     RewriteStrSettings {
+    ///         element_content_handlers: vec![
+    ///             text!("div", |t| {
+    ///                 if !t.last_in_text_node() {
+    ///                     assert_eq!(t.byte_range(), 5..10);
+    ///                 }
+    ///
+    ///                 Ok(())
+    ///             })
+    ///         ],
+    ///         ..RewriteStrSettings::default()
+    ///     }
+    /// ).unwrap();
+    /// ```
+    #[inline]
+    pub fn byte_range(&self) -> Range<usize> {
+        self.byte_range.clone()
+    }
+
     /// Inserts `content` before the text chunk.
     ///
     /// Consequent calls to the method append `content` to the previously inserted content.
@@ -245,13 +294,26 @@ impl<'i> TextChunk<'i> {
         self.mutations.removed()
     }
 
+    /// Sets the label of the content handler the subsequent insertions are attributed to.
+    #[inline]
+    pub(crate) fn set_provenance(&mut self, provenance: Option<Rc<str>>) {
+        self.mutations.set_provenance(provenance);
+    }
+
     #[inline]
     fn raw(&self) -> Option<&Bytes> {
         None
     }
 
+    // NOTE: text is re-encoded on serialization, we can be sure that the result is identical
+    // to the source only for UTF-8 text that wasn't altered by the decoder.
     #[inline]
-    fn serialize_from_parts(&self, output_handler: &mut dyn FnMut(&[u8])) {
+    fn serializes_to_source(&self) -> bool {
+        self.encoding == UTF_8 && self.text.len() == self.byte_range.len()
+    }
+
+    #[inline]
+    fn serialize_from_parts(&self, output_handler: &mut impl FnMut(&[u8])) {
         if !self.text.is_empty() {
             output_handler(&Bytes::from_str(&self.text, self.encoding));
         }
@@ -313,6 +375,108 @@ mod tests {
         });
     }
 
+    #[test]
+    fn char_refs_are_not_split_between_chunks() {
+        let mut chunks = Vec::default();
+
+        {
+            let mut rewriter = HtmlRewriter::try_new(
+                Settings {
+                    document_content_handlers: vec![doc_text!(|c| {
+                        chunks.push(c.as_str().to_string());
+                        Ok(())
+                    })],
+                    ..Settings::default()
+                },
+                |_: &[u8]| {},
+            )
+            .unwrap();
+
+            rewriter.write(b"<div>Foo &am").unwrap();
+            rewriter.write(b"p; bar &#x3").unwrap();
+            rewriter.write(b"C; baz &").unwrap();
+            rewriter.write(b"</div>").unwrap();
+            rewriter.end().unwrap();
+        }
+
+        assert_eq!(chunks, ["Foo ", "&amp; bar ", "&#x3C; baz ", "&"]);
+    }
+
+    #[test]
+    fn whole_script_and_style_text() {
+        let mut chunks = Vec::default();
+
+        {
+            let mut rewriter = HtmlRewriter::try_new(
+                Settings {
+                    document_content_handlers: vec![doc_text!(|c| {
+                        chunks.push((c.as_str().to_string(), c.last_in_text_node()));
+                        Ok(())
+                    })],
+                    buffer_script_and_style_text: true,
+                    ..Settings::default()
+                },
+                |_: &[u8]| {},
+            )
+            .unwrap();
+
+            rewriter.write(b"<script>var a").unwrap();
+            rewriter.write(b" = 1;</script><style>p").unwrap();
+            rewriter.write(b" { color: red }</style><p>Hi").unwrap();
+            rewriter.write(b" there</p>").unwrap();
+            rewriter.end().unwrap();
+        }
+
+        assert_eq!(
+            chunks,
+            [
+                ("var a = 1;".to_string(), true),
+                ("p { color: red }".to_string(), true),
+                ("Hi".to_string(), false),
+                (" there".to_string(), false),
+                ("".to_string(), true),
+            ]
+        );
+    }
+
+    #[test]
+    fn byte_ranges() {
+        let mut chunks = Vec::default();
+
+        {
+            let mut rewriter = HtmlRewriter::try_new(
+                Settings {
+                    document_content_handlers: vec![doc_text!(|c| {
+                        chunks.push((c.as_str().to_string(), c.byte_range()));
+                        Ok(())
+                    })],
+                    ..Settings::default()
+                },
+                |_: &[u8]| {},
+            )
+            .unwrap();
+
+            rewriter.write(b"<div>Hello ").unwrap();
+            rewriter.write(b"wor").unwrap();
+            rewriter.write(b"ld &am").unwrap();
+            rewriter.write(b"p;</div><p>!</p>").unwrap();
+            rewriter.end().unwrap();
+        }
+
+        assert_eq!(
+            chunks,
+            [
+                ("Hello ".to_string(), 5..11),
+                ("wor".to_string(), 11..14),
+                ("ld ".to_string(), 14..17),
+                ("&amp;".to_string(), 17..22),
+                ("".to_string(), 22..22),
+                ("!".to_string(), 31..32),
+                ("".to_string(), 32..32),
+            ]
+        );
+    }
+
     mod serialization {
         use super::*;
 
@@ -0,0 +1,190 @@
+use super::settings::ElementContentHandlers;
+use crate::html::{is_valid_custom_element_name, Namespace};
+use crate::rewritable_units::Element;
+use crate::selectors_vm::Selector;
+use hashbrown::HashSet;
+use std::borrow::Cow;
+use std::error::Error;
+
+/// A definition of a [custom element] used in the document.
+///
+/// [custom element]: https://html.spec.whatwg.org/multipage/custom-elements.html
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum CustomElementDefinition {
+    /// An autonomous custom element, e.g. `<fancy-button>`.
+    Autonomous {
+        /// The name of the custom element.
+        name: String,
+    },
+    /// A customized built-in element, e.g. `<button is="fancy-button">`.
+    CustomizedBuiltIn {
+        /// The name of the custom element.
+        name: String,
+        /// The tag name of the built-in element the custom element extends.
+        extends: String,
+    },
+}
+
+impl CustomElementDefinition {
+    /// Returns the name of the custom element.
+    #[inline]
+    pub fn name(&self) -> &str {
+        match self {
+            CustomElementDefinition::Autonomous { name } => name,
+            CustomElementDefinition::CustomizedBuiltIn { name, .. } => name,
+        }
+    }
+
+    fn from_element(el: &Element) -> Option<Self> {
+        if el.namespace_uri() != Namespace::Html.uri() {
+            return None;
+        }
+
+        let tag_name = el.tag_name();
+
+        if is_valid_custom_element_name(tag_name) {
+            Some(CustomElementDefinition::Autonomous {
+                name: tag_name.to_string(),
+            })
+        } else {
+            el.is_attribute()
+                .map(|name| CustomElementDefinition::CustomizedBuiltIn {
+                    name,
+                    extends: tag_name.to_string(),
+                })
+        }
+    }
+}
+
+/// Creates content handlers that invoke the `handler` for the first element of each distinct
+/// [`CustomElementDefinition`] encountered in the document, e.g. to inject the scripts that
+/// define only the custom elements actually used by the page.
+///
+/// Both autonomous custom elements (`<fancy-button>`) and customized built-in elements
+/// (`<button is="fancy-button">`) are reported. The same name used with different built-in
+/// elements is reported once for each of them.
+///
+/// # Example
+///
+/// ```
+/// use lol_html::{custom_element_definitions, rewrite_str, RewriteStrSettings};
+/// use lol_html::html_content::ContentType;
+///
+/// let html = rewrite_str(
+///     r#"<fancy-card></fancy-card><button is="fancy-button"></button><fancy-card></fancy-card>"#,
+///     RewriteStrSettings {
+///         element_content_handlers: vec![custom_element_definitions(|el, definition| {
+///             let script = format!(r#"<script src="/c/{}.js"></script>"#, definition.name());
+///
+///             el.before(&script, ContentType::Html);
+///
+///             Ok(())
+///         })],
+///         ..RewriteStrSettings::default()
+///     },
+/// )
+/// .unwrap();
+///
+/// assert_eq!(
+///     html,
+///     concat!(
+///         r#"<script src="/c/fancy-card.js"></script><fancy-card></fancy-card>"#,
+///         r#"<script src="/c/fancy-button.js"></script><button is="fancy-button"></button>"#,
+///         r#"<fancy-card></fancy-card>"#
+///     )
+/// );
+/// ```
+///
+/// [`CustomElementDefinition`]: enum.CustomElementDefinition.html
+pub fn custom_element_definitions<'h, F>(
+    mut handler: F,
+) -> (Cow<'static, Selector>, ElementContentHandlers<'h>)
+where
+    F: FnMut(&mut Element, &CustomElementDefinition) -> Result<(), Box<dyn Error + Send + Sync>>
+        + 'h,
+{
+    let mut reported = HashSet::new();
+
+    let handlers = ElementContentHandlers::default().element(move |el| {
+        match CustomElementDefinition::from_element(el) {
+            Some(definition) if !reported.contains(&definition) => {
+                handler(el, &definition)?;
+                reported.insert(definition);
+
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    });
+
+    // NOTE: never panics as the universal selector is always valid.
+    (Cow::Owned("*".parse().unwrap()), handlers)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{rewrite_str, RewriteStrSettings};
+
+    #[test]
+    fn reports_distinct_definitions() {
+        let mut definitions = Vec::new();
+
+        rewrite_str(
+            concat!(
+                "<FANCY-CARD><button is=fancy-button></button></fancy-card>",
+                "<a is=fancy-button><button is=fancy-button></button><p is=Fancy>",
+                "<fancy-card is=x-y></fancy-card><svg><x-shape></x-shape></svg>",
+                "<font-face></font-face>"
+            ),
+            RewriteStrSettings {
+                element_content_handlers: vec![custom_element_definitions(|_, definition| {
+                    definitions.push(definition.clone());
+                    Ok(())
+                })],
+                ..RewriteStrSettings::default()
+            },
+        )
+        .unwrap();
+
+        assert_eq!(
+            definitions,
+            [
+                CustomElementDefinition::Autonomous {
+                    name: "fancy-card".into()
+                },
+                CustomElementDefinition::CustomizedBuiltIn {
+                    name: "fancy-button".into(),
+                    extends: "button".into()
+                },
+                CustomElementDefinition::CustomizedBuiltIn {
+                    name: "fancy-button".into(),
+                    extends: "a".into()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn is_attribute_selector() {
+        let html = rewrite_str(
+            r#"<button is="fancy-button">A</button><button is="plain-button">B</button>"#,
+            RewriteStrSettings {
+                element_content_handlers: vec![crate::element!(
+                    "button[is=fancy-button], button[is=other-button]",
+                    |el| {
+                        el.set_attribute("data-enhanced", "")?;
+                        Ok(())
+                    }
+                )],
+                ..RewriteStrSettings::default()
+            },
+        )
+        .unwrap();
+
+        assert_eq!(
+            html,
+            r#"<button is="fancy-button" data-enhanced="">A</button><button is="plain-button">B</button>"#
+        );
+    }
+}
@@ -0,0 +1,1288 @@
+use super::settings::{ElementContentHandlers, Settings};
+use super::{discard_output, EncodingError, HtmlRewriter, RewritingError};
+use crate::memory::{MemoryLimiter, SharedMemoryLimiter};
+use crate::selectors_vm::{NthLast, Selector};
+use std::borrow::Cow;
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::mem;
+use std::rc::Rc;
+
+/// The resolution of the deferred predicates of a candidate element. The predicates are
+/// resolved in parts, e.g. the text predicates and the last-counting pseudo-classes, and are
+/// satisfied once all the parts are satisfied.
+struct PredicatesResolution {
+    satisfied: Cell<Option<bool>>,
+    pending_parts: Cell<usize>,
+}
+
+impl PredicatesResolution {
+    fn new(parts: usize) -> Rc<Self> {
+        Rc::new(PredicatesResolution {
+            satisfied: Cell::new(if parts == 0 { Some(true) } else { None }),
+            pending_parts: Cell::new(parts),
+        })
+    }
+
+    #[inline]
+    fn satisfied(&self) -> Option<bool> {
+        self.satisfied.get()
+    }
+
+    fn resolve_part(&self, satisfied: bool) {
+        if self.satisfied.get().is_some() {
+            return;
+        }
+
+        if satisfied {
+            let pending_parts = self.pending_parts.get() - 1;
+
+            self.pending_parts.set(pending_parts);
+
+            if pending_parts == 0 {
+                self.satisfied.set(Some(true));
+            }
+        } else {
+            self.satisfied.set(Some(false));
+        }
+    }
+
+    #[inline]
+    fn reject(&self) {
+        if self.satisfied.get().is_none() {
+            self.satisfied.set(Some(false));
+        }
+    }
+}
+
+type Resolution = Rc<PredicatesResolution>;
+type ResolutionQueue = Rc<RefCell<VecDeque<Resolution>>>;
+type OpenCandidates = Rc<RefCell<Vec<Resolution>>>;
+
+/// The deferred predicates of a selector in the list.
+struct ItemPredicates {
+    needles: Vec<String>,
+    own_needles: Vec<String>,
+    // NOTE: the candidates that are currently open and have the `:not(:has())` conditions.
+    // `None` if the selector has no such conditions.
+    open_candidates: Option<OpenCandidates>,
+    // NOTE: the candidates that are currently open and have no descendants matching
+    // the selectors of a `:has()` condition yet, for each such condition of the selector.
+    required_candidates: Vec<OpenCandidates>,
+    // NOTE: the candidates that are currently open and have the `:empty` pseudo-class.
+    // `None` if the selector has no such pseudo-class.
+    empty_candidates: Option<OpenCandidates>,
+    last_counting: Vec<NthLast>,
+}
+
+/// A candidate element whose last-counting pseudo-classes are resolved once its parent
+/// is closed.
+struct SiblingCandidate {
+    tag_name: String,
+    predicates: Vec<NthLast>,
+    following_siblings: usize,
+    following_siblings_of_type: usize,
+    resolution: Resolution,
+}
+
+impl SiblingCandidate {
+    fn resolve(&self) {
+        let satisfied = self.predicates.iter().all(|p| {
+            let following_siblings = if p.of_type {
+                self.following_siblings_of_type
+            } else {
+                self.following_siblings
+            };
+
+            p.nth.has_index(following_siblings as i32 + 1)
+        });
+
+        self.resolution.resolve_part(satisfied);
+    }
+}
+
+struct SiblingsFrame {
+    id: usize,
+    candidates: Vec<SiblingCandidate>,
+}
+
+/// Counts the following siblings of the candidate elements with last-counting pseudo-classes
+/// on the detector stream.
+///
+/// The tracker is registered for all the elements after the candidate handlers, which put
+/// the candidates to the `incoming` list, so the candidates are attributed to the frame of
+/// their parent. The elements are closed in the same way as by the selector matching VM, i.e.
+/// an end tag closes all the elements opened after the element it belongs to.
+struct SiblingsTracker {
+    frames: Vec<SiblingsFrame>,
+    incoming: Vec<SiblingCandidate>,
+    next_frame_id: usize,
+}
+
+impl SiblingsTracker {
+    fn new() -> Self {
+        SiblingsTracker {
+            frames: vec![SiblingsFrame {
+                id: 0,
+                candidates: Vec::new(),
+            }],
+            incoming: Vec::new(),
+            next_frame_id: 1,
+        }
+    }
+
+    fn add_element(&mut self, tag_name: &str) -> usize {
+        let incoming = mem::take(&mut self.incoming);
+
+        if let Some(frame) = self.frames.last_mut() {
+            for candidate in frame.candidates.iter_mut() {
+                candidate.following_siblings += 1;
+
+                if candidate.tag_name == tag_name {
+                    candidate.following_siblings_of_type += 1;
+                }
+            }
+
+            frame.candidates.extend(incoming);
+        }
+
+        self.next_frame_id += 1;
+        self.next_frame_id - 1
+    }
+
+    fn open_frame(&mut self, id: usize) {
+        self.frames.push(SiblingsFrame {
+            id,
+            candidates: Vec::new(),
+        });
+    }
+
+    fn close_frame(&mut self, id: usize) {
+        if let Some(idx) = self.frames.iter().position(|f| f.id == id) {
+            self.frames
+                .drain(idx..)
+                .flat_map(|f| f.candidates)
+                .for_each(|c| c.resolve());
+        }
+    }
+
+    fn close_all_frames(&mut self) {
+        self.close_frame(0);
+    }
+}
+
+/// A candidate element whose `:contains-own()` text predicates are resolved once it's closed.
+struct OwnTextCandidate {
+    id: usize,
+    needles: Vec<String>,
+    text: String,
+    resolution: Resolution,
+}
+
+impl OwnTextCandidate {
+    #[inline]
+    fn resolve(&self) {
+        let satisfied = self.needles.iter().all(|n| self.text.contains(n.as_str()));
+
+        self.resolution.resolve_part(satisfied);
+    }
+}
+
+/// Accumulates the text of the candidate elements with `:contains-own()` text predicates on
+/// the detector stream, excluding the text of their descendant elements.
+///
+/// Like the siblings tracker, the tracker is registered for all the elements after
+/// the candidate handlers and closes the elements in the same way as the selector matching VM.
+struct OwnTextTracker {
+    // NOTE: the ids of the open elements, which increase towards the innermost one.
+    open: Vec<usize>,
+    candidates: Vec<OwnTextCandidate>,
+    incoming: Vec<OwnTextCandidate>,
+    next_id: usize,
+    limit: usize,
+}
+
+impl OwnTextTracker {
+    fn new(limit: usize) -> Self {
+        OwnTextTracker {
+            open: Vec::new(),
+            candidates: Vec::new(),
+            incoming: Vec::new(),
+            next_id: 0,
+            limit,
+        }
+    }
+
+    fn open_element(&mut self) -> usize {
+        let id = self.next_id;
+
+        self.next_id += 1;
+        self.open.push(id);
+
+        for mut candidate in self.incoming.drain(..) {
+            candidate.id = id;
+            self.candidates.push(candidate);
+        }
+
+        id
+    }
+
+    fn close_element(&mut self, id: usize) {
+        if let Some(idx) = self.open.iter().position(|&i| i == id) {
+            self.open.truncate(idx);
+
+            let idx = self
+                .candidates
+                .iter()
+                .position(|c| c.id >= id)
+                .unwrap_or(self.candidates.len());
+
+            self.candidates
+                .drain(idx..)
+                .for_each(|candidate| candidate.resolve());
+        }
+    }
+
+    fn add_text(&mut self, text: &str) {
+        let id = match self.open.last() {
+            Some(&id) => id,
+            None => return,
+        };
+
+        let limit = self.limit;
+
+        // NOTE: the candidates are ordered by the ids, so only the last ones can be
+        // the innermost open element.
+        for candidate in self.candidates.iter_mut().rev() {
+            if candidate.id != id {
+                break;
+            }
+
+            let mut end = limit.saturating_sub(candidate.text.len()).min(text.len());
+
+            while !text.is_char_boundary(end) {
+                end -= 1;
+            }
+
+            candidate.text.push_str(&text[..end]);
+        }
+    }
+}
+
+fn own_text_tracker_handlers(
+    tracker: &Rc<RefCell<OwnTextTracker>>,
+) -> ElementContentHandlers<'static> {
+    let element_tracker = Rc::clone(tracker);
+    let text_tracker = Rc::clone(tracker);
+
+    ElementContentHandlers::default()
+        .element(move |el| {
+            if el.can_have_content() {
+                let id = element_tracker.borrow_mut().open_element();
+                let tracker = Rc::clone(&element_tracker);
+
+                el.add_end_tag_hook(move || tracker.borrow_mut().close_element(id));
+            }
+
+            Ok(())
+        })
+        .text(move |t| {
+            text_tracker.borrow_mut().add_text(t.as_str());
+
+            Ok(())
+        })
+}
+
+fn siblings_tracker_handlers(
+    tracker: &Rc<RefCell<SiblingsTracker>>,
+) -> ElementContentHandlers<'static> {
+    let tracker = Rc::clone(tracker);
+
+    ElementContentHandlers::default().element(move |el| {
+        let id = tracker.borrow_mut().add_element(el.tag_name());
+
+        if el.can_have_content() {
+            let tracker = Rc::clone(&tracker);
+
+            tracker.borrow_mut().open_frame(id);
+            el.add_end_tag_hook(move || tracker.borrow_mut().close_frame(id));
+        }
+
+        Ok(())
+    })
+}
+
+// NOTE: the handlers run on the detector stream. Any element matched by one of the excluded
+// descendant selectors makes the conditions of the open candidates unsatisfied. The handlers
+// are registered before the candidate ones, so a candidate is not affected by itself.
+fn excluded_descendant_handlers(
+    open_candidates: &OpenCandidates,
+) -> ElementContentHandlers<'static> {
+    let open_candidates = Rc::clone(open_candidates);
+
+    ElementContentHandlers::default().element(move |_| {
+        open_candidates
+            .borrow()
+            .iter()
+            .for_each(|resolution| resolution.resolve_part(false));
+
+        Ok(())
+    })
+}
+
+// NOTE: the handlers run on the detector stream. Any element matched by the selectors of
+// a `:has()` condition satisfies the condition for all the open candidates, which then stop
+// waiting for it. The handlers are registered before the candidate ones, so a candidate is not
+// affected by itself.
+fn required_descendant_handlers(
+    required_candidates: &OpenCandidates,
+) -> ElementContentHandlers<'static> {
+    let required_candidates = Rc::clone(required_candidates);
+
+    ElementContentHandlers::default().element(move |_| {
+        required_candidates
+            .borrow_mut()
+            .drain(..)
+            .for_each(|resolution| resolution.resolve_part(true));
+
+        Ok(())
+    })
+}
+
+// NOTE: the handlers run on the detector stream for all the elements. Any element or non-empty
+// text chunk makes the open `:empty` candidates unsatisfied, while comments are ignored.
+// The handlers are registered before the candidate ones, so a candidate is not affected by
+// itself.
+fn non_empty_content_handlers(
+    empty_candidates: &OpenCandidates,
+) -> ElementContentHandlers<'static> {
+    let element_empty_candidates = Rc::clone(empty_candidates);
+    let text_empty_candidates = Rc::clone(empty_candidates);
+
+    let reject = |empty_candidates: &OpenCandidates| {
+        empty_candidates
+            .borrow()
+            .iter()
+            .for_each(|resolution| resolution.resolve_part(false));
+    };
+
+    ElementContentHandlers::default()
+        .element(move |_| {
+            reject(&element_empty_candidates);
+
+            Ok(())
+        })
+        .text(move |t| {
+            if !t.as_str().is_empty() {
+                reject(&text_empty_candidates);
+            }
+
+            Ok(())
+        })
+}
+
+// NOTE: the handlers run on the detector stream. For each element matched by the selector,
+// they enqueue the resolution of the deferred predicates of the first matched selector in
+// the list.
+fn candidate_handlers(
+    predicates: Vec<Option<ItemPredicates>>,
+    queue: &ResolutionQueue,
+    unresolved: &Rc<RefCell<Vec<Resolution>>>,
+    siblings_tracker: &Rc<RefCell<SiblingsTracker>>,
+    own_text_tracker: &Rc<RefCell<OwnTextTracker>>,
+    lookahead_limit: usize,
+) -> ElementContentHandlers<'static> {
+    let queue = Rc::clone(queue);
+    let unresolved = Rc::clone(unresolved);
+    let siblings_tracker = Rc::clone(siblings_tracker);
+    let own_text_tracker = Rc::clone(own_text_tracker);
+
+    ElementContentHandlers::default().element(move |el| {
+        let predicates = el
+            .matched_selector_index()
+            .and_then(|idx| predicates.get(idx))
+            .and_then(Option::as_ref);
+
+        let predicates = match predicates {
+            Some(predicates) => predicates,
+            None => {
+                queue.borrow_mut().push_back(PredicatesResolution::new(0));
+
+                return Ok(());
+            }
+        };
+
+        // NOTE: void elements have neither text content nor descendants.
+        let has_content = el.can_have_content();
+        let has_needles = !predicates.needles.is_empty();
+        let has_own_needles = !predicates.own_needles.is_empty();
+        let has_excluded = predicates.open_candidates.is_some() && has_content;
+        let has_empty = predicates.empty_candidates.is_some() && has_content;
+        let has_last_counting = !predicates.last_counting.is_empty();
+        let has_required = !predicates.required_candidates.is_empty();
+
+        let resolution = PredicatesResolution::new(
+            [
+                has_needles,
+                has_own_needles,
+                has_excluded,
+                has_empty,
+                has_last_counting,
+            ]
+            .iter()
+            .filter(|&&p| p)
+            .count()
+                + if has_content {
+                    predicates.required_candidates.len()
+                } else {
+                    0
+                },
+        );
+
+        if (has_needles || has_own_needles || has_required) && !has_content {
+            resolution.reject();
+        }
+
+        for required_candidates in predicates
+            .required_candidates
+            .iter()
+            .filter(|_| has_content)
+        {
+            let required_candidates = Rc::clone(required_candidates);
+            let candidate = Rc::clone(&resolution);
+
+            required_candidates.borrow_mut().push(Rc::clone(&candidate));
+
+            // NOTE: the condition is unsatisfied if the candidate is still waiting for
+            // a matching descendant once it's closed.
+            el.add_end_tag_hook(move || {
+                let mut required_candidates = required_candidates.borrow_mut();
+
+                if let Some(idx) = required_candidates
+                    .iter()
+                    .position(|c| Rc::ptr_eq(c, &candidate))
+                {
+                    required_candidates.remove(idx);
+                    candidate.resolve_part(false);
+                }
+            });
+        }
+
+        if let Some(open_candidates) = predicates.open_candidates.as_ref().filter(|_| has_excluded)
+        {
+            let open_candidates = Rc::clone(open_candidates);
+            let candidate = Rc::clone(&resolution);
+
+            open_candidates.borrow_mut().push(Rc::clone(&candidate));
+
+            // NOTE: the candidate is removed by identity, as the end tags of the nested
+            // candidates may be missing.
+            el.add_end_tag_hook(move || {
+                open_candidates
+                    .borrow_mut()
+                    .retain(|c| !Rc::ptr_eq(c, &candidate));
+
+                candidate.resolve_part(true);
+            });
+        }
+
+        if let Some(empty_candidates) = predicates.empty_candidates.as_ref().filter(|_| has_empty) {
+            let empty_candidates = Rc::clone(empty_candidates);
+            let candidate = Rc::clone(&resolution);
+
+            empty_candidates.borrow_mut().push(Rc::clone(&candidate));
+
+            el.add_end_tag_hook(move || {
+                empty_candidates
+                    .borrow_mut()
+                    .retain(|c| !Rc::ptr_eq(c, &candidate));
+
+                candidate.resolve_part(true);
+            });
+        }
+
+        if has_needles && has_content {
+            let needles = predicates.needles.to_owned();
+            let text_resolution = Rc::clone(&resolution);
+
+            el.accumulate_text(lookahead_limit, move |text| {
+                text_resolution.resolve_part(needles.iter().all(|n| text.contains(n.as_str())));
+
+                Ok(())
+            });
+        }
+
+        if has_own_needles && has_content {
+            own_text_tracker
+                .borrow_mut()
+                .incoming
+                .push(OwnTextCandidate {
+                    id: 0,
+                    needles: predicates.own_needles.to_owned(),
+                    text: String::new(),
+                    resolution: Rc::clone(&resolution),
+                });
+        }
+
+        if has_last_counting {
+            siblings_tracker
+                .borrow_mut()
+                .incoming
+                .push(SiblingCandidate {
+                    tag_name: el.tag_name().to_owned(),
+                    predicates: predicates.last_counting.to_owned(),
+                    following_siblings: 0,
+                    following_siblings_of_type: 0,
+                    resolution: Rc::clone(&resolution),
+                });
+        }
+
+        if resolution.satisfied().is_none() {
+            unresolved.borrow_mut().push(Rc::clone(&resolution));
+        }
+
+        queue.borrow_mut().push_back(resolution);
+
+        Ok(())
+    })
+}
+
+// NOTE: the handlers run on the main stream, which sees the same elements as the detector
+// stream, so the resolutions are dequeued in the order they were enqueued. Content handlers
+// are invoked only in the inner content of the elements with satisfied predicates.
+//
+// The elements of the content reprocessed by the handlers are seen only by the main stream,
+// so they don't have resolutions and their deferred predicates are considered unsatisfied.
+fn gated_handlers(
+    handlers: ElementContentHandlers<'_>,
+    queue: ResolutionQueue,
+) -> ElementContentHandlers<'_> {
+    let ElementContentHandlers {
+        mut element,
+        comments,
+        text,
+        priority,
+        // NOTE: the depth bound is applied to the selector before the lookahead is set up.
+        max_depth: _,
+        max_matches,
+    } = handlers;
+
+    let open_satisfied_count = Rc::new(Cell::new(0));
+    let element_open_satisfied_count = Rc::clone(&open_satisfied_count);
+
+    // NOTE: the selector can't be dropped from the VM once the match limit is reached, as
+    // the resolutions of the following candidates still need to be dequeued, so only
+    // the elements with satisfied predicates are counted here.
+    let mut remaining_matches = max_matches.unwrap_or(usize::MAX);
+
+    let mut gated = ElementContentHandlers::default().element(move |el| {
+        if el.is_reprocessed() {
+            return Ok(());
+        }
+
+        let satisfied = queue
+            .borrow_mut()
+            .pop_front()
+            .and_then(|resolution| resolution.satisfied())
+            .unwrap_or(false);
+
+        if !satisfied || remaining_matches == 0 {
+            return Ok(());
+        }
+
+        remaining_matches -= 1;
+
+        if el.can_have_content() {
+            let count = Rc::clone(&element_open_satisfied_count);
+
+            count.set(count.get() + 1);
+            el.add_end_tag_hook(move || count.set(count.get() - 1));
+        }
+
+        match element {
+            Some(ref mut handler) => handler(el),
+            None => Ok(()),
+        }
+    });
+
+    if let Some(mut handler) = comments {
+        let count = Rc::clone(&open_satisfied_count);
+
+        gated = gated.comments(move |c| if count.get() > 0 { handler(c) } else { Ok(()) });
+    }
+
+    if let Some(mut handler) = text {
+        let count = Rc::clone(&open_satisfied_count);
+
+        gated = gated.text(move |t| if count.get() > 0 { handler(t) } else { Ok(()) });
+    }
+
+    gated.priority(priority)
+}
+
+/// Evaluates the deferred predicates of the selectors, i.e. the `:contains()` and
+/// `:contains-own()` text predicates, `:empty`, the last-counting pseudo-classes and the `:has()`
+/// and `:not(:has())` conditions, ahead of the main stream.
+///
+/// The input is parsed by an analysis-only detector stream first and is held back until
+/// the predicates of all the candidate elements seen by the detector are resolved. The held
+/// back input is counted against the memory limit of the rewriter.
+pub(super) struct DeferredPredicatesLookahead {
+    detector: HtmlRewriter<'static, fn(&[u8])>,
+    unresolved: Rc<RefCell<Vec<Resolution>>>,
+    siblings_tracker: Rc<RefCell<SiblingsTracker>>,
+    pending: Vec<u8>,
+    lookahead_limit: usize,
+    memory_limiter: SharedMemoryLimiter,
+}
+
+impl DeferredPredicatesLookahead {
+    /// Replaces the handlers of the selectors with deferred predicates in the `settings` with
+    /// the gated ones. Returns `None` if none of the selectors has deferred predicates.
+    pub fn try_new(settings: &mut Settings) -> Result<Option<Self>, EncodingError> {
+        let unresolved = Rc::new(RefCell::new(Vec::new()));
+        let siblings_tracker = Rc::new(RefCell::new(SiblingsTracker::new()));
+        let mut detector_handlers = Vec::new();
+        let mut has_last_counting = false;
+        let mut has_own_text = false;
+
+        // NOTE: bounds both the inspected text content of a candidate element and the input held
+        // back while the predicates of the candidates are not resolved.
+        let lookahead_limit = settings.max_lookahead.unwrap_or(usize::MAX);
+        let own_text_tracker = Rc::new(RefCell::new(OwnTextTracker::new(lookahead_limit)));
+
+        for (selector, handlers) in settings.element_content_handlers.iter_mut() {
+            let text_predicates = selector.text_predicates();
+            let own_text_predicates = selector.own_text_predicates();
+            let excluded_descendants = selector.excluded_descendants();
+            let required_descendants = selector.required_descendants();
+            let empty_predicates = selector.empty_predicates();
+            let last_counting_predicates = selector.last_counting_predicates();
+
+            if text_predicates.iter().all(Vec::is_empty)
+                && own_text_predicates.iter().all(Vec::is_empty)
+                && excluded_descendants.iter().all(Vec::is_empty)
+                && required_descendants.iter().all(Vec::is_empty)
+                && empty_predicates.iter().all(|&e| !e)
+                && last_counting_predicates.iter().all(Vec::is_empty)
+            {
+                continue;
+            }
+
+            let mut predicates = Vec::with_capacity(text_predicates.len());
+
+            for (((((needles, own_needles), excluded), required), empty), last_counting) in
+                text_predicates
+                    .into_iter()
+                    .zip(own_text_predicates)
+                    .zip(excluded_descendants)
+                    .zip(required_descendants)
+                    .zip(empty_predicates)
+                    .zip(last_counting_predicates)
+            {
+                if needles.is_empty()
+                    && own_needles.is_empty()
+                    && excluded.is_empty()
+                    && required.is_empty()
+                    && !empty
+                    && last_counting.is_empty()
+                {
+                    predicates.push(None);
+                    continue;
+                }
+
+                let open_candidates = if excluded.is_empty() {
+                    None
+                } else {
+                    let open_candidates = Rc::new(RefCell::new(Vec::new()));
+
+                    for excluded_selector in excluded {
+                        detector_handlers.push((
+                            Cow::Owned(excluded_selector),
+                            excluded_descendant_handlers(&open_candidates),
+                        ));
+                    }
+
+                    Some(open_candidates)
+                };
+
+                let required_candidates = required
+                    .into_iter()
+                    .map(|required_selector| {
+                        let required_candidates = Rc::new(RefCell::new(Vec::new()));
+
+                        detector_handlers.push((
+                            Cow::Owned(required_selector),
+                            required_descendant_handlers(&required_candidates),
+                        ));
+
+                        required_candidates
+                    })
+                    .collect();
+
+                let empty_candidates = if empty {
+                    let empty_candidates = Rc::new(RefCell::new(Vec::new()));
+
+                    detector_handlers.push((
+                        Cow::Owned("*".parse().expect("Universal selector should be valid")),
+                        non_empty_content_handlers(&empty_candidates),
+                    ));
+
+                    Some(empty_candidates)
+                } else {
+                    None
+                };
+
+                has_last_counting |= !last_counting.is_empty();
+                has_own_text |= !own_needles.is_empty();
+
+                predicates.push(Some(ItemPredicates {
+                    needles,
+                    own_needles,
+                    open_candidates,
+                    required_candidates,
+                    empty_candidates,
+                    last_counting,
+                }));
+            }
+
+            let queue = Rc::new(RefCell::new(VecDeque::new()));
+
+            detector_handlers.push((
+                Cow::Owned(Selector::clone(selector)),
+                candidate_handlers(
+                    predicates,
+                    &queue,
+                    &unresolved,
+                    &siblings_tracker,
+                    &own_text_tracker,
+                    lookahead_limit,
+                ),
+            ));
+
+            *handlers = gated_handlers(mem::take(handlers), queue);
+        }
+
+        if detector_handlers.is_empty() {
+            return Ok(None);
+        }
+
+        if has_last_counting {
+            detector_handlers.push((
+                Cow::Owned("*".parse().expect("Universal selector should be valid")),
+                siblings_tracker_handlers(&siblings_tracker),
+            ));
+        }
+
+        if has_own_text {
+            detector_handlers.push((
+                Cow::Owned("*".parse().expect("Universal selector should be valid")),
+                own_text_tracker_handlers(&own_text_tracker),
+            ));
+        }
+
+        let detector = HtmlRewriter::try_new_without_lookahead(
+            Settings {
+                element_content_handlers: detector_handlers,
+                encoding: settings.encoding,
+                strict: settings.strict,
+                custom_void_elements: settings.custom_void_elements.clone(),
+                ..Settings::default()
+            },
+            discard_output as fn(&[u8]),
+            false,
+        )?;
+
+        Ok(Some(DeferredPredicatesLookahead {
+            detector,
+            unresolved,
+            siblings_tracker,
+            pending: Vec::new(),
+            lookahead_limit,
+            memory_limiter: MemoryLimiter::new_shared(usize::MAX),
+        }))
+    }
+
+    /// Makes the held back input count against the memory limit of the rewriter.
+    #[inline]
+    pub fn set_memory_limiter(&mut self, memory_limiter: SharedMemoryLimiter) {
+        self.memory_limiter = memory_limiter;
+    }
+
+    fn release_pending(&mut self) -> Vec<u8> {
+        self.memory_limiter
+            .borrow_mut()
+            .decrease_usage(self.pending.len());
+
+        mem::take(&mut self.pending)
+    }
+
+    fn reject_all_unresolved(&mut self) {
+        self.unresolved
+            .borrow_mut()
+            .drain(..)
+            .for_each(|resolution| resolution.reject());
+    }
+
+    /// Writes a chunk of the input to the detector stream and returns the input that can be
+    /// passed to the main stream.
+    pub fn write(&mut self, data: &[u8]) -> Result<Vec<u8>, RewritingError> {
+        self.detector.write(data)?;
+        self.pending.extend_from_slice(data);
+
+        // NOTE: the held back input is released right away once the memory limit is reached,
+        // so the limit is exceeded only until the end of this call.
+        let memory_limit_exceeded = self
+            .memory_limiter
+            .borrow_mut()
+            .increase_usage(data.len())
+            .is_err();
+
+        self.unresolved
+            .borrow_mut()
+            .retain(|resolution| resolution.satisfied().is_none());
+
+        if self.pending.len() > self.lookahead_limit || memory_limit_exceeded {
+            self.reject_all_unresolved();
+        }
+
+        Ok(if self.unresolved.borrow().is_empty() {
+            self.release_pending()
+        } else {
+            Vec::new()
+        })
+    }
+
+    /// Finalizes the detector stream and returns the rest of the input. The elements that
+    /// have not been closed are considered closed at the end of the input for the purposes of
+    /// counting their children, while the rest of the predicates of the elements that have not
+    /// been closed are considered unsatisfied.
+    pub fn end(&mut self) -> Result<Vec<u8>, RewritingError> {
+        self.detector.end()?;
+        self.siblings_tracker.borrow_mut().close_all_frames();
+        self.reject_all_unresolved();
+
+        Ok(self.release_pending())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::html_content::ContentType;
+    use crate::test_utils::assert_golden_output;
+    use crate::*;
+
+    #[test]
+    fn element_handlers() {
+        assert_golden_output(
+            concat!(
+                "<div class=banner>Big <b>Sale</b>!</div>",
+                "<div class=banner>News</div>",
+                "<div>Sale</div>"
+            ),
+            "<div class=banner>News</div><div>Sale</div>",
+            || Settings {
+                element_content_handlers: vec![element!(r#"div.banner:contains("Sale")"#, |el| {
+                    el.remove();
+                    Ok(())
+                })],
+                ..Settings::default()
+            },
+        );
+    }
+
+    #[test]
+    fn selector_list() {
+        assert_golden_output(
+            "<p>foo bar</p><p>foo</p><b>baz</b><br><i>qux</i>",
+            "<p>foo bar!</p><p>foo</p><b>baz!</b><br><i>qux</i>",
+            || Settings {
+                element_content_handlers: vec![element!(
+                    r#"p:contains(foo):contains("bar"), b, br:contains(""), i:contains(baz)"#,
+                    |el| {
+                        el.append("!", ContentType::Text);
+                        Ok(())
+                    }
+                )],
+                ..Settings::default()
+            },
+        );
+    }
+
+    #[test]
+    fn nested_candidates() {
+        assert_golden_output(
+            "<div><div>foo</div><div>bar</div></div><div>baz</div>",
+            r#"<div data-foo=""><div data-foo="">foo</div><div>bar</div></div><div>baz</div>"#,
+            || Settings {
+                element_content_handlers: vec![element!("div:contains(foo)", |el| {
+                    el.set_attribute("data-foo", "")?;
+                    Ok(())
+                })],
+                ..Settings::default()
+            },
+        );
+    }
+
+    #[test]
+    fn content_handlers() {
+        assert_golden_output(
+            "<p>foo<!--1--><i>bar</i></p><p>bar<!--2--></p>",
+            "<p>FOO<!--1!--><i>BAR</i></p><p>bar<!--2--></p>",
+            || Settings {
+                element_content_handlers: vec![
+                    text!("p:contains(foo)", |t| {
+                        t.replace(&t.as_str().to_uppercase(), ContentType::Text);
+                        Ok(())
+                    }),
+                    comments!("p:contains(foo)", |c| {
+                        c.set_text(&format!("{}!", c.text()))?;
+                        Ok(())
+                    }),
+                ],
+                ..Settings::default()
+            },
+        );
+    }
+
+    #[test]
+    fn unclosed_candidate() {
+        assert_golden_output("<div>foo", "<div>foo", || Settings {
+            element_content_handlers: vec![element!("div:contains(foo)", |el| {
+                el.remove();
+                Ok(())
+            })],
+            ..Settings::default()
+        });
+    }
+
+    #[test]
+    fn own_text_predicates() {
+        assert_golden_output(
+            concat!(
+                "<p>Click <a>here</a> to subscribe</p>",
+                "<p>Read <b>Click here to subscribe</b></p>",
+                "<p>Click here <i>now</i></p>",
+                "<ul><li>Click here<li>to subscribe</ul>"
+            ),
+            concat!(
+                "<p>Read <b>Click here to subscribe</b></p>",
+                "<p>Click here <i>now</i></p>",
+                r#"<ul><li x="">Click here<li>to subscribe</ul>"#
+            ),
+            || Settings {
+                element_content_handlers: vec![element!(
+                    r#"p:contains-own("Click"):contains-own("subscribe"), li:contains-own(here)"#,
+                    |el| {
+                        if el.tag_name() == "li" {
+                            el.set_attribute("x", "")?;
+                        } else {
+                            el.remove();
+                        }
+
+                        Ok(())
+                    }
+                )],
+                ..Settings::default()
+            },
+        );
+    }
+
+    #[test]
+    fn empty_elements() {
+        assert_golden_output(
+            concat!(
+                "<div></div><div><!--c--></div><div> </div><div><br></div>",
+                "<div><p></p></div><br><div>"
+            ),
+            concat!(
+                r#"<div x=""></div><div x=""><!--c--></div><div> </div><div><br x=""></div>"#,
+                r#"<div><p x=""></p></div><br x=""><div>"#
+            ),
+            || Settings {
+                element_content_handlers: vec![element!(":empty", |el| {
+                    el.set_attribute("x", "")?;
+                    Ok(())
+                })],
+                ..Settings::default()
+            },
+        );
+    }
+
+    #[test]
+    fn excluded_descendants() {
+        assert_golden_output(
+            concat!(
+                r#"<div class=card><img src=a.png></div>"#,
+                r#"<div class=card><p>foo</p></div>"#,
+                r#"<div class=card><p><i><img></i></p></div>"#,
+                r#"<img class=card>"#
+            ),
+            concat!(
+                r#"<div class=card><img src=a.png></div>"#,
+                r#"<div class=card no-img=""><p>foo</p></div>"#,
+                r#"<div class=card><p><i><img></i></p></div>"#,
+                r#"<img class=card no-img="">"#
+            ),
+            || Settings {
+                element_content_handlers: vec![element!(".card:not(:has(img))", |el| {
+                    el.set_attribute("no-img", "")?;
+                    Ok(())
+                })],
+                ..Settings::default()
+            },
+        );
+    }
+
+    #[test]
+    fn required_descendants() {
+        assert_golden_output(
+            concat!(
+                r#"<article><p><img class=lazy></p></article>"#,
+                r#"<article><img></article>"#,
+                r#"<article><video></video><img class=lazy></article>"#,
+                r#"<img class=lazy><article></article>"#
+            ),
+            concat!(
+                r#"<article x=""><p><img class=lazy></p></article>"#,
+                r#"<article><img></article>"#,
+                r#"<article><video></video><img class=lazy></article>"#,
+                r#"<img class=lazy><article></article>"#
+            ),
+            || Settings {
+                element_content_handlers: vec![element!(
+                    "article:has(img.lazy):not(:has(video))",
+                    |el| {
+                        el.set_attribute("x", "")?;
+                        Ok(())
+                    }
+                )],
+                ..Settings::default()
+            },
+        );
+    }
+
+    #[test]
+    fn nested_required_descendants_candidates() {
+        assert_golden_output(
+            "<div><div><b></b></div><div></div></div><div><i></i></div><b></b><br>",
+            r#"<div x=""><div x=""><b></b></div><div></div></div><div><i></i></div><b></b><br>"#,
+            || Settings {
+                element_content_handlers: vec![element!(":has(b)", |el| {
+                    el.set_attribute("x", "")?;
+                    Ok(())
+                })],
+                ..Settings::default()
+            },
+        );
+    }
+
+    #[test]
+    fn nested_excluded_descendants_candidates() {
+        assert_golden_output(
+            "<div><div></div><div><b></b></div></div><div><i></i></div>",
+            r#"<div><div x=""></div><div><b></b></div></div><div x=""><i></i></div>"#,
+            || Settings {
+                element_content_handlers: vec![element!("div:not(:has(b, div > b))", |el| {
+                    el.set_attribute("x", "")?;
+                    Ok(())
+                })],
+                ..Settings::default()
+            },
+        );
+    }
+
+    #[test]
+    fn combined_predicates() {
+        assert_golden_output(
+            "<p>foo<img></p><p>foo</p><p>bar</p>",
+            "<p>foo<img></p><p>FOO</p><p>bar</p>",
+            || Settings {
+                element_content_handlers: vec![text!("p:contains(foo):not(:has(img))", |t| {
+                    t.replace(&t.as_str().to_uppercase(), ContentType::Text);
+                    Ok(())
+                })],
+                ..Settings::default()
+            },
+        );
+    }
+
+    #[test]
+    fn last_counting_pseudo_classes() {
+        assert_golden_output(
+            "<ul><li>1</li><li>2</li><li>3</li></ul><ul><li>4</li></ul>",
+            "<ul><li>1</li></ul><ul></ul>",
+            || Settings {
+                element_content_handlers: vec![element!("li:nth-last-child(-n+2)", |el| {
+                    el.remove();
+                    Ok(())
+                })],
+                ..Settings::default()
+            },
+        );
+
+        assert_golden_output(
+            "<div><p>1</p><b>2</b><p>3</p><i>4</i></div><p>5</p>",
+            r#"<div><p>1</p><b x="">2</b><p x="">3</p><i x="">4</i></div><p x="">5</p>"#,
+            || Settings {
+                element_content_handlers: vec![element!(":last-of-type", |el| {
+                    el.set_attribute("x", "")?;
+                    Ok(())
+                })],
+                ..Settings::default()
+            },
+        );
+    }
+
+    #[test]
+    fn only_child_pseudo_classes() {
+        assert_golden_output(
+            "<ul><li>1</li></ul><ul><li>2</li><li>3</li></ul>",
+            "<ul></ul><ul><li>2</li><li>3</li></ul>",
+            || Settings {
+                element_content_handlers: vec![element!("li:only-child", |el| {
+                    el.remove();
+                    Ok(())
+                })],
+                ..Settings::default()
+            },
+        );
+
+        assert_golden_output(
+            "<section><div><p>1</p><b>2</b><b>3</b></div></section>",
+            r#"<section><div x=""><p x="">1</p><b>2</b><b>3</b></div></section>"#,
+            || Settings {
+                element_content_handlers: vec![element!("section :only-of-type", |el| {
+                    el.set_attribute("x", "")?;
+                    Ok(())
+                })],
+                ..Settings::default()
+            },
+        );
+    }
+
+    #[test]
+    fn unclosed_parent_of_last_counting_candidates() {
+        assert_golden_output(
+            "<ul><li>1</li><li>2</li><li>3",
+            r#"<ul><li>1</li><li>2</li><li x="">3"#,
+            || Settings {
+                element_content_handlers: vec![element!("li:last-child", |el| {
+                    el.set_attribute("x", "")?;
+                    Ok(())
+                })],
+                ..Settings::default()
+            },
+        );
+    }
+
+    #[test]
+    fn reprocessed_content() {
+        assert_golden_output(
+            "<span></span><div><p></p></div><div></div>",
+            r#"<span></span><div><p></p></div><div x=""><p></p></div><div></div>"#,
+            || Settings {
+                element_content_handlers: vec![
+                    element!("span", |el| {
+                        el.after("<div><p></p></div>", ContentType::HtmlReprocessed);
+                        Ok(())
+                    }),
+                    element!("div:has(p)", |el| {
+                        el.set_attribute("x", "")?;
+                        Ok(())
+                    }),
+                ],
+                ..Settings::default()
+            },
+        );
+    }
+
+    #[test]
+    fn lookahead_limit() {
+        let html = format!("<div>{}foo</div><b>foo</b>", "-".repeat(64 * 1024));
+
+        let rewrite = |max_lookahead| {
+            let mut output = Vec::new();
+
+            {
+                let mut rewriter = HtmlRewriter::try_new(
+                    Settings {
+                        element_content_handlers: vec![element!(":contains(foo)", |el| {
+                            el.set_attribute("foo", "")?;
+                            Ok(())
+                        })],
+                        max_lookahead,
+                        ..Settings::default()
+                    },
+                    |c: &[u8]| output.extend_from_slice(c),
+                )
+                .unwrap();
+
+                for chunk in html.as_bytes().chunks(1024) {
+                    rewriter.write(chunk).unwrap();
+                }
+
+                rewriter.end().unwrap();
+            }
+
+            String::from_utf8(output).unwrap()
+        };
+
+        assert_eq!(
+            rewrite(Settings::default().max_lookahead),
+            html.replace("<b>", r#"<b foo="">"#)
+        );
+
+        // NOTE: without the bound the held back input is limited by the memory limit only.
+        assert_eq!(
+            rewrite(None),
+            html.replace("<div>", r#"<div foo="">"#)
+                .replace("<b>", r#"<b foo="">"#)
+        );
+
+        assert_eq!(rewrite(Some(1024)), html.replace("<b>", r#"<b foo="">"#));
+    }
+
+    #[test]
+    fn held_back_input_memory_limit() {
+        let html = format!("<ul><li>1</li><li>{}</li></ul>", "-".repeat(4096));
+
+        let rewrite = |max_allowed_memory_usage| {
+            let mut output = Vec::new();
+
+            {
+                let mut rewriter = HtmlRewriter::try_new(
+                    Settings {
+                        element_content_handlers: vec![element!(
+                            "li:only-child, li:last-child",
+                            |el| {
+                                el.set_attribute("x", "")?;
+                                Ok(())
+                            }
+                        )],
+                        memory_settings: MemorySettings {
+                            max_allowed_memory_usage,
+                            ..MemorySettings::default()
+                        },
+                        ..Settings::default()
+                    },
+                    |c: &[u8]| output.extend_from_slice(c),
+                )
+                .unwrap();
+
+                for chunk in html.as_bytes().chunks(1024) {
+                    rewriter.write(chunk).unwrap();
+                }
+
+                rewriter.end().unwrap();
+            }
+
+            String::from_utf8(output).unwrap()
+        };
+
+        assert_eq!(rewrite(16 * 1024), html.replace("<li>-", r#"<li x="">-"#));
+
+        // NOTE: the candidate is rejected once the held back input reaches the limit.
+        assert_eq!(rewrite(4096), html);
+    }
+}
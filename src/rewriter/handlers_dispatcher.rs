@@ -1,13 +1,95 @@
 use super::settings::*;
 use super::ElementDescriptor;
-use crate::rewritable_units::{DocumentEnd, Element, StartTag, Token, TokenCaptureFlags};
-use crate::selectors_vm::MatchInfo;
+use crate::memory::{MemoryLimitExceededError, SharedMemoryLimiter};
+use crate::rewritable_units::{
+    DocumentEnd, Element, StartTag, SubtreeBuilder, TextAccumulator, Token, TokenCaptureFlags,
+};
+#[cfg(feature = "element_handlers")]
+use crate::selectors_vm::{MatchInfo, PseudoElementKind};
+use std::marker::PhantomData;
+use std::mem;
+use std::ops::Range;
+use std::rc::Rc;
+
+/// Statically describes the kinds of the handlers that can be registered in the dispatcher.
+pub trait HandlersLayout: 'static {
+    /// `false` if only element handlers are registered, so the dispatch of doctype, comment,
+    /// text and document end handlers can be omitted.
+    const HAS_CONTENT_HANDLERS: bool;
+}
+
+/// Layout of the dispatcher that can have handlers of any kind.
+pub enum AnyHandlers {}
+
+impl HandlersLayout for AnyHandlers {
+    const HAS_CONTENT_HANDLERS: bool = true;
+}
+
+/// Layout of the dispatcher that has only element handlers.
+pub enum ElementHandlersOnly {}
 
+impl HandlersLayout for ElementHandlersOnly {
+    const HAS_CONTENT_HANDLERS: bool = false;
+}
+
+#[cfg(feature = "element_handlers")]
 #[derive(Copy, Clone, Default, Debug, PartialEq, Eq, Hash)]
 pub struct SelectorHandlersLocator {
     pub element_handler_idx: Option<usize>,
     pub comment_handler_idx: Option<usize>,
     pub text_handler_idx: Option<usize>,
+    pub match_limit_idx: Option<usize>,
+    pub selector_idx: usize,
+}
+
+#[cfg(feature = "element_handlers")]
+impl SelectorHandlersLocator {
+    /// Narrows the locator down to the selector with the `selector_idx` in the selector list and
+    /// to the handlers that are applicable to the given pseudo-element.
+    #[inline]
+    pub fn scoped_to(self, selector_idx: usize, pseudo_element: Option<PseudoElementKind>) -> Self {
+        match pseudo_element {
+            Some(PseudoElementKind::Text) => SelectorHandlersLocator {
+                text_handler_idx: self.text_handler_idx,
+                match_limit_idx: self.match_limit_idx,
+                selector_idx,
+                ..Default::default()
+            },
+            Some(PseudoElementKind::Comment) => SelectorHandlersLocator {
+                comment_handler_idx: self.comment_handler_idx,
+                match_limit_idx: self.match_limit_idx,
+                selector_idx,
+                ..Default::default()
+            },
+            None => SelectorHandlersLocator {
+                selector_idx,
+                ..self
+            },
+        }
+    }
+
+    /// Returns `true` if the locator has been produced from the `locator` by [`scoped_to`].
+    ///
+    /// [`scoped_to`]: #method.scoped_to
+    #[inline]
+    pub fn is_scoped_from(&self, locator: SelectorHandlersLocator) -> bool {
+        [
+            None,
+            Some(PseudoElementKind::Text),
+            Some(PseudoElementKind::Comment),
+        ]
+        .iter()
+        .any(|&p| *self == locator.scoped_to(self.selector_idx, p))
+    }
+}
+
+/// The number of the elements a selector can still match.
+#[cfg(feature = "element_handlers")]
+struct MatchLimit {
+    remaining: usize,
+    // NOTE: an element can be matched by multiple selectors in the selector list,
+    // so it's counted once all of its matches are collected.
+    matched: bool,
 }
 
 struct HandlerVecItem<H> {
@@ -46,12 +128,20 @@ impl<H> HandlerVec<H> {
         self.items.len()
     }
 
+    #[cfg(feature = "element_handlers")]
+    #[inline]
+    pub fn replace(&mut self, idx: usize, handler: H) {
+        self.items[idx].handler = handler;
+    }
+
+    #[cfg(feature = "element_handlers")]
     #[inline]
     pub fn inc_user_count(&mut self, idx: usize) {
         self.items[idx].user_count += 1;
         self.user_count += 1;
     }
 
+    #[cfg(feature = "element_handlers")]
     #[inline]
     pub fn dec_user_count(&mut self, idx: usize) {
         self.items[idx].user_count -= 1;
@@ -80,11 +170,11 @@ impl<H> HandlerVec<H> {
     #[inline]
     pub fn do_for_each_active_and_deactivate(
         &mut self,
-        mut cb: impl FnMut(&mut H) -> HandlerResult,
+        mut cb: impl FnMut(usize, &mut H) -> HandlerResult,
     ) -> HandlerResult {
-        for item in self.items.iter_mut() {
+        for (idx, item) in self.items.iter_mut().enumerate() {
             if item.user_count > 0 {
-                cb(&mut item.handler)?;
+                cb(idx, &mut item.handler)?;
                 self.user_count -= item.user_count;
                 item.user_count = 0;
             }
@@ -112,21 +202,147 @@ impl<H> HandlerVec<H> {
     }
 }
 
-#[derive(Default)]
-pub struct ContentHandlersDispatcher<'h> {
+/// A list of the items attached to the currently open elements.
+///
+/// Items are removed by the index returned on insertion. The index stays valid regardless
+/// of the order in which the elements are closed, as removed items leave an empty slot behind
+/// that is reclaimed only once all the slots after it are empty.
+struct ElementSlots<T> {
+    slots: Vec<Option<T>>,
+}
+
+impl<T> Default for ElementSlots<T> {
+    fn default() -> Self {
+        ElementSlots { slots: Vec::new() }
+    }
+}
+
+impl<T> ElementSlots<T> {
+    #[inline]
+    pub fn push(&mut self, item: T) -> usize {
+        self.slots.push(Some(item));
+        self.slots.len() - 1
+    }
+
+    #[cfg(feature = "element_handlers")]
+    #[inline]
+    pub fn remove(&mut self, idx: usize) {
+        self.slots[idx] = None;
+
+        while let Some(None) = self.slots.last() {
+            self.slots.pop();
+        }
+    }
+
+    /// Returns the number of the slots. Items pushed later are guaranteed to have an index
+    /// that is greater or equal to it.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.slots.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.slots.is_empty()
+    }
+
+    /// Iterates over the items in the slots within the `range`.
+    #[inline]
+    pub fn range_mut(&mut self, range: Range<usize>) -> impl Iterator<Item = &mut T> {
+        self.slots
+            .iter_mut()
+            .take(range.end)
+            .skip(range.start)
+            .flatten()
+    }
+}
+
+/// The state of the dispatcher that is put aside while a standalone fragment of the content is
+/// dispatched, see [`ContentHandlersDispatcher::enter_scope`].
+pub struct DispatcherScope {
+    text_accumulators_start: usize,
+    subtree_builders_start: usize,
+    matched_elements_with_removed_content: usize,
+    matched_elements_with_buffered_content: usize,
+    in_reprocessed_content: bool,
+}
+
+pub struct ContentHandlersDispatcher<'h, L: HandlersLayout = AnyHandlers> {
     doctype_handlers: HandlerVec<DoctypeHandler<'h>>,
     comment_handlers: HandlerVec<CommentHandler<'h>>,
     text_handlers: HandlerVec<TextHandler<'h>>,
     end_tag_handlers: HandlerVec<EndTagHandler<'h>>,
     element_handlers: HandlerVec<ElementHandler<'h>>,
+    // NOTE: the index of the first matched selector in the selector list of each element
+    // handler, so a handler shared by the selectors in the list can tell them apart.
+    element_handler_selectors: Vec<Option<usize>>,
     end_handlers: HandlerVec<EndHandler<'h>>,
+    #[cfg(feature = "element_handlers")]
+    match_limits: Vec<MatchLimit>,
+    #[cfg(feature = "element_handlers")]
+    matched_limits: Vec<usize>,
+    #[cfg(feature = "element_handlers")]
+    reached_match_limits: usize,
+    text_accumulators: ElementSlots<TextAccumulator>,
+    subtree_builders: ElementSlots<SubtreeBuilder>,
+    // NOTE: the text accumulators and the subtree builders of the elements that have started
+    // before the currently dispatched fragment of the content don't receive its tokens.
+    text_accumulators_start: usize,
+    subtree_builders_start: usize,
+    memory_limiter: SharedMemoryLimiter,
+    custom_void_elements: Rc<[String]>,
     next_element_can_have_content: bool,
     matched_elements_with_removed_content: usize,
+    // NOTE: buffered content is written to the output by the end tag handler of the element,
+    // but unlike removed content it's not considered removed by the handlers of its elements.
+    matched_elements_with_buffered_content: usize,
+    // NOTE: the elements of the reprocessed content are not seen by the deferred predicates
+    // lookahead, so they are marked for its gated handlers.
+    in_reprocessed_content: bool,
+    layout: PhantomData<L>,
 }
 
-impl<'h> ContentHandlersDispatcher<'h> {
+impl<'h, L: HandlersLayout> ContentHandlersDispatcher<'h, L> {
+    pub fn new(memory_limiter: SharedMemoryLimiter) -> Self {
+        ContentHandlersDispatcher {
+            doctype_handlers: HandlerVec::default(),
+            comment_handlers: HandlerVec::default(),
+            text_handlers: HandlerVec::default(),
+            end_tag_handlers: HandlerVec::default(),
+            element_handlers: HandlerVec::default(),
+            element_handler_selectors: Vec::default(),
+            end_handlers: HandlerVec::default(),
+            #[cfg(feature = "element_handlers")]
+            match_limits: Vec::default(),
+            #[cfg(feature = "element_handlers")]
+            matched_limits: Vec::default(),
+            #[cfg(feature = "element_handlers")]
+            reached_match_limits: 0,
+            text_accumulators: ElementSlots::default(),
+            subtree_builders: ElementSlots::default(),
+            text_accumulators_start: 0,
+            subtree_builders_start: 0,
+            memory_limiter,
+            custom_void_elements: Rc::default(),
+            next_element_can_have_content: false,
+            matched_elements_with_removed_content: 0,
+            matched_elements_with_buffered_content: 0,
+            in_reprocessed_content: false,
+            layout: PhantomData,
+        }
+    }
+
+    /// Sets the lowercase tag names of the elements that are treated as void in addition to
+    /// the standard void elements.
+    #[inline]
+    pub fn set_custom_void_elements(&mut self, custom_void_elements: Rc<[String]>) {
+        self.custom_void_elements = custom_void_elements;
+    }
+
     #[inline]
     pub fn add_document_content_handlers(&mut self, handlers: DocumentContentHandlers<'h>) {
+        debug_assert!(L::HAS_CONTENT_HANDLERS);
+
         if let Some(handler) = handlers.doctype {
             self.doctype_handlers.push(handler, true);
         }
@@ -144,14 +360,20 @@ impl<'h> ContentHandlersDispatcher<'h> {
         }
     }
 
+    #[cfg(feature = "element_handlers")]
     #[inline]
     pub fn add_selector_associated_handlers(
         &mut self,
         handlers: ElementContentHandlers<'h>,
     ) -> SelectorHandlersLocator {
+        debug_assert!(
+            L::HAS_CONTENT_HANDLERS || (handlers.comments.is_none() && handlers.text.is_none())
+        );
+
         SelectorHandlersLocator {
             element_handler_idx: handlers.element.map(|h| {
                 self.element_handlers.push(h, false);
+                self.element_handler_selectors.push(None);
                 self.element_handlers.len() - 1
             }),
             comment_handler_idx: handlers.comments.map(|h| {
@@ -162,6 +384,72 @@ impl<'h> ContentHandlersDispatcher<'h> {
                 self.text_handlers.push(h, false);
                 self.text_handlers.len() - 1
             }),
+            match_limit_idx: handlers.max_matches.map(|max_matches| {
+                if max_matches == 0 {
+                    self.reached_match_limits += 1;
+                }
+
+                self.match_limits.push(MatchLimit {
+                    remaining: max_matches,
+                    matched: false,
+                });
+                self.match_limits.len() - 1
+            }),
+            selector_idx: 0,
+        }
+    }
+
+    /// Counts the elements matched since the previous call against the match limits of
+    /// the selectors. Returns `true` if any of the limits has been reached.
+    #[cfg(feature = "element_handlers")]
+    pub fn count_matches(&mut self) -> bool {
+        let mut has_reached_limits = false;
+
+        for idx in self.matched_limits.drain(..) {
+            let limit = &mut self.match_limits[idx];
+
+            limit.matched = false;
+            limit.remaining -= 1;
+
+            if limit.remaining == 0 {
+                self.reached_match_limits += 1;
+                has_reached_limits = true;
+            }
+        }
+
+        has_reached_limits
+    }
+
+    #[cfg(feature = "element_handlers")]
+    #[inline]
+    pub fn has_reached_match_limits(&self) -> bool {
+        self.reached_match_limits > 0
+    }
+
+    /// Returns `true` if the selector with the handlers located by the `locator` has
+    /// matched as many elements as it's allowed to.
+    #[cfg(feature = "element_handlers")]
+    #[inline]
+    pub fn is_match_limit_reached(&self, locator: &SelectorHandlersLocator) -> bool {
+        locator
+            .match_limit_idx
+            .map_or(false, |idx| self.match_limits[idx].remaining == 0)
+    }
+
+    /// Replaces the handlers located by the `locator` with the ones that do nothing, so
+    /// the handlers are dropped, while the compiled selectors that refer to them remain valid.
+    #[cfg(feature = "element_handlers")]
+    pub fn remove_selector_associated_handlers(&mut self, locator: SelectorHandlersLocator) {
+        if let Some(idx) = locator.element_handler_idx {
+            self.element_handlers.replace(idx, Box::new(|_| Ok(())));
+        }
+
+        if let Some(idx) = locator.comment_handler_idx {
+            self.comment_handlers.replace(idx, Box::new(|_| Ok(())));
+        }
+
+        if let Some(idx) = locator.text_handler_idx {
+            self.text_handlers.replace(idx, Box::new(|_| Ok(())));
         }
     }
 
@@ -170,10 +458,68 @@ impl<'h> ContentHandlersDispatcher<'h> {
         self.matched_elements_with_removed_content > 0
     }
 
+    #[inline]
+    pub fn has_matched_elements_with_buffered_content(&self) -> bool {
+        self.matched_elements_with_buffered_content > 0
+    }
+
+    #[inline]
+    pub fn memory_limiter(&self) -> &SharedMemoryLimiter {
+        &self.memory_limiter
+    }
+
+    /// Starts dispatching a standalone fragment of the content, e.g. the content inserted by
+    /// the handlers. The fragment isn't removed along with the content of the elements that
+    /// are currently open, and it doesn't get into their text accumulators and buffered
+    /// subtrees. Returns the previous scope that should be restored with [`leave_scope`].
+    ///
+    /// [`leave_scope`]: #method.leave_scope
+    #[inline]
+    pub fn enter_scope(&mut self) -> DispatcherScope {
+        DispatcherScope {
+            text_accumulators_start: mem::replace(
+                &mut self.text_accumulators_start,
+                self.text_accumulators.len(),
+            ),
+            subtree_builders_start: mem::replace(
+                &mut self.subtree_builders_start,
+                self.subtree_builders.len(),
+            ),
+            matched_elements_with_removed_content: mem::take(
+                &mut self.matched_elements_with_removed_content,
+            ),
+            matched_elements_with_buffered_content: mem::take(
+                &mut self.matched_elements_with_buffered_content,
+            ),
+            in_reprocessed_content: mem::replace(&mut self.in_reprocessed_content, true),
+        }
+    }
+
+    #[inline]
+    pub fn leave_scope(&mut self, prev_scope: DispatcherScope) {
+        self.text_accumulators_start = prev_scope.text_accumulators_start;
+        self.subtree_builders_start = prev_scope.subtree_builders_start;
+        self.matched_elements_with_removed_content =
+            prev_scope.matched_elements_with_removed_content;
+        self.matched_elements_with_buffered_content =
+            prev_scope.matched_elements_with_buffered_content;
+        self.in_reprocessed_content = prev_scope.in_reprocessed_content;
+    }
+
+    #[cfg(feature = "element_handlers")]
     #[inline]
     pub fn start_matching(&mut self, match_info: MatchInfo<SelectorHandlersLocator>) {
         let locator = match_info.payload;
 
+        if let Some(idx) = locator.match_limit_idx {
+            let limit = &mut self.match_limits[idx];
+
+            if !limit.matched {
+                limit.matched = true;
+                self.matched_limits.push(idx);
+            }
+        }
+
         if match_info.with_content {
             if let Some(idx) = locator.comment_handler_idx {
                 self.comment_handlers.inc_user_count(idx);
@@ -185,12 +531,20 @@ impl<'h> ContentHandlersDispatcher<'h> {
         }
 
         if let Some(idx) = locator.element_handler_idx {
+            let selector_idx = &mut self.element_handler_selectors[idx];
+
+            *selector_idx = Some(match *selector_idx {
+                Some(current) => current.min(locator.selector_idx),
+                None => locator.selector_idx,
+            });
+
             self.element_handlers.inc_user_count(idx);
         }
 
         self.next_element_can_have_content = match_info.with_content;
     }
 
+    #[cfg(feature = "element_handlers")]
     #[inline]
     pub fn stop_matching(&mut self, elem_desc: ElementDescriptor) {
         for locator in elem_desc.matched_content_handlers {
@@ -207,11 +561,35 @@ impl<'h> ContentHandlersDispatcher<'h> {
             self.end_tag_handlers.inc_user_count(idx);
         }
 
+        if let Some(idx) = elem_desc.text_accumulator_idx {
+            self.text_accumulators.remove(idx);
+        }
+
+        // NOTE: dropping the builder finalizes the subtree for the end tag handler.
+        if let Some(idx) = elem_desc.subtree_builder_idx {
+            self.subtree_builders.remove(idx);
+            self.matched_elements_with_buffered_content -= 1;
+        }
+
         if elem_desc.remove_content {
             self.matched_elements_with_removed_content -= 1;
         }
     }
 
+    /// Same as [`stop_matching`], but for the elements that are left unclosed by a fragment of
+    /// the content, so their end tag handlers are dropped instead of being invoked.
+    ///
+    /// [`stop_matching`]: #method.stop_matching
+    #[cfg(feature = "element_handlers")]
+    #[inline]
+    pub fn discard_matching(&mut self, mut elem_desc: ElementDescriptor) {
+        if let Some(idx) = elem_desc.end_tag_handler_idx.take() {
+            self.end_tag_handlers.replace(idx, Box::new(|_| Ok(())));
+        }
+
+        self.stop_matching(elem_desc);
+    }
+
     pub fn handle_start_tag(
         &mut self,
         start_tag: &mut StartTag,
@@ -223,8 +601,16 @@ impl<'h> ContentHandlersDispatcher<'h> {
 
         let mut element = Element::new(start_tag, self.next_element_can_have_content);
 
+        #[cfg(feature = "element_handlers")]
+        element.set_reprocessed(self.in_reprocessed_content);
+
+        let selectors = &mut self.element_handler_selectors;
+
         self.element_handlers
-            .do_for_each_active_and_deactivate(|h| h(&mut element))?;
+            .do_for_each_active_and_deactivate(|idx, h| {
+                element.set_matched_selector_index(selectors[idx].take());
+                h(&mut element)
+            })?;
 
         if self.next_element_can_have_content {
             if let Some(elem_desc) = current_element_data {
@@ -233,6 +619,24 @@ impl<'h> ContentHandlersDispatcher<'h> {
                     self.matched_elements_with_removed_content += 1;
                 }
 
+                if let Some(accumulator) = element.text_accumulator() {
+                    elem_desc.text_accumulator_idx = Some(
+                        self.text_accumulators
+                            .push(accumulator.with_memory_limiter(Rc::clone(&self.memory_limiter))),
+                    );
+                }
+
+                if let Some(subtree) = element.buffered_subtree() {
+                    elem_desc.subtree_builder_idx =
+                        Some(self.subtree_builders.push(SubtreeBuilder::new(
+                            subtree,
+                            Rc::clone(&self.memory_limiter),
+                            Rc::clone(&self.custom_void_elements),
+                        )));
+
+                    self.matched_elements_with_buffered_content += 1;
+                }
+
                 if let Some(handler) = element.into_end_tag_handler() {
                     elem_desc.end_tag_handler_idx = Some(self.end_tag_handlers.len());
 
@@ -250,17 +654,72 @@ impl<'h> ContentHandlersDispatcher<'h> {
         current_element_data: Option<&mut ElementDescriptor>,
     ) -> HandlerResult {
         match token {
+            // NOTE: the dispatcher without content handlers receives doctypes and comments
+            // only for the buffered subtrees, so there is nothing to dispatch.
+            Token::Doctype(_) | Token::Comment(_) if !L::HAS_CONTENT_HANDLERS => Ok(()),
             Token::Doctype(doctype) => self.doctype_handlers.for_each_active(|h| h(doctype)),
             Token::StartTag(start_tag) => self.handle_start_tag(start_tag, current_element_data),
             Token::EndTag(end_tag) => self
                 .end_tag_handlers
                 .do_for_each_active_and_remove(|h| h(end_tag)),
+            Token::TextChunk(_) if !L::HAS_CONTENT_HANDLERS => Ok(()),
             Token::TextChunk(text) => self.text_handlers.for_each_active(|h| h(text)),
             Token::Comment(comment) => self.comment_handlers.for_each_active(|h| h(comment)),
         }
     }
 
+    /// Adds the text of the token to the active text accumulators.
+    #[inline]
+    pub fn add_token_to_text_accumulators(
+        &mut self,
+        token: &Token,
+    ) -> Result<(), MemoryLimitExceededError> {
+        if let Token::TextChunk(text) = token {
+            let range = self.text_accumulators_start..self.text_accumulators.len();
+
+            for accumulator in self.text_accumulators.range_mut(range) {
+                accumulator.push(text.as_str())?;
+            }
+        }
+
+        Ok(())
+    }
+
+    #[inline]
+    pub fn buffered_subtree_count(&self) -> usize {
+        self.subtree_builders.len()
+    }
+
+    /// Adds the token to the innermost of the first `count` buffered subtrees. Subtrees that
+    /// were started by the token itself are not affected, and the outer subtrees receive
+    /// the content of the inner ones serialized by the end tag handlers of their elements.
+    ///
+    /// Tokens of the content that is removed by the handlers are not added, since they are
+    /// not written to the output.
+    #[inline]
+    pub fn add_token_to_buffered_subtrees(
+        &mut self,
+        token: &Token,
+        count: usize,
+        in_removed_content: bool,
+    ) -> Result<(), MemoryLimitExceededError> {
+        if in_removed_content {
+            return Ok(());
+        }
+
+        let range = self.subtree_builders_start..count;
+
+        match self.subtree_builders.range_mut(range).last() {
+            Some(builder) => builder.add_token(token),
+            None => Ok(()),
+        }
+    }
+
     pub fn handle_end(&mut self, document_end: &mut DocumentEnd) -> HandlerResult {
+        if !L::HAS_CONTENT_HANDLERS {
+            return Ok(());
+        }
+
         self.end_handlers
             .do_for_each_active_and_remove(|h| h(document_end))
     }
@@ -269,18 +728,31 @@ impl<'h> ContentHandlersDispatcher<'h> {
     pub fn get_token_capture_flags(&self) -> TokenCaptureFlags {
         let mut flags = TokenCaptureFlags::empty();
 
-        if self.doctype_handlers.has_active() {
-            flags |= TokenCaptureFlags::DOCTYPES;
-        }
+        if L::HAS_CONTENT_HANDLERS {
+            if self.doctype_handlers.has_active() {
+                flags |= TokenCaptureFlags::DOCTYPES;
+            }
 
-        if self.comment_handlers.has_active() {
-            flags |= TokenCaptureFlags::COMMENTS;
+            if self.comment_handlers.has_active() {
+                flags |= TokenCaptureFlags::COMMENTS;
+            }
+
+            if self.text_handlers.has_active() {
+                flags |= TokenCaptureFlags::TEXT;
+            }
         }
 
-        if self.text_handlers.has_active() {
+        if !self.text_accumulators.is_empty() {
             flags |= TokenCaptureFlags::TEXT;
         }
 
+        if !self.subtree_builders.is_empty() {
+            flags |= TokenCaptureFlags::TEXT
+                | TokenCaptureFlags::COMMENTS
+                | TokenCaptureFlags::NEXT_START_TAG
+                | TokenCaptureFlags::NEXT_END_TAG;
+        }
+
         if self.end_tag_handlers.has_active() {
             flags |= TokenCaptureFlags::NEXT_END_TAG;
         }
@@ -0,0 +1,335 @@
+use super::settings::{ElementContentHandlers, Settings};
+use super::{discard_output, try_encoding_from_str, EncodingError, HtmlRewriter, RewritingError};
+use std::borrow::Cow;
+use std::ops::Range;
+
+// NOTE: attributes that contain URLs and the elements they contain URLs for, as defined by
+// the HTML Living Standard.
+const URL_ATTRIBUTES: &[(&str, &[&str])] = &[
+    ("href", &["a", "area", "base", "link"]),
+    (
+        "src",
+        &[
+            "audio", "embed", "frame", "iframe", "img", "input", "script", "source", "track",
+            "video",
+        ],
+    ),
+    ("srcset", &["img", "source"]),
+    ("poster", &["video"]),
+    ("action", &["form"]),
+    ("formaction", &["button", "input"]),
+    ("data", &["object"]),
+    ("cite", &["blockquote", "del", "ins", "q"]),
+    ("longdesc", &["frame", "iframe", "img"]),
+    ("manifest", &["html"]),
+    ("background", &["body"]),
+];
+
+/// A URL found in the document by the [`LinkAuditor`].
+///
+/// [`LinkAuditor`]: struct.LinkAuditor.html
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AuditedUrl {
+    /// The name of the element that contains the URL.
+    pub tag_name: String,
+    /// The name of the attribute that contains the URL.
+    pub attribute_name: String,
+    /// The URL as it's specified in the document.
+    pub url: String,
+    /// The range of the input bytes occupied by the URL.
+    pub byte_range: Range<usize>,
+}
+
+fn is_url_attribute(tag_name: &str, attribute_name: &str) -> bool {
+    URL_ATTRIBUTES
+        .iter()
+        .any(|&(name, tags)| name == attribute_name && tags.contains(&tag_name))
+}
+
+fn url_attributes_selector() -> String {
+    URL_ATTRIBUTES
+        .iter()
+        .flat_map(|&(name, tags)| tags.iter().map(move |tag| format!("{}[{}]", tag, name)))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Returns ranges of the URLs in the value of the [`srcset`] attribute.
+///
+/// [`srcset`]: https://html.spec.whatwg.org/multipage/images.html#srcset-attributes
+fn srcset_urls(srcset: &str) -> Vec<Range<usize>> {
+    let bytes = srcset.as_bytes();
+    let mut urls = Vec::new();
+    let mut pos = 0;
+
+    loop {
+        while pos < bytes.len() && (bytes[pos].is_ascii_whitespace() || bytes[pos] == b',') {
+            pos += 1;
+        }
+
+        if pos == bytes.len() {
+            return urls;
+        }
+
+        let start = pos;
+
+        while pos < bytes.len() && !bytes[pos].is_ascii_whitespace() {
+            pos += 1;
+        }
+
+        let mut end = pos;
+
+        // NOTE: trailing commas of the URL separate it from the next image candidate. The URL
+        // can't consist of commas only, as we've skipped them above.
+        while bytes[end - 1] == b',' {
+            end -= 1;
+        }
+
+        urls.push(start..end);
+
+        if end == pos {
+            // NOTE: skip descriptors, they can contain commas in parentheses.
+            let mut depth = 0usize;
+
+            while pos < bytes.len() {
+                match bytes[pos] {
+                    b'(' => depth += 1,
+                    b')' => depth = depth.saturating_sub(1),
+                    b',' if depth == 0 => break,
+                    _ => (),
+                }
+
+                pos += 1;
+            }
+        }
+    }
+}
+
+/// A parse-only driver that collects URLs specified in the attributes of the document, e.g.
+/// `<a href>`, `<img src>` or `<img srcset>`.
+///
+/// Each found URL is reported to the provided sink along with the element and the attribute it
+/// was found in and the range of the input bytes it occupies. The document is not serialized,
+/// so the auditor is considerably cheaper than a rewriter with a dummy output sink.
+///
+/// # Example
+/// ```
+/// use lol_html::LinkAuditor;
+///
+/// let mut urls = vec![];
+///
+/// {
+///     let mut auditor = LinkAuditor::try_new("utf-8", |url| urls.push(url)).unwrap();
+///
+///     auditor.write(br#"<a href="/foo">Foo</a><img srcset="/a.png 1x, "#).unwrap();
+///     auditor.write(br#"/b.png 2x">"#).unwrap();
+///     auditor.end().unwrap();
+/// }
+///
+/// let urls: Vec<_> = urls.iter().map(|u| (u.url.as_str(), u.byte_range.clone())).collect();
+///
+/// assert_eq!(urls, [("/foo", 9..13), ("/a.png", 35..41), ("/b.png", 46..52)]);
+/// ```
+pub struct LinkAuditor<'h> {
+    rewriter: HtmlRewriter<'h, fn(&[u8])>,
+}
+
+impl<'h> LinkAuditor<'h> {
+    /// Constructs a new auditor for the document in the `encoding` that reports found URLs to
+    /// the `url_sink`.
+    pub fn try_new(
+        encoding: &str,
+        mut url_sink: impl FnMut(AuditedUrl) + 'h,
+    ) -> Result<Self, EncodingError> {
+        let document_encoding = try_encoding_from_str(encoding)?;
+
+        // NOTE: never panics as the selector is constructed from the predefined valid parts.
+        let selector = url_attributes_selector().parse().unwrap();
+
+        let handlers = ElementContentHandlers::default().element(move |el| {
+            let tag_name = el.tag_name();
+
+            for attr in el.attributes() {
+                let attribute_name = attr.name();
+
+                if !is_url_attribute(tag_name, attribute_name) {
+                    continue;
+                }
+
+                let byte_range = match attr.value_byte_range() {
+                    Some(range) => range,
+                    None => continue,
+                };
+
+                let value = attr.value();
+
+                if attribute_name == "srcset" {
+                    for url in srcset_urls(value) {
+                        // NOTE: URLs are located in the decoded value, so we need to encode
+                        // the preceding text to get the offsets in the input.
+                        let start = byte_range.start
+                            + document_encoding.encode(&value[..url.start]).0.len();
+
+                        let end = start + document_encoding.encode(&value[url.clone()]).0.len();
+
+                        url_sink(AuditedUrl {
+                            tag_name: tag_name.to_string(),
+                            attribute_name: attribute_name.to_string(),
+                            url: value[url].to_string(),
+                            byte_range: start..end,
+                        });
+                    }
+                } else {
+                    url_sink(AuditedUrl {
+                        tag_name: tag_name.to_string(),
+                        attribute_name: attribute_name.to_string(),
+                        url: value.to_string(),
+                        byte_range,
+                    });
+                }
+            }
+
+            Ok(())
+        });
+
+        let rewriter = HtmlRewriter::try_new_with_output(
+            Settings {
+                element_content_handlers: vec![(Cow::Owned(selector), handlers)],
+                encoding,
+                ..Settings::default()
+            },
+            discard_output as fn(&[u8]),
+            false,
+        )?;
+
+        Ok(LinkAuditor { rewriter })
+    }
+
+    /// Writes a chunk of input data to the auditor.
+    ///
+    /// # Panics
+    /// Under the same conditions as [`HtmlRewriter::write`].
+    ///
+    /// [`HtmlRewriter::write`]: struct.HtmlRewriter.html#method.write
+    #[inline]
+    pub fn write(&mut self, data: &[u8]) -> Result<(), RewritingError> {
+        self.rewriter.write(data)
+    }
+
+    /// Finalizes the audit.
+    ///
+    /// Should be called once the last chunk of the input is written.
+    #[inline]
+    pub fn end(mut self) -> Result<(), RewritingError> {
+        self.rewriter.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::Output;
+    use encoding_rs::{Encoding, WINDOWS_1251};
+
+    fn audit(html: &[u8], encoding: &'static Encoding) -> Vec<AuditedUrl> {
+        let mut urls = vec![];
+
+        {
+            let mut auditor = LinkAuditor::try_new(encoding.name(), |url| urls.push(url)).unwrap();
+
+            for chunk in html.chunks(3) {
+                auditor.write(chunk).unwrap();
+            }
+
+            auditor.end().unwrap();
+        }
+
+        urls
+    }
+
+    fn url(
+        tag_name: &str,
+        attribute_name: &str,
+        url: &str,
+        byte_range: Range<usize>,
+    ) -> AuditedUrl {
+        AuditedUrl {
+            tag_name: tag_name.into(),
+            attribute_name: attribute_name.into(),
+            url: url.into(),
+            byte_range,
+        }
+    }
+
+    #[test]
+    fn srcset_parsing() {
+        let srcset = "a.png, b.png 2x,c.png,, d(1,2).png 100w, e.png (1, 2) 3x , f.png,";
+        let urls: Vec<_> = srcset_urls(srcset)
+            .into_iter()
+            .map(|r| &srcset[r])
+            .collect();
+
+        assert_eq!(
+            urls,
+            ["a.png", "b.png", "c.png", "d(1,2).png", "e.png", "f.png"]
+        );
+
+        assert!(srcset_urls(" , ").is_empty());
+    }
+
+    #[test]
+    fn url_attributes() {
+        let html = concat!(
+            r#"<a href="/a" title="/t">"#,
+            r#"<div href="/div" src='/div'></div>"#,
+            r#"<form action=/form><button formaction="/button"></button></form>"#,
+            r#"<img src="/img" srcset=" /1x.png 1x,/2x.png 2x">"#,
+        );
+
+        assert_eq!(
+            audit(html.as_bytes(), encoding_rs::UTF_8),
+            [
+                url("a", "href", "/a", 9..11),
+                url("form", "action", "/form", 71..76),
+                url("button", "formaction", "/button", 97..104),
+                url("img", "src", "/img", 132..136),
+                url("img", "srcset", "/1x.png", 147..154),
+                url("img", "srcset", "/2x.png", 158..165),
+            ]
+        );
+    }
+
+    #[test]
+    fn non_utf8_encoding() {
+        let (html, _, _) = WINDOWS_1251.encode(r#"<img srcset="/я.png 1x, /b.png 2x">"#);
+
+        assert_eq!(
+            audit(&html, WINDOWS_1251),
+            [
+                url("img", "srcset", "/я.png", 13..19),
+                url("img", "srcset", "/b.png", 24..30),
+            ]
+        );
+    }
+
+    #[test]
+    fn no_output() {
+        let mut output = Output::new(encoding_rs::UTF_8);
+
+        {
+            let mut rewriter = HtmlRewriter::try_new_with_output(
+                Settings::default(),
+                |c: &[u8]| output.push(c),
+                false,
+            )
+            .unwrap();
+
+            rewriter.write(b"<div>Hello</div>").unwrap();
+            rewriter.end().unwrap();
+        }
+
+        let output: String = output.into();
+
+        assert_eq!(output, "");
+    }
+}
@@ -0,0 +1,377 @@
+use super::settings::*;
+#[cfg(feature = "element_handlers")]
+use crate::rewritable_units::Element;
+use crate::rewritable_units::{Comment, DocumentEnd, TextChunk};
+#[cfg(feature = "element_handlers")]
+use std::cmp::Reverse;
+
+macro_rules! skip_removed {
+    ($handler:expr, $Unit:ident, $Handler:ty) => {
+        $handler.map(|mut handler| {
+            Box::new(move |unit: &mut $Unit| {
+                if unit.removed() {
+                    Ok(())
+                } else {
+                    handler(unit)
+                }
+            }) as $Handler
+        })
+    };
+}
+
+/// Merges the content handlers of independently built [`Settings`] into a single
+/// [`HtmlRewriter`], so they are run in one pass over the document.
+///
+/// This is not a chain of rewriters: the selectors of all the merged settings are matched by
+/// a single rewriter against the original markup. For example, an element whose attribute is
+/// modified by the handlers of one of the settings is matched by the selectors of the settings
+/// merged after them for the original value of the attribute rather than the new one, and
+/// content inserted by the handlers (e.g. with [`Element::append`]) is not matched by them at
+/// all. The handlers that depend on the modifications made by the preceding handlers should
+/// check the content instead. Settings whose selectors need to observe the rewritten markup
+/// require rewriters chained by piping the output of one into the input of another.
+///
+/// For each element, text chunk or comment, the handlers of the settings are invoked after
+/// the handlers of the settings merged before them, and they observe the modifications made by
+/// those handlers (e.g. updated attributes or tag names) through the API of the content. Content
+/// removed by the preceding handlers is not passed to the handlers of the subsequent settings.
+/// The [priority] of the handlers only orders the handlers of the same settings. Document-level
+/// text and comment handlers of all the settings are invoked after element content handlers.
+///
+/// Token transformers of all the settings are invoked before any of the content handlers, in the
+/// order the settings are merged. Only the [`encoding`], [`memory_settings`], [`strict`],
+/// [`buffer_script_and_style_text`], [`xhtml_output`] and [`observability_hooks`] of the first
+/// settings are used by the resulting rewriter.
+///
+/// # Example
+/// ```
+/// use lol_html::{element, HtmlRewriter, MergedHandlers, Settings};
+///
+/// let mut output = vec![];
+///
+/// let merged = MergedHandlers::new(Settings {
+///     element_content_handlers: vec![
+///         element!("a[href^='http:']", |el| {
+///             let href = el.get_attribute("href").unwrap().replace("http:", "https:");
+///
+///             el.set_attribute("href", &href)?;
+///
+///             Ok(())
+///         })
+///     ],
+///     ..Settings::default()
+/// })
+/// .merge(Settings {
+///     element_content_handlers: vec![
+///         // NOTE: `a[href^='https:']` would match the original `https:` links only.
+///         element!("a[href]", |el| {
+///             if el.get_attribute("href").unwrap().starts_with("https:") {
+///                 el.set_attribute("rel", "noopener")?;
+///             }
+///
+///             Ok(())
+///         })
+///     ],
+///     ..Settings::default()
+/// });
+///
+/// {
+///     let mut rewriter = HtmlRewriter::try_new(
+///         merged.into_settings(),
+///         |c: &[u8]| output.extend_from_slice(c)
+///     ).unwrap();
+///
+///     rewriter.write(br#"<a href="http://example.com"><a href="/foo">"#).unwrap();
+///     rewriter.end().unwrap();
+/// }
+///
+/// assert_eq!(
+///     String::from_utf8(output).unwrap(),
+///     r#"<a href="https://example.com" rel="noopener"><a href="/foo">"#
+/// );
+/// ```
+///
+/// [`Settings`]: struct.Settings.html
+/// [`HtmlRewriter`]: struct.HtmlRewriter.html
+/// [priority]: struct.ElementContentHandlers.html#method.priority
+/// [`Element::append`]: html_content/struct.Element.html#method.append
+/// [`encoding`]: struct.Settings.html#structfield.encoding
+/// [`memory_settings`]: struct.Settings.html#structfield.memory_settings
+/// [`strict`]: struct.Settings.html#structfield.strict
+/// [`buffer_script_and_style_text`]: struct.Settings.html#structfield.buffer_script_and_style_text
+/// [`xhtml_output`]: struct.Settings.html#structfield.xhtml_output
+/// [`observability_hooks`]: struct.Settings.html#structfield.observability_hooks
+pub struct MergedHandlers<'h, 's> {
+    settings: Settings<'h, 's>,
+    end_handlers: Vec<EndHandler<'h>>,
+}
+
+impl<'h, 's> MergedHandlers<'h, 's> {
+    /// Creates the merged handlers with the given first settings.
+    pub fn new(mut first: Settings<'h, 's>) -> Self {
+        let end_handlers = take_end_handlers(&mut first.document_content_handlers);
+
+        #[cfg(feature = "element_handlers")]
+        scope_priorities(&mut first.element_content_handlers);
+
+        MergedHandlers {
+            settings: first,
+            end_handlers,
+        }
+    }
+
+    /// Merges the handlers of the `settings`, which are invoked after the handlers merged so far.
+    pub fn merge(mut self, settings: Settings<'h, 's>) -> Self {
+        let Settings {
+            #[cfg(feature = "element_handlers")]
+            mut element_content_handlers,
+            mut document_content_handlers,
+            token_transformers,
+            ..
+        } = settings;
+
+        self.settings.token_transformers.extend(token_transformers);
+
+        self.end_handlers
+            .extend(take_end_handlers(&mut document_content_handlers));
+
+        #[cfg(feature = "element_handlers")]
+        scope_priorities(&mut element_content_handlers);
+
+        #[cfg(feature = "element_handlers")]
+        for (selector, handlers) in element_content_handlers {
+            self.settings.element_content_handlers.push((
+                selector,
+                ElementContentHandlers {
+                    element: skip_removed!(handlers.element, Element, ElementHandler<'h>),
+                    comments: skip_removed!(handlers.comments, Comment, CommentHandler<'h>),
+                    text: skip_removed!(handlers.text, TextChunk, TextHandler<'h>),
+                    priority: handlers.priority,
+                    max_depth: handlers.max_depth,
+                    max_matches: handlers.max_matches,
+                },
+            ));
+        }
+
+        for handlers in document_content_handlers {
+            self.settings
+                .document_content_handlers
+                .push(DocumentContentHandlers {
+                    doctype: handlers.doctype,
+                    comments: skip_removed!(handlers.comments, Comment, CommentHandler<'h>),
+                    text: skip_removed!(handlers.text, TextChunk, TextHandler<'h>),
+                    end: None,
+                });
+        }
+
+        self
+    }
+
+    /// Produces the settings for the [`HtmlRewriter`] that runs all the merged handlers.
+    ///
+    /// [`HtmlRewriter`]: struct.HtmlRewriter.html
+    pub fn into_settings(mut self) -> Settings<'h, 's> {
+        let end_handlers = self.end_handlers;
+
+        if !end_handlers.is_empty() {
+            // NOTE: the rewriter invokes document end handlers in the reverse order of their
+            // registration, so we merge them into a single handler to preserve the merge order.
+            self.settings
+                .document_content_handlers
+                .push(DocumentContentHandlers {
+                    end: Some(Box::new(move |end: &mut DocumentEnd| {
+                        for handler in end_handlers {
+                            handler(end)?;
+                        }
+
+                        Ok(())
+                    })),
+                    ..DocumentContentHandlers::default()
+                });
+        }
+
+        self.settings
+    }
+}
+
+/// Orders the element content handlers of the settings by their priority and resets the priority,
+/// so the rewriter doesn't reorder the handlers of the different settings.
+#[cfg(feature = "element_handlers")]
+fn scope_priorities<S>(handlers: &mut [(S, ElementContentHandlers)]) {
+    // NOTE: the sort is stable, the same way as the one in the rewriter.
+    handlers.sort_by_key(|(_, handlers)| Reverse(handlers.priority));
+    handlers
+        .iter_mut()
+        .for_each(|(_, handlers)| handlers.priority = 0);
+}
+
+fn take_end_handlers<'h>(handlers: &mut [DocumentContentHandlers<'h>]) -> Vec<EndHandler<'h>> {
+    handlers.iter_mut().filter_map(|h| h.end.take()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::html_content::ContentType;
+    use crate::*;
+
+    fn rewrite(html: &str, merged: MergedHandlers) -> String {
+        let mut output = vec![];
+
+        {
+            let mut rewriter = HtmlRewriter::try_new(merged.into_settings(), |c: &[u8]| {
+                output.extend_from_slice(c)
+            })
+            .unwrap();
+
+            rewriter.write(html.as_bytes()).unwrap();
+            rewriter.end().unwrap();
+        }
+
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn merge_order() {
+        let merged = MergedHandlers::new(Settings {
+            element_content_handlers: vec![element!("div", |el| {
+                el.set_tag_name("section")?;
+                el.prepend("1", ContentType::Text);
+                Ok(())
+            })],
+            ..Settings::default()
+        })
+        .merge(Settings {
+            element_content_handlers: vec![element!("div", |el| {
+                assert_eq!(el.tag_name(), "section");
+                el.prepend("2", ContentType::Text);
+                Ok(())
+            })],
+            ..Settings::default()
+        });
+
+        assert_eq!(
+            rewrite("<div>foo</div>", merged),
+            "<section>21foo</section>"
+        );
+    }
+
+    #[test]
+    fn priority() {
+        let merged = MergedHandlers::new(Settings {
+            element_content_handlers: vec![
+                element!("div", |el| {
+                    el.append("1", ContentType::Text);
+                    Ok(())
+                }),
+                element!("div", priority = -1, |el| {
+                    el.append("2", ContentType::Text);
+                    Ok(())
+                }),
+            ],
+            ..Settings::default()
+        })
+        .merge(Settings {
+            element_content_handlers: vec![
+                element!("div", |el| {
+                    el.append("4", ContentType::Text);
+                    Ok(())
+                }),
+                element!("div", priority = 1, |el| {
+                    el.append("3", ContentType::Text);
+                    Ok(())
+                }),
+            ],
+            ..Settings::default()
+        });
+
+        assert_eq!(rewrite("<div></div>", merged), "<div>1234</div>");
+    }
+
+    #[test]
+    fn selectors_match_original_markup() {
+        let merged = MergedHandlers::new(Settings {
+            element_content_handlers: vec![element!("a", |el| {
+                el.set_attribute("href", "https://example.com")?;
+                Ok(())
+            })],
+            ..Settings::default()
+        })
+        .merge(Settings {
+            element_content_handlers: vec![
+                element!("a[href^='https:']", |el| {
+                    el.set_attribute("secure", "")?;
+                    Ok(())
+                }),
+                element!("a[href^='http:']", |el| {
+                    assert_eq!(el.get_attribute("href").unwrap(), "https://example.com");
+                    el.set_attribute("rel", "noopener")?;
+                    Ok(())
+                }),
+            ],
+            ..Settings::default()
+        });
+
+        assert_eq!(
+            rewrite(r#"<a href="http://example.com">"#, merged),
+            r#"<a href="https://example.com" rel="noopener">"#
+        );
+    }
+
+    #[test]
+    fn removed_content() {
+        let merged = MergedHandlers::new(Settings {
+            element_content_handlers: vec![
+                element!("b", |el| {
+                    el.remove();
+                    Ok(())
+                }),
+                comments!("div", |c| {
+                    c.remove();
+                    Ok(())
+                }),
+            ],
+            ..Settings::default()
+        })
+        .merge(Settings {
+            element_content_handlers: vec![
+                element!("*", |el| {
+                    assert_ne!(el.tag_name(), "b");
+                    el.set_attribute("stage", "2")?;
+                    Ok(())
+                }),
+                comments!("div", |_| panic!("Removed comment passed to the handler")),
+            ],
+            document_content_handlers: vec![doc_comments!(|_| {
+                panic!("Removed comment passed to the handler")
+            })],
+            ..Settings::default()
+        });
+
+        assert_eq!(
+            rewrite("<div><b>foo</b><!-- bar --><i>baz</i></div>", merged),
+            r#"<div stage="2"><i stage="2">baz</i></div>"#
+        );
+    }
+
+    #[test]
+    fn document_end_order() {
+        let merged = MergedHandlers::new(Settings {
+            document_content_handlers: vec![end!(|end| {
+                end.append("1", ContentType::Text);
+                Ok(())
+            })],
+            ..Settings::default()
+        })
+        .merge(Settings::default())
+        .merge(Settings {
+            document_content_handlers: vec![end!(|end| {
+                end.append("2", ContentType::Text);
+                Ok(())
+            })],
+            ..Settings::default()
+        });
+
+        assert_eq!(rewrite("foo", merged), "foo12");
+    }
+}
@@ -0,0 +1,430 @@
+use super::settings::{ElementContentHandlers, HandlerResult, Settings};
+use super::{HtmlRewriter, RewritingError};
+use crate::rewritable_units::Element;
+use crate::selectors_vm::Selector;
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+const HTML_NAMESPACE_URI: &str = "http://www.w3.org/1999/xhtml";
+
+// NOTE: text of the title that exceeds the limit is dropped, so we don't buffer
+// arbitrary amounts of text if the title element is not closed properly.
+const MAX_TITLE_LEN: usize = 4096;
+
+const ICON_RELS: &[&str] = &[
+    "icon",
+    "apple-touch-icon",
+    "apple-touch-icon-precomposed",
+    "mask-icon",
+];
+
+/// A link to a related resource extracted from a `<link>` element.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MetadataLink {
+    /// The value of the `rel` attribute.
+    pub rel: String,
+    /// The value of the `href` attribute.
+    pub href: String,
+    /// The value of the `hreflang` attribute.
+    pub hreflang: Option<String>,
+    /// The value of the `type` attribute.
+    pub media_type: Option<String>,
+    /// The value of the `sizes` attribute.
+    pub sizes: Option<String>,
+}
+
+impl MetadataLink {
+    fn from_element(rel: String, href: String, element: &Element) -> Self {
+        MetadataLink {
+            rel,
+            href,
+            hreflang: element.get_attribute("hreflang"),
+            media_type: element.get_attribute("type"),
+            sizes: element.get_attribute("sizes"),
+        }
+    }
+}
+
+/// Metadata of the document collected by the [`MetadataCollector`].
+///
+/// Values are provided as they are specified in the document, i.e. character references are
+/// not decoded and relative URLs are not resolved against the document's base URL.
+///
+/// [`MetadataCollector`]: struct.MetadataCollector.html
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Metadata {
+    /// Text of the first `<title>` element with whitespace stripped and collapsed.
+    pub title: Option<String>,
+    /// Name and content pairs of the `<meta name="..." content="...">` elements, e.g.
+    /// `twitter:card` or `description`, in the document order.
+    pub names: Vec<(String, String)>,
+    /// Property and content pairs of the `<meta property="..." content="...">` elements, e.g.
+    /// OpenGraph's `og:title`, in the document order.
+    pub properties: Vec<(String, String)>,
+    /// The `href` of the first `<link rel="canonical">` element.
+    pub canonical: Option<String>,
+    /// Links of the `<link rel="alternate">` elements, e.g. translations or feeds.
+    pub alternates: Vec<MetadataLink>,
+    /// Links of the `<link>` elements that specify icons of the document, e.g.
+    /// `rel="icon"` or `rel="apple-touch-icon"`.
+    pub icons: Vec<MetadataLink>,
+}
+
+impl Metadata {
+    /// Returns the content of the first `<meta>` element with the `name`.
+    ///
+    /// Names are compared ASCII case-insensitively.
+    pub fn name(&self, name: &str) -> Option<&str> {
+        find_content(&self.names, name)
+    }
+
+    /// Returns the content of the first `<meta>` element with the `property`.
+    ///
+    /// Properties are compared ASCII case-insensitively.
+    pub fn property(&self, property: &str) -> Option<&str> {
+        find_content(&self.properties, property)
+    }
+}
+
+fn find_content<'m>(pairs: &'m [(String, String)], key: &str) -> Option<&'m str> {
+    pairs
+        .iter()
+        .find(|(k, _)| k.eq_ignore_ascii_case(key))
+        .map(|(_, content)| content.as_str())
+}
+
+fn element_handler<'h>(
+    selector: &str,
+    handler: impl FnMut(&mut Element) -> HandlerResult + 'h,
+) -> (Cow<'static, Selector>, ElementContentHandlers<'h>) {
+    // NOTE: never panics because we use only the predefined valid selectors.
+    let selector = selector.parse().unwrap();
+
+    (
+        Cow::Owned(selector),
+        ElementContentHandlers::default().element(handler),
+    )
+}
+
+fn normalize_whitespace(text: &str) -> String {
+    text.split_ascii_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+#[derive(Default)]
+struct CollectorState {
+    metadata: Metadata,
+    title_found: bool,
+}
+
+impl CollectorState {
+    fn add_meta(&mut self, element: &Element) {
+        let content = match element.get_attribute("content") {
+            Some(content) => content,
+            None => return,
+        };
+
+        if let Some(name) = element.get_attribute("name") {
+            self.metadata.names.push((name, content.clone()));
+        }
+
+        if let Some(property) = element.get_attribute("property") {
+            self.metadata.properties.push((property, content));
+        }
+    }
+
+    fn add_link(&mut self, element: &Element) {
+        let (rel, href) = match (element.get_attribute("rel"), element.get_attribute("href")) {
+            (Some(rel), Some(href)) => (rel, href),
+            _ => return,
+        };
+
+        let mut is_alternate = false;
+        let mut is_icon = false;
+
+        for keyword in rel.split_ascii_whitespace() {
+            if keyword.eq_ignore_ascii_case("canonical") {
+                if self.metadata.canonical.is_none() {
+                    self.metadata.canonical = Some(href.clone());
+                }
+            } else if keyword.eq_ignore_ascii_case("alternate") {
+                is_alternate = true;
+            } else if ICON_RELS.iter().any(|r| keyword.eq_ignore_ascii_case(r)) {
+                is_icon = true;
+            }
+        }
+
+        if is_alternate {
+            let link = MetadataLink::from_element(rel.clone(), href.clone(), element);
+
+            self.metadata.alternates.push(link);
+        }
+
+        if is_icon {
+            let link = MetadataLink::from_element(rel, href, element);
+
+            self.metadata.icons.push(link);
+        }
+    }
+}
+
+/// Collects metadata of the document, such as the title, OpenGraph and Twitter `<meta>` tags,
+/// canonical and alternate links and favicons, during a rewriting pass.
+///
+/// Handlers returned by [`handlers`] should be added to the [`element_content_handlers`] of
+/// the rewriter. The collected [`Metadata`] can be obtained with [`metadata`] once the rewriter
+/// has processed the document. For documents that don't need to be rewritten, the
+/// [`extract_metadata`] function can be used instead.
+///
+/// # Example
+/// ```
+/// use lol_html::{rewrite_str, MetadataCollector, RewriteStrSettings};
+///
+/// let collector = MetadataCollector::new();
+///
+/// rewrite_str(
+///     r#"<head>
+///         <title>Hello world</title>
+///         <meta property="og:title" content="Hello">
+///         <link rel="canonical" href="https://example.com/hello">
+///     </head>"#,
+///     RewriteStrSettings {
+///         element_content_handlers: collector.handlers(),
+///         ..RewriteStrSettings::default()
+///     }
+/// ).unwrap();
+///
+/// let metadata = collector.metadata();
+///
+/// assert_eq!(metadata.title.as_deref(), Some("Hello world"));
+/// assert_eq!(metadata.property("og:title"), Some("Hello"));
+/// assert_eq!(metadata.canonical.as_deref(), Some("https://example.com/hello"));
+/// ```
+///
+/// [`handlers`]: #method.handlers
+/// [`metadata`]: #method.metadata
+/// [`element_content_handlers`]: struct.Settings.html#structfield.element_content_handlers
+/// [`Metadata`]: struct.Metadata.html
+/// [`extract_metadata`]: fn.extract_metadata.html
+#[derive(Default)]
+pub struct MetadataCollector {
+    state: Rc<RefCell<CollectorState>>,
+}
+
+impl MetadataCollector {
+    /// Creates a new collector.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the content handlers that collect metadata into the collector.
+    pub fn handlers<'h>(&self) -> Vec<(Cow<'static, Selector>, ElementContentHandlers<'h>)> {
+        let title_state = Rc::clone(&self.state);
+        let meta_state = Rc::clone(&self.state);
+        let link_state = Rc::clone(&self.state);
+
+        vec![
+            element_handler("title", move |el| {
+                // NOTE: SVG documents have their own title elements.
+                if el.namespace_uri() != HTML_NAMESPACE_URI {
+                    return Ok(());
+                }
+
+                let mut state = title_state.borrow_mut();
+
+                if !state.title_found {
+                    let state = Rc::clone(&title_state);
+
+                    el.accumulate_text(MAX_TITLE_LEN, move |text| {
+                        state.borrow_mut().metadata.title = Some(normalize_whitespace(text));
+
+                        Ok(())
+                    });
+                }
+
+                state.title_found = true;
+
+                Ok(())
+            }),
+            element_handler("meta", move |el| {
+                meta_state.borrow_mut().add_meta(el);
+
+                Ok(())
+            }),
+            element_handler("link", move |el| {
+                link_state.borrow_mut().add_link(el);
+
+                Ok(())
+            }),
+        ]
+    }
+
+    /// Returns the metadata collected so far.
+    pub fn metadata(&self) -> Metadata {
+        self.state.borrow().metadata.clone()
+    }
+}
+
+/// Extracts metadata of the given `html` string without rewriting it.
+///
+/// # Example
+/// ```
+/// use lol_html::extract_metadata;
+///
+/// let metadata = extract_metadata(
+///     r#"<meta name="twitter:card" content="summary"><link rel="icon" href="/favicon.ico">"#
+/// ).unwrap();
+///
+/// assert_eq!(metadata.name("twitter:card"), Some("summary"));
+/// assert_eq!(metadata.icons[0].href, "/favicon.ico");
+/// ```
+pub fn extract_metadata(html: &str) -> Result<Metadata, RewritingError> {
+    let collector = MetadataCollector::new();
+
+    {
+        // NOTE: never panics because encoding is always "utf-8".
+        let mut rewriter = HtmlRewriter::try_new(
+            Settings {
+                element_content_handlers: collector.handlers(),
+                ..Settings::default()
+            },
+            |_: &[u8]| {},
+        )
+        .unwrap();
+
+        rewriter.write(html.as_bytes())?;
+        rewriter.end()?;
+    }
+
+    Ok(collector.metadata())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn title() {
+        let metadata = extract_metadata(
+            "<title>\n  Hello &amp;\n  world </title><svg><title>Circle</title></svg>\
+             <title>Second</title>",
+        )
+        .unwrap();
+
+        assert_eq!(metadata.title.as_deref(), Some("Hello &amp; world"));
+
+        let metadata = extract_metadata("<svg><title>Circle</title></svg>").unwrap();
+
+        assert_eq!(metadata.title, None);
+    }
+
+    #[test]
+    fn meta() {
+        let metadata = extract_metadata(
+            r#"
+            <meta charset="utf-8">
+            <meta name="Description" content="Foo">
+            <meta property="og:image" content="/a.png">
+            <meta property="og:image" content="/b.png">
+            <meta name="twitter:title" property="og:title" content="Bar">
+            <meta name="robots">
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            metadata.names,
+            vec![
+                ("Description".into(), "Foo".into()),
+                ("twitter:title".into(), "Bar".into()),
+            ]
+        );
+
+        assert_eq!(metadata.name("description"), Some("Foo"));
+        assert_eq!(metadata.name("robots"), None);
+        assert_eq!(metadata.property("og:image"), Some("/a.png"));
+        assert_eq!(metadata.property("og:title"), Some("Bar"));
+        assert_eq!(metadata.properties.len(), 3);
+    }
+
+    #[test]
+    fn links() {
+        let metadata = extract_metadata(
+            r#"
+            <link rel="stylesheet" href="/style.css">
+            <link rel="Canonical" href="/foo">
+            <link rel="canonical" href="/bar">
+            <link rel="alternate" hreflang="de" href="/de/foo">
+            <link rel="alternate" type="application/rss+xml" href="/feed.xml">
+            <link rel="shortcut icon" href="/favicon.ico">
+            <link rel="apple-touch-icon" sizes="180x180" href="/touch.png">
+            <link rel="icon">
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(metadata.canonical.as_deref(), Some("/foo"));
+
+        assert_eq!(
+            metadata.alternates,
+            vec![
+                MetadataLink {
+                    rel: "alternate".into(),
+                    href: "/de/foo".into(),
+                    hreflang: Some("de".into()),
+                    ..MetadataLink::default()
+                },
+                MetadataLink {
+                    rel: "alternate".into(),
+                    href: "/feed.xml".into(),
+                    media_type: Some("application/rss+xml".into()),
+                    ..MetadataLink::default()
+                },
+            ]
+        );
+
+        assert_eq!(
+            metadata.icons,
+            vec![
+                MetadataLink {
+                    rel: "shortcut icon".into(),
+                    href: "/favicon.ico".into(),
+                    ..MetadataLink::default()
+                },
+                MetadataLink {
+                    rel: "apple-touch-icon".into(),
+                    href: "/touch.png".into(),
+                    sizes: Some("180x180".into()),
+                    ..MetadataLink::default()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn collect_during_rewrite() {
+        use crate::html_content::ContentType;
+        use crate::*;
+
+        let collector = MetadataCollector::new();
+        let mut element_content_handlers = collector.handlers();
+
+        element_content_handlers.push(element!("title", |el| {
+            el.set_inner_content("Rewritten", ContentType::Text);
+
+            Ok(())
+        }));
+
+        let html = rewrite_str(
+            "<title>Original</title>",
+            RewriteStrSettings {
+                element_content_handlers,
+                ..RewriteStrSettings::default()
+            },
+        )
+        .unwrap();
+
+        assert_eq!(html, "<title>Rewritten</title>");
+        assert_eq!(collector.metadata().title.as_deref(), Some("Original"));
+    }
+}
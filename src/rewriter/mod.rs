@@ -1,38 +1,251 @@
+#[cfg(feature = "element_handlers")]
+mod custom_elements;
+#[cfg(feature = "element_handlers")]
+mod deferred_predicates;
 mod handlers_dispatcher;
+#[cfg(feature = "element_handlers")]
+mod link_audit;
+mod merged_handlers;
+#[cfg(feature = "element_handlers")]
+mod metadata;
+mod multi_document;
+#[cfg(feature = "element_handlers")]
+mod prefixed_attributes;
+mod progress;
+mod provenance;
+mod recording;
 mod rewrite_controller;
+#[cfg(feature = "element_handlers")]
+mod runtime_handlers;
 
+#[cfg(feature = "element_handlers")]
+mod selector_detector;
 #[macro_use]
 mod settings;
-
-use self::handlers_dispatcher::ContentHandlersDispatcher;
+mod slot_rewriter;
+mod text_extraction;
+mod token_transformer;
+#[cfg(feature = "legacy_encodings")]
+mod transcoder;
+#[cfg(feature = "element_handlers")]
+mod truncate;
+mod two_pass;
+mod validator;
+mod whitespace_sensitive;
+mod xhtml;
+
+#[cfg(feature = "element_handlers")]
+use self::deferred_predicates::DeferredPredicatesLookahead;
+use self::handlers_dispatcher::{
+    AnyHandlers, ContentHandlersDispatcher, ElementHandlersOnly, HandlersLayout,
+};
+use self::progress::Progress;
 use self::rewrite_controller::*;
+#[cfg(feature = "element_handlers")]
+use self::runtime_handlers::RuntimeHandlersState;
+#[cfg(feature = "legacy_encodings")]
+use self::transcoder::Transcoder;
+#[cfg(feature = "element_handlers")]
+use crate::html::LocalName;
 use crate::memory::MemoryLimitExceededError;
-use crate::memory::MemoryLimiter;
+use crate::memory::{MemoryLimiter, SharedMemoryLimiter};
 use crate::parser::ParsingAmbiguityError;
+#[cfg(feature = "element_handlers")]
 use crate::selectors_vm::{self, SelectorMatchingVm};
 use crate::transform_stream::*;
-use encoding_rs::Encoding;
+use encoding_rs::{Encoding, UTF_8};
+#[cfg(feature = "element_handlers")]
+use std::borrow::Cow;
+#[cfg(feature = "element_handlers")]
+use std::cmp::Reverse;
 use std::error::Error as StdError;
 use std::fmt::{self, Debug};
 use std::rc::Rc;
 use thiserror::Error;
 
+#[cfg(feature = "element_handlers")]
+pub use self::custom_elements::{custom_element_definitions, CustomElementDefinition};
+#[cfg(feature = "element_handlers")]
+pub use self::link_audit::{AuditedUrl, LinkAuditor};
+pub use self::merged_handlers::MergedHandlers;
+#[cfg(feature = "element_handlers")]
+pub use self::metadata::{extract_metadata, Metadata, MetadataCollector, MetadataLink};
+pub use self::multi_document::{
+    DocumentBoundary, DocumentBoundaryDetector, DocumentDelimiter, MultiDocumentRewriter,
+    MultiDocumentRewritingError,
+};
+#[cfg(feature = "element_handlers")]
+pub use self::prefixed_attributes::prefixed_attributes;
+pub use self::progress::ProgressEvent;
+pub use self::recording::{Recording, ReplayError, TokenRecorder};
+#[cfg(feature = "element_handlers")]
+pub use self::runtime_handlers::{RuntimeHandlers, RuntimeHandlersError, RuntimeHandlersHandle};
+#[cfg(feature = "element_handlers")]
+pub use self::selector_detector::{detect_selector_match, SelectorDetector};
 pub use self::settings::*;
+pub use self::slot_rewriter::SlotRewriter;
+pub use self::text_extraction::{html_to_text, TextExtractor};
+pub use self::token_transformer::TokenTransformer;
+#[cfg(feature = "element_handlers")]
+pub use self::truncate::truncate_content;
+pub use self::two_pass::{TwoPassRewriter, TwoPassRewritingError};
+pub use self::validator::{StructureValidator, ValidationIssue, ValidationReport};
+pub use self::whitespace_sensitive::WhitespaceSensitiveElements;
+
+// NOTE: the output is never transcoded if only UTF-8 is supported.
+#[cfg(not(feature = "legacy_encodings"))]
+enum Transcoder {}
+
+#[cfg(not(feature = "legacy_encodings"))]
+impl Transcoder {
+    #[inline]
+    fn transcode(&mut self, _chunk: &[u8]) -> &[u8] {
+        match *self {}
+    }
+}
+
+struct HookedOutputSink<'h, O: OutputSink> {
+    output_sink: O,
+    flush_hook: Option<FlushHook<'h>>,
+    progress: Option<Progress<'h>>,
+    buffer: Vec<u8>,
+    buffer_watermark: usize,
+    transcoder: Option<Transcoder>,
+}
+
+impl<'h, O: OutputSink> HookedOutputSink<'h, O> {
+    fn new(
+        output_sink: O,
+        flush_hook: Option<FlushHook<'h>>,
+        progress: Option<Progress<'h>>,
+        buffer_watermark: usize,
+        transcoder: Option<Transcoder>,
+    ) -> Self {
+        HookedOutputSink {
+            output_sink,
+            flush_hook,
+            progress,
+            buffer: Vec::with_capacity(buffer_watermark),
+            buffer_watermark,
+            transcoder,
+        }
+    }
+
+    #[inline]
+    fn emit_chunk(&mut self, chunk: &[u8]) {
+        let chunk = match self.transcoder {
+            Some(ref mut transcoder) => transcoder.transcode(chunk),
+            None => chunk,
+        };
+
+        if !chunk.is_empty() {
+            if let Some(ref mut hook) = self.flush_hook {
+                hook(chunk.len());
+            }
+
+            self.output_sink.handle_chunk(chunk);
+
+            if let Some(ref mut progress) = self.progress {
+                progress.add_output(chunk.len());
+            }
+        }
+    }
+
+    #[inline]
+    fn report_closed_elements(&mut self) {
+        if let Some(ref mut progress) = self.progress {
+            progress.report_closed_elements();
+        }
+    }
+
+    #[inline]
+    fn flush_buffer(&mut self) {
+        if !self.buffer.is_empty() {
+            let buffer = std::mem::take(&mut self.buffer);
+
+            self.emit_chunk(&buffer);
+            self.buffer = buffer;
+            self.buffer.clear();
+        }
+    }
+}
+
+impl<O: OutputSink> OutputSink for HookedOutputSink<'_, O> {
+    #[inline]
+    fn handle_chunk(&mut self, chunk: &[u8]) {
+        // NOTE: the finalizing chunk and chunks that are big enough on their own go
+        // straight to the output sink.
+        if chunk.is_empty() {
+            self.flush_buffer();
+
+            // NOTE: the transcoder may have pending output for the finalizing chunk.
+            self.emit_chunk(chunk);
+            self.output_sink.handle_chunk(chunk);
+        } else if chunk.len() >= self.buffer_watermark {
+            self.flush_buffer();
+            self.emit_chunk(chunk);
+        } else {
+            self.buffer.extend_from_slice(chunk);
+
+            if self.buffer.len() >= self.buffer_watermark {
+                self.flush_buffer();
+            }
+        }
+
+        self.report_closed_elements();
+    }
+
+    #[inline]
+    fn handle_unmodified_chunk(&mut self, chunk: &[u8], input_offset: usize) {
+        self.flush_buffer();
+
+        // NOTE: transcoded output doesn't correspond to the input, so it's passed to the output
+        // sink as a regular output.
+        if self.transcoder.is_some() {
+            if !chunk.is_empty() {
+                self.emit_chunk(chunk);
+            }
+        } else {
+            if let Some(ref mut hook) = self.flush_hook {
+                if !chunk.is_empty() {
+                    hook(chunk.len());
+                }
+            }
+
+            self.output_sink
+                .handle_unmodified_chunk(chunk, input_offset);
+
+            if let Some(ref mut progress) = self.progress {
+                progress.add_output(chunk.len());
+            }
+        }
+
+        self.report_closed_elements();
+    }
+}
+
+/// The output sink of the analysis-only rewriters, whose output is not used.
+#[inline]
+pub(crate) fn discard_output(_: &[u8]) {}
 
 fn try_encoding_from_str(encoding: &str) -> Result<&'static Encoding, EncodingError> {
     let encoding = Encoding::for_label_no_replacement(encoding.as_bytes())
         .ok_or(EncodingError::UnknownEncoding)?;
 
-    if encoding.is_ascii_compatible() {
-        Ok(encoding)
-    } else {
+    if !encoding.is_ascii_compatible() {
         Err(EncodingError::NonAsciiCompatibleEncoding)
+    } else if cfg!(not(feature = "legacy_encodings")) && encoding != UTF_8 {
+        Err(EncodingError::UnsupportedEncoding)
+    } else {
+        Ok(encoding)
     }
 }
 
-/// An error that occurs if incorrect [`encoding`] label was provided in [`Settings`].
+/// An error that occurs if incorrect [`encoding`] or [`output_encoding`] label was provided in
+/// [`Settings`].
 ///
 /// [`encoding`]: ../struct.Settings.html#structfield.encoding
+/// [`output_encoding`]: ../struct.Settings.html#structfield.output_encoding
 /// [`Settings`]: ../struct.Settings.html
 #[derive(Error, Debug, PartialEq, Copy, Clone)]
 pub enum EncodingError {
@@ -46,6 +259,11 @@ pub enum EncodingError {
     /// `ISO-2022-JP` and `replacement`). These encodings are not supported.
     #[error("Expected ASCII-compatible encoding.")]
     NonAsciiCompatibleEncoding,
+
+    /// The provided label is for an encoding other than `UTF-8`, while the crate is built without
+    /// the `legacy_encodings` feature.
+    #[error("Only UTF-8 encoding is supported.")]
+    UnsupportedEncoding,
 }
 
 /// A compound error type that can be returned by [`write`] and [`end`] methods of the rewriter.
@@ -73,6 +291,53 @@ pub enum RewritingError {
     /// An error that was propagated from one of the content handlers.
     #[error("{0}")]
     ContentHandlerError(Box<dyn StdError + Send + Sync>),
+
+    /// The content of the [`HtmlReprocessed`] type has been inserted by the content handlers
+    /// while they were processing such content, more than 16 times in a row.
+    ///
+    /// [`HtmlReprocessed`]: ../html_content/enum.ContentType.html#variant.HtmlReprocessed
+    #[error("The inserted content that is run through the content handlers is nested too deeply.")]
+    ReprocessingDepthExceeded,
+}
+
+type RewriterStream<'h, L, O> =
+    TransformStream<HtmlRewriteController<'h, L>, HookedOutputSink<'h, O>>;
+
+// NOTE: the rewriter that has only element handlers is the most common configuration, so
+// we have a specialised stream for it, that doesn't dispatch document content handlers.
+enum Stream<'h, O: OutputSink> {
+    AnyHandlers(RewriterStream<'h, AnyHandlers, O>),
+    ElementHandlersOnly(RewriterStream<'h, ElementHandlersOnly, O>),
+}
+
+macro_rules! with_stream {
+    ($stream:expr, |$s:ident| $expr:expr) => {
+        match $stream {
+            Stream::AnyHandlers(ref mut $s) => $expr,
+            Stream::ElementHandlersOnly(ref mut $s) => $expr,
+        }
+    };
+}
+
+#[inline]
+#[cfg(feature = "element_handlers")]
+fn has_element_handlers_only(settings: &Settings) -> bool {
+    settings.document_content_handlers.is_empty()
+        && settings.token_transformers.is_empty()
+        && !settings.xhtml_output
+        // NOTE: handlers of any kind can be added at runtime.
+        && settings.runtime_handlers.is_none()
+        && settings
+            .element_content_handlers
+            .iter()
+            .all(|(_, h)| h.comments.is_none() && h.text.is_none())
+}
+
+#[cfg(not(feature = "element_handlers"))]
+fn has_element_handlers_only(settings: &Settings) -> bool {
+    settings.document_content_handlers.is_empty()
+        && settings.token_transformers.is_empty()
+        && !settings.xhtml_output
 }
 
 /// A streaming HTML rewriter.
@@ -116,7 +381,11 @@ pub enum RewritingError {
 /// );
 /// ```
 pub struct HtmlRewriter<'h, O: OutputSink> {
-    stream: TransformStream<HtmlRewriteController<'h>, O>,
+    stream: Stream<'h, O>,
+    #[cfg(feature = "element_handlers")]
+    deferred_predicates_lookahead: Option<Box<DeferredPredicatesLookahead>>,
+    chunk_start_hook: Option<ChunkStartHook<'h>>,
+    memory_limiter: SharedMemoryLimiter,
     finished: bool,
     poisoned: bool,
 }
@@ -148,52 +417,307 @@ impl<'h, O: OutputSink> HtmlRewriter<'h, O> {
     ///
     /// [`OutputSink`]: trait.OutputSink.html
     pub fn try_new<'s>(settings: Settings<'h, 's>, output_sink: O) -> Result<Self, EncodingError> {
+        Self::try_new_with_output(settings, output_sink, true)
+    }
+
+    /// Constructs a new rewriter. If `emit_output` is `false`, the rewriter doesn't serialize
+    /// the content and `output_sink` receives only the empty chunks signaling the end of the
+    /// input, which makes it a cheap driver for the analysis-only handlers.
+    pub(crate) fn try_new_with_output<'s>(
+        settings: Settings<'h, 's>,
+        output_sink: O,
+        emit_output: bool,
+    ) -> Result<Self, EncodingError> {
+        #[cfg(feature = "element_handlers")]
+        let (settings, deferred_predicates_lookahead) = {
+            let mut settings = settings;
+
+            // NOTE: the depth bounds are applied before the lookahead is set up, so the detector
+            // stream matches the same elements as the main one.
+            for (selector, handlers) in settings.element_content_handlers.iter_mut() {
+                if let Some(max_depth) = handlers.max_depth {
+                    *selector = Cow::Owned(
+                        selectors_vm::Selector::clone(selector).with_max_depth(max_depth),
+                    );
+                }
+            }
+
+            let lookahead = DeferredPredicatesLookahead::try_new(&mut settings)?;
+
+            (settings, lookahead.map(Box::new))
+        };
+
+        #[cfg_attr(not(feature = "element_handlers"), allow(unused_mut))]
+        let mut rewriter = Self::try_new_without_lookahead(settings, output_sink, emit_output)?;
+
+        #[cfg(feature = "element_handlers")]
+        {
+            rewriter.deferred_predicates_lookahead =
+                deferred_predicates_lookahead.map(|mut lookahead| {
+                    lookahead.set_memory_limiter(Rc::clone(&rewriter.memory_limiter));
+                    lookahead
+                });
+        }
+
+        Ok(rewriter)
+    }
+
+    /// Constructs a new rewriter that ignores the `:contains()` text predicates of
+    /// the selectors.
+    fn try_new_without_lookahead<'s>(
+        settings: Settings<'h, 's>,
+        output_sink: O,
+        emit_output: bool,
+    ) -> Result<Self, EncodingError> {
         let encoding = try_encoding_from_str(settings.encoding)?;
+
+        let transcoder = match settings.output_encoding {
+            Some(output_encoding) => match try_encoding_from_str(output_encoding)? {
+                #[cfg(feature = "legacy_encodings")]
+                output_encoding if output_encoding != encoding => {
+                    Some(Transcoder::new(encoding, output_encoding))
+                }
+                _ => None,
+            },
+            None => None,
+        };
+
+        let memory_limiter =
+            MemoryLimiter::new_shared(settings.memory_settings.max_allowed_memory_usage);
+
+        let (stream, chunk_start_hook) = if has_element_handlers_only(&settings) {
+            let (stream, hook) = Self::create_stream(
+                settings,
+                output_sink,
+                emit_output,
+                encoding,
+                transcoder,
+                Rc::clone(&memory_limiter),
+            );
+
+            (Stream::ElementHandlersOnly(stream), hook)
+        } else {
+            let (stream, hook) = Self::create_stream(
+                settings,
+                output_sink,
+                emit_output,
+                encoding,
+                transcoder,
+                Rc::clone(&memory_limiter),
+            );
+
+            (Stream::AnyHandlers(stream), hook)
+        };
+
+        Ok(HtmlRewriter {
+            stream,
+            #[cfg(feature = "element_handlers")]
+            deferred_predicates_lookahead: None,
+            chunk_start_hook,
+            memory_limiter,
+            finished: false,
+            poisoned: false,
+        })
+    }
+
+    fn create_stream<'s, L: HandlersLayout>(
+        settings: Settings<'h, 's>,
+        output_sink: O,
+        emit_output: bool,
+        encoding: &'static Encoding,
+        transcoder: Option<Transcoder>,
+        memory_limiter: SharedMemoryLimiter,
+    ) -> (RewriterStream<'h, L, O>, Option<ChunkStartHook<'h>>) {
+        let ObservabilityHooks {
+            chunk_start: chunk_start_hook,
+            token: token_hook,
+            flush: flush_hook,
+            progress: progress_hook,
+        } = settings.observability_hooks;
+
+        #[cfg(feature = "element_handlers")]
         let mut selectors_ast = selectors_vm::Ast::default();
-        let mut dispatcher = ContentHandlersDispatcher::default();
-        let has_selectors = !settings.element_content_handlers.is_empty();
+        let custom_void_elements = settings
+            .custom_void_elements
+            .iter()
+            .map(|n| n.to_ascii_lowercase())
+            .collect::<Vec<_>>();
+
+        let mut dispatcher = ContentHandlersDispatcher::new(Rc::clone(&memory_limiter));
+
+        dispatcher.set_custom_void_elements(Rc::from(custom_void_elements.as_slice()));
+
+        #[cfg(feature = "element_handlers")]
+        let mut has_selectors =
+            !settings.element_content_handlers.is_empty() || settings.runtime_handlers.is_some();
+
+        // NOTE: handlers of the token transformers are added first, so they are invoked
+        // before any of the content handlers.
+        for transformer in settings.token_transformers {
+            let (document_handlers, element_handlers) =
+                token_transformer::into_handlers(transformer);
+
+            dispatcher.add_document_content_handlers(document_handlers);
+
+            #[cfg(feature = "element_handlers")]
+            if let Some(handlers) = element_handlers {
+                let locator = dispatcher.add_selector_associated_handlers(handlers);
+
+                // NOTE: never panics as the universal selector is always valid.
+                let selector = "*".parse().unwrap();
+
+                selectors_ast
+                    .add_selector_with_scoped_payload(&selector, |i, p| locator.scoped_to(i, p));
+                has_selectors = true;
+            }
+
+            // NOTE: start tags can't be dispatched to the transformers without
+            // the selector matching VM.
+            #[cfg(not(feature = "element_handlers"))]
+            drop(element_handlers);
+        }
+
+        #[cfg(feature = "element_handlers")]
+        let mut element_content_handlers = settings
+            .element_content_handlers
+            .into_iter()
+            .enumerate()
+            .collect::<Vec<_>>();
+
+        // NOTE: the sort is stable, so the handlers with the same priority are invoked in
+        // the order they have been specified.
+        #[cfg(feature = "element_handlers")]
+        element_content_handlers.sort_by_key(|(_, (_, handlers))| Reverse(handlers.priority));
+
+        #[cfg(feature = "element_handlers")]
+        for (idx, (selector, handlers)) in element_content_handlers {
+            let handlers = if settings.provenance_comments {
+                provenance::element_content_handlers(handlers, idx)
+            } else {
+                handlers
+            };
 
-        for (selector, handlers) in settings.element_content_handlers {
             let locator = dispatcher.add_selector_associated_handlers(handlers);
 
-            selectors_ast.add_selector(&selector, locator);
+            selectors_ast
+                .add_selector_with_scoped_payload(&selector, |i, p| locator.scoped_to(i, p));
+        }
+
+        let progress = progress_hook.map(|(watermark, hook)| Progress::new(watermark, hook));
+
+        #[cfg(feature = "element_handlers")]
+        if let Some(ref progress) = progress {
+            let locator = dispatcher.add_selector_associated_handlers(progress.element_handlers());
+
+            // NOTE: never panics as the universal selector is always valid.
+            let selector = "*".parse().unwrap();
+
+            selectors_ast
+                .add_selector_with_scoped_payload(&selector, |i, p| locator.scoped_to(i, p));
+            has_selectors = true;
         }
 
-        for handlers in settings.document_content_handlers {
+        for (idx, handlers) in settings.document_content_handlers.into_iter().enumerate() {
+            let handlers = if settings.provenance_comments {
+                provenance::document_content_handlers(handlers, idx)
+            } else {
+                handlers
+            };
+
             dispatcher.add_document_content_handlers(handlers);
         }
 
-        let memory_limiter =
-            MemoryLimiter::new_shared(settings.memory_settings.max_allowed_memory_usage);
+        // NOTE: XHTML serialization handlers are added last, so they observe the modifications
+        // made by all the other handlers.
+        if settings.xhtml_output {
+            dispatcher.add_document_content_handlers(xhtml::document_handlers());
+
+            #[cfg(feature = "element_handlers")]
+            {
+                let locator =
+                    dispatcher.add_selector_associated_handlers(xhtml::element_handlers());
 
+                // NOTE: never panics as the universal selector is always valid.
+                let selector = "*".parse().unwrap();
+
+                selectors_ast
+                    .add_selector_with_scoped_payload(&selector, |i, p| locator.scoped_to(i, p));
+                has_selectors = true;
+            }
+        }
+
+        // NOTE: the AST is kept to recompile the program of the VM once the handlers are added
+        // at runtime.
+        #[cfg(feature = "element_handlers")]
+        let runtime_handlers = settings
+            .runtime_handlers
+            .map(|r| RuntimeHandlersState::new(r, selectors_ast.clone(), encoding));
+
+        #[cfg(feature = "element_handlers")]
         let selector_matching_vm = if has_selectors {
-            Some(SelectorMatchingVm::new(
-                selectors_ast,
-                encoding,
-                Rc::clone(&memory_limiter),
-            ))
+            let mut vm =
+                SelectorMatchingVm::new(selectors_ast, encoding, Rc::clone(&memory_limiter));
+
+            // NOTE: names that can't be represented in the document's encoding can't be
+            // encountered in the document, so they are skipped.
+            vm.set_custom_void_elements(
+                custom_void_elements
+                    .iter()
+                    .filter_map(|n| LocalName::from_str_without_replacements(n, encoding).ok())
+                    .map(LocalName::into_owned)
+                    .collect(),
+            );
+
+            Some(vm)
         } else {
             None
         };
 
-        let controller = HtmlRewriteController::new(dispatcher, selector_matching_vm);
+        let reprocessing_settings = ReprocessingSettings {
+            encoding,
+            strict: settings.strict,
+            buffer_script_and_style_text: settings.buffer_script_and_style_text,
+        };
+
+        #[cfg(feature = "element_handlers")]
+        let controller = HtmlRewriteController::new(
+            dispatcher,
+            selector_matching_vm,
+            runtime_handlers,
+            token_hook,
+            emit_output,
+            reprocessing_settings,
+        );
+
+        #[cfg(not(feature = "element_handlers"))]
+        let controller =
+            HtmlRewriteController::new(dispatcher, token_hook, emit_output, reprocessing_settings);
 
         let stream = TransformStream::new(TransformStreamSettings {
             transform_controller: controller,
-            output_sink,
+            output_sink: HookedOutputSink::new(
+                output_sink,
+                flush_hook,
+                progress,
+                settings.output_buffer_watermark.unwrap_or(0),
+                transcoder,
+            ),
             preallocated_parsing_buffer_size: settings
                 .memory_settings
                 .preallocated_parsing_buffer_size,
+            parsing_buffer_compaction_watermark: settings
+                .memory_settings
+                .parsing_buffer_compaction_watermark,
+            parsing_buffer_shrink_watermark: settings
+                .memory_settings
+                .parsing_buffer_shrink_watermark,
             memory_limiter,
             encoding,
             strict: settings.strict,
+            buffer_script_and_style_text: settings.buffer_script_and_style_text,
         });
 
-        Ok(HtmlRewriter {
-            stream,
-            finished: false,
-            poisoned: false,
-        })
+        (stream, chunk_start_hook)
     }
 
     /// Writes a chunk of input data to the rewriter.
@@ -212,7 +736,95 @@ impl<'h, O: OutputSink> HtmlRewriter<'h, O> {
             "Data was written into the stream after it has ended."
         );
 
-        guarded!(self, self.stream.write(data))
+        if let Some(ref mut hook) = self.chunk_start_hook {
+            hook(data.len());
+        }
+
+        #[cfg(feature = "element_handlers")]
+        if let Some(ref mut lookahead) = self.deferred_predicates_lookahead {
+            let released = guarded!(self, lookahead.write(data))?;
+
+            if !released.is_empty() {
+                self.write_to_stream(&released)?;
+            }
+
+            return Ok(());
+        }
+
+        self.write_to_stream(data)
+    }
+
+    #[inline]
+    fn write_to_stream(&mut self, data: &[u8]) -> Result<(), RewritingError> {
+        guarded!(self, with_stream!(self.stream, |s| s.write(data)))
+    }
+
+    /// Changes the maximum amount of memory the rewriter is allowed to use, e.g. to relax
+    /// the limit for a trusted origin once the response headers that identify it have been
+    /// inspected.
+    ///
+    /// The new limit takes effect for the subsequent invocations of [`write`] and [`end`].
+    /// Memory that is already in use is not released if it exceeds the new limit, instead
+    /// the next allocation fails with [`MemoryLimitExceededError`].
+    ///
+    /// # Panics
+    ///  * If previous invocation of [`write`] returned a [`RewritingError`] (these errors
+    ///    are unrecovarable).
+    ///  * If called after [`end`].
+    ///
+    /// [`MemoryLimitExceededError`]: struct.MemoryLimitExceededError.html
+    /// [`RewritingError`]: errors/enum.RewritingError.html
+    /// [`write`]: struct.HtmlRewriter.html#method.write
+    /// [`end`]: struct.HtmlRewriter.html#method.end
+    #[inline]
+    pub fn set_max_allowed_memory_usage(&mut self, max_allowed_memory_usage: usize) {
+        assert!(
+            !self.finished,
+            "Memory limit was changed after the stream has ended."
+        );
+        assert!(
+            !self.poisoned,
+            "Attempt to use the HtmlRewriter after a fatal error."
+        );
+
+        self.memory_limiter
+            .borrow_mut()
+            .set_max(max_allowed_memory_usage);
+    }
+
+    /// Passes all the output produced so far to the output sink without finalizing
+    /// the rewriting process, e.g. to push the rewritten content to a client at a latency
+    /// deadline. The rewriter can be written to after that as usual.
+    ///
+    /// The output coalesced due to [`output_buffer_watermark`] is passed to the output sink
+    /// regardless of the size it has reached.
+    ///
+    /// The output that depends on the input which is yet to be written is still held back:
+    ///  * the bytes of an unfinished token, a character or a character reference;
+    ///  * the input held back until the deferred predicates of the selectors, e.g. `:contains()`,
+    ///    are resolved;
+    ///  * the text of `<script>` and `<style>` elements buffered due to
+    ///    [`buffer_script_and_style_text`].
+    ///
+    /// # Panics
+    ///  * If previous invocation of [`write`] returned a [`RewritingError`] (these errors
+    ///    are unrecovarable).
+    ///  * If called after [`end`].
+    ///
+    /// [`output_buffer_watermark`]: struct.Settings.html#structfield.output_buffer_watermark
+    /// [`buffer_script_and_style_text`]: struct.Settings.html#structfield.buffer_script_and_style_text
+    /// [`RewritingError`]: errors/enum.RewritingError.html
+    /// [`write`]: struct.HtmlRewriter.html#method.write
+    /// [`end`]: struct.HtmlRewriter.html#method.end
+    #[inline]
+    pub fn flush(&mut self) {
+        assert!(!self.finished, "Stream was flushed after it has ended.");
+        assert!(
+            !self.poisoned,
+            "Attempt to use the HtmlRewriter after a fatal error."
+        );
+
+        with_stream!(self.stream, |s| s.output_sink_mut().flush_buffer());
     }
 
     /// Finalizes the rewriting process.
@@ -231,7 +843,16 @@ impl<'h, O: OutputSink> HtmlRewriter<'h, O> {
         assert!(!self.finished, "Stream was ended twice.");
         self.finished = true;
 
-        guarded!(self, self.stream.end())
+        #[cfg(feature = "element_handlers")]
+        if let Some(ref mut lookahead) = self.deferred_predicates_lookahead {
+            let released = guarded!(self, lookahead.end())?;
+
+            if !released.is_empty() {
+                self.write_to_stream(&released)?;
+            }
+        }
+
+        guarded!(self, with_stream!(self.stream, |s| s.end()))
     }
 }
 
@@ -296,8 +917,9 @@ pub fn rewrite_str<'h, 's>(
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::html_content::ContentType;
+    use crate::html_content::{ContentType, TokenKind};
     use crate::test_utils::{Output, ASCII_COMPATIBLE_ENCODINGS};
+    use std::borrow::Cow;
     use std::cell::RefCell;
     use std::rc::Rc;
 
@@ -345,53 +967,791 @@ mod tests {
     }
 
     #[test]
-    fn unknown_encoding() {
-        let err = HtmlRewriter::try_new(
-            Settings {
-                encoding: "hey-yo",
-                ..Settings::default()
-            },
-            |_: &[u8]| {},
-        )
-        .unwrap_err();
+    fn observability_hooks() {
+        let chunk_sizes = RefCell::new(vec![]);
+        let token_kinds = RefCell::new(vec![]);
+        let flushed_size = RefCell::new(0);
+        let mut output = vec![];
+
+        {
+            let mut rewriter = HtmlRewriter::try_new(
+                Settings {
+                    element_content_handlers: vec![
+                        element!("span", |el| {
+                            el.set_attribute("foo", "bar").unwrap();
+                            Ok(())
+                        }),
+                        comments!("div", |_| Ok(())),
+                    ],
+                    observability_hooks: ObservabilityHooks::default()
+                        .on_chunk_start(|size| chunk_sizes.borrow_mut().push(size))
+                        .on_token(|kind| token_kinds.borrow_mut().push(kind))
+                        .on_flush(|size| *flushed_size.borrow_mut() += size),
+                    ..Settings::default()
+                },
+                |c: &[u8]| output.extend_from_slice(c),
+            )
+            .unwrap();
 
-        assert_eq!(err, EncodingError::UnknownEncoding);
-    }
+            write_chunks(
+                &mut rewriter,
+                encoding_rs::UTF_8,
+                &["<div><!--a-", "-><span>", "b</span></div>"],
+            );
+        }
 
-    #[test]
-    fn non_ascii_compatible_encoding() {
-        let err = HtmlRewriter::try_new(
-            Settings {
-                encoding: "utf-16be",
-                ..Settings::default()
-            },
-            |_: &[u8]| {},
-        )
-        .unwrap_err();
+        assert_eq!(*chunk_sizes.borrow(), [11, 8, 14]);
+        assert_eq!(*flushed_size.borrow(), output.len());
 
-        assert_eq!(err, EncodingError::NonAsciiCompatibleEncoding);
+        let token_kinds = token_kinds.borrow();
+
+        assert!(token_kinds.contains(&TokenKind::Comment));
+        assert!(token_kinds.contains(&TokenKind::StartTag));
     }
 
     #[test]
-    fn doctype_info() {
-        for enc in ASCII_COMPATIBLE_ENCODINGS.iter() {
-            let mut doctypes = Vec::default();
+    fn progress_hook() {
+        let progress = |chunks: &[&str]| {
+            let output = RefCell::new(Vec::new());
+            let events = RefCell::new(Vec::new());
 
             {
                 let mut rewriter = HtmlRewriter::try_new(
                     Settings {
-                        document_content_handlers: vec![doctype!(|d| {
-                            doctypes.push((d.name(), d.public_id(), d.system_id()));
-                            Ok(())
-                        })],
-                        encoding: enc.name(),
+                        observability_hooks: ObservabilityHooks::default().on_progress(
+                            10,
+                            |event| {
+                                let output_size = output.borrow().len();
+
+                                match event {
+                                    ProgressEvent::OutputWatermark { output_size: size } => {
+                                        assert_eq!(size, output_size);
+                                    }
+                                    ProgressEvent::TopLevelElementEnd { tag_name } => {
+                                        let output = output.borrow();
+
+                                        assert!(
+                                            output.ends_with(format!("</{}>", tag_name).as_bytes())
+                                        );
+                                        events.borrow_mut().push(tag_name.to_string());
+                                    }
+                                }
+                            },
+                        ),
                         ..Settings::default()
                     },
-                    |_: &[u8]| {},
+                    |c: &[u8]| output.borrow_mut().extend_from_slice(c),
                 )
                 .unwrap();
 
-                write_chunks(
+                for chunk in chunks {
+                    rewriter.write(chunk.as_bytes()).unwrap();
+                }
+
+                rewriter.end().unwrap();
+            }
+
+            assert_eq!(output.into_inner(), chunks.concat().into_bytes());
+
+            events.into_inner()
+        };
+
+        assert_eq!(
+            progress(&["<div><p>a</p></div><sp", "an>b</span><br>", "<i>c"]),
+            ["div", "span"]
+        );
+
+        assert_eq!(
+            progress(&[
+                "<html><head><title>a</title></head><body><p>b",
+                "</body></html>"
+            ]),
+            ["head", "body", "html"]
+        );
+    }
+
+    #[test]
+    fn progress_output_watermark() {
+        let output_sizes = RefCell::new(Vec::new());
+
+        let mut rewriter = HtmlRewriter::try_new(
+            Settings {
+                observability_hooks: ObservabilityHooks::default().on_progress(10, |event| {
+                    if let ProgressEvent::OutputWatermark { output_size } = event {
+                        output_sizes.borrow_mut().push(output_size);
+                    }
+                }),
+                ..Settings::default()
+            },
+            |_: &[u8]| {},
+        )
+        .unwrap();
+
+        for chunk in &["<div>", "12345", "67890", "1234567890123456789", "0</div>"] {
+            rewriter.write(chunk.as_bytes()).unwrap();
+        }
+
+        rewriter.end().unwrap();
+
+        assert_eq!(*output_sizes.borrow(), [10, 34, 41]);
+    }
+
+    #[test]
+    fn nth_child_zebra_striping() {
+        let html = rewrite_str(
+            concat!(
+                "<table><tr><td>1</td></tr><tr><td>2</td></tr><!-- x -->text<tr><td>3</td></tr>",
+                "<tbody><tr><td>4</td></tr></tbody></table>"
+            ),
+            RewriteStrSettings {
+                element_content_handlers: vec![
+                    element!("tr:nth-child(odd)", |el| {
+                        el.set_attribute("class", "odd")?;
+
+                        Ok(())
+                    }),
+                    element!("tr:nth-of-type(2n)", |el| {
+                        el.set_attribute("class", "even")?;
+
+                        Ok(())
+                    }),
+                ],
+                ..RewriteStrSettings::default()
+            },
+        )
+        .unwrap();
+
+        assert_eq!(
+            html,
+            concat!(
+                r#"<table><tr class="odd"><td>1</td></tr><tr class="even"><td>2</td></tr>"#,
+                r#"<!-- x -->text<tr class="odd"><td>3</td></tr>"#,
+                r#"<tbody><tr class="odd"><td>4</td></tr></tbody></table>"#
+            )
+        );
+    }
+
+    #[test]
+    fn first_child_and_first_of_type() {
+        let html = rewrite_str(
+            "<ul><li>a<ul><li>b</li><li>c</li></ul></li><li>d</li></ul><div><p>e</p><h1>f</h1></div>",
+            RewriteStrSettings {
+                element_content_handlers: vec![
+                    element!("li:first-child", |el| {
+                        el.set_attribute("class", "first")?;
+
+                        Ok(())
+                    }),
+                    element!("div > :first-of-type", |el| {
+                        el.before("^", ContentType::Text);
+
+                        Ok(())
+                    }),
+                ],
+                ..RewriteStrSettings::default()
+            },
+        )
+        .unwrap();
+
+        assert_eq!(
+            html,
+            concat!(
+                r#"<ul><li class="first">a<ul><li class="first">b</li><li>c</li></ul></li>"#,
+                "<li>d</li></ul><div>^<p>e</p>^<h1>f</h1></div>"
+            )
+        );
+    }
+
+    #[test]
+    fn sibling_combinators() {
+        let html = rewrite_str(
+            concat!(
+                "<h2>A</h2><p>1</p><p>2</p><div><p>3</p></div><h2 class=x>B</h2><img>",
+                "<p title=t>4</p><section><h2>C</h2>text<p>5</p></section>"
+            ),
+            RewriteStrSettings {
+                element_content_handlers: vec![
+                    element!("h2 + p", |el| {
+                        el.set_attribute("class", "lead")?;
+
+                        Ok(())
+                    }),
+                    element!("h2.x ~ p", |el| {
+                        el.append("*", ContentType::Text);
+
+                        Ok(())
+                    }),
+                    element!("img + p[title]", |el| {
+                        el.prepend("^", ContentType::Text);
+
+                        Ok(())
+                    }),
+                ],
+                ..RewriteStrSettings::default()
+            },
+        )
+        .unwrap();
+
+        assert_eq!(
+            html,
+            concat!(
+                r#"<h2>A</h2><p class="lead">1</p><p>2</p><div><p>3</p></div><h2 class=x>B</h2>"#,
+                r#"<img><p title=t>^4*</p><section><h2>C</h2>text<p class="lead">5</p></section>"#
+            )
+        );
+    }
+
+    #[test]
+    fn selector_list_pseudo_classes() {
+        let html = rewrite_str(
+            concat!(
+                "<h1><a href=1>1</a></h1><h2><a href=2>2</a></h2><h4><a href=4>4</a></h4>",
+                r#"<p class="note"><a>5</a></p><p><a>6</a></p>"#
+            ),
+            RewriteStrSettings {
+                element_content_handlers: vec![
+                    element!(":is(h1, h2, h3) a[href]", |el| {
+                        el.set_attribute("class", "heading-link")?;
+
+                        Ok(())
+                    }),
+                    element!("p:where(.note, .tip) > a", |el| {
+                        el.prepend("!", ContentType::Text);
+
+                        Ok(())
+                    }),
+                ],
+                ..RewriteStrSettings::default()
+            },
+        )
+        .unwrap();
+
+        assert_eq!(
+            html,
+            concat!(
+                r#"<h1><a href=1 class="heading-link">1</a></h1>"#,
+                r#"<h2><a href=2 class="heading-link">2</a></h2><h4><a href=4>4</a></h4>"#,
+                r#"<p class="note"><a>!5</a></p><p><a>6</a></p>"#
+            )
+        );
+    }
+
+    #[test]
+    fn attribute_case_sensitivity_flags() {
+        let html = rewrite_str(
+            concat!(
+                "<input type=text><input type=Text><input type=password>",
+                "<a rel=NoFollow>1</a><a rel=nofollow>2</a>"
+            ),
+            RewriteStrSettings {
+                element_content_handlers: vec![
+                    element!("input[type=TEXT i]", |el| {
+                        el.set_attribute("data-m", "")?;
+
+                        Ok(())
+                    }),
+                    element!("a[rel=nofollow s]", |el| {
+                        el.set_attribute("data-m", "")?;
+
+                        Ok(())
+                    }),
+                ],
+                ..RewriteStrSettings::default()
+            },
+        )
+        .unwrap();
+
+        assert_eq!(
+            html,
+            concat!(
+                r#"<input type=text data-m=""><input type=Text data-m=""><input type=password>"#,
+                r#"<a rel=NoFollow>1</a><a rel=nofollow data-m="">2</a>"#
+            )
+        );
+    }
+
+    #[test]
+    fn custom_void_elements() {
+        let html = rewrite_str(
+            "<p><ICON name=star><b>Starred</b></p>",
+            RewriteStrSettings {
+                element_content_handlers: vec![
+                    element!("p > b", |el| {
+                        el.set_inner_content("Favorite", ContentType::Text);
+
+                        Ok(())
+                    }),
+                    element!("icon", |el| {
+                        el.append("!", ContentType::Text);
+
+                        Ok(())
+                    }),
+                ],
+                custom_void_elements: vec!["icon"],
+                ..RewriteStrSettings::default()
+            },
+        )
+        .unwrap();
+
+        assert_eq!(html, "<p><ICON name=star><b>Favorite</b></p>");
+
+        let rewrite_subtree = |custom_void_elements| {
+            rewrite_str(
+                "<ul><li><icon name=a>A</li></ul>",
+                RewriteStrSettings {
+                    element_content_handlers: vec![element!("li", |el| {
+                        el.buffer_subtree(|subtree| {
+                            subtree.children_mut().reverse();
+
+                            Ok(())
+                        });
+
+                        Ok(())
+                    })],
+                    custom_void_elements,
+                    ..RewriteStrSettings::default()
+                },
+            )
+            .unwrap()
+        };
+
+        assert_eq!(
+            rewrite_subtree(vec!["icon"]),
+            r#"<ul><li>A<icon name="a"></li></ul>"#
+        );
+
+        assert_eq!(
+            rewrite_subtree(vec![]),
+            r#"<ul><li><icon name="a">A</icon></li></ul>"#
+        );
+    }
+
+    #[test]
+    fn unmatched_content_pass_through() {
+        struct UnmodifiedChunks(Vec<u8>, Vec<usize>);
+
+        impl OutputSink for &mut UnmodifiedChunks {
+            fn handle_chunk(&mut self, chunk: &[u8]) {
+                self.0.extend_from_slice(chunk);
+            }
+
+            fn handle_unmodified_chunk(&mut self, chunk: &[u8], input_offset: usize) {
+                self.1.push(input_offset);
+                self.handle_chunk(chunk);
+            }
+        }
+
+        let input = "<div>a<!--b--></div><div foo><!--c--></div><p>d</p>";
+        let token_kinds = RefCell::new(vec![]);
+        let mut output = UnmodifiedChunks(vec![], vec![]);
+
+        {
+            let mut rewriter = HtmlRewriter::try_new(
+                Settings {
+                    element_content_handlers: vec![element!("div[foo]", |el| {
+                        el.set_attribute("foo", "bar").unwrap();
+                        Ok(())
+                    })],
+                    observability_hooks: ObservabilityHooks::default()
+                        .on_token(|kind| token_kinds.borrow_mut().push(kind)),
+                    ..Settings::default()
+                },
+                &mut output,
+            )
+            .unwrap();
+
+            write_chunks(&mut rewriter, encoding_rs::UTF_8, &[input]);
+        }
+
+        assert_eq!(*token_kinds.borrow(), [TokenKind::StartTag]);
+
+        assert_eq!(
+            String::from_utf8(output.0).unwrap(),
+            r#"<div>a<!--b--></div><div foo="bar"><!--c--></div><p>d</p>"#
+        );
+
+        // NOTE: content around the matched start tag is emitted as unmodified input.
+        assert_eq!(output.1, [0, 29, input.len()]);
+    }
+
+    #[test]
+    fn output_encoding() {
+        let (input, _, _) = encoding_rs::WINDOWS_1251.encode("<p>Привет</p><p>мир</p>");
+        let mut output = vec![];
+
+        {
+            let mut rewriter = HtmlRewriter::try_new(
+                Settings {
+                    element_content_handlers: vec![element!("p", |el| {
+                        el.append("!", ContentType::Text);
+                        Ok(())
+                    })],
+                    encoding: "windows-1251",
+                    output_encoding: Some("utf-8"),
+                    ..Settings::default()
+                },
+                |c: &[u8]| output.extend_from_slice(c),
+            )
+            .unwrap();
+
+            for chunk in input.chunks(3) {
+                rewriter.write(chunk).unwrap();
+            }
+
+            rewriter.end().unwrap();
+        }
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "<p>Привет!</p><p>мир!</p>"
+        );
+    }
+
+    #[test]
+    fn unmatched_chunks_are_not_copied() {
+        let chunks = RefCell::new(vec![]);
+
+        let mut rewriter = HtmlRewriter::try_new(
+            Settings {
+                element_content_handlers: vec![element!("span", |_| Ok(()))],
+                ..Settings::default()
+            },
+            |c: &[u8]| chunks.borrow_mut().push((c.as_ptr(), c.len())),
+        )
+        .unwrap();
+
+        let data = b"<div>Hello</div>";
+
+        rewriter.write(data).unwrap();
+
+        assert_eq!(*chunks.borrow(), [(data.as_ptr(), data.len())]);
+
+        chunks.borrow_mut().clear();
+
+        // NOTE: the incomplete tag is buffered, but the output for the next chunk still
+        // refers to the caller's data.
+        rewriter.write(b"<p>world<di").unwrap();
+
+        let data = b"v>!</div></p>";
+
+        rewriter.write(data).unwrap();
+
+        assert_eq!(chunks.borrow().last(), Some(&(data.as_ptr(), data.len())));
+    }
+
+    #[test]
+    fn element_handlers_only() {
+        let settings = |with_comments| {
+            let text = Rc::new(RefCell::new(String::new()));
+            let text_clone = Rc::clone(&text);
+
+            let mut element_content_handlers = vec![
+                element!("h1", move |el| {
+                    let text = Rc::clone(&text_clone);
+
+                    el.accumulate_text(100, move |t| {
+                        text.borrow_mut().push_str(t);
+                        Ok(())
+                    });
+
+                    Ok(())
+                }),
+                element!("ul", |el| {
+                    el.buffer_subtree(|subtree| {
+                        subtree.children_mut().reverse();
+                        Ok(())
+                    });
+
+                    Ok(())
+                }),
+            ];
+
+            if with_comments {
+                element_content_handlers.push(comments!("p", |_| Ok(())));
+            }
+
+            (
+                Settings {
+                    element_content_handlers,
+                    ..Settings::default()
+                },
+                text,
+            )
+        };
+
+        assert!(has_element_handlers_only(&settings(false).0));
+        assert!(!has_element_handlers_only(&settings(true).0));
+        assert!(!has_element_handlers_only(&Settings {
+            document_content_handlers: vec![doc_comments!(|_| Ok(()))],
+            ..Settings::default()
+        }));
+
+        for &with_comments in &[false, true] {
+            let (settings, text) = settings(with_comments);
+            let mut output = vec![];
+
+            {
+                let mut rewriter =
+                    HtmlRewriter::try_new(settings, |c: &[u8]| output.extend_from_slice(c))
+                        .unwrap();
+
+                write_chunks(
+                    &mut rewriter,
+                    encoding_rs::UTF_8,
+                    &[
+                        "<h1>Hel",
+                        "lo</h1><ul><li>1</li><!--2--><li>3</li></ul><p><!--4--></p>",
+                    ],
+                );
+            }
+
+            assert_eq!(
+                String::from_utf8(output).unwrap(),
+                "<h1>Hello</h1><ul><li>3</li><!--2--><li>1</li></ul><p><!--4--></p>"
+            );
+
+            assert_eq!(*text.borrow(), "Hello");
+        }
+    }
+
+    #[test]
+    fn closing_sequences_search() {
+        use crate::test_utils::assert_golden_output;
+
+        let input = concat!(
+            "<!-- a - b -- c <!- d --!>",
+            "<style>a < b</s </style>",
+            "<textarea>x</t>y</textarea>",
+            "<script>if (a<b && c-->d) {} <!-- <script></script> --></script>",
+            "<svg><![CDATA[ ] ]] ]]></svg>",
+            "<! bogus - -->",
+        );
+
+        assert_golden_output(
+            input,
+            concat!(
+                "<!--[ a - b -- c <!- d ]-->",
+                "<style>[a < b</s ]</style>",
+                "<textarea>[x</t>y]</textarea>",
+                "<script>[if (a<b && c-->d) {} <!-- <script></script> -->]</script>",
+                "<svg><![CDATA[ ] ]] ]]></svg>",
+                "<!--[ bogus - --]-->",
+            ),
+            || Settings {
+                element_content_handlers: vec![element!("style, textarea, script", |el| {
+                    el.prepend("[", ContentType::Text);
+                    el.append("]", ContentType::Text);
+
+                    Ok(())
+                })],
+                document_content_handlers: vec![doc_comments!(|c| {
+                    let text = format!("[{}]", c.text());
+
+                    c.set_text(&text).unwrap();
+
+                    Ok(())
+                })],
+                ..Settings::default()
+            },
+        );
+    }
+
+    #[test]
+    fn flush() {
+        let output = RefCell::new(Vec::new());
+        let output_str = || String::from_utf8(output.borrow().clone()).unwrap();
+
+        let mut rewriter = HtmlRewriter::try_new(
+            Settings {
+                element_content_handlers: vec![element!("span", |el| {
+                    el.set_attribute("foo", "bar").unwrap();
+                    Ok(())
+                })],
+                output_buffer_watermark: Some(1024),
+                ..Settings::default()
+            },
+            |c: &[u8]| output.borrow_mut().extend_from_slice(c),
+        )
+        .unwrap();
+
+        rewriter.write(b"<div><span>a</span><span><sp").unwrap();
+
+        // NOTE: the modified start tag is held in the output buffer until the flush.
+        assert_eq!(output_str(), r#"<div><span foo="bar">a</span>"#);
+
+        rewriter.flush();
+
+        assert_eq!(
+            output_str(),
+            r#"<div><span foo="bar">a</span><span foo="bar">"#
+        );
+
+        rewriter.write(b"an>").unwrap();
+        rewriter.flush();
+
+        assert_eq!(
+            output_str(),
+            r#"<div><span foo="bar">a</span><span foo="bar"><span foo="bar">"#
+        );
+
+        rewriter.end().unwrap();
+    }
+
+    #[test]
+    fn output_coalescing() {
+        let rewrite = |output_buffer_watermark| {
+            let mut chunks = vec![];
+            let flushed_size = RefCell::new(0);
+
+            {
+                let mut rewriter = HtmlRewriter::try_new(
+                    Settings {
+                        element_content_handlers: vec![element!("span", |el| {
+                            el.set_attribute("foo", "bar").unwrap();
+                            el.prepend("<b>", ContentType::Html);
+                            el.append("</b>", ContentType::Html);
+                            Ok(())
+                        })],
+                        output_buffer_watermark,
+                        observability_hooks: ObservabilityHooks::default()
+                            .on_flush(|size| *flushed_size.borrow_mut() += size),
+                        ..Settings::default()
+                    },
+                    |c: &[u8]| chunks.push(c.to_vec()),
+                )
+                .unwrap();
+
+                write_chunks(
+                    &mut rewriter,
+                    encoding_rs::UTF_8,
+                    &["<div><span>a</span><span>", "b</span></div>"],
+                );
+            }
+
+            assert_eq!(*flushed_size.borrow(), chunks.concat().len());
+            assert!(chunks.last().unwrap().is_empty());
+
+            chunks
+        };
+
+        let uncoalesced = rewrite(None);
+        let coalesced = rewrite(Some(1024));
+
+        assert_eq!(coalesced.concat(), uncoalesced.concat());
+        assert!(coalesced.len() < uncoalesced.len());
+
+        assert_eq!(
+            String::from_utf8(coalesced.concat()).unwrap(),
+            concat!(
+                r#"<div><span foo="bar"><b>a</b></span>"#,
+                r#"<span foo="bar"><b>b</b></span></div>"#
+            )
+        );
+
+        // NOTE: chunks that are not smaller than the watermark bypass the buffer.
+        let small_watermark = rewrite(Some(1));
+
+        assert_eq!(small_watermark, uncoalesced);
+    }
+
+    #[test]
+    fn output_coalescing_across_writes() {
+        let sink_calls = RefCell::new(Vec::new());
+
+        let mut rewriter = HtmlRewriter::try_new(
+            Settings {
+                element_content_handlers: vec![element!("span", |el| {
+                    el.set_attribute("foo", "bar").unwrap();
+                    Ok(())
+                })],
+                output_buffer_watermark: Some(40),
+                ..Settings::default()
+            },
+            |c: &[u8]| sink_calls.borrow_mut().push(c.len()),
+        )
+        .unwrap();
+
+        let mut calls_after_writes = Vec::new();
+
+        // NOTE: each write produces a 16 bytes long modified start tag, so the buffer reaches
+        // the watermark on the third write only.
+        for _ in 0..3 {
+            rewriter.write(b"<span>").unwrap();
+            calls_after_writes.push(sink_calls.borrow().len());
+        }
+
+        assert_eq!(calls_after_writes, [0, 0, 1]);
+        assert_eq!(*sink_calls.borrow(), [48]);
+
+        rewriter.write(b"<span>").unwrap();
+        rewriter.end().unwrap();
+
+        // NOTE: the rest of the buffer is flushed on the end, followed by the finalizing chunk.
+        assert_eq!(*sink_calls.borrow(), [48, 16, 0]);
+    }
+
+    #[test]
+    fn unknown_encoding() {
+        let err = HtmlRewriter::try_new(
+            Settings {
+                encoding: "hey-yo",
+                ..Settings::default()
+            },
+            |_: &[u8]| {},
+        )
+        .unwrap_err();
+
+        assert_eq!(err, EncodingError::UnknownEncoding);
+    }
+
+    #[test]
+    fn unknown_output_encoding() {
+        let err = HtmlRewriter::try_new(
+            Settings {
+                output_encoding: Some("hey-yo"),
+                ..Settings::default()
+            },
+            |_: &[u8]| {},
+        )
+        .unwrap_err();
+
+        assert_eq!(err, EncodingError::UnknownEncoding);
+    }
+
+    #[test]
+    fn non_ascii_compatible_encoding() {
+        let err = HtmlRewriter::try_new(
+            Settings {
+                encoding: "utf-16be",
+                ..Settings::default()
+            },
+            |_: &[u8]| {},
+        )
+        .unwrap_err();
+
+        assert_eq!(err, EncodingError::NonAsciiCompatibleEncoding);
+    }
+
+    #[test]
+    fn doctype_info() {
+        for enc in ASCII_COMPATIBLE_ENCODINGS.iter() {
+            let mut doctypes = Vec::default();
+
+            {
+                let mut rewriter = HtmlRewriter::try_new(
+                    Settings {
+                        document_content_handlers: vec![doctype!(|d| {
+                            doctypes.push((d.name(), d.public_id(), d.system_id()));
+                            Ok(())
+                        })],
+                        encoding: enc.name(),
+                        ..Settings::default()
+                    },
+                    |_: &[u8]| {},
+                )
+                .unwrap();
+
+                write_chunks(
                     &mut rewriter,
                     enc,
                     &[
@@ -456,119 +1816,519 @@ mod tests {
                 output.into()
             };
 
-            assert_eq!(
-                actual,
-                concat!(
-                    "<!doctype html>\n",
-                    "<html foo=\"bar\"><test></test>\n",
-                    "   <head foo=\"bar\"><test></test></head>\n",
-                    "   <body foo=\"bar\"><test></test>\n",
-                    "       <div foo=\"bar\"><test></test>Test</div>\n",
-                    "   </body>\n",
-                    "</html>",
-                )
-            );
-        }
+            assert_eq!(
+                actual,
+                concat!(
+                    "<!doctype html>\n",
+                    "<html foo=\"bar\"><test></test>\n",
+                    "   <head foo=\"bar\"><test></test></head>\n",
+                    "   <body foo=\"bar\"><test></test>\n",
+                    "       <div foo=\"bar\"><test></test>Test</div>\n",
+                    "   </body>\n",
+                    "</html>",
+                )
+            );
+        }
+    }
+
+    #[test]
+    fn rewrite_document_content() {
+        for enc in ASCII_COMPATIBLE_ENCODINGS.iter() {
+            let actual: String = {
+                let mut output = Output::new(enc);
+
+                let mut rewriter = HtmlRewriter::try_new(
+                    Settings {
+                        element_content_handlers: vec![],
+                        document_content_handlers: vec![
+                            doc_comments!(|c| {
+                                c.set_text(&(c.text() + "1337")).unwrap();
+                                Ok(())
+                            }),
+                            doc_text!(|c| {
+                                if c.last_in_text_node() {
+                                    c.after("BAZ", ContentType::Text);
+                                }
+
+                                Ok(())
+                            }),
+                        ],
+                        encoding: enc.name(),
+                        ..Settings::default()
+                    },
+                    |c: &[u8]| output.push(c),
+                )
+                .unwrap();
+
+                write_chunks(
+                    &mut rewriter,
+                    enc,
+                    &[
+                        "<!doctype html>\n",
+                        "<!-- hey -->\n",
+                        "<html>\n",
+                        "   <head><!-- aloha --></head>\n",
+                        "   <body>\n",
+                        "       <div>Test</div>\n",
+                        "   </body>\n",
+                        "   <!-- bonjour -->\n",
+                        "</html>Pshhh",
+                    ],
+                );
+
+                output.into()
+            };
+
+            assert_eq!(
+                actual,
+                concat!(
+                    "<!doctype html>\nBAZ",
+                    "<!-- hey 1337-->\nBAZ",
+                    "<html>\n",
+                    "   BAZ<head><!-- aloha 1337--></head>\n",
+                    "   BAZ<body>\n",
+                    "       BAZ<div>TestBAZ</div>\n",
+                    "   BAZ</body>\n",
+                    "   BAZ<!-- bonjour 1337-->\nBAZ",
+                    "</html>PshhhBAZ",
+                )
+            );
+        }
+    }
+
+    #[test]
+    fn handler_invocation_order() {
+        let handlers_executed = Rc::new(RefCell::new(Vec::default()));
+
+        macro_rules! create_handlers {
+            ($sel:expr, $idx:expr) => {
+                element!($sel, {
+                    let handlers_executed = Rc::clone(&handlers_executed);
+
+                    move |_| {
+                        handlers_executed.borrow_mut().push($idx);
+                        Ok(())
+                    }
+                })
+            };
+        }
+
+        let _res = rewrite_str(
+            "<div><span foo></span></div>",
+            RewriteStrSettings {
+                element_content_handlers: vec![
+                    create_handlers!("div span", 0),
+                    create_handlers!("div > span", 1),
+                    create_handlers!("span", 2),
+                    create_handlers!("[foo]", 3),
+                    create_handlers!("div span[foo]", 4),
+                ],
+                ..RewriteStrSettings::default()
+            },
+        )
+        .unwrap();
+
+        assert_eq!(*handlers_executed.borrow(), vec![0, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn handler_priority() {
+        let handlers_executed = Rc::new(RefCell::new(Vec::default()));
+
+        macro_rules! create_handlers {
+            ($sel:expr, $idx:expr, $priority:expr) => {
+                element!($sel, priority = $priority, {
+                    let handlers_executed = Rc::clone(&handlers_executed);
+
+                    move |_| {
+                        handlers_executed.borrow_mut().push($idx);
+                        Ok(())
+                    }
+                })
+            };
+        }
+
+        let _res = rewrite_str(
+            "<div><span foo></span></div>",
+            RewriteStrSettings {
+                element_content_handlers: vec![
+                    create_handlers!("div span", 0, 0),
+                    create_handlers!("div > span", 1, -1),
+                    create_handlers!("span", 2, 5),
+                    create_handlers!("[foo]", 3, 0),
+                    create_handlers!("div span[foo]", 4, 5),
+                ],
+                ..RewriteStrSettings::default()
+            },
+        )
+        .unwrap();
+
+        assert_eq!(*handlers_executed.borrow(), vec![2, 4, 0, 3, 1]);
+    }
+
+    #[test]
+    fn handler_max_depth() {
+        let handlers = |selector: &str, max_depth| {
+            (
+                Cow::Owned(selector.parse::<crate::Selector>().unwrap()),
+                ElementContentHandlers::default()
+                    .element(|el| {
+                        el.set_attribute("x", "")?;
+                        Ok(())
+                    })
+                    .max_depth(max_depth),
+            )
+        };
+
+        let res = rewrite_str(
+            "<div><span><div><span></span></div></span></div><span></span>",
+            RewriteStrSettings {
+                element_content_handlers: vec![handlers("div span", 3), handlers("div", 1)],
+                ..RewriteStrSettings::default()
+            },
+        )
+        .unwrap();
+
+        assert_eq!(
+            res,
+            r#"<div x=""><span x=""><div><span></span></div></span></div><span></span>"#
+        );
+
+        // NOTE: the bound applies to the selectors with the deferred predicates as well.
+        let res = rewrite_str(
+            "<ul><li>1</li><li><ul><li>2</li></ul></li></ul>",
+            RewriteStrSettings {
+                element_content_handlers: vec![handlers("li:last-child", 2)],
+                ..RewriteStrSettings::default()
+            },
+        )
+        .unwrap();
+
+        assert_eq!(
+            res,
+            r#"<ul><li>1</li><li x=""><ul><li>2</li></ul></li></ul>"#
+        );
+    }
+
+    #[test]
+    fn handler_max_matches() {
+        let handlers = |selector: &str, max_matches| {
+            (
+                Cow::Owned(selector.parse::<crate::Selector>().unwrap()),
+                ElementContentHandlers::default()
+                    .element(|el| {
+                        el.set_attribute("x", "")?;
+                        Ok(())
+                    })
+                    .text(|t| {
+                        if t.last_in_text_node() {
+                            t.after("!", ContentType::Text);
+                        }
+                        Ok(())
+                    })
+                    .max_matches(max_matches),
+            )
+        };
+
+        let rewrite = |html: &str, handlers| {
+            rewrite_str(
+                html,
+                RewriteStrSettings {
+                    element_content_handlers: vec![handlers],
+                    ..RewriteStrSettings::default()
+                },
+            )
+            .unwrap()
+        };
+
+        assert_eq!(
+            rewrite("<h1>a</h1><h1>b</h1>", handlers("h1", 1)),
+            r#"<h1 x="">a!</h1><h1>b</h1>"#
+        );
+
+        // NOTE: the element matched by multiple selectors in the list is counted once.
+        assert_eq!(
+            rewrite(
+                r#"<h2 class="t">a</h2><p class="t">b</p><h2>c</h2>"#,
+                handlers("h2, .t", 2)
+            ),
+            r#"<h2 class="t" x="">a!</h2><p class="t" x="">b!</p><h2>c</h2>"#
+        );
+
+        assert_eq!(rewrite("<div>a</div>", handlers("div", 0)), "<div>a</div>");
+
+        // NOTE: the elements with unsatisfied deferred predicates are not counted.
+        assert_eq!(
+            rewrite(
+                "<ul><li>1</li><li>2</li></ul><ul><li>3</li></ul>",
+                handlers("li:last-child", 1)
+            ),
+            r#"<ul><li>1</li><li x="">2!</li></ul><ul><li>3</li></ul>"#
+        );
+    }
+
+    #[test]
+    fn comments_matching() {
+        let res = rewrite_str(
+            "<head><!--build:js--><!--x--><!--build:css--></head><!--build:js-->",
+            RewriteStrSettings {
+                element_content_handlers: vec![comments!(
+                    "head",
+                    matching = |text| text.starts_with("build:"),
+                    |c| {
+                        c.set_text("done")?;
+                        Ok(())
+                    }
+                )],
+                ..RewriteStrSettings::default()
+            },
+        )
+        .unwrap();
+
+        assert_eq!(
+            res,
+            "<head><!--done--><!--x--><!--done--></head><!--build:js-->"
+        );
+    }
+
+    #[test]
+    fn custom_pseudo_classes() {
+        use crate::{PseudoClassElement, PseudoClassPredicate};
+        use std::sync::Arc;
+
+        let resolver = |name: &str, argument: &str| -> Option<PseudoClassPredicate> {
+            let tag_names = argument
+                .split_whitespace()
+                .map(String::from)
+                .collect::<Vec<_>>();
+
+            match name {
+                "tag" => Some(Arc::new(move |el: &PseudoClassElement| {
+                    tag_names.iter().any(|n| n == el.tag_name())
+                })),
+                "flag" => Some(Arc::new(|el: &PseudoClassElement| {
+                    el.get_attribute("a") == Some("1")
+                })),
+                _ => None,
+            }
+        };
+
+        let selector = |selector| {
+            Cow::Owned(crate::Selector::parse_with_pseudo_classes(selector, resolver).unwrap())
+        };
+
+        let res = rewrite_str(
+            r#"<h1 A="1"></h1><my-el a="2"></my-el><div><h1></h1></div>"#,
+            RewriteStrSettings {
+                element_content_handlers: vec![
+                    (
+                        selector(":tag(h1 my-el)"),
+                        ElementContentHandlers::default().element(|el| {
+                            el.set_attribute("x", "")?;
+                            Ok(())
+                        }),
+                    ),
+                    (
+                        selector("div > :not(:flag)"),
+                        ElementContentHandlers::default().element(|el| {
+                            el.set_tag_name("h2")?;
+                            Ok(())
+                        }),
+                    ),
+                ],
+                ..RewriteStrSettings::default()
+            },
+        )
+        .unwrap();
+
+        assert_eq!(
+            res,
+            r#"<h1 A="1" x=""></h1><my-el a="2" x=""></my-el><div><h2 x=""></h2></div>"#
+        );
+    }
+
+    #[test]
+    fn text_pseudo_element_scopes_handlers() {
+        let res = rewrite_str(
+            "<div>Hi<span>there</span><!--42--></div>",
+            RewriteStrSettings {
+                element_content_handlers: vec![(
+                    Cow::Owned("div::text".parse().unwrap()),
+                    ElementContentHandlers::default()
+                        .element(|el| {
+                            el.set_tag_name("p").unwrap();
+                            Ok(())
+                        })
+                        .comments(|c| {
+                            c.remove();
+                            Ok(())
+                        })
+                        .text(|t| {
+                            t.remove();
+                            Ok(())
+                        }),
+                )],
+                ..RewriteStrSettings::default()
+            },
+        )
+        .unwrap();
+
+        assert_eq!(res, "<div><span></span><!--42--></div>");
     }
 
     #[test]
-    fn rewrite_document_content() {
-        for enc in ASCII_COMPATIBLE_ENCODINGS.iter() {
-            let actual: String = {
-                let mut output = Output::new(enc);
+    fn comment_pseudo_element_scopes_handlers() {
+        let res = rewrite_str(
+            "<div class=note>Hi<!--1--><span><!--2--></span></div><!--3--><p>Yo<!--4--></p>",
+            RewriteStrSettings {
+                element_content_handlers: vec![(
+                    Cow::Owned("div.note::comment, p::text".parse().unwrap()),
+                    ElementContentHandlers::default()
+                        .element(|el| {
+                            el.set_attribute("seen", "")?;
+                            Ok(())
+                        })
+                        .comments(|c| {
+                            c.remove();
+                            Ok(())
+                        })
+                        .text(|t| {
+                            t.remove();
+                            Ok(())
+                        }),
+                )],
+                ..RewriteStrSettings::default()
+            },
+        )
+        .unwrap();
 
-                let mut rewriter = HtmlRewriter::try_new(
-                    Settings {
-                        element_content_handlers: vec![],
-                        document_content_handlers: vec![
-                            doc_comments!(|c| {
-                                c.set_text(&(c.text() + "1337")).unwrap();
-                                Ok(())
-                            }),
-                            doc_text!(|c| {
-                                if c.last_in_text_node() {
-                                    c.after("BAZ", ContentType::Text);
-                                }
+        assert_eq!(
+            res,
+            "<div class=note>Hi<span></span></div><!--3--><p><!--4--></p>"
+        );
+    }
 
-                                Ok(())
-                            }),
-                        ],
-                        encoding: enc.name(),
-                        ..Settings::default()
-                    },
-                    |c: &[u8]| output.push(c),
-                )
-                .unwrap();
+    #[test]
+    fn matched_selector_index() {
+        let res = rewrite_str(
+            "<div><span class='foo'></span><b class='foo'></b><i></i></div>",
+            RewriteStrSettings {
+                element_content_handlers: vec![
+                    element!("i, b, .foo", |el| {
+                        let idx = el.matched_selector_index().unwrap();
 
-                write_chunks(
-                    &mut rewriter,
-                    enc,
-                    &[
-                        "<!doctype html>\n",
-                        "<!-- hey -->\n",
-                        "<html>\n",
-                        "   <head><!-- aloha --></head>\n",
-                        "   <body>\n",
-                        "       <div>Test</div>\n",
-                        "   </body>\n",
-                        "   <!-- bonjour -->\n",
-                        "</html>Pshhh",
-                    ],
-                );
+                        el.set_attribute("idx", &idx.to_string())?;
 
-                output.into()
-            };
+                        Ok(())
+                    }),
+                    element!("div", |el| {
+                        assert_eq!(el.matched_selector_index(), Some(0));
 
-            assert_eq!(
-                actual,
-                concat!(
-                    "<!doctype html>\nBAZ",
-                    "<!-- hey 1337-->\nBAZ",
-                    "<html>\n",
-                    "   BAZ<head><!-- aloha 1337--></head>\n",
-                    "   BAZ<body>\n",
-                    "       BAZ<div>TestBAZ</div>\n",
-                    "   BAZ</body>\n",
-                    "   BAZ<!-- bonjour 1337-->\nBAZ",
-                    "</html>PshhhBAZ",
-                )
-            );
-        }
+                        Ok(())
+                    }),
+                ],
+                ..RewriteStrSettings::default()
+            },
+        )
+        .unwrap();
+
+        assert_eq!(
+            res,
+            concat!(
+                "<div><span class='foo' idx=\"2\"></span>",
+                "<b class='foo' idx=\"1\"></b><i idx=\"0\"></i></div>"
+            )
+        );
     }
 
     #[test]
-    fn handler_invocation_order() {
-        let handlers_executed = Rc::new(RefCell::new(Vec::default()));
+    fn reprocessed_content() {
+        let res = rewrite_str(
+            r#"<div><a href="/origin">origin</a></div><p>text</p>"#,
+            RewriteStrSettings {
+                element_content_handlers: vec![
+                    element!("div", |el| {
+                        el.before("<script>x</script>", ContentType::HtmlReprocessed);
+                        el.prepend(
+                            "<script>y</script><a href=/prepend></a>",
+                            ContentType::HtmlReprocessed,
+                        );
+                        el.append("<a href=/append></a>", ContentType::Html);
 
-        macro_rules! create_handlers {
-            ($sel:expr, $idx:expr) => {
-                element!($sel, {
-                    let handlers_executed = Rc::clone(&handlers_executed);
+                        Ok(())
+                    }),
+                    element!("p", |el| {
+                        el.set_inner_content(
+                            "<b><a href=/inner></a></b>",
+                            ContentType::HtmlReprocessed,
+                        );
 
-                    move |_| {
-                        handlers_executed.borrow_mut().push($idx);
                         Ok(())
-                    }
-                })
-            };
-        }
+                    }),
+                    element!("div > a[href], b > a", |el| {
+                        let href = el.get_attribute("href").unwrap();
 
-        let _res = rewrite_str(
-            "<div><span foo></span></div>",
+                        el.set_attribute("href", &format!("https://example.com{}", href))?;
+
+                        Ok(())
+                    }),
+                    element!("script", |el| {
+                        el.remove();
+
+                        Ok(())
+                    }),
+                ],
+                document_content_handlers: vec![end!(|end| {
+                    end.append("<script></script>end", ContentType::HtmlReprocessed);
+
+                    Ok(())
+                })],
+                ..RewriteStrSettings::default()
+            },
+        )
+        .unwrap();
+
+        assert_eq!(
+            res,
+            concat!(
+                "<div><a href=\"https://example.com/prepend\"></a>",
+                "<a href=\"https://example.com/origin\">origin</a><a href=/append></a></div>",
+                "<p><b><a href=\"https://example.com/inner\"></a></b></p>end"
+            )
+        );
+    }
+
+    #[test]
+    fn reprocessed_content_is_a_standalone_fragment() {
+        let res = rewrite_str(
+            "<div><span></span><i></i></div><i></i>",
             RewriteStrSettings {
                 element_content_handlers: vec![
-                    create_handlers!("div span", 0),
-                    create_handlers!("div > span", 1),
-                    create_handlers!("span", 2),
-                    create_handlers!("[foo]", 3),
-                    create_handlers!("div span[foo]", 4),
+                    element!("span", |el| {
+                        el.after("</div><b><i></i>", ContentType::HtmlReprocessed);
+
+                        Ok(())
+                    }),
+                    element!("div > i", |el| {
+                        el.set_attribute("child", "")?;
+
+                        Ok(())
+                    }),
+                    element!("b", |el| {
+                        el.append("!", ContentType::Text);
+
+                        Ok(())
+                    }),
                 ],
                 ..RewriteStrSettings::default()
             },
         )
         .unwrap();
 
-        assert_eq!(*handlers_executed.borrow(), vec![0, 1, 2, 3, 4]);
+        assert_eq!(
+            res,
+            r#"<div><span></span></div><b><i></i><i child=""></i></div><i></i>"#
+        );
     }
 
     mod fatal_errors {
@@ -585,6 +2345,7 @@ mod tests {
                     memory_settings: MemorySettings {
                         max_allowed_memory_usage,
                         preallocated_parsing_buffer_size: 0,
+                        ..MemorySettings::default()
                     },
                     ..Settings::default()
                 },
@@ -614,6 +2375,132 @@ mod tests {
             }
         }
 
+        #[test]
+        fn relaxed_memory_limit() {
+            const MAX: usize = 100;
+
+            let mut rewriter = create_rewriter(MAX, |_: &[u8]| {});
+
+            let chunk_1 = format!("<img alt=\"{}", "l".repeat(MAX / 2));
+            let chunk_2 = format!("{}\" />", "r".repeat(MAX / 2));
+
+            rewriter.write(chunk_1.as_bytes()).unwrap();
+            rewriter.set_max_allowed_memory_usage(MAX * 10);
+            rewriter.write(chunk_2.as_bytes()).unwrap();
+            rewriter.end().unwrap();
+        }
+
+        #[test]
+        #[should_panic(expected = "Memory limit was changed after the stream has ended.")]
+        fn memory_limit_change_after_end() {
+            let mut rewriter = create_rewriter(512, |_: &[u8]| {});
+
+            rewriter.end().unwrap();
+            rewriter.set_max_allowed_memory_usage(1024);
+        }
+
+        #[test]
+        fn buffered_script_text_limit() {
+            const MAX: usize = 100;
+
+            let mut rewriter = HtmlRewriter::try_new(
+                Settings {
+                    element_content_handlers: vec![text!("script", |_| Ok(()))],
+                    memory_settings: MemorySettings {
+                        max_allowed_memory_usage: MAX,
+                        preallocated_parsing_buffer_size: 0,
+                        ..MemorySettings::default()
+                    },
+                    buffer_script_and_style_text: true,
+                    ..Settings::default()
+                },
+                |_: &[u8]| {},
+            )
+            .unwrap();
+
+            let chunk_1 = format!("<script>{}", "a".repeat(MAX / 2));
+            let chunk_2 = "b".repeat(MAX);
+
+            rewriter.write(chunk_1.as_bytes()).unwrap();
+
+            let write_err = rewriter.write(chunk_2.as_bytes()).unwrap_err();
+
+            match write_err {
+                RewritingError::MemoryLimitExceeded(e) => assert_eq!(e, MemoryLimitExceededError),
+                _ => panic!("{}", write_err),
+            }
+        }
+
+        #[test]
+        fn buffered_subtree_limit() {
+            const MAX: usize = 100;
+
+            let mut rewriter = HtmlRewriter::try_new(
+                Settings {
+                    element_content_handlers: vec![element!("div", |el| {
+                        el.buffer_subtree(|_| Ok(()));
+
+                        Ok(())
+                    })],
+                    memory_settings: MemorySettings {
+                        max_allowed_memory_usage: MAX,
+                        preallocated_parsing_buffer_size: 0,
+                        ..MemorySettings::default()
+                    },
+                    ..Settings::default()
+                },
+                |_: &[u8]| {},
+            )
+            .unwrap();
+
+            let chunk_1 = format!("<div>{}", "a".repeat(MAX / 2));
+            let chunk_2 = format!("<p>{}</p>", "b".repeat(MAX));
+
+            rewriter.write(chunk_1.as_bytes()).unwrap();
+
+            let write_err = rewriter.write(chunk_2.as_bytes()).unwrap_err();
+
+            match write_err {
+                RewritingError::MemoryLimitExceeded(e) => assert_eq!(e, MemoryLimitExceededError),
+                _ => panic!("{}", write_err),
+            }
+        }
+
+        #[test]
+        fn accumulated_text_limit() {
+            const MAX: usize = 100;
+
+            let mut rewriter = HtmlRewriter::try_new(
+                Settings {
+                    element_content_handlers: vec![element!("div", |el| {
+                        el.on_inner_text(|_| Ok(()));
+
+                        Ok(())
+                    })],
+                    memory_settings: MemorySettings {
+                        max_allowed_memory_usage: MAX,
+                        preallocated_parsing_buffer_size: 0,
+                        ..MemorySettings::default()
+                    },
+                    ..Settings::default()
+                },
+                |_: &[u8]| {},
+            )
+            .unwrap();
+
+            let chunk_1 = format!("<div>{}", "a".repeat(MAX / 2));
+            let chunk_2 = format!("<p>{}</p>", "b".repeat(MAX));
+
+            rewriter.write(chunk_1.as_bytes()).unwrap();
+
+            let write_err = rewriter.write(chunk_2.as_bytes()).unwrap_err();
+
+            match write_err {
+                RewritingError::MemoryLimitExceeded(e) => assert_eq!(e, MemoryLimitExceededError),
+                _ => panic!("{}", write_err),
+            }
+        }
+
         #[test]
         #[should_panic(expected = "Data was written into the stream after it has ended.")]
         fn write_after_end() {
@@ -632,6 +2519,15 @@ mod tests {
             rewriter.end().unwrap();
         }
 
+        #[test]
+        #[should_panic(expected = "Stream was flushed after it has ended.")]
+        fn flush_after_end() {
+            let mut rewriter = create_rewriter(512, |_: &[u8]| {});
+
+            rewriter.end().unwrap();
+            rewriter.flush();
+        }
+
         #[test]
         #[should_panic(expected = "Attempt to use the HtmlRewriter after a fatal error.")]
         fn poisoning_after_fatal_error() {
@@ -644,6 +2540,24 @@ mod tests {
             rewriter.end().unwrap_err();
         }
 
+        #[test]
+        fn reprocessing_depth_limit() {
+            let err = rewrite_str(
+                "<div></div>",
+                RewriteStrSettings {
+                    element_content_handlers: vec![element!("div", |el| {
+                        el.append("<div></div>", ContentType::HtmlReprocessed);
+
+                        Ok(())
+                    })],
+                    ..RewriteStrSettings::default()
+                },
+            )
+            .unwrap_err();
+
+            assert!(matches!(err, RewritingError::ReprocessingDepthExceeded));
+        }
+
         #[test]
         fn content_handler_error_propagation() {
             fn assert_err(
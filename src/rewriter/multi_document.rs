@@ -0,0 +1,500 @@
+use super::{EncodingError, HtmlRewriter, RewritingError, Settings};
+use crate::memory::MemoryLimitExceededError;
+use crate::transform_stream::OutputSink;
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::fmt::{self, Debug};
+use std::ops::Range;
+use std::rc::Rc;
+use thiserror::Error;
+
+/// An error that can be returned by [`write`] and [`end`] methods of
+/// the [`MultiDocumentRewriter`].
+///
+/// # Note
+/// This error is unrecoverable. The rewriter instance will panic on attempt to use it after such an
+/// error.
+///
+/// [`write`]: ../struct.MultiDocumentRewriter.html#method.write
+/// [`end`]: ../struct.MultiDocumentRewriter.html#method.end
+/// [`MultiDocumentRewriter`]: ../struct.MultiDocumentRewriter.html
+#[derive(Error, Debug)]
+pub enum MultiDocumentRewritingError {
+    /// The settings factory has produced settings with an invalid encoding for a document other
+    /// than the first one.
+    #[error("{0}")]
+    Encoding(#[from] EncodingError),
+
+    /// See [`RewritingError`].
+    ///
+    /// [`RewritingError`]: enum.RewritingError.html
+    #[error("{0}")]
+    Rewriting(#[from] RewritingError),
+}
+
+/// A result of the document boundary detection in the input of a [`MultiDocumentRewriter`].
+///
+/// [`MultiDocumentRewriter`]: struct.MultiDocumentRewriter.html
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DocumentBoundary {
+    /// The input doesn't contain a boundary.
+    None,
+    /// The input ends with a byte sequence that starts at the given offset and can be a beginning
+    /// of a boundary. The sequence is passed to the detector once again with the next chunk of
+    /// the input.
+    Partial(usize),
+    /// The input contains a boundary in the given byte range.
+    Found(Range<usize>),
+}
+
+/// Detects boundaries between the documents in the input of a [`MultiDocumentRewriter`].
+///
+/// # Note
+/// For the convenience the trait is implemented for closures.
+///
+/// [`MultiDocumentRewriter`]: struct.MultiDocumentRewriter.html
+pub trait DocumentBoundaryDetector {
+    /// Looks for the first boundary in the `input`.
+    fn detect(&mut self, input: &[u8]) -> DocumentBoundary;
+}
+
+impl<F: FnMut(&[u8]) -> DocumentBoundary> DocumentBoundaryDetector for F {
+    #[inline]
+    fn detect(&mut self, input: &[u8]) -> DocumentBoundary {
+        self(input)
+    }
+}
+
+/// A [`DocumentBoundaryDetector`] that treats the given byte sequence as a boundary between
+/// the documents.
+///
+/// [`DocumentBoundaryDetector`]: trait.DocumentBoundaryDetector.html
+#[derive(Debug, Clone)]
+pub struct DocumentDelimiter<'d>(pub &'d [u8]);
+
+impl DocumentBoundaryDetector for DocumentDelimiter<'_> {
+    fn detect(&mut self, input: &[u8]) -> DocumentBoundary {
+        let delimiter = self.0;
+
+        if delimiter.is_empty() {
+            return DocumentBoundary::None;
+        }
+
+        if let Some(start) = input
+            .windows(delimiter.len())
+            .position(|window| window == delimiter)
+        {
+            return DocumentBoundary::Found(start..start + delimiter.len());
+        }
+
+        let partial_len = (1..delimiter.len().min(input.len() + 1))
+            .rev()
+            .find(|&len| input.ends_with(&delimiter[..len]));
+
+        match partial_len {
+            Some(len) => DocumentBoundary::Partial(input.len() - len),
+            None => DocumentBoundary::None,
+        }
+    }
+}
+
+struct SharedOutputSink<O: OutputSink>(Rc<RefCell<O>>);
+
+impl<O: OutputSink> OutputSink for SharedOutputSink<O> {
+    #[inline]
+    fn handle_chunk(&mut self, chunk: &[u8]) {
+        // NOTE: each document's rewriter produces the last zero length chunk, but for the
+        // output sink the output ends only once the last document ends.
+        if !chunk.is_empty() {
+            self.0.borrow_mut().handle_chunk(chunk);
+        }
+    }
+}
+
+/// A streaming rewriter for a stream of multiple concatenated HTML documents (e.g. parts of a
+/// MHTML file or captured responses in a log).
+///
+/// Boundaries between the documents are found by the provided [`DocumentBoundaryDetector`].
+/// Each document is rewritten by a separate [`HtmlRewriter`] with the [`Settings`] produced by
+/// the settings factory for the document, so the parsing state is reset between the documents
+/// and the document end handlers are invoked at the end of each document. The factory is
+/// invoked with a zero-based index of the document in the stream. Boundaries are written to the
+/// output as is.
+///
+/// # Example
+/// ```
+/// use lol_html::{element, end, DocumentDelimiter, MultiDocumentRewriter, Settings};
+/// use lol_html::html_content::ContentType;
+///
+/// let mut output = vec![];
+///
+/// {
+///     let mut rewriter = MultiDocumentRewriter::try_new(
+///         |idx| Settings {
+///             element_content_handlers: vec![
+///                 element!("title", move |el| {
+///                     el.prepend(&format!("{}. ", idx + 1), ContentType::Text);
+///
+///                     Ok(())
+///                 })
+///             ],
+///             document_content_handlers: vec![
+///                 end!(|end| {
+///                     end.append("<!-- end -->", ContentType::Html);
+///
+///                     Ok(())
+///                 })
+///             ],
+///             ..Settings::default()
+///         },
+///         DocumentDelimiter(b"\n---\n"),
+///         |c: &[u8]| output.extend_from_slice(c)
+///     ).unwrap();
+///
+///     rewriter.write(b"<title>Foo</title>\n--").unwrap();
+///     rewriter.write(b"-\n<title>Bar</title>").unwrap();
+///     rewriter.end().unwrap();
+/// }
+///
+/// assert_eq!(
+///     String::from_utf8(output).unwrap(),
+///     "<title>1. Foo</title><!-- end -->\n---\n<title>2. Bar</title><!-- end -->"
+/// );
+/// ```
+///
+/// [`DocumentBoundaryDetector`]: trait.DocumentBoundaryDetector.html
+/// [`HtmlRewriter`]: struct.HtmlRewriter.html
+/// [`Settings`]: struct.Settings.html
+pub struct MultiDocumentRewriter<'h, F, D, O: OutputSink> {
+    settings_factory: F,
+    boundary_detector: D,
+    output_sink: Rc<RefCell<O>>,
+    rewriter: HtmlRewriter<'h, SharedOutputSink<O>>,
+    document_idx: usize,
+    pending_input: Vec<u8>,
+}
+
+impl<'h, F, D, O> MultiDocumentRewriter<'h, F, D, O>
+where
+    F: FnMut(usize) -> Settings<'h, 'static>,
+    D: DocumentBoundaryDetector,
+    O: OutputSink,
+{
+    /// Constructs a new rewriter with the provided `settings_factory` and `boundary_detector`
+    /// that writes the output to the `output_sink`.
+    ///
+    /// # Note
+    ///
+    /// The settings for the first document are produced and validated on construction.
+    pub fn try_new(
+        mut settings_factory: F,
+        boundary_detector: D,
+        output_sink: O,
+    ) -> Result<Self, EncodingError> {
+        let output_sink = Rc::new(RefCell::new(output_sink));
+
+        let rewriter = HtmlRewriter::try_new(
+            settings_factory(0),
+            SharedOutputSink(Rc::clone(&output_sink)),
+        )?;
+
+        Ok(MultiDocumentRewriter {
+            settings_factory,
+            boundary_detector,
+            output_sink,
+            rewriter,
+            document_idx: 0,
+            pending_input: Vec::default(),
+        })
+    }
+
+    fn start_next_document(&mut self, boundary: &[u8]) -> Result<(), MultiDocumentRewritingError> {
+        self.rewriter.end()?;
+        self.output_sink.borrow_mut().handle_chunk(boundary);
+        self.document_idx += 1;
+
+        self.rewriter = HtmlRewriter::try_new(
+            (self.settings_factory)(self.document_idx),
+            SharedOutputSink(Rc::clone(&self.output_sink)),
+        )?;
+
+        Ok(())
+    }
+
+    // NOTE: the pending input is counted against the memory limit of the current document's
+    // rewriter, which stays the same until the pending input is taken.
+    fn set_pending_input(&mut self, input: &[u8]) -> Result<(), MemoryLimitExceededError> {
+        self.rewriter
+            .memory_limiter
+            .borrow_mut()
+            .increase_usage(input.len())?;
+
+        self.pending_input.extend_from_slice(input);
+
+        Ok(())
+    }
+
+    fn take_pending_input(&mut self) -> Vec<u8> {
+        self.rewriter
+            .memory_limiter
+            .borrow_mut()
+            .decrease_usage(self.pending_input.len());
+
+        std::mem::take(&mut self.pending_input)
+    }
+
+    /// Writes a chunk of input data to the rewriter.
+    ///
+    /// A byte sequence at the end of the chunk that can be a beginning of a boundary is held
+    /// back until the next chunk, and is counted against the [`max_allowed_memory_usage`] limit
+    /// of the current document.
+    ///
+    /// # Panics
+    ///  * In the same cases as [`HtmlRewriter::write`].
+    ///  * If the boundary detector finds an empty boundary.
+    ///
+    /// [`HtmlRewriter::write`]: struct.HtmlRewriter.html#method.write
+    /// [`max_allowed_memory_usage`]: struct.MemorySettings.html#structfield.max_allowed_memory_usage
+    pub fn write(&mut self, data: &[u8]) -> Result<(), MultiDocumentRewritingError> {
+        let input = if self.pending_input.is_empty() {
+            Cow::Borrowed(data)
+        } else {
+            let mut input = self.take_pending_input();
+
+            input.extend_from_slice(data);
+
+            Cow::Owned(input)
+        };
+
+        let mut input = &input[..];
+
+        loop {
+            match self.boundary_detector.detect(input) {
+                DocumentBoundary::Found(boundary) => {
+                    assert!(
+                        !boundary.is_empty(),
+                        "Document boundary should not be empty."
+                    );
+
+                    self.rewriter.write(&input[..boundary.start])?;
+                    self.start_next_document(&input[boundary.clone()])?;
+
+                    input = &input[boundary.end..];
+                }
+                DocumentBoundary::Partial(start) => {
+                    self.rewriter.write(&input[..start])?;
+                    self.set_pending_input(&input[start..])
+                        .map_err(RewritingError::MemoryLimitExceeded)?;
+
+                    return Ok(());
+                }
+                DocumentBoundary::None => return Ok(self.rewriter.write(input)?),
+            }
+        }
+    }
+
+    /// Finalizes the rewriting process.
+    ///
+    /// Should be called once the last chunk of the input is written.
+    ///
+    /// # Panics
+    ///  * In the same cases as [`HtmlRewriter::end`].
+    ///
+    /// [`HtmlRewriter::end`]: struct.HtmlRewriter.html#method.end
+    pub fn end(&mut self) -> Result<(), MultiDocumentRewritingError> {
+        // NOTE: the input ended, so the pending input is not a boundary.
+        let pending_input = self.take_pending_input();
+
+        self.rewriter.write(&pending_input)?;
+        self.rewriter.end()?;
+        self.output_sink.borrow_mut().handle_chunk(&[]);
+
+        Ok(())
+    }
+}
+
+impl<F, D, O: OutputSink> Debug for MultiDocumentRewriter<'_, F, D, O> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "MultiDocumentRewriter")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::html_content::ContentType;
+    use crate::*;
+
+    fn rewrite(chunks: &[&str], delimiter: &str) -> (String, Vec<usize>) {
+        let mut output = vec![];
+        let ended = Rc::new(RefCell::new(Vec::default()));
+
+        {
+            let mut rewriter = MultiDocumentRewriter::try_new(
+                |idx| Settings {
+                    element_content_handlers: vec![element!("p", move |el| {
+                        el.set_attribute("data-doc", &idx.to_string())?;
+
+                        Ok(())
+                    })],
+                    document_content_handlers: vec![end!({
+                        let ended = Rc::clone(&ended);
+
+                        move |_| {
+                            ended.borrow_mut().push(idx);
+
+                            Ok(())
+                        }
+                    })],
+                    ..Settings::default()
+                },
+                DocumentDelimiter(delimiter.as_bytes()),
+                |c: &[u8]| output.extend_from_slice(c),
+            )
+            .unwrap();
+
+            for chunk in chunks {
+                rewriter.write(chunk.as_bytes()).unwrap();
+            }
+
+            rewriter.end().unwrap();
+        }
+
+        let ended = ended.borrow().clone();
+
+        (String::from_utf8(output).unwrap(), ended)
+    }
+
+    #[test]
+    fn delimiter_detection() {
+        let mut delimiter = DocumentDelimiter(b"<!--EOD-->");
+
+        assert_eq!(delimiter.detect(b"foo"), DocumentBoundary::None);
+        assert_eq!(delimiter.detect(b""), DocumentBoundary::None);
+        assert_eq!(delimiter.detect(b"foo<!--"), DocumentBoundary::Partial(3));
+        assert_eq!(delimiter.detect(b"<!--EO"), DocumentBoundary::Partial(0));
+        assert_eq!(
+            delimiter.detect(b"a<!--EOD-->b<!--EOD-->"),
+            DocumentBoundary::Found(1..11)
+        );
+    }
+
+    #[test]
+    fn multiple_documents() {
+        let (output, ended) = rewrite(
+            &["<p>1</p>", "<!--EOD--><div><p>2</p", "><!--E", "OD--><p>3"],
+            "<!--EOD-->",
+        );
+
+        assert_eq!(
+            output,
+            concat!(
+                r#"<p data-doc="0">1</p><!--EOD-->"#,
+                r#"<div><p data-doc="1">2</p><!--EOD-->"#,
+                r#"<p data-doc="2">3"#
+            )
+        );
+
+        assert_eq!(ended, [0, 1, 2]);
+    }
+
+    #[test]
+    fn state_is_reset_between_documents() {
+        // NOTE: the first document ends in the middle of a tag and a script element,
+        // that shouldn't affect parsing of the following document.
+        let (output, ended) = rewrite(
+            &["<script>if (a <", "\n\n", "<p>Hi</p>\n\n<title><p"],
+            "\n\n",
+        );
+
+        assert_eq!(
+            output,
+            "<script>if (a <\n\n<p data-doc=\"1\">Hi</p>\n\n<title><p"
+        );
+
+        assert_eq!(ended, [0, 1, 2]);
+    }
+
+    #[test]
+    fn pending_input_at_the_end() {
+        let (output, ended) = rewrite(&["<p>Hi</p><!--EO"], "<!--EOD-->");
+
+        assert_eq!(output, r#"<p data-doc="0">Hi</p><!--EO"#);
+        assert_eq!(ended, [0]);
+    }
+
+    #[test]
+    fn closure_detector() {
+        let mut output = vec![];
+
+        {
+            let mut rewriter = MultiDocumentRewriter::try_new(
+                |_| Settings {
+                    document_content_handlers: vec![end!(|end| {
+                        end.append("!", ContentType::Text);
+
+                        Ok(())
+                    })],
+                    ..Settings::default()
+                },
+                |input: &[u8]| match input.iter().position(|&b| b == 0) {
+                    Some(pos) => DocumentBoundary::Found(pos..pos + 1),
+                    None => DocumentBoundary::None,
+                },
+                |c: &[u8]| output.extend_from_slice(c),
+            )
+            .unwrap();
+
+            rewriter.write(b"foo\0bar\0").unwrap();
+            rewriter.end().unwrap();
+        }
+
+        assert_eq!(output, b"foo!\0bar!\0!");
+    }
+
+    #[test]
+    fn invalid_encoding_for_next_document() {
+        let mut rewriter = MultiDocumentRewriter::try_new(
+            |idx| Settings {
+                encoding: if idx == 0 { "utf-8" } else { "foo" },
+                ..Settings::default()
+            },
+            DocumentDelimiter(b"\n---\n"),
+            |_: &[u8]| {},
+        )
+        .unwrap();
+
+        let err = rewriter.write(b"foo\n---\nbar").unwrap_err();
+
+        assert!(matches!(
+            err,
+            MultiDocumentRewritingError::Encoding(EncodingError::UnknownEncoding)
+        ));
+    }
+
+    #[test]
+    fn pending_input_memory_limit() {
+        let mut rewriter = MultiDocumentRewriter::try_new(
+            |_| Settings {
+                memory_settings: MemorySettings {
+                    max_allowed_memory_usage: 6,
+                    preallocated_parsing_buffer_size: 0,
+                    ..MemorySettings::default()
+                },
+                ..Settings::default()
+            },
+            DocumentDelimiter(b"0123456789"),
+            |_: &[u8]| {},
+        )
+        .unwrap();
+
+        rewriter.write(b"0123").unwrap();
+
+        let err = rewriter.write(b"4567").unwrap_err();
+
+        assert!(matches!(
+            err,
+            MultiDocumentRewritingError::Rewriting(RewritingError::MemoryLimitExceeded(_))
+        ));
+    }
+}
@@ -0,0 +1,155 @@
+use super::settings::ElementContentHandlers;
+use crate::rewritable_units::Element;
+use crate::selectors_vm::Selector;
+use std::borrow::Cow;
+use std::error::Error;
+
+/// Creates content handlers that invoke the `handler` once for each element that has at least
+/// one attribute whose name starts with the `prefix`, e.g. `data-` or `aria-`, passing the names
+/// and the values of all such attributes in the order of their appearance.
+///
+/// The prefix is matched ASCII case-insensitively, as the attribute names are. The elements are
+/// filtered by the selector matching VM with the non-standard `:attr-prefix()` pseudo-class, so,
+/// unlike a handler for the universal selector, the handler is not invoked for the elements
+/// without such attributes.
+///
+/// # Example
+///
+/// ```
+/// use lol_html::{prefixed_attributes, rewrite_str, RewriteStrSettings};
+///
+/// let mut datasets = vec![];
+///
+/// rewrite_str(
+///     r#"<div data-id="1" data-kind="card"><p class="x"><i data-id="2"></i></p></div>"#,
+///     RewriteStrSettings {
+///         element_content_handlers: vec![prefixed_attributes("data-", |el, attrs| {
+///             datasets.push((el.tag_name().to_string(), attrs.to_vec()));
+///             Ok(())
+///         })],
+///         ..RewriteStrSettings::default()
+///     },
+/// )
+/// .unwrap();
+///
+/// assert_eq!(
+///     datasets,
+///     [
+///         (
+///             "div".to_string(),
+///             vec![
+///                 ("data-id".to_string(), "1".to_string()),
+///                 ("data-kind".to_string(), "card".to_string()),
+///             ]
+///         ),
+///         ("i".to_string(), vec![("data-id".to_string(), "2".to_string())]),
+///     ]
+/// );
+/// ```
+pub fn prefixed_attributes<'h, F>(
+    prefix: &str,
+    mut handler: F,
+) -> (Cow<'static, Selector>, ElementContentHandlers<'h>)
+where
+    F: FnMut(&mut Element, &[(String, String)]) -> Result<(), Box<dyn Error + Send + Sync>> + 'h,
+{
+    let selector = Selector::attribute_name_prefix(prefix);
+    let prefix = prefix.to_ascii_lowercase();
+
+    let handlers = ElementContentHandlers::default().element(move |el| {
+        let attributes = el
+            .attributes()
+            .iter()
+            .filter(|a| a.name().starts_with(prefix.as_str()))
+            .map(|a| (a.name().to_string(), a.value().to_string()))
+            .collect::<Vec<_>>();
+
+        handler(el, &attributes)
+    });
+
+    (Cow::Owned(selector), handlers)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{element, rewrite_str, RewriteStrSettings};
+
+    fn collect(html: &str, prefix: &str) -> Vec<Vec<(String, String)>> {
+        let mut collected = Vec::new();
+
+        rewrite_str(
+            html,
+            RewriteStrSettings {
+                element_content_handlers: vec![prefixed_attributes(prefix, |_, attrs| {
+                    collected.push(attrs.to_vec());
+                    Ok(())
+                })],
+                ..RewriteStrSettings::default()
+            },
+        )
+        .unwrap();
+
+        collected
+    }
+
+    fn pairs(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|&(n, v)| (n.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn collected_attributes() {
+        assert_eq!(
+            collect(
+                r#"<div aria-label="x" id=a ARIA-Hidden><b aria></b><svg aria-x=1 /></div>"#,
+                "aria-"
+            ),
+            [
+                pairs(&[("aria-label", "x"), ("aria-hidden", "")]),
+                pairs(&[("aria-x", "1")]),
+            ]
+        );
+
+        assert_eq!(collect("<p><b>", "data-"), Vec::<Vec<_>>::new());
+    }
+
+    #[test]
+    fn prefix_with_special_characters() {
+        assert_eq!(
+            collect(r#"<p x"y=1 x'z=2 xy=3>"#, "x\""),
+            [pairs(&[("x\"y", "1")])]
+        );
+    }
+
+    #[test]
+    fn selector() {
+        let html = rewrite_str(
+            r#"<p data-a=1></p><p DATA-b></p><p data=2></p><p><b data-c></b></p>"#,
+            RewriteStrSettings {
+                element_content_handlers: vec![
+                    element!("p:attr-prefix(data-)", |el| {
+                        el.set_attribute("p", "")?;
+                        Ok(())
+                    }),
+                    element!(r#":not(:attr-prefix("data-"))"#, |el| {
+                        el.set_attribute("n", "")?;
+                        Ok(())
+                    }),
+                ],
+                ..RewriteStrSettings::default()
+            },
+        )
+        .unwrap();
+
+        assert_eq!(
+            html,
+            concat!(
+                r#"<p data-a=1 p=""></p><p DATA-b p=""></p><p data=2 n=""></p>"#,
+                r#"<p n=""><b data-c></b></p>"#
+            )
+        );
+    }
+}
@@ -0,0 +1,136 @@
+#[cfg(feature = "element_handlers")]
+use super::settings::ElementContentHandlers;
+use super::settings::ProgressHook;
+#[cfg(feature = "element_handlers")]
+use std::cell::RefCell;
+#[cfg(feature = "element_handlers")]
+use std::mem;
+#[cfg(feature = "element_handlers")]
+use std::rc::Rc;
+
+/// An event reported to the [`on_progress`] observability hook.
+///
+/// [`on_progress`]: struct.ObservabilityHooks.html#method.on_progress
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProgressEvent<'a> {
+    /// The total size of the output passed to the output sink has reached another multiple of
+    /// the output watermark.
+    OutputWatermark {
+        /// The total size of the output passed to the output sink so far.
+        output_size: usize,
+    },
+    /// The end tag of a top-level element has been serialized.
+    TopLevelElementEnd {
+        /// The tag name of the element.
+        tag_name: &'a str,
+    },
+}
+
+/// Tracks the open elements of the document to detect the end of the top-level ones, i.e.
+/// the elements that are not nested in any other element and the children of `<html>`.
+///
+/// The elements are closed in the same way as by the selector matching VM, i.e. an end tag
+/// closes all the elements opened after the element it belongs to.
+#[cfg(feature = "element_handlers")]
+#[derive(Default)]
+struct TopLevelElementsTracker {
+    open_elements: Vec<(usize, String)>,
+    next_id: usize,
+    closed: Vec<String>,
+}
+
+#[cfg(feature = "element_handlers")]
+impl TopLevelElementsTracker {
+    fn open(&mut self, tag_name: String) -> usize {
+        let id = self.next_id;
+
+        self.next_id += 1;
+        self.open_elements.push((id, tag_name));
+
+        id
+    }
+
+    fn close(&mut self, id: usize) {
+        if let Some(idx) = self.open_elements.iter().position(|&(i, _)| i == id) {
+            let is_top_level = idx == 0 || (idx == 1 && self.open_elements[0].1 == "html");
+
+            if let Some((_, tag_name)) = self.open_elements.drain(idx..).next() {
+                if is_top_level {
+                    self.closed.push(tag_name);
+                }
+            }
+        }
+    }
+}
+
+/// Reports the progress of the rewriting to the progress hook.
+pub(super) struct Progress<'h> {
+    hook: ProgressHook<'h>,
+    output_watermark: usize,
+    output_size: usize,
+    #[cfg(feature = "element_handlers")]
+    top_level_elements: Rc<RefCell<TopLevelElementsTracker>>,
+}
+
+impl<'h> Progress<'h> {
+    pub fn new(output_watermark: usize, hook: ProgressHook<'h>) -> Self {
+        Progress {
+            hook,
+            output_watermark,
+            output_size: 0,
+            #[cfg(feature = "element_handlers")]
+            top_level_elements: Rc::default(),
+        }
+    }
+
+    /// Creates the handlers that track the top-level elements. The handlers should be
+    /// registered for all the elements.
+    #[cfg(feature = "element_handlers")]
+    pub fn element_handlers(&self) -> ElementContentHandlers<'static> {
+        let tracker = Rc::clone(&self.top_level_elements);
+
+        ElementContentHandlers::default().element(move |el| {
+            if el.can_have_content() {
+                let id = tracker.borrow_mut().open(el.tag_name().to_owned());
+                let tracker = Rc::clone(&tracker);
+
+                el.add_end_tag_hook(move || tracker.borrow_mut().close(id));
+            }
+
+            Ok(())
+        })
+    }
+
+    #[inline]
+    pub fn add_output(&mut self, size: usize) {
+        let output_size = self.output_size + size;
+
+        if self.output_watermark > 0
+            && output_size / self.output_watermark > self.output_size / self.output_watermark
+        {
+            (self.hook)(ProgressEvent::OutputWatermark { output_size });
+        }
+
+        self.output_size = output_size;
+    }
+
+    // NOTE: the end tag hooks run before the end tag is serialized, so the closed elements are
+    // reported once the output that follows the hooks is passed to the output sink.
+    #[inline]
+    pub fn report_closed_elements(&mut self) {
+        #[cfg(feature = "element_handlers")]
+        {
+            if self.top_level_elements.borrow().closed.is_empty() {
+                return;
+            }
+
+            let closed = mem::take(&mut self.top_level_elements.borrow_mut().closed);
+
+            for tag_name in closed {
+                (self.hook)(ProgressEvent::TopLevelElementEnd {
+                    tag_name: &tag_name,
+                });
+            }
+        }
+    }
+}
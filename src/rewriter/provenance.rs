@@ -0,0 +1,173 @@
+use super::settings::DocumentContentHandlers;
+#[cfg(feature = "element_handlers")]
+use super::settings::ElementContentHandlers;
+use std::rc::Rc;
+
+#[inline]
+fn label(prefix: &str, idx: usize, offset: Option<usize>) -> Rc<str> {
+    match offset {
+        Some(offset) => format!("{}#{}@{}", prefix, idx, offset).into(),
+        None => format!("{}#{}", prefix, idx).into(),
+    }
+}
+
+/// Wraps the element content `handlers` registered under the `idx` in the settings, so the content
+/// inserted by them is labeled with the provenance comments.
+#[cfg(feature = "element_handlers")]
+pub(super) fn element_content_handlers<'h>(
+    handlers: ElementContentHandlers<'h>,
+    idx: usize,
+) -> ElementContentHandlers<'h> {
+    let mut wrapped = ElementContentHandlers::default();
+
+    if let Some(mut handler) = handlers.element {
+        wrapped = wrapped.element(move |el| {
+            let offset = el.source_offset();
+
+            el.set_provenance(Some(label("rule", idx, Some(offset))));
+
+            let result = handler(el);
+
+            el.set_provenance(None);
+
+            result
+        });
+    }
+
+    if let Some(mut handler) = handlers.comments {
+        wrapped = wrapped.comments(move |comment| {
+            comment.set_provenance(Some(label("rule", idx, None)));
+
+            let result = handler(comment);
+
+            comment.set_provenance(None);
+
+            result
+        });
+    }
+
+    if let Some(mut handler) = handlers.text {
+        wrapped = wrapped.text(move |text| {
+            let offset = text.byte_range().start;
+
+            text.set_provenance(Some(label("rule", idx, Some(offset))));
+
+            let result = handler(text);
+
+            text.set_provenance(None);
+
+            result
+        });
+    }
+
+    wrapped.max_matches = handlers.max_matches;
+
+    wrapped
+}
+
+/// Wraps the document content `handlers` registered under the `idx` in the settings, so the
+/// content inserted by them is labeled with the provenance comments.
+pub(super) fn document_content_handlers<'h>(
+    handlers: DocumentContentHandlers<'h>,
+    idx: usize,
+) -> DocumentContentHandlers<'h> {
+    // NOTE: doctypes can't have content inserted around them.
+    let mut wrapped = DocumentContentHandlers {
+        doctype: handlers.doctype,
+        ..DocumentContentHandlers::default()
+    };
+
+    if let Some(mut handler) = handlers.comments {
+        wrapped = wrapped.comments(move |comment| {
+            comment.set_provenance(Some(label("doc", idx, None)));
+
+            let result = handler(comment);
+
+            comment.set_provenance(None);
+
+            result
+        });
+    }
+
+    if let Some(mut handler) = handlers.text {
+        wrapped = wrapped.text(move |text| {
+            let offset = text.byte_range().start;
+
+            text.set_provenance(Some(label("doc", idx, Some(offset))));
+
+            let result = handler(text);
+
+            text.set_provenance(None);
+
+            result
+        });
+    }
+
+    if let Some(handler) = handlers.end {
+        // NOTE: the end handler is invoked only once.
+        let mut handler = Some(handler);
+
+        wrapped = wrapped.end(move |end| {
+            end.set_provenance(Some(label("doc", idx, None)));
+
+            handler.take().map_or(Ok(()), |handler| handler(end))
+        });
+    }
+
+    wrapped
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::html_content::ContentType;
+    use crate::*;
+
+    #[test]
+    fn labels_inserted_content() {
+        let html = rewrite_str(
+            "<div><p>Hi</p><!-- c --></div>",
+            RewriteStrSettings {
+                element_content_handlers: vec![
+                    element!("div", |el| {
+                        el.set_attribute("class", "x")?;
+                        Ok(())
+                    }),
+                    element!("p", |el| {
+                        el.before("<hr>", ContentType::Html);
+                        el.append("!", ContentType::Text);
+                        Ok(())
+                    }),
+                    text!("p", |t| {
+                        if !t.last_in_text_node() {
+                            t.replace("Hello", ContentType::Text);
+                        }
+                        Ok(())
+                    }),
+                ],
+                document_content_handlers: vec![
+                    doc_comments!(|c| {
+                        c.remove();
+                        Ok(())
+                    }),
+                    end!(|end| {
+                        end.append("<footer>", ContentType::Html);
+                        Ok(())
+                    }),
+                ],
+                provenance_comments: true,
+                ..RewriteStrSettings::default()
+            },
+        )
+        .unwrap();
+
+        assert_eq!(
+            html,
+            concat!(
+                r#"<div class="x"><!--lolhtml:rule#1@5--><hr><!--/lolhtml:rule#1@5--><p>"#,
+                "<!--lolhtml:rule#2@8-->Hello<!--/lolhtml:rule#2@8-->",
+                "<!--lolhtml:rule#1@5-->!<!--/lolhtml:rule#1@5--></p></div>",
+                "<!--lolhtml:doc#1--><footer><!--/lolhtml:doc#1-->"
+            )
+        );
+    }
+}
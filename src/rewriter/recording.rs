@@ -0,0 +1,520 @@
+#[cfg(feature = "legacy_encodings")]
+use super::transcoder::Transcoder;
+use super::{try_encoding_from_str, EncodingError};
+use crate::transform_stream::OutputSink;
+use encoding_rs::Encoding;
+use std::ops::Range;
+use thiserror::Error;
+
+const MAGIC: &[u8] = b"LOLR\x01";
+const COPY_TAG: u8 = 0;
+const INSERT_TAG: u8 = 1;
+const END_TAG: u8 = 2;
+
+/// An error that can occur on the replay of a [`Recording`].
+///
+/// [`Recording`]: ../struct.Recording.html
+#[derive(Error, Debug, PartialEq, Copy, Clone)]
+pub enum ReplayError {
+    /// The recording is malformed, e.g. it has been truncated or produced by an incompatible
+    /// version of the crate.
+    #[error("The recording is malformed.")]
+    MalformedRecording,
+
+    /// The input doesn't have the length of the input the recording has been made for.
+    #[error("The input doesn't match the recorded input.")]
+    InputMismatch,
+
+    /// See [`EncodingError`].
+    ///
+    /// [`EncodingError`]: enum.EncodingError.html
+    #[error("{0}")]
+    Encoding(#[from] EncodingError),
+}
+
+#[inline]
+fn write_varint(log: &mut Vec<u8>, mut value: usize) {
+    while value >= 0x80 {
+        log.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+
+    log.push(value as u8);
+}
+
+#[inline]
+fn read_varint(log: &[u8], pos: &mut usize) -> Result<usize, ReplayError> {
+    let mut value = 0usize;
+
+    for shift in (0..usize::MAX.count_ones()).step_by(7) {
+        let byte = *log.get(*pos).ok_or(ReplayError::MalformedRecording)?;
+
+        *pos += 1;
+        value |= usize::from(byte & 0x7F)
+            .checked_shl(shift)
+            .ok_or(ReplayError::MalformedRecording)?;
+
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+
+    Err(ReplayError::MalformedRecording)
+}
+
+/// A record of the [`Recording`].
+enum Record<'r> {
+    Copy(Range<usize>),
+    Insert(&'r [u8]),
+    End { input_len: usize },
+}
+
+/// An [`OutputSink`] that records the output of the [`HtmlRewriter`] to a compact binary log,
+/// so the rewriting of the same input can be replayed later without running the content
+/// handlers, e.g. to cache the output for the repeated fragments like shared headers and footers.
+///
+/// Parts of the output that are unmodified copies of the input are recorded as references
+/// to the input, so the size of the log is proportional to the size of the modifications
+/// rather than of the whole output. Therefore the replay requires the original input. The
+/// [`Recording`] is handed over to the `recording_handler` once the output ends.
+///
+/// # Note
+/// The recorded rewriter shouldn't use the [`output_encoding`] setting, the output encoding
+/// can be specified on the replay instead.
+///
+/// # Example
+/// ```
+/// use lol_html::{element, HtmlRewriter, Recording, Settings, TokenRecorder};
+/// use lol_html::html_content::ContentType;
+///
+/// let input = b"<header><nav></nav></header>";
+/// let mut log = vec![];
+///
+/// {
+///     let mut rewriter = HtmlRewriter::try_new(
+///         Settings {
+///             element_content_handlers: vec![element!("nav", |el| {
+///                 el.set_inner_content("<a href=/>Home</a>", ContentType::Html);
+///                 Ok(())
+///             })],
+///             ..Settings::default()
+///         },
+///         TokenRecorder::try_new("utf-8", |recording| log = recording.into_bytes()).unwrap(),
+///     )
+///     .unwrap();
+///
+///     rewriter.write(input).unwrap();
+///     rewriter.end().unwrap();
+/// }
+///
+/// let mut output = vec![];
+///
+/// Recording::from_bytes(log)
+///     .replay(input, |c: &[u8]| output.extend_from_slice(c))
+///     .unwrap();
+///
+/// assert_eq!(output, b"<header><nav><a href=/>Home</a></nav></header>");
+/// ```
+///
+/// [`OutputSink`]: trait.OutputSink.html
+/// [`HtmlRewriter`]: struct.HtmlRewriter.html
+/// [`Recording`]: struct.Recording.html
+/// [`output_encoding`]: struct.Settings.html#structfield.output_encoding
+pub struct TokenRecorder<F: FnMut(Recording)> {
+    recording_handler: F,
+    log: Vec<u8>,
+    pending_copy: Option<Range<usize>>,
+    pending_insertion: Vec<u8>,
+}
+
+impl<F: FnMut(Recording)> TokenRecorder<F> {
+    /// Creates a recorder for the output of the rewriter that processes the document in the
+    /// `encoding` and passes the recording to the `recording_handler`. The encoding is stored
+    /// in the recording, so the recording can be replayed with a different output encoding.
+    pub fn try_new(encoding: &str, recording_handler: F) -> Result<Self, EncodingError> {
+        let encoding = try_encoding_from_str(encoding)?;
+        let name = encoding.name().as_bytes();
+        let mut log = MAGIC.to_vec();
+
+        write_varint(&mut log, name.len());
+        log.extend_from_slice(name);
+
+        Ok(TokenRecorder {
+            recording_handler,
+            log,
+            pending_copy: None,
+            pending_insertion: Vec::default(),
+        })
+    }
+
+    fn flush(&mut self) {
+        if let Some(range) = self.pending_copy.take() {
+            self.log.push(COPY_TAG);
+            write_varint(&mut self.log, range.start);
+            write_varint(&mut self.log, range.len());
+        }
+
+        if !self.pending_insertion.is_empty() {
+            self.log.push(INSERT_TAG);
+            write_varint(&mut self.log, self.pending_insertion.len());
+            self.log.append(&mut self.pending_insertion);
+        }
+    }
+}
+
+impl<F: FnMut(Recording)> OutputSink for TokenRecorder<F> {
+    #[inline]
+    fn handle_chunk(&mut self, chunk: &[u8]) {
+        if chunk.is_empty() {
+            // NOTE: the last chunk of the output.
+            self.flush();
+
+            (self.recording_handler)(Recording {
+                log: std::mem::take(&mut self.log),
+            });
+        } else {
+            if self.pending_copy.is_some() {
+                self.flush();
+            }
+
+            self.pending_insertion.extend_from_slice(chunk);
+        }
+    }
+
+    #[inline]
+    fn handle_unmodified_chunk(&mut self, chunk: &[u8], input_offset: usize) {
+        if chunk.is_empty() {
+            // NOTE: the input has ended, but content can still be appended to the document
+            // end, so the end record is followed by the records of the appended content.
+            self.flush();
+            self.log.push(END_TAG);
+            write_varint(&mut self.log, input_offset);
+        } else {
+            if !self.pending_insertion.is_empty() {
+                self.flush();
+            }
+
+            match self.pending_copy {
+                Some(ref mut range) if range.end == input_offset => range.end += chunk.len(),
+                _ => {
+                    self.flush();
+                    self.pending_copy = Some(input_offset..input_offset + chunk.len());
+                }
+            }
+        }
+    }
+}
+
+/// A binary log of the rewriter's output produced by the [`TokenRecorder`].
+///
+/// [`TokenRecorder`]: struct.TokenRecorder.html
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Recording {
+    log: Vec<u8>,
+}
+
+impl Recording {
+    /// Creates a recording from the `bytes` previously obtained with [`into_bytes`]. The bytes
+    /// are validated on the replay.
+    ///
+    /// [`into_bytes`]: #method.into_bytes
+    #[inline]
+    pub fn from_bytes(bytes: Vec<u8>) -> Self {
+        Recording { log: bytes }
+    }
+
+    /// Returns the binary representation of the recording.
+    #[inline]
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.log
+    }
+
+    /// Converts the recording into its binary representation, e.g. to store it in a cache.
+    #[inline]
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.log
+    }
+
+    /// Returns the encoding of the recorded document and the position of the first record.
+    fn header(&self) -> Result<(&'static Encoding, usize), ReplayError> {
+        if !self.log.starts_with(MAGIC) {
+            return Err(ReplayError::MalformedRecording);
+        }
+
+        let mut pos = MAGIC.len();
+        let name_len = read_varint(&self.log, &mut pos)?;
+
+        let name = self
+            .log
+            .get(pos..pos + name_len)
+            .ok_or(ReplayError::MalformedRecording)?;
+
+        let encoding =
+            Encoding::for_label_no_replacement(name).ok_or(ReplayError::MalformedRecording)?;
+
+        Ok((encoding, pos + name_len))
+    }
+
+    fn read_record(&self, pos: &mut usize) -> Result<Record, ReplayError> {
+        let tag = *self.log.get(*pos).ok_or(ReplayError::MalformedRecording)?;
+
+        *pos += 1;
+
+        match tag {
+            COPY_TAG => {
+                let start = read_varint(&self.log, pos)?;
+                let len = read_varint(&self.log, pos)?;
+                let end = start
+                    .checked_add(len)
+                    .ok_or(ReplayError::MalformedRecording)?;
+
+                Ok(Record::Copy(start..end))
+            }
+            INSERT_TAG => {
+                let len = read_varint(&self.log, pos)?;
+
+                let content = pos
+                    .checked_add(len)
+                    .and_then(|end| self.log.get(*pos..end))
+                    .ok_or(ReplayError::MalformedRecording)?;
+
+                *pos += len;
+
+                Ok(Record::Insert(content))
+            }
+            END_TAG => Ok(Record::End {
+                input_len: read_varint(&self.log, pos)?,
+            }),
+            _ => Err(ReplayError::MalformedRecording),
+        }
+    }
+
+    /// Validates the records against the `input`, so nothing is written to the output sink
+    /// if the replay fails.
+    fn validate(&self, input: &[u8], mut pos: usize) -> Result<(), ReplayError> {
+        let mut ended = false;
+
+        while pos < self.log.len() {
+            match self.read_record(&mut pos)? {
+                Record::Copy(range) if range.end > input.len() => {
+                    return Err(ReplayError::InputMismatch)
+                }
+                Record::End { input_len } if input_len != input.len() => {
+                    return Err(ReplayError::InputMismatch)
+                }
+                Record::End { .. } if ended => return Err(ReplayError::MalformedRecording),
+                Record::End { .. } => ended = true,
+                _ => (),
+            }
+        }
+
+        if ended {
+            Ok(())
+        } else {
+            Err(ReplayError::MalformedRecording)
+        }
+    }
+
+    fn emit(&self, input: &[u8], mut pos: usize, output_sink: &mut impl OutputSink) {
+        while pos < self.log.len() {
+            // NOTE: the records have been validated.
+            match self.read_record(&mut pos) {
+                Ok(Record::Copy(range)) => {
+                    output_sink.handle_unmodified_chunk(&input[range.clone()], range.start)
+                }
+                Ok(Record::Insert(content)) => output_sink.handle_chunk(content),
+                Ok(Record::End { input_len }) => {
+                    output_sink.handle_unmodified_chunk(&[], input_len)
+                }
+                Err(_) => unreachable!("Records should be validated before the replay"),
+            }
+        }
+
+        output_sink.handle_chunk(&[]);
+    }
+
+    /// Replays the recorded output of the rewriting of the `input` to the `output_sink`.
+    ///
+    /// The `input` should be the same as the one the recording has been made for. Only
+    /// the length of the input is verified, so it's up to the caller to make sure that
+    /// the recording is replayed for the same content, e.g. by using a hash of the content as
+    /// the cache key.
+    pub fn replay<O: OutputSink>(
+        &self,
+        input: &[u8],
+        mut output_sink: O,
+    ) -> Result<(), ReplayError> {
+        let (_, pos) = self.header()?;
+
+        self.validate(input, pos)?;
+        self.emit(input, pos, &mut output_sink);
+
+        Ok(())
+    }
+
+    /// Same as [`replay`], but converts the output to the `output_encoding`. Characters that
+    /// can't be represented in the output encoding are replaced with numeric character
+    /// references.
+    ///
+    /// [`replay`]: #method.replay
+    #[cfg(feature = "legacy_encodings")]
+    pub fn replay_with_output_encoding<O: OutputSink>(
+        &self,
+        input: &[u8],
+        output_encoding: &str,
+        mut output_sink: O,
+    ) -> Result<(), ReplayError> {
+        let (encoding, pos) = self.header()?;
+        let output_encoding = try_encoding_from_str(output_encoding)?;
+
+        self.validate(input, pos)?;
+
+        if encoding == output_encoding {
+            self.emit(input, pos, &mut output_sink);
+        } else {
+            let mut transcoder = Transcoder::new(encoding, output_encoding);
+
+            self.emit(input, pos, &mut |chunk: &[u8]| {
+                let transcoded = transcoder.transcode(chunk);
+
+                if !transcoded.is_empty() {
+                    output_sink.handle_chunk(transcoded);
+                }
+
+                if chunk.is_empty() {
+                    output_sink.handle_chunk(&[]);
+                }
+            });
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::html_content::ContentType;
+    use crate::*;
+
+    fn settings(encoding: &'static str) -> Settings<'static, 'static> {
+        Settings {
+            element_content_handlers: vec![
+                element!("b", |el| {
+                    el.set_attribute("class", "bold")?;
+                    Ok(())
+                }),
+                text!("i", |t| {
+                    t.replace(&t.as_str().to_uppercase(), ContentType::Text);
+                    Ok(())
+                }),
+            ],
+            document_content_handlers: vec![end!(|end| {
+                end.append("<!-- end -->", ContentType::Html);
+                Ok(())
+            })],
+            encoding,
+            ..Settings::default()
+        }
+    }
+
+    fn record(input: &[u8], encoding: &'static str, chunk_size: usize) -> (Recording, Vec<u8>) {
+        let mut recording = None;
+        let mut output = vec![];
+
+        {
+            let recorder = TokenRecorder::try_new(encoding, |r| recording = Some(r)).unwrap();
+            let mut rewriter = HtmlRewriter::try_new(settings(encoding), recorder).unwrap();
+
+            for chunk in input.chunks(chunk_size) {
+                rewriter.write(chunk).unwrap();
+            }
+
+            rewriter.end().unwrap();
+        }
+
+        {
+            let mut rewriter =
+                HtmlRewriter::try_new(settings(encoding), |c: &[u8]| output.extend_from_slice(c))
+                    .unwrap();
+
+            rewriter.write(input).unwrap();
+            rewriter.end().unwrap();
+        }
+
+        (recording.unwrap(), output)
+    }
+
+    fn replay(recording: &Recording, input: &[u8]) -> Result<Vec<u8>, ReplayError> {
+        let mut output = vec![];
+
+        recording.replay(input, |c: &[u8]| output.extend_from_slice(c))?;
+
+        Ok(output)
+    }
+
+    #[test]
+    fn replays_output() {
+        let input = b"<div><b>foo</b> bar <i>baz</i></div>";
+
+        for chunk_size in 1..10 {
+            let (recording, expected) = record(input, "utf-8", chunk_size);
+
+            assert_eq!(replay(&recording, input).unwrap(), expected);
+
+            let recording = Recording::from_bytes(recording.into_bytes());
+
+            assert_eq!(replay(&recording, input).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn compact_log() {
+        let input = format!("<div>{}<i>baz</i></div>", "a".repeat(1000));
+        let (recording, _) = record(input.as_bytes(), "utf-8", 1024);
+
+        assert!(recording.as_bytes().len() < 64);
+    }
+
+    #[test]
+    fn invalid_replays() {
+        let input = b"<div><b>foo</b></div>";
+        let (recording, _) = record(input, "utf-8", 1024);
+
+        assert_eq!(
+            replay(&recording, b"<div>").unwrap_err(),
+            ReplayError::InputMismatch
+        );
+
+        let mut truncated = recording.into_bytes();
+
+        truncated.pop();
+
+        assert_eq!(
+            replay(&Recording::from_bytes(truncated), input).unwrap_err(),
+            ReplayError::MalformedRecording
+        );
+
+        assert_eq!(
+            replay(&Recording::from_bytes(b"foo".to_vec()), input).unwrap_err(),
+            ReplayError::MalformedRecording
+        );
+    }
+
+    #[cfg(feature = "legacy_encodings")]
+    #[test]
+    fn replay_with_output_encoding() {
+        let input = b"<p>\xCF\xF0\xE8\xE2\xE5\xF2 <i>\xEC\xE8\xF0</i></p>";
+        let (recording, _) = record(input, "windows-1251", 1024);
+        let mut output = vec![];
+
+        recording
+            .replay_with_output_encoding(input, "utf-8", |c: &[u8]| output.extend_from_slice(c))
+            .unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "<p>Привет <i>МИР</i></p><!-- end -->"
+        );
+    }
+}
@@ -1,20 +1,44 @@
-use super::handlers_dispatcher::{ContentHandlersDispatcher, SelectorHandlersLocator};
+#[cfg(feature = "element_handlers")]
+use super::handlers_dispatcher::SelectorHandlersLocator;
+use super::handlers_dispatcher::{AnyHandlers, ContentHandlersDispatcher, HandlersLayout};
+#[cfg(feature = "element_handlers")]
+use super::runtime_handlers::RuntimeHandlersState;
+use super::settings::TokenHook;
 use super::RewritingError;
 use crate::html::{LocalName, Namespace};
-use crate::rewritable_units::{DocumentEnd, Token, TokenCaptureFlags};
+use crate::rewritable_units::{DocumentEnd, Mutations, Token, TokenCaptureFlags};
+#[cfg(feature = "element_handlers")]
 use crate::selectors_vm::{AuxStartTagInfoRequest, ElementData, SelectorMatchingVm, VmError};
 use crate::transform_stream::*;
+use encoding_rs::Encoding;
+#[cfg(feature = "element_handlers")]
 use hashbrown::HashSet;
 use std::cell::RefCell;
 use std::rc::Rc;
 
+/// The maximal nesting depth of the inserted content that is run through the content handlers.
+const MAX_REPROCESSING_DEPTH: usize = 16;
+
+/// The settings of the parser that the inserted content of the `HtmlReprocessed` type is
+/// run through.
+#[derive(Clone, Copy)]
+pub struct ReprocessingSettings {
+    pub encoding: &'static Encoding,
+    pub strict: bool,
+    pub buffer_script_and_style_text: bool,
+}
+
 #[derive(Default)]
 pub struct ElementDescriptor {
+    #[cfg(feature = "element_handlers")]
     pub matched_content_handlers: HashSet<SelectorHandlersLocator>,
     pub end_tag_handler_idx: Option<usize>,
+    pub text_accumulator_idx: Option<usize>,
+    pub subtree_builder_idx: Option<usize>,
     pub remove_content: bool,
 }
 
+#[cfg(feature = "element_handlers")]
 impl ElementData for ElementDescriptor {
     type MatchPayload = SelectorHandlersLocator;
 
@@ -24,26 +48,54 @@ impl ElementData for ElementDescriptor {
     }
 }
 
-pub struct HtmlRewriteController<'h> {
-    handlers_dispatcher: Rc<RefCell<ContentHandlersDispatcher<'h>>>,
+pub struct HtmlRewriteController<'h, L: HandlersLayout = AnyHandlers> {
+    handlers_dispatcher: Rc<RefCell<ContentHandlersDispatcher<'h, L>>>,
+    #[cfg(feature = "element_handlers")]
     selector_matching_vm: Option<SelectorMatchingVm<ElementDescriptor>>,
+    #[cfg(feature = "element_handlers")]
+    runtime_handlers: Option<RuntimeHandlersState<'h>>,
+    token_hook: Option<TokenHook<'h>>,
+    emit_output: bool,
+    reprocessing_settings: ReprocessingSettings,
+    reprocessing_depth: usize,
 }
 
-impl<'h> HtmlRewriteController<'h> {
+impl<'h, L: HandlersLayout> HtmlRewriteController<'h, L> {
     #[inline]
     pub fn new(
-        handlers_dispatcher: ContentHandlersDispatcher<'h>,
-        selector_matching_vm: Option<SelectorMatchingVm<ElementDescriptor>>,
+        handlers_dispatcher: ContentHandlersDispatcher<'h, L>,
+        #[cfg(feature = "element_handlers")] selector_matching_vm: Option<
+            SelectorMatchingVm<ElementDescriptor>,
+        >,
+        #[cfg(feature = "element_handlers")] runtime_handlers: Option<RuntimeHandlersState<'h>>,
+        token_hook: Option<TokenHook<'h>>,
+        emit_output: bool,
+        reprocessing_settings: ReprocessingSettings,
     ) -> Self {
-        HtmlRewriteController {
+        #[cfg_attr(not(feature = "element_handlers"), allow(unused_mut))]
+        let mut controller = HtmlRewriteController {
             handlers_dispatcher: Rc::new(RefCell::new(handlers_dispatcher)),
+            #[cfg(feature = "element_handlers")]
             selector_matching_vm,
-        }
+            #[cfg(feature = "element_handlers")]
+            runtime_handlers,
+            token_hook,
+            emit_output,
+            reprocessing_settings,
+            reprocessing_depth: 0,
+        };
+
+        // NOTE: the selectors with the match limit of `0` shouldn't match anything.
+        #[cfg(feature = "element_handlers")]
+        controller.drop_selectors_with_reached_match_limits();
+
+        controller
     }
 }
 
 // NOTE: it's a macro instead of an instance method, so it can be executed
 // when we hold a mutable reference for the selector matching VM.
+#[cfg(feature = "element_handlers")]
 macro_rules! create_match_handler {
     ($self:tt) => {{
         let handlers_dispatcher = Rc::clone(&$self.handlers_dispatcher);
@@ -52,7 +104,8 @@ macro_rules! create_match_handler {
     }};
 }
 
-impl<'h> HtmlRewriteController<'h> {
+impl<'h, L: HandlersLayout> HtmlRewriteController<'h, L> {
+    #[cfg(feature = "element_handlers")]
     #[inline]
     fn respond_to_aux_info_request(
         aux_info_req: AuxStartTagInfoRequest<ElementDescriptor, SelectorHandlersLocator>,
@@ -75,19 +128,149 @@ impl<'h> HtmlRewriteController<'h> {
     fn get_capture_flags(&self) -> TokenCaptureFlags {
         self.handlers_dispatcher.borrow().get_token_capture_flags()
     }
+
+    /// Applies the changes made in the runtime handlers registry since the previous token.
+    #[cfg(feature = "element_handlers")]
+    #[inline]
+    fn apply_runtime_handlers_changes(&mut self) {
+        if let (Some(runtime_handlers), Some(vm)) =
+            (&mut self.runtime_handlers, &mut self.selector_matching_vm)
+        {
+            if runtime_handlers.has_pending_changes() {
+                runtime_handlers
+                    .apply_pending_changes(&mut self.handlers_dispatcher.borrow_mut(), vm);
+
+                // NOTE: the recompiled program has the selectors that have reached their match
+                // limits restored, so they need to be dropped once again.
+                self.drop_selectors_with_reached_match_limits();
+            }
+        }
+    }
+
+    /// Runs the content of the `HtmlReprocessed` type inserted by the content handlers through
+    /// the rewriter, replacing it with the output.
+    fn reprocess_inserted_content(
+        &mut self,
+        mutations: &mut Mutations,
+    ) -> Result<(), RewritingError> {
+        if !mutations.has_reprocessed_content() {
+            return Ok(());
+        }
+
+        if self.reprocessing_depth == MAX_REPROCESSING_DEPTH {
+            return Err(RewritingError::ReprocessingDepthExceeded);
+        }
+
+        mutations.reprocess_content(|content| self.reprocess(content))
+    }
+
+    fn reprocess(&mut self, content: &[u8]) -> Result<Vec<u8>, RewritingError> {
+        let settings = self.reprocessing_settings;
+        let dispatcher_scope = self.handlers_dispatcher.borrow_mut().enter_scope();
+        let memory_limiter = Rc::clone(self.handlers_dispatcher.borrow().memory_limiter());
+
+        #[cfg(feature = "element_handlers")]
+        let vm_scope = self
+            .selector_matching_vm
+            .as_mut()
+            .map(SelectorMatchingVm::enter_scope);
+
+        // NOTE: the content is parsed by a separate stream, while the state of the matching and
+        // the handlers are lent to it for the time of parsing.
+        let controller = HtmlRewriteController {
+            handlers_dispatcher: Rc::clone(&self.handlers_dispatcher),
+            #[cfg(feature = "element_handlers")]
+            selector_matching_vm: self.selector_matching_vm.take(),
+            #[cfg(feature = "element_handlers")]
+            runtime_handlers: self.runtime_handlers.take(),
+            token_hook: self.token_hook.take(),
+            emit_output: self.emit_output,
+            reprocessing_settings: settings,
+            reprocessing_depth: self.reprocessing_depth + 1,
+        };
+
+        let mut output = Vec::default();
+
+        let mut stream = TransformStream::new(TransformStreamSettings {
+            transform_controller: controller,
+            output_sink: |c: &[u8]| output.extend_from_slice(c),
+            preallocated_parsing_buffer_size: 0,
+            parsing_buffer_compaction_watermark: 0,
+            parsing_buffer_shrink_watermark: 0,
+            memory_limiter,
+            encoding: settings.encoding,
+            strict: settings.strict,
+            buffer_script_and_style_text: settings.buffer_script_and_style_text,
+        });
+
+        let result = stream.write(content).and_then(|_| stream.end());
+
+        {
+            let mut controller = stream.transform_controller_mut();
+
+            #[cfg(feature = "element_handlers")]
+            {
+                self.selector_matching_vm = controller.selector_matching_vm.take();
+                self.runtime_handlers = controller.runtime_handlers.take();
+            }
+
+            self.token_hook = controller.token_hook.take();
+        }
+
+        drop(stream);
+
+        #[cfg(feature = "element_handlers")]
+        if let (Some(vm), Some(prev_scope)) = (&mut self.selector_matching_vm, vm_scope) {
+            let handlers_dispatcher = &self.handlers_dispatcher;
+
+            vm.leave_scope(prev_scope, |elem_desc| {
+                handlers_dispatcher.borrow_mut().discard_matching(elem_desc);
+            });
+        }
+
+        self.handlers_dispatcher
+            .borrow_mut()
+            .leave_scope(dispatcher_scope);
+
+        result.map(|_| output)
+    }
+
+    /// Removes the selectors that have matched as many elements as they are allowed to
+    /// from the program of the VM.
+    #[cfg(feature = "element_handlers")]
+    fn drop_selectors_with_reached_match_limits(&mut self) {
+        let handlers_dispatcher = self.handlers_dispatcher.borrow();
+
+        if let (true, Some(vm)) = (
+            handlers_dispatcher.has_reached_match_limits(),
+            &mut self.selector_matching_vm,
+        ) {
+            vm.discard_payloads(|locator| handlers_dispatcher.is_match_limit_reached(locator));
+        }
+    }
 }
 
-impl TransformController for HtmlRewriteController<'_> {
+impl<L: HandlersLayout> TransformController for HtmlRewriteController<'_, L> {
     #[inline]
     fn initial_capture_flags(&self) -> TokenCaptureFlags {
         self.get_capture_flags()
     }
 
+    #[cfg(feature = "element_handlers")]
     fn handle_start_tag(
         &mut self,
         local_name: LocalName,
         ns: Namespace,
     ) -> StartTagHandlingResult<Self> {
+        self.apply_runtime_handlers_changes();
+
+        // NOTE: the elements matched by the selectors are counted once the next element
+        // starts, so the selectors that have reached their match limits can be dropped
+        // before the VM is executed for it.
+        if self.handlers_dispatcher.borrow_mut().count_matches() {
+            self.drop_selectors_with_reached_match_limits();
+        }
+
         match self.selector_matching_vm {
             Some(ref mut vm) => {
                 let mut match_handler = create_match_handler!(self);
@@ -106,6 +289,7 @@ impl TransformController for HtmlRewriteController<'_> {
         }
     }
 
+    #[cfg(feature = "element_handlers")]
     fn handle_end_tag(&mut self, local_name: LocalName) -> TokenCaptureFlags {
         if let Some(ref mut vm) = self.selector_matching_vm {
             let handlers_dispatcher = Rc::clone(&self.handlers_dispatcher);
@@ -118,31 +302,99 @@ impl TransformController for HtmlRewriteController<'_> {
         self.get_capture_flags()
     }
 
+    // NOTE: without the selector matching VM there are no matched elements, so
+    // the capture flags are determined by the document content handlers only.
+    #[cfg(not(feature = "element_handlers"))]
+    #[inline]
+    fn handle_start_tag(&mut self, _: LocalName, _: Namespace) -> StartTagHandlingResult<Self> {
+        Ok(self.get_capture_flags())
+    }
+
+    #[cfg(not(feature = "element_handlers"))]
+    #[inline]
+    fn handle_end_tag(&mut self, _: LocalName) -> TokenCaptureFlags {
+        self.get_capture_flags()
+    }
+
     #[inline]
     fn handle_token(&mut self, token: &mut Token) -> Result<(), RewritingError> {
+        if let Some(ref mut hook) = self.token_hook {
+            hook(token.kind());
+        }
+
+        #[cfg(feature = "element_handlers")]
         let current_element_data = self
             .selector_matching_vm
             .as_mut()
             .and_then(SelectorMatchingVm::current_element_data_mut);
 
-        self.handlers_dispatcher
-            .borrow_mut()
-            .handle_token(token, current_element_data)
-            .map_err(RewritingError::ContentHandlerError)
+        #[cfg(not(feature = "element_handlers"))]
+        let current_element_data = None;
+
+        let (buffered_subtree_count, in_removed_content) = {
+            let mut handlers_dispatcher = self.handlers_dispatcher.borrow_mut();
+            let buffered_subtree_count = handlers_dispatcher.buffered_subtree_count();
+
+            // NOTE: the element that removes its content is not a part of the removed content,
+            // so the state is taken before the handlers of its start tag are invoked.
+            let in_removed_content =
+                handlers_dispatcher.has_matched_elements_with_removed_content();
+
+            handlers_dispatcher
+                .handle_token(token, current_element_data)
+                .map_err(RewritingError::ContentHandlerError)?;
+
+            (buffered_subtree_count, in_removed_content)
+        };
+
+        self.reprocess_inserted_content(token.mutations_mut())?;
+
+        {
+            let mut handlers_dispatcher = self.handlers_dispatcher.borrow_mut();
+
+            handlers_dispatcher
+                .add_token_to_text_accumulators(token)
+                .map_err(RewritingError::MemoryLimitExceeded)?;
+
+            // NOTE: tokens are added to the buffered subtrees after they have been processed by
+            // the content handlers, so the subtrees reflect modifications made by the handlers.
+            handlers_dispatcher
+                .add_token_to_buffered_subtrees(token, buffered_subtree_count, in_removed_content)
+                .map_err(RewritingError::MemoryLimitExceeded)?;
+        }
+
+        // NOTE: changes made by the content handlers of the token take effect right after it.
+        #[cfg(feature = "element_handlers")]
+        self.apply_runtime_handlers_changes();
+
+        Ok(())
     }
 
     fn handle_end(&mut self, document_end: &mut DocumentEnd) -> Result<(), RewritingError> {
+        // NOTE: the end of the reprocessed content isn't the end of the document.
+        if self.reprocessing_depth > 0 {
+            return Ok(());
+        }
+
         self.handlers_dispatcher
             .borrow_mut()
             .handle_end(document_end)
-            .map_err(RewritingError::ContentHandlerError)
+            .map_err(RewritingError::ContentHandlerError)?;
+
+        self.reprocess_inserted_content(&mut document_end.mutations)
+    }
+
+    #[inline]
+    fn capture_flags_after_start_tag(&self) -> Option<TokenCaptureFlags> {
+        Some(self.get_capture_flags())
     }
 
     #[inline]
     fn should_emit_content(&self) -> bool {
-        !self
-            .handlers_dispatcher
-            .borrow()
-            .has_matched_elements_with_removed_content()
+        let handlers_dispatcher = self.handlers_dispatcher.borrow();
+
+        self.emit_output
+            && !handlers_dispatcher.has_matched_elements_with_removed_content()
+            && !handlers_dispatcher.has_matched_elements_with_buffered_content()
     }
 }
@@ -0,0 +1,446 @@
+use super::handlers_dispatcher::{
+    ContentHandlersDispatcher, HandlersLayout, SelectorHandlersLocator,
+};
+use super::rewrite_controller::ElementDescriptor;
+use super::settings::ElementContentHandlers;
+use crate::selectors_vm::{Ast, Selector, SelectorMatchingVm};
+use encoding_rs::Encoding;
+use hashbrown::HashMap;
+use std::cell::RefCell;
+use std::rc::Rc;
+use thiserror::Error;
+
+/// An error that occurs if element content handlers can't be added to a running rewriter.
+#[derive(Error, Debug, PartialEq, Copy, Clone)]
+pub enum RuntimeHandlersError {
+    /// The selector has pseudo-classes that are resolved by looking ahead in the input, e.g.
+    /// `:contains()`, `:empty` or `:nth-last-child()`. Such selectors can only be specified in
+    /// the [`element_content_handlers`] of the [`Settings`].
+    ///
+    /// [`element_content_handlers`]: ../struct.Settings.html#structfield.element_content_handlers
+    /// [`Settings`]: ../struct.Settings.html
+    #[error("Selectors with lookahead pseudo-classes can't be added to a running rewriter.")]
+    LookaheadSelector,
+}
+
+/// A handle of the element content handlers added with [`RuntimeHandlers::add`].
+///
+/// [`RuntimeHandlers::add`]: struct.RuntimeHandlers.html#method.add
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct RuntimeHandlersHandle(usize);
+
+enum Change<'h> {
+    Add(RuntimeHandlersHandle, Selector, ElementContentHandlers<'h>),
+    Remove(RuntimeHandlersHandle),
+}
+
+#[derive(Default)]
+struct PendingChanges<'h> {
+    changes: Vec<Change<'h>>,
+    next_handle: usize,
+}
+
+/// A registry that adds and removes element content handlers of a running rewriter, e.g. to enable
+/// a handler only after a certain `<meta>` element has been seen.
+///
+/// The registry is passed to the rewriter in the [`runtime_handlers`] setting. It can be cloned
+/// and used by the content handlers as well as between the [`write`] calls. Changes made by
+/// a content handler take effect after the token it has been invoked for is processed. Changes
+/// made between the [`write`] calls take effect before the next token is processed.
+///
+/// Added handlers are invoked only for the elements that start after the handlers have been
+/// added. Elements that have already started are not reliably taken into account when the
+/// combinators of the selector are matched, e.g. `body p` may not match `<p>` if `<body>` has
+/// started before the handlers have been added.
+///
+/// # Example
+/// ```
+/// use lol_html::html_content::ContentType;
+/// use lol_html::{element, ElementContentHandlers, HtmlRewriter, RuntimeHandlers, Settings};
+///
+/// let runtime_handlers = RuntimeHandlers::new();
+/// let mut output = vec![];
+///
+/// {
+///     let registry = runtime_handlers.clone();
+///
+///     let mut rewriter = HtmlRewriter::try_new(
+///         Settings {
+///             element_content_handlers: vec![element!("meta[name=beta]", move |_| {
+///                 registry.add(
+///                     &"p".parse().unwrap(),
+///                     ElementContentHandlers::default().element(|el| {
+///                         el.append("!", ContentType::Text);
+///
+///                         Ok(())
+///                     }),
+///                 )?;
+///
+///                 Ok(())
+///             })],
+///             runtime_handlers: Some(runtime_handlers),
+///             ..Settings::default()
+///         },
+///         |c: &[u8]| output.extend_from_slice(c),
+///     )
+///     .unwrap();
+///
+///     rewriter.write(b"<p>Hi</p><meta name=beta><p>Hello</p>").unwrap();
+///     rewriter.end().unwrap();
+/// }
+///
+/// assert_eq!(
+///     String::from_utf8(output).unwrap(),
+///     "<p>Hi</p><meta name=beta><p>Hello!</p>"
+/// );
+/// ```
+///
+/// [`runtime_handlers`]: struct.Settings.html#structfield.runtime_handlers
+/// [`write`]: struct.HtmlRewriter.html#method.write
+#[derive(Clone, Default)]
+pub struct RuntimeHandlers<'h>(Rc<RefCell<PendingChanges<'h>>>);
+
+impl<'h> RuntimeHandlers<'h> {
+    /// Creates an empty registry.
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the `handlers` for the elements matched by the `selector`.
+    ///
+    /// Returns a handle that can be used to remove the handlers later.
+    pub fn add(
+        &self,
+        selector: &Selector,
+        handlers: ElementContentHandlers<'h>,
+    ) -> Result<RuntimeHandlersHandle, RuntimeHandlersError> {
+        if selector.has_deferred_predicates() {
+            return Err(RuntimeHandlersError::LookaheadSelector);
+        }
+
+        let mut pending = self.0.borrow_mut();
+        let handle = RuntimeHandlersHandle(pending.next_handle);
+
+        let selector = match handlers.max_depth {
+            Some(max_depth) => selector.to_owned().with_max_depth(max_depth),
+            None => selector.to_owned(),
+        };
+
+        pending.next_handle += 1;
+        pending
+            .changes
+            .push(Change::Add(handle, selector, handlers));
+
+        Ok(handle)
+    }
+
+    /// Removes the handlers identified by the `handle`. The handlers are dropped once
+    /// the change takes effect.
+    ///
+    /// Does nothing if the handlers have already been removed.
+    pub fn remove(&self, handle: RuntimeHandlersHandle) {
+        let mut pending = self.0.borrow_mut();
+        let changes = &mut pending.changes;
+
+        // NOTE: if the handlers haven't been added to the rewriter yet, just drop them.
+        match changes
+            .iter()
+            .position(|c| matches!(c, Change::Add(h, ..) if *h == handle))
+        {
+            Some(idx) => drop(changes.remove(idx)),
+            None => changes.push(Change::Remove(handle)),
+        }
+    }
+
+    #[inline]
+    fn has_pending_changes(&self) -> bool {
+        !self.0.borrow().changes.is_empty()
+    }
+
+    #[inline]
+    fn take_pending_changes(&self) -> Vec<Change<'h>> {
+        std::mem::take(&mut self.0.borrow_mut().changes)
+    }
+}
+
+/// Applies the changes made in the [`RuntimeHandlers`] to the handlers dispatcher and
+/// the selector matching VM of a rewriter.
+pub struct RuntimeHandlersState<'h> {
+    registry: RuntimeHandlers<'h>,
+    // NOTE: the AST the current program of the VM has been compiled from. Added selectors
+    // are appended to it and the program is recompiled, so the jumps of the elements that
+    // have already started can be carried over to the new program. For the same reason only
+    // the payloads of the removed selectors are pruned from it, while their nodes are kept
+    // and still executed by the VM until the end of the document.
+    selectors_ast: Ast<SelectorHandlersLocator>,
+    locators: HashMap<RuntimeHandlersHandle, SelectorHandlersLocator>,
+    encoding: &'static Encoding,
+}
+
+impl<'h> RuntimeHandlersState<'h> {
+    pub fn new(
+        registry: RuntimeHandlers<'h>,
+        selectors_ast: Ast<SelectorHandlersLocator>,
+        encoding: &'static Encoding,
+    ) -> Self {
+        RuntimeHandlersState {
+            registry,
+            selectors_ast,
+            locators: HashMap::default(),
+            encoding,
+        }
+    }
+
+    #[inline]
+    pub fn has_pending_changes(&self) -> bool {
+        self.registry.has_pending_changes()
+    }
+
+    pub fn apply_pending_changes<L: HandlersLayout>(
+        &mut self,
+        dispatcher: &mut ContentHandlersDispatcher<'h, L>,
+        vm: &mut SelectorMatchingVm<ElementDescriptor>,
+    ) {
+        let prev_ast = self.selectors_ast.clone();
+        let mut has_added_selectors = false;
+
+        for change in self.registry.take_pending_changes() {
+            match change {
+                Change::Add(handle, selector, handlers) => {
+                    let locator = dispatcher.add_selector_associated_handlers(handlers);
+
+                    self.selectors_ast
+                        .add_selector_with_scoped_payload(&selector, |i, p| {
+                            locator.scoped_to(i, p)
+                        });
+
+                    self.locators.insert(handle, locator);
+                    has_added_selectors = true;
+                }
+                Change::Remove(handle) => {
+                    if let Some(locator) = self.locators.remove(&handle) {
+                        dispatcher.remove_selector_associated_handlers(locator);
+                        self.selectors_ast
+                            .retain_payloads(|p| !p.is_scoped_from(locator));
+                        vm.discard_payloads(|p| p.is_scoped_from(locator));
+                    }
+                }
+            }
+        }
+
+        if has_added_selectors {
+            vm.recompile(&prev_ast, self.selectors_ast.clone(), self.encoding);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::html_content::ContentType;
+    use crate::*;
+    use std::cell::Cell;
+
+    fn rewrite_with_registry(
+        chunks: &[&str],
+        settings: Settings,
+        mut between_chunks: impl FnMut(usize),
+    ) -> String {
+        let mut output = vec![];
+
+        {
+            let mut rewriter =
+                HtmlRewriter::try_new(settings, |c: &[u8]| output.extend_from_slice(c)).unwrap();
+
+            for (i, chunk) in chunks.iter().enumerate() {
+                rewriter.write(chunk.as_bytes()).unwrap();
+                between_chunks(i);
+            }
+
+            rewriter.end().unwrap();
+        }
+
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn add_from_content_handler() {
+        let runtime_handlers = RuntimeHandlers::new();
+        let registry = runtime_handlers.clone();
+
+        let output = rewrite_with_registry(
+            &[
+                "<div><p>1</p><meta name=x><p>2</p>",
+                "<span><p>3</p></span></div>",
+            ],
+            Settings {
+                element_content_handlers: vec![element!("meta[name=x]", move |_| {
+                    registry.add(
+                        &"p".parse().unwrap(),
+                        ElementContentHandlers::default().element(|el| {
+                            el.set_attribute("on", "")?;
+
+                            Ok(())
+                        }),
+                    )?;
+
+                    Ok(())
+                })],
+                runtime_handlers: Some(runtime_handlers),
+                ..Settings::default()
+            },
+            |_| {},
+        );
+
+        assert_eq!(
+            output,
+            concat!(
+                "<div><p>1</p><meta name=x><p on=\"\">2</p>",
+                "<span><p on=\"\">3</p></span></div>"
+            )
+        );
+    }
+
+    #[test]
+    fn add_and_remove_between_writes() {
+        let runtime_handlers = RuntimeHandlers::new();
+        let registry = runtime_handlers.clone();
+        let handle = Cell::new(None);
+
+        let output = rewrite_with_registry(
+            &["<b>1</b><!--a-->", "<b>2</b><!--b-->", "<b>3</b>"],
+            Settings {
+                element_content_handlers: vec![element!("b", |el| {
+                    el.set_tag_name("i")?;
+
+                    Ok(())
+                })],
+                runtime_handlers: Some(runtime_handlers),
+                ..Settings::default()
+            },
+            |i| match i {
+                0 => handle.set(Some(
+                    registry
+                        .add(
+                            &"b, b::text".parse().unwrap(),
+                            ElementContentHandlers::default()
+                                .element(|el| {
+                                    el.before("[", ContentType::Text);
+
+                                    Ok(())
+                                })
+                                .text(|t| {
+                                    if t.last_in_text_node() {
+                                        t.after("]", ContentType::Text);
+                                    }
+
+                                    Ok(())
+                                }),
+                        )
+                        .unwrap(),
+                )),
+                1 => registry.remove(handle.get().unwrap()),
+                _ => (),
+            },
+        );
+
+        assert_eq!(output, "<i>1</i><!--a-->[<i>2]</i><!--b--><i>3</i>");
+    }
+
+    #[test]
+    fn existing_selectors_are_not_affected() {
+        let runtime_handlers = RuntimeHandlers::new();
+        let registry = runtime_handlers.clone();
+
+        let output = rewrite_with_registry(
+            &[
+                "<ul><li>1</li><li>2</li>",
+                "<li>3</li></ul><div><em>4</em></div>",
+            ],
+            Settings {
+                element_content_handlers: vec![
+                    element!("ul > li + li", |el| {
+                        el.set_attribute("next", "")?;
+
+                        Ok(())
+                    }),
+                    element!("div em", |el| {
+                        el.set_attribute("div", "")?;
+
+                        Ok(())
+                    }),
+                ],
+                runtime_handlers: Some(runtime_handlers),
+                ..Settings::default()
+            },
+            |i| {
+                if i == 0 {
+                    registry
+                        .add(
+                            &"li, ul > li ~ li, div > *".parse().unwrap(),
+                            ElementContentHandlers::default().element(|el| {
+                                el.set_attribute("added", "")?;
+
+                                Ok(())
+                            }),
+                        )
+                        .unwrap();
+                }
+            },
+        );
+
+        assert_eq!(
+            output,
+            concat!(
+                "<ul><li>1</li><li next=\"\">2</li>",
+                "<li next=\"\" added=\"\">3</li></ul>",
+                "<div><em div=\"\" added=\"\">4</em></div>"
+            )
+        );
+    }
+
+    #[test]
+    fn remove_before_applied() {
+        let runtime_handlers = RuntimeHandlers::new();
+        let registry = runtime_handlers.clone();
+
+        let output = rewrite_with_registry(
+            &["<p>1</p>", "<p>2</p>"],
+            Settings {
+                runtime_handlers: Some(runtime_handlers),
+                ..Settings::default()
+            },
+            |_| {
+                let handle = registry
+                    .add(
+                        &"p".parse().unwrap(),
+                        ElementContentHandlers::default()
+                            .element(|_| panic!("Removed handler has been invoked")),
+                    )
+                    .unwrap();
+
+                registry.remove(handle);
+            },
+        );
+
+        assert_eq!(output, "<p>1</p><p>2</p>");
+    }
+
+    #[test]
+    fn lookahead_selector() {
+        let registry = RuntimeHandlers::new();
+
+        for selector in &["p:empty", "p:contains(\"foo\")", "li:nth-last-child(2)"] {
+            assert_eq!(
+                registry
+                    .add(
+                        &selector.parse().unwrap(),
+                        ElementContentHandlers::default()
+                    )
+                    .unwrap_err(),
+                RuntimeHandlersError::LookaheadSelector
+            );
+        }
+    }
+}
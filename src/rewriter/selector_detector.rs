@@ -0,0 +1,200 @@
+use super::settings::{ElementContentHandlers, Settings};
+use super::{discard_output, EncodingError, HtmlRewriter, RewritingError};
+use crate::selectors_vm::Selector;
+use std::borrow::Cow;
+use std::cell::Cell;
+use std::rc::Rc;
+use thiserror::Error;
+
+// NOTE: the error is returned from the element handler to stop the parsing of the chunk
+// as soon as the first match is found. It never leaves the detector.
+#[derive(Error, Debug)]
+#[error("Selector has matched.")]
+struct SelectorMatched;
+
+/// Answers whether a document contains an element that matches a selector.
+///
+/// The detector runs only the selector matching engine: the document is not serialized and only
+/// the start tags of the matched elements are materialized. Once the first match is found, the rest
+/// of the input is skipped.
+///
+/// # Example
+/// ```
+/// use lol_html::SelectorDetector;
+///
+/// let selector = "form, input[type=password]".parse().unwrap();
+/// let mut detector = SelectorDetector::try_new(&selector, "utf-8").unwrap();
+///
+/// assert!(!detector.write(b"<div><p>Hello</p>").unwrap());
+/// assert!(detector.write(b"<input name=pwd type=password>").unwrap());
+/// assert_eq!(detector.end().unwrap(), Some(1));
+/// ```
+pub struct SelectorDetector {
+    rewriter: HtmlRewriter<'static, fn(&[u8])>,
+    matched_selector_index: Rc<Cell<Option<usize>>>,
+}
+
+impl SelectorDetector {
+    /// Constructs a new detector for the `selector` and the document in the `encoding`.
+    ///
+    /// The selector can be a selector list, in which case the detector reports the index of
+    /// the matched selector in the list.
+    pub fn try_new(selector: &Selector, encoding: &str) -> Result<Self, EncodingError> {
+        let matched_selector_index = Rc::new(Cell::new(None));
+        let handler_matched_selector_index = Rc::clone(&matched_selector_index);
+
+        let handlers = ElementContentHandlers::default().element(move |el| {
+            handler_matched_selector_index.set(el.matched_selector_index());
+
+            Err(Box::new(SelectorMatched))
+        });
+
+        let rewriter = HtmlRewriter::try_new_with_output(
+            Settings {
+                element_content_handlers: vec![(Cow::Borrowed(selector), handlers)],
+                encoding,
+                ..Settings::default()
+            },
+            discard_output as fn(&[u8]),
+            false,
+        )?;
+
+        Ok(SelectorDetector {
+            rewriter,
+            matched_selector_index,
+        })
+    }
+
+    #[inline]
+    fn handle_result(&self, result: Result<(), RewritingError>) -> Result<bool, RewritingError> {
+        match result {
+            Ok(()) => Ok(self.has_match()),
+            Err(RewritingError::ContentHandlerError(e)) if e.is::<SelectorMatched>() => Ok(true),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Writes a chunk of the document to the detector.
+    ///
+    /// Returns `true` if the document contains a matching element. Once a match is found,
+    /// subsequent chunks are ignored.
+    #[inline]
+    pub fn write(&mut self, data: &[u8]) -> Result<bool, RewritingError> {
+        if self.has_match() {
+            return Ok(true);
+        }
+
+        let result = self.rewriter.write(data);
+
+        self.handle_result(result)
+    }
+
+    /// Finalizes the detection.
+    ///
+    /// Returns the index of the matched selector in the selector list or `None` if the document
+    /// doesn't contain a matching element.
+    pub fn end(mut self) -> Result<Option<usize>, RewritingError> {
+        if !self.has_match() {
+            let result = self.rewriter.end();
+
+            self.handle_result(result)?;
+        }
+
+        Ok(self.matched_selector_index.get())
+    }
+
+    /// Returns `true` if a matching element has been found in the document written so far.
+    #[inline]
+    pub fn has_match(&self) -> bool {
+        self.matched_selector_index.get().is_some()
+    }
+}
+
+/// Returns the index of the first selector in the `selector` list that matches an element of
+/// the `html` or `None` if there are no matching elements.
+///
+/// See [`SelectorDetector`] for the details.
+///
+/// # Example
+/// ```
+/// use lol_html::detect_selector_match;
+///
+/// let selector = "script[src], iframe".parse().unwrap();
+///
+/// assert_eq!(detect_selector_match("<p>Hi</p><iframe></iframe>", &selector).unwrap(), Some(1));
+/// assert_eq!(detect_selector_match("<p>Hi</p>", &selector).unwrap(), None);
+/// ```
+///
+/// [`SelectorDetector`]: struct.SelectorDetector.html
+pub fn detect_selector_match(
+    html: &str,
+    selector: &Selector,
+) -> Result<Option<usize>, RewritingError> {
+    // NOTE: never panics because encoding is always "utf-8".
+    let mut detector = SelectorDetector::try_new(selector, "utf-8").unwrap();
+
+    detector.write(html.as_bytes())?;
+    detector.end()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::ASCII_COMPATIBLE_ENCODINGS;
+
+    #[test]
+    fn first_match() {
+        let selector = "b, .foo".parse().unwrap();
+
+        assert_eq!(
+            detect_selector_match("<div><i class=foo></i><b></b></div>", &selector).unwrap(),
+            Some(1)
+        );
+
+        assert_eq!(
+            detect_selector_match("<div><b class=foo></b></div>", &selector).unwrap(),
+            Some(0)
+        );
+
+        assert_eq!(
+            detect_selector_match("<div><i class=bar></i><!-- <b> --></div>", &selector).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn short_circuit() {
+        let selector = "b".parse().unwrap();
+
+        for encoding in ASCII_COMPATIBLE_ENCODINGS.iter() {
+            let mut detector = SelectorDetector::try_new(&selector, encoding.name()).unwrap();
+
+            assert!(!detector.write(b"<div><").unwrap());
+            assert!(detector.write(b"b></b><i>").unwrap());
+            assert!(detector.has_match());
+
+            // NOTE: the input is not parsed after the match, so malformed input is fine.
+            assert!(detector.write(&[0xFF, 0xFE]).unwrap());
+            assert_eq!(detector.end().unwrap(), Some(0));
+        }
+    }
+
+    #[test]
+    fn unfinished_start_tag() {
+        let selector = "b".parse().unwrap();
+        let mut detector = SelectorDetector::try_new(&selector, "utf-8").unwrap();
+
+        assert!(!detector.write(b"<div><b").unwrap());
+        assert_eq!(detector.end().unwrap(), None);
+    }
+
+    #[test]
+    fn unknown_encoding() {
+        let selector = "b".parse().unwrap();
+
+        assert_eq!(
+            SelectorDetector::try_new(&selector, "hey-there").err(),
+            Some(EncodingError::UnknownEncoding)
+        );
+    }
+}
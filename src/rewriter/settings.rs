@@ -1,5 +1,13 @@
-use crate::rewritable_units::{Comment, Doctype, DocumentEnd, Element, EndTag, TextChunk};
+use super::progress::ProgressEvent;
+#[cfg(feature = "element_handlers")]
+use super::runtime_handlers::RuntimeHandlers;
+use super::token_transformer::TokenTransformer;
+use crate::rewritable_units::{
+    Comment, Doctype, DocumentEnd, Element, EndTag, TextChunk, TokenKind,
+};
+#[cfg(feature = "element_handlers")]
 use crate::selectors_vm::Selector;
+#[cfg(feature = "element_handlers")]
 use std::borrow::Cow;
 use std::error::Error;
 
@@ -10,6 +18,10 @@ pub type TextHandler<'h> = Box<dyn FnMut(&mut TextChunk) -> HandlerResult + 'h>;
 pub type ElementHandler<'h> = Box<dyn FnMut(&mut Element) -> HandlerResult + 'h>;
 pub type EndTagHandler<'h> = Box<dyn FnOnce(&mut EndTag) -> HandlerResult + 'h>;
 pub type EndHandler<'h> = Box<dyn FnOnce(&mut DocumentEnd) -> HandlerResult + 'h>;
+pub type ChunkStartHook<'h> = Box<dyn FnMut(usize) + 'h>;
+pub type TokenHook<'h> = Box<dyn FnMut(TokenKind) + 'h>;
+pub type FlushHook<'h> = Box<dyn FnMut(usize) + 'h>;
+pub type ProgressHook<'h> = Box<dyn FnMut(ProgressEvent) + 'h>;
 
 /// Specifies element content handlers associated with a selector.
 #[derive(Default)]
@@ -17,6 +29,9 @@ pub struct ElementContentHandlers<'h> {
     pub(super) element: Option<ElementHandler<'h>>,
     pub(super) comments: Option<CommentHandler<'h>>,
     pub(super) text: Option<TextHandler<'h>>,
+    pub(super) priority: i32,
+    pub(super) max_depth: Option<usize>,
+    pub(super) max_matches: Option<usize>,
 }
 
 impl<'h> ElementContentHandlers<'h> {
@@ -36,6 +51,50 @@ impl<'h> ElementContentHandlers<'h> {
         self
     }
 
+    /// Sets a handler for HTML comments in the inner content of elements matched by a selector,
+    /// which is invoked only for the comments whose text satisfies the `predicate`.
+    ///
+    /// # Example
+    /// ```
+    /// use lol_html::{rewrite_str, ElementContentHandlers, RewriteStrSettings, Selector};
+    /// use std::borrow::Cow;
+    ///
+    /// let html = rewrite_str(
+    ///     "<head><!-- build:js --><!-- note --></head><body><!-- build:css --></body>",
+    ///     RewriteStrSettings {
+    ///         element_content_handlers: vec![(
+    ///             Cow::Owned("head".parse::<Selector>().unwrap()),
+    ///             ElementContentHandlers::default().comments_matching(
+    ///                 |text| text.trim().starts_with("build:"),
+    ///                 |c| {
+    ///                     c.remove();
+    ///
+    ///                     Ok(())
+    ///                 },
+    ///             ),
+    ///         )],
+    ///         ..RewriteStrSettings::default()
+    ///     },
+    /// )
+    /// .unwrap();
+    ///
+    /// assert_eq!(html, "<head><!-- note --></head><body><!-- build:css --></body>");
+    /// ```
+    #[inline]
+    pub fn comments_matching(
+        self,
+        predicate: impl Fn(&str) -> bool + 'h,
+        mut handler: impl FnMut(&mut Comment) -> HandlerResult + 'h,
+    ) -> Self {
+        self.comments(move |c| {
+            if predicate(&c.text()) {
+                handler(c)
+            } else {
+                Ok(())
+            }
+        })
+    }
+
     /// Sets a handler for text chunks in the inner content of elements matched by a selector.
     #[inline]
     pub fn text(mut self, handler: impl FnMut(&mut TextChunk) -> HandlerResult + 'h) -> Self {
@@ -43,6 +102,118 @@ impl<'h> ElementContentHandlers<'h> {
 
         self
     }
+
+    /// Sets the priority of the handlers. If the same content is matched by multiple selectors,
+    /// handlers with a higher priority are invoked first, so their modifications are observed by
+    /// the handlers with a lower priority. Handlers with the same priority are invoked in the order
+    /// they are specified in the [`element_content_handlers`].
+    ///
+    /// Within [`MergedHandlers`], the priority only orders the handlers of the same settings, as
+    /// they are always invoked after the handlers of the settings merged before them.
+    /// The priority of the handlers added with [`RuntimeHandlers`] is ignored.
+    ///
+    /// ### Default
+    ///
+    /// `0` when constructed with `ElementContentHandlers::default()`.
+    ///
+    /// [`element_content_handlers`]: struct.Settings.html#structfield.element_content_handlers
+    /// [`MergedHandlers`]: struct.MergedHandlers.html
+    /// [`RuntimeHandlers`]: struct.RuntimeHandlers.html
+    #[inline]
+    pub fn priority(mut self, priority: i32) -> Self {
+        self.priority = priority;
+
+        self
+    }
+
+    /// Limits the matching of the selector to the elements nested at most at the `max_depth`
+    /// level, where the root elements of the document are at the level of `1`. E.g. with
+    /// the limit of `50`, `div.ad` matches only the elements in the first 50 nesting levels.
+    ///
+    /// The selector matching VM checks the nesting level of an element before the rest of
+    /// the selector, so the bound also caps the matching work spent on the deeper elements of
+    /// pathological documents.
+    ///
+    /// ### Default
+    ///
+    /// No limit when constructed with `ElementContentHandlers::default()`.
+    ///
+    /// # Example
+    /// ```
+    /// use lol_html::{rewrite_str, ElementContentHandlers, RewriteStrSettings, Selector};
+    /// use std::borrow::Cow;
+    ///
+    /// let html = rewrite_str(
+    ///     r#"<div class="ad"><div class="ad"></div></div>"#,
+    ///     RewriteStrSettings {
+    ///         element_content_handlers: vec![(
+    ///             Cow::Owned("div.ad".parse::<Selector>().unwrap()),
+    ///             ElementContentHandlers::default()
+    ///                 .element(|el| {
+    ///                     el.set_attribute("hidden", "")?;
+    ///
+    ///                     Ok(())
+    ///                 })
+    ///                 .max_depth(1),
+    ///         )],
+    ///         ..RewriteStrSettings::default()
+    ///     },
+    /// )
+    /// .unwrap();
+    ///
+    /// assert_eq!(html, r#"<div class="ad" hidden=""><div class="ad"></div></div>"#);
+    /// ```
+    #[inline]
+    pub fn max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = Some(max_depth);
+
+        self
+    }
+
+    /// Limits the number of the elements matched by the selector to `max_matches`. Once
+    /// the limit is reached, the selector is dropped from the selector matching VM, so
+    /// the following elements are neither matched by it nor passed to the handlers. E.g.
+    /// with the limit of `1`, `h1` matches only the first heading of the document.
+    ///
+    /// An element is counted once, even if it's matched by multiple selectors in the selector
+    /// list. The handlers of the inner content of the matched elements are still invoked after
+    /// the limit is reached.
+    ///
+    /// ### Default
+    ///
+    /// No limit when constructed with `ElementContentHandlers::default()`.
+    ///
+    /// # Example
+    /// ```
+    /// use lol_html::{rewrite_str, ElementContentHandlers, RewriteStrSettings, Selector};
+    /// use std::borrow::Cow;
+    ///
+    /// let html = rewrite_str(
+    ///     "<h1>Foo</h1><h1>Bar</h1>",
+    ///     RewriteStrSettings {
+    ///         element_content_handlers: vec![(
+    ///             Cow::Owned("h1".parse::<Selector>().unwrap()),
+    ///             ElementContentHandlers::default()
+    ///                 .element(|el| {
+    ///                     el.set_attribute("id", "title")?;
+    ///
+    ///                     Ok(())
+    ///                 })
+    ///                 .max_matches(1),
+    ///         )],
+    ///         ..RewriteStrSettings::default()
+    ///     },
+    /// )
+    /// .unwrap();
+    ///
+    /// assert_eq!(html, r#"<h1 id="title">Foo</h1><h1>Bar</h1>"#);
+    /// ```
+    #[inline]
+    pub fn max_matches(mut self, max_matches: usize) -> Self {
+        self.max_matches = Some(max_matches);
+
+        self
+    }
 }
 
 /// Specifies document-level content handlers.
@@ -104,13 +275,19 @@ impl<'h> DocumentContentHandlers<'h> {
     }
 }
 
+#[cfg(feature = "element_handlers")]
 #[doc(hidden)]
 #[macro_export]
 macro_rules! __element_content_handler {
     ($selector:expr, $handler_name:ident, $handler:expr) => {
+        $crate::__element_content_handler!($selector, $handler_name, 0, $handler)
+    };
+    ($selector:expr, $handler_name:ident, $priority:expr, $handler:expr) => {
         (
             ::std::borrow::Cow::Owned($selector.parse::<$crate::Selector>().unwrap()),
-            $crate::ElementContentHandlers::default().$handler_name($handler),
+            $crate::ElementContentHandlers::default()
+                .$handler_name($handler)
+                .priority($priority),
         )
     };
 }
@@ -118,6 +295,10 @@ macro_rules! __element_content_handler {
 /// A convenience macro to construct a rewriting handler for elements that can be matched by the
 /// specified CSS selector.
 ///
+/// The [priority] of the handler can be specified as `priority = N` after the selector.
+///
+/// [priority]: struct.ElementContentHandlers.html#method.priority
+///
 /// # Example
 /// ```
 /// use lol_html::{rewrite_str, element, RewriteStrSettings};
@@ -139,8 +320,43 @@ macro_rules! __element_content_handler {
 ///
 /// assert_eq!(html, r#"<span id="foo">Hello!</span>"#);
 /// ```
+///
+/// With the priority:
+/// ```
+/// use lol_html::{rewrite_str, element, RewriteStrSettings};
+///
+/// let html = rewrite_str(
+///     r#"<a href="/about">"#,
+///     RewriteStrSettings {
+///         element_content_handlers: vec![
+///             element!("a", |el| {
+///                 el.set_attribute("title", &el.get_attribute("href").unwrap())?;
+///
+///                 Ok(())
+///             }),
+///             element!("a[href^='/']", priority = 1, |el| {
+///                 let href = el.get_attribute("href").unwrap();
+///
+///                 el.set_attribute("href", &format!("https://example.com{}", href))?;
+///
+///                 Ok(())
+///             })
+///         ],
+///         ..RewriteStrSettings::default()
+///     }
+/// ).unwrap();
+///
+/// assert_eq!(
+///     html,
+///     r#"<a href="https://example.com/about" title="https://example.com/about">"#
+/// );
+/// ```
+#[cfg(feature = "element_handlers")]
 #[macro_export(local_inner_macros)]
 macro_rules! element {
+    ($selector:expr, priority = $priority:expr, $handler:expr) => {
+        __element_content_handler!($selector, element, $priority, $handler);
+    };
     ($selector:expr, $handler:expr) => {
         __element_content_handler!($selector, element, $handler);
     };
@@ -172,8 +388,12 @@ macro_rules! element {
 ///
 /// assert_eq!(html, r#"<span>Hello world</span>"#);
 /// ```
+#[cfg(feature = "element_handlers")]
 #[macro_export(local_inner_macros)]
 macro_rules! text {
+    ($selector:expr, priority = $priority:expr, $handler:expr) => {
+        __element_content_handler!($selector, text, $priority, $handler);
+    };
     ($selector:expr, $handler:expr) => {
         __element_content_handler!($selector, text, $handler);
     };
@@ -182,6 +402,12 @@ macro_rules! text {
 /// A convenience macro to construct a rewriting handler for HTML comments in the inner content of
 /// an element that can be matched by the specified CSS selector.
 ///
+/// The handler can be restricted to the comments whose text satisfies a predicate specified as
+/// `matching = predicate` after the selector, e.g. `comments!("head", matching = |text|
+/// text.contains("build:"), handler)`. See [`ElementContentHandlers::comments_matching`].
+///
+/// [`ElementContentHandlers::comments_matching`]: struct.ElementContentHandlers.html#method.comments_matching
+///
 /// # Example
 /// ```
 /// use lol_html::{rewrite_str, comments, RewriteStrSettings};
@@ -203,8 +429,18 @@ macro_rules! text {
 ///
 /// assert_eq!(html, r#"<span><!--Hello!--></span>"#);
 /// ```
+#[cfg(feature = "element_handlers")]
 #[macro_export(local_inner_macros)]
 macro_rules! comments {
+    ($selector:expr, matching = $predicate:expr, $handler:expr) => {
+        (
+            ::std::borrow::Cow::Owned($selector.parse::<$crate::Selector>().unwrap()),
+            $crate::ElementContentHandlers::default().comments_matching($predicate, $handler),
+        )
+    };
+    ($selector:expr, priority = $priority:expr, $handler:expr) => {
+        __element_content_handler!($selector, comments, $priority, $handler);
+    };
     ($selector:expr, $handler:expr) => {
         __element_content_handler!($selector, comments, $handler);
     };
@@ -393,6 +629,37 @@ pub struct MemorySettings {
     /// [`write`]: struct.HtmlRewriter.html#method.write
     /// [`end`]: struct.HtmlRewriter.html#method.end
     pub max_allowed_memory_usage: usize,
+
+    /// Specifies the number of already parsed bytes that can be kept at the beginning of the
+    /// internal parsing buffer.
+    ///
+    /// When the parsing buffer is in use, the unparsed tail of each input chunk is moved to the
+    /// beginning of the buffer. With a non-zero watermark the tail stays in place until the
+    /// parsed bytes in front of it exceed the watermark or the buffer runs out of capacity,
+    /// which avoids copies for the content split across many small chunks at the cost of
+    /// the memory occupied by the parsed bytes.
+    ///
+    /// ### Default
+    ///
+    /// `0` when constructed with `MemorySettings::default()`, i.e. the tail is moved on
+    /// each input chunk.
+    pub parsing_buffer_compaction_watermark: usize,
+
+    /// Specifies the capacity in bytes of the internal parsing buffer above which the buffer is
+    /// shrunk back to the [`preallocated_parsing_buffer_size`] once it's no longer in use.
+    ///
+    /// The parsing buffer grows to fit tokens that are split across input chunks. By default,
+    /// the grown buffer is retained to avoid further reallocations, so a single large token
+    /// (e.g. a start tag with a huge attribute value) keeps the memory allocated until the
+    /// rewriter is dropped. The watermark allows to release such memory, trading it for a
+    /// potential reallocation later.
+    ///
+    /// ### Default
+    ///
+    /// `0` when constructed with `MemorySettings::default()`, which disables shrinking.
+    ///
+    /// [`preallocated_parsing_buffer_size`]: #structfield.preallocated_parsing_buffer_size
+    pub parsing_buffer_shrink_watermark: usize,
 }
 
 impl Default for MemorySettings {
@@ -401,10 +668,157 @@ impl Default for MemorySettings {
         MemorySettings {
             preallocated_parsing_buffer_size: 1024,
             max_allowed_memory_usage: std::usize::MAX,
+            parsing_buffer_compaction_watermark: 0,
+            parsing_buffer_shrink_watermark: 0,
         }
     }
 }
 
+/// Specifies observability hooks for [`HtmlRewriter`].
+///
+/// Hooks are intended for collecting metrics (e.g. histograms of input chunk sizes or counts
+/// of token kinds) and can't modify the content. The rewriter doesn't do any extra work for
+/// the hooks that are not set.
+///
+/// # Example
+/// ```
+/// use lol_html::{text, HtmlRewriter, ObservabilityHooks, Settings};
+/// use lol_html::html_content::TokenKind;
+/// use std::cell::Cell;
+///
+/// let chunk_count = Cell::new(0);
+/// let text_token_count = Cell::new(0);
+/// let output_size = Cell::new(0);
+///
+/// {
+///     let mut rewriter = HtmlRewriter::try_new(
+///         Settings {
+///             element_content_handlers: vec![text!("p", |_| Ok(()))],
+///             observability_hooks: ObservabilityHooks::default()
+///                 .on_chunk_start(|_| chunk_count.set(chunk_count.get() + 1))
+///                 .on_token(|kind| {
+///                     if kind == TokenKind::Text {
+///                         text_token_count.set(text_token_count.get() + 1);
+///                     }
+///                 })
+///                 .on_flush(|size| output_size.set(output_size.get() + size)),
+///             ..Settings::default()
+///         },
+///         |_: &[u8]| {}
+///     ).unwrap();
+///
+///     rewriter.write(b"<p>Hello</p>").unwrap();
+///     rewriter.write(b"<p>world</p>").unwrap();
+///     rewriter.end().unwrap();
+/// }
+///
+/// assert_eq!(chunk_count.get(), 2);
+/// assert_eq!(text_token_count.get(), 4);
+/// assert_eq!(output_size.get(), 24);
+/// ```
+///
+/// [`HtmlRewriter`]: struct.HtmlRewriter.html
+#[derive(Default)]
+pub struct ObservabilityHooks<'h> {
+    pub(super) chunk_start: Option<ChunkStartHook<'h>>,
+    pub(super) token: Option<TokenHook<'h>>,
+    pub(super) flush: Option<FlushHook<'h>>,
+    pub(super) progress: Option<(usize, ProgressHook<'h>)>,
+}
+
+impl<'h> ObservabilityHooks<'h> {
+    /// Sets a hook that is called with the length of each chunk written to the rewriter,
+    /// before the chunk is processed.
+    #[inline]
+    pub fn on_chunk_start(mut self, hook: impl FnMut(usize) + 'h) -> Self {
+        self.chunk_start = Some(Box::new(hook));
+
+        self
+    }
+
+    /// Sets a hook that is called with the kind of each token produced by the rewriter.
+    ///
+    /// # Note
+    /// The rewriter produces tokens only for the content captured by the content handlers,
+    /// the rest of the content is passed through to the output without tokenization.
+    #[inline]
+    pub fn on_token(mut self, hook: impl FnMut(TokenKind) + 'h) -> Self {
+        self.token = Some(Box::new(hook));
+
+        self
+    }
+
+    /// Sets a hook that is called with the length of each chunk of the output passed to the
+    /// output sink.
+    #[inline]
+    pub fn on_flush(mut self, hook: impl FnMut(usize) + 'h) -> Self {
+        self.flush = Some(Box::new(hook));
+
+        self
+    }
+
+    /// Sets a hook that reports the progress of the rewriting, so streaming consumers and early
+    /// flush heuristics don't need to wrap the output sink. The hook is called:
+    ///  * whenever the total size of the output passed to the output sink reaches another
+    ///    multiple of the `output_watermark`, unless it's `0`;
+    ///  * right after the end tag of a top-level element is serialized. Top-level elements are
+    ///    the elements that are not nested in any other element and the children of `<html>`,
+    ///    e.g. `<head>`. Only the elements closed by their own end tags are reported.
+    ///
+    /// E.g. a proxy can [`flush`] the rewriter once `</head>` is written to send the head of
+    /// the document to the client as early as possible.
+    ///
+    /// # Note
+    /// The top-level elements are tracked only if the crate is built with the `element_handlers`
+    /// feature. Tracking them requires the rewriter to match a selector for all the elements,
+    /// which has a performance cost.
+    ///
+    /// # Example
+    /// ```
+    /// use lol_html::{HtmlRewriter, ObservabilityHooks, ProgressEvent, Settings};
+    /// use std::cell::RefCell;
+    ///
+    /// let closed = RefCell::new(vec![]);
+    ///
+    /// {
+    ///     let mut rewriter = HtmlRewriter::try_new(
+    ///         Settings {
+    ///             observability_hooks: ObservabilityHooks::default().on_progress(0, |event| {
+    ///                 if let ProgressEvent::TopLevelElementEnd { tag_name } = event {
+    ///                     closed.borrow_mut().push(tag_name.to_string());
+    ///                 }
+    ///             }),
+    ///             ..Settings::default()
+    ///         },
+    ///         |_: &[u8]| {}
+    ///     ).unwrap();
+    ///
+    ///     rewriter.write(b"<html><head><title>Hi</title></head>").unwrap();
+    ///
+    ///     if closed.borrow().iter().any(|tag_name| tag_name == "head") {
+    ///         rewriter.flush();
+    ///     }
+    ///
+    ///     rewriter.write(b"<body><p>Hello</p></body></html>").unwrap();
+    ///     rewriter.end().unwrap();
+    /// }
+    ///
+    /// assert_eq!(*closed.borrow(), ["head", "body", "html"]);
+    /// ```
+    ///
+    /// [`flush`]: struct.HtmlRewriter.html#method.flush
+    #[inline]
+    pub fn on_progress(
+        mut self,
+        output_watermark: usize,
+        hook: impl FnMut(ProgressEvent) + 'h,
+    ) -> Self {
+        self.progress = Some((output_watermark, Box::new(hook)));
+
+        self
+    }
+}
+
 /// Specifies settings for [`HtmlRewriter`].
 ///
 /// [`HtmlRewriter`]: struct.HtmlRewriter.html
@@ -447,6 +861,7 @@ pub struct Settings<'h, 's> {
     /// [`element`]: macro.element.html
     /// [`comments`]: macro.comments.html
     /// [`text`]: macro.text.html
+    #[cfg(feature = "element_handlers")]
     pub element_content_handlers: Vec<(Cow<'s, Selector>, ElementContentHandlers<'h>)>,
 
     /// Specifies rewriting handlers for the content without associating it to a particular
@@ -464,20 +879,80 @@ pub struct Settings<'h, 's> {
     /// [`doc_text`]: macro.doc_text.html
     pub document_content_handlers: Vec<DocumentContentHandlers<'h>>,
 
-    /// Specifies the [character encoding] for the input and the output of the rewriter.
+    /// Specifies token transformers that are invoked before any of the content handlers.
+    ///
+    /// Refer to [`TokenTransformer`] documentation for more information.
+    ///
+    /// [`TokenTransformer`]: trait.TokenTransformer.html
+    pub token_transformers: Vec<Box<dyn TokenTransformer + 'h>>,
+
+    /// Specifies the registry that adds and removes element content handlers once the rewriting
+    /// has started.
+    ///
+    /// Refer to [`RuntimeHandlers`] documentation for more information.
+    ///
+    /// [`RuntimeHandlers`]: struct.RuntimeHandlers.html
+    ///
+    /// ### Default
+    ///
+    /// `None` when constructed with `Settings::default()`.
+    #[cfg(feature = "element_handlers")]
+    pub runtime_handlers: Option<RuntimeHandlers<'h>>,
+
+    /// Specifies the maximum number of bytes of the input the rewriter holds back while
+    /// the deferred predicates of the selectors, e.g. `:contains()`, `:empty` or `:last-child`,
+    /// are not resolved. The elements whose predicates are not resolved once the bound is
+    /// reached are considered not matching.
+    ///
+    /// The held back input is also counted against the [`max_allowed_memory_usage`] limit, so
+    /// with `None` the memory limit is the only bound of the lookahead. Refer to [`Selector`]
+    /// documentation for more information.
+    ///
+    /// [`max_allowed_memory_usage`]: struct.MemorySettings.html#structfield.max_allowed_memory_usage
+    /// [`Selector`]: struct.Selector.html
+    ///
+    /// ### Default
+    ///
+    /// `Some(65536)` when constructed with `Settings::default()`.
+    #[cfg(feature = "element_handlers")]
+    pub max_lookahead: Option<usize>,
+
+    /// Specifies the [character encoding] for the input and, unless the [`output_encoding`] is
+    /// specified, the output of the rewriter.
     ///
     /// Can be a [label] for any of the web-compatible encodings with an exception for `UTF-16LE`,
     /// `UTF-16BE`, `ISO-2022-JP` and `replacement` (these non-ASCII-compatible encodings
-    /// are not supported).
+    /// are not supported). If the crate is built without the `legacy_encodings` feature, only
+    /// the labels of `UTF-8` are accepted.
     ///
     /// [character encoding]: https://developer.mozilla.org/en-US/docs/Glossary/character_encoding
     /// [label]: https://encoding.spec.whatwg.org/#names-and-labels
+    /// [`output_encoding`]: #structfield.output_encoding
     ///
     /// ### Default
     ///
     /// `"utf-8"` when constructed with `Settings::default()`.
     pub encoding: &'s str,
 
+    /// Specifies the [character encoding] for the output of the rewriter, if it should differ
+    /// from the [`encoding`] of the input, e.g. to produce UTF-8 output regardless of the
+    /// document's charset.
+    ///
+    /// Accepts the same labels as the [`encoding`]. Characters that can't be represented in
+    /// the output encoding are replaced with numeric character references. Since the transcoded
+    /// output doesn't correspond to the input, [`OutputSink::handle_unmodified_chunk`] is not
+    /// invoked if the output encoding differs from the input one.
+    ///
+    /// [character encoding]: https://developer.mozilla.org/en-US/docs/Glossary/character_encoding
+    /// [`encoding`]: #structfield.encoding
+    /// [`OutputSink::handle_unmodified_chunk`]: trait.OutputSink.html#method.handle_unmodified_chunk
+    ///
+    /// ### Default
+    ///
+    /// `None` when constructed with `Settings::default()`, i.e. the output has the same encoding
+    /// as the input.
+    pub output_encoding: Option<&'s str>,
+
     /// Specifies the memory settings.
     pub memory_settings: MemorySettings,
 
@@ -514,17 +989,164 @@ pub struct Settings<'h, 's> {
     ///
     /// `true` when constructed with `Settings::default()`.
     pub strict: bool,
+
+    /// If set to `true` the rewriter buffers the whole text content of `<script>` and `<style>`
+    /// elements and delivers it to text handlers as a single chunk.
+    ///
+    /// By default, text is delivered in chunks that are split at arbitrary points, which makes
+    /// it impossible to apply JavaScript or CSS transformations to the chunks. The option also
+    /// applies to the text of other elements with the same parsing rules as `<style>` (see
+    /// [`TextType::RawText`]).
+    ///
+    /// The buffered text counts towards the memory limit specified in the [`MemorySettings`].
+    ///
+    /// [`TextType::RawText`]: html_content/enum.TextType.html#variant.RawText
+    /// [`MemorySettings`]: struct.MemorySettings.html
+    ///
+    /// ### Default
+    ///
+    /// `false` when constructed with `Settings::default()`.
+    pub buffer_script_and_style_text: bool,
+
+    /// If set to `true` the rewriter produces well-formed [XHTML] output suitable for strict XML
+    /// consumers, e.g. EPUB packagers:
+    ///  * void elements are self-closed (`<br/>`) and the self-closing flag of the other HTML
+    ///    elements is dropped, as their end tags follow in the markup;
+    ///  * tag and attribute names are serialized in lowercase;
+    ///  * attribute values are always double-quoted;
+    ///  * `&` and `<` are escaped in text and attribute values, character references are decoded
+    ///    and re-escaped, so references not defined in XML (e.g. `&nbsp;`) are not emitted;
+    ///  * boolean attributes are not minimized (`checked="checked"`);
+    ///  * `--` sequences are removed from comments.
+    ///
+    /// The output is produced after all the content handlers have been invoked, so it reflects
+    /// the modifications made by them. Content inserted by the handlers as HTML is emitted as is.
+    /// End tags omitted in the input are not inserted, so the input is expected to close all the
+    /// non-void elements. Start and end tags are rewritten only if the crate is built with the
+    /// `element_handlers` feature.
+    ///
+    /// [XHTML]: https://www.w3.org/TR/xhtml1/
+    ///
+    /// ### Default
+    ///
+    /// `false` when constructed with `Settings::default()`.
+    pub xhtml_output: bool,
+
+    /// Specifies the tag names of the elements that are treated as [void elements] in addition
+    /// to the standard ones, e.g. `<icon>` emitted as void by some template languages and email
+    /// generators.
+    ///
+    /// The content that follows the start tag of such an element is not considered to be
+    /// the content of the element: e.g. it's not matched by the selectors of the element's
+    /// descendants, the end tag related APIs of the [`Element`] have no effect and the element is
+    /// serialized without an end tag in the buffered subtrees. Tag names are matched
+    /// case-insensitively.
+    ///
+    /// ### Example
+    /// ```
+    /// use lol_html::{element, rewrite_str, RewriteStrSettings};
+    /// use lol_html::html_content::ContentType;
+    ///
+    /// let html = rewrite_str(
+    ///     "<p><icon name=star><b>Starred</b></p>",
+    ///     RewriteStrSettings {
+    ///         element_content_handlers: vec![
+    ///             element!("p > b", |el| {
+    ///                 el.set_inner_content("Favorite", ContentType::Text);
+    ///
+    ///                 Ok(())
+    ///             })
+    ///         ],
+    ///         custom_void_elements: vec!["icon"],
+    ///         ..RewriteStrSettings::default()
+    ///     }
+    /// ).unwrap();
+    ///
+    /// assert_eq!(html, "<p><icon name=star><b>Favorite</b></p>");
+    /// ```
+    ///
+    /// [void elements]: https://html.spec.whatwg.org/multipage/syntax.html#void-elements
+    /// [`Element`]: html_content/struct.Element.html
+    ///
+    /// ### Default
+    ///
+    /// Empty vector when constructed with `Settings::default()`.
+    pub custom_void_elements: Vec<&'s str>,
+
+    /// If set to `true` the content inserted by the content handlers is wrapped in comments that
+    /// identify the handler that inserted it, e.g. to find out which of the numerous rules
+    /// produced a given fragment of the output. Intended for debugging only.
+    ///
+    /// Content inserted by the handlers of the [`element_content_handlers`] is labeled as
+    /// `rule#N` and content inserted by the handlers of the [`document_content_handlers`] is
+    /// labeled as `doc#N`, where `N` is the index of the handlers in the respective list. The label
+    /// is followed by the offset of the modified element or text in the input, if it's known:
+    ///
+    /// ```html
+    /// <!--lolhtml:rule#12@345--><b>inserted content</b><!--/lolhtml:rule#12@345-->
+    /// ```
+    ///
+    /// Modifications of the tag names and attributes as well as removals are not labeled, as
+    /// they don't produce any content.
+    ///
+    /// [`element_content_handlers`]: #structfield.element_content_handlers
+    /// [`document_content_handlers`]: #structfield.document_content_handlers
+    ///
+    /// ### Default
+    ///
+    /// `false` when constructed with `Settings::default()`.
+    pub provenance_comments: bool,
+
+    /// If set, the rewriter coalesces small pieces of the output produced on serialization of
+    /// the rewritten content and passes them to the output sink once their total size reaches
+    /// the specified number of bytes.
+    ///
+    /// Buffered output is also flushed before the unmodified parts of the input, which are never
+    /// buffered, on [`end`] and on [`flush`], so the output latency can be bounded by flushing
+    /// the rewriter at the deadlines.
+    ///
+    /// The buffer is preallocated on the rewriter instantiation and doesn't count towards the
+    /// memory limit specified in the [`MemorySettings`].
+    ///
+    /// [`end`]: struct.HtmlRewriter.html#method.end
+    /// [`flush`]: struct.HtmlRewriter.html#method.flush
+    /// [`MemorySettings`]: struct.MemorySettings.html
+    ///
+    /// ### Default
+    ///
+    /// `None` when constructed with `Settings::default()`.
+    pub output_buffer_watermark: Option<usize>,
+
+    /// Specifies the observability hooks.
+    ///
+    /// Refer to [`ObservabilityHooks`] documentation for more information.
+    ///
+    /// [`ObservabilityHooks`]: struct.ObservabilityHooks.html
+    pub observability_hooks: ObservabilityHooks<'h>,
 }
 
 impl Default for Settings<'_, '_> {
     #[inline]
     fn default() -> Self {
         Settings {
+            #[cfg(feature = "element_handlers")]
             element_content_handlers: vec![],
             document_content_handlers: vec![],
+            token_transformers: vec![],
+            #[cfg(feature = "element_handlers")]
+            runtime_handlers: None,
+            #[cfg(feature = "element_handlers")]
+            max_lookahead: Some(64 * 1024),
             encoding: "utf-8",
+            output_encoding: None,
             memory_settings: MemorySettings::default(),
             strict: true,
+            buffer_script_and_style_text: false,
+            xhtml_output: false,
+            custom_void_elements: vec![],
+            provenance_comments: false,
+            output_buffer_watermark: None,
+            observability_hooks: ObservabilityHooks::default(),
         }
     }
 }
@@ -533,9 +1155,15 @@ impl<'h, 's> From<RewriteStrSettings<'h, 's>> for Settings<'h, 's> {
     #[inline]
     fn from(settings: RewriteStrSettings<'h, 's>) -> Self {
         Settings {
+            #[cfg(feature = "element_handlers")]
             element_content_handlers: settings.element_content_handlers,
             document_content_handlers: settings.document_content_handlers,
+            token_transformers: settings.token_transformers,
             strict: settings.strict,
+            buffer_script_and_style_text: settings.buffer_script_and_style_text,
+            xhtml_output: settings.xhtml_output,
+            custom_void_elements: settings.custom_void_elements,
+            provenance_comments: settings.provenance_comments,
             ..Settings::default()
         }
     }
@@ -583,6 +1211,7 @@ pub struct RewriteStrSettings<'h, 's> {
     /// [`element`]: macro.element.html
     /// [`comments`]: macro.comments.html
     /// [`text`]: macro.text.html
+    #[cfg(feature = "element_handlers")]
     pub element_content_handlers: Vec<(Cow<'s, Selector>, ElementContentHandlers<'h>)>,
 
     /// Specifies rewriting handlers for the content without associating it to a particular
@@ -600,6 +1229,13 @@ pub struct RewriteStrSettings<'h, 's> {
     /// [`doc_text`]: macro.doc_text.html
     pub document_content_handlers: Vec<DocumentContentHandlers<'h>>,
 
+    /// Specifies token transformers that are invoked before any of the content handlers.
+    ///
+    /// Refer to [`TokenTransformer`] documentation for more information.
+    ///
+    /// [`TokenTransformer`]: trait.TokenTransformer.html
+    pub token_transformers: Vec<Box<dyn TokenTransformer + 'h>>,
+
     /// If set to `true` the rewriter bails out if it encounters markup that drives the HTML parser
     /// into ambigious state.
     ///
@@ -633,15 +1269,128 @@ pub struct RewriteStrSettings<'h, 's> {
     ///
     /// `true` when constructed with `Settings::default()`.
     pub strict: bool,
+
+    /// If set to `true` the rewriter buffers the whole text content of `<script>` and `<style>`
+    /// elements and delivers it to text handlers as a single chunk.
+    ///
+    /// By default, text is delivered in chunks that are split at arbitrary points, which makes
+    /// it impossible to apply JavaScript or CSS transformations to the chunks. The option also
+    /// applies to the text of other elements with the same parsing rules as `<style>` (see
+    /// [`TextType::RawText`]).
+    ///
+    /// The buffered text counts towards the memory limit specified in the [`MemorySettings`].
+    ///
+    /// [`TextType::RawText`]: html_content/enum.TextType.html#variant.RawText
+    /// [`MemorySettings`]: struct.MemorySettings.html
+    ///
+    /// ### Default
+    ///
+    /// `false` when constructed with `RewriteStrSettings::default()`.
+    pub buffer_script_and_style_text: bool,
+
+    /// If set to `true` the rewriter produces well-formed [XHTML] output suitable for strict XML
+    /// consumers, e.g. EPUB packagers:
+    ///  * void elements are self-closed (`<br/>`) and the self-closing flag of the other HTML
+    ///    elements is dropped, as their end tags follow in the markup;
+    ///  * tag and attribute names are serialized in lowercase;
+    ///  * attribute values are always double-quoted;
+    ///  * `&` and `<` are escaped in text and attribute values, character references are decoded
+    ///    and re-escaped, so references not defined in XML (e.g. `&nbsp;`) are not emitted;
+    ///  * boolean attributes are not minimized (`checked="checked"`);
+    ///  * `--` sequences are removed from comments.
+    ///
+    /// The output is produced after all the content handlers have been invoked, so it reflects
+    /// the modifications made by them. Content inserted by the handlers as HTML is emitted as is.
+    /// End tags omitted in the input are not inserted, so the input is expected to close all the
+    /// non-void elements. Start and end tags are rewritten only if the crate is built with the
+    /// `element_handlers` feature.
+    ///
+    /// [XHTML]: https://www.w3.org/TR/xhtml1/
+    ///
+    /// ### Default
+    ///
+    /// `false` when constructed with `RewriteStrSettings::default()`.
+    pub xhtml_output: bool,
+
+    /// Specifies the tag names of the elements that are treated as [void elements] in addition
+    /// to the standard ones, e.g. `<icon>` emitted as void by some template languages and email
+    /// generators.
+    ///
+    /// The content that follows the start tag of such an element is not considered to be
+    /// the content of the element: e.g. it's not matched by the selectors of the element's
+    /// descendants, the end tag related APIs of the [`Element`] have no effect and the element is
+    /// serialized without an end tag in the buffered subtrees. Tag names are matched
+    /// case-insensitively.
+    ///
+    /// ### Example
+    /// ```
+    /// use lol_html::{element, rewrite_str, RewriteStrSettings};
+    /// use lol_html::html_content::ContentType;
+    ///
+    /// let html = rewrite_str(
+    ///     "<p><icon name=star><b>Starred</b></p>",
+    ///     RewriteStrSettings {
+    ///         element_content_handlers: vec![
+    ///             element!("p > b", |el| {
+    ///                 el.set_inner_content("Favorite", ContentType::Text);
+    ///
+    ///                 Ok(())
+    ///             })
+    ///         ],
+    ///         custom_void_elements: vec!["icon"],
+    ///         ..RewriteStrSettings::default()
+    ///     }
+    /// ).unwrap();
+    ///
+    /// assert_eq!(html, "<p><icon name=star><b>Favorite</b></p>");
+    /// ```
+    ///
+    /// [void elements]: https://html.spec.whatwg.org/multipage/syntax.html#void-elements
+    /// [`Element`]: html_content/struct.Element.html
+    ///
+    /// ### Default
+    ///
+    /// Empty vector when constructed with `RewriteStrSettings::default()`.
+    pub custom_void_elements: Vec<&'s str>,
+
+    /// If set to `true` the content inserted by the content handlers is wrapped in comments that
+    /// identify the handler that inserted it, e.g. to find out which of the numerous rules
+    /// produced a given fragment of the output. Intended for debugging only.
+    ///
+    /// Content inserted by the handlers of the [`element_content_handlers`] is labeled as
+    /// `rule#N` and content inserted by the handlers of the [`document_content_handlers`] is
+    /// labeled as `doc#N`, where `N` is the index of the handlers in the respective list. The label
+    /// is followed by the offset of the modified element or text in the input, if it's known:
+    ///
+    /// ```html
+    /// <!--lolhtml:rule#12@345--><b>inserted content</b><!--/lolhtml:rule#12@345-->
+    /// ```
+    ///
+    /// Modifications of the tag names and attributes as well as removals are not labeled, as
+    /// they don't produce any content.
+    ///
+    /// [`element_content_handlers`]: #structfield.element_content_handlers
+    /// [`document_content_handlers`]: #structfield.document_content_handlers
+    ///
+    /// ### Default
+    ///
+    /// `false` when constructed with `RewriteStrSettings::default()`.
+    pub provenance_comments: bool,
 }
 
 impl Default for RewriteStrSettings<'_, '_> {
     #[inline]
     fn default() -> Self {
         RewriteStrSettings {
+            #[cfg(feature = "element_handlers")]
             element_content_handlers: vec![],
             document_content_handlers: vec![],
+            token_transformers: vec![],
             strict: true,
+            buffer_script_and_style_text: false,
+            xhtml_output: false,
+            custom_void_elements: vec![],
+            provenance_comments: false,
         }
     }
 }
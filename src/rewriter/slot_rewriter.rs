@@ -0,0 +1,513 @@
+use crate::transform_stream::OutputSink;
+use memchr::memchr;
+use std::ops::Range;
+
+const COMMENT_MARKER_PREFIX: &[u8] = b"<!--slot:";
+const COMMENT_MARKER_SUFFIX: &[u8] = b"-->";
+const TEMPLATE_START_TAG_PREFIX: &[u8] = b"<template";
+const TEMPLATE_END_TAG_PREFIX: &[u8] = b"</template";
+const SLOT_ATTR_NAME: &[u8] = b"data-slot";
+
+// NOTE: markers that are longer than the limit are not recognized, so we never buffer
+// more than that amount of input.
+const MAX_MARKER_LEN: usize = 1024;
+
+#[derive(Debug, PartialEq, Eq)]
+enum MarkerMatch {
+    NeedMoreInput,
+    NoMatch,
+    Comment {
+        len: usize,
+        name: Range<usize>,
+    },
+    TemplateStartTag {
+        len: usize,
+        name: Option<Range<usize>>,
+    },
+    TemplateEndTag {
+        len: usize,
+    },
+}
+
+use MarkerMatch::*;
+
+#[inline]
+fn starts_with_ignore_case(input: &[u8], prefix: &[u8]) -> Option<bool> {
+    if input.len() < prefix.len() {
+        if prefix[..input.len()].eq_ignore_ascii_case(input) {
+            None
+        } else {
+            Some(false)
+        }
+    } else {
+        Some(prefix.eq_ignore_ascii_case(&input[..prefix.len()]))
+    }
+}
+
+#[inline]
+fn is_whitespace(b: u8) -> bool {
+    b.is_ascii_whitespace()
+}
+
+fn find_subslice(input: &[u8], needle: &[u8]) -> Option<usize> {
+    input.windows(needle.len()).position(|w| w == needle)
+}
+
+fn match_comment_marker(input: &[u8]) -> MarkerMatch {
+    match starts_with_ignore_case(input, COMMENT_MARKER_PREFIX) {
+        None => return NeedMoreInput,
+        Some(false) => return NoMatch,
+        Some(true) => (),
+    }
+
+    let name_start = COMMENT_MARKER_PREFIX.len();
+    let search_end = input.len().min(MAX_MARKER_LEN);
+
+    match find_subslice(&input[name_start..search_end], COMMENT_MARKER_SUFFIX) {
+        Some(name_len) => {
+            let mut name = name_start..name_start + name_len;
+
+            while name.start < name.end && is_whitespace(input[name.start]) {
+                name.start += 1;
+            }
+
+            while name.start < name.end && is_whitespace(input[name.end - 1]) {
+                name.end -= 1;
+            }
+
+            if name.start == name.end {
+                NoMatch
+            } else {
+                Comment {
+                    len: name_start + name_len + COMMENT_MARKER_SUFFIX.len(),
+                    name,
+                }
+            }
+        }
+        None if input.len() < MAX_MARKER_LEN => NeedMoreInput,
+        None => NoMatch,
+    }
+}
+
+/// Parses attributes of a tag, starting at `pos`, up to the closing `>`. Returns the length
+/// of the tag and the value of the `data-slot` attribute.
+fn parse_tag_attributes(input: &[u8], mut pos: usize) -> MarkerMatch {
+    let mut slot_name = None;
+    let input = &input[..input.len().min(MAX_MARKER_LEN)];
+
+    macro_rules! byte_at {
+        ($pos:expr) => {
+            match input.get($pos) {
+                Some(&b) => b,
+                None if input.len() < MAX_MARKER_LEN => return NeedMoreInput,
+                None => return NoMatch,
+            }
+        };
+    }
+
+    loop {
+        while is_whitespace(byte_at!(pos)) || byte_at!(pos) == b'/' {
+            pos += 1;
+        }
+
+        if byte_at!(pos) == b'>' {
+            return TemplateStartTag {
+                len: pos + 1,
+                name: slot_name,
+            };
+        }
+
+        let name_start = pos;
+
+        while !is_whitespace(byte_at!(pos)) && !matches!(byte_at!(pos), b'/' | b'>' | b'=') {
+            pos += 1;
+        }
+
+        let attr_name = name_start..pos;
+
+        while is_whitespace(byte_at!(pos)) {
+            pos += 1;
+        }
+
+        let mut value = pos..pos;
+
+        if byte_at!(pos) == b'=' {
+            pos += 1;
+
+            while is_whitespace(byte_at!(pos)) {
+                pos += 1;
+            }
+
+            let quote = byte_at!(pos);
+
+            if quote == b'"' || quote == b'\'' {
+                pos += 1;
+                value = pos..pos;
+
+                while byte_at!(pos) != quote {
+                    pos += 1;
+                }
+
+                value.end = pos;
+                pos += 1;
+            } else {
+                value = pos..pos;
+
+                while !is_whitespace(byte_at!(pos)) && byte_at!(pos) != b'>' {
+                    pos += 1;
+                }
+
+                value.end = pos;
+            }
+        }
+
+        if slot_name.is_none() && input[attr_name].eq_ignore_ascii_case(SLOT_ATTR_NAME) {
+            slot_name = Some(value).filter(|v| v.start < v.end);
+        }
+    }
+}
+
+fn match_template_tag(input: &[u8]) -> MarkerMatch {
+    let (prefix, is_end_tag) = match input.get(1) {
+        Some(b'/') => (TEMPLATE_END_TAG_PREFIX, true),
+        Some(_) => (TEMPLATE_START_TAG_PREFIX, false),
+        None => return NeedMoreInput,
+    };
+
+    match starts_with_ignore_case(input, prefix) {
+        None => return NeedMoreInput,
+        Some(false) => return NoMatch,
+        Some(true) => (),
+    }
+
+    match input.get(prefix.len()) {
+        Some(&b) if is_whitespace(b) || b == b'>' || b == b'/' => (),
+        Some(_) => return NoMatch,
+        None => return NeedMoreInput,
+    }
+
+    match parse_tag_attributes(input, prefix.len()) {
+        TemplateStartTag { len, .. } if is_end_tag => TemplateEndTag { len },
+        m => m,
+    }
+}
+
+fn match_marker(input: &[u8]) -> MarkerMatch {
+    match input.get(1) {
+        Some(b'!') => match_comment_marker(input),
+        Some(b't') | Some(b'T') => match match_template_tag(input) {
+            TemplateStartTag { name: None, .. } => NoMatch,
+            m => m,
+        },
+        Some(_) => NoMatch,
+        None => NeedMoreInput,
+    }
+}
+
+/// A streaming rewriter for the templating use case that replaces named slot markers with the
+/// content supplied by a provider.
+///
+/// The rewriter recognizes two kinds of markers:
+///  * `<!--slot:name-->` comments, which are replaced with the provided content.
+///  * `<template data-slot="name">` elements, which are replaced with the provided content
+///    along with their inner content, including nested `<template>` elements.
+///
+/// The `provider` is called with the name of each found marker. If it returns `None`, the marker
+/// is left intact. The provided content is written to the output as is.
+///
+/// Unlike [`HtmlRewriter`], the slot rewriter doesn't parse HTML: it scans the input for the
+/// markers with a specialized scanner, which makes it an order of magnitude cheaper than the
+/// generic selector matching. As a drawback, markers are recognized anywhere in the input,
+/// e.g. inside of `<script>` elements or inside of other comments. Markers longer than 1KiB are
+/// not recognized, so the rewriter never buffers more than that amount of input.
+///
+/// # Example
+/// ```
+/// use lol_html::SlotRewriter;
+///
+/// let mut output = vec![];
+///
+/// {
+///     let mut rewriter = SlotRewriter::new(
+///         |name: &str| match name {
+///             "header" => Some("<h1>Hello</h1>".to_string()),
+///             "footer" => Some("<p>Bye</p>".to_string()),
+///             _ => None,
+///         },
+///         |c: &[u8]| output.extend_from_slice(c)
+///     );
+///
+///     rewriter.write(b"<body><!--slot:header--><main>");
+///     rewriter.write(br#"</main><template data-slot="footer">Default</template></body>"#);
+///     rewriter.end();
+/// }
+///
+/// assert_eq!(
+///     String::from_utf8(output).unwrap(),
+///     "<body><h1>Hello</h1><main></main><p>Bye</p></body>"
+/// );
+/// ```
+///
+/// [`HtmlRewriter`]: struct.HtmlRewriter.html
+pub struct SlotRewriter<P, O>
+where
+    P: FnMut(&str) -> Option<String>,
+    O: OutputSink,
+{
+    provider: P,
+    output_sink: O,
+    pending: Vec<u8>,
+    // NOTE: the depth of nested templates in the content of a replaced template.
+    skipped_template_depth: Option<usize>,
+    finished: bool,
+}
+
+impl<P, O> SlotRewriter<P, O>
+where
+    P: FnMut(&str) -> Option<String>,
+    O: OutputSink,
+{
+    /// Constructs a new rewriter that replaces markers with the content produced by the
+    /// `provider` and writes the output to the `output_sink`.
+    #[inline]
+    pub fn new(provider: P, output_sink: O) -> Self {
+        SlotRewriter {
+            provider,
+            output_sink,
+            pending: Vec::default(),
+            skipped_template_depth: None,
+            finished: false,
+        }
+    }
+
+    fn emit(&mut self, chunk: &[u8]) {
+        if !chunk.is_empty() {
+            self.output_sink.handle_chunk(chunk);
+        }
+    }
+
+    fn emit_slot(&mut self, input: &[u8], name: Range<usize>, marker_len: usize) -> bool {
+        let content = std::str::from_utf8(&input[name])
+            .ok()
+            .and_then(|name| (self.provider)(name));
+
+        match content {
+            Some(content) => {
+                self.emit(content.as_bytes());
+                true
+            }
+            None => {
+                self.emit(&input[..marker_len]);
+                false
+            }
+        }
+    }
+
+    fn skip_template_content(&mut self, input: &[u8], last: bool) -> usize {
+        let mut pos = 0;
+
+        while let Some(depth) = self.skipped_template_depth {
+            let lt_pos = match memchr(b'<', &input[pos..]) {
+                Some(p) => pos + p,
+                None => return input.len(),
+            };
+
+            match match_template_tag(&input[lt_pos..]) {
+                NeedMoreInput if last => return input.len(),
+                NeedMoreInput => return lt_pos,
+                TemplateStartTag { len, .. } => {
+                    self.skipped_template_depth = Some(depth + 1);
+                    pos = lt_pos + len;
+                }
+                TemplateEndTag { len } => {
+                    self.skipped_template_depth = depth.checked_sub(1);
+                    pos = lt_pos + len;
+                }
+                _ => pos = lt_pos + 1,
+            }
+        }
+
+        pos
+    }
+
+    /// Processes the input and returns the number of consumed bytes.
+    fn process(&mut self, input: &[u8], last: bool) -> usize {
+        let mut pos = 0;
+
+        loop {
+            if self.skipped_template_depth.is_some() {
+                pos += self.skip_template_content(&input[pos..], last);
+
+                if self.skipped_template_depth.is_some() {
+                    return pos;
+                }
+            }
+
+            let lt_pos = match memchr(b'<', &input[pos..]) {
+                Some(p) => pos + p,
+                None => {
+                    self.emit(&input[pos..]);
+                    return input.len();
+                }
+            };
+
+            self.emit(&input[pos..lt_pos]);
+
+            let marker = &input[lt_pos..];
+
+            match match_marker(marker) {
+                NeedMoreInput if last => {
+                    self.emit(marker);
+                    return input.len();
+                }
+                NeedMoreInput => return lt_pos,
+                NoMatch | TemplateEndTag { .. } => {
+                    self.emit(b"<");
+                    pos = lt_pos + 1;
+                }
+                Comment { len, name } => {
+                    self.emit_slot(marker, name, len);
+                    pos = lt_pos + len;
+                }
+                TemplateStartTag { len, name } => {
+                    if let Some(name) = name {
+                        if self.emit_slot(marker, name, len) {
+                            self.skipped_template_depth = Some(0);
+                        }
+                    }
+
+                    pos = lt_pos + len;
+                }
+            }
+        }
+    }
+
+    /// Writes a chunk of input data to the rewriter.
+    ///
+    /// # Panics
+    ///  * If called after [`end`].
+    ///
+    /// [`end`]: #method.end
+    pub fn write(&mut self, data: &[u8]) {
+        assert!(
+            !self.finished,
+            "Data was written into the stream after it has ended."
+        );
+
+        if self.pending.is_empty() {
+            let consumed = self.process(data, false);
+
+            self.pending.extend_from_slice(&data[consumed..]);
+        } else {
+            let mut input = std::mem::take(&mut self.pending);
+
+            input.extend_from_slice(data);
+
+            let consumed = self.process(&input, false);
+
+            input.drain(..consumed);
+            self.pending = input;
+        }
+    }
+
+    /// Finalizes the rewriting process.
+    ///
+    /// Should be called once the last chunk of the input is written.
+    ///
+    /// # Panics
+    ///  * If called twice.
+    pub fn end(&mut self) {
+        assert!(!self.finished, "Stream was ended twice.");
+        self.finished = true;
+
+        let input = std::mem::take(&mut self.pending);
+
+        self.process(&input, true);
+        self.output_sink.handle_chunk(&[]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rewrite_slots(input: &str, chunk_size: usize) -> String {
+        let mut output = vec![];
+
+        {
+            let mut rewriter = SlotRewriter::new(
+                |name: &str| match name {
+                    "unknown" => None,
+                    _ => Some(format!("[{}]", name)),
+                },
+                |c: &[u8]| output.extend_from_slice(c),
+            );
+
+            for chunk in input.as_bytes().chunks(chunk_size) {
+                rewriter.write(chunk);
+            }
+
+            rewriter.end();
+        }
+
+        String::from_utf8(output).unwrap()
+    }
+
+    fn assert_rewrite(input: &str, expected: &str) {
+        for chunk_size in 1..=input.len() {
+            assert_eq!(
+                rewrite_slots(input, chunk_size),
+                expected,
+                "Chunk size: {}",
+                chunk_size
+            );
+        }
+    }
+
+    #[test]
+    fn comment_markers() {
+        assert_rewrite(
+            "<div><!--slot:a--><!-- slot:b --><!--slot: c --></div>",
+            "<div>[a]<!-- slot:b -->[c]</div>",
+        );
+
+        assert_rewrite(
+            "<!--slot:unknown--><!--slot:--><!--SLOT:x-->",
+            "<!--slot:unknown--><!--slot:-->[x]",
+        );
+    }
+
+    #[test]
+    fn template_markers() {
+        assert_rewrite(
+            r#"<p><template id=t data-slot="a">x</template><TEMPLATE data-slot='b'></template></p>"#,
+            "<p>[a][b]</p>",
+        );
+
+        assert_rewrite(
+            "<template data-slot=a><template>x</template><template></template></template>y",
+            "[a]y",
+        );
+
+        assert_rewrite(
+            "<template data-slot=unknown>x</template><template>y</template><templates>",
+            "<template data-slot=unknown>x</template><template>y</template><templates>",
+        );
+    }
+
+    #[test]
+    fn incomplete_markers() {
+        assert_rewrite("foo<!--slot:a-", "foo<!--slot:a-");
+        assert_rewrite("foo<template data-slot=a", "foo<template data-slot=a");
+        assert_rewrite("foo<template data-slot=a>bar</templ", "foo[a]");
+        assert_rewrite("foo<", "foo<");
+    }
+
+    #[test]
+    fn marker_length_limit() {
+        let name = "a".repeat(MAX_MARKER_LEN);
+        let input = format!("<!--slot:{}-->", name);
+
+        assert_eq!(rewrite_slots(&input, 100), input);
+    }
+}
@@ -0,0 +1,480 @@
+use super::{
+    discard_output, try_encoding_from_str, EncodingError, MemorySettings, RewritingError,
+    WhitespaceSensitiveElements,
+};
+use crate::html::{decode_char_refs, LocalName, Namespace, TextType};
+use crate::memory::MemoryLimiter;
+use crate::rewritable_units::{DocumentEnd, EndTag, StartTag, TextChunk, Token, TokenCaptureFlags};
+use crate::transform_stream::*;
+use std::cmp::max;
+
+const HTML_NAMESPACE_URI: &str = "http://www.w3.org/1999/xhtml";
+
+// NOTE: text of `<script>`, `<style>` and other raw text elements is skipped based on the
+// text type, the elements below contain regular text that is not displayed in the document.
+const SKIPPED_ELEMENTS: &[&str] = &["template", "title"];
+
+// NOTE: elements that ignore a newline that immediately follows their start tag.
+const LEADING_NEWLINE_ELEMENTS: &[&str] = &["listing", "pre", "textarea"];
+
+// NOTE: elements that are separated from the surrounding text by a blank line.
+const PARAGRAPH_ELEMENTS: &[&str] = &[
+    "blockquote",
+    "dl",
+    "figure",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "hr",
+    "listing",
+    "ol",
+    "p",
+    "pre",
+    "table",
+    "ul",
+];
+
+const BLOCK_ELEMENTS: &[&str] = &[
+    "address",
+    "article",
+    "aside",
+    "body",
+    "caption",
+    "center",
+    "dd",
+    "details",
+    "dialog",
+    "div",
+    "dt",
+    "fieldset",
+    "figcaption",
+    "footer",
+    "form",
+    "header",
+    "hgroup",
+    "html",
+    "legend",
+    "li",
+    "main",
+    "menu",
+    "nav",
+    "option",
+    "section",
+    "summary",
+    "tr",
+];
+
+const CELL_ELEMENTS: &[&str] = &["td", "th"];
+
+struct TextExtractionController<'s> {
+    text_sink: Box<dyn FnMut(&str) + 's>,
+    whitespace_sensitive_elements: WhitespaceSensitiveElements,
+    skip_depth: usize,
+    preformatted_depth: usize,
+    preformatted_start: bool,
+    pending_newlines: usize,
+    pending_space: bool,
+    has_output: bool,
+}
+
+impl TextExtractionController<'_> {
+    fn write(&mut self, text: &str) {
+        if self.has_output {
+            if self.pending_newlines > 0 {
+                (self.text_sink)(&"\n".repeat(self.pending_newlines));
+            } else if self.pending_space {
+                (self.text_sink)(" ");
+            }
+        }
+
+        (self.text_sink)(text);
+
+        self.pending_newlines = 0;
+        self.pending_space = false;
+        self.has_output = true;
+    }
+
+    #[inline]
+    fn line_break(&mut self, newlines: usize) {
+        self.pending_newlines = max(self.pending_newlines, newlines);
+    }
+
+    fn handle_block_boundary(&mut self, tag_name: &str) {
+        if PARAGRAPH_ELEMENTS.contains(&tag_name) {
+            self.line_break(2);
+        } else if BLOCK_ELEMENTS.contains(&tag_name) {
+            self.line_break(1);
+        } else if CELL_ELEMENTS.contains(&tag_name) {
+            self.pending_space = true;
+        }
+    }
+
+    fn handle_start_tag(&mut self, start_tag: &StartTag) {
+        if start_tag.namespace_uri() != HTML_NAMESPACE_URI {
+            return;
+        }
+
+        let tag_name = start_tag.name();
+
+        if SKIPPED_ELEMENTS.contains(&tag_name) {
+            self.skip_depth += 1;
+        } else if self.whitespace_sensitive_elements.contains(&tag_name) {
+            self.preformatted_depth += 1;
+            self.preformatted_start = LEADING_NEWLINE_ELEMENTS.contains(&tag_name);
+        } else if tag_name == "br" && self.skip_depth == 0 {
+            self.pending_newlines += 1;
+        }
+
+        if self.skip_depth == 0 {
+            self.handle_block_boundary(&tag_name);
+        }
+    }
+
+    fn handle_end_tag(&mut self, end_tag: &EndTag) {
+        let tag_name = end_tag.name();
+
+        if SKIPPED_ELEMENTS.contains(&tag_name) {
+            self.skip_depth = self.skip_depth.saturating_sub(1);
+        } else if self.whitespace_sensitive_elements.contains(&tag_name) {
+            self.preformatted_depth = self.preformatted_depth.saturating_sub(1);
+        } else if tag_name == "br" && self.skip_depth == 0 {
+            // NOTE: `</br>` is treated by browsers as `<br>`.
+            self.pending_newlines += 1;
+        }
+
+        if self.skip_depth == 0 {
+            self.handle_block_boundary(&tag_name);
+        }
+    }
+
+    fn handle_text(&mut self, text_chunk: &TextChunk) {
+        let text_type = text_chunk.text_type();
+
+        if self.skip_depth > 0
+            || text_type == TextType::ScriptData
+            || text_type == TextType::RawText
+        {
+            return;
+        }
+
+        let text = if text_type.allows_html_entities() {
+            decode_char_refs(text_chunk.as_str())
+        } else {
+            text_chunk.as_str().into()
+        };
+
+        let preformatted = self.preformatted_depth > 0
+            || text_type == TextType::RCData
+            || text_type == TextType::PlainText;
+
+        if preformatted {
+            let mut text = &*text;
+
+            if self.preformatted_start && !text.is_empty() {
+                self.preformatted_start = false;
+
+                // NOTE: a newline that immediately follows the `<pre>` start tag is ignored.
+                if text.starts_with('\n') {
+                    text = &text[1..];
+                }
+            }
+
+            if !text.is_empty() {
+                self.write(text);
+            }
+        } else {
+            let mut words = text.split_ascii_whitespace();
+
+            if text.starts_with(|c: char| c.is_ascii_whitespace()) {
+                self.pending_space = true;
+            }
+
+            if let Some(first) = words.next() {
+                let mut collapsed = first.to_string();
+
+                for word in words {
+                    collapsed.push(' ');
+                    collapsed.push_str(word);
+                }
+
+                self.write(&collapsed);
+            }
+
+            if text.ends_with(|c: char| c.is_ascii_whitespace()) {
+                self.pending_space = true;
+            }
+        }
+    }
+}
+
+impl TransformController for TextExtractionController<'_> {
+    #[inline]
+    fn initial_capture_flags(&self) -> TokenCaptureFlags {
+        TokenCaptureFlags::TEXT
+            | TokenCaptureFlags::NEXT_START_TAG
+            | TokenCaptureFlags::NEXT_END_TAG
+    }
+
+    #[inline]
+    fn handle_start_tag(&mut self, _: LocalName, _: Namespace) -> StartTagHandlingResult<Self> {
+        Ok(self.initial_capture_flags())
+    }
+
+    #[inline]
+    fn handle_end_tag(&mut self, _: LocalName) -> TokenCaptureFlags {
+        self.initial_capture_flags()
+    }
+
+    fn handle_token(&mut self, token: &mut Token) -> Result<(), RewritingError> {
+        match token {
+            Token::StartTag(start_tag) => self.handle_start_tag(start_tag),
+            Token::EndTag(end_tag) => self.handle_end_tag(end_tag),
+            Token::TextChunk(text_chunk) => self.handle_text(text_chunk),
+            _ => (),
+        }
+
+        Ok(())
+    }
+
+    #[inline]
+    fn handle_end(&mut self, _: &mut DocumentEnd) -> Result<(), RewritingError> {
+        Ok(())
+    }
+
+    #[inline]
+    fn should_emit_content(&self) -> bool {
+        false
+    }
+}
+
+/// A streaming extractor of the readable plain text of a document.
+///
+/// The extractor strips all the markup and writes the text of the document to the provided
+/// sink. Character references are decoded, whitespace is collapsed the way browsers do it
+/// (except for the content of the [whitespace-sensitive elements]), block elements are separated
+/// by newlines and `<br>` elements produce line breaks. Content of `<script>`, `<style>`,
+/// `<template>`, `<title>` and other elements that are not rendered as text is skipped.
+///
+/// Leading and trailing whitespace of the document text is not written to the sink.
+///
+/// # Example
+/// ```
+/// use lol_html::TextExtractor;
+///
+/// let mut text = String::new();
+///
+/// {
+///     let mut extractor = TextExtractor::try_new("utf-8", |t| text.push_str(t)).unwrap();
+///
+///     extractor.write(b"<h1>Hello</h1><p>Fish &amp;\n  chips<br>").unwrap();
+///     extractor.write(b"<script>alert(1)</script>to go</p>").unwrap();
+///     extractor.end().unwrap();
+/// }
+///
+/// assert_eq!(text, "Hello\n\nFish & chips\nto go");
+/// ```
+///
+/// [whitespace-sensitive elements]: struct.WhitespaceSensitiveElements.html
+pub struct TextExtractor<'s> {
+    stream: TransformStream<TextExtractionController<'s>, fn(&[u8])>,
+}
+
+impl<'s> TextExtractor<'s> {
+    /// Constructs a new extractor for the document in the `encoding` that writes the text
+    /// to the `text_sink`.
+    #[inline]
+    pub fn try_new(
+        encoding: &str,
+        text_sink: impl FnMut(&str) + 's,
+    ) -> Result<Self, EncodingError> {
+        Self::try_with_whitespace_sensitive_elements(
+            encoding,
+            WhitespaceSensitiveElements::default(),
+            text_sink,
+        )
+    }
+
+    /// Constructs a new extractor that preserves whitespace in the content of the
+    /// `whitespace_sensitive_elements`.
+    ///
+    /// See [`WhitespaceSensitiveElements`] for an example.
+    ///
+    /// [`WhitespaceSensitiveElements`]: struct.WhitespaceSensitiveElements.html
+    pub fn try_with_whitespace_sensitive_elements(
+        encoding: &str,
+        whitespace_sensitive_elements: WhitespaceSensitiveElements,
+        text_sink: impl FnMut(&str) + 's,
+    ) -> Result<Self, EncodingError> {
+        let encoding = try_encoding_from_str(encoding)?;
+        let memory_settings = MemorySettings::default();
+
+        let stream = TransformStream::new(TransformStreamSettings {
+            transform_controller: TextExtractionController {
+                text_sink: Box::new(text_sink),
+                whitespace_sensitive_elements,
+                skip_depth: 0,
+                preformatted_depth: 0,
+                preformatted_start: false,
+                pending_newlines: 0,
+                pending_space: false,
+                has_output: false,
+            },
+            // NOTE: the text is passed to the text sink, while the markup is never serialized.
+            output_sink: discard_output as fn(&[u8]),
+            preallocated_parsing_buffer_size: memory_settings.preallocated_parsing_buffer_size,
+            parsing_buffer_compaction_watermark: memory_settings
+                .parsing_buffer_compaction_watermark,
+            parsing_buffer_shrink_watermark: memory_settings.parsing_buffer_shrink_watermark,
+            memory_limiter: MemoryLimiter::new_shared(memory_settings.max_allowed_memory_usage),
+            encoding,
+            strict: false,
+            buffer_script_and_style_text: false,
+        });
+
+        Ok(TextExtractor { stream })
+    }
+
+    /// Writes a chunk of input data to the extractor.
+    #[inline]
+    pub fn write(&mut self, data: &[u8]) -> Result<(), RewritingError> {
+        self.stream.write(data)
+    }
+
+    /// Finalizes the extraction.
+    ///
+    /// Should be called once the last chunk of the input is written.
+    #[inline]
+    pub fn end(mut self) -> Result<(), RewritingError> {
+        self.stream.end()
+    }
+}
+
+/// Extracts the readable plain text of the given `html` string.
+///
+/// See [`TextExtractor`] for the details of the extraction.
+///
+/// # Example
+/// ```
+/// use lol_html::html_to_text;
+///
+/// let text = html_to_text("<ul><li>Foo</li><li>Bar <b>baz</b></li></ul>").unwrap();
+///
+/// assert_eq!(text, "Foo\nBar baz");
+/// ```
+///
+/// [`TextExtractor`]: struct.TextExtractor.html
+pub fn html_to_text(html: &str) -> Result<String, RewritingError> {
+    let mut text = String::new();
+
+    {
+        // NOTE: never panics because encoding is always "utf-8".
+        let mut extractor = TextExtractor::try_new("utf-8", |t| text.push_str(t)).unwrap();
+
+        extractor.write(html.as_bytes())?;
+        extractor.end()?;
+    }
+
+    Ok(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn extract(html: &str) -> String {
+        let mut text = String::new();
+
+        {
+            let mut extractor = TextExtractor::try_new("utf-8", |t| text.push_str(t)).unwrap();
+
+            for chunk in html.as_bytes().chunks(3) {
+                extractor.write(chunk).unwrap();
+            }
+
+            extractor.end().unwrap();
+        }
+
+        text
+    }
+
+    #[test]
+    fn whitespace_collapsing() {
+        assert_eq!(
+            extract("  <div>\n  Foo   bar\t<b>baz</b><i> qux </i>quux  </div>  "),
+            "Foo bar baz qux quux"
+        );
+    }
+
+    #[test]
+    fn blocks_and_line_breaks() {
+        assert_eq!(
+            extract("<div>Foo</div><div><div>Bar</div></div><p>Baz</p><p>Qux<br><br>Quux</p>"),
+            "Foo\nBar\n\nBaz\n\nQux\n\nQuux"
+        );
+
+        assert_eq!(extract("Foo<br></br>Bar<br>"), "Foo\n\nBar");
+    }
+
+    #[test]
+    fn tables() {
+        assert_eq!(
+            extract("<table><tr><th>A<th>B<tr><td>1</td><td>2</td></tr></table>After"),
+            "A B\n1 2\n\nAfter"
+        );
+    }
+
+    #[test]
+    fn skipped_content() {
+        assert_eq!(
+            extract(concat!(
+                "<head><title>Title</title><style>p { color: red }</style></head>",
+                "<body>Foo <script>document.write('<p>bar</p>')</script>",
+                "<template><p>Baz</p></template><noscript>Qux</noscript>",
+                "<svg><title>Circle</title></svg></body>",
+            )),
+            "Foo Circle"
+        );
+    }
+
+    #[test]
+    fn char_refs() {
+        assert_eq!(
+            extract("<p title='&amp;'>&lt;b&gt; &copy; &#x1F600;&nbsp;&amp</p><textarea>&lt;</textarea>"),
+            "<b> \u{A9} \u{1F600}\u{A0}&amp\n\n<"
+        );
+    }
+
+    #[test]
+    fn preformatted_text() {
+        assert_eq!(
+            extract("<p>Foo</p><pre>\n  fn main() {\n      foo();\n  }</pre>Bar"),
+            "Foo\n\n  fn main() {\n      foo();\n  }\n\nBar"
+        );
+    }
+
+    #[test]
+    fn custom_whitespace_sensitive_elements() {
+        let mut text = String::new();
+
+        {
+            let mut extractor = TextExtractor::try_with_whitespace_sensitive_elements(
+                "utf-8",
+                WhitespaceSensitiveElements::default()
+                    .add("code-block")
+                    .remove("pre"),
+                |t| text.push_str(t),
+            )
+            .unwrap();
+
+            extractor
+                .write(b"<pre>a   b</pre><code-block>c   d </code-block>")
+                .unwrap();
+            extractor.end().unwrap();
+        }
+
+        assert_eq!(text, "a b\n\nc   d ");
+    }
+}
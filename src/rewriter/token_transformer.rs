@@ -0,0 +1,265 @@
+use super::settings::*;
+use crate::rewritable_units::{Comment, Doctype, Element, TextChunk, TokenKind};
+use std::cell::RefCell;
+use std::error::Error;
+use std::rc::Rc;
+
+/// A reusable transform of the tokens of the document.
+///
+/// Transformers are specified in the [`token_transformers`] setting of the rewriter and are
+/// invoked for tokens before any of the content handlers, so libraries can ship transforms
+/// (e.g. removal of tracking pixels) that compose with the user's handlers. Transformers can
+/// be stacked: each transformer observes the modifications made by the transformers that
+/// precede it in the list, and the content handlers observe the modifications made by all of
+/// them.
+///
+/// Only the methods for the token kinds returned by [`token_kinds`] are invoked. Note that each
+/// requested token kind requires the rewriter to tokenize the whole document, which has a
+/// performance cost. Start tags are passed to the transformers only if the crate is built with
+/// the `element_handlers` feature.
+///
+/// # Note
+/// Selectors of the content handlers are matched against the original markup, so, for example,
+/// an element renamed by a transformer is still matched by the selectors for the original name.
+///
+/// # Example
+/// ```
+/// use lol_html::{rewrite_str, RewriteStrSettings, TokenTransformer};
+/// use lol_html::html_content::{Element, TokenKind};
+/// use std::error::Error;
+///
+/// struct TrackingPixelRemover;
+///
+/// impl TokenTransformer for TrackingPixelRemover {
+///     fn token_kinds(&self) -> &[TokenKind] {
+///         &[TokenKind::StartTag]
+///     }
+///
+///     fn transform_element(
+///         &mut self,
+///         element: &mut Element,
+///     ) -> Result<(), Box<dyn Error + Send + Sync>> {
+///         let width = element.get_attribute("width");
+///
+///         if element.tag_name() == "img" && width.as_deref() == Some("1") {
+///             element.remove();
+///         }
+///
+///         Ok(())
+///     }
+/// }
+///
+/// let html = rewrite_str(
+///     r#"<p>Hi<img width="1" src="/pixel.gif"></p>"#,
+///     RewriteStrSettings {
+///         token_transformers: vec![Box::new(TrackingPixelRemover)],
+///         ..RewriteStrSettings::default()
+///     }
+/// ).unwrap();
+///
+/// assert_eq!(html, "<p>Hi</p>");
+/// ```
+///
+/// [`token_transformers`]: struct.Settings.html#structfield.token_transformers
+/// [`token_kinds`]: #tymethod.token_kinds
+pub trait TokenTransformer {
+    /// Returns the kinds of tokens the transformer should be invoked for.
+    ///
+    /// Start tags are passed to the transformer as [`Element`]s. End tags are not passed to
+    /// transformers, so [`TokenKind::EndTag`] is ignored.
+    ///
+    /// [`Element`]: html_content/struct.Element.html
+    /// [`TokenKind::EndTag`]: html_content/enum.TokenKind.html#variant.EndTag
+    fn token_kinds(&self) -> &[TokenKind];
+
+    /// Transforms an element.
+    #[inline]
+    fn transform_element(
+        &mut self,
+        element: &mut Element,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let _ = element;
+
+        Ok(())
+    }
+
+    /// Transforms a text chunk.
+    #[inline]
+    fn transform_text(&mut self, text: &mut TextChunk) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let _ = text;
+
+        Ok(())
+    }
+
+    /// Transforms an HTML comment.
+    #[inline]
+    fn transform_comment(
+        &mut self,
+        comment: &mut Comment,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let _ = comment;
+
+        Ok(())
+    }
+
+    /// Transforms a document type declaration.
+    #[inline]
+    fn transform_doctype(
+        &mut self,
+        doctype: &mut Doctype,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let _ = doctype;
+
+        Ok(())
+    }
+}
+
+macro_rules! add_handler {
+    ($handlers:ident, $setter:ident, $transformer:ident, $method:ident) => {{
+        let transformer = Rc::clone(&$transformer);
+
+        $handlers.$setter(move |unit| transformer.borrow_mut().$method(unit))
+    }};
+}
+
+/// Converts the transformer into the document-level handlers for text, comments and
+/// doctypes and the element handler which should be associated with the universal selector.
+pub(super) fn into_handlers<'h>(
+    transformer: Box<dyn TokenTransformer + 'h>,
+) -> (
+    DocumentContentHandlers<'h>,
+    Option<ElementContentHandlers<'h>>,
+) {
+    let kinds = transformer.token_kinds().to_vec();
+    let transformer = Rc::new(RefCell::new(transformer));
+    let mut document_handlers = DocumentContentHandlers::default();
+    let mut element_handlers = None;
+
+    for kind in kinds {
+        match kind {
+            TokenKind::Text => {
+                document_handlers =
+                    add_handler!(document_handlers, text, transformer, transform_text);
+            }
+            TokenKind::Comment => {
+                document_handlers =
+                    add_handler!(document_handlers, comments, transformer, transform_comment);
+            }
+            TokenKind::Doctype => {
+                document_handlers =
+                    add_handler!(document_handlers, doctype, transformer, transform_doctype);
+            }
+            TokenKind::StartTag => {
+                let handlers = ElementContentHandlers::default();
+
+                element_handlers = Some(add_handler!(
+                    handlers,
+                    element,
+                    transformer,
+                    transform_element
+                ));
+            }
+            TokenKind::EndTag => (),
+        }
+    }
+
+    (document_handlers, element_handlers)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::html_content::ContentType;
+    use crate::*;
+
+    struct Tagger(&'static str, Vec<TokenKind>);
+
+    impl TokenTransformer for Tagger {
+        fn token_kinds(&self) -> &[TokenKind] {
+            &self.1
+        }
+
+        fn transform_element(
+            &mut self,
+            element: &mut Element,
+        ) -> Result<(), Box<dyn Error + Send + Sync>> {
+            let tags = element.get_attribute("tags").unwrap_or_default();
+
+            element.set_attribute("tags", &format!("{}{}", tags, self.0))?;
+
+            Ok(())
+        }
+
+        fn transform_text(
+            &mut self,
+            text: &mut TextChunk,
+        ) -> Result<(), Box<dyn Error + Send + Sync>> {
+            if !text.as_str().is_empty() {
+                text.before(self.0, ContentType::Text);
+            }
+
+            Ok(())
+        }
+
+        fn transform_comment(
+            &mut self,
+            comment: &mut Comment,
+        ) -> Result<(), Box<dyn Error + Send + Sync>> {
+            comment.set_text(&format!("{}{}", comment.text(), self.0))?;
+
+            Ok(())
+        }
+    }
+
+    fn all_kinds() -> Vec<TokenKind> {
+        vec![
+            TokenKind::Text,
+            TokenKind::Comment,
+            TokenKind::StartTag,
+            TokenKind::EndTag,
+            TokenKind::Doctype,
+        ]
+    }
+
+    #[test]
+    fn stacked_transformers() {
+        let html = rewrite_str(
+            "<!doctype html><div>foo<!--bar--></div>",
+            RewriteStrSettings {
+                element_content_handlers: vec![element!("div", |el| {
+                    assert_eq!(el.get_attribute("tags").unwrap(), "12");
+                    el.set_attribute("tags", "user")?;
+                    Ok(())
+                })],
+                token_transformers: vec![
+                    Box::new(Tagger("1", all_kinds())),
+                    Box::new(Tagger("2", all_kinds())),
+                ],
+                ..RewriteStrSettings::default()
+            },
+        )
+        .unwrap();
+
+        assert_eq!(
+            html,
+            r#"<!doctype html><div tags="user">12foo<!--bar12--></div>"#
+        );
+    }
+
+    #[test]
+    fn token_kinds() {
+        let html = rewrite_str(
+            "<!doctype html><div>foo<!--bar--></div>",
+            RewriteStrSettings {
+                token_transformers: vec![Box::new(Tagger(
+                    "1",
+                    vec![TokenKind::Comment, TokenKind::EndTag],
+                ))],
+                ..RewriteStrSettings::default()
+            },
+        )
+        .unwrap();
+
+        assert_eq!(html, "<!doctype html><div>foo<!--bar1--></div>");
+    }
+}
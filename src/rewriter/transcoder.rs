@@ -0,0 +1,106 @@
+use encoding_rs::{CoderResult, Decoder, Encoder, Encoding};
+
+/// Converts the output of the rewriter from the document's encoding to the output encoding.
+///
+/// Characters split between chunks are carried over to the next chunk. Characters that can't be
+/// represented in the output encoding are replaced with numeric character references.
+pub struct Transcoder {
+    decoder: Decoder,
+    encoder: Encoder,
+    text: String,
+    output: Vec<u8>,
+}
+
+impl Transcoder {
+    pub fn new(encoding: &'static Encoding, output_encoding: &'static Encoding) -> Self {
+        Transcoder {
+            decoder: encoding.new_decoder_without_bom_handling(),
+            encoder: output_encoding.new_encoder(),
+            text: String::new(),
+            output: Vec::new(),
+        }
+    }
+
+    /// Transcodes the `chunk`. An empty chunk is considered to be the last one.
+    pub fn transcode(&mut self, chunk: &[u8]) -> &[u8] {
+        let last = chunk.is_empty();
+        let mut input = chunk;
+
+        self.text.clear();
+        self.output.clear();
+
+        loop {
+            let additional = self
+                .decoder
+                .max_utf8_buffer_length(input.len())
+                .unwrap_or(input.len());
+
+            self.text.reserve(additional);
+
+            let (result, read, _) = self.decoder.decode_to_string(input, &mut self.text, last);
+
+            input = &input[read..];
+
+            if let CoderResult::InputEmpty = result {
+                break;
+            }
+        }
+
+        let mut text = &self.text[..];
+
+        loop {
+            let additional = self
+                .encoder
+                .max_buffer_length_from_utf8_if_no_unmappables(text.len())
+                .unwrap_or(text.len());
+
+            self.output.reserve(additional);
+
+            let (result, read, _) =
+                self.encoder
+                    .encode_from_utf8_to_vec(text, &mut self.output, last);
+
+            text = &text[read..];
+
+            if let CoderResult::InputEmpty = result {
+                break;
+            }
+        }
+
+        &self.output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use encoding_rs::{UTF_8, WINDOWS_1251};
+
+    #[test]
+    fn split_characters() {
+        let mut transcoder = Transcoder::new(UTF_8, WINDOWS_1251);
+        let input = "<p>Привет</p>".as_bytes();
+        let mut output = Vec::new();
+
+        for chunk in input.chunks(1) {
+            output.extend_from_slice(transcoder.transcode(chunk));
+        }
+
+        output.extend_from_slice(transcoder.transcode(&[]));
+
+        assert_eq!(
+            WINDOWS_1251.decode_without_bom_handling(&output).0,
+            "<p>Привет</p>"
+        );
+    }
+
+    #[test]
+    fn unmappable_characters() {
+        let mut transcoder = Transcoder::new(UTF_8, WINDOWS_1251);
+        let mut output = transcoder.transcode("Ĥey".as_bytes()).to_vec();
+
+        output.extend_from_slice(transcoder.transcode(&[]));
+
+        assert_eq!(output, b"&#292;ey");
+    }
+}
@@ -0,0 +1,244 @@
+use super::settings::ElementContentHandlers;
+use crate::rewritable_units::{ContentType, TextChunk};
+use crate::selectors_vm::{Selector, SelectorError};
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+struct TruncationState {
+    remaining_chars: usize,
+    truncated: bool,
+}
+
+/// Returns the number of visible characters and the byte length of the longest prefix of the
+/// `text` that contains at most `max_chars` visible characters. A character reference is counted
+/// as a single visible character if `with_char_refs` is `true`.
+fn visible_prefix(text: &str, max_chars: usize, with_char_refs: bool) -> (usize, usize) {
+    let mut char_count = 0;
+    let mut byte_len = 0;
+
+    while char_count < max_chars && byte_len < text.len() {
+        let rest = &text[byte_len..];
+
+        let char_ref_len = if with_char_refs && rest.starts_with('&') {
+            let name_len = rest[1..]
+                .find(|c: char| !c.is_ascii_alphanumeric() && c != '#')
+                .unwrap_or(rest.len() - 1);
+
+            if name_len > 0 && rest[1 + name_len..].starts_with(';') {
+                Some(name_len + 2)
+            } else {
+                None
+            }
+        } else {
+            None
+        };
+
+        byte_len += match char_ref_len {
+            Some(len) => len,
+            None => rest.chars().next().map_or(0, char::len_utf8),
+        };
+
+        char_count += 1;
+    }
+
+    (char_count, byte_len)
+}
+
+fn truncate_text_chunk(chunk: &mut TextChunk, state: &mut TruncationState, ellipsis: &str) {
+    if state.truncated {
+        chunk.remove();
+        return;
+    }
+
+    let text = chunk.as_str();
+    let with_char_refs = chunk.text_type().allows_html_entities();
+    let (char_count, byte_len) = visible_prefix(text, state.remaining_chars, with_char_refs);
+
+    if byte_len == text.len() {
+        state.remaining_chars -= char_count;
+    } else {
+        // NOTE: the prefix is taken from the source as is, so we insert it as HTML to
+        // keep character references in it intact.
+        let prefix = text[..byte_len].to_string();
+
+        chunk.replace(&prefix, ContentType::Html);
+        chunk.after(ellipsis, ContentType::Text);
+
+        state.remaining_chars = 0;
+        state.truncated = true;
+    }
+}
+
+/// Creates content handlers that truncate inner content of the elements matched by the `selector`
+/// to `max_chars` visible characters, appending `ellipsis` to the truncated text.
+///
+/// Elements, comments and text that follow the truncation point are removed from the matched
+/// element, while end tags of the elements that were open at the truncation point are
+/// preserved, so the output stays well-formed. Character references are counted as a single
+/// visible character. `ellipsis` is inserted as text, i.e. with HTML special characters escaped.
+///
+/// The truncation state is shared between all the elements matched by the `selector`, the state
+/// is reset every time a new matched element is encountered. So, the behaviour is not specified
+/// for the nested matched elements.
+///
+/// # Example
+///
+/// ```
+/// use lol_html::{rewrite_str, truncate_content, RewriteStrSettings};
+///
+/// let html = rewrite_str(
+///     r#"<p class="teaser">Lorem <b>ipsum dolor</b> sit <i>amet</i></p>"#,
+///     RewriteStrSettings {
+///         element_content_handlers: truncate_content("p.teaser", 9, "…").unwrap(),
+///         ..RewriteStrSettings::default()
+///     }
+/// ).unwrap();
+///
+/// assert_eq!(html, r#"<p class="teaser">Lorem <b>ips…</b></p>"#);
+/// ```
+pub fn truncate_content<'h>(
+    selector: &str,
+    max_chars: usize,
+    ellipsis: &str,
+) -> Result<Vec<(Cow<'static, Selector>, ElementContentHandlers<'h>)>, SelectorError> {
+    let selector: Selector = selector.parse()?;
+    let descendants = selector.descendants()?;
+    let ellipsis = ellipsis.to_string();
+
+    let state = Rc::new(RefCell::new(TruncationState {
+        remaining_chars: max_chars,
+        truncated: false,
+    }));
+
+    let content_handlers = ElementContentHandlers::default()
+        .element({
+            let state = Rc::clone(&state);
+
+            move |_| {
+                *state.borrow_mut() = TruncationState {
+                    remaining_chars: max_chars,
+                    truncated: false,
+                };
+
+                Ok(())
+            }
+        })
+        .comments({
+            let state = Rc::clone(&state);
+
+            move |c| {
+                if state.borrow().truncated {
+                    c.remove();
+                }
+
+                Ok(())
+            }
+        })
+        .text({
+            let state = Rc::clone(&state);
+
+            move |t| {
+                truncate_text_chunk(t, &mut state.borrow_mut(), &ellipsis);
+
+                Ok(())
+            }
+        });
+
+    let descendant_handlers = ElementContentHandlers::default().element(move |el| {
+        if state.borrow().truncated {
+            el.remove();
+        }
+
+        Ok(())
+    });
+
+    Ok(vec![
+        (Cow::Owned(selector), content_handlers),
+        (Cow::Owned(descendants), descendant_handlers),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{rewrite_str, RewriteStrSettings};
+
+    fn truncate(html: &str, selector: &str, max_chars: usize) -> String {
+        rewrite_str(
+            html,
+            RewriteStrSettings {
+                element_content_handlers: truncate_content(selector, max_chars, "...").unwrap(),
+                ..RewriteStrSettings::default()
+            },
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn visible_prefix_with_char_refs() {
+        assert_eq!(visible_prefix("a&amp;b", 2, true), (2, 6));
+        assert_eq!(visible_prefix("a&amp;b", 2, false), (2, 2));
+        assert_eq!(visible_prefix("a&#x3C;", 5, true), (2, 7));
+        assert_eq!(visible_prefix("a & b", 3, true), (3, 3));
+        assert_eq!(visible_prefix("çava", 2, true), (2, 3));
+    }
+
+    #[test]
+    fn short_content() {
+        assert_eq!(
+            truncate("<div>Hello <b>world</b></div>", "div", 11),
+            "<div>Hello <b>world</b></div>"
+        );
+    }
+
+    #[test]
+    fn tags_are_balanced() {
+        assert_eq!(
+            truncate(
+                "<div>Hello <b>wonderful <i>world</i></b><img><!-- hey --> foo</div>",
+                "div",
+                10
+            ),
+            "<div>Hello <b>wond...</b></div>"
+        );
+    }
+
+    #[test]
+    fn char_refs_are_not_split() {
+        assert_eq!(
+            truncate("<div>Fish &amp; chips</div>", "div", 6),
+            "<div>Fish &amp;...</div>"
+        );
+    }
+
+    #[test]
+    fn ellipsis_is_escaped() {
+        let html = rewrite_str(
+            "<div>Hello world</div>",
+            RewriteStrSettings {
+                element_content_handlers: truncate_content("div", 5, "<more>").unwrap(),
+                ..RewriteStrSettings::default()
+            },
+        )
+        .unwrap();
+
+        assert_eq!(html, "<div>Hello&lt;more&gt;</div>");
+    }
+
+    #[test]
+    fn state_is_reset_for_each_element() {
+        assert_eq!(
+            truncate("<p>Hello world</p><p>Hi</p><p>Bonjour</p>", "p", 5),
+            "<p>Hello...</p><p>Hi</p><p>Bonjo...</p>"
+        );
+    }
+
+    #[test]
+    fn selector_error() {
+        assert_eq!(
+            truncate_content("div >", 5, "...").err(),
+            Some(SelectorError::DanglingCombinator)
+        );
+    }
+}
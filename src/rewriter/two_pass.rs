@@ -0,0 +1,307 @@
+use super::{discard_output, EncodingError, HtmlRewriter, RewritingError, Settings};
+use crate::transform_stream::OutputSink;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use thiserror::Error;
+
+const REPLAY_CHUNK_SIZE: usize = 8192;
+
+/// An error that can be returned by the methods of the [`TwoPassRewriter`].
+///
+/// [`TwoPassRewriter`]: ../struct.TwoPassRewriter.html
+#[derive(Error, Debug)]
+pub enum TwoPassRewritingError {
+    /// An I/O error that occurred while buffering or replaying the input.
+    #[error("{0}")]
+    Io(#[from] io::Error),
+
+    /// See [`RewritingError`].
+    ///
+    /// [`RewritingError`]: enum.RewritingError.html
+    #[error("{0}")]
+    Rewriting(#[from] RewritingError),
+}
+
+/// A driver for the "analyze then rewrite" processing of a document.
+///
+/// The first pass runs the analysis [`Settings`] handlers over the input, which can collect
+/// facts about the document, while the input is written to the `input_buffer`. Once the input
+/// ends, the second pass rewrites the input with the settings produced by the settings factory,
+/// so the rewriting handlers can use the facts collected in the first pass.
+///
+/// The input can be buffered in memory (e.g. with [`Cursor`]), on disk (e.g. with a temporary
+/// [`File`]), or not buffered at all (with [`sink`]) if the caller can supply the input once
+/// again for the second pass with the [`end_with_input`] method.
+///
+/// Parsed [`Selector`]s can be shared between the passes with [`Cow::Borrowed`], so each
+/// selector is parsed only once.
+///
+/// # Example
+/// ```
+/// use lol_html::{element, ElementContentHandlers, Selector, Settings, TwoPassRewriter};
+/// use lol_html::html_content::ContentType;
+/// use std::borrow::Cow;
+/// use std::cell::RefCell;
+/// use std::io::Cursor;
+///
+/// let headings: Selector = "h2[id]".parse().unwrap();
+/// let ids = RefCell::new(vec![]);
+/// let mut output = vec![];
+///
+/// let mut rewriter = TwoPassRewriter::try_new(
+///     Settings {
+///         element_content_handlers: vec![(
+///             Cow::Borrowed(&headings),
+///             ElementContentHandlers::default().element(|el| {
+///                 ids.borrow_mut().push(el.get_attribute("id").unwrap());
+///
+///                 Ok(())
+///             }),
+///         )],
+///         ..Settings::default()
+///     },
+///     Cursor::new(vec![]),
+/// ).unwrap();
+///
+/// rewriter.write(b"<nav></nav><h2 id=a>A</h2>").unwrap();
+/// rewriter.write(b"<h2 id=b>B</h2>").unwrap();
+///
+/// rewriter.end(
+///     || Settings {
+///         element_content_handlers: vec![
+///             element!("nav", |el| {
+///                 for id in ids.borrow().iter() {
+///                     el.append(&format!(r##"<a href="#{0}">{0}</a>"##, id), ContentType::Html);
+///                 }
+///
+///                 Ok(())
+///             }),
+///             (
+///                 Cow::Borrowed(&headings),
+///                 ElementContentHandlers::default().element(|el| {
+///                     el.set_attribute("class", "heading")?;
+///
+///                     Ok(())
+///                 }),
+///             ),
+///         ],
+///         ..Settings::default()
+///     },
+///     |c: &[u8]| output.extend_from_slice(c),
+/// ).unwrap();
+///
+/// assert_eq!(
+///     String::from_utf8(output).unwrap(),
+///     concat!(
+///         r##"<nav><a href="#a">a</a><a href="#b">b</a></nav>"##,
+///         r#"<h2 id=a class="heading">A</h2><h2 id=b class="heading">B</h2>"#
+///     )
+/// );
+/// ```
+///
+/// [`Settings`]: struct.Settings.html
+/// [`Selector`]: struct.Selector.html
+/// [`end_with_input`]: #method.end_with_input
+/// [`Cursor`]: https://doc.rust-lang.org/std/io/struct.Cursor.html
+/// [`File`]: https://doc.rust-lang.org/std/fs/struct.File.html
+/// [`sink`]: https://doc.rust-lang.org/std/io/fn.sink.html
+/// [`Cow::Borrowed`]: https://doc.rust-lang.org/std/borrow/enum.Cow.html#variant.Borrowed
+pub struct TwoPassRewriter<'h, B> {
+    analyzer: HtmlRewriter<'h, fn(&[u8])>,
+    input_buffer: B,
+}
+
+impl<'h, B: Write> TwoPassRewriter<'h, B> {
+    /// Constructs a new rewriter that runs the first pass with the `analysis_settings` and
+    /// writes the input to the `input_buffer`.
+    pub fn try_new<'s>(
+        analysis_settings: Settings<'h, 's>,
+        input_buffer: B,
+    ) -> Result<Self, EncodingError> {
+        Ok(TwoPassRewriter {
+            analyzer: HtmlRewriter::try_new_with_output(
+                analysis_settings,
+                discard_output as fn(&[u8]),
+                false,
+            )?,
+            input_buffer,
+        })
+    }
+
+    /// Writes a chunk of input data to the first pass and to the input buffer.
+    ///
+    /// # Panics
+    ///  * In the same cases as [`HtmlRewriter::write`].
+    ///
+    /// [`HtmlRewriter::write`]: struct.HtmlRewriter.html#method.write
+    #[inline]
+    pub fn write(&mut self, data: &[u8]) -> Result<(), TwoPassRewritingError> {
+        self.analyzer.write(data)?;
+        self.input_buffer.write_all(data)?;
+
+        Ok(())
+    }
+}
+
+impl<'h, B> TwoPassRewriter<'h, B> {
+    /// Finalizes the first pass and rewrites the `input` supplied by the caller with the settings
+    /// produced by the `rewrite_settings` factory, writing the output to the `output_sink`.
+    ///
+    /// The `input` should be identical to the input written to the rewriter.
+    pub fn end_with_input<'r, 's, O: OutputSink>(
+        mut self,
+        rewrite_settings: impl FnOnce() -> Settings<'r, 's>,
+        mut input: impl Read,
+        output_sink: O,
+    ) -> Result<(), TwoPassRewritingError> {
+        self.analyzer.end()?;
+
+        // NOTE: we can't report an encoding error for the settings of the second pass
+        // in any other way, so convert it to an I/O error.
+        let mut rewriter = HtmlRewriter::try_new(rewrite_settings(), output_sink)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+
+        let mut chunk = [0; REPLAY_CHUNK_SIZE];
+
+        loop {
+            match input.read(&mut chunk) {
+                Ok(0) => break,
+                Ok(len) => rewriter.write(&chunk[..len])?,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => (),
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        rewriter.end()?;
+
+        Ok(())
+    }
+}
+
+impl<'h, B: Read + Seek> TwoPassRewriter<'h, B> {
+    /// Finalizes the first pass and rewrites the buffered input with the settings produced by
+    /// the `rewrite_settings` factory, writing the output to the `output_sink`.
+    pub fn end<'r, 's, O: OutputSink>(
+        mut self,
+        rewrite_settings: impl FnOnce() -> Settings<'r, 's>,
+        output_sink: O,
+    ) -> Result<(), TwoPassRewritingError> {
+        self.input_buffer.seek(SeekFrom::Start(0))?;
+
+        let TwoPassRewriter {
+            analyzer,
+            input_buffer,
+        } = self;
+
+        TwoPassRewriter {
+            analyzer,
+            input_buffer: (),
+        }
+        .end_with_input(rewrite_settings, input_buffer, output_sink)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::html_content::ContentType;
+    use crate::*;
+    use std::cell::Cell;
+    use std::io::Cursor;
+
+    fn analysis_settings(count: &Cell<usize>) -> Settings<'_, 'static> {
+        Settings {
+            element_content_handlers: vec![element!("li", move |_| {
+                count.set(count.get() + 1);
+                Ok(())
+            })],
+            ..Settings::default()
+        }
+    }
+
+    fn rewrite_settings(count: &Cell<usize>) -> Settings<'_, 'static> {
+        Settings {
+            element_content_handlers: vec![element!("ul", move |el| {
+                el.set_attribute("data-count", &count.get().to_string())?;
+                Ok(())
+            })],
+            document_content_handlers: vec![end!(move |end| {
+                end.append(&format!("<!-- {} -->", count.get()), ContentType::Html);
+                Ok(())
+            })],
+            ..Settings::default()
+        }
+    }
+
+    const CHUNKS: [&str; 3] = ["<ul><li>1</li>", "<li>2</li><l", "i>3</li></ul>"];
+
+    #[test]
+    fn buffered_input() {
+        let count = Cell::new(0);
+        let mut output = vec![];
+
+        let mut rewriter =
+            TwoPassRewriter::try_new(analysis_settings(&count), Cursor::new(vec![])).unwrap();
+
+        for chunk in CHUNKS.iter() {
+            rewriter.write(chunk.as_bytes()).unwrap();
+        }
+
+        rewriter
+            .end(
+                || rewrite_settings(&count),
+                |c: &[u8]| output.extend_from_slice(c),
+            )
+            .unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            r#"<ul data-count="3"><li>1</li><li>2</li><li>3</li></ul><!-- 3 -->"#
+        );
+    }
+
+    #[test]
+    fn input_supplied_by_caller() {
+        let count = Cell::new(0);
+        let mut output = vec![];
+
+        let mut rewriter = TwoPassRewriter::try_new(analysis_settings(&count), io::sink()).unwrap();
+
+        for chunk in CHUNKS.iter() {
+            rewriter.write(chunk.as_bytes()).unwrap();
+        }
+
+        rewriter
+            .end_with_input(
+                || rewrite_settings(&count),
+                CHUNKS.concat().as_bytes(),
+                |c: &[u8]| output.extend_from_slice(c),
+            )
+            .unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            r#"<ul data-count="3"><li>1</li><li>2</li><li>3</li></ul><!-- 3 -->"#
+        );
+    }
+
+    #[test]
+    fn invalid_rewrite_settings() {
+        let rewriter = TwoPassRewriter::try_new(Settings::default(), io::sink()).unwrap();
+
+        let err = rewriter
+            .end_with_input(
+                || Settings {
+                    encoding: "utf-16",
+                    ..Settings::default()
+                },
+                &b""[..],
+                |_: &[u8]| {},
+            )
+            .unwrap_err();
+
+        match err {
+            TwoPassRewritingError::Io(e) => assert_eq!(e.kind(), io::ErrorKind::InvalidInput),
+            _ => panic!("{}", err),
+        }
+    }
+}
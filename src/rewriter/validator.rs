@@ -0,0 +1,438 @@
+use super::{discard_output, try_encoding_from_str, EncodingError, MemorySettings, RewritingError};
+use crate::html::{LocalName, Namespace};
+use crate::memory::MemoryLimiter;
+use crate::rewritable_units::{DocumentEnd, EndTag, StartTag, Token, TokenCaptureFlags};
+use crate::transform_stream::*;
+use hashbrown::HashSet;
+use std::cell::RefCell;
+use std::fmt::{self, Display};
+use std::rc::Rc;
+
+const HTML_NAMESPACE_URI: &str = "http://www.w3.org/1999/xhtml";
+
+const VOID_ELEMENTS: &[&str] = &[
+    "area", "base", "basefont", "bgsound", "br", "col", "embed", "frame", "hr", "img", "input",
+    "keygen", "link", "meta", "param", "source", "track", "wbr",
+];
+
+const FORMATTING_ELEMENTS: &[&str] = &[
+    "a", "b", "big", "code", "em", "font", "i", "nobr", "s", "small", "strike", "strong", "tt", "u",
+];
+
+// NOTE: elements whose end tags can be omitted according to the HTML spec, so we don't report
+// them as unclosed.
+const OPTIONAL_END_TAG_ELEMENTS: &[&str] = &[
+    "body", "colgroup", "dd", "dt", "head", "html", "li", "optgroup", "option", "p", "rb", "rp",
+    "rt", "rtc", "tbody", "td", "tfoot", "th", "thead", "tr",
+];
+
+// NOTE: elements whose start tags can be omitted, so their end tags are not stray even if the
+// element wasn't explicitly opened.
+const OPTIONAL_START_TAG_ELEMENTS: &[&str] = &["body", "colgroup", "head", "html", "tbody"];
+
+/// A structural issue found by the [`StructureValidator`].
+///
+/// [`StructureValidator`]: struct.StructureValidator.html
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ValidationIssue {
+    /// The element is not closed by the end tag.
+    UnclosedElement {
+        /// The name of the element.
+        tag_name: String,
+    },
+
+    /// The end tag doesn't have a corresponding open element.
+    StrayEndTag {
+        /// The name of the end tag.
+        tag_name: String,
+    },
+
+    /// The formatting element (e.g. `<b>` or `<a>`) is closed implicitly by the end tag of the
+    /// element it was opened in, e.g. `<p><b>Foo</p>`.
+    MisnestedFormattingElement {
+        /// The name of the formatting element.
+        tag_name: String,
+        /// The name of the end tag that closed the formatting element.
+        closed_by: String,
+    },
+
+    /// The `id` attribute value is used by more than one element.
+    DuplicateId {
+        /// The value of the `id` attribute.
+        id: String,
+    },
+}
+
+impl Display for ValidationIssue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ValidationIssue::UnclosedElement { tag_name } => {
+                write!(f, "Unclosed `{}` element.", tag_name)
+            }
+            ValidationIssue::StrayEndTag { tag_name } => {
+                write!(f, "Stray `{}` end tag.", tag_name)
+            }
+            ValidationIssue::MisnestedFormattingElement {
+                tag_name,
+                closed_by,
+            } => write!(
+                f,
+                "Formatting element `{}` is closed by the `{}` end tag.",
+                tag_name, closed_by
+            ),
+            ValidationIssue::DuplicateId { id } => write!(f, "Duplicate id `{}`.", id),
+        }
+    }
+}
+
+/// A report produced by the [`StructureValidator`].
+///
+/// [`StructureValidator`]: struct.StructureValidator.html
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ValidationReport {
+    /// Issues found in the document in the order they were encountered. Unclosed elements are
+    /// reported last, as they are known only once the input ends.
+    pub issues: Vec<ValidationIssue>,
+}
+
+impl ValidationReport {
+    /// Returns `true` if no issues were found in the document.
+    #[inline]
+    pub fn is_valid(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+#[derive(Default)]
+struct ValidationState {
+    custom_void_elements: Vec<String>,
+    open_elements: Vec<String>,
+    misnested_formatting_elements: Vec<String>,
+    ids: HashSet<String>,
+    duplicate_ids: HashSet<String>,
+    report: ValidationReport,
+}
+
+impl ValidationState {
+    fn handle_start_tag(&mut self, start_tag: &StartTag) {
+        let tag_name = start_tag.name().to_string();
+
+        if let Some(id) = start_tag.attributes().iter().find(|a| a.name() == "id") {
+            let id = id.value().to_string();
+
+            if !self.ids.insert(id.clone()) && self.duplicate_ids.insert(id.clone()) {
+                self.report.issues.push(ValidationIssue::DuplicateId { id });
+            }
+        }
+
+        let is_html_element = start_tag.namespace_uri() == HTML_NAMESPACE_URI;
+
+        // NOTE: self-closing syntax is ignored for the non-void HTML elements.
+        let is_void_element = VOID_ELEMENTS.contains(&tag_name.as_str())
+            || self.custom_void_elements.contains(&tag_name);
+
+        if (is_html_element && is_void_element) || (!is_html_element && start_tag.self_closing()) {
+            return;
+        }
+
+        self.open_elements.push(tag_name);
+    }
+
+    fn handle_end_tag(&mut self, end_tag: &EndTag) {
+        let tag_name = end_tag.name().to_string();
+
+        let idx = match self.open_elements.iter().rposition(|e| *e == tag_name) {
+            Some(idx) => idx,
+            None => {
+                if let Some(idx) = self
+                    .misnested_formatting_elements
+                    .iter()
+                    .rposition(|e| *e == tag_name)
+                {
+                    // NOTE: the end tag of the misnested element has been already reported.
+                    self.misnested_formatting_elements.remove(idx);
+                } else if !OPTIONAL_START_TAG_ELEMENTS.contains(&tag_name.as_str()) {
+                    self.report
+                        .issues
+                        .push(ValidationIssue::StrayEndTag { tag_name });
+                }
+
+                return;
+            }
+        };
+
+        for element in self.open_elements.drain(idx + 1..) {
+            if FORMATTING_ELEMENTS.contains(&element.as_str()) {
+                self.misnested_formatting_elements.push(element.clone());
+
+                self.report
+                    .issues
+                    .push(ValidationIssue::MisnestedFormattingElement {
+                        tag_name: element,
+                        closed_by: tag_name.clone(),
+                    });
+            } else if !OPTIONAL_END_TAG_ELEMENTS.contains(&element.as_str()) {
+                self.report
+                    .issues
+                    .push(ValidationIssue::UnclosedElement { tag_name: element });
+            }
+        }
+
+        self.open_elements.pop();
+    }
+
+    fn handle_end(&mut self) {
+        for tag_name in self.open_elements.drain(..) {
+            if !OPTIONAL_END_TAG_ELEMENTS.contains(&tag_name.as_str()) {
+                self.report
+                    .issues
+                    .push(ValidationIssue::UnclosedElement { tag_name });
+            }
+        }
+    }
+}
+
+struct ValidationController {
+    state: Rc<RefCell<ValidationState>>,
+}
+
+impl TransformController for ValidationController {
+    #[inline]
+    fn initial_capture_flags(&self) -> TokenCaptureFlags {
+        TokenCaptureFlags::NEXT_START_TAG | TokenCaptureFlags::NEXT_END_TAG
+    }
+
+    #[inline]
+    fn handle_start_tag(&mut self, _: LocalName, _: Namespace) -> StartTagHandlingResult<Self> {
+        Ok(self.initial_capture_flags())
+    }
+
+    #[inline]
+    fn handle_end_tag(&mut self, _: LocalName) -> TokenCaptureFlags {
+        self.initial_capture_flags()
+    }
+
+    fn handle_token(&mut self, token: &mut Token) -> Result<(), RewritingError> {
+        match token {
+            Token::StartTag(start_tag) => self.state.borrow_mut().handle_start_tag(start_tag),
+            Token::EndTag(end_tag) => self.state.borrow_mut().handle_end_tag(end_tag),
+            _ => (),
+        }
+
+        Ok(())
+    }
+
+    fn handle_end(&mut self, _: &mut DocumentEnd) -> Result<(), RewritingError> {
+        self.state.borrow_mut().handle_end();
+
+        Ok(())
+    }
+
+    #[inline]
+    fn should_emit_content(&self) -> bool {
+        false
+    }
+}
+
+/// A streaming structural sanity check of a document.
+///
+/// The validator tracks open elements of the document and reports unclosed elements, stray
+/// end tags, misnested formatting elements and duplicate `id` attribute values once the input
+/// ends. The validator is a lightweight approximation of the HTML parsing algorithm: it's aware
+/// of void elements and elements with optional end tags, but doesn't account for the elements
+/// that are implicitly closed by the start tags of other elements. The document is not
+/// serialized.
+///
+/// # Example
+/// ```
+/// use lol_html::{StructureValidator, ValidationIssue};
+///
+/// let mut validator = StructureValidator::try_new("utf-8").unwrap();
+///
+/// validator.write(b"<div id=foo><p><b>Foo</p><img id=foo>").unwrap();
+///
+/// let report = validator.end().unwrap();
+///
+/// assert_eq!(
+///     report.issues,
+///     [
+///         ValidationIssue::MisnestedFormattingElement {
+///             tag_name: "b".into(),
+///             closed_by: "p".into(),
+///         },
+///         ValidationIssue::DuplicateId { id: "foo".into() },
+///         ValidationIssue::UnclosedElement { tag_name: "div".into() },
+///     ]
+/// );
+/// ```
+pub struct StructureValidator {
+    stream: TransformStream<ValidationController, fn(&[u8])>,
+    state: Rc<RefCell<ValidationState>>,
+}
+
+impl StructureValidator {
+    /// Constructs a new validator for the document in the `encoding`.
+    #[inline]
+    pub fn try_new(encoding: &str) -> Result<Self, EncodingError> {
+        Self::try_with_custom_void_elements(encoding, &[])
+    }
+
+    /// Constructs a new validator that treats the elements with the given tag names as void
+    /// in addition to the standard void elements.
+    ///
+    /// See [`custom_void_elements`] for the details.
+    ///
+    /// [`custom_void_elements`]: struct.Settings.html#structfield.custom_void_elements
+    pub fn try_with_custom_void_elements(
+        encoding: &str,
+        custom_void_elements: &[&str],
+    ) -> Result<Self, EncodingError> {
+        let encoding = try_encoding_from_str(encoding)?;
+        let memory_settings = MemorySettings::default();
+
+        let state = Rc::new(RefCell::new(ValidationState {
+            custom_void_elements: custom_void_elements
+                .iter()
+                .map(|n| n.to_ascii_lowercase())
+                .collect(),
+            ..ValidationState::default()
+        }));
+
+        let stream = TransformStream::new(TransformStreamSettings {
+            transform_controller: ValidationController {
+                state: Rc::clone(&state),
+            },
+            // NOTE: the controller doesn't emit the content, so the document is not serialized.
+            output_sink: discard_output as fn(&[u8]),
+            preallocated_parsing_buffer_size: memory_settings.preallocated_parsing_buffer_size,
+            parsing_buffer_compaction_watermark: memory_settings
+                .parsing_buffer_compaction_watermark,
+            parsing_buffer_shrink_watermark: memory_settings.parsing_buffer_shrink_watermark,
+            memory_limiter: MemoryLimiter::new_shared(memory_settings.max_allowed_memory_usage),
+            encoding,
+            strict: false,
+            buffer_script_and_style_text: false,
+        });
+
+        Ok(StructureValidator { stream, state })
+    }
+
+    /// Writes a chunk of input data to the validator.
+    #[inline]
+    pub fn write(&mut self, data: &[u8]) -> Result<(), RewritingError> {
+        self.stream.write(data)
+    }
+
+    /// Finalizes the validation and returns the report.
+    ///
+    /// Should be called once the last chunk of the input is written.
+    pub fn end(mut self) -> Result<ValidationReport, RewritingError> {
+        self.stream.end()?;
+
+        let report = std::mem::take(&mut self.state.borrow_mut().report);
+
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ValidationIssue::*;
+
+    fn validate(html: &str) -> Vec<ValidationIssue> {
+        let mut validator = StructureValidator::try_new("utf-8").unwrap();
+
+        for chunk in html.as_bytes().chunks(5) {
+            validator.write(chunk).unwrap();
+        }
+
+        validator.end().unwrap().issues
+    }
+
+    fn unclosed(tag_name: &str) -> ValidationIssue {
+        UnclosedElement {
+            tag_name: tag_name.into(),
+        }
+    }
+
+    fn stray(tag_name: &str) -> ValidationIssue {
+        StrayEndTag {
+            tag_name: tag_name.into(),
+        }
+    }
+
+    #[test]
+    fn valid_document() {
+        let html = r#"
+            <!doctype html>
+            <html>
+            <head><title>Foo</title><meta charset="utf-8"></head>
+            <body>
+                <ul><li>Foo<li>Bar</ul>
+                <p>Baz<br/><img src="foo.png">
+                <svg><path d="M0 0"/></svg>
+                <table><tr><td>1<td>2</table>
+                <script>if (a < b) document.write("</div>")</script>
+            </body>
+            </html>
+        "#;
+
+        assert_eq!(validate(html), []);
+    }
+
+    #[test]
+    fn unclosed_elements() {
+        assert_eq!(
+            validate("<div><section><p>Foo</div><span>Bar"),
+            [unclosed("section"), unclosed("span")]
+        );
+
+        assert_eq!(validate("<div/>Foo"), [unclosed("div")]);
+    }
+
+    #[test]
+    fn stray_end_tags() {
+        assert_eq!(
+            validate("<div>Foo</div></div></span></body></html>"),
+            [stray("div"), stray("span")]
+        );
+    }
+
+    #[test]
+    fn misnested_formatting_elements() {
+        assert_eq!(
+            validate("<div><b><i>Foo</b></i></div><p><a href=/>Bar</p>"),
+            [
+                MisnestedFormattingElement {
+                    tag_name: "i".into(),
+                    closed_by: "b".into(),
+                },
+                MisnestedFormattingElement {
+                    tag_name: "a".into(),
+                    closed_by: "p".into(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn duplicate_ids() {
+        assert_eq!(
+            validate(r#"<div id="foo"><p id=bar></p><i id="foo"></i><b id=foo></b></div>"#),
+            [DuplicateId { id: "foo".into() }]
+        );
+    }
+
+    #[test]
+    fn custom_void_elements() {
+        let html = "<p><ICON name=star>Foo</p>";
+        let mut validator =
+            StructureValidator::try_with_custom_void_elements("utf-8", &["Icon"]).unwrap();
+
+        validator.write(html.as_bytes()).unwrap();
+
+        assert!(validator.end().unwrap().is_valid());
+        assert_eq!(validate(html), [unclosed("icon")]);
+    }
+}
@@ -0,0 +1,105 @@
+/// A set of elements whose inner whitespace must never be altered.
+///
+/// By default the set contains the elements that preserve whitespace in the HTML rendering
+/// model: `<pre>`, `<listing>` and `<textarea>`. Elements that are styled to preserve whitespace
+/// (e.g. a `<code-block>` custom element) can be added to the set, so the features that
+/// normalize whitespace leave their content intact. The set is used by the [`TextExtractor`].
+///
+/// Tag names are matched case-insensitively.
+///
+/// # Example
+/// ```
+/// use lol_html::{TextExtractor, WhitespaceSensitiveElements};
+///
+/// let mut text = String::new();
+///
+/// {
+///     let mut extractor = TextExtractor::try_with_whitespace_sensitive_elements(
+///         "utf-8",
+///         WhitespaceSensitiveElements::default().add("code-block"),
+///         |t| text.push_str(t),
+///     )
+///     .unwrap();
+///
+///     extractor.write(b"<p>a   b</p><code-block>c   d</code-block>").unwrap();
+///     extractor.end().unwrap();
+/// }
+///
+/// assert_eq!(text, "a b\n\nc   d");
+/// ```
+///
+/// [`TextExtractor`]: struct.TextExtractor.html
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WhitespaceSensitiveElements {
+    tag_names: Vec<String>,
+}
+
+impl WhitespaceSensitiveElements {
+    /// Creates an empty set.
+    #[inline]
+    pub fn empty() -> Self {
+        WhitespaceSensitiveElements { tag_names: vec![] }
+    }
+
+    /// Adds an element with the `tag_name` to the set.
+    pub fn add(mut self, tag_name: &str) -> Self {
+        let tag_name = tag_name.to_ascii_lowercase();
+
+        if !self.tag_names.contains(&tag_name) {
+            self.tag_names.push(tag_name);
+        }
+
+        self
+    }
+
+    /// Removes an element with the `tag_name` from the set.
+    pub fn remove(mut self, tag_name: &str) -> Self {
+        self.tag_names.retain(|n| !n.eq_ignore_ascii_case(tag_name));
+
+        self
+    }
+
+    /// Returns `true` if the set contains an element with the `tag_name`.
+    #[inline]
+    pub fn contains(&self, tag_name: &str) -> bool {
+        self.tag_names
+            .iter()
+            .any(|n| n.eq_ignore_ascii_case(tag_name))
+    }
+
+    /// Returns an iterator over the tag names of the elements in the set.
+    #[inline]
+    pub fn iter(&self) -> impl Iterator<Item = &str> {
+        self.tag_names.iter().map(String::as_str)
+    }
+}
+
+impl Default for WhitespaceSensitiveElements {
+    #[inline]
+    fn default() -> Self {
+        Self::empty().add("listing").add("pre").add("textarea")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn add_and_remove() {
+        let elements = WhitespaceSensitiveElements::default()
+            .add("Code-Block")
+            .add("pre")
+            .remove("LISTING");
+
+        assert_eq!(
+            elements.iter().collect::<Vec<_>>(),
+            ["pre", "textarea", "code-block"]
+        );
+
+        assert!(elements.contains("PRE"));
+        assert!(elements.contains("code-block"));
+        assert!(!elements.contains("listing"));
+        assert!(!WhitespaceSensitiveElements::empty().contains("pre"));
+    }
+}
@@ -0,0 +1,228 @@
+use super::settings::DocumentContentHandlers;
+#[cfg(feature = "element_handlers")]
+use super::settings::ElementContentHandlers;
+use crate::html::decode_char_refs;
+use crate::rewritable_units::ContentType;
+use std::borrow::Cow;
+
+/// Escapes the characters that are not allowed to appear literally in XML attribute values.
+/// Double quotes are escaped on the serialization of the attribute.
+#[cfg(feature = "element_handlers")]
+fn escape_attribute_value(value: &str) -> Cow<str> {
+    if !value.contains(|c| c == '&' || c == '<') {
+        return Cow::Borrowed(value);
+    }
+
+    let mut escaped = String::with_capacity(value.len() + 8);
+
+    for ch in value.chars() {
+        match ch {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            _ => escaped.push(ch),
+        }
+    }
+
+    Cow::Owned(escaped)
+}
+
+/// Removes `--` sequences and the trailing `-` that are not allowed in XML comments.
+fn sanitize_comment_text(text: &str) -> Cow<str> {
+    if !text.contains("--") && !text.ends_with('-') {
+        return Cow::Borrowed(text);
+    }
+
+    let mut sanitized = String::with_capacity(text.len() + 1);
+
+    for ch in text.chars() {
+        if ch != '-' || !sanitized.ends_with('-') {
+            sanitized.push(ch);
+        }
+    }
+
+    if sanitized.ends_with('-') {
+        sanitized.push(' ');
+    }
+
+    Cow::Owned(sanitized)
+}
+
+// NOTE: https://html.spec.whatwg.org/multipage/indices.html#attributes-3
+#[cfg(feature = "element_handlers")]
+#[inline]
+fn is_boolean_attribute(name: &str) -> bool {
+    matches!(
+        name,
+        "allowfullscreen"
+            | "async"
+            | "autofocus"
+            | "autoplay"
+            | "checked"
+            | "controls"
+            | "default"
+            | "defer"
+            | "disabled"
+            | "formnovalidate"
+            | "hidden"
+            | "inert"
+            | "ismap"
+            | "itemscope"
+            | "loop"
+            | "multiple"
+            | "muted"
+            | "nomodule"
+            | "novalidate"
+            | "open"
+            | "playsinline"
+            | "readonly"
+            | "required"
+            | "reversed"
+            | "selected"
+    )
+}
+
+/// Creates the handlers that serialize text, comments and doctypes as XHTML. The handlers
+/// should be registered after all the other handlers, so they observe the final content.
+pub(super) fn document_handlers() -> DocumentContentHandlers<'static> {
+    DocumentContentHandlers::default()
+        .doctype(|doctype| {
+            if let Some(name) = doctype.name() {
+                // NOTE: resetting the name makes the doctype serialized in the canonical
+                // form, as XML requires the uppercase `DOCTYPE` keyword.
+                doctype.set_name(&name)?;
+            }
+
+            Ok(())
+        })
+        .comments(|comment| {
+            if !comment.removed() {
+                if let Cow::Owned(text) = sanitize_comment_text(&comment.text()) {
+                    comment.set_text(&text)?;
+                }
+            }
+
+            Ok(())
+        })
+        .text(|text| {
+            if !text.removed() {
+                let content = text.as_str();
+
+                let decoded = if text.text_type().allows_html_entities() {
+                    decode_char_refs(content)
+                } else {
+                    Cow::Borrowed(content)
+                };
+
+                if decoded.contains(|c| c == '&' || c == '<' || c == '>') || decoded != content {
+                    let decoded = decoded.into_owned();
+
+                    text.replace(&decoded, ContentType::Text);
+                }
+            }
+
+            Ok(())
+        })
+}
+
+/// Creates the handlers that serialize start and end tags as XHTML. The handlers should be
+/// registered for all the elements after all the other handlers.
+#[cfg(feature = "element_handlers")]
+pub(super) fn element_handlers() -> ElementContentHandlers<'static> {
+    ElementContentHandlers::default().element(|el| {
+        if el.removed() {
+            return Ok(());
+        }
+
+        let tag_name = el.tag_name().to_owned();
+
+        // NOTE: resetting the name forces serialization of both the start and the end tag
+        // in the canonical form.
+        el.set_tag_name(&tag_name)?;
+        el.set_self_closing(!el.can_have_content());
+
+        let attributes = el
+            .attributes()
+            .iter()
+            .map(|a| (a.name().to_owned(), a.value().to_owned()))
+            .collect::<Vec<_>>();
+
+        for (name, value) in attributes {
+            let value = decode_char_refs(&value);
+
+            let value = if value.is_empty() && is_boolean_attribute(&name) {
+                Cow::Borrowed(name.as_str())
+            } else {
+                escape_attribute_value(&value)
+            };
+
+            // NOTE: the names of the attributes in the input can contain characters that are
+            // forbidden by the serializer, such attributes can't be represented in XML anyway.
+            if el.set_attribute(&name, &value).is_err() {
+                el.remove_attribute(&name);
+            }
+        }
+
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    fn rewrite(html: &str) -> String {
+        rewrite_str(
+            html,
+            RewriteStrSettings {
+                xhtml_output: true,
+                ..RewriteStrSettings::default()
+            },
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn text_and_comments() {
+        assert_eq!(
+            rewrite("<!doctype html>a & b &amp;&nbsp;&lt; c &unknown; <!-- x -- y--->"),
+            "<!DOCTYPE html>a &amp; b &amp;\u{a0}&lt; c &amp;unknown; <!-- x - y- -->"
+        );
+    }
+
+    #[cfg(feature = "element_handlers")]
+    #[test]
+    fn tags_and_attributes() {
+        assert_eq!(
+            rewrite(concat!(
+                "<DIV Class=foo data-x='a & b \"c\"'><br><img src=a.png alt=''>",
+                "<input type=checkbox checked><p/>text</P>",
+                r#"<svg><circle r="1"/></svg></div>"#
+            )),
+            concat!(
+                r#"<div class="foo" data-x="a &amp; b &quot;c&quot;"><br/><img src="a.png" alt="" />"#,
+                r#"<input type="checkbox" checked="checked" /><p>text</p>"#,
+                r#"<svg><circle r="1" /></svg></div>"#
+            )
+        );
+    }
+
+    #[cfg(feature = "element_handlers")]
+    #[test]
+    fn reflects_content_handlers() {
+        let html = rewrite_str(
+            "<a href='/?a=1&b=2'>Hi</a>",
+            RewriteStrSettings {
+                element_content_handlers: vec![element!("a", |el| {
+                    el.set_attribute("title", "1 < 2")?;
+
+                    Ok(())
+                })],
+                xhtml_output: true,
+                ..RewriteStrSettings::default()
+            },
+        )
+        .unwrap();
+
+        assert_eq!(html, r#"<a href="/?a=1&amp;b=2" title="1 &lt; 2">Hi</a>"#);
+    }
+}
@@ -1,4 +1,8 @@
-use super::parser::{Selector, SelectorImplDescriptor};
+#[cfg(feature = "regex_selectors")]
+use super::parser::AttributeRegex;
+use super::parser::{NonTSPseudoClassKind, PseudoElementKind, Selector, SelectorImplDescriptor};
+use super::template::PlaceholderValues;
+use super::CustomPseudoClass;
 use selectors::attr::{AttrSelectorOperator, ParsedCaseSensitivity};
 use selectors::parser::{Combinator, Component};
 use hashbrown::HashSet;
@@ -42,16 +46,18 @@ impl NthChild {
     }
 }
 
-#[derive(PartialEq, Eq, Debug)]
+#[derive(PartialEq, Eq, Debug, Clone)]
 pub enum OnTagNameExpr {
     ExplicitAny,
     Unmatchable,
     LocalName(String),
     NthChild(NthChild),
     NthOfType(NthChild),
+    /// The element is nested at most at the given level.
+    MaxDepth(usize),
 }
 
-#[derive(Eq, PartialEq)]
+#[derive(Eq, PartialEq, Clone)]
 pub struct AttributeComparisonExpr {
     pub name: String,
     pub value: String,
@@ -90,12 +96,16 @@ impl Debug for AttributeComparisonExpr {
 }
 
 /// An attribute check when attributes are received and parsed.
-#[derive(PartialEq, Eq, Debug)]
+#[derive(PartialEq, Eq, Debug, Clone)]
 pub enum OnAttributesExpr {
     Id(String),
     Class(String),
     AttributeExists(String),
     AttributeComparisonExpr(AttributeComparisonExpr),
+    AttributeNamePrefix(String),
+    #[cfg(feature = "regex_selectors")]
+    AttributeRegex(AttributeRegex),
+    Custom(CustomPseudoClass),
 }
 
 #[derive(PartialEq, Eq, Debug)]
@@ -106,12 +116,14 @@ enum Condition {
     OnAttributes(OnAttributesExpr),
 }
 
-impl From<&Component<SelectorImplDescriptor>> for Condition {
+impl Condition {
     #[inline]
-    fn from(component: &Component<SelectorImplDescriptor>) -> Self {
+    fn new(component: &Component<SelectorImplDescriptor>, values: &PlaceholderValues) -> Self {
+        let owned = |s: &str| values.apply(s).into_owned();
+
         match component {
             Component::LocalName(n) => {
-                Condition::OnTagName(OnTagNameExpr::LocalName(n.name.to_owned()))
+                Condition::OnTagName(OnTagNameExpr::LocalName(owned(&n.name)))
             }
             Component::ExplicitUniversalType | Component::ExplicitAnyNamespace => {
                 Condition::OnTagName(OnTagNameExpr::ExplicitAny)
@@ -119,10 +131,10 @@ impl From<&Component<SelectorImplDescriptor>> for Condition {
             Component::ExplicitNoNamespace => {
                 Condition::OnTagName(OnTagNameExpr::Unmatchable)
             }
-            Component::ID(id) => Condition::OnAttributes(OnAttributesExpr::Id(id.to_owned())),
-            Component::Class(c) => Condition::OnAttributes(OnAttributesExpr::Class(c.to_owned())),
+            Component::ID(id) => Condition::OnAttributes(OnAttributesExpr::Id(owned(id))),
+            Component::Class(c) => Condition::OnAttributes(OnAttributesExpr::Class(owned(c))),
             Component::AttributeInNoNamespaceExists { local_name, .. } => {
-                Condition::OnAttributes(OnAttributesExpr::AttributeExists(local_name.to_owned()))
+                Condition::OnAttributes(OnAttributesExpr::AttributeExists(owned(local_name)))
             }
             &Component::AttributeInNoNamespace {
                 ref local_name,
@@ -135,25 +147,35 @@ impl From<&Component<SelectorImplDescriptor>> for Condition {
                     Condition::OnTagName(OnTagNameExpr::Unmatchable)
                 } else {
                     Condition::OnAttributes(OnAttributesExpr::AttributeComparisonExpr(AttributeComparisonExpr::new(
-                        local_name.to_owned(),
-                        value.to_owned(),
+                        owned(local_name),
+                        owned(value),
                         case_sensitivity,
                         operator,
                     )))
                 }
             }
-            Component::FirstChild => {
+            Component::FirstChild | Component::OnlyChild => {
                 Condition::OnTagName(OnTagNameExpr::NthChild(NthChild::first()))
             }
             &Component::NthChild(a, b) => {
                 Condition::OnTagName(OnTagNameExpr::NthChild(NthChild::new(a, b)))
             }
-            Component::FirstOfType => {
+            Component::FirstOfType | Component::OnlyOfType => {
                 Condition::OnTagName(OnTagNameExpr::NthOfType(NthChild::first()))
             },
             &Component::NthOfType(a, b) => {
                 Condition::OnTagName(OnTagNameExpr::NthOfType(NthChild::new(a, b)))
             }
+            Component::NonTSPseudoClass(NonTSPseudoClassKind::AttributeNamePrefix(p)) => {
+                Condition::OnAttributes(OnAttributesExpr::AttributeNamePrefix(owned(p).to_ascii_lowercase()))
+            }
+            #[cfg(feature = "regex_selectors")]
+            Component::NonTSPseudoClass(NonTSPseudoClassKind::AttributeMatches(r)) => {
+                Condition::OnAttributes(OnAttributesExpr::AttributeRegex(r.to_owned()))
+            }
+            Component::NonTSPseudoClass(NonTSPseudoClassKind::Custom(c)) => {
+                Condition::OnAttributes(OnAttributesExpr::Custom(c.to_owned()))
+            }
             // NOTE: the rest of the components are explicit namespace or
             // pseudo class-related. Ideally none of them should appear in
             // the parsed selector as we should bail earlier in the parser.
@@ -165,7 +187,7 @@ impl From<&Component<SelectorImplDescriptor>> for Condition {
     }
 }
 
-#[derive(PartialEq, Eq, Debug)]
+#[derive(PartialEq, Eq, Debug, Clone)]
 pub struct Expr<E>
 where
     E: PartialEq + Eq + Debug,
@@ -187,7 +209,7 @@ where
     }
 }
 
-#[derive(PartialEq, Eq, Debug, Default)]
+#[derive(PartialEq, Eq, Debug, Default, Clone)]
 pub struct Predicate {
     pub on_tag_name_exprs: Vec<Expr<OnTagNameExpr>>,
     pub on_attr_exprs: Vec<Expr<OnAttributesExpr>>,
@@ -203,8 +225,33 @@ where
 
 impl Predicate {
     #[inline]
-    fn add_component(&mut self, component: &Component<SelectorImplDescriptor>, negation: bool) {
-        match Condition::from(component) {
+    fn add_component(
+        &mut self,
+        component: &Component<SelectorImplDescriptor>,
+        negation: bool,
+        selector: &Selector,
+    ) {
+        let case_insensitive_attr = |name: &str, value: &str, operator| {
+            Condition::OnAttributes(OnAttributesExpr::AttributeComparisonExpr(AttributeComparisonExpr::new(
+                name.into(),
+                selector.1.apply(value).into_owned(),
+                ParsedCaseSensitivity::AsciiCaseInsensitive,
+                operator,
+            )))
+        };
+
+        let condition = match component {
+            // NOTE: IDs and class names are matched ASCII case-insensitively in the quirks mode.
+            Component::ID(id) if selector.2.case_insensitive_ids_and_classes => {
+                case_insensitive_attr("id", id, AttrSelectorOperator::Equal)
+            }
+            Component::Class(class) if selector.2.case_insensitive_ids_and_classes => {
+                case_insensitive_attr("class", class, AttrSelectorOperator::Includes)
+            }
+            _ => Condition::new(component, &selector.1),
+        };
+
+        match condition {
             Condition::OnTagName(e) =>
                 add_expr_to_list(&mut self.on_tag_name_exprs, e, negation),
             Condition::OnAttributes(e) =>
@@ -213,7 +260,7 @@ impl Predicate {
     }
 }
 
-#[derive(PartialEq, Eq, Debug)]
+#[derive(PartialEq, Eq, Debug, Clone)]
 pub struct AstNode<P>
 where
     P: Hash + Eq,
@@ -221,6 +268,8 @@ where
     pub predicate: Predicate,
     pub children: Vec<AstNode<P>>,
     pub descendants: Vec<AstNode<P>>,
+    pub next_siblings: Vec<AstNode<P>>,
+    pub later_siblings: Vec<AstNode<P>>,
     pub payload: HashSet<P>,
 }
 
@@ -233,12 +282,14 @@ where
             predicate,
             children: Vec::default(),
             descendants: Vec::default(),
+            next_siblings: Vec::default(),
+            later_siblings: Vec::default(),
             payload: HashSet::default(),
         }
     }
 }
 
-#[derive(Default, PartialEq, Eq, Debug)]
+#[derive(Default, PartialEq, Eq, Debug, Clone)]
 pub struct Ast<P>
 where
     P: PartialEq + Eq + Copy + Debug + Hash,
@@ -273,45 +324,215 @@ where
         }
     }
 
+    #[inline]
     pub fn add_selector(&mut self, selector: &Selector, payload: P) {
-        for selector_item in &(selector.0).0 {
-            let mut predicate = Predicate::default();
-            let mut branches = &mut self.root;
-
-            macro_rules! host_and_switch_branch_vec {
-                ($branches:ident) => {{
-                    let node_idx = Self::host_expressions(
-                        predicate,
-                        branches,
-                        &mut self.cumulative_node_count,
-                    );
-
-                    branches = &mut branches[node_idx].$branches;
-                    predicate = Predicate::default();
-                }};
-            }
+        self.add_selector_with_scoped_payload(selector, |_, _| payload);
+    }
+
+    /// Adds a selector, obtaining the payload for each selector in the selector list depending
+    /// on the index of the selector in the list and the pseudo-element the selector ends with.
+    pub fn add_selector_with_scoped_payload(
+        &mut self,
+        selector: &Selector,
+        mut get_payload: impl FnMut(usize, Option<PseudoElementKind>) -> P,
+    ) {
+        for (selector_idx, selector_item) in (selector.0).0.iter().enumerate() {
+            let payload = get_payload(selector_idx, selector_item.pseudo_element().copied());
+            let mut compounds = vec![Compound::default()];
 
             for component in selector_item.iter_raw_parse_order_from(0) {
                 match component {
                     Component::Combinator(c) => match c {
-                        Combinator::Child => host_and_switch_branch_vec!(children),
-                        Combinator::Descendant => host_and_switch_branch_vec!(descendants),
+                        Combinator::Child
+                        | Combinator::Descendant
+                        | Combinator::NextSibling
+                        | Combinator::LaterSibling => {
+                            if let Some(last) = compounds.last_mut() {
+                                last.combinator = Some(*c);
+                            }
+
+                            compounds.push(Compound::default());
+                        }
+                        // NOTE: pseudo-elements only affect the payload.
+                        Combinator::PseudoElement => (),
                         _ => unreachable!(
                             "Unsupported selector components should be filtered out by the parser."
                         ),
                     },
-                    Component::Negation(c) => {
-                        c.iter().for_each(|c| predicate.add_component(c, true))
+                    _ => if let Some(last) = compounds.last_mut() {
+                        last.components.push(component);
+                    },
+                }
+            }
+
+            Self::add_compounds(
+                &compounds,
+                &mut self.root,
+                &mut self.cumulative_node_count,
+                selector,
+                payload,
+            );
+        }
+    }
+
+    /// Removes the payloads that don't satisfy the `predicate`. The nodes are kept, so
+    /// the addresses of the program compiled from the AST are not affected.
+    pub fn retain_payloads(&mut self, mut predicate: impl FnMut(&P) -> bool) {
+        fn retain<P: Hash + Eq>(nodes: &mut [AstNode<P>], predicate: &mut impl FnMut(&P) -> bool) {
+            for node in nodes {
+                node.payload.retain(|p| predicate(p));
+
+                retain(&mut node.children, predicate);
+                retain(&mut node.descendants, predicate);
+                retain(&mut node.next_siblings, predicate);
+                retain(&mut node.later_siblings, predicate);
+            }
+        }
+
+        retain(&mut self.root, &mut predicate);
+    }
+
+    fn add_compounds(
+        compounds: &[Compound],
+        branches: &mut Vec<AstNode<P>>,
+        cumulative_node_count: &mut usize,
+        selector: &Selector,
+        payload: P,
+    ) {
+        let (compound, rest) = match compounds.split_first() {
+            Some(split) => split,
+            None => return,
+        };
+
+        // NOTE: the compound selector with the `:is()` or `:where()` pseudo-classes
+        // is expanded into a branch for each combination of their alternatives.
+        for mut predicate in compound.predicates(selector) {
+            // NOTE: the depth bound is checked first, so the rest of the expressions of
+            // the rightmost compound selector are not executed for the deeper elements.
+            if let (None, Some(max_depth)) = (compound.combinator, selector.2.max_depth) {
+                predicate
+                    .on_tag_name_exprs
+                    .insert(0, Expr::new(OnTagNameExpr::MaxDepth(max_depth), false));
+            }
+
+            let node_idx = Self::host_expressions(predicate, branches, cumulative_node_count);
+            let node = &mut branches[node_idx];
+
+            match (compound.combinator, selector.2.descendant_depth_limit) {
+                (None, _) => {
+                    node.payload.insert(payload);
+                }
+                (Some(Combinator::Descendant), None) => {
+                    Self::add_compounds(rest, &mut node.descendants, cumulative_node_count, selector, payload)
+                }
+                // NOTE: a depth-limited descendant combinator is expanded into the chains of
+                // the child combinators with the universal selectors in between, so that it's
+                // matched without hereditary jumps.
+                (Some(Combinator::Descendant), Some(limit)) => {
+                    let mut branches = &mut node.children;
+
+                    for depth in 1..=limit {
+                        Self::add_compounds(rest, branches, cumulative_node_count, selector, payload);
+
+                        if depth < limit {
+                            let mut any = Predicate::default();
+
+                            any.add_component(&Component::ExplicitUniversalType, false, selector);
+
+                            let any_idx = Self::host_expressions(any, branches, cumulative_node_count);
+
+                            branches = &mut branches[any_idx].children;
+                        }
                     }
-                    _ => predicate.add_component(component, false),
                 }
+                (Some(Combinator::NextSibling), _) => {
+                    Self::add_compounds(rest, &mut node.next_siblings, cumulative_node_count, selector, payload)
+                }
+                (Some(Combinator::LaterSibling), _) => {
+                    Self::add_compounds(rest, &mut node.later_siblings, cumulative_node_count, selector, payload)
+                }
+                (Some(_), _) => {
+                    Self::add_compounds(rest, &mut node.children, cumulative_node_count, selector, payload)
+                }
+            }
+        }
+    }
+}
+
+/// A compound selector in a selector and the combinator that connects it to the next one.
+#[derive(Default)]
+struct Compound<'s> {
+    components: Vec<&'s Component<SelectorImplDescriptor>>,
+    combinator: Option<Combinator>,
+}
+
+impl<'s> Compound<'s> {
+    /// Returns a predicate for each combination of the alternatives of the `:is()` and
+    /// `:where()` pseudo-classes in the compound selector. The number of the combinations is
+    /// bounded by the parser.
+    fn predicates(&self, selector: &Selector) -> Vec<Predicate> {
+        let mut combinations: Vec<Vec<&'s Component<SelectorImplDescriptor>>> = vec![vec![]];
+
+        for &component in &self.components {
+            match component {
+                Component::NonTSPseudoClass(NonTSPseudoClassKind::Is(alternatives))
+                | Component::NonTSPseudoClass(NonTSPseudoClassKind::Where(alternatives)) => {
+                    combinations = combinations
+                        .iter()
+                        .flat_map(|components| {
+                            (alternatives.0).0.iter().map(move |alternative| {
+                                let mut components = components.clone();
+
+                                components.extend(alternative.iter_raw_parse_order_from(0));
+
+                                components
+                            })
+                        })
+                        .collect();
+                }
+                _ => combinations.iter_mut().for_each(|c| c.push(component)),
             }
+        }
+
+        combinations
+            .iter()
+            .map(|components| Self::predicate(components, selector))
+            .collect()
+    }
 
-            let node_idx =
-                Self::host_expressions(predicate, branches, &mut self.cumulative_node_count);
+    fn predicate(components: &[&Component<SelectorImplDescriptor>], selector: &Selector) -> Predicate {
+        let mut predicate = Predicate::default();
 
-            branches[node_idx].payload.insert(payload);
+        for &component in components {
+            match component {
+                // NOTE: `:not(:has())` conditions are evaluated by the rewriter.
+                Component::Negation(c) => c
+                    .iter()
+                    .filter(|c| {
+                        !matches!(c, Component::NonTSPseudoClass(NonTSPseudoClassKind::Has(_)))
+                    })
+                    .for_each(|c| predicate.add_component(c, true, selector)),
+                Component::PseudoElement(_) => (),
+                // NOTE: text predicates, `:empty`, `:has()` and last-counting pseudo-classes
+                // are evaluated by the rewriter.
+                Component::NonTSPseudoClass(NonTSPseudoClassKind::Contains(_))
+                | Component::NonTSPseudoClass(NonTSPseudoClassKind::ContainsOwn(_))
+                | Component::NonTSPseudoClass(NonTSPseudoClassKind::Has(_))
+                | Component::Empty
+                | Component::LastChild
+                | Component::LastOfType
+                | Component::NthLastChild(_, _)
+                | Component::NthLastOfType(_, _) => (),
+                _ => predicate.add_component(component, false, selector),
+            }
+        }
+
+        // NOTE: the compound selector consists only of a pseudo-element (e.g. `::text`).
+        if predicate == Predicate::default() {
+            predicate.add_component(&Component::ExplicitUniversalType, false, selector);
         }
+
+        predicate
     }
 }
 
@@ -384,6 +605,8 @@ mod tests {
                         },
                         children: vec![],
                         descendants: vec![],
+                        next_siblings: vec![],
+                        later_siblings: vec![],
                         payload: set![0],
                     }],
                     cumulative_node_count: 1,
@@ -513,6 +736,8 @@ mod tests {
                         },
                         children: vec![],
                         descendants: vec![],
+                        next_siblings: vec![],
+                        later_siblings: vec![],
                         payload: set![0],
                     }],
                     cumulative_node_count: 1,
@@ -549,6 +774,8 @@ mod tests {
                     },
                     children: vec![],
                     descendants: vec![],
+                    next_siblings: vec![],
+                    later_siblings: vec![],
                     payload: set![0],
                 }],
                 cumulative_node_count: 1,
@@ -571,6 +798,8 @@ mod tests {
                     },
                     children: vec![],
                     descendants: vec![],
+                    next_siblings: vec![],
+                    later_siblings: vec![],
                     payload: set![0, 1],
                 }],
                 cumulative_node_count: 1,
@@ -602,6 +831,8 @@ mod tests {
                             },
                             children: vec![],
                             descendants: vec![],
+                            next_siblings: vec![],
+                            later_siblings: vec![],
                             payload: set![0],
                         },
                         AstNode {
@@ -614,6 +845,8 @@ mod tests {
                             },
                             children: vec![],
                             descendants: vec![],
+                            next_siblings: vec![],
+                            later_siblings: vec![],
                             payload: set![0],
                         },
                         AstNode {
@@ -626,6 +859,8 @@ mod tests {
                             },
                             children: vec![],
                             descendants: vec![],
+                            next_siblings: vec![],
+                            later_siblings: vec![],
                             payload: set![1],
                         },
                         AstNode {
@@ -638,10 +873,14 @@ mod tests {
                             },
                             children: vec![],
                             descendants: vec![],
+                            next_siblings: vec![],
+                            later_siblings: vec![],
                             payload: set![1],
                         },
                     ],
                     descendants: vec![],
+                    next_siblings: vec![],
+                    later_siblings: vec![],
                     payload: set![],
                 }],
                 cumulative_node_count: 5,
@@ -649,6 +888,89 @@ mod tests {
         )
     }
 
+    #[test]
+    fn text_pseudo_element() {
+        assert_ast(
+            &["p::text", "::text"],
+            Ast {
+                root: vec![
+                    AstNode {
+                        predicate: Predicate {
+                            on_tag_name_exprs: vec![Expr {
+                                simple_expr: OnTagNameExpr::LocalName("p".into()),
+                                negation: false,
+                            }],
+                            ..Default::default()
+                        },
+                        children: vec![],
+                        descendants: vec![],
+                        next_siblings: vec![],
+                        later_siblings: vec![],
+                        payload: set![0],
+                    },
+                    AstNode {
+                        predicate: Predicate {
+                            on_tag_name_exprs: vec![Expr {
+                                simple_expr: OnTagNameExpr::ExplicitAny,
+                                negation: false,
+                            }],
+                            ..Default::default()
+                        },
+                        children: vec![],
+                        descendants: vec![],
+                        next_siblings: vec![],
+                        later_siblings: vec![],
+                        payload: set![1],
+                    },
+                ],
+                cumulative_node_count: 2,
+            },
+        );
+    }
+
+    #[test]
+    fn scoped_payload() {
+        let mut ast = Ast::default();
+
+        ast.add_selector_with_scoped_payload(&"div, div::text".parse().unwrap(), |_, p| {
+            p.is_some()
+        });
+
+        assert_eq!(ast.root.len(), 1);
+        assert_eq!(ast.root[0].payload, set![false, true]);
+    }
+
+    #[test]
+    fn comment_pseudo_element_scoped_payload() {
+        let mut ast = Ast::default();
+
+        ast.add_selector_with_scoped_payload(
+            &"div::comment, div::TEXT, div::comment".parse().unwrap(),
+            |idx, p| (idx, p),
+        );
+
+        assert_eq!(ast.root.len(), 1);
+        assert_eq!(
+            ast.root[0].payload,
+            set![
+                (0, Some(PseudoElementKind::Comment)),
+                (1, Some(PseudoElementKind::Text)),
+                (2, Some(PseudoElementKind::Comment))
+            ]
+        );
+    }
+
+    #[test]
+    fn selector_indices() {
+        let mut ast = Ast::default();
+
+        ast.add_selector_with_scoped_payload(&"div, span, div".parse().unwrap(), |i, _| i);
+
+        assert_eq!(ast.root.len(), 2);
+        assert_eq!(ast.root[0].payload, set![0, 2]);
+        assert_eq!(ast.root[1].payload, set![1]);
+    }
+
     #[test]
     fn combinators() {
         assert_ast(
@@ -700,8 +1022,12 @@ mod tests {
                                             },
                                             children: vec![],
                                             descendants: vec![],
+                                            next_siblings: vec![],
+                                            later_siblings: vec![],
                                             payload: set![0],
                                         }],
+                                        next_siblings: vec![],
+                                        later_siblings: vec![],
                                         payload: set![],
                                     },
                                     AstNode {
@@ -714,9 +1040,13 @@ mod tests {
                                         },
                                         children: vec![],
                                         descendants: vec![],
+                                        next_siblings: vec![],
+                                        later_siblings: vec![],
                                         payload: set![1],
                                     },
                                 ],
+                                next_siblings: vec![],
+                                later_siblings: vec![],
                                 payload: set![],
                             },
                             AstNode {
@@ -729,6 +1059,8 @@ mod tests {
                                 },
                                 children: vec![],
                                 descendants: vec![],
+                                next_siblings: vec![],
+                                later_siblings: vec![],
                                 payload: set![2],
                             },
                         ],
@@ -743,6 +1075,8 @@ mod tests {
                                 },
                                 children: vec![],
                                 descendants: vec![],
+                                next_siblings: vec![],
+                                later_siblings: vec![],
                                 payload: set![3],
                             },
                             AstNode {
@@ -766,11 +1100,17 @@ mod tests {
                                     },
                                     children: vec![],
                                     descendants: vec![],
+                                    next_siblings: vec![],
+                                    later_siblings: vec![],
                                     payload: set![4],
                                 }],
+                                next_siblings: vec![],
+                                later_siblings: vec![],
                                 payload: set![],
                             },
                         ],
+                        next_siblings: vec![],
+                        later_siblings: vec![],
                         payload: set![],
                     },
                     AstNode {
@@ -783,6 +1123,8 @@ mod tests {
                         },
                         children: vec![],
                         descendants: vec![],
+                        next_siblings: vec![],
+                        later_siblings: vec![],
                         payload: set![5],
                     },
                 ],
@@ -791,6 +1133,308 @@ mod tests {
         );
     }
 
+    #[test]
+    fn sibling_combinators() {
+        let local_name = |name: &str| Predicate {
+            on_tag_name_exprs: vec![Expr {
+                simple_expr: OnTagNameExpr::LocalName(name.into()),
+                negation: false,
+            }],
+            ..Default::default()
+        };
+
+        assert_ast(
+            &["h2 + p", "h2 ~ p > b"],
+            Ast {
+                root: vec![AstNode {
+                    predicate: local_name("h2"),
+                    children: vec![],
+                    descendants: vec![],
+                    next_siblings: vec![AstNode {
+                        predicate: local_name("p"),
+                        children: vec![],
+                        descendants: vec![],
+                        next_siblings: vec![],
+                        later_siblings: vec![],
+                        payload: set![0],
+                    }],
+                    later_siblings: vec![AstNode {
+                        predicate: local_name("p"),
+                        children: vec![AstNode {
+                            predicate: local_name("b"),
+                            children: vec![],
+                            descendants: vec![],
+                            next_siblings: vec![],
+                            later_siblings: vec![],
+                            payload: set![1],
+                        }],
+                        descendants: vec![],
+                        next_siblings: vec![],
+                        later_siblings: vec![],
+                        payload: set![],
+                    }],
+                    payload: set![],
+                }],
+                cumulative_node_count: 4,
+            },
+        );
+    }
+
+    #[test]
+    fn selector_list_pseudo_classes() {
+        let local_name = |name: &str| Predicate {
+            on_tag_name_exprs: vec![Expr {
+                simple_expr: OnTagNameExpr::LocalName(name.into()),
+                negation: false,
+            }],
+            ..Default::default()
+        };
+
+        let link = || AstNode {
+            predicate: local_name("a"),
+            children: vec![],
+            descendants: vec![],
+            next_siblings: vec![],
+            later_siblings: vec![],
+            payload: set![0],
+        };
+
+        assert_ast(
+            &[":is(h1, h2) a"],
+            Ast {
+                root: vec![
+                    AstNode {
+                        predicate: local_name("h1"),
+                        children: vec![],
+                        descendants: vec![link()],
+                        next_siblings: vec![],
+                        later_siblings: vec![],
+                        payload: set![],
+                    },
+                    AstNode {
+                        predicate: local_name("h2"),
+                        children: vec![],
+                        descendants: vec![link()],
+                        next_siblings: vec![],
+                        later_siblings: vec![],
+                        payload: set![],
+                    },
+                ],
+                cumulative_node_count: 4,
+            },
+        );
+
+        let mut ast = Ast::default();
+
+        ast.add_selector(&"div:where(.a, .b):is(p, span, [c])".parse().unwrap(), 0);
+
+        assert_eq!(ast.root.len(), 6);
+
+        assert_err(":is(div p)", SelectorError::UnsupportedCombinator(' '));
+        assert_err(":where(div > p)", SelectorError::UnsupportedCombinator('>'));
+        assert_err(":is(p::text)", SelectorError::UnsupportedPseudoClassOrElement);
+        assert_err(":is(p:contains(foo))", SelectorError::UnsupportedPseudoClassOrElement);
+        assert_err(":is(:last-child)", SelectorError::UnsupportedPseudoClassOrElement);
+        assert_err(":is(:is(p))", SelectorError::UnsupportedPseudoClassOrElement);
+        assert_err(":not(:is(p))", SelectorError::UnsupportedPseudoClassOrElement);
+
+        let alternatives = ":is(.a, .b, .c, .d)";
+
+        assert!(alternatives.repeat(4).parse::<Selector>().is_ok());
+        assert!(format!("{}, {}", alternatives.repeat(4), alternatives).parse::<Selector>().is_ok());
+        assert_err(&alternatives.repeat(5), SelectorError::TooManyAlternatives(256));
+        assert_err(
+            &format!("{} {}", alternatives.repeat(4), alternatives),
+            SelectorError::TooManyAlternatives(256),
+        );
+    }
+
+    #[test]
+    fn retain_payloads() {
+        let mut ast = Ast::default();
+
+        for (idx, selector) in ["div", "div span", "div > span", "p ~ span"].iter().enumerate() {
+            ast.add_selector(&selector.parse().unwrap(), idx);
+        }
+
+        let prev_ast = ast.clone();
+
+        ast.retain_payloads(|&p| p % 2 == 0);
+
+        assert_eq!(ast.cumulative_node_count, prev_ast.cumulative_node_count);
+        assert_eq!(ast.root[0].payload, set![0]);
+        assert_eq!(ast.root[0].descendants[0].payload, set![]);
+        assert_eq!(ast.root[0].children[0].payload, set![2]);
+        assert_eq!(ast.root[1].later_siblings[0].payload, set![]);
+    }
+
+    #[test]
+    fn descendant_depth_limit() {
+        let predicate = |name: &str| Predicate {
+            on_tag_name_exprs: vec![Expr {
+                simple_expr: OnTagNameExpr::LocalName(name.into()),
+                negation: false,
+            }],
+            ..Default::default()
+        };
+
+        let any = Predicate {
+            on_tag_name_exprs: vec![Expr {
+                simple_expr: OnTagNameExpr::ExplicitAny,
+                negation: false,
+            }],
+            ..Default::default()
+        };
+
+        let selector: Selector = "div a".parse().unwrap();
+        let mut ast = Ast::default();
+
+        ast.add_selector(&selector.with_descendant_depth_limit(2).unwrap(), 0);
+
+        assert_eq!(
+            ast,
+            Ast {
+                root: vec![AstNode {
+                    predicate: predicate("div"),
+                    children: vec![
+                        AstNode {
+                            predicate: predicate("a"),
+                            children: vec![],
+                            descendants: vec![],
+                            next_siblings: vec![],
+                            later_siblings: vec![],
+                            payload: set![0],
+                        },
+                        AstNode {
+                            predicate: any,
+                            children: vec![AstNode {
+                                predicate: predicate("a"),
+                                children: vec![],
+                                descendants: vec![],
+                                next_siblings: vec![],
+                                later_siblings: vec![],
+                                payload: set![0],
+                            }],
+                            descendants: vec![],
+                            next_siblings: vec![],
+                            later_siblings: vec![],
+                            payload: set![],
+                        },
+                    ],
+                    descendants: vec![],
+                    next_siblings: vec![],
+                    later_siblings: vec![],
+                    payload: set![],
+                }],
+                cumulative_node_count: 4,
+            }
+        );
+
+        let selector: Selector = "div a, p > b".parse().unwrap();
+        let mut ast = Ast::default();
+
+        // NOTE: the limit of 1 makes the descendant combinator equivalent to the child one.
+        ast.add_selector(&selector.with_descendant_depth_limit(0).unwrap(), 0);
+
+        assert_eq!(ast.root[0].children[0].predicate, predicate("a"));
+        assert!(ast.root[0].descendants.is_empty());
+        assert_eq!(ast.root[1].children[0].predicate, predicate("b"));
+        assert_eq!(ast.cumulative_node_count, 4);
+
+        let selector: Selector = "a b c, d".parse().unwrap();
+
+        assert!(selector.clone().with_descendant_depth_limit(32).is_ok());
+
+        assert_eq!(
+            selector.with_descendant_depth_limit(33).unwrap_err(),
+            SelectorError::DescendantDepthLimitTooLarge(33)
+        );
+    }
+
+    #[test]
+    fn max_depth() {
+        let selector: Selector = "div > p".parse().unwrap();
+        let mut ast = Ast::default();
+
+        ast.add_selector(&selector.with_max_depth(3), 0);
+
+        // NOTE: only the rightmost compound selector is bounded.
+        assert_eq!(ast.root[0].predicate.on_tag_name_exprs.len(), 1);
+        assert_eq!(
+            ast.root[0].children[0].predicate.on_tag_name_exprs[0],
+            Expr {
+                simple_expr: OnTagNameExpr::MaxDepth(3),
+                negation: false
+            }
+        );
+    }
+
+    #[test]
+    fn custom_pseudo_classes() {
+        use crate::selectors_vm::{PseudoClassElement, PseudoClassPredicate};
+        use std::sync::Arc;
+
+        let predicate: PseudoClassPredicate = Arc::new(|_: &PseudoClassElement| true);
+        let resolver = |name: &str, _: &str| match name {
+            "ext" => Some(Arc::clone(&predicate)),
+            _ => None,
+        };
+
+        let custom_expr = |argument: Option<&str>, negation| Expr {
+            simple_expr: OnAttributesExpr::Custom(CustomPseudoClass {
+                name: "ext".into(),
+                argument: argument.map(String::from),
+                predicate: Arc::clone(&predicate),
+            }),
+            negation,
+        };
+
+        let selector =
+            Selector::parse_with_pseudo_classes("div:ext( foo  bar ):not(:EXT)", resolver).unwrap();
+        let mut ast = Ast::default();
+
+        ast.add_selector(&selector, 0);
+
+        assert_eq!(
+            ast.root[0].predicate.on_attr_exprs,
+            vec![custom_expr(Some("foo  bar"), false), custom_expr(None, true)]
+        );
+
+        assert_eq!(
+            Selector::parse_with_pseudo_classes(":other(foo)", resolver).unwrap_err(),
+            SelectorError::UnsupportedPseudoClassOrElement
+        );
+
+        assert_err(":ext(foo)", SelectorError::UnsupportedPseudoClassOrElement);
+    }
+
+    #[test]
+    fn case_insensitive_ids_and_classes() {
+        let selector: Selector = "#Foo.bar:not(.baz)".parse().unwrap();
+        let mut ast = Ast::default();
+
+        ast.add_selector(&selector.with_case_insensitive_ids_and_classes(), 0);
+
+        let attr_expr = |name: &str, value: &str, operator, negation| Expr {
+            simple_expr: OnAttributesExpr::AttributeComparisonExpr(AttributeComparisonExpr {
+                name: name.into(),
+                value: value.into(),
+                case_sensitivity: ParsedCaseSensitivity::AsciiCaseInsensitive,
+                operator,
+            }),
+            negation,
+        };
+
+        assert_eq!(
+            ast.root[0].predicate.on_attr_exprs,
+            vec![
+                attr_expr("id", "Foo", AttrSelectorOperator::Equal, false),
+                attr_expr("class", "bar", AttrSelectorOperator::Includes, false),
+                attr_expr("class", "baz", AttrSelectorOperator::Includes, true),
+            ]
+        );
+    }
+
     #[test]
     fn parse_errors() {
         assert_err("div@", SelectorError::UnexpectedToken);
@@ -806,8 +1450,94 @@ mod tests {
         assert_err("svg|img", SelectorError::NamespacedSelector);
         assert_err(".foo()", SelectorError::InvalidClassName);
         assert_err(":not()", SelectorError::EmptyNegation);
-        assert_err("div + span", SelectorError::UnsupportedCombinator('+'));
-        assert_err("div ~ span", SelectorError::UnsupportedCombinator('~'));
+    }
+
+    #[test]
+    fn attribute_name_wildcards() {
+        let ast = |selector: &str| {
+            let mut ast = Ast::default();
+
+            ast.add_selector(&selector.parse().unwrap(), 0);
+            ast
+        };
+
+        assert_eq!(ast("p[data-*]"), ast("p:attr-prefix(data-)"));
+        assert_eq!(ast("[ DATA-* ]"), ast(":attr-prefix(data-)"));
+        assert_eq!(ast(r#"[\\x-*]"#), ast(r#":attr-prefix("\\x-")"#));
+        assert_eq!(
+            ast(":not([data-*]), div [aria-*]"),
+            ast(":not(:attr-prefix(data-)), div :attr-prefix(aria-)")
+        );
+        assert_ne!(ast(r#"[data-*="foo"]"#), ast(":attr-prefix(data-)"));
+
+        assert_err("[data- *]", SelectorError::UnexpectedTokenInAttribute);
+
+        let err = Selector::parse_detailed("[data-*] [aria-*]@").unwrap_err();
+
+        assert_eq!(err.offset(), Some(17));
+        assert_eq!(err.token(), Some("@"));
+    }
+
+    #[test]
+    fn attribute_inequality() {
+        let ast = |selector: &str| {
+            let mut ast = Ast::default();
+
+            ast.add_selector(&selector.parse().unwrap(), 0);
+            ast
+        };
+
+        assert_eq!(ast("img[loading!=lazy]"), ast("img:not([loading=lazy])"));
+        assert_eq!(ast(r#"[ foo != "bar" i ]"#), ast(r#":not([foo="bar" i])"#));
+        assert_eq!(
+            ast("p[a!=b] [c!='d'], :is([e!=f])"),
+            ast("p:not([a=b]) :not([c='d']), :is(:not([e=f]))")
+        );
+
+        assert_err("[foo!=@]", SelectorError::UnexpectedTokenInAttribute);
+        assert_err("[foo! =bar]", SelectorError::UnexpectedTokenInAttribute);
+        assert_err(":not([foo!=bar])", SelectorError::NestedNegation);
+
+        let err = Selector::parse_detailed("[a!=b] [c!=d]@").unwrap_err();
+
+        assert_eq!(err.offset(), Some(13));
+        assert_eq!(err.token(), Some("@"));
+    }
+
+    #[test]
+    fn parse_error_details() {
+        let err = Selector::parse_detailed("div@").unwrap_err();
+
+        assert_eq!(err.kind(), SelectorError::UnexpectedToken);
+        assert_eq!(err.offset(), Some(3));
+        assert_eq!(err.token(), Some("@"));
+        assert!(err.expected().contains(&"combinator"));
+
+        let err = Selector::parse_detailed("div.").unwrap_err();
+
+        assert_eq!(err.kind(), SelectorError::UnexpectedEnd);
+        assert_eq!(err.offset(), Some(4));
+        assert_eq!(err.token(), None);
+
+        let err = Selector::parse_detailed("p, .foo()").unwrap_err();
+
+        assert_eq!(err.kind(), SelectorError::InvalidClassName);
+        assert_eq!(err.offset(), Some(4));
+        assert_eq!(err.token(), Some("foo("));
+        assert_eq!(err.expected(), &["class name"]);
+
+        let err = Selector::parse_detailed("div p,\n  span@").unwrap_err();
+
+        assert_eq!(err.offset(), Some(13));
+        assert_eq!(err.token(), Some("@"));
+
+        let err = Selector::parse_detailed("p:empty span").unwrap_err();
+
+        assert_eq!(err.kind(), SelectorError::UnsupportedPseudoClassOrElement);
+        assert_eq!(err.offset(), None);
+        assert_eq!(err.token(), None);
+
+        assert!(Selector::parse_detailed("div > p").is_ok());
     }
 
     #[test]
@@ -823,7 +1553,6 @@ mod tests {
             ":dir(rtl)",
             ":disabled",
             ":drop",
-            ":empty",
             ":enabled",
             ":first",
             ":fullscreen",
@@ -831,7 +1560,6 @@ mod tests {
             ":focus",
             ":focus-visible",
             ":focus-within",
-            ":has(div)",
             ":host",
             ":host(h1)",
             ":host-context(h1)",
@@ -839,19 +1567,12 @@ mod tests {
             ":indeterminate",
             ":in-range",
             ":invalid",
-            ":is(header)",
             ":lang(en)",
-            ":last-child",
-            ":last-of-type",
             ":left",
             ":link",
             ":local-link",
             ":nth-col(1)",
-            ":nth-last-child(1)",
             ":nth-last-col(1)",
-            ":nth-last-of-type(1)",
-            ":only-child",
-            ":only-of-type",
             ":optional",
             ":out-of-range",
             ":past",
@@ -867,7 +1588,6 @@ mod tests {
             ":user-invalid",
             ":valid",
             ":visited",
-            ":where(p)",
         ]
         .iter()
         .for_each(|s| assert_err(s, SelectorError::UnsupportedPseudoClassOrElement));
@@ -901,6 +1621,231 @@ mod tests {
         );
     }
 
+    #[test]
+    fn text_predicates() {
+        let selector: Selector = r#"div:contains("foo"), p, :contains(bar):contains("baz")"#
+            .parse()
+            .unwrap();
+
+        assert_eq!(
+            selector.text_predicates(),
+            vec![vec!["foo".to_string()], vec![], vec!["bar".to_string(), "baz".to_string()]]
+        );
+
+        let own: Selector = r#"p:contains-own("foo"):contains(bar), div"#.parse().unwrap();
+
+        assert_eq!(own.text_predicates(), vec![vec!["bar".to_string()], vec![]]);
+        assert_eq!(own.own_text_predicates(), vec![vec!["foo".to_string()], vec![]]);
+
+        let mut ast = Ast::default();
+
+        ast.add_selector(&selector, 0);
+
+        // NOTE: text predicates are not reflected in the AST, so the last selector is
+        // represented by the universal selector.
+        assert_eq!(ast.root.len(), 3);
+        assert_eq!(ast.root[2].predicate.on_tag_name_exprs.len(), 1);
+
+        [
+            r#"div:contains("foo") p"#,
+            r#"div:contains("foo") > p"#,
+            r#"div:not(:contains("foo"))"#,
+            r#"div:contains("foo")::text"#,
+            "div:contains()",
+            "div:contains(1)",
+            r#"div:contains-own("foo") p"#,
+            r#"div:not(:contains-own("foo"))"#,
+        ]
+        .iter()
+        .for_each(|s| assert!(s.parse::<Selector>().is_err(), "{}", s));
+    }
+
+    #[test]
+    fn empty_predicates() {
+        let selector: Selector = "div:empty, p, :empty".parse().unwrap();
+
+        assert_eq!(selector.empty_predicates(), vec![true, false, true]);
+
+        let mut ast = Ast::default();
+
+        ast.add_selector(&selector, 0);
+
+        // NOTE: the pseudo-class is not reflected in the AST, so the last selector is
+        // represented by the universal selector.
+        assert_eq!(ast.root.len(), 3);
+        assert_eq!(ast.root[2].predicate.on_tag_name_exprs.len(), 1);
+
+        ["div:empty p", "div:empty + p", "div:not(:empty)", "div:empty::text"]
+            .iter()
+            .for_each(|s| assert!(s.parse::<Selector>().is_err(), "{}", s));
+    }
+
+    #[test]
+    fn last_counting_predicates() {
+        use super::super::parser::NthLast;
+
+        let selector: Selector = "li:nth-last-child(-n+2), p:last-child:last-of-type, b"
+            .parse()
+            .unwrap();
+
+        assert_eq!(
+            selector.last_counting_predicates(),
+            vec![
+                vec![NthLast {
+                    nth: NthChild::new(-1, 2),
+                    of_type: false
+                }],
+                vec![
+                    NthLast {
+                        nth: NthChild::first(),
+                        of_type: false
+                    },
+                    NthLast {
+                        nth: NthChild::first(),
+                        of_type: true
+                    }
+                ],
+                vec![]
+            ]
+        );
+
+        let mut ast = Ast::default();
+
+        ast.add_selector(&selector, 0);
+
+        // NOTE: the pseudo-classes are not reflected in the AST.
+        assert_eq!(ast.root.len(), 3);
+        assert_eq!(ast.root[1].predicate.on_tag_name_exprs.len(), 1);
+
+        [
+            "li:last-child a",
+            "li:nth-last-child(2) > a",
+            "li:last-of-type::text",
+            "li:not(:last-child)",
+            "div:not(:has(li:nth-last-of-type(2)))",
+        ]
+        .iter()
+        .for_each(|s| {
+            assert_eq!(
+                s.parse::<Selector>().unwrap_err(),
+                SelectorError::UnsupportedPseudoClassOrElement,
+                "{}",
+                s
+            )
+        });
+    }
+
+    #[test]
+    fn excluded_descendants() {
+        use cssparser::ToCss;
+
+        let selector: Selector = "div:not(:has(img, a > b)).card, p".parse().unwrap();
+        let excluded = selector.excluded_descendants();
+
+        assert_eq!(excluded.len(), 2);
+        assert_eq!(excluded[0].len(), 1);
+        assert_eq!((excluded[0][0].0).to_css_string(), "img, a > b");
+        assert!(excluded[1].is_empty());
+
+        let mut ast = Ast::default();
+
+        ast.add_selector(&selector, 0);
+
+        // NOTE: the condition is not reflected in the AST.
+        assert_eq!(ast.root[0].predicate.on_tag_name_exprs.len(), 1);
+        assert_eq!(ast.root[0].predicate.on_attr_exprs.len(), 1);
+
+        [
+            "div:not(:has(img)) p",
+            "div:not(:has(img)) > p",
+            "div:not(:has(img))::text",
+            "div:not(:has(p:contains(foo)))",
+            "div:not(:has(p:not(:has(img))))",
+            "div:not(:has(:last-child))",
+        ]
+        .iter()
+        .for_each(|s| {
+            assert_eq!(
+                s.parse::<Selector>().unwrap_err(),
+                SelectorError::UnsupportedPseudoClassOrElement,
+                "{}",
+                s
+            )
+        });
+    }
+
+    #[test]
+    fn required_descendants() {
+        use cssparser::ToCss;
+
+        let selector: Selector = "article:has(img.lazy, video):has(p), p".parse().unwrap();
+        let required = selector.required_descendants();
+
+        assert_eq!(required.len(), 2);
+
+        let mut conditions = required[0]
+            .iter()
+            .map(|s| (s.0).to_css_string())
+            .collect::<Vec<_>>();
+
+        conditions.sort();
+
+        assert_eq!(conditions, vec!["img.lazy, video", "p"]);
+        assert!(required[1].is_empty());
+
+        let mut ast = Ast::default();
+
+        ast.add_selector(&selector, 0);
+
+        // NOTE: the conditions are not reflected in the AST.
+        assert_eq!(ast.root[0].predicate.on_tag_name_exprs.len(), 1);
+        assert!(ast.root[0].predicate.on_attr_exprs.is_empty());
+
+        [
+            "div:has(img) p",
+            "div:has(img) > p",
+            "div:has(img)::text",
+            "div:has(p:has(img))",
+            "div:has(p:contains(foo))",
+            "div:is(:has(img))",
+        ]
+        .iter()
+        .for_each(|s| {
+            assert_eq!(
+                s.parse::<Selector>().unwrap_err(),
+                SelectorError::UnsupportedPseudoClassOrElement,
+                "{}",
+                s
+            )
+        });
+    }
+
+    #[test]
+    #[cfg(feature = "regex_selectors")]
+    fn attribute_regex() {
+        use cssparser::ToCss;
+
+        let selector: Selector = r#"a:attr-matches(HREF, "^https?://")"#.parse().unwrap();
+
+        assert_eq!(
+            (selector.0).0[0].to_css_string(),
+            r#"a:attr-matches(href, "^https?://")"#
+        );
+
+        assert_err(r#"a:attr-matches(href, "[")"#, SelectorError::InvalidRegex);
+        assert_err(r#"a:attr-matches("href", "a")"#, SelectorError::UnexpectedToken);
+        assert_err("a:attr-matches(href)", SelectorError::UnexpectedEnd);
+    }
+
+    #[test]
+    #[cfg(not(feature = "regex_selectors"))]
+    fn attribute_regex_without_feature() {
+        assert_err(
+            r#"a:attr-matches(href, "^https?://")"#,
+            SelectorError::UnsupportedPseudoClassOrElement,
+        );
+    }
+
     #[test]
     fn nth_child_is_index() {
         let even = NthChild::new(2, 0);
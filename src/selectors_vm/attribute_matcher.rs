@@ -2,15 +2,18 @@ use super::compiler::AttrExprOperands;
 use crate::base::Bytes;
 use crate::html::Namespace;
 use crate::parser::{AttributeOutline, SharedAttributeBuffer};
-use encoding_rs::UTF_8;
+use encoding_rs::{Encoding, UTF_8};
 use lazy_static::lazy_static;
 use lazycell::LazyCell;
 use memchr::{memchr, memchr2};
+#[cfg(feature = "regex_selectors")]
+use regex::Regex;
 use selectors::attr::CaseSensitivity;
 
 lazy_static! {
     static ref ID_ATTR: Bytes<'static> = Bytes::from_str("id", UTF_8);
     static ref CLASS_ATTR: Bytes<'static> = Bytes::from_str("class", UTF_8);
+    static ref IS_ATTR: Bytes<'static> = Bytes::from_str("is", UTF_8);
 }
 
 #[inline]
@@ -20,11 +23,70 @@ fn is_attr_whitespace(b: u8) -> bool {
 
 type MemoizedAttrValue<'i> = LazyCell<Option<Bytes<'i>>>;
 
+const ID_KEY_KIND: u8 = 0;
+const CLASS_KEY_KIND: u8 = 1;
+const ATTR_NAME_KEY_KIND: u8 = 2;
+
+/// An id, a class or an attribute name an element is required to have to match a selector,
+/// hashed to a bit of the [`AttributeKeyFilter`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AttributeKey(u8);
+
+impl AttributeKey {
+    #[inline]
+    fn new(kind: u8, bytes: impl Iterator<Item = u8>) -> Self {
+        // NOTE: FNV-1a, the filter is small, so the quality of the hash doesn't matter much.
+        let hash = bytes.fold(0xcbf2_9ce4_8422_2325 ^ u64::from(kind), |h, b| {
+            (h ^ u64::from(b)).wrapping_mul(0x0100_0000_01b3)
+        });
+
+        AttributeKey((hash ^ (hash >> 32)) as u8)
+    }
+
+    #[inline]
+    pub fn id(id: &[u8]) -> Self {
+        Self::new(ID_KEY_KIND, id.iter().copied())
+    }
+
+    #[inline]
+    pub fn class(class: &[u8]) -> Self {
+        Self::new(CLASS_KEY_KIND, class.iter().copied())
+    }
+
+    /// Attribute names are matched ASCII case-insensitively, so the key is built from
+    /// the lowercased name.
+    #[inline]
+    pub fn attribute_name(name: &[u8]) -> Self {
+        Self::new(ATTR_NAME_KEY_KIND, name.iter().map(u8::to_ascii_lowercase))
+    }
+}
+
+/// A Bloom filter of the id, the classes and the attribute names of an element.
+///
+/// If the filter doesn't contain a key, the element definitely doesn't have the id, the class
+/// or the attribute. The opposite is not true, as different keys can share the same bit.
+#[derive(Default)]
+pub struct AttributeKeyFilter([u64; 4]);
+
+impl AttributeKeyFilter {
+    #[inline]
+    fn insert(&mut self, AttributeKey(bit): AttributeKey) {
+        self.0[usize::from(bit >> 6)] |= 1 << (bit & 63);
+    }
+
+    #[inline]
+    fn may_contain(&self, AttributeKey(bit): AttributeKey) -> bool {
+        self.0[usize::from(bit >> 6)] & (1 << (bit & 63)) != 0
+    }
+}
+
 pub struct AttributeMatcher<'i> {
     input: &'i Bytes<'i>,
     attributes: SharedAttributeBuffer,
     id: MemoizedAttrValue<'i>,
     class: MemoizedAttrValue<'i>,
+    is: MemoizedAttrValue<'i>,
+    key_filter: LazyCell<AttributeKeyFilter>,
     is_html_element: bool,
 }
 
@@ -36,6 +98,8 @@ impl<'i> AttributeMatcher<'i> {
             attributes,
             id: LazyCell::default(),
             class: LazyCell::default(),
+            is: LazyCell::default(),
+            key_filter: LazyCell::default(),
             is_html_element: ns == Namespace::Html,
         }
     }
@@ -43,7 +107,8 @@ impl<'i> AttributeMatcher<'i> {
     #[inline]
     fn find(&self, lowercased_name: &Bytes) -> Option<AttributeOutline> {
         self.attributes
-            .borrow()
+            .borrow_mut()
+            .outlines(self.input)
             .iter()
             .find(|a| {
                 if lowercased_name.len() != a.name.end - a.name.start {
@@ -74,6 +139,67 @@ impl<'i> AttributeMatcher<'i> {
         self.find(lowercased_name).is_some()
     }
 
+    /// Decodes the names and the values of all the attributes. The names are ASCII-lowercased.
+    pub fn decoded_attributes(&self, encoding: &'static Encoding) -> Vec<(String, String)> {
+        self.attributes
+            .borrow_mut()
+            .outlines(self.input)
+            .iter()
+            .map(|a| {
+                (
+                    self.input.slice(a.name).as_lowercase_string(encoding),
+                    self.input.slice(a.value).as_string(encoding),
+                )
+            })
+            .collect()
+    }
+
+    #[inline]
+    pub fn has_attr_with_name_prefix(&self, lowercased_prefix: &Bytes) -> bool {
+        self.attributes
+            .borrow_mut()
+            .outlines(self.input)
+            .iter()
+            .any(|a| {
+                let attr_name = self.input.slice(a.name);
+
+                attr_name.len() >= lowercased_prefix.len()
+                    && attr_name
+                        .iter()
+                        .zip(lowercased_prefix.iter())
+                        .all(|(b, p)| b.to_ascii_lowercase() == *p)
+            })
+    }
+
+    fn build_key_filter(&self) -> AttributeKeyFilter {
+        let mut filter = AttributeKeyFilter::default();
+
+        for a in self.attributes.borrow_mut().outlines(self.input).iter() {
+            filter.insert(AttributeKey::attribute_name(&self.input.slice(a.name)));
+        }
+
+        if let Some(id) = self.id.borrow_with(|| self.get_value(&ID_ATTR)) {
+            filter.insert(AttributeKey::id(id));
+        }
+
+        if let Some(class) = self.class.borrow_with(|| self.get_value(&CLASS_ATTR)) {
+            class
+                .split(|&b| is_attr_whitespace(b))
+                .for_each(|class_name| filter.insert(AttributeKey::class(class_name)));
+        }
+
+        filter
+    }
+
+    /// Returns `false` if the element definitely doesn't have the id, the class or
+    /// the attribute of the `key`.
+    #[inline]
+    pub fn may_have(&self, key: AttributeKey) -> bool {
+        self.key_filter
+            .borrow_with(|| self.build_key_filter())
+            .may_contain(key)
+    }
+
     #[inline]
     pub fn has_id(&self, id: &Bytes) -> bool {
         match self.id.borrow_with(|| self.get_value(&ID_ATTR)) {
@@ -94,7 +220,15 @@ impl<'i> AttributeMatcher<'i> {
 
     #[inline]
     fn value_matches(&self, name: &Bytes, matcher: impl Fn(Bytes) -> bool) -> bool {
-        match self.get_value(name) {
+        // NOTE: the value of the `is` attribute is memoized, as selectors of customized
+        // built-in elements (e.g. `button[is=fancy-button]`) usually come in large numbers.
+        let value = if **name == **IS_ATTR {
+            self.is.borrow_with(|| self.get_value(&IS_ATTR)).clone()
+        } else {
+            self.get_value(name)
+        };
+
+        match value {
             Some(value) => matcher(value),
             None => false,
         }
@@ -155,6 +289,19 @@ impl<'i> AttributeMatcher<'i> {
         })
     }
 
+    #[cfg(feature = "regex_selectors")]
+    #[inline]
+    pub fn has_attr_matching_regex(
+        &self,
+        lowercased_name: &Bytes,
+        regex: &Regex,
+        encoding: &'static Encoding,
+    ) -> bool {
+        self.value_matches(lowercased_name, |actual_value| {
+            regex.is_match(&encoding.decode_without_bom_handling(&actual_value).0)
+        })
+    }
+
     #[inline]
     pub fn has_attr_with_suffix(&self, operand: &AttrExprOperands) -> bool {
         self.value_matches(&operand.name, |actual_value| {
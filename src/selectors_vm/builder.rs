@@ -0,0 +1,929 @@
+//! A structural representation of the parsed selectors and a builder of selectors from it.
+//!
+//! The built selectors are serialized to CSS with all the names and values escaped and then
+//! parsed, so they are validated in the same way as the selectors parsed from strings.
+#[cfg(feature = "regex_selectors")]
+use super::parser::AttributeRegex;
+use super::parser::{
+    NestedSelectorList, NonTSPseudoClassKind, PseudoElementKind, SelectorImplDescriptor,
+};
+use super::template::PlaceholderValues;
+use super::{CustomPseudoClass, Selector, SelectorError};
+use cssparser::{serialize_identifier, serialize_string, ToCss};
+use selectors::attr::{AttrSelectorOperator, ParsedCaseSensitivity};
+use selectors::parser::{Combinator, Component, SelectorList};
+use std::fmt::Write;
+
+/// A combinator between two compound selectors.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SelectorCombinator {
+    /// `E F` - an `F` element descendant of an `E` element.
+    Descendant,
+    /// `E > F` - an `F` element child of an `E` element.
+    Child,
+    /// `E + F` - an `F` element immediately preceded by an `E` element.
+    NextSibling,
+    /// `E ~ F` - an `F` element preceded by an `E` element.
+    LaterSibling,
+}
+
+/// An operator of an attribute selector that compares the value of the attribute.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum AttributeOperator {
+    /// `[foo="bar"]` - the value is exactly equal to `"bar"`.
+    Equal,
+    /// `[foo~="bar"]` - the value is a list of whitespace-separated values, one of which is
+    /// exactly equal to `"bar"`.
+    Includes,
+    /// `[foo|="en"]` - the value is a hyphen-separated list of values beginning with `"en"`.
+    DashMatch,
+    /// `[foo^="bar"]` - the value begins with `"bar"`.
+    Prefix,
+    /// `[foo$="bar"]` - the value ends with `"bar"`.
+    Suffix,
+    /// `[foo*="bar"]` - the value contains `"bar"`.
+    Substring,
+}
+
+/// The case sensitivity of the comparison of an attribute value.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum AttributeCaseSensitivity {
+    /// The default case sensitivity of the attribute, e.g. the values of the `type` attribute
+    /// are compared case-insensitively.
+    Default,
+    /// `[foo="bar" s]` - the value is compared case-sensitively.
+    CaseSensitive,
+    /// `[foo="bar" i]` - the value is compared ASCII case-insensitively.
+    AsciiCaseInsensitive,
+}
+
+/// A pseudo-class of a compound selector.
+///
+/// The `step` and the `offset` of the `:nth-*()` pseudo-classes are the `a` and the `b` of
+/// the `an+b` notation, e.g. `:nth-child(2n+1)` has the step of `2` and the offset of `1`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PseudoClass {
+    /// `:first-child`.
+    FirstChild,
+    /// `:nth-child(an+b)`.
+    NthChild { step: i32, offset: i32 },
+    /// `:first-of-type`.
+    FirstOfType,
+    /// `:nth-of-type(an+b)`.
+    NthOfType { step: i32, offset: i32 },
+    /// `:last-child`.
+    LastChild,
+    /// `:nth-last-child(an+b)`.
+    NthLastChild { step: i32, offset: i32 },
+    /// `:last-of-type`.
+    LastOfType,
+    /// `:nth-last-of-type(an+b)`.
+    NthLastOfType { step: i32, offset: i32 },
+    /// `:only-child`.
+    OnlyChild,
+    /// `:only-of-type`.
+    OnlyOfType,
+    /// `:empty`.
+    Empty,
+    /// `:contains("text")`.
+    Contains(String),
+    /// `:contains-own("text")`.
+    ContainsOwn(String),
+    /// `:attr-prefix(data-)`.
+    AttributeNamePrefix(String),
+    /// `:has(s)` with the selectors of the selector list `s`. Can be negated only on its own
+    /// in [`SelectorPart::Not`].
+    ///
+    /// [`SelectorPart::Not`]: enum.SelectorPart.html#variant.Not
+    Has(Vec<Vec<SelectorPart>>),
+    /// `:is(s)` with the compound selectors of the selector list `s`.
+    Is(Vec<Vec<SelectorPart>>),
+    /// `:where(s)` with the compound selectors of the selector list `s`.
+    Where(Vec<Vec<SelectorPart>>),
+    /// `:attr-matches(name, "regex")`.
+    #[cfg(feature = "regex_selectors")]
+    AttributeMatches { name: String, pattern: String },
+    /// A custom pseudo-class, e.g. `:my-ext(foo)`, with the source text of its argument or
+    /// `None` if it's not functional. The predicates of the custom pseudo-classes are not
+    /// a part of the structural representation, so [`SelectorBuilder`] fails to build
+    /// the selectors that have them.
+    ///
+    /// [`SelectorBuilder`]: struct.SelectorBuilder.html
+    Custom {
+        name: String,
+        argument: Option<String>,
+    },
+}
+
+/// A part of a selector: a simple selector, a combinator or a pseudo-element.
+///
+/// A selector is a sequence of parts in the order they appear in the selector text, e.g.
+/// `ul > li.item` consists of the `ul` local name, the child combinator, the `li` local name and
+/// the `item` class.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SelectorPart {
+    /// `*`.
+    Universal,
+    /// A tag name, e.g. `div`.
+    LocalName(String),
+    /// `#id`.
+    Id(String),
+    /// `.class`.
+    Class(String),
+    /// `[name]`.
+    AttributeExists(String),
+    /// `[name="value"]` and the other attribute value comparisons.
+    Attribute {
+        name: String,
+        operator: AttributeOperator,
+        value: String,
+        case_sensitivity: AttributeCaseSensitivity,
+    },
+    /// A pseudo-class, e.g. `:first-child`.
+    PseudoClass(PseudoClass),
+    /// `:not()` with the simple selectors it negates.
+    Not(Vec<SelectorPart>),
+    /// A combinator between the compound selectors.
+    Combinator(SelectorCombinator),
+    /// A pseudo-element, e.g. `::text`. Can only be the last part of a selector.
+    PseudoElement(PseudoElementKind),
+}
+
+impl SelectorPart {
+    fn from_component(
+        component: &Component<SelectorImplDescriptor>,
+        values: &PlaceholderValues,
+    ) -> Option<Self> {
+        let owned = |s: &str| values.apply(s).into_owned();
+
+        Some(match component {
+            Component::LocalName(n) => SelectorPart::LocalName(owned(&n.name)),
+            Component::ExplicitUniversalType => SelectorPart::Universal,
+            Component::ID(id) => SelectorPart::Id(owned(id)),
+            Component::Class(c) => SelectorPart::Class(owned(c)),
+            Component::AttributeInNoNamespaceExists { local_name, .. } => {
+                SelectorPart::AttributeExists(owned(local_name))
+            }
+            &Component::AttributeInNoNamespace {
+                ref local_name,
+                ref value,
+                operator,
+                case_sensitivity,
+                ..
+            } => SelectorPart::Attribute {
+                name: owned(local_name),
+                operator: match operator {
+                    AttrSelectorOperator::Equal => AttributeOperator::Equal,
+                    AttrSelectorOperator::Includes => AttributeOperator::Includes,
+                    AttrSelectorOperator::DashMatch => AttributeOperator::DashMatch,
+                    AttrSelectorOperator::Prefix => AttributeOperator::Prefix,
+                    AttrSelectorOperator::Suffix => AttributeOperator::Suffix,
+                    AttrSelectorOperator::Substring => AttributeOperator::Substring,
+                },
+                value: owned(value),
+                case_sensitivity: match case_sensitivity {
+                    ParsedCaseSensitivity::ExplicitCaseSensitive => {
+                        AttributeCaseSensitivity::CaseSensitive
+                    }
+                    ParsedCaseSensitivity::AsciiCaseInsensitive => {
+                        AttributeCaseSensitivity::AsciiCaseInsensitive
+                    }
+                    ParsedCaseSensitivity::CaseSensitive
+                    | ParsedCaseSensitivity::AsciiCaseInsensitiveIfInHtmlElementInHtmlDocument => {
+                        AttributeCaseSensitivity::Default
+                    }
+                },
+            },
+            Component::FirstChild => SelectorPart::PseudoClass(PseudoClass::FirstChild),
+            Component::FirstOfType => SelectorPart::PseudoClass(PseudoClass::FirstOfType),
+            Component::LastChild => SelectorPart::PseudoClass(PseudoClass::LastChild),
+            Component::LastOfType => SelectorPart::PseudoClass(PseudoClass::LastOfType),
+            Component::OnlyChild => SelectorPart::PseudoClass(PseudoClass::OnlyChild),
+            Component::OnlyOfType => SelectorPart::PseudoClass(PseudoClass::OnlyOfType),
+            Component::Empty => SelectorPart::PseudoClass(PseudoClass::Empty),
+            &Component::NthChild(step, offset) => {
+                SelectorPart::PseudoClass(PseudoClass::NthChild { step, offset })
+            }
+            &Component::NthOfType(step, offset) => {
+                SelectorPart::PseudoClass(PseudoClass::NthOfType { step, offset })
+            }
+            &Component::NthLastChild(step, offset) => {
+                SelectorPart::PseudoClass(PseudoClass::NthLastChild { step, offset })
+            }
+            &Component::NthLastOfType(step, offset) => {
+                SelectorPart::PseudoClass(PseudoClass::NthLastOfType { step, offset })
+            }
+            Component::NonTSPseudoClass(pseudo_class) => {
+                SelectorPart::PseudoClass(match pseudo_class {
+                    NonTSPseudoClassKind::Contains(text) => PseudoClass::Contains(owned(text)),
+                    NonTSPseudoClassKind::ContainsOwn(text) => {
+                        PseudoClass::ContainsOwn(owned(text))
+                    }
+                    NonTSPseudoClassKind::AttributeNamePrefix(prefix) => {
+                        PseudoClass::AttributeNamePrefix(owned(prefix))
+                    }
+                    NonTSPseudoClassKind::Has(NestedSelectorList(selector_list)) => {
+                        PseudoClass::Has(selector_list_parts(selector_list, values))
+                    }
+                    NonTSPseudoClassKind::Is(NestedSelectorList(selector_list)) => {
+                        PseudoClass::Is(selector_list_parts(selector_list, values))
+                    }
+                    NonTSPseudoClassKind::Where(NestedSelectorList(selector_list)) => {
+                        PseudoClass::Where(selector_list_parts(selector_list, values))
+                    }
+                    #[cfg(feature = "regex_selectors")]
+                    NonTSPseudoClassKind::AttributeMatches(AttributeRegex { name, regex }) => {
+                        PseudoClass::AttributeMatches {
+                            name: name.to_owned(),
+                            pattern: regex.as_str().to_owned(),
+                        }
+                    }
+                    NonTSPseudoClassKind::Custom(CustomPseudoClass { name, argument, .. }) => {
+                        PseudoClass::Custom {
+                            name: name.to_owned(),
+                            argument: argument.to_owned(),
+                        }
+                    }
+                })
+            }
+            Component::Negation(components) => SelectorPart::Not(parts(components, values)),
+            Component::Combinator(Combinator::Child) => {
+                SelectorPart::Combinator(SelectorCombinator::Child)
+            }
+            Component::Combinator(Combinator::Descendant) => {
+                SelectorPart::Combinator(SelectorCombinator::Descendant)
+            }
+            Component::Combinator(Combinator::NextSibling) => {
+                SelectorPart::Combinator(SelectorCombinator::NextSibling)
+            }
+            Component::Combinator(Combinator::LaterSibling) => {
+                SelectorPart::Combinator(SelectorCombinator::LaterSibling)
+            }
+            &Component::PseudoElement(kind) => SelectorPart::PseudoElement(kind),
+            // NOTE: the pseudo-element combinator is implied by the pseudo-element, the namespace
+            // prefixes are handled by `parts`. The rest of the components are rejected by
+            // the parser.
+            _ => return None,
+        })
+    }
+
+    fn to_css(&self, dest: &mut String) {
+        // NOTE: writing to a string never fails.
+        match self {
+            SelectorPart::Universal => dest.push('*'),
+            SelectorPart::LocalName(name) => serialize_identifier(name, dest).unwrap(),
+            SelectorPart::Id(id) => {
+                dest.push('#');
+                serialize_identifier(id, dest).unwrap();
+            }
+            SelectorPart::Class(class) => {
+                dest.push('.');
+                serialize_identifier(class, dest).unwrap();
+            }
+            SelectorPart::AttributeExists(name) => {
+                dest.push('[');
+                serialize_identifier(name, dest).unwrap();
+                dest.push(']');
+            }
+            SelectorPart::Attribute {
+                name,
+                operator,
+                value,
+                case_sensitivity,
+            } => {
+                dest.push('[');
+                serialize_identifier(name, dest).unwrap();
+
+                dest.push_str(match operator {
+                    AttributeOperator::Equal => "=",
+                    AttributeOperator::Includes => "~=",
+                    AttributeOperator::DashMatch => "|=",
+                    AttributeOperator::Prefix => "^=",
+                    AttributeOperator::Suffix => "$=",
+                    AttributeOperator::Substring => "*=",
+                });
+
+                serialize_string(value, dest).unwrap();
+
+                dest.push_str(match case_sensitivity {
+                    AttributeCaseSensitivity::Default => "]",
+                    AttributeCaseSensitivity::CaseSensitive => " s]",
+                    AttributeCaseSensitivity::AsciiCaseInsensitive => " i]",
+                });
+            }
+            SelectorPart::PseudoClass(pseudo_class) => pseudo_class.to_css(dest),
+            SelectorPart::Not(parts) => {
+                dest.push_str(":not(");
+                parts.iter().for_each(|p| p.to_css(dest));
+                dest.push(')');
+            }
+            SelectorPart::Combinator(SelectorCombinator::Descendant) => dest.push(' '),
+            SelectorPart::Combinator(SelectorCombinator::Child) => dest.push_str(" > "),
+            SelectorPart::Combinator(SelectorCombinator::NextSibling) => dest.push_str(" + "),
+            SelectorPart::Combinator(SelectorCombinator::LaterSibling) => dest.push_str(" ~ "),
+            SelectorPart::PseudoElement(kind) => kind.to_css(dest).unwrap(),
+        }
+    }
+}
+
+impl PseudoClass {
+    fn to_css(&self, dest: &mut String) {
+        let nth = |dest: &mut String, name, step: i32, offset: i32| {
+            write!(dest, ":{}({}n{:+})", name, step, offset).unwrap()
+        };
+
+        // NOTE: writing to a string never fails.
+        match *self {
+            PseudoClass::FirstChild => dest.push_str(":first-child"),
+            PseudoClass::FirstOfType => dest.push_str(":first-of-type"),
+            PseudoClass::LastChild => dest.push_str(":last-child"),
+            PseudoClass::LastOfType => dest.push_str(":last-of-type"),
+            PseudoClass::OnlyChild => dest.push_str(":only-child"),
+            PseudoClass::OnlyOfType => dest.push_str(":only-of-type"),
+            PseudoClass::Empty => dest.push_str(":empty"),
+            PseudoClass::NthChild { step, offset } => nth(dest, "nth-child", step, offset),
+            PseudoClass::NthOfType { step, offset } => nth(dest, "nth-of-type", step, offset),
+            PseudoClass::NthLastChild { step, offset } => nth(dest, "nth-last-child", step, offset),
+            PseudoClass::NthLastOfType { step, offset } => {
+                nth(dest, "nth-last-of-type", step, offset)
+            }
+            PseudoClass::Contains(ref text) => {
+                dest.push_str(":contains(");
+                serialize_string(text, dest).unwrap();
+                dest.push(')');
+            }
+            PseudoClass::ContainsOwn(ref text) => {
+                dest.push_str(":contains-own(");
+                serialize_string(text, dest).unwrap();
+                dest.push(')');
+            }
+            PseudoClass::AttributeNamePrefix(ref prefix) => {
+                dest.push_str(":attr-prefix(");
+                serialize_string(prefix, dest).unwrap();
+                dest.push(')');
+            }
+            PseudoClass::Has(ref selectors) => {
+                dest.push_str(":has(");
+                selector_list_to_css(selectors, dest);
+                dest.push(')');
+            }
+            PseudoClass::Is(ref selectors) => {
+                dest.push_str(":is(");
+                selector_list_to_css(selectors, dest);
+                dest.push(')');
+            }
+            PseudoClass::Where(ref selectors) => {
+                dest.push_str(":where(");
+                selector_list_to_css(selectors, dest);
+                dest.push(')');
+            }
+            #[cfg(feature = "regex_selectors")]
+            PseudoClass::AttributeMatches {
+                ref name,
+                ref pattern,
+            } => {
+                dest.push_str(":attr-matches(");
+                serialize_identifier(name, dest).unwrap();
+                dest.push_str(", ");
+                serialize_string(pattern, dest).unwrap();
+                dest.push(')');
+            }
+            PseudoClass::Custom {
+                ref name,
+                ref argument,
+            } => {
+                dest.push(':');
+                serialize_identifier(name, dest).unwrap();
+
+                if let Some(argument) = argument {
+                    write!(dest, "({})", argument).unwrap();
+                }
+            }
+        }
+    }
+}
+
+fn parts<'c>(
+    components: impl IntoIterator<Item = &'c Component<SelectorImplDescriptor>>,
+    values: &PlaceholderValues,
+) -> Vec<SelectorPart> {
+    let mut parts = Vec::new();
+    let mut unmatchable = false;
+
+    for component in components {
+        // NOTE: `|E` never matches, as the HTML elements are always in a namespace. The prefix
+        // is followed by the type selector, which is negated by the added `:not(*)`.
+        if let Component::ExplicitNoNamespace = component {
+            unmatchable = true;
+            continue;
+        }
+
+        parts.extend(SelectorPart::from_component(component, values));
+
+        if unmatchable {
+            parts.push(SelectorPart::Not(vec![SelectorPart::Universal]));
+            unmatchable = false;
+        }
+    }
+
+    parts
+}
+
+fn selector_list_parts(
+    selector_list: &SelectorList<SelectorImplDescriptor>,
+    values: &PlaceholderValues,
+) -> Vec<Vec<SelectorPart>> {
+    selector_list
+        .0
+        .iter()
+        .map(|s| parts(s.iter_raw_parse_order_from(0), values))
+        .collect()
+}
+
+fn selector_list_to_css(selectors: &[Vec<SelectorPart>], dest: &mut String) {
+    for (idx, parts) in selectors.iter().enumerate() {
+        if idx > 0 {
+            dest.push_str(", ");
+        }
+
+        parts.iter().for_each(|p| p.to_css(dest));
+    }
+}
+
+/// Builds a [`Selector`] from its [parts], so the selectors can be constructed from
+/// the configuration without formatting and escaping the selector text.
+///
+/// The parts are added to the last selector of the selector list, and [`or`] starts the next
+/// selector of the list. The names and the values are used verbatim.
+///
+/// # Example
+/// ```
+/// use lol_html::{
+///     rewrite_str, AttributeOperator, ElementContentHandlers, PseudoClass, RewriteStrSettings,
+///     SelectorBuilder,
+/// };
+/// use std::borrow::Cow;
+///
+/// let title = r#"Say "hi"]"#;
+///
+/// let selector = SelectorBuilder::new()
+///     .local_name("a")
+///     .attribute_value("title", AttributeOperator::Equal, title)
+///     .or()
+///     .class("note")
+///     .pseudo_class(PseudoClass::FirstChild)
+///     .build()
+///     .unwrap();
+///
+/// let html = rewrite_str(
+///     r#"<a title='Say "hi"]'></a><a title="hi"></a><p class="note"></p>"#,
+///     RewriteStrSettings {
+///         element_content_handlers: vec![(
+///             Cow::Owned(selector),
+///             ElementContentHandlers::default().element(|el| {
+///                 el.remove();
+///
+///                 Ok(())
+///             }),
+///         )],
+///         ..RewriteStrSettings::default()
+///     },
+/// )
+/// .unwrap();
+///
+/// assert_eq!(html, r#"<a title="hi"></a><p class="note"></p>"#);
+/// ```
+///
+/// [`Selector`]: struct.Selector.html
+/// [parts]: enum.SelectorPart.html
+/// [`or`]: #method.or
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SelectorBuilder {
+    selectors: Vec<Vec<SelectorPart>>,
+}
+
+impl Default for SelectorBuilder {
+    #[inline]
+    fn default() -> Self {
+        SelectorBuilder {
+            selectors: vec![Vec::new()],
+        }
+    }
+}
+
+impl SelectorBuilder {
+    /// Creates a builder of an empty selector.
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a builder of the selector list with the given parts of its selectors, e.g.
+    /// the ones produced by [`Selector::parts`], so an existing selector can be amended.
+    ///
+    /// [`Selector::parts`]: struct.Selector.html#method.parts
+    #[inline]
+    pub fn from_parts(selectors: Vec<Vec<SelectorPart>>) -> Self {
+        SelectorBuilder { selectors }
+    }
+
+    /// Adds the `part` to the last selector of the list.
+    #[inline]
+    pub fn part(mut self, part: SelectorPart) -> Self {
+        if let Some(selector) = self.selectors.last_mut() {
+            selector.push(part);
+        }
+
+        self
+    }
+
+    /// Adds the tag name to the last selector of the list.
+    #[inline]
+    pub fn local_name(self, name: &str) -> Self {
+        self.part(SelectorPart::LocalName(name.to_owned()))
+    }
+
+    /// Adds the universal selector, `*`, to the last selector of the list.
+    #[inline]
+    pub fn universal(self) -> Self {
+        self.part(SelectorPart::Universal)
+    }
+
+    /// Adds the ID to the last selector of the list.
+    #[inline]
+    pub fn id(self, id: &str) -> Self {
+        self.part(SelectorPart::Id(id.to_owned()))
+    }
+
+    /// Adds the class name to the last selector of the list.
+    #[inline]
+    pub fn class(self, class: &str) -> Self {
+        self.part(SelectorPart::Class(class.to_owned()))
+    }
+
+    /// Adds the attribute presence check to the last selector of the list.
+    #[inline]
+    pub fn attribute(self, name: &str) -> Self {
+        self.part(SelectorPart::AttributeExists(name.to_owned()))
+    }
+
+    /// Adds the attribute value comparison with the default case sensitivity to the last
+    /// selector of the list.
+    #[inline]
+    pub fn attribute_value(self, name: &str, operator: AttributeOperator, value: &str) -> Self {
+        self.part(SelectorPart::Attribute {
+            name: name.to_owned(),
+            operator,
+            value: value.to_owned(),
+            case_sensitivity: AttributeCaseSensitivity::Default,
+        })
+    }
+
+    /// Adds the pseudo-class to the last selector of the list.
+    #[inline]
+    pub fn pseudo_class(self, pseudo_class: PseudoClass) -> Self {
+        self.part(SelectorPart::PseudoClass(pseudo_class))
+    }
+
+    /// Adds the child combinator to the last selector of the list.
+    #[inline]
+    pub fn child(self) -> Self {
+        self.part(SelectorPart::Combinator(SelectorCombinator::Child))
+    }
+
+    /// Adds the descendant combinator to the last selector of the list.
+    #[inline]
+    pub fn descendant(self) -> Self {
+        self.part(SelectorPart::Combinator(SelectorCombinator::Descendant))
+    }
+
+    /// Adds the next-sibling combinator to the last selector of the list.
+    #[inline]
+    pub fn next_sibling(self) -> Self {
+        self.part(SelectorPart::Combinator(SelectorCombinator::NextSibling))
+    }
+
+    /// Adds the subsequent-sibling combinator to the last selector of the list.
+    #[inline]
+    pub fn later_sibling(self) -> Self {
+        self.part(SelectorPart::Combinator(SelectorCombinator::LaterSibling))
+    }
+
+    /// Adds the pseudo-element to the last selector of the list.
+    #[inline]
+    pub fn pseudo_element(self, kind: PseudoElementKind) -> Self {
+        self.part(SelectorPart::PseudoElement(kind))
+    }
+
+    /// Starts the next selector of the list.
+    #[inline]
+    pub fn or(mut self) -> Self {
+        self.selectors.push(Vec::new());
+        self
+    }
+
+    /// Builds the selector.
+    ///
+    /// Fails if the parts don't form a valid selector, e.g. if a selector of the list is empty
+    /// or the parts are not supported in their position.
+    pub fn build(&self) -> Result<Selector, SelectorError> {
+        let mut css = String::new();
+
+        selector_list_to_css(&self.selectors, &mut css);
+
+        css.parse()
+    }
+}
+
+impl Selector {
+    /// Returns the parts of each selector in the selector list.
+    ///
+    /// # Example
+    /// ```
+    /// use lol_html::{Selector, SelectorCombinator, SelectorPart};
+    ///
+    /// let selector: Selector = "ul > li.item".parse().unwrap();
+    ///
+    /// assert_eq!(
+    ///     selector.parts(),
+    ///     [vec![
+    ///         SelectorPart::LocalName("ul".into()),
+    ///         SelectorPart::Combinator(SelectorCombinator::Child),
+    ///         SelectorPart::LocalName("li".into()),
+    ///         SelectorPart::Class("item".into()),
+    ///     ]]
+    /// );
+    /// ```
+    pub fn parts(&self) -> Vec<Vec<SelectorPart>> {
+        selector_list_parts(&self.0, &self.1)
+    }
+
+    /// Returns the tag names the selector list refers to, including the ones in the nested
+    /// selectors of the pseudo-classes. The names are ASCII-lowercased, sorted and deduplicated.
+    ///
+    /// Along with [`attribute_names`], this allows to find out which content a set of handlers
+    /// can match without running a rewrite, e.g. to skip the rewriting of the documents
+    /// that don't have any of the tag names.
+    ///
+    /// # Example
+    /// ```
+    /// use lol_html::Selector;
+    ///
+    /// let selector: Selector = "ul > li.item:not(:has(img)), P".parse().unwrap();
+    ///
+    /// assert_eq!(selector.local_names(), ["img", "li", "p", "ul"]);
+    /// ```
+    ///
+    /// [`attribute_names`]: #method.attribute_names
+    pub fn local_names(&self) -> Vec<String> {
+        self.collect_names(|part| match part {
+            SelectorPart::LocalName(name) => Some(name),
+            _ => None,
+        })
+    }
+
+    /// Returns the names of the attributes the selector list refers to, including `id` and
+    /// `class` for the ID and class selectors and the names in the nested selectors of
+    /// the pseudo-classes. The names are ASCII-lowercased, sorted and deduplicated.
+    ///
+    /// The attribute name prefixes and the attributes inspected by the custom pseudo-classes
+    /// are not included.
+    ///
+    /// # Example
+    /// ```
+    /// use lol_html::Selector;
+    ///
+    /// let selector: Selector = r#"a[HREF^="http:"]:not(.ext), #main"#.parse().unwrap();
+    ///
+    /// assert_eq!(selector.attribute_names(), ["class", "href", "id"]);
+    /// ```
+    pub fn attribute_names(&self) -> Vec<String> {
+        self.collect_names(|part| match part {
+            SelectorPart::Id(_) => Some("id"),
+            SelectorPart::Class(_) => Some("class"),
+            SelectorPart::AttributeExists(name) | SelectorPart::Attribute { name, .. } => {
+                Some(name)
+            }
+            #[cfg(feature = "regex_selectors")]
+            SelectorPart::PseudoClass(PseudoClass::AttributeMatches { name, .. }) => Some(name),
+            _ => None,
+        })
+    }
+
+    fn collect_names(&self, name: impl Fn(&SelectorPart) -> Option<&str>) -> Vec<String> {
+        let mut names = Vec::new();
+
+        visit_selector_list_parts(&self.parts(), &mut |part| {
+            if let Some(n) = name(part) {
+                names.push(n.to_ascii_lowercase());
+            }
+        });
+
+        names.sort();
+        names.dedup();
+        names
+    }
+}
+
+/// Invokes the `visitor` for each part of the selectors, including the parts of the nested
+/// selectors.
+fn visit_selector_list_parts(
+    selectors: &[Vec<SelectorPart>],
+    visitor: &mut dyn FnMut(&SelectorPart),
+) {
+    for part in selectors.iter().flatten() {
+        visit_part(part, visitor);
+    }
+}
+
+fn visit_part(part: &SelectorPart, visitor: &mut dyn FnMut(&SelectorPart)) {
+    visitor(part);
+
+    match part {
+        SelectorPart::Not(parts) => parts.iter().for_each(|p| visit_part(p, visitor)),
+        SelectorPart::PseudoClass(PseudoClass::Has(selectors))
+        | SelectorPart::PseudoClass(PseudoClass::Is(selectors))
+        | SelectorPart::PseudoClass(PseudoClass::Where(selectors)) => {
+            visit_selector_list_parts(selectors, visitor)
+        }
+        _ => (),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::selectors_vm::SelectorTemplate;
+
+    fn parts(selector: &str) -> Vec<Vec<SelectorPart>> {
+        selector.parse::<Selector>().unwrap().parts()
+    }
+
+    #[test]
+    fn selector_parts() {
+        assert_eq!(
+            parts(r#"div#foo.bar[baz] > *:nth-child(2n+1), a[href^="/" i]::text"#),
+            [
+                vec![
+                    SelectorPart::LocalName("div".into()),
+                    SelectorPart::Id("foo".into()),
+                    SelectorPart::Class("bar".into()),
+                    SelectorPart::AttributeExists("baz".into()),
+                    SelectorPart::Combinator(SelectorCombinator::Child),
+                    SelectorPart::Universal,
+                    SelectorPart::PseudoClass(PseudoClass::NthChild { step: 2, offset: 1 }),
+                ],
+                vec![
+                    SelectorPart::LocalName("a".into()),
+                    SelectorPart::Attribute {
+                        name: "href".into(),
+                        operator: AttributeOperator::Prefix,
+                        value: "/".into(),
+                        case_sensitivity: AttributeCaseSensitivity::AsciiCaseInsensitive,
+                    },
+                    SelectorPart::PseudoElement(PseudoElementKind::Text),
+                ],
+            ]
+        );
+
+        assert_eq!(
+            parts("p:not(:has(img, a > b)):last-of-type"),
+            [vec![
+                SelectorPart::LocalName("p".into()),
+                SelectorPart::Not(vec![SelectorPart::PseudoClass(PseudoClass::Has(vec![
+                    vec![SelectorPart::LocalName("img".into())],
+                    vec![
+                        SelectorPart::LocalName("a".into()),
+                        SelectorPart::Combinator(SelectorCombinator::Child),
+                        SelectorPart::LocalName("b".into()),
+                    ],
+                ]))]),
+                SelectorPart::PseudoClass(PseudoClass::LastOfType),
+            ]]
+        );
+    }
+
+    #[test]
+    fn round_trip() {
+        [
+            "*",
+            "div span",
+            "#foo.bar.baz",
+            r#"[foo="bar"], [foo~="bar" s], [foo|=en], [foo$="bar"], [foo*="bar"]"#,
+            ":first-child, :first-of-type, :nth-of-type(-n+3), :nth-last-child(odd)",
+            ":last-child:nth-last-of-type(0n+2)",
+            "div:empty",
+            r#"p:contains("foo"):attr-prefix(data-)"#,
+            r#"li:contains-own("foo")"#,
+            "div:not(.foo) > p:not(:has(img))",
+            ":is(h1, h2.title) a:where([href], :first-child)",
+            "div::comment",
+            "|div",
+        ]
+        .iter()
+        .for_each(|selector| {
+            let parts = parts(selector);
+            let built = SelectorBuilder::from_parts(parts.clone()).build().unwrap();
+
+            assert_eq!(built.parts(), parts, "{}", selector);
+        });
+    }
+
+    #[test]
+    fn names() {
+        let selector: Selector = concat!(
+            r#"DIV#foo > a[href][Title~="x"]:not(.bar) + span:first-child, "#,
+            r#":is(h1, h2) :not(:has(IMG[alt])):attr-prefix(data-)"#
+        )
+        .parse()
+        .unwrap();
+
+        assert_eq!(
+            selector.local_names(),
+            ["a", "div", "h1", "h2", "img", "span"]
+        );
+        assert_eq!(
+            selector.attribute_names(),
+            ["alt", "class", "href", "id", "title"]
+        );
+
+        let selector: Selector = "*::text".parse().unwrap();
+
+        assert!(selector.local_names().is_empty());
+        assert!(selector.attribute_names().is_empty());
+    }
+
+    #[test]
+    fn escaping() {
+        let selector = SelectorBuilder::new()
+            .local_name("my-el")
+            .id("1st item")
+            .class("a.b")
+            .attribute_value("x\"]", AttributeOperator::Equal, "\"]) , *")
+            .build()
+            .unwrap();
+
+        assert_eq!(
+            selector.parts(),
+            [vec![
+                SelectorPart::LocalName("my-el".into()),
+                SelectorPart::Id("1st item".into()),
+                SelectorPart::Class("a.b".into()),
+                SelectorPart::Attribute {
+                    name: "x\"]".into(),
+                    operator: AttributeOperator::Equal,
+                    value: "\"]) , *".into(),
+                    case_sensitivity: AttributeCaseSensitivity::Default,
+                },
+            ]]
+        );
+    }
+
+    #[test]
+    fn builder() {
+        let selector = SelectorBuilder::new()
+            .local_name("ul")
+            .descendant()
+            .universal()
+            .attribute("href")
+            .child()
+            .pseudo_class(PseudoClass::FirstChild)
+            .or()
+            .local_name("p")
+            .pseudo_element(PseudoElementKind::Text)
+            .build()
+            .unwrap();
+
+        assert_eq!(
+            selector.parts(),
+            parts("ul *[href] > :first-child, p::text")
+        );
+    }
+
+    #[test]
+    fn bound_template_parts() {
+        let template: SelectorTemplate = "a[href^={prefix}]".parse().unwrap();
+        let selector = template.bind(&[("prefix", "https://")]).unwrap();
+
+        assert_eq!(selector.parts(), parts(r#"a[href^="https://"]"#));
+    }
+
+    #[test]
+    fn build_errors() {
+        assert!(SelectorBuilder::new().build().is_err());
+        assert!(SelectorBuilder::new().local_name("a").or().build().is_err());
+        assert!(SelectorBuilder::new()
+            .local_name("a")
+            .child()
+            .build()
+            .is_err());
+
+        assert_eq!(
+            SelectorBuilder::new()
+                .pseudo_class(PseudoClass::Has(vec![vec![SelectorPart::Universal]]))
+                .build()
+                .err(),
+            Some(SelectorError::UnsupportedPseudoClassOrElement)
+        );
+    }
+}
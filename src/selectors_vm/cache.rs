@@ -0,0 +1,161 @@
+use super::{Selector, SelectorError};
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// A cache of parsed selectors keyed by the selector string.
+///
+/// Applications that rebuild [`Settings`] from the user configuration for each document
+/// can use the cache to avoid re-parsing identical selectors. Selectors obtained from the cache
+/// share their internal representation, so cloning them is cheap.
+///
+/// Selectors that fail to parse are not cached. The cache is owned by the application, so
+/// the application controls its lifetime and can bound it with [`with_max_entries`].
+///
+/// # Example
+/// ```
+/// use lol_html::{element, rewrite_str, RewriteStrSettings, SelectorCache};
+/// use std::borrow::Cow;
+///
+/// let cache = SelectorCache::new();
+///
+/// for _ in 0..3 {
+///     let selector = cache.get_or_parse("a[href]").unwrap();
+///
+///     let html = rewrite_str(
+///         r#"<a href="/">Home</a>"#,
+///         RewriteStrSettings {
+///             element_content_handlers: vec![(
+///                 Cow::Owned(selector),
+///                 lol_html::ElementContentHandlers::default().element(|el| {
+///                     el.set_attribute("rel", "nofollow")?;
+///                     Ok(())
+///                 }),
+///             )],
+///             ..RewriteStrSettings::default()
+///         },
+///     )
+///     .unwrap();
+///
+///     assert_eq!(html, r#"<a href="/" rel="nofollow">Home</a>"#);
+/// }
+///
+/// assert_eq!(cache.len(), 1);
+/// ```
+///
+/// [`Settings`]: struct.Settings.html
+/// [`with_max_entries`]: #method.with_max_entries
+#[derive(Debug, Default)]
+pub struct SelectorCache {
+    selectors: Mutex<HashMap<Box<str>, Selector>>,
+    max_entries: Option<usize>,
+}
+
+impl SelectorCache {
+    /// Creates a new empty cache with an unlimited number of entries.
+    #[inline]
+    pub fn new() -> Self {
+        SelectorCache::default()
+    }
+
+    /// Creates a new empty cache that holds at most `max_entries` selectors.
+    ///
+    /// Once the cache is full, new selectors are parsed on each request without being cached.
+    #[inline]
+    pub fn with_max_entries(max_entries: usize) -> Self {
+        SelectorCache {
+            selectors: Mutex::default(),
+            max_entries: Some(max_entries),
+        }
+    }
+
+    /// Returns the parsed `selector`, parsing it only if it's not in the cache yet.
+    pub fn get_or_parse(&self, selector: &str) -> Result<Selector, SelectorError> {
+        if let Some(parsed) = self.lock().get(selector) {
+            return Ok(parsed.clone());
+        }
+
+        // NOTE: parse outside of the lock, so concurrent lookups are not blocked by the parser.
+        let parsed: Selector = selector.parse()?;
+        let mut selectors = self.lock();
+
+        if self.max_entries.map_or(true, |max| selectors.len() < max) {
+            selectors
+                .entry(selector.into())
+                .or_insert_with(|| parsed.clone());
+        }
+
+        Ok(parsed)
+    }
+
+    /// Returns the number of the cached selectors.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    /// Returns `true` if the cache contains no selectors.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.lock().is_empty()
+    }
+
+    /// Removes all the selectors from the cache.
+    #[inline]
+    pub fn clear(&self) {
+        self.lock().clear();
+    }
+
+    #[inline]
+    fn lock(&self) -> std::sync::MutexGuard<HashMap<Box<str>, Selector>> {
+        // NOTE: the map is never left in an inconsistent state, so it's safe to
+        // ignore poisoning caused by a panic in another thread.
+        self.selectors
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn caching() {
+        let cache = SelectorCache::new();
+
+        assert!(cache.is_empty());
+
+        cache.get_or_parse("div > span").unwrap();
+        cache.get_or_parse("div > span").unwrap();
+        cache.get_or_parse("#foo").unwrap();
+
+        assert_eq!(cache.len(), 2);
+
+        cache.clear();
+
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn invalid_selectors_are_not_cached() {
+        let cache = SelectorCache::new();
+
+        assert_eq!(
+            cache.get_or_parse("div >").unwrap_err(),
+            SelectorError::DanglingCombinator
+        );
+
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn max_entries() {
+        let cache = SelectorCache::with_max_entries(1);
+
+        cache.get_or_parse("div").unwrap();
+        cache.get_or_parse("span").unwrap();
+        cache.get_or_parse("div").unwrap();
+
+        assert_eq!(cache.len(), 1);
+    }
+}
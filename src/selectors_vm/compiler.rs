@@ -1,20 +1,29 @@
-use super::attribute_matcher::AttributeMatcher;
+use super::attribute_matcher::{AttributeKey, AttributeMatcher};
 use super::program::{
-    AddressRange, ExecutionBranch, Program, Instruction
+    AddressRange, ExecutionBranch, InstrSetIndex, Instruction, Program, RequiredLocalName
 };
 use super::{Ast, AstNode, Expr, AttributeComparisonExpr, OnTagNameExpr, OnAttributesExpr, Predicate, SelectorState};
+use super::{CustomPseudoClass, PseudoClassElement};
+#[cfg(feature = "regex_selectors")]
+use super::parser::AttributeRegex;
 use crate::base::{Bytes, HasReplacementsError};
 use crate::html::LocalName;
 use encoding_rs::Encoding;
+use hashbrown::HashMap;
 use selectors::attr::{ParsedCaseSensitivity, AttrSelectorOperator};
 use std::fmt::Debug;
 use std::hash::Hash;
 use std::iter;
+use std::sync::Arc;
 
 /// An expression using only the tag name of an element.
 pub type CompiledLocalNameExpr = Box<dyn Fn(&SelectorState, &LocalName) -> bool>;
-/// An expression using the attributes of an element.
-pub type CompiledAttributeExpr = Box<dyn Fn(&SelectorState, &AttributeMatcher) -> bool>;
+/// An expression using the attributes of an element. The tag name is available to
+/// the expressions that need the whole element, e.g. the custom pseudo-classes.
+pub type CompiledAttributeExpr = Box<dyn Fn(&SelectorState, &LocalName, &AttributeMatcher) -> bool>;
+
+/// The minimal number of instructions in a set for the set to be indexed by the tag names.
+const MIN_INDEXED_INSTR_SET_LEN: usize = 16;
 
 #[derive(Default)]
 struct ExprSet {
@@ -72,6 +81,9 @@ impl Compilable for Expr<OnTagNameExpr> {
                 *enable_nth_of_type = true;
                 self.compile_expr(move |state, _| state.typed.expect("Counter for type required at this point").is_nth(nth))
             }
+            &OnTagNameExpr::MaxDepth(max_depth) => {
+                self.compile_expr(move |state, _| state.depth <= max_depth)
+            }
         };
 
         exprs.local_name_exprs.push(expr);
@@ -81,8 +93,13 @@ impl Compilable for Expr<OnTagNameExpr> {
 impl Expr<OnAttributesExpr> {
     #[inline]
     pub fn compile_expr<F: Fn(&SelectorState, &AttributeMatcher) -> bool + 'static>(&self, f: F) -> CompiledAttributeExpr {
+        self.compile_element_expr(move |s, _, a| f(s, a))
+    }
+
+    #[inline]
+    pub fn compile_element_expr<F: Fn(&SelectorState, &LocalName, &AttributeMatcher) -> bool + 'static>(&self, f: F) -> CompiledAttributeExpr {
         if self.negation {
-            Box::new(move |s, a| !f(s, a))
+            Box::new(move |s, n, a| !f(s, n, a))
         } else {
             Box::new(f)
         }
@@ -145,6 +162,29 @@ impl Compilable for Expr<OnAttributesExpr> {
                         }
                     })
             }
+
+            OnAttributesExpr::AttributeNamePrefix(prefix) =>
+                compile_literal_lowercase(encoding, prefix)
+                    .map(|prefix| self.compile_expr(move |_, m| m.has_attr_with_name_prefix(&prefix))),
+
+            #[cfg(feature = "regex_selectors")]
+            OnAttributesExpr::AttributeRegex(AttributeRegex { name, regex }) => {
+                let regex = regex.to_owned();
+
+                compile_literal_lowercase(encoding, name)
+                    .map(|name| self.compile_expr(move |_, m| m.has_attr_matching_regex(&name, &regex, encoding)))
+            }
+
+            OnAttributesExpr::Custom(CustomPseudoClass { predicate, .. }) => {
+                let predicate = Arc::clone(predicate);
+
+                Ok(self.compile_element_expr(move |_, local_name, m| {
+                    predicate(&PseudoClassElement::new(
+                        local_name.as_lowercase_string(encoding),
+                        m.decoded_attributes(encoding),
+                    ))
+                }))
+            }
         };
 
         exprs.attribute_exprs.push(expr_result.unwrap_or_else(|_| self.compile_expr(|_, _| false)));
@@ -157,6 +197,7 @@ where
 {
     encoding: &'static Encoding,
     instructions: Box<[Option<Instruction<P>>]>,
+    instr_set_indexes: HashMap<usize, InstrSetIndex>,
     free_space_start: usize,
 }
 
@@ -168,6 +209,7 @@ where
         Compiler {
             encoding,
             instructions: Default::default(),
+            instr_set_indexes: HashMap::default(),
             free_space_start: 0,
         }
     }
@@ -200,6 +242,48 @@ where
         }
     }
 
+    fn required_local_name(&self, predicate: &Predicate) -> RequiredLocalName {
+        for expr in predicate.on_tag_name_exprs.iter().filter(|e| !e.negation) {
+            match &expr.simple_expr {
+                OnTagNameExpr::LocalName(local_name) => {
+                    return match LocalName::from_str_without_replacements(local_name, self.encoding) {
+                        Ok(local_name) => RequiredLocalName::Name(local_name.into_owned()),
+                        Err(_) => RequiredLocalName::Unmatchable,
+                    };
+                }
+                OnTagNameExpr::Unmatchable => return RequiredLocalName::Unmatchable,
+                _ => (),
+            }
+        }
+
+        RequiredLocalName::Any
+    }
+
+    /// Returns the key of an id, a class or an attribute the predicate requires an element
+    /// to have, if any. Only the first of them is used for the prefilter, as that's enough
+    /// to skip most of the non-matching instructions.
+    fn required_attribute(&self, predicate: &Predicate) -> Option<AttributeKey> {
+        predicate
+            .on_attr_exprs
+            .iter()
+            .filter(|e| !e.negation)
+            .find_map(|expr| match &expr.simple_expr {
+                OnAttributesExpr::Id(id) => compile_literal(self.encoding, id)
+                    .ok()
+                    .map(|id| AttributeKey::id(&id)),
+                OnAttributesExpr::Class(class) => compile_literal(self.encoding, class)
+                    .ok()
+                    .map(|class| AttributeKey::class(&class)),
+                OnAttributesExpr::AttributeExists(name)
+                | OnAttributesExpr::AttributeComparisonExpr(AttributeComparisonExpr {
+                    name, ..
+                }) => compile_literal_lowercase(self.encoding, name)
+                    .ok()
+                    .map(|name| AttributeKey::attribute_name(&name)),
+                _ => None,
+            })
+    }
+
     /// Reserves space for a set of nodes, returning the range for the nodes to be placed
     #[inline]
     fn reserve(&mut self, nodes: &[AstNode<P>]) -> AddressRange {
@@ -226,11 +310,27 @@ where
         // we can reference them by range instead of vector of addresses.
         let addr_range = self.reserve(&nodes);
 
+        if nodes.len() >= MIN_INDEXED_INSTR_SET_LEN {
+            let index = InstrSetIndex::new(
+                addr_range.start,
+                nodes.iter().map(|n| {
+                    (
+                        self.required_local_name(&n.predicate),
+                        self.required_attribute(&n.predicate),
+                    )
+                }),
+            );
+
+            self.instr_set_indexes.insert(addr_range.start, index);
+        }
+
         for (node, position) in nodes.into_iter().zip(addr_range.clone()) {
             let branch = ExecutionBranch {
                 matched_payload: node.payload,
                 jumps: self.compile_descendants(node.children, enable_nth_of_type),
                 hereditary_jumps: self.compile_descendants(node.descendants, enable_nth_of_type),
+                next_sibling_jumps: self.compile_descendants(node.next_siblings, enable_nth_of_type),
+                later_sibling_jumps: self.compile_descendants(node.later_siblings, enable_nth_of_type),
             };
 
             self.instructions[position] = Some(self.compile_predicate(&node.predicate, branch, enable_nth_of_type));
@@ -249,10 +349,70 @@ where
             instructions: self.instructions.into_vec().into_iter().map(|o| o.unwrap()).collect(),
             entry_points,
             enable_nth_of_type,
+            instr_set_indexes: self.instr_set_indexes,
+        }
+    }
+}
+
+/// Mirrors the allocation of the node lists by the compiler, matching the lists of `prev_nodes`
+/// with the lists of `nodes` they have been extended to.
+fn map_node_lists<P>(
+    prev_nodes: Option<&[AstNode<P>]>,
+    nodes: &[AstNode<P>],
+    prev_free_space_start: &mut usize,
+    free_space_start: &mut usize,
+    address_map: &mut HashMap<usize, AddressRange>,
+) where
+    P: Hash + Eq,
+{
+    let addr_range = *free_space_start..*free_space_start + nodes.len();
+
+    *free_space_start = addr_range.end;
+
+    if let Some(prev_nodes) = prev_nodes {
+        address_map.insert(*prev_free_space_start, addr_range);
+        *prev_free_space_start += prev_nodes.len();
+    }
+
+    for (i, node) in nodes.iter().enumerate() {
+        let prev_node = prev_nodes.and_then(|n| n.get(i));
+
+        macro_rules! map_descendants {
+            ($field:ident) => {
+                if !node.$field.is_empty() {
+                    let prev_list = prev_node
+                        .map(|n| n.$field.as_slice())
+                        .filter(|l| !l.is_empty());
+
+                    map_node_lists(prev_list, &node.$field, prev_free_space_start, free_space_start, address_map);
+                }
+            };
         }
+
+        map_descendants!(children);
+        map_descendants!(descendants);
+        map_descendants!(next_siblings);
+        map_descendants!(later_siblings);
     }
 }
 
+/// Maps the start addresses of the jumps of the program compiled from `prev_ast` to the jumps
+/// of the program compiled from `ast`, given that `ast` is `prev_ast` with more selectors added
+/// to it.
+///
+/// NOTE: selectors are only ever appended to the node lists of the AST, so the lists of `prev_ast`
+/// are the prefixes of the lists of `ast` and are visited by the compiler in the same order.
+pub fn map_jump_addresses<P>(prev_ast: &Ast<P>, ast: &Ast<P>) -> HashMap<usize, AddressRange>
+where
+    P: PartialEq + Eq + Copy + Debug + Hash,
+{
+    let mut address_map = HashMap::default();
+
+    map_node_lists(Some(&prev_ast.root), &ast.root, &mut 0, &mut 0, &mut address_map);
+
+    address_map
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -350,7 +510,7 @@ mod tests {
         for (input, matching_data) in test_cases.iter() {
             with_start_tag(input, encoding, |local_name, attr_matcher| {
                 let counter = Default::default();
-                let state = SelectorState { cumulative: &counter, typed: None };
+                let state = SelectorState { cumulative: &counter, typed: None, depth: 1 };
                 action(input, matching_data, &state, local_name, attr_matcher);
             });
         }
@@ -370,7 +530,7 @@ mod tests {
             |input, should_match, state, local_name, attr_matcher| {
                 assert!(matches!(instr.try_exec_without_attrs(state, &local_name), TryExecResult::AttributesRequired), "Instruction should not execute without attributes");
 
-                let multi_step_res = instr.complete_exec_with_attrs(&*state, &attr_matcher);
+                let multi_step_res = instr.complete_exec_with_attrs(&*state, &local_name, &attr_matcher);
                 let res = instr.exec(state, &local_name, &attr_matcher);
 
                 assert_eq!(multi_step_res, res);
@@ -426,7 +586,7 @@ mod tests {
             let multi_step_res = match $instr.try_exec_without_attrs($state, &$local_name) {
                 TryExecResult::Branch(b) => Some(b),
                 TryExecResult::Fail => None,
-                TryExecResult::AttributesRequired => $instr.complete_exec_with_attrs(&*$state, &$attr_matcher),
+                TryExecResult::AttributesRequired => $instr.complete_exec_with_attrs(&*$state, &$local_name, &$attr_matcher),
             };
 
             assert_eq!(res, multi_step_res);
@@ -804,6 +964,25 @@ mod tests {
         }
     }
 
+    #[test]
+    #[cfg(feature = "regex_selectors")]
+    fn compiled_attr_regex_expression() {
+        for encoding in ASCII_COMPATIBLE_ENCODINGS.iter() {
+            assert_attr_expr_matches_and_negation_reverses_match(
+                r#":attr-matches(href, "^https?://[a-z]+\\.example/")"#,
+                encoding,
+                &[
+                    ("<a HREF='https://ads.example/banner'>", true),
+                    ("<a href='http://cdn.example/'>", true),
+                    ("<a href='ftp://ads.example/'>", false),
+                    ("<a href='https://ads.example.com/'>", false),
+                    ("<a src='https://ads.example/'>", false),
+                    ("<a>", false),
+                ],
+            );
+        }
+    }
+
     #[test]
     fn generic_expressions() {
         for encoding in ASCII_COMPATIBLE_ENCODINGS.iter() {
@@ -874,6 +1053,111 @@ mod tests {
         );
     }
 
+    #[test]
+    fn indexed_instr_sets() {
+        let selectors = [
+            "div.a", "div.b", "span", "span.x", "p", "h1", "h2", "h3", "ul", "li", "a", "b", "i",
+            "em", ".c", ":not(div)", "my-element", "фу",
+        ];
+
+        let program = compile(&selectors, UTF_8, selectors.len());
+
+        assert!(program.instr_set_indexes.contains_key(&program.entry_points.start));
+
+        for_each_test_case(
+            &[
+                ("<div class='a c'>", vec![0, 14]),
+                ("<span class=x>", vec![2, 3, 15]),
+                ("<My-Element>", vec![15, 16]),
+                ("<фу>", vec![15, 17]),
+                ("<table class=c>", vec![14, 15]),
+            ],
+            UTF_8,
+            |input, expected_payload, state, local_name, attr_matcher| {
+                let addrs = program
+                    .instr_set_addrs(&program.entry_points, &local_name)
+                    .collect::<Vec<_>>();
+
+                let mut sorted_addrs = addrs.to_owned();
+
+                sorted_addrs.sort_unstable();
+                assert_eq!(addrs, sorted_addrs);
+
+                let matched_payload = addrs
+                    .into_iter()
+                    .filter_map(|addr| {
+                        exec_generic_instr!(program.instructions[addr], state, local_name, attr_matcher)
+                    })
+                    .flat_map(|b| b.matched_payload.iter().cloned())
+                    .collect::<HashSet<_>>();
+
+                let (expected_matched_payload, _, _) =
+                    exec_instr_range!(program.entry_points, program, state, local_name, attr_matcher);
+
+                assert_eq!(matched_payload, expected_matched_payload);
+                assert_payload!(matched_payload, expected_payload, selectors, input);
+            },
+        );
+
+        // NOTE: only the instructions that require the tag name of the element and the ones
+        // that don't require any are executed.
+        with_start_tag("<div>", UTF_8, |local_name, _| {
+            assert_eq!(
+                program.instr_set_addrs(&program.entry_points, &local_name).count(),
+                4
+            );
+        });
+    }
+
+    #[test]
+    fn attribute_prefilters() {
+        let selectors = [
+            ".ad", ".banner", "#top", "#Top", "[data-ad]", "[DATA-X=y]", "[foo^=bar]", "div.a",
+            "span#b", ":not(.c)", "[data-ad]:not(.d)", "p", "h1", "h2", "h3", "ul", "li", "a",
+        ];
+
+        let program = compile(&selectors, UTF_8, selectors.len());
+        let index = program.instr_set_index(&program.entry_points).unwrap();
+
+        for_each_test_case(
+            &[
+                ("<div class='ad a'>", vec![0, 7, 9]),
+                ("<span id=top Data-Ad>", vec![2, 4, 9, 10]),
+                ("<p data-x=y class='c banner'>", vec![1, 5, 11]),
+                ("<a foo=barbaz id=Top>", vec![3, 6, 9, 17]),
+                ("<div>", vec![9]),
+            ],
+            UTF_8,
+            |input, expected_payload, state, local_name, attr_matcher| {
+                let matched_payload = program
+                    .instr_set_addrs(&program.entry_points, &local_name)
+                    .filter(|&addr| index.attrs_may_match(addr, &attr_matcher))
+                    .filter_map(|addr| {
+                        exec_generic_instr!(program.instructions[addr], state, local_name, attr_matcher)
+                    })
+                    .flat_map(|b| b.matched_payload.iter().cloned())
+                    .collect::<HashSet<_>>();
+
+                let (expected_matched_payload, _, _) =
+                    exec_instr_range!(program.entry_points, program, state, local_name, attr_matcher);
+
+                assert_eq!(matched_payload, expected_matched_payload);
+                assert_payload!(matched_payload, expected_payload, selectors, input);
+            },
+        );
+
+        // NOTE: an element without attributes doesn't have any of the keys, so only
+        // the instructions without a prefilter are left.
+        with_start_tag("<div>", UTF_8, |local_name, attr_matcher| {
+            let addrs = program
+                .instr_set_addrs(&program.entry_points, &local_name)
+                .filter(|&addr| index.attrs_may_match(addr, &attr_matcher))
+                .collect::<Vec<_>>();
+
+            assert_eq!(addrs, vec![program.entry_points.start + 9]);
+        });
+    }
+
     #[test]
     fn jumps() {
         let selectors = [
@@ -891,7 +1175,7 @@ mod tests {
                 let mut jumps = Vec::default();
                 let mut hereditary_jumps = Vec::default();
                 let counter = Default::default();
-                let state = SelectorState { cumulative: &counter, typed: None };
+                let state = SelectorState { cumulative: &counter, typed: None, depth: 1 };
 
                 with_start_tag($html, UTF_8, |local_name, attr_matcher| {
                     let res = exec_instr_range!($add_range, program, &state, local_name, attr_matcher);
@@ -963,4 +1247,53 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn jump_addresses_mapping() {
+        let mut prev_ast = Ast::default();
+
+        for (idx, selector) in ["div > p", "span", "ul > li + li"].iter().enumerate() {
+            prev_ast.add_selector(&selector.parse().unwrap(), idx);
+        }
+
+        let mut ast = prev_ast.clone();
+
+        for (idx, selector) in ["div > a", "div em", "ul > li ~ li", "b"].iter().enumerate() {
+            ast.add_selector(&selector.parse().unwrap(), idx + 3);
+        }
+
+        let address_map = map_jump_addresses(&prev_ast, &ast);
+        let prev_program = Compiler::new(UTF_8).compile(prev_ast);
+        let program = Compiler::new(UTF_8).compile(ast);
+
+        let jump_ranges = iter::once(prev_program.entry_points.clone()).chain(
+            prev_program.instructions.iter().flat_map(|i| {
+                let branch = &i.associated_branch;
+
+                vec![
+                    branch.jumps.clone(),
+                    branch.hereditary_jumps.clone(),
+                    branch.next_sibling_jumps.clone(),
+                    branch.later_sibling_jumps.clone(),
+                ]
+                .into_iter()
+                .flatten()
+            }),
+        );
+
+        for prev_range in jump_ranges {
+            let range = &address_map[&prev_range.start];
+
+            assert!(range.len() >= prev_range.len());
+
+            for (prev_addr, addr) in prev_range.zip(range.clone()) {
+                assert!(prev_program.instructions[prev_addr]
+                    .associated_branch
+                    .matched_payload
+                    .is_subset(&program.instructions[addr].associated_branch.matched_payload));
+            }
+        }
+
+        assert_eq!(address_map[&prev_program.entry_points.start], program.entry_points);
+    }
 }
@@ -0,0 +1,100 @@
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
+
+/// A predicate of a custom pseudo-class.
+///
+/// See [`Selector::parse_with_pseudo_classes`] for the details.
+///
+/// [`Selector::parse_with_pseudo_classes`]: struct.Selector.html#method.parse_with_pseudo_classes
+pub type PseudoClassPredicate = Arc<dyn Fn(&PseudoClassElement) -> bool + Send + Sync>;
+
+/// An element a custom pseudo-class predicate is evaluated for.
+///
+/// The tag name and the attribute names are ASCII-lowercased, and all of them, as well as
+/// the attribute values, are decoded from the encoding of the document.
+#[derive(Debug)]
+pub struct PseudoClassElement {
+    tag_name: String,
+    attributes: Vec<(String, String)>,
+}
+
+impl PseudoClassElement {
+    #[inline]
+    pub(crate) fn new(tag_name: String, attributes: Vec<(String, String)>) -> Self {
+        PseudoClassElement {
+            tag_name,
+            attributes,
+        }
+    }
+
+    /// Returns the tag name of the element.
+    #[inline]
+    pub fn tag_name(&self) -> &str {
+        &self.tag_name
+    }
+
+    /// Returns the value of the attribute with the `name`, which is matched ASCII
+    /// case-insensitively.
+    #[inline]
+    pub fn get_attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Returns the names and the values of the attributes of the element.
+    #[inline]
+    pub fn attributes(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.attributes.iter().map(|(n, v)| (n.as_str(), v.as_str()))
+    }
+}
+
+/// A custom pseudo-class with the predicate it has been resolved to.
+///
+/// The pseudo-classes are compared by their names, arguments and the identity of
+/// the predicates.
+#[derive(Clone)]
+pub struct CustomPseudoClass {
+    pub name: String,
+    /// The source text of the argument, or `None` for a non-functional pseudo-class.
+    pub argument: Option<String>,
+    pub predicate: PseudoClassPredicate,
+}
+
+impl CustomPseudoClass {
+    #[inline]
+    fn predicate_addr(&self) -> *const u8 {
+        Arc::as_ptr(&self.predicate) as *const u8
+    }
+}
+
+impl PartialEq for CustomPseudoClass {
+    #[inline]
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name
+            && self.argument == other.argument
+            && self.predicate_addr() == other.predicate_addr()
+    }
+}
+
+impl Eq for CustomPseudoClass {}
+
+impl Hash for CustomPseudoClass {
+    #[inline]
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.name.hash(state);
+        self.argument.hash(state);
+        self.predicate_addr().hash(state);
+    }
+}
+
+impl fmt::Debug for CustomPseudoClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CustomPseudoClass")
+            .field("name", &self.name)
+            .field("argument", &self.argument)
+            .finish()
+    }
+}
@@ -1,5 +1,7 @@
 use cssparser::{BasicParseErrorKind, ParseErrorKind};
 use selectors::parser::{SelectorParseError, SelectorParseErrorKind};
+use std::error::Error as StdError;
+use std::fmt;
 use thiserror::Error;
 
 /// A CSS selector parsing error.
@@ -56,6 +58,226 @@ pub enum SelectorError {
     /// CSS syntax in the selector which is yet unsupported.
     #[error("Unsupported syntax in selector.")]
     UnsupportedSyntax,
+
+    /// Invalid regular expression in the `:attr-matches()` pseudo-class.
+    #[error("Invalid regular expression in selector.")]
+    InvalidRegex,
+
+    /// The selector has more compound selectors than allowed by the [`SelectorLimits`].
+    ///
+    /// [`SelectorLimits`]: ../struct.SelectorLimits.html
+    #[error("The selector has more than {0} compound selectors.")]
+    TooManyCompoundSelectors(usize),
+
+    /// The pseudo-classes in the selector are nested deeper than allowed by
+    /// the [`SelectorLimits`].
+    ///
+    /// [`SelectorLimits`]: ../struct.SelectorLimits.html
+    #[error("The pseudo-classes in the selector are nested deeper than {0} levels.")]
+    NestingTooDeep(usize),
+
+    /// The selector has more attribute matchers than allowed by the [`SelectorLimits`].
+    ///
+    /// [`SelectorLimits`]: ../struct.SelectorLimits.html
+    #[error("The selector has more than {0} attribute matchers.")]
+    TooManyAttributeMatchers(usize),
+
+    /// The selector is compiled into more instructions than allowed by the [`SelectorLimits`].
+    ///
+    /// [`SelectorLimits`]: ../struct.SelectorLimits.html
+    #[error("The selector is compiled into more than {0} instructions.")]
+    TooManyInstructions(usize),
+
+    /// The descendant depth limit is too large for the number of the descendant combinators
+    /// in the selector.
+    #[error("The descendant depth limit of {0} is too large for the selector.")]
+    DescendantDepthLimitTooLarge(usize),
+
+    /// The alternatives of the `:is()` and `:where()` pseudo-classes in the selector have more
+    /// combinations than allowed.
+    #[error("The `:is()` and `:where()` alternatives have more than {0} combinations.")]
+    TooManyAlternatives(usize),
+}
+
+impl SelectorError {
+    /// Returns a human-readable list of what the parser expected where the error occurred.
+    pub fn expected(&self) -> &'static [&'static str] {
+        match self {
+            SelectorError::UnexpectedToken => &[
+                "type selector",
+                "`*`",
+                "class selector",
+                "ID selector",
+                "attribute selector",
+                "pseudo-class",
+                "combinator",
+                "`,`",
+            ],
+            SelectorError::UnexpectedEnd => &["identifier", "closing `]` or `)`"],
+            SelectorError::MissingAttributeName => &["attribute name"],
+            SelectorError::EmptySelector => &["selector"],
+            SelectorError::DanglingCombinator => &["selector after the combinator"],
+            SelectorError::UnexpectedTokenInAttribute => &[
+                "`=`",
+                "`~=`",
+                "`|=`",
+                "`^=`",
+                "`$=`",
+                "`*=`",
+                "attribute value",
+                "`i` or `s` flag",
+                "`]`",
+            ],
+            SelectorError::UnsupportedPseudoClassOrElement => {
+                &["supported pseudo-class", "supported pseudo-element"]
+            }
+            SelectorError::NestedNegation => &["simple selector other than `:not()`"],
+            SelectorError::NamespacedSelector => &["selector without a namespace"],
+            SelectorError::InvalidClassName => &["class name"],
+            SelectorError::EmptyNegation => &["selector"],
+            SelectorError::UnsupportedCombinator(_) => &["compound selector"],
+            SelectorError::UnsupportedSyntax => &["selector"],
+            SelectorError::InvalidRegex => &["valid regular expression"],
+            SelectorError::TooManyCompoundSelectors(_)
+            | SelectorError::NestingTooDeep(_)
+            | SelectorError::TooManyAttributeMatchers(_)
+            | SelectorError::TooManyInstructions(_)
+            | SelectorError::TooManyAlternatives(_) => &["less complex selector"],
+            SelectorError::DescendantDepthLimitTooLarge(_) => &["smaller descendant depth limit"],
+        }
+    }
+}
+
+/// A CSS selector parsing error with the details of where the parsing failed. Allows
+/// the tools that accept user-supplied selectors to produce useful diagnostics.
+///
+/// Produced by [`Selector::parse_detailed`].
+///
+/// [`Selector::parse_detailed`]: ../struct.Selector.html#method.parse_detailed
+#[derive(Debug, PartialEq, Clone)]
+pub struct SelectorDiagnostic {
+    pub(crate) kind: SelectorError,
+    pub(crate) offset: Option<usize>,
+    pub(crate) token: Option<String>,
+}
+
+impl SelectorDiagnostic {
+    /// Returns the kind of the error.
+    #[inline]
+    pub fn kind(&self) -> SelectorError {
+        self.kind
+    }
+
+    /// Returns the byte offset in the selector where the parsing failed.
+    ///
+    /// Returns `None` for the errors that are detected once the selector is fully parsed,
+    /// e.g. for a pseudo-class that can't be used in the given position of the selector.
+    #[inline]
+    pub fn offset(&self) -> Option<usize> {
+        self.offset
+    }
+
+    /// Returns the serialized offending token, if there is any at the [`offset`].
+    ///
+    /// [`offset`]: #method.offset
+    #[inline]
+    pub fn token(&self) -> Option<&str> {
+        self.token.as_deref()
+    }
+
+    /// Returns a human-readable list of what the parser expected at the [`offset`].
+    ///
+    /// [`offset`]: #method.offset
+    #[inline]
+    pub fn expected(&self) -> &'static [&'static str] {
+        self.kind.expected()
+    }
+}
+
+impl fmt::Display for SelectorDiagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.kind)?;
+
+        if let Some(offset) = self.offset {
+            write!(f, " At offset {}", offset)?;
+
+            if let Some(ref token) = self.token {
+                write!(f, ", found `{}`", token)?;
+            }
+
+            f.write_str(".")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl StdError for SelectorDiagnostic {
+    #[inline]
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        Some(&self.kind)
+    }
+}
+
+impl From<SelectorDiagnostic> for SelectorError {
+    #[inline]
+    fn from(diagnostic: SelectorDiagnostic) -> Self {
+        diagnostic.kind
+    }
+}
+
+/// An error that occurs when values are bound to a [`SelectorTemplate`].
+///
+/// [`SelectorTemplate`]: ../struct.SelectorTemplate.html
+#[derive(Error, Debug, PartialEq, Clone)]
+pub enum SelectorBindingError {
+    /// No value has been provided for the placeholder.
+    #[error("No value has been provided for the `{0}` placeholder.")]
+    MissingValue(String),
+
+    /// The template doesn't have the placeholder a value has been provided for.
+    #[error("The selector template doesn't have the `{0}` placeholder.")]
+    UnknownPlaceholder(String),
+}
+
+/// An XPath expression parsing error.
+#[derive(Error, Debug, PartialEq, Copy, Clone)]
+pub enum XPathError {
+    /// The expression is empty.
+    #[error("The XPath expression is empty.")]
+    EmptyExpression,
+
+    /// Unexpected character in the expression.
+    #[error("Unexpected character `{0}` in XPath expression.")]
+    UnexpectedCharacter(char),
+
+    /// Unexpected end of the expression.
+    #[error("Unexpected end of XPath expression.")]
+    UnexpectedEnd,
+
+    /// Axes other than `child` and `descendant` are not supported.
+    #[error("Unsupported axis in XPath expression.")]
+    UnsupportedAxis,
+
+    /// Unsupported function in the expression.
+    #[error("Unsupported function in XPath expression.")]
+    UnsupportedFunction,
+
+    /// Predicate that can't be expressed with the supported selectors (e.g. `[@a or @b]`).
+    #[error("Unsupported predicate in XPath expression.")]
+    UnsupportedPredicate,
+
+    /// Absolute location path that doesn't start with the `html` element.
+    #[error("Absolute location paths should start with the `html` element.")]
+    UnsupportedAbsolutePath,
+
+    /// `text()` node test anywhere but the last step of the descendant axis (e.g. `//p//text()`).
+    #[error("The `text()` node test is only supported as the last step of the descendant axis.")]
+    UnsupportedTextNodeTest,
+
+    /// The expression can't be compiled to a supported selector.
+    #[error("{0}")]
+    InvalidSelector(#[from] SelectorError),
 }
 
 impl From<SelectorParseError<'_>> for SelectorError {
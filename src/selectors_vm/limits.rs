@@ -0,0 +1,328 @@
+use super::ast::Ast;
+use super::parser::{NonTSPseudoClassKind, Selector, SelectorImplDescriptor};
+use super::SelectorError;
+use selectors::parser::{Combinator, Component, Selector as GenericSelector, SelectorList};
+
+/// Limits of the complexity of a selector, enforced by [`Selector::parse_with_limits`].
+///
+/// Allows the services that accept user-supplied selectors to reject the ones that are too
+/// expensive to match. All the limits are disabled by default.
+///
+/// # Example
+/// ```
+/// use lol_html::{Selector, SelectorLimits};
+/// use lol_html::errors::SelectorError;
+///
+/// let limits = SelectorLimits {
+///     max_compound_selectors: Some(3),
+///     ..SelectorLimits::default()
+/// };
+///
+/// assert!(Selector::parse_with_limits("ul > li.item", &limits).is_ok());
+///
+/// assert_eq!(
+///     Selector::parse_with_limits("ul > li, p:is(.a, .b)", &limits).unwrap_err(),
+///     SelectorError::TooManyCompoundSelectors(3)
+/// );
+/// ```
+///
+/// [`Selector::parse_with_limits`]: struct.Selector.html#method.parse_with_limits
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SelectorLimits {
+    /// The maximum number of compound selectors in the selector, including the ones in
+    /// the arguments of the `:is()`, `:where()` and `:has()` pseudo-classes. E.g.
+    /// `ul > li, p:is(.a, .b)` has 5 compound selectors: `ul`, `li`, `p`, `.a` and `.b`.
+    pub max_compound_selectors: Option<usize>,
+
+    /// The maximum nesting depth of the pseudo-classes that take selectors as arguments, i.e.
+    /// `:not()`, `:is()`, `:where()` and `:has()`. E.g. `div` has the depth of `0`, `div:not(.a)`
+    /// has the depth of `1` and `div:has(p:not(.a))` has the depth of `2`.
+    pub max_nesting_depth: Option<usize>,
+
+    /// The maximum number of the ID, class and attribute selectors, as well as the attribute
+    /// name prefix and regular expression pseudo-classes, in the selector, including the ones
+    /// in the arguments of pseudo-classes.
+    pub max_attribute_matchers: Option<usize>,
+
+    /// The maximum number of the instructions the selector is compiled into. Unlike the other
+    /// limits, it accounts for the expansion of the `:is()` and `:where()` pseudo-classes, as
+    /// each combination of their alternatives is compiled into a separate branch along with
+    /// the rest of the selector. E.g. `:is(ul, ol) > li` is compiled into 4 instructions:
+    /// `ul`, `ol` and `li` for each of them.
+    pub max_instructions: Option<usize>,
+}
+
+#[derive(Default)]
+struct Complexity {
+    compound_selectors: usize,
+    nesting_depth: usize,
+    attribute_matchers: usize,
+}
+
+impl Complexity {
+    fn add_selector_list(&mut self, list: &SelectorList<SelectorImplDescriptor>, depth: usize) {
+        for selector in list.0.iter() {
+            self.add_selector(selector, depth);
+        }
+    }
+
+    fn add_selector(&mut self, selector: &GenericSelector<SelectorImplDescriptor>, depth: usize) {
+        self.compound_selectors += 1;
+
+        for component in selector.iter_raw_match_order() {
+            self.add_component(component, depth);
+        }
+    }
+
+    fn add_component(&mut self, component: &Component<SelectorImplDescriptor>, depth: usize) {
+        match component {
+            Component::Combinator(Combinator::PseudoElement) => (),
+            Component::Combinator(_) => self.compound_selectors += 1,
+            Component::ID(_)
+            | Component::Class(_)
+            | Component::AttributeInNoNamespaceExists { .. }
+            | Component::AttributeInNoNamespace { .. }
+            | Component::AttributeOther(_)
+            | Component::NonTSPseudoClass(NonTSPseudoClassKind::AttributeNamePrefix(_)) => {
+                self.attribute_matchers += 1
+            }
+            #[cfg(feature = "regex_selectors")]
+            Component::NonTSPseudoClass(NonTSPseudoClassKind::AttributeMatches(_)) => {
+                self.attribute_matchers += 1
+            }
+            Component::Negation(components) => {
+                self.enter(depth);
+
+                for c in components.iter() {
+                    self.add_component(c, depth + 1);
+                }
+            }
+            Component::NonTSPseudoClass(NonTSPseudoClassKind::Is(list))
+            | Component::NonTSPseudoClass(NonTSPseudoClassKind::Where(list))
+            | Component::NonTSPseudoClass(NonTSPseudoClassKind::Has(list)) => {
+                self.enter(depth);
+                self.add_selector_list(&list.0, depth + 1);
+            }
+            _ => (),
+        }
+    }
+
+    #[inline]
+    fn enter(&mut self, depth: usize) {
+        self.nesting_depth = self.nesting_depth.max(depth + 1);
+    }
+}
+
+/// Returns the number of the instructions the selector is compiled into, including the ones of
+/// the arguments of `:has()` that are compiled separately.
+fn instruction_count(selector: &Selector) -> usize {
+    let mut ast = Ast::default();
+
+    ast.add_selector(selector, ());
+
+    let nested = selector
+        .required_descendants()
+        .into_iter()
+        .chain(selector.excluded_descendants())
+        .flatten()
+        .map(|s| instruction_count(&s))
+        .sum::<usize>();
+
+    ast.cumulative_node_count + nested
+}
+
+impl SelectorLimits {
+    pub(super) fn check(&self, selector: &Selector) -> Result<(), SelectorError> {
+        let mut complexity = Complexity::default();
+
+        complexity.add_selector_list(&selector.0, 0);
+
+        let exceeded = |limit: Option<usize>, value| limit.filter(|&max| value > max);
+
+        if let Some(max) = exceeded(self.max_compound_selectors, complexity.compound_selectors) {
+            return Err(SelectorError::TooManyCompoundSelectors(max));
+        }
+
+        if let Some(max) = exceeded(self.max_nesting_depth, complexity.nesting_depth) {
+            return Err(SelectorError::NestingTooDeep(max));
+        }
+
+        if let Some(max) = exceeded(self.max_attribute_matchers, complexity.attribute_matchers) {
+            return Err(SelectorError::TooManyAttributeMatchers(max));
+        }
+
+        // NOTE: the selector is compiled to an AST only if the limit is set.
+        if let Some(max) = self
+            .max_instructions
+            .filter(|&max| instruction_count(selector) > max)
+        {
+            return Err(SelectorError::TooManyInstructions(max));
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::selectors_vm::Selector;
+
+    fn parse(selector: &str, limits: SelectorLimits) -> Result<Selector, SelectorError> {
+        Selector::parse_with_limits(selector, &limits)
+    }
+
+    #[test]
+    fn no_limits() {
+        assert!(parse(
+            "div > p.a#b[c]:not(.d) ~ span::text",
+            SelectorLimits::default()
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn compound_selectors() {
+        let limits = SelectorLimits {
+            max_compound_selectors: Some(3),
+            ..SelectorLimits::default()
+        };
+
+        [
+            "div > p a",
+            "div, p, a",
+            "p:is(.a, .b)",
+            "li::text",
+            "a:not(.b)",
+        ]
+        .iter()
+        .for_each(|s| assert!(parse(s, limits).is_ok(), "{}", s));
+
+        [
+            "div > p a b",
+            "div, p a, b",
+            "p:is(.a, .b, .c)",
+            "div:has(p a span)",
+        ]
+        .iter()
+        .for_each(|s| {
+            assert_eq!(
+                parse(s, limits).unwrap_err(),
+                SelectorError::TooManyCompoundSelectors(3),
+                "{}",
+                s
+            )
+        });
+    }
+
+    #[test]
+    fn nesting_depth() {
+        let limits = SelectorLimits {
+            max_nesting_depth: Some(1),
+            ..SelectorLimits::default()
+        };
+
+        ["div p", "div:not(.a)", "p:is(.a, .b):not(.c)", "div:has(p)"]
+            .iter()
+            .for_each(|s| assert!(parse(s, limits).is_ok(), "{}", s));
+
+        [
+            "div:has(p:not(.a))",
+            "p:is(.a, :not(.b))",
+            "div:has(:is(p, a))",
+        ]
+        .iter()
+        .for_each(|s| {
+            assert_eq!(
+                parse(s, limits).unwrap_err(),
+                SelectorError::NestingTooDeep(1),
+                "{}",
+                s
+            )
+        });
+
+        let limits = SelectorLimits {
+            max_nesting_depth: Some(0),
+            ..SelectorLimits::default()
+        };
+
+        assert_eq!(
+            parse("div:not(.a)", limits).unwrap_err(),
+            SelectorError::NestingTooDeep(0)
+        );
+    }
+
+    #[test]
+    fn attribute_matchers() {
+        let limits = SelectorLimits {
+            max_attribute_matchers: Some(2),
+            ..SelectorLimits::default()
+        };
+
+        [
+            "#a.b",
+            "div[a] span[b]",
+            "div:not(.a) p:attr-prefix(data-)",
+            "div p span",
+        ]
+        .iter()
+        .for_each(|s| assert!(parse(s, limits).is_ok(), "{}", s));
+
+        [
+            "#a.b.c",
+            "[a], [b=c], [d]",
+            "p:is(.a, .b, .c)",
+            "div:has(a[href]):not(.a.b)",
+        ]
+        .iter()
+        .for_each(|s| {
+            assert_eq!(
+                parse(s, limits).unwrap_err(),
+                SelectorError::TooManyAttributeMatchers(2),
+                "{}",
+                s
+            )
+        });
+    }
+
+    #[test]
+    fn instructions() {
+        let limits = SelectorLimits {
+            max_instructions: Some(4),
+            ..SelectorLimits::default()
+        };
+
+        [
+            ":is(ul, ol) > li",
+            "div p span a",
+            "div:has(p a)",
+            "p:where(.a, .b):not(:has(a))",
+        ]
+        .iter()
+        .for_each(|s| assert!(parse(s, limits).is_ok(), "{}", s));
+
+        [
+            ":is(ul, ol) > li > a",
+            ":is(ul, ol) :is(li, p)",
+            "div:has(p a span, b)",
+            "p:is(.a, .b, .c, .d, .e)",
+        ]
+        .iter()
+        .for_each(|s| {
+            assert_eq!(
+                parse(s, limits).unwrap_err(),
+                SelectorError::TooManyInstructions(4),
+                "{}",
+                s
+            )
+        });
+    }
+
+    #[test]
+    fn invalid_selector() {
+        assert_eq!(
+            parse("div >", SelectorLimits::default()).unwrap_err(),
+            SelectorError::DanglingCombinator
+        );
+    }
+}
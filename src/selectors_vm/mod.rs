@@ -1,13 +1,19 @@
 mod ast;
 mod attribute_matcher;
+mod builder;
+mod cache;
 mod compiler;
+mod custom_pseudo_class;
 mod error;
+mod limits;
 mod parser;
 mod program;
 mod stack;
+mod template;
+mod xpath;
 
 use self::program::AddressRange;
-use self::stack::StackDirective;
+use self::stack::{SiblingJumps, StackDirective};
 use crate::html::{LocalName, Namespace};
 use crate::memory::{MemoryLimitExceededError, SharedMemoryLimiter};
 use crate::transform_stream::AuxStartTagInfo;
@@ -15,11 +21,19 @@ use encoding_rs::Encoding;
 
 pub use self::ast::*;
 pub use self::attribute_matcher::AttributeMatcher;
-pub use self::compiler::Compiler;
-pub use self::error::SelectorError;
-pub use self::parser::Selector;
+pub use self::builder::{
+    AttributeCaseSensitivity, AttributeOperator, PseudoClass, SelectorBuilder, SelectorCombinator,
+    SelectorPart,
+};
+pub use self::cache::SelectorCache;
+pub use self::compiler::{map_jump_addresses, Compiler};
+pub use self::custom_pseudo_class::{CustomPseudoClass, PseudoClassElement, PseudoClassPredicate};
+pub use self::error::{SelectorBindingError, SelectorDiagnostic, SelectorError, XPathError};
+pub use self::limits::SelectorLimits;
+pub use self::parser::{NthLast, PseudoElementKind, Selector};
 pub use self::program::{ExecutionBranch, Program, TryExecResult};
 pub use self::stack::{ElementData, Stack, StackItem, ChildCounter};
+pub use self::template::SelectorTemplate;
 
 pub struct MatchInfo<P> {
     pub payload: P,
@@ -69,19 +83,31 @@ struct Bailout<T> {
 pub struct SelectorState<'i> {
     pub cumulative: &'i ChildCounter,
     pub typed: Option<&'i ChildCounter>,
+    /// The nesting level of the element, `1` for the root elements.
+    pub depth: usize,
 }
 
 struct ExecutionCtx<'i, E: ElementData> {
     stack_item: StackItem<'i, E>,
+    /// Jumps left by the preceding siblings of the element.
+    sibling_jumps: Vec<AddressRange>,
+    /// Jumps left by the element for its following siblings.
+    following_sibling_jumps: SiblingJumps,
     with_content: bool,
     ns: Namespace,
 }
 
 impl<'i, E: ElementData> ExecutionCtx<'i, E> {
     #[inline]
-    pub fn new(local_name: LocalName<'i>, ns: Namespace) -> Self {
+    pub fn new(
+        local_name: LocalName<'i>,
+        ns: Namespace,
+        sibling_jumps: Vec<AddressRange>,
+    ) -> Self {
         ExecutionCtx {
             stack_item: StackItem::new(local_name),
+            sibling_jumps,
+            following_sibling_jumps: SiblingJumps::default(),
             with_content: true,
             ns,
         }
@@ -116,12 +142,23 @@ impl<'i, E: ElementData> ExecutionCtx<'i, E> {
                     .push(hereditary_jumps.to_owned());
             }
         }
+
+        // NOTE: void and self-closing elements have siblings as well.
+        if let Some(ref jumps) = branch.next_sibling_jumps {
+            self.following_sibling_jumps.add_next(jumps);
+        }
+
+        if let Some(ref jumps) = branch.later_sibling_jumps {
+            self.following_sibling_jumps.add_later(jumps);
+        }
     }
 
     #[inline]
     pub fn into_owned(self) -> ExecutionCtx<'static, E> {
         ExecutionCtx {
             stack_item: self.stack_item.into_owned(),
+            sibling_jumps: self.sibling_jumps,
+            following_sibling_jumps: self.following_sibling_jumps,
             with_content: self.with_content,
             ns: self.ns,
         }
@@ -155,6 +192,46 @@ impl<E: ElementData> SelectorMatchingVm<E> {
         }
     }
 
+    /// Replaces the program with the one compiled from `ast`, which is `prev_ast` the current
+    /// program has been compiled from with more selectors added to it. The jumps left by
+    /// the elements that have already started are carried over to the new program, so
+    /// the matching of the previously added selectors is not affected.
+    pub fn recompile(
+        &mut self,
+        prev_ast: &Ast<E::MatchPayload>,
+        ast: Ast<E::MatchPayload>,
+        encoding: &'static Encoding,
+    ) {
+        let address_map = map_jump_addresses(prev_ast, &ast);
+
+        self.program = Compiler::new(encoding).compile(ast);
+
+        if self.program.enable_nth_of_type {
+            self.stack.enable_nth_of_type();
+        }
+
+        // NOTE: never panics as all the jumps of the previous program are mapped.
+        self.stack.remap_jumps(|jumps| address_map[&jumps.start].to_owned());
+    }
+
+    /// Removes the payloads that satisfy the `predicate` from the program, so the selectors
+    /// they are associated with no longer match the elements that start afterwards.
+    pub fn discard_payloads(&mut self, mut predicate: impl FnMut(&E::MatchPayload) -> bool) {
+        for instr in self.program.instructions.iter_mut() {
+            instr
+                .associated_branch
+                .matched_payload
+                .retain(|p| !predicate(p));
+        }
+    }
+
+    /// Makes the VM treat the elements with the given names as void in addition to
+    /// the standard void elements.
+    #[inline]
+    pub fn set_custom_void_elements(&mut self, custom_void_elements: Vec<LocalName<'static>>) {
+        self.stack.set_custom_void_elements(custom_void_elements);
+    }
+
     pub fn exec_for_start_tag(
         &mut self,
         local_name: LocalName,
@@ -165,9 +242,10 @@ impl<E: ElementData> SelectorMatchingVm<E> {
 
         self.stack.add_child(&local_name);
 
-        let mut ctx = ExecutionCtx::new(local_name, ns);
+        let sibling_jumps = self.stack.take_sibling_jumps();
+        let mut ctx = ExecutionCtx::new(local_name, ns, sibling_jumps);
 
-        match Stack::get_stack_directive(&ctx.stack_item, ns) {
+        match self.stack.get_stack_directive(&ctx.stack_item, ns) {
             PopImmediately => {
                 ctx.with_content = false;
                 self.exec_without_attrs(ctx, match_handler)
@@ -197,6 +275,30 @@ impl<E: ElementData> SelectorMatchingVm<E> {
         self.stack.current_element_data_mut()
     }
 
+    /// Starts matching a standalone fragment of the content: the end tags of the fragment
+    /// can't close the elements that are currently open. Returns the previous scope that
+    /// should be passed to [`leave_scope`] once the fragment ends.
+    ///
+    /// [`leave_scope`]: #method.leave_scope
+    #[inline]
+    pub fn enter_scope(&mut self) -> usize {
+        self.stack.enter_scope()
+    }
+
+    /// Ends the fragment started by the matching [`enter_scope`] call, passing the data of
+    /// the elements that the fragment has left unclosed to the `unclosed_element_data_handler`.
+    ///
+    /// [`enter_scope`]: #method.enter_scope
+    #[inline]
+    pub fn leave_scope(
+        &mut self,
+        prev_scope: usize,
+        unclosed_element_data_handler: impl FnMut(E),
+    ) {
+        self.stack
+            .leave_scope(prev_scope, unclosed_element_data_handler);
+    }
+
     fn exec_after_immediate_aux_info_request(
         &mut self,
         mut ctx: ExecutionCtx<'static, E>,
@@ -225,11 +327,20 @@ impl<E: ElementData> SelectorMatchingVm<E> {
             match_handler,
         );
 
+        self.exec_sibling_jumps_with_attrs(&attr_matcher, &mut ctx, JumpPtr::default(), match_handler);
+
+        self.complete_execution(ctx)
+    }
+
+    #[inline]
+    fn complete_execution(&mut self, ctx: ExecutionCtx<E>) -> Result<(), MemoryLimitExceededError> {
+        self.stack.add_sibling_jumps(ctx.following_sibling_jumps);
+
         if ctx.with_content {
-            self.stack.push_item(ctx.stack_item)?;
+            self.stack.push_item(ctx.stack_item.into_owned())
+        } else {
+            Ok(())
         }
-
-        Ok(())
     }
 
     fn bailout<T: 'static>(
@@ -257,11 +368,7 @@ impl<E: ElementData> SelectorMatchingVm<E> {
                 match_handler,
             );
 
-            if ctx.with_content {
-                this.stack.push_item(ctx.stack_item)?;
-            }
-
-            Ok(())
+            this.complete_execution(ctx)
         })
     }
 
@@ -288,6 +395,8 @@ impl<E: ElementData> SelectorMatchingVm<E> {
             HereditaryJumpPtr::default(),
             match_handler,
         );
+
+        self.exec_sibling_jumps_with_attrs(attr_matcher, ctx, JumpPtr::default(), match_handler);
     }
 
     fn recover_after_bailout_in_jumps(
@@ -305,6 +414,8 @@ impl<E: ElementData> SelectorMatchingVm<E> {
             HereditaryJumpPtr::default(),
             match_handler,
         );
+
+        self.exec_sibling_jumps_with_attrs(attr_matcher, ctx, JumpPtr::default(), match_handler);
     }
 
     #[inline]
@@ -316,6 +427,19 @@ impl<E: ElementData> SelectorMatchingVm<E> {
         match_handler: &mut dyn FnMut(MatchInfo<E::MatchPayload>),
     ) {
         self.exec_hereditary_jumps_with_attrs(attr_matcher, ctx, recovery_point, match_handler);
+
+        self.exec_sibling_jumps_with_attrs(attr_matcher, ctx, JumpPtr::default(), match_handler);
+    }
+
+    #[inline]
+    fn recover_after_bailout_in_sibling_jumps(
+        &mut self,
+        ctx: &mut ExecutionCtx<'static, E>,
+        attr_matcher: &AttributeMatcher,
+        recovery_point: JumpPtr,
+        match_handler: &mut dyn FnMut(MatchInfo<E::MatchPayload>),
+    ) {
+        self.exec_sibling_jumps_with_attrs(attr_matcher, ctx, recovery_point, match_handler);
     }
 
     fn exec_without_attrs(
@@ -339,13 +463,12 @@ impl<E: ElementData> SelectorMatchingVm<E> {
             return Self::bailout(ctx, b, Self::recover_after_bailout_in_hereditary_jumps);
         }
 
-        if ctx.with_content {
-            self.stack
-                .push_item(ctx.stack_item.into_owned())
-                .map_err(VmError::MemoryLimitExceeded)
-        } else {
-            Ok(())
+        if let Err(b) = self.try_exec_sibling_jumps_without_attrs(&mut ctx, match_handler) {
+            return Self::bailout(ctx, b, Self::recover_after_bailout_in_sibling_jumps);
         }
+
+        self.complete_execution(ctx)
+            .map_err(VmError::MemoryLimitExceeded)
     }
 
     #[inline]
@@ -357,9 +480,11 @@ impl<E: ElementData> SelectorMatchingVm<E> {
         match_handler: &mut dyn FnMut(MatchInfo<E::MatchPayload>),
     ) {
         let state = self.stack.build_state(&ctx.stack_item.local_name);
-        if let Some(branch) =
-            self.program.instructions[addr].complete_exec_with_attrs(&state, &attr_matcher)
-        {
+        if let Some(branch) = self.program.instructions[addr].complete_exec_with_attrs(
+            &state,
+            &ctx.stack_item.local_name,
+            &attr_matcher,
+        ) {
             ctx.add_execution_branch(branch, match_handler);
         }
     }
@@ -371,10 +496,12 @@ impl<E: ElementData> SelectorMatchingVm<E> {
         ctx: &mut ExecutionCtx<E>,
         match_handler: &mut dyn FnMut(MatchInfo<E::MatchPayload>),
     ) -> Result<(), Bailout<usize>> {
-        let start = addr_range.start;
         let state = self.stack.build_state(&ctx.stack_item.local_name);
+        let addrs = self.program.instr_set_addrs(&addr_range, &ctx.stack_item.local_name);
 
-        for addr in addr_range {
+        // NOTE: the recovery point is the position in the addresses of the instruction set
+        // rather than the address offset, as the indexed sets are executed partially.
+        for (i, addr) in addrs.enumerate() {
             match self.program.instructions[addr].try_exec_without_attrs(&state, &ctx.stack_item.local_name) {
                 TryExecResult::Branch(branch) => {
                     ctx.add_execution_branch(branch, match_handler)
@@ -382,7 +509,7 @@ impl<E: ElementData> SelectorMatchingVm<E> {
                 TryExecResult::AttributesRequired => {
                     return Err(Bailout {
                         at_addr: addr,
-                        recovery_point: addr - start + 1,
+                        recovery_point: i + 1,
                     });
                 },
                 _ => ()
@@ -402,7 +529,14 @@ impl<E: ElementData> SelectorMatchingVm<E> {
         match_handler: &mut dyn FnMut(MatchInfo<E::MatchPayload>),
     ) {
         let state = self.stack.build_state(&ctx.stack_item.local_name);
-        for addr in addr_range.start + offset..addr_range.end {
+        let addrs = self.program.instr_set_addrs(addr_range, &ctx.stack_item.local_name);
+        let index = self.program.instr_set_index(addr_range);
+
+        for addr in addrs.skip(offset) {
+            if index.map_or(false, |i| !i.attrs_may_match(addr, attr_matcher)) {
+                continue;
+            }
+
             let instr = &self.program.instructions[addr];
 
             if let Some(branch) = instr.exec(&state, &ctx.stack_item.local_name, attr_matcher) {
@@ -535,6 +669,57 @@ impl<E: ElementData> SelectorMatchingVm<E> {
             }
         }
     }
+
+    fn try_exec_sibling_jumps_without_attrs(
+        &self,
+        ctx: &mut ExecutionCtx<E>,
+        match_handler: &mut dyn FnMut(MatchInfo<E::MatchPayload>),
+    ) -> Result<(), Bailout<JumpPtr>> {
+        // NOTE: the jumps are moved out of the context for the time of the execution, as
+        // the execution mutates the context.
+        let sibling_jumps = std::mem::take(&mut ctx.sibling_jumps);
+        let mut result = Ok(());
+
+        for (i, jumps) in sibling_jumps.iter().enumerate() {
+            if let Err(b) = self.try_exec_instr_set_without_attrs(jumps.clone(), ctx, match_handler) {
+                result = Err(Bailout {
+                    at_addr: b.at_addr,
+                    recovery_point: JumpPtr {
+                        instr_set_idx: i,
+                        offset: b.recovery_point,
+                    },
+                });
+
+                break;
+            }
+        }
+
+        ctx.sibling_jumps = sibling_jumps;
+
+        result
+    }
+
+    fn exec_sibling_jumps_with_attrs(
+        &self,
+        attr_matcher: &AttributeMatcher,
+        ctx: &mut ExecutionCtx<E>,
+        ptr: JumpPtr,
+        match_handler: &mut dyn FnMut(MatchInfo<E::MatchPayload>),
+    ) {
+        let sibling_jumps = std::mem::take(&mut ctx.sibling_jumps);
+
+        // NOTE: find pointed jumps instruction set and execute it with the offset.
+        if let Some(ptr_jumps) = sibling_jumps.get(ptr.instr_set_idx) {
+            self.exec_instr_set_with_attrs(ptr_jumps, attr_matcher, ctx, ptr.offset, match_handler);
+
+            // NOTE: execute remaining jumps instruction sets as usual.
+            for jumps in sibling_jumps.iter().skip(ptr.instr_set_idx + 1) {
+                self.exec_instr_set_with_attrs(jumps, attr_matcher, ctx, 0, match_handler);
+            }
+        }
+
+        ctx.sibling_jumps = sibling_jumps;
+    }
 }
 
 #[cfg(test)]
@@ -623,9 +808,12 @@ mod tests {
             transform_controller: TestTransformController(test_fn),
             output_sink: |_: &[u8]| {},
             preallocated_parsing_buffer_size: 0,
+            parsing_buffer_compaction_watermark: 0,
+            parsing_buffer_shrink_watermark: 0,
             encoding,
             memory_limiter: MemoryLimiter::new_shared(2048),
             strict: true,
+            buffer_script_and_style_text: false,
         });
 
         transform_stream.write(&*html).unwrap();
@@ -979,6 +1167,77 @@ mod tests {
         );
     }
 
+    #[test]
+    fn indexed_entry_points() {
+        let mut vm = create_vm!(&[
+            "h1", "h2", "h3", "h4", "h5", "h6", "ul", "ol", "li", "p", "a", "b", "i", "em",
+            "span[foo=bar]", "span", "*", "span#test",
+        ]);
+
+        exec_for_start_tag_and_assert!(
+            vm,
+            "<div>",
+            Namespace::Html,
+            Expectation {
+                should_bailout: false,
+                should_match_with_content: true,
+                matched_payload: set![16],
+            }
+        );
+
+        exec_for_start_tag_and_assert!(
+            vm,
+            "<span>",
+            Namespace::Html,
+            Expectation {
+                should_bailout: true,
+                should_match_with_content: true,
+                matched_payload: set![15, 16],
+            }
+        );
+
+        exec_for_start_tag_and_assert!(
+            vm,
+            "<span foo=bar id=test>",
+            Namespace::Html,
+            Expectation {
+                should_bailout: true,
+                should_match_with_content: true,
+                matched_payload: set![14, 15, 16, 17],
+            }
+        );
+    }
+
+    #[test]
+    fn prefiltered_entry_points() {
+        let mut vm = create_vm!(&[
+            "h1", "h2", "h3", "h4", "h5", "h6", "ul", "ol", "li", "p", "a", "b", "i", "em",
+            ".ad", "[data-ad]", "*", "div#top", ":not(.c)",
+        ]);
+
+        exec_for_start_tag_and_assert!(
+            vm,
+            "<div data-x class=c>",
+            Namespace::Html,
+            Expectation {
+                should_bailout: true,
+                should_match_with_content: true,
+                matched_payload: set![16],
+            }
+        );
+
+        exec_for_start_tag_and_assert!(
+            vm,
+            "<div id=top class='c ad' DATA-AD>",
+            Namespace::Html,
+            Expectation {
+                should_bailout: true,
+                should_match_with_content: true,
+                matched_payload: set![14, 15, 16, 17],
+            }
+        );
+    }
+
     #[test]
     fn nth_child() {
         let mut vm = create_vm!(&["div:first-child", "div:nth-child(2n+1)"]);
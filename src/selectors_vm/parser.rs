@@ -1,12 +1,32 @@
-use super::SelectorError;
+use super::ast::NthChild;
+use super::template::PlaceholderValues;
+use super::{
+    CustomPseudoClass, PseudoClassPredicate, SelectorDiagnostic, SelectorError, SelectorLimits,
+};
 use crate::html::Namespace;
-use cssparser::{Parser as CssParser, ParserInput, ToCss};
+use cssparser::{
+    serialize_identifier, serialize_string, BasicParseErrorKind, CowRcStr, ParseError,
+    ParseErrorKind, Parser as CssParser, ParserInput, SourceLocation, ToCss, Token,
+};
+#[cfg(feature = "regex_selectors")]
+use regex::Regex;
 use selectors::parser::{
-    Combinator, Component, NonTSPseudoClass, Parser, PseudoElement, SelectorImpl, SelectorList,
-    SelectorParseErrorKind,
+    Combinator, Component, NonTSPseudoClass, Parser, PseudoElement, Selector as GenericSelector,
+    SelectorImpl, SelectorList, SelectorParseError, SelectorParseErrorKind,
 };
 use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::ops::Range;
 use std::str::FromStr;
+use std::sync::Arc;
+
+/// The maximal number of the chains of child combinators the depth-limited descendant
+/// combinators of a selector are expanded into.
+const MAX_DESCENDANT_DEPTH_EXPANSION: usize = 1024;
+
+/// The maximal number of the combinations of the `:is()` and `:where()` alternatives in
+/// a selector.
+const MAX_ALTERNATIVE_COMBINATIONS: usize = 256;
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct SelectorImplDescriptor;
@@ -21,46 +41,470 @@ impl SelectorImpl for SelectorImplDescriptor {
     type BorrowedNamespaceUrl = Namespace;
     type BorrowedLocalName = String;
 
-    type NonTSPseudoClass = NonTSPseudoClassStub;
-    type PseudoElement = PseudoElementStub;
+    type NonTSPseudoClass = NonTSPseudoClassKind;
+    type PseudoElement = PseudoElementKind;
 
     type ExtraMatchingData = ();
 }
 
-#[derive(PartialEq, Eq, Clone, Debug, Hash)]
-pub enum PseudoElementStub {}
+/// A pseudo-element that narrows a selector down to a certain kind of the inner content
+/// of the matched elements.
+#[derive(PartialEq, Eq, Copy, Clone, Debug, Hash)]
+pub enum PseudoElementKind {
+    /// `::text` - text chunks in the inner content of the matched elements.
+    Text,
+    /// `::comment` - comments in the inner content of the matched elements.
+    Comment,
+}
 
-impl ToCss for PseudoElementStub {
-    fn to_css<W: fmt::Write>(&self, _dest: &mut W) -> fmt::Result {
-        match *self {}
+impl ToCss for PseudoElementKind {
+    fn to_css<W: fmt::Write>(&self, dest: &mut W) -> fmt::Result {
+        match *self {
+            PseudoElementKind::Text => dest.write_str("::text"),
+            PseudoElementKind::Comment => dest.write_str("::comment"),
+        }
     }
 }
 
-impl PseudoElement for PseudoElementStub {
+impl PseudoElement for PseudoElementKind {
     type Impl = SelectorImplDescriptor;
 }
 
+/// An attribute name with the regular expression its value is matched against. The expression
+/// is compiled once, when the selector is parsed.
+#[cfg(feature = "regex_selectors")]
+#[derive(Clone, Debug)]
+pub struct AttributeRegex {
+    pub name: String,
+    pub regex: Regex,
+}
+
+#[cfg(feature = "regex_selectors")]
+impl PartialEq for AttributeRegex {
+    #[inline]
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name && self.regex.as_str() == other.regex.as_str()
+    }
+}
+
+#[cfg(feature = "regex_selectors")]
+impl Eq for AttributeRegex {}
+
+#[cfg(feature = "regex_selectors")]
+impl Hash for AttributeRegex {
+    #[inline]
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.name.hash(state);
+        self.regex.as_str().hash(state);
+    }
+}
+
+/// A selector list nested in a pseudo-class. Compared by its serialization, as the parsed
+/// selector lists are not hashable.
+#[derive(Clone, Debug)]
+pub struct NestedSelectorList(pub SelectorList<SelectorImplDescriptor>);
+
+impl PartialEq for NestedSelectorList {
+    #[inline]
+    fn eq(&self, other: &Self) -> bool {
+        self.0.to_css_string() == other.0.to_css_string()
+    }
+}
+
+impl Eq for NestedSelectorList {}
+
+impl Hash for NestedSelectorList {
+    #[inline]
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.to_css_string().hash(state);
+    }
+}
+
+/// A non-standard pseudo-class.
 #[derive(PartialEq, Eq, Clone, Debug, Hash)]
-pub enum NonTSPseudoClassStub {}
+pub enum NonTSPseudoClassKind {
+    /// `:contains("text")` - the text content of the element contains the string. Evaluated by
+    /// the rewriter rather than by the selector matching VM.
+    Contains(String),
+    /// `:contains-own("text")` - the text of the element itself, i.e. without the text of its
+    /// descendant elements, contains the string. Evaluated by the rewriter rather than by
+    /// the selector matching VM.
+    ContainsOwn(String),
+    /// `:has(s)` - the element has a descendant matching the selector list. Evaluated by
+    /// the rewriter rather than by the selector matching VM.
+    Has(NestedSelectorList),
+    /// `:is(s)` - the element matches any of the compound selectors in the selector list.
+    Is(NestedSelectorList),
+    /// `:where(s)` - the same as `:is(s)`, as the specificity of selectors is irrelevant for
+    /// the rewriter.
+    Where(NestedSelectorList),
+    /// `:attr-prefix(data-)` - the element has an attribute whose name starts with the prefix.
+    AttributeNamePrefix(String),
+    /// `:attr-matches(name, "regex")` - the value of the attribute matches the regular expression.
+    #[cfg(feature = "regex_selectors")]
+    AttributeMatches(AttributeRegex),
+    /// A pseudo-class resolved by the user-provided resolver, e.g. `:my-ext(foo)`.
+    Custom(CustomPseudoClass),
+}
 
-impl NonTSPseudoClass for NonTSPseudoClassStub {
+impl NonTSPseudoClass for NonTSPseudoClassKind {
     type Impl = SelectorImplDescriptor;
 
     fn is_active_or_hover(&self) -> bool {
-        match *self {}
+        false
+    }
+}
+
+impl ToCss for NonTSPseudoClassKind {
+    fn to_css<W: fmt::Write>(&self, dest: &mut W) -> fmt::Result {
+        match self {
+            NonTSPseudoClassKind::Contains(text) => {
+                dest.write_str(":contains(")?;
+                serialize_string(text, dest)?;
+                dest.write_str(")")
+            }
+            NonTSPseudoClassKind::ContainsOwn(text) => {
+                dest.write_str(":contains-own(")?;
+                serialize_string(text, dest)?;
+                dest.write_str(")")
+            }
+            NonTSPseudoClassKind::AttributeNamePrefix(prefix) => {
+                dest.write_str(":attr-prefix(")?;
+                serialize_string(prefix, dest)?;
+                dest.write_str(")")
+            }
+            NonTSPseudoClassKind::Has(NestedSelectorList(selector_list)) => {
+                dest.write_str(":has(")?;
+                selector_list.to_css(dest)?;
+                dest.write_str(")")
+            }
+            NonTSPseudoClassKind::Is(NestedSelectorList(selector_list)) => {
+                dest.write_str(":is(")?;
+                selector_list.to_css(dest)?;
+                dest.write_str(")")
+            }
+            NonTSPseudoClassKind::Where(NestedSelectorList(selector_list)) => {
+                dest.write_str(":where(")?;
+                selector_list.to_css(dest)?;
+                dest.write_str(")")
+            }
+            #[cfg(feature = "regex_selectors")]
+            NonTSPseudoClassKind::AttributeMatches(AttributeRegex { name, regex }) => {
+                dest.write_str(":attr-matches(")?;
+                serialize_identifier(name, dest)?;
+                dest.write_str(", ")?;
+                serialize_string(regex.as_str(), dest)?;
+                dest.write_str(")")
+            }
+            NonTSPseudoClassKind::Custom(CustomPseudoClass { name, argument, .. }) => {
+                dest.write_str(":")?;
+                serialize_identifier(name, dest)?;
+
+                match argument {
+                    Some(argument) => write!(dest, "({})", argument),
+                    None => Ok(()),
+                }
+            }
+        }
     }
 }
 
-impl ToCss for NonTSPseudoClassStub {
-    fn to_css<W: fmt::Write>(&self, _dest: &mut W) -> fmt::Result {
-        match *self {}
+/// A selector parsing error kind that extends the standard ones with the errors of
+/// the non-standard pseudo-classes.
+enum CustomParseErrorKind<'i> {
+    Selector(SelectorParseErrorKind<'i>),
+    NestedSelector(SelectorError),
+    #[cfg(feature = "regex_selectors")]
+    InvalidRegex,
+}
+
+impl<'i> From<SelectorParseErrorKind<'i>> for CustomParseErrorKind<'i> {
+    #[inline]
+    fn from(kind: SelectorParseErrorKind<'i>) -> Self {
+        CustomParseErrorKind::Selector(kind)
     }
 }
 
-#[allow(dead_code)]
-struct SelectorsParser;
+fn into_selector_error(err: ParseError<CustomParseErrorKind>) -> SelectorError {
+    let kind = match err.kind {
+        ParseErrorKind::Basic(kind) => ParseErrorKind::Basic(kind),
+        ParseErrorKind::Custom(CustomParseErrorKind::Selector(kind)) => {
+            ParseErrorKind::Custom(kind)
+        }
+        ParseErrorKind::Custom(CustomParseErrorKind::NestedSelector(err)) => return err,
+        #[cfg(feature = "regex_selectors")]
+        ParseErrorKind::Custom(CustomParseErrorKind::InvalidRegex) => {
+            return SelectorError::InvalidRegex
+        }
+    };
+
+    SelectorError::from(SelectorParseError {
+        kind,
+        location: err.location,
+    })
+}
+
+/// Converts the source location reported by the CSS parser to the byte offset in the input.
+// NOTE: the parser counts lines from 0 and columns from 1 in UTF-16 code units.
+fn byte_offset(input: &str, location: SourceLocation) -> usize {
+    let mut line = 0;
+    let mut column = 1;
+    let mut chars = input.char_indices().peekable();
+
+    while let Some((offset, ch)) = chars.next() {
+        if line == location.line && column >= location.column {
+            return offset;
+        }
+
+        match ch {
+            '\r' => {
+                if let Some(&(_, '\n')) = chars.peek() {
+                    chars.next();
+                }
+
+                line += 1;
+                column = 1;
+            }
+            '\n' | '\x0C' => {
+                line += 1;
+                column = 1;
+            }
+            _ => column += ch.len_utf16() as u32,
+        }
+    }
+
+    input.len()
+}
+
+fn into_selector_diagnostic(
+    input: &str,
+    err: ParseError<CustomParseErrorKind>,
+) -> SelectorDiagnostic {
+    let offset = byte_offset(input, err.location);
+
+    // NOTE: the location of the unexpected token errors may point past the token,
+    // so we take the token from the error. For other errors we re-tokenize the input
+    // at the error location.
+    let token = match err.kind {
+        ParseErrorKind::Basic(BasicParseErrorKind::UnexpectedToken(ref token)) => {
+            Some(token.to_css_string())
+        }
+        _ => {
+            let mut input = ParserInput::new(&input[offset..]);
+            let mut css_parser = CssParser::new(&mut input);
+
+            css_parser
+                .next_including_whitespace()
+                .ok()
+                .map(ToCss::to_css_string)
+        }
+    };
+
+    SelectorDiagnostic {
+        kind: into_selector_error(err),
+        offset: Some(offset),
+        token,
+    }
+}
+
+/// A selector with the attribute name wildcards (e.g. `[data-*]`) replaced by the equivalent
+/// `:attr-prefix()` pseudo-classes and the attribute inequality selectors (e.g. `[foo!=bar]`)
+/// replaced by the equivalent negations, which the CSS selectors parser can handle.
+struct DesugaredSelector {
+    selector: String,
+    // NOTE: the ranges of the replaced extensions in the original selector and the ranges of
+    // their replacements in the desugared one, used to map error offsets back.
+    replacements: Vec<(Range<usize>, Range<usize>)>,
+}
+
+impl DesugaredSelector {
+    fn new(selector: &str) -> Self {
+        let mut input = ParserInput::new(selector);
+        let mut css_parser = CssParser::new(&mut input);
+        let mut extensions = Vec::new();
+
+        Self::find_extensions(&mut css_parser, &mut extensions);
 
-impl SelectorsParser {
+        let mut desugared = DesugaredSelector {
+            selector: String::with_capacity(selector.len()),
+            replacements: Vec::with_capacity(extensions.len()),
+        };
+
+        let mut last_end = 0;
+
+        for (range, replacement) in extensions {
+            desugared
+                .selector
+                .push_str(&selector[last_end..range.start]);
+
+            let start = desugared.selector.len();
+
+            desugared.selector.push_str(&replacement);
+
+            last_end = range.end;
+
+            desugared
+                .replacements
+                .push((range, start..desugared.selector.len()));
+        }
+
+        desugared.selector.push_str(&selector[last_end..]);
+
+        desugared
+    }
+
+    fn find_extensions(css_parser: &mut CssParser, extensions: &mut Vec<(Range<usize>, String)>) {
+        loop {
+            let start = css_parser.position().byte_index();
+
+            let token = match css_parser.next_including_whitespace() {
+                Ok(token) => token.clone(),
+                Err(_) => break,
+            };
+
+            match token {
+                Token::SquareBracketBlock => {
+                    let replacement = css_parser.parse_nested_block(|p| {
+                        Ok::<_, ParseError<()>>(Self::desugar_attribute_selector(p))
+                    });
+
+                    if let Ok(Some(replacement)) = replacement {
+                        let end = css_parser.position().byte_index();
+
+                        extensions.push((start..end, replacement));
+                    }
+                }
+                Token::Function(_) | Token::ParenthesisBlock | Token::CurlyBracketBlock => {
+                    let _ = css_parser.parse_nested_block(|p| {
+                        Self::find_extensions(p, extensions);
+
+                        Ok::<_, ParseError<()>>(())
+                    });
+                }
+                _ => (),
+            }
+        }
+    }
+
+    /// Returns the replacement of the attribute selector, if it's an attribute name wildcard or
+    /// an attribute inequality selector.
+    fn desugar_attribute_selector(p: &mut CssParser) -> Option<String> {
+        let name_start = p.position();
+
+        let name = match p.next() {
+            Ok(Token::Ident(name)) => name.clone(),
+            _ => return None,
+        };
+
+        let name_src = p.slice_from(name_start);
+
+        // NOTE: unlike the inequality operator, the wildcard must immediately follow the name.
+        match p.next_including_whitespace().ok().cloned() {
+            Some(Token::Delim('*')) if p.is_exhausted() => {
+                let mut replacement = String::from(":attr-prefix(");
+
+                // NOTE: serialization to a string is always valid, even for the escaped names.
+                let _ = serialize_string(&name, &mut replacement);
+                replacement.push(')');
+
+                Some(replacement)
+            }
+            Some(Token::WhiteSpace(_)) if matches!(p.next(), Ok(Token::Delim('!'))) => {
+                Self::desugar_attribute_inequality(p, name_src)
+            }
+            Some(Token::Delim('!')) => Self::desugar_attribute_inequality(p, name_src),
+            _ => None,
+        }
+    }
+
+    fn desugar_attribute_inequality(p: &mut CssParser, name: &str) -> Option<String> {
+        if !matches!(p.next_including_whitespace(), Ok(Token::Delim('='))) {
+            return None;
+        }
+
+        let value_start = p.position();
+
+        while p.next_including_whitespace().is_ok() {}
+
+        // NOTE: the name and the value are copied as is, so that they, as well as the case
+        // sensitivity flag, are validated by the CSS selectors parser.
+        Some(format!(":not([{}={}])", name, p.slice_from(value_start)))
+    }
+
+    /// Maps the offset in the desugared selector to the offset in the original one.
+    fn original_offset(&self, offset: usize) -> usize {
+        let mut last_ends = (0, 0);
+
+        for (original, replacement) in &self.replacements {
+            if offset < replacement.start {
+                break;
+            }
+
+            if offset < replacement.end {
+                return original.start;
+            }
+
+            last_ends = (original.end, replacement.end);
+        }
+
+        offset - last_ends.1 + last_ends.0
+    }
+}
+
+/// A last-counting pseudo-class, e.g. `:nth-last-child()`. Evaluated by the rewriter once
+/// the parent of the element is closed.
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub(crate) struct NthLast {
+    pub nth: NthChild,
+    pub of_type: bool,
+}
+
+impl NthLast {
+    #[inline]
+    pub fn from_component(component: &Component<SelectorImplDescriptor>) -> Option<Self> {
+        let (nth, of_type) = match *component {
+            Component::LastChild => (NthChild::first(), false),
+            Component::NthLastChild(a, b) => (NthChild::new(a, b), false),
+            Component::LastOfType => (NthChild::first(), true),
+            Component::NthLastOfType(a, b) => (NthChild::new(a, b), true),
+            // NOTE: the `:first-child` and `:first-of-type` parts of these pseudo-classes
+            // are matched by the selector matching VM.
+            Component::OnlyChild => (NthChild::first(), false),
+            Component::OnlyOfType => (NthChild::first(), true),
+            _ => return None,
+        };
+
+        Some(NthLast { nth, of_type })
+    }
+}
+
+#[inline]
+fn is_selector_list_pseudo_class(component: &Component<SelectorImplDescriptor>) -> bool {
+    matches!(
+        component,
+        Component::NonTSPseudoClass(NonTSPseudoClassKind::Is(_))
+            | Component::NonTSPseudoClass(NonTSPseudoClassKind::Where(_))
+    )
+}
+
+#[inline]
+fn is_negated_has(components: &[Component<SelectorImplDescriptor>]) -> bool {
+    match components {
+        [Component::NonTSPseudoClass(NonTSPseudoClassKind::Has(_))] => true,
+        _ => false,
+    }
+}
+
+/// Resolves the custom pseudo-classes by their lowercased names and the source text of
+/// their arguments.
+pub(super) type PseudoClassResolver<'r> = dyn Fn(&str, &str) -> Option<PseudoClassPredicate> + 'r;
+
+#[derive(Default)]
+pub(super) struct SelectorsParser<'r> {
+    pseudo_class_resolver: Option<&'r PseudoClassResolver<'r>>,
+}
+
+impl<'r> SelectorsParser<'r> {
     fn validate_component(
         component: &Component<SelectorImplDescriptor>,
     ) -> Result<(), SelectorError> {
@@ -71,13 +515,15 @@ impl SelectorsParser {
         match component {
             Component::Combinator(combinator) => match combinator {
                 // Supported
-                Combinator::Child | Combinator::Descendant => Ok(()),
+                Combinator::Child
+                | Combinator::Descendant
+                | Combinator::NextSibling
+                | Combinator::LaterSibling
+                | Combinator::PseudoElement => Ok(()),
 
                 // Unsupported
-                Combinator::NextSibling => Err(SelectorError::UnsupportedCombinator('+')),
-                Combinator::LaterSibling => Err(SelectorError::UnsupportedCombinator('~')),
-                Combinator::PseudoElement | Combinator::SlotAssignment => {
-                    unreachable!("Pseudo element combinators should be filtered out at this point")
+                Combinator::SlotAssignment => {
+                    unreachable!("Slot assignment combinators should be filtered out at this point")
                 }
             },
 
@@ -93,25 +539,65 @@ impl SelectorsParser {
             | Component::FirstOfType
             | Component::NthOfType(_, _)
             | Component::AttributeInNoNamespaceExists { .. }
-            | Component::AttributeInNoNamespace { .. } => Ok(()),
+            | Component::AttributeInNoNamespace { .. }
+            | Component::PseudoElement(_) => Ok(()),
 
-            Component::Negation(components) => {
-                components.iter().map(Self::validate_component).collect()
-            }
+            Component::Negation(components) => components
+                .iter()
+                .map(|c| {
+                    // NOTE: text predicates and `:empty` can't be negated, as they are evaluated
+                    // only for the elements that match the rest of the selector. For the same reason,
+                    // `:has()` can be negated only on its own.
+                    if let Component::NonTSPseudoClass(NonTSPseudoClassKind::Contains(_))
+                    | Component::NonTSPseudoClass(NonTSPseudoClassKind::ContainsOwn(_))
+                    | Component::Empty = c
+                    {
+                        Err(SelectorError::UnsupportedPseudoClassOrElement)
+                    } else if NthLast::from_component(c).is_some()
+                        || is_selector_list_pseudo_class(c)
+                    {
+                        Err(SelectorError::UnsupportedPseudoClassOrElement)
+                    } else if let Component::NonTSPseudoClass(NonTSPseudoClassKind::Has(_)) = c {
+                        if components.len() == 1 {
+                            Ok(())
+                        } else {
+                            Err(SelectorError::UnsupportedPseudoClassOrElement)
+                        }
+                    } else {
+                        Self::validate_component(c)
+                    }
+                })
+                .collect(),
 
-            // Unsupported
-            Component::Empty
-            | Component::Host(_)
+            // NOTE: the position of text predicates, `:empty` and last-counting pseudo-classes
+            // is validated separately.
+            Component::NonTSPseudoClass(NonTSPseudoClassKind::Contains(_))
+            | Component::NonTSPseudoClass(NonTSPseudoClassKind::ContainsOwn(_))
+            | Component::Empty
             | Component::LastChild
             | Component::LastOfType
             | Component::NthLastChild(_, _)
             | Component::NthLastOfType(_, _)
             | Component::OnlyChild
-            | Component::OnlyOfType
+            | Component::OnlyOfType => Ok(()),
+
+            Component::NonTSPseudoClass(NonTSPseudoClassKind::AttributeNamePrefix(_))
+            | Component::NonTSPseudoClass(NonTSPseudoClassKind::Custom(_)) => Ok(()),
+
+            // NOTE: the nested selector lists are validated when parsed.
+            Component::NonTSPseudoClass(NonTSPseudoClassKind::Is(_))
+            | Component::NonTSPseudoClass(NonTSPseudoClassKind::Where(_)) => Ok(()),
+
+            // NOTE: the position of `:has()` is validated separately.
+            Component::NonTSPseudoClass(NonTSPseudoClassKind::Has(_)) => Ok(()),
+
+            #[cfg(feature = "regex_selectors")]
+            Component::NonTSPseudoClass(NonTSPseudoClassKind::AttributeMatches(_)) => Ok(()),
+
+            // Unsupported
+            Component::Host(_)
             | Component::Root
             | Component::Scope
-            | Component::PseudoElement(_)
-            | Component::NonTSPseudoClass(_)
             | Component::Slotted(_) => Err(SelectorError::UnsupportedPseudoClassOrElement),
 
             Component::DefaultNamespace(_)
@@ -124,7 +610,47 @@ impl SelectorsParser {
         selector_list: SelectorList<SelectorImplDescriptor>,
     ) -> Result<SelectorList<SelectorImplDescriptor>, SelectorError> {
         for selector in selector_list.0.iter() {
+            let mut in_rightmost_compound = !selector.has_pseudo_element();
+            let mut alternative_combinations = 1usize;
+
             for component in selector.iter_raw_match_order() {
+                match component {
+                    Component::Combinator(_) => in_rightmost_compound = false,
+                    // NOTE: a compound selector is expanded into a branch for each combination
+                    // of its alternatives, and each of the branches has its own copy of the rest
+                    // of the selector, so the number of the combinations in the whole selector
+                    // is bounded.
+                    Component::NonTSPseudoClass(NonTSPseudoClassKind::Is(alternatives))
+                    | Component::NonTSPseudoClass(NonTSPseudoClassKind::Where(alternatives)) => {
+                        alternative_combinations =
+                            alternative_combinations.saturating_mul((alternatives.0).0.len());
+
+                        if alternative_combinations > MAX_ALTERNATIVE_COMBINATIONS {
+                            return Err(SelectorError::TooManyAlternatives(
+                                MAX_ALTERNATIVE_COMBINATIONS,
+                            ));
+                        }
+                    }
+                    // NOTE: text predicates, `:empty` and `:has()` are supported only for
+                    // the elements that are the subject of the selector and can't be combined
+                    // with pseudo-elements.
+                    Component::NonTSPseudoClass(NonTSPseudoClassKind::Contains(_))
+                    | Component::NonTSPseudoClass(NonTSPseudoClassKind::ContainsOwn(_))
+                    | Component::NonTSPseudoClass(NonTSPseudoClassKind::Has(_))
+                    | Component::Empty
+                        if !in_rightmost_compound =>
+                    {
+                        return Err(SelectorError::UnsupportedPseudoClassOrElement);
+                    }
+                    Component::Negation(c) if !in_rightmost_compound && is_negated_has(c) => {
+                        return Err(SelectorError::UnsupportedPseudoClassOrElement);
+                    }
+                    c if !in_rightmost_compound && NthLast::from_component(c).is_some() => {
+                        return Err(SelectorError::UnsupportedPseudoClassOrElement);
+                    }
+                    _ => (),
+                }
+
                 Self::validate_component(component)?;
             }
         }
@@ -132,20 +658,285 @@ impl SelectorsParser {
         Ok(selector_list)
     }
 
+    fn parse_has<'i, 't>(
+        &self,
+        arguments: &mut CssParser<'i, 't>,
+    ) -> Result<NonTSPseudoClassKind, ParseError<'i, CustomParseErrorKind<'i>>> {
+        let selector_list = SelectorList::parse(self, arguments)?;
+
+        // NOTE: the nested selectors are matched against the descendants of the candidate
+        // elements by the rewriter, so they can't have deferred predicates of their own.
+        let has_deferred_predicates = selector_list.0.iter().any(|s| {
+            s.iter_raw_match_order().any(|c| match c {
+                Component::NonTSPseudoClass(NonTSPseudoClassKind::Contains(_))
+                | Component::NonTSPseudoClass(NonTSPseudoClassKind::ContainsOwn(_))
+                | Component::NonTSPseudoClass(NonTSPseudoClassKind::Has(_))
+                | Component::Empty => true,
+                Component::Negation(c) => is_negated_has(c),
+                c => NthLast::from_component(c).is_some(),
+            })
+        });
+
+        if has_deferred_predicates {
+            return Err(
+                arguments.new_custom_error(CustomParseErrorKind::NestedSelector(
+                    SelectorError::UnsupportedPseudoClassOrElement,
+                )),
+            );
+        }
+
+        Self::validate(selector_list)
+            .map(|s| NonTSPseudoClassKind::Has(NestedSelectorList(s)))
+            .map_err(|e| arguments.new_custom_error(CustomParseErrorKind::NestedSelector(e)))
+    }
+
+    /// Validates an alternative of the `:is()` or `:where()` pseudo-class.
+    fn validate_alternative(
+        selector: &GenericSelector<SelectorImplDescriptor>,
+    ) -> Result<(), SelectorError> {
+        // NOTE: the alternatives are expanded into the compound selector that contains
+        // the pseudo-class, so they should be compound selectors themselves and can't have
+        // the predicates evaluated by the rewriter.
+        if selector.has_pseudo_element() {
+            return Err(SelectorError::UnsupportedPseudoClassOrElement);
+        }
+
+        for component in selector.iter_raw_match_order() {
+            match component {
+                Component::Combinator(Combinator::Child) => {
+                    return Err(SelectorError::UnsupportedCombinator('>'))
+                }
+                Component::Combinator(Combinator::Descendant) => {
+                    return Err(SelectorError::UnsupportedCombinator(' '))
+                }
+                Component::Combinator(Combinator::NextSibling) => {
+                    return Err(SelectorError::UnsupportedCombinator('+'))
+                }
+                Component::Combinator(Combinator::LaterSibling) => {
+                    return Err(SelectorError::UnsupportedCombinator('~'))
+                }
+                Component::NonTSPseudoClass(NonTSPseudoClassKind::Contains(_))
+                | Component::NonTSPseudoClass(NonTSPseudoClassKind::ContainsOwn(_))
+                | Component::NonTSPseudoClass(NonTSPseudoClassKind::Has(_))
+                | Component::Empty => return Err(SelectorError::UnsupportedPseudoClassOrElement),
+                Component::Negation(c) if is_negated_has(c) => {
+                    return Err(SelectorError::UnsupportedPseudoClassOrElement)
+                }
+                c if NthLast::from_component(c).is_some() || is_selector_list_pseudo_class(c) => {
+                    return Err(SelectorError::UnsupportedPseudoClassOrElement)
+                }
+                c => Self::validate_component(c)?,
+            }
+        }
+
+        Ok(())
+    }
+
+    fn parse_selector_list_pseudo_class<'i, 't>(
+        &self,
+        arguments: &mut CssParser<'i, 't>,
+    ) -> Result<NestedSelectorList, ParseError<'i, CustomParseErrorKind<'i>>> {
+        let selector_list = SelectorList::parse(self, arguments)?;
+
+        match selector_list
+            .0
+            .iter()
+            .try_for_each(Self::validate_alternative)
+        {
+            Ok(()) => Ok(NestedSelectorList(selector_list)),
+            Err(e) => Err(arguments.new_custom_error(CustomParseErrorKind::NestedSelector(e))),
+        }
+    }
+
+    #[cfg(feature = "regex_selectors")]
+    fn parse_attr_matches<'i, 't>(
+        arguments: &mut CssParser<'i, 't>,
+    ) -> Result<NonTSPseudoClassKind, ParseError<'i, CustomParseErrorKind<'i>>> {
+        let name = arguments.expect_ident()?.to_ascii_lowercase();
+
+        arguments.expect_comma()?;
+
+        let location = arguments.current_source_location();
+        let pattern: &str = arguments.expect_string()?;
+
+        match Regex::new(pattern) {
+            Ok(regex) => Ok(NonTSPseudoClassKind::AttributeMatches(AttributeRegex {
+                name,
+                regex,
+            })),
+            Err(_) => Err(location.new_custom_error(CustomParseErrorKind::InvalidRegex)),
+        }
+    }
+
     #[inline]
     pub fn parse(selector: &str) -> Result<SelectorList<SelectorImplDescriptor>, SelectorError> {
-        let mut input = ParserInput::new(selector);
+        Self::parse_detailed(selector).map_err(SelectorError::from)
+    }
+
+    #[inline]
+    pub fn parse_detailed(
+        selector: &str,
+    ) -> Result<SelectorList<SelectorImplDescriptor>, SelectorDiagnostic> {
+        SelectorsParser::default().parse_list(selector)
+    }
+
+    #[inline]
+    pub fn parse_with_resolver(
+        selector: &str,
+        resolver: &'r PseudoClassResolver<'r>,
+    ) -> Result<SelectorList<SelectorImplDescriptor>, SelectorError> {
+        SelectorsParser {
+            pseudo_class_resolver: Some(resolver),
+        }
+        .parse_list(selector)
+        .map_err(SelectorError::from)
+    }
+
+    fn parse_list(
+        &self,
+        selector: &str,
+    ) -> Result<SelectorList<SelectorImplDescriptor>, SelectorDiagnostic> {
+        let desugared = DesugaredSelector::new(selector);
+        let mut input = ParserInput::new(&desugared.selector);
         let mut css_parser = CssParser::new(&mut input);
 
-        SelectorList::parse(&Self, &mut css_parser)
-            .map_err(SelectorError::from)
-            .and_then(Self::validate)
+        SelectorList::parse(self, &mut css_parser)
+            .map_err(|err| {
+                let mut diagnostic = into_selector_diagnostic(&desugared.selector, err);
+
+                diagnostic.offset = diagnostic.offset.map(|o| desugared.original_offset(o));
+                diagnostic
+            })
+            .and_then(|list| {
+                Self::validate(list).map_err(|kind| SelectorDiagnostic {
+                    kind,
+                    offset: None,
+                    token: None,
+                })
+            })
+    }
+}
+
+impl SelectorsParser<'_> {
+    fn resolve_custom_pseudo_class(
+        &self,
+        name: &str,
+        argument: Option<&str>,
+    ) -> Option<NonTSPseudoClassKind> {
+        let resolver = self.pseudo_class_resolver?;
+        let name = name.to_ascii_lowercase();
+
+        resolver(&name, argument.unwrap_or("")).map(|predicate| {
+            NonTSPseudoClassKind::Custom(CustomPseudoClass {
+                name,
+                argument: argument.map(str::to_owned),
+                predicate,
+            })
+        })
     }
 }
 
-impl<'i> Parser<'i> for SelectorsParser {
+impl<'i> Parser<'i> for SelectorsParser<'_> {
     type Impl = SelectorImplDescriptor;
-    type Error = SelectorParseErrorKind<'i>;
+    type Error = CustomParseErrorKind<'i>;
+
+    fn parse_pseudo_element(
+        &self,
+        location: SourceLocation,
+        name: CowRcStr<'i>,
+    ) -> Result<PseudoElementKind, ParseError<'i, CustomParseErrorKind<'i>>> {
+        if name.eq_ignore_ascii_case("text") {
+            Ok(PseudoElementKind::Text)
+        } else if name.eq_ignore_ascii_case("comment") {
+            Ok(PseudoElementKind::Comment)
+        } else {
+            Err(
+                location.new_custom_error(SelectorParseErrorKind::UnsupportedPseudoClassOrElement(
+                    name,
+                )),
+            )
+        }
+    }
+
+    fn parse_non_ts_pseudo_class(
+        &self,
+        location: SourceLocation,
+        name: CowRcStr<'i>,
+    ) -> Result<NonTSPseudoClassKind, ParseError<'i, CustomParseErrorKind<'i>>> {
+        match self.resolve_custom_pseudo_class(&name, None) {
+            Some(pseudo_class) => Ok(pseudo_class),
+            None => Err(location.new_custom_error(
+                SelectorParseErrorKind::UnsupportedPseudoClassOrElement(name),
+            )),
+        }
+    }
+
+    fn parse_non_ts_functional_pseudo_class<'t>(
+        &self,
+        name: CowRcStr<'i>,
+        arguments: &mut CssParser<'i, 't>,
+    ) -> Result<NonTSPseudoClassKind, ParseError<'i, CustomParseErrorKind<'i>>> {
+        if name.eq_ignore_ascii_case("contains") {
+            let text: &str = arguments.expect_ident_or_string()?;
+
+            return Ok(NonTSPseudoClassKind::Contains(text.to_owned()));
+        }
+
+        if name.eq_ignore_ascii_case("contains-own") {
+            let text: &str = arguments.expect_ident_or_string()?;
+
+            return Ok(NonTSPseudoClassKind::ContainsOwn(text.to_owned()));
+        }
+
+        if name.eq_ignore_ascii_case("attr-prefix") {
+            let prefix: &str = arguments.expect_ident_or_string()?;
+
+            return Ok(NonTSPseudoClassKind::AttributeNamePrefix(
+                prefix.to_ascii_lowercase(),
+            ));
+        }
+
+        if name.eq_ignore_ascii_case("has") {
+            return self.parse_has(arguments);
+        }
+
+        if name.eq_ignore_ascii_case("is") {
+            return self
+                .parse_selector_list_pseudo_class(arguments)
+                .map(NonTSPseudoClassKind::Is);
+        }
+
+        if name.eq_ignore_ascii_case("where") {
+            return self
+                .parse_selector_list_pseudo_class(arguments)
+                .map(NonTSPseudoClassKind::Where);
+        }
+
+        #[cfg(feature = "regex_selectors")]
+        {
+            if name.eq_ignore_ascii_case("attr-matches") {
+                return Self::parse_attr_matches(arguments);
+            }
+        }
+
+        if self.pseudo_class_resolver.is_some() {
+            let start = arguments.position();
+
+            while arguments.next_including_whitespace_and_comments().is_ok() {}
+
+            let argument = arguments.slice_from(start).trim();
+
+            if let Some(pseudo_class) = self.resolve_custom_pseudo_class(&name, Some(argument)) {
+                return Ok(pseudo_class);
+            }
+        }
+
+        Err(
+            arguments.new_custom_error(SelectorParseErrorKind::UnsupportedPseudoClassOrElement(
+                name,
+            )),
+        )
+    }
 }
 
 /// Parsed CSS selector.
@@ -164,6 +955,9 @@ impl<'i> Parser<'i> for SelectorsParser {
 /// let selector: Selector = "#foo".parse().unwrap();
 /// ```
 ///
+/// Selectors can also be constructed structurally with [`SelectorBuilder`], without escaping
+/// the names and values, and the parsed selectors can be introspected with [`parts`].
+///
 /// # Supported selector
 ///
 /// Currently the rewriter supports the following CSS selectors:
@@ -190,19 +984,602 @@ impl<'i> Parser<'i> for SelectorsParser {
 /// <code>E[foo&#124;="en"]</code> | an `E` element whose foo attribute value is a hyphen-separated list of values beginning with `"en"`                         |
 /// `E F`                          | an `F` element descendant of an `E` element                                                                                 |
 /// `E > F`                        | an `F` element child of an `E` element                                                                                      |
+/// `E + F`                        | an `F` element immediately preceded by an `E` element                                                                       |
+/// `E ~ F`                        | an `F` element preceded by an `E` element                                                                                   |
+/// `E::text`                      | text chunks in the inner content of an `E` element                                                                          |
+/// `E::comment`                   | comments in the inner content of an `E` element                                                                             |
+/// `E:nth-last-child(n)`          | an `E` element, the n-th child of its parent, counting from the last one (see below)                                        |
+/// `E:last-child`                 | an `E` element, last child of its parent (see below)                                                                        |
+/// `E:nth-last-of-type(n)`        | an `E` element, the n-th sibling of its type, counting from the last one (see below)                                        |
+/// `E:last-of-type`               | an `E` element, last sibling of its type (see below)                                                                        |
+/// `E:only-child`                 | an `E` element, only child of its parent (see below)                                                                        |
+/// `E:only-of-type`               | an `E` element, only sibling of its type (see below)                                                                        |
+/// `E:contains("foo")`            | an `E` element whose text content contains the substring `"foo"` (non-standard, see below)                                  |
+/// `E:contains-own("foo")`        | an `E` element whose own text, excluding the text of its descendants, contains `"foo"` (non-standard, see below)            |
+/// `E:empty`                      | an `E` element that has no children, including text (see below)                                                            |
+/// `E:attr-prefix(data-)`         | an `E` element with an attribute whose name begins with `data-` (non-standard)                                              |
+/// `E[data-*]`                    | an `E` element with an attribute whose name begins with `data-` (non-standard)                                              |
+/// `E[foo!="bar"]`                | an `E` element without a foo attribute or whose foo attribute value is not equal to `"bar"` (non-standard, see below)       |
+/// `E:has(s)`                     | an `E` element that has a descendant matching the selector `s` (see below)                                                  |
+/// `E:not(:has(s))`               | an `E` element that has no descendants matching the selector `s` (see below)                                                |
+/// `E:is(s)`, `E:where(s)`        | an `E` element that matches any of the compound selectors in the list `s`                                                   |
+/// `E:attr-matches(foo, "^b.r")`  | an `E` element whose foo attribute value matches the regular expression `"^b.r"` (non-standard, requires `regex_selectors`) |
+/// `E:my-ext(foo)`                | an `E` element matched by a user-defined predicate (non-standard, see below)                                                |
+///
+/// # Pseudo-elements
 ///
+/// A selector that ends with the `::text` pseudo-element scopes [element content handlers] to
+/// the textual content of the matched elements: only the text handler associated with such a
+/// selector is invoked, while element and comment handlers are ignored. E.g.
+/// `text!("p.description::text", handler)` invokes the handler only for the text inside
+/// `<p class="description">` elements, and in a selector list like `"h1, p::text"` element
+/// handlers fire only for `<h1>` elements.
+///
+/// Similarly, a selector that ends with the `::comment` pseudo-element scopes the handlers to
+/// the comments in the inner content of the matched elements. Thus, a single selector list can
+/// route the handlers of one [`ElementContentHandlers`] to the elements themselves, their text
+/// or their comments, e.g. with `"h1, div.note::comment, p::text"` element handlers fire only
+/// for `<h1>` elements, while comment handlers additionally fire for the comments inside
+/// `<div class="note">` elements and text handlers for the text inside `<p>` elements.
+///
+/// # Text predicates
+///
+/// The non-standard `:contains("text")` pseudo-class matches elements whose text content contains
+/// the given string, e.g. `element!("div.banner:contains(\"Sale\")", handler)`. The text is
+/// compared case-sensitively and character references in it are not decoded. Since the text
+/// content is known only once the end tag of the element is reached, the rewriter holds back
+/// the input starting from the start tag of such an element until the predicate is resolved.
+/// Only the first [`max_lookahead`] bytes (64KiB by default) of the text content are inspected.
+/// Once more than that is held back, the elements with unresolved predicates are considered not
+/// matching. The held back input is counted against the [`max_allowed_memory_usage`] limit and
+/// the unresolved predicates are rejected in the same way once the limit is reached, so the held
+/// back input never takes more memory than the smaller of the two bounds. With `max_lookahead`
+/// set to `None` the held back input is bounded by the memory limit only.
+///
+/// The `:contains-own("text")` pseudo-class is evaluated in the same way, but only the text
+/// of the element itself, i.e. without the text of its descendant elements, is inspected. E.g.
+/// `p:contains-own("Subscribe")` matches `<p>Subscribe <a>here</a></p>`, but not
+/// `<p><b>Subscribe</b></p>`. The text chunks separated by the descendant elements are
+/// concatenated.
+///
+/// The pseudo-classes are supported only in the rightmost compound selector and can't be used
+/// with pseudo-elements or inside `:not()`. If an element matches several selectors of a selector
+/// list, only the predicates of the first of them are evaluated.
+///
+/// # Last-counting pseudo-classes
+///
+/// The position of an element among its siblings counting from the last one is known only once
+/// the parent of the element is closed, so, like for the text predicates, the rewriter holds
+/// back the input starting from the start tag of an element matched by the rest of the selector
+/// until the parent's end tag, under the same [`max_lookahead`] bound. This allows rules like
+/// "remove the last two list items" (`li:nth-last-child(-n+2)`). The elements whose parent is not
+/// closed once the bound is reached are considered not matching, the elements whose parent is not
+/// closed by the end of the input are resolved as if the parent was closed there.
+///
+/// `:only-child` and `:only-of-type` are resolved in the same way, as the combinations of
+/// `:first-child` and `:last-child`, and `:first-of-type` and `:last-of-type` respectively.
+/// Only the candidate elements that are the first among their siblings are held back.
+///
+/// The pseudo-classes are supported only in the rightmost compound selector and can't be used
+/// with pseudo-elements or inside `:not()`.
+///
+/// # Empty elements
+///
+/// `:empty` matches elements that have neither child elements nor text, while comments are
+/// ignored. Since the emptiness of an element is known only once its first child or its end tag
+/// is reached, the input is held back in the same way as for the text predicates and under
+/// the same [`max_lookahead`] bound. The pseudo-class is supported only in the rightmost compound selector
+/// and can't be used with pseudo-elements or inside `:not()`.
+///
+/// # Custom pseudo-classes
+///
+/// The selectors parsed with [`parse_with_pseudo_classes`] can have custom pseudo-classes, e.g.
+/// `:my-ext(foo)`, which are resolved by the user-provided resolver to the predicates invoked with
+/// the tag name and the attributes of the elements. The custom pseudo-classes can be used
+/// anywhere the attribute selectors can.
+///
+/// # Descendant conditions
+///
+/// `:has(s)` matches elements that have a descendant matching the selector list `s`, e.g.
+/// `element!("article:has(img.lazy)", handler)`, and `:not(:has(s))` matches elements that have
+/// no such descendants. Like the text predicates, the conditions are resolved only at the end tag
+/// of the element, unless a matching descendant is found earlier, so the input is held back in
+/// the same way and under the same bounds. The conditions are supported only in the rightmost
+/// compound selector and can't be used with pseudo-elements. The selectors in `s` are matched
+/// against the descendants of the element regardless of the combinators, i.e. only
+/// the descendant `:has()` is supported, and they can't use `:contains()` or `:has()`.
+///
+/// # Attribute inequality
+///
+/// The non-standard `[foo!="bar"]` attribute selector, common in the jQuery-like selector
+/// engines, is a shorthand for `:not([foo="bar"])`, e.g. `img[loading!=lazy]` matches the `<img>`
+/// elements that either don't have the `loading` attribute or have it set to any value other than
+/// `lazy`. The `i` and `s` modifiers are supported in the same way as for the `[foo="bar"]`
+/// selector. Since the selector is a negation, it can't be used inside `:not()`.
+///
+/// # Attribute regular expressions
+///
+/// With the `regex_selectors` feature, the non-standard `:attr-matches(name, "regex")`
+/// pseudo-class matches elements with the attribute whose value matches the regular expression
+/// in the syntax of the [`regex`] crate, e.g. `a:attr-matches(href, "^https?://ads\\.")`.
+/// The expression is compiled once, when the selector is parsed, and matches anywhere in
+/// the value unless it's anchored. An invalid expression is reported as
+/// [`SelectorError::InvalidRegex`].
+///
+/// [`regex`]: https://docs.rs/regex
+/// [`max_allowed_memory_usage`]: struct.MemorySettings.html#structfield.max_allowed_memory_usage
+/// [`max_lookahead`]: struct.Settings.html#structfield.max_lookahead
+/// [`SelectorError::InvalidRegex`]: errors/enum.SelectorError.html#variant.InvalidRegex
 /// [`str`]: https://doc.rust-lang.org/std/primitive.str.html
 /// [`parse`]: https://doc.rust-lang.org/std/primitive.str.html#method.parse
 /// [element content handlers]: struct.Settings.html#structfield.element_content_handlers
+/// [`ElementContentHandlers`]: struct.ElementContentHandlers.html
 /// [`FromStr`]: https://doc.rust-lang.org/std/str/trait.FromStr.html
+/// [`SelectorBuilder`]: struct.SelectorBuilder.html
+/// [`parts`]: #method.parts
+/// [`parse_with_pseudo_classes`]: #method.parse_with_pseudo_classes
 #[derive(Clone, Debug)]
-pub struct Selector(pub(crate) SelectorList<SelectorImplDescriptor>);
+pub struct Selector(
+    pub(crate) SelectorList<SelectorImplDescriptor>,
+    pub(crate) PlaceholderValues,
+    pub(crate) SelectorOptions,
+);
+
+/// The options that affect the matching of a [`Selector`].
+///
+/// [`Selector`]: struct.Selector.html
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct SelectorOptions {
+    pub descendant_depth_limit: Option<usize>,
+    pub case_insensitive_ids_and_classes: bool,
+    pub max_depth: Option<usize>,
+}
 
 impl FromStr for Selector {
     type Err = SelectorError;
 
     #[inline]
     fn from_str(selector: &str) -> Result<Self, Self::Err> {
-        Ok(Selector(SelectorsParser::parse(selector)?))
+        Ok(Selector(
+            SelectorsParser::parse(selector)?,
+            PlaceholderValues::default(),
+            SelectorOptions::default(),
+        ))
+    }
+}
+
+impl Selector {
+    /// Parses the selector like [`parse`] does, but on failure returns the error with
+    /// the byte offset where the parsing failed, the offending token and the list of
+    /// what the parser expected there.
+    ///
+    /// # Example
+    /// ```
+    /// use lol_html::Selector;
+    /// use lol_html::errors::SelectorError;
+    ///
+    /// let err = Selector::parse_detailed("div@").unwrap_err();
+    ///
+    /// assert_eq!(err.kind(), SelectorError::UnexpectedToken);
+    /// assert_eq!(err.offset(), Some(3));
+    /// assert_eq!(err.token(), Some("@"));
+    /// assert!(err.expected().contains(&"combinator"));
+    /// ```
+    ///
+    /// [`parse`]: https://doc.rust-lang.org/std/primitive.str.html#method.parse
+    #[inline]
+    pub fn parse_detailed(selector: &str) -> Result<Self, SelectorDiagnostic> {
+        Ok(Selector(
+            SelectorsParser::parse_detailed(selector)?,
+            PlaceholderValues::default(),
+            SelectorOptions::default(),
+        ))
+    }
+
+    /// Parses the selector with the custom pseudo-classes, e.g. `:my-ext` or `:my-ext(foo)`,
+    /// which are matched by user-defined predicates.
+    ///
+    /// The pseudo-classes that aren't supported by the parser are passed to the `resolver`
+    /// along with the source text of their arguments (an empty string for the non-functional
+    /// pseudo-classes). The resolver returns the predicate the elements are matched with or
+    /// `None` if it doesn't recognize the pseudo-class, in which case the parsing fails.
+    /// The names of the pseudo-classes are ASCII-lowercased.
+    ///
+    /// The predicates are invoked with the tag name and the attributes of the elements the rest
+    /// of the compound selector may match, so they can be used wherever the attribute selectors
+    /// can, e.g. in `:not()` or in the ancestor parts of the selector.
+    ///
+    /// # Example
+    /// ```
+    /// use lol_html::{rewrite_str, ElementContentHandlers, RewriteStrSettings};
+    /// use lol_html::{PseudoClassElement, PseudoClassPredicate, Selector};
+    /// use std::borrow::Cow;
+    /// use std::sync::Arc;
+    ///
+    /// let selector = Selector::parse_with_pseudo_classes("a:links-to(example.com)", |name, arg| {
+    ///     if name != "links-to" {
+    ///         return None;
+    ///     }
+    ///
+    ///     let host = format!("//{}/", arg);
+    ///     let predicate: PseudoClassPredicate = Arc::new(move |el: &PseudoClassElement| {
+    ///         el.get_attribute("href").map_or(false, |href| href.contains(&host))
+    ///     });
+    ///
+    ///     Some(predicate)
+    /// })
+    /// .unwrap();
+    ///
+    /// let html = rewrite_str(
+    ///     r#"<a href="https://example.com/foo"></a><a href="https://example.org/"></a>"#,
+    ///     RewriteStrSettings {
+    ///         element_content_handlers: vec![(
+    ///             Cow::Owned(selector),
+    ///             ElementContentHandlers::default().element(|el| {
+    ///                 el.set_attribute("rel", "noopener")?;
+    ///
+    ///                 Ok(())
+    ///             }),
+    ///         )],
+    ///         ..RewriteStrSettings::default()
+    ///     },
+    /// )
+    /// .unwrap();
+    ///
+    /// assert_eq!(
+    ///     html,
+    ///     r#"<a href="https://example.com/foo" rel="noopener"></a><a href="https://example.org/"></a>"#
+    /// );
+    /// ```
+    pub fn parse_with_pseudo_classes(
+        selector: &str,
+        resolver: impl Fn(&str, &str) -> Option<PseudoClassPredicate>,
+    ) -> Result<Self, SelectorError> {
+        Ok(Selector(
+            SelectorsParser::parse_with_resolver(selector, &resolver)?,
+            PlaceholderValues::default(),
+            SelectorOptions::default(),
+        ))
+    }
+
+    /// Parses the selector and checks that its complexity doesn't exceed the `limits`, so
+    /// the selectors supplied by untrusted parties can be rejected before they are matched
+    /// against any content.
+    ///
+    /// # Example
+    /// ```
+    /// use lol_html::{Selector, SelectorLimits};
+    /// use lol_html::errors::SelectorError;
+    ///
+    /// let limits = SelectorLimits {
+    ///     max_nesting_depth: Some(1),
+    ///     max_attribute_matchers: Some(2),
+    ///     ..SelectorLimits::default()
+    /// };
+    ///
+    /// assert!(Selector::parse_with_limits("a[href]:not(.external)", &limits).is_ok());
+    ///
+    /// assert_eq!(
+    ///     Selector::parse_with_limits("div:has(a:not(.external))", &limits).unwrap_err(),
+    ///     SelectorError::NestingTooDeep(1)
+    /// );
+    ///
+    /// assert_eq!(
+    ///     Selector::parse_with_limits("#main .post[data-id]", &limits).unwrap_err(),
+    ///     SelectorError::TooManyAttributeMatchers(2)
+    /// );
+    /// ```
+    pub fn parse_with_limits(
+        selector: &str,
+        limits: &SelectorLimits,
+    ) -> Result<Self, SelectorError> {
+        let selector = Selector(
+            SelectorsParser::parse(selector)?,
+            PlaceholderValues::default(),
+            SelectorOptions::default(),
+        );
+
+        limits.check(&selector)?;
+
+        Ok(selector)
+    }
+
+    /// Limits how deep the descendant combinators of the selector may reach. E.g. with the limit
+    /// of `2`, `div a` matches only the `a` elements that are children or grandchildren of
+    /// a `div` element, and with the limit of `1` it's equivalent to `div > a`.
+    ///
+    /// The matching of the descendant combinators requires the matcher to check all
+    /// the ancestors of each element, while the depth-limited ones are matched as a bounded
+    /// number of the child combinators, which caps the matching work and memory for the rules
+    /// that only care about shallow structures. A limit of `0` is treated as `1`.
+    ///
+    /// Each depth-limited descendant combinator is expanded into `limit` chains of the child
+    /// combinators, so the size of the compiled selector grows as `limit` to the power of
+    /// the number of the descendant combinators in it. An error is returned if that exceeds
+    /// 1024 chains for any selector in the selector list.
+    ///
+    /// # Example
+    /// ```
+    /// use lol_html::{rewrite_str, ElementContentHandlers, RewriteStrSettings, Selector};
+    /// use std::borrow::Cow;
+    ///
+    /// let selector = "ul a"
+    ///     .parse::<Selector>()
+    ///     .unwrap()
+    ///     .with_descendant_depth_limit(2)
+    ///     .unwrap();
+    ///
+    /// let html = rewrite_str(
+    ///     "<ul><li><a></a><p><a></a></p></li></ul>",
+    ///     RewriteStrSettings {
+    ///         element_content_handlers: vec![(
+    ///             Cow::Owned(selector),
+    ///             ElementContentHandlers::default().element(|el| {
+    ///                 el.set_attribute("shallow", "")?;
+    ///
+    ///                 Ok(())
+    ///             }),
+    ///         )],
+    ///         ..RewriteStrSettings::default()
+    ///     },
+    /// )
+    /// .unwrap();
+    ///
+    /// assert_eq!(html, r#"<ul><li><a shallow=""></a><p><a></a></p></li></ul>"#);
+    /// ```
+    pub fn with_descendant_depth_limit(mut self, limit: usize) -> Result<Self, SelectorError> {
+        let limit = limit.max(1);
+
+        for selector_item in &(self.0).0 {
+            let descendant_combinators = selector_item
+                .iter_raw_parse_order_from(0)
+                .filter(|c| matches!(c, Component::Combinator(Combinator::Descendant)))
+                .count();
+
+            match limit.checked_pow(descendant_combinators as u32) {
+                Some(expansion) if expansion <= MAX_DESCENDANT_DEPTH_EXPANSION => (),
+                _ => return Err(SelectorError::DescendantDepthLimitTooLarge(limit)),
+            }
+        }
+
+        self.2.descendant_depth_limit = Some(limit);
+
+        Ok(self)
+    }
+
+    /// Makes the selector match IDs and class names ASCII case-insensitively, e.g. `#Foo.bar`
+    /// matches `<div id="foo" class="BAR">`, as browsers do for the documents rendered in
+    /// the [quirks mode].
+    ///
+    /// This allows sanitizers to select the same elements as a quirks mode renderer would.
+    ///
+    /// # Example
+    /// ```
+    /// use lol_html::{rewrite_str, ElementContentHandlers, RewriteStrSettings, Selector};
+    /// use std::borrow::Cow;
+    ///
+    /// let selector = ".ad".parse::<Selector>().unwrap().with_case_insensitive_ids_and_classes();
+    ///
+    /// let html = rewrite_str(
+    ///     r#"<div class="AD"></div><div class="add"></div>"#,
+    ///     RewriteStrSettings {
+    ///         element_content_handlers: vec![(
+    ///             Cow::Owned(selector),
+    ///             ElementContentHandlers::default().element(|el| {
+    ///                 el.remove();
+    ///
+    ///                 Ok(())
+    ///             }),
+    ///         )],
+    ///         ..RewriteStrSettings::default()
+    ///     },
+    /// )
+    /// .unwrap();
+    ///
+    /// assert_eq!(html, r#"<div class="add"></div>"#);
+    /// ```
+    ///
+    /// [quirks mode]: https://quirks.spec.whatwg.org/#class-and-id-selectors
+    #[inline]
+    pub fn with_case_insensitive_ids_and_classes(mut self) -> Self {
+        self.2.case_insensitive_ids_and_classes = true;
+        self
+    }
+
+    /// Produces a selector that matches all the elements with an attribute whose name starts
+    /// with the `prefix`.
+    pub(crate) fn attribute_name_prefix(prefix: &str) -> Selector {
+        NonTSPseudoClassKind::AttributeNamePrefix(prefix.to_ascii_lowercase())
+            .to_css_string()
+            .parse()
+            .expect("Serialized attribute name prefix selector should be valid")
+    }
+
+    /// Makes the selector match only the elements nested at most at the `max_depth` level,
+    /// where the root elements are at the level of `1`.
+    #[inline]
+    pub(crate) fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.2.max_depth = Some(max_depth);
+        self
+    }
+
+    /// Produces a selector that matches all the descendants of the elements matched by this
+    /// selector.
+    ///
+    /// Fails if the selector ends with a pseudo-element.
+    pub(crate) fn descendants(&self) -> Result<Selector, SelectorError> {
+        let selector = (self.0)
+            .0
+            .iter()
+            .map(|s| format!("{} *", s.to_css_string()))
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        // NOTE: the custom pseudo-classes are serialized with the source text of their
+        // arguments, so they are resolved to the same predicates once again.
+        let custom_pseudo_classes = self.custom_pseudo_classes();
+        let resolver = |name: &str, argument: &str| {
+            custom_pseudo_classes
+                .iter()
+                .find(|c| c.name == name && c.argument.as_deref().unwrap_or("") == argument)
+                .map(|c| Arc::clone(&c.predicate))
+        };
+
+        // NOTE: the placeholder markers survive the serialization, so the values bound to
+        // the placeholders apply to the produced selector as well. The depth limit applies to
+        // the added descendant combinator too, while the nesting level bound of the elements
+        // doesn't apply to their descendants.
+        Ok(Selector(
+            SelectorsParser::parse_with_resolver(&selector, &resolver)?,
+            self.1.clone(),
+            SelectorOptions {
+                max_depth: None,
+                ..self.2
+            },
+        ))
+    }
+
+    /// Returns the custom pseudo-classes used anywhere in the selector list, including
+    /// the nested selectors.
+    fn custom_pseudo_classes(&self) -> Vec<CustomPseudoClass> {
+        fn collect<'s>(
+            components: impl Iterator<Item = &'s Component<SelectorImplDescriptor>>,
+            pseudo_classes: &mut Vec<CustomPseudoClass>,
+        ) {
+            for component in components {
+                match component {
+                    Component::NonTSPseudoClass(NonTSPseudoClassKind::Custom(c)) => {
+                        pseudo_classes.push(c.clone())
+                    }
+                    Component::NonTSPseudoClass(NonTSPseudoClassKind::Has(nested))
+                    | Component::NonTSPseudoClass(NonTSPseudoClassKind::Is(nested))
+                    | Component::NonTSPseudoClass(NonTSPseudoClassKind::Where(nested)) => {
+                        for s in (nested.0).0.iter() {
+                            collect(s.iter_raw_match_order(), pseudo_classes);
+                        }
+                    }
+                    Component::Negation(c) => collect(c.iter(), pseudo_classes),
+                    _ => (),
+                }
+            }
+        }
+
+        let mut pseudo_classes = Vec::new();
+
+        for s in (self.0).0.iter() {
+            collect(s.iter_raw_match_order(), &mut pseudo_classes);
+        }
+
+        pseudo_classes
+    }
+
+    /// Returns the strings of the `:contains()` text predicates for each selector in the list.
+    pub(crate) fn text_predicates(&self) -> Vec<Vec<String>> {
+        (self.0)
+            .0
+            .iter()
+            .map(|s| {
+                s.iter()
+                    .filter_map(|c| match c {
+                        Component::NonTSPseudoClass(NonTSPseudoClassKind::Contains(text)) => {
+                            Some(self.1.apply(text).into_owned())
+                        }
+                        _ => None,
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Returns the strings of the `:contains-own()` text predicates for each selector in
+    /// the list.
+    pub(crate) fn own_text_predicates(&self) -> Vec<Vec<String>> {
+        (self.0)
+            .0
+            .iter()
+            .map(|s| {
+                s.iter()
+                    .filter_map(|c| match c {
+                        Component::NonTSPseudoClass(NonTSPseudoClassKind::ContainsOwn(text)) => {
+                            Some(self.1.apply(text).into_owned())
+                        }
+                        _ => None,
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Returns whether each selector in the list has the `:empty` pseudo-class.
+    pub(crate) fn empty_predicates(&self) -> Vec<bool> {
+        (self.0)
+            .0
+            .iter()
+            .map(|s| s.iter().any(|c| matches!(c, Component::Empty)))
+            .collect()
+    }
+
+    /// Returns the last-counting pseudo-classes for each selector in the list.
+    pub(crate) fn last_counting_predicates(&self) -> Vec<Vec<NthLast>> {
+        (self.0)
+            .0
+            .iter()
+            .map(|s| s.iter().filter_map(NthLast::from_component).collect())
+            .collect()
+    }
+
+    /// Returns the selectors of the `:not(:has())` conditions for each selector in the list.
+    /// The elements matched by the selector must have no descendants matching them.
+    pub(crate) fn excluded_descendants(&self) -> Vec<Vec<Selector>> {
+        (self.0)
+            .0
+            .iter()
+            .map(|s| {
+                s.iter()
+                    .filter_map(|c| match c {
+                        Component::Negation(c) => match &c[..] {
+                            [Component::NonTSPseudoClass(NonTSPseudoClassKind::Has(nested))] => {
+                                Some(Selector(nested.0.clone(), self.1.clone(), self.2))
+                            }
+                            _ => None,
+                        },
+                        _ => None,
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Returns the selectors of the `:has()` conditions for each selector in the list.
+    /// The elements matched by the selector must have a descendant matching each of them.
+    pub(crate) fn required_descendants(&self) -> Vec<Vec<Selector>> {
+        (self.0)
+            .0
+            .iter()
+            .map(|s| {
+                s.iter()
+                    .filter_map(|c| match c {
+                        Component::NonTSPseudoClass(NonTSPseudoClassKind::Has(nested)) => {
+                            Some(Selector(nested.0.clone(), self.1.clone(), self.2))
+                        }
+                        _ => None,
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Returns `true` if any of the selectors in the list has the predicates that are resolved
+    /// by looking ahead in the input, e.g. `:contains()` or `:empty`.
+    pub(crate) fn has_deferred_predicates(&self) -> bool {
+        self.text_predicates().iter().any(|p| !p.is_empty())
+            || self.own_text_predicates().iter().any(|p| !p.is_empty())
+            || self.excluded_descendants().iter().any(|p| !p.is_empty())
+            || self.required_descendants().iter().any(|p| !p.is_empty())
+            || self.empty_predicates().iter().any(|&p| p)
+            || self
+                .last_counting_predicates()
+                .iter()
+                .any(|p| !p.is_empty())
     }
 }
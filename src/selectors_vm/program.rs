@@ -1,10 +1,12 @@
 use super::SelectorState;
-use super::attribute_matcher::AttributeMatcher;
+use super::attribute_matcher::{AttributeKey, AttributeMatcher};
 use super::compiler::{CompiledAttributeExpr, CompiledLocalNameExpr};
 use crate::html::LocalName;
-use hashbrown::HashSet;
-use std::hash::Hash;
+use hashbrown::{HashMap, HashSet};
+use std::hash::{BuildHasher, Hash, Hasher};
+use std::iter::Peekable;
 use std::ops::Range;
+use std::slice;
 
 pub type AddressRange = Range<usize>;
 
@@ -16,6 +18,10 @@ where
     pub matched_payload: HashSet<P>,
     pub jumps: Option<AddressRange>,
     pub hereditary_jumps: Option<AddressRange>,
+    /// Instructions executed for the next sibling element of the matched element.
+    pub next_sibling_jumps: Option<AddressRange>,
+    /// Instructions executed for all the following sibling elements of the matched element.
+    pub later_sibling_jumps: Option<AddressRange>,
 }
 
 /// The result of trying to execute an instruction without having parsed all attributes
@@ -63,9 +69,10 @@ where
     pub fn complete_exec_with_attrs<'i>(
         &'i self,
         state: &SelectorState,
+        local_name: &LocalName,
         attr_matcher: &AttributeMatcher,
     ) -> Option<&'i ExecutionBranch<P>> {
-        if self.attribute_exprs.iter().all(|e| e(state, attr_matcher)) {
+        if self.attribute_exprs.iter().all(|e| e(state, local_name, attr_matcher)) {
             Some(&self.associated_branch)
         } else {
             None
@@ -84,7 +91,7 @@ where
                 .all(|e| e(&*state, local_name)) &&
             self.attribute_exprs
                 .iter()
-                .all(|e| e(&*state, attr_matcher));
+                .all(|e| e(&*state, local_name, attr_matcher));
 
         if is_match {
             Some(&self.associated_branch)
@@ -103,4 +110,150 @@ where
     /// Enables tracking child types for nth-of-type selectors.
     /// This is disabled if no nth-of-type selectors are used in the program.
     pub enable_nth_of_type: bool,
+    /// Indexes of the large instruction sets, keyed by the start addresses of the sets.
+    pub instr_set_indexes: HashMap<usize, InstrSetIndex>,
+}
+
+impl<P> Program<P>
+where
+    P: Hash + Eq,
+{
+    /// Returns the addresses of the instructions in the `addr_range` that can match an element
+    /// with the `local_name`, in the ascending order.
+    #[inline]
+    pub fn instr_set_addrs(
+        &self,
+        addr_range: &AddressRange,
+        local_name: &LocalName,
+    ) -> InstrSetAddrs<'_> {
+        match self.instr_set_index(addr_range) {
+            Some(index) => index.addrs(local_name),
+            None => InstrSetAddrs::Range(addr_range.clone()),
+        }
+    }
+
+    #[inline]
+    pub fn instr_set_index(&self, addr_range: &AddressRange) -> Option<&InstrSetIndex> {
+        self.instr_set_indexes.get(&addr_range.start)
+    }
+}
+
+/// The tag name an instruction requires an element to have to match it.
+pub enum RequiredLocalName {
+    Any,
+    Name(LocalName<'static>),
+    /// The instruction can't ever match, e.g. as the tag name can't be represented in
+    /// the encoding of the document.
+    Unmatchable,
+}
+
+/// The addresses of the instructions of an instruction set grouped by the tag names
+/// the instructions require, along with the prefilters of the attributes they require.
+///
+/// Sets with a lot of instructions usually come from the large selector lists, where most of
+/// the selectors start with a tag name. With the index, the VM executes only the instructions
+/// that require the tag name of the element, along with the ones that don't require any.
+///
+/// The instructions are not grouped by the attributes they require, as the attributes of
+/// the element are not available yet when the addresses are looked up. Instead, once
+/// the attributes are parsed, the instructions that require an id, a class or an attribute
+/// the element doesn't have, e.g. the ones of `.ad` or `[data-ad]`, are skipped with a lookup
+/// in the `AttributeKeyFilter` of the element, without executing their expressions.
+#[derive(Default)]
+pub struct InstrSetIndex {
+    by_local_name: HashMap<LocalName<'static>, Box<[usize]>>,
+    unkeyed: Box<[usize]>,
+    start: usize,
+    attr_prefilters: Box<[Option<AttributeKey>]>,
+}
+
+impl InstrSetIndex {
+    /// Builds the index from the tag names and the attribute keys required by the instructions
+    /// of the set that starts at the `start` address. The instructions that can't ever match
+    /// are omitted.
+    pub fn new(
+        start: usize,
+        requirements: impl IntoIterator<Item = (RequiredLocalName, Option<AttributeKey>)>,
+    ) -> Self {
+        let mut by_local_name = HashMap::<_, Vec<_>>::default();
+        let mut unkeyed = Vec::new();
+        let mut attr_prefilters = Vec::new();
+
+        for (addr, (required_local_name, attr_prefilter)) in (start..).zip(requirements) {
+            match required_local_name {
+                RequiredLocalName::Name(n) => by_local_name.entry(n).or_default().push(addr),
+                RequiredLocalName::Any => unkeyed.push(addr),
+                RequiredLocalName::Unmatchable => (),
+            }
+
+            attr_prefilters.push(attr_prefilter);
+        }
+
+        InstrSetIndex {
+            by_local_name: by_local_name
+                .into_iter()
+                .map(|(n, addrs)| (n, addrs.into()))
+                .collect(),
+            unkeyed: unkeyed.into(),
+            start,
+            attr_prefilters: attr_prefilters.into(),
+        }
+    }
+
+    /// Returns `false` if the instruction at the `addr` can't match the element, as
+    /// the element doesn't have the id, the class or the attribute the instruction requires.
+    #[inline]
+    pub fn attrs_may_match(&self, addr: usize, attr_matcher: &AttributeMatcher) -> bool {
+        match self.attr_prefilters[addr - self.start] {
+            Some(key) => attr_matcher.may_have(key),
+            None => true,
+        }
+    }
+
+    #[inline]
+    fn addrs(&self, local_name: &LocalName) -> InstrSetAddrs<'_> {
+        let mut hasher = self.by_local_name.hasher().build_hasher();
+
+        local_name.hash(&mut hasher);
+
+        let keyed = self
+            .by_local_name
+            .raw_entry()
+            .from_hash(hasher.finish(), |n| local_name == n)
+            .map(|(_, addrs)| &addrs[..])
+            .unwrap_or_default();
+
+        InstrSetAddrs::Indexed {
+            keyed: keyed.iter().peekable(),
+            unkeyed: self.unkeyed.iter().peekable(),
+        }
+    }
+}
+
+/// An iterator over the addresses of the instructions to execute for an element.
+pub enum InstrSetAddrs<'i> {
+    Range(AddressRange),
+    Indexed {
+        keyed: Peekable<slice::Iter<'i, usize>>,
+        unkeyed: Peekable<slice::Iter<'i, usize>>,
+    },
+}
+
+impl Iterator for InstrSetAddrs<'_> {
+    type Item = usize;
+
+    #[inline]
+    fn next(&mut self) -> Option<usize> {
+        match self {
+            InstrSetAddrs::Range(range) => range.next(),
+            // NOTE: both lists are sorted, so they are merged to keep the order of execution
+            // the same as without the index.
+            InstrSetAddrs::Indexed { keyed, unkeyed } => match (keyed.peek(), unkeyed.peek()) {
+                (Some(k), Some(u)) if k < u => keyed.next(),
+                (Some(_), Some(_)) | (None, _) => unkeyed.next(),
+                (Some(_), None) => keyed.next(),
+            }
+            .copied(),
+        }
+    }
 }
\ No newline at end of file
@@ -2,7 +2,7 @@ use super::SelectorState;
 use super::program::AddressRange;
 use super::ast::NthChild;
 use crate::html::{LocalName, Namespace, Tag};
-use crate::memory::{LimitedVec, MemoryLimitExceededError, SharedMemoryLimiter};
+use crate::memory::{profile, LimitedVec, MemoryLimitExceededError, SharedMemoryLimiter};
 // use hashbrown for raw entry, switch back to std once it stablizes there
 use hashbrown::{HashSet, HashMap, hash_map::RawEntryMut};
 use std::fmt::Debug;
@@ -146,12 +146,67 @@ impl TypedChildCounterMap {
     }
 }
 
+/// Jumps left by the matched elements for their following siblings.
+#[derive(Default)]
+pub struct SiblingJumps {
+    /// Jumps executed only for the next sibling element.
+    next: Vec<AddressRange>,
+    /// Jumps executed for all the following sibling elements.
+    later: Vec<AddressRange>,
+}
+
+impl SiblingJumps {
+    #[inline]
+    pub fn add_next(&mut self, jumps: &AddressRange) {
+        if !self.next.contains(jumps) {
+            self.next.push(jumps.to_owned());
+        }
+    }
+
+    #[inline]
+    pub fn add_later(&mut self, jumps: &AddressRange) {
+        // NOTE: the same jumps can be added by multiple siblings, e.g. for `h2 ~ p`
+        // with multiple `<h2>` elements.
+        if !self.later.contains(jumps) {
+            self.later.push(jumps.to_owned());
+        }
+    }
+
+    #[inline]
+    fn extend(&mut self, other: SiblingJumps) {
+        other.next.iter().for_each(|j| self.add_next(j));
+        other.later.iter().for_each(|j| self.add_later(j));
+    }
+
+    #[inline]
+    fn remap(&mut self, map: &impl Fn(&AddressRange) -> AddressRange) {
+        self.next.iter_mut().for_each(|j| *j = map(j));
+        self.later.iter_mut().for_each(|j| *j = map(j));
+    }
+
+    /// Takes the jumps that should be executed for the next sibling element.
+    #[inline]
+    fn take_for_next_sibling(&mut self) -> Vec<AddressRange> {
+        let mut jumps = std::mem::take(&mut self.next);
+
+        for later in &self.later {
+            if !jumps.contains(later) {
+                jumps.push(later.to_owned());
+            }
+        }
+
+        jumps
+    }
+}
+
 pub struct StackItem<'i, E: ElementData> {
     pub local_name: LocalName<'i>,
     pub element_data: E,
     pub jumps: Vec<AddressRange>,
     pub hereditary_jumps: Vec<AddressRange>,
     pub child_counter: ChildCounter,
+    /// Jumps left by the children of the element for their following siblings.
+    pub sibling_jumps: SiblingJumps,
     pub has_ancestor_with_hereditary_jumps: bool,
     pub stack_directive: StackDirective,
 }
@@ -165,6 +220,7 @@ impl<'i, E: ElementData> StackItem<'i, E> {
             jumps: Vec::default(),
             hereditary_jumps: Vec::default(),
             child_counter: Default::default(),
+            sibling_jumps: SiblingJumps::default(),
             has_ancestor_with_hereditary_jumps: false,
             stack_directive: StackDirective::Push,
         }
@@ -178,6 +234,7 @@ impl<'i, E: ElementData> StackItem<'i, E> {
             jumps: self.jumps,
             hereditary_jumps: self.hereditary_jumps,
             child_counter: self.child_counter,
+            sibling_jumps: self.sibling_jumps,
             has_ancestor_with_hereditary_jumps: self.has_ancestor_with_hereditary_jumps,
             stack_directive: self.stack_directive,
         }
@@ -187,20 +244,34 @@ impl<'i, E: ElementData> StackItem<'i, E> {
 pub struct Stack<E: ElementData> {
     /// A counter for root elements
     root_child_counter: ChildCounter,
+    /// Jumps left by root elements for their following siblings
+    root_sibling_jumps: SiblingJumps,
     /// A typed counter for all elements on all frames. This is optional to indicate if types are actually being counted.
     typed_child_counters: Option<TypedChildCounterMap>,
     items: LimitedVec<StackItem<'static, E>>,
+    /// Names of the elements that are treated as void in addition to the standard ones.
+    custom_void_elements: Vec<LocalName<'static>>,
+    /// Index of the first item that can be popped by end tags.
+    scope_start: usize,
 }
 
 impl<E: ElementData> Stack<E> {
     pub fn new(memory_limiter: SharedMemoryLimiter, enable_nth_of_type: bool) -> Self {
         Stack {
             root_child_counter: Default::default(),
+            root_sibling_jumps: SiblingJumps::default(),
             typed_child_counters: if enable_nth_of_type { Some(Default::default()) } else { None },
-            items: LimitedVec::new(memory_limiter),
+            items: LimitedVec::with_capacity(memory_limiter, profile::SELECTOR_STACK_CAPACITY),
+            custom_void_elements: Vec::default(),
+            scope_start: 0,
         }
     }
 
+    #[inline]
+    pub fn set_custom_void_elements(&mut self, custom_void_elements: Vec<LocalName<'static>>) {
+        self.custom_void_elements = custom_void_elements;
+    }
+
     /// Adds a child to child counters. Called before pushing the element to the stack.
     pub fn add_child<'i>(&mut self, name: &LocalName<'i>) {
         match self.items.last_mut() {
@@ -213,6 +284,28 @@ impl<E: ElementData> Stack<E> {
         }
     }
 
+    /// Takes the jumps left by the preceding siblings for the element that is about to be
+    /// added to the current stack frame.
+    #[inline]
+    pub fn take_sibling_jumps(&mut self) -> Vec<AddressRange> {
+        match self.items.last_mut() {
+            Some(last) => &mut last.sibling_jumps,
+            None => &mut self.root_sibling_jumps,
+        }
+        .take_for_next_sibling()
+    }
+
+    /// Adds the jumps left by the element for its following siblings to the current stack frame.
+    /// Called before pushing the element to the stack.
+    #[inline]
+    pub fn add_sibling_jumps(&mut self, jumps: SiblingJumps) {
+        match self.items.last_mut() {
+            Some(last) => &mut last.sibling_jumps,
+            None => &mut self.root_sibling_jumps,
+        }
+        .extend(jumps);
+    }
+
     pub fn build_state<'a, 'i>(&'a self, name: &LocalName<'i>) -> SelectorState<'i>
     where
         'a: 'i // 'a outlives 'i, required to downcast 'a lifetimes into 'i
@@ -226,14 +319,20 @@ impl<E: ElementData> Stack<E> {
             typed:
                 self.typed_child_counters
                     .as_ref()
-                    .and_then(|f| f.get(name, self.items.len()))
+                    .and_then(|f| f.get(name, self.items.len())),
+            depth: self.items.len() + 1,
         }
     }
 
     #[inline]
-    pub fn get_stack_directive(item: &StackItem<E>, ns: Namespace) -> StackDirective {
+    pub fn get_stack_directive(&self, item: &StackItem<E>, ns: Namespace) -> StackDirective {
         if ns == Namespace::Html {
-            if is_void_element(&item.local_name) {
+            if is_void_element(&item.local_name)
+                || self
+                    .custom_void_elements
+                    .iter()
+                    .any(|n| *n == item.local_name)
+            {
                 StackDirective::PopImmediately
             } else {
                 StackDirective::Push
@@ -243,6 +342,27 @@ impl<E: ElementData> Stack<E> {
         }
     }
 
+    /// Starts counting the children of each type, if it hasn't been done so far. Children that
+    /// have been added before are not taken into account.
+    #[inline]
+    pub fn enable_nth_of_type(&mut self) {
+        if self.typed_child_counters.is_none() {
+            self.typed_child_counters = Some(Default::default());
+        }
+    }
+
+    /// Replaces the jumps left by the elements on the stack using `map`, e.g. when the program
+    /// they point to is recompiled.
+    pub fn remap_jumps(&mut self, map: impl Fn(&AddressRange) -> AddressRange) {
+        self.root_sibling_jumps.remap(&map);
+
+        for item in self.items.iter_mut() {
+            item.jumps.iter_mut().for_each(|j| *j = map(j));
+            item.hereditary_jumps.iter_mut().for_each(|j| *j = map(j));
+            item.sibling_jumps.remap(&map);
+        }
+    }
+
     pub fn pop_up_to(
         &mut self,
         local_name: LocalName,
@@ -252,15 +372,43 @@ impl<E: ElementData> Stack<E> {
             self.items
                 .iter()
                 .enumerate()
+                .skip(self.scope_start)
                 .rev()
                 .find(|(_, item)| item.local_name == local_name)
                 .map(|(i, _)| i);
         if let Some(index) = pop_to_index {
-            if let Some(c) = self.typed_child_counters.as_mut() {
-                c.pop_to(index)
-            }
-            self.items.drain(index..).map(|i| i.element_data).for_each(popped_element_data_handler)
+            self.pop_to(index, popped_element_data_handler);
+        }
+    }
+
+    fn pop_to(&mut self, index: usize, popped_element_data_handler: impl FnMut(E)) {
+        if let Some(c) = self.typed_child_counters.as_mut() {
+            c.pop_to(index)
         }
+        self.items.drain(index..).map(|i| i.element_data).for_each(popped_element_data_handler)
+    }
+
+    /// Makes the items that are currently on the stack unreachable for the end tags. Returns
+    /// the previous scope that should be restored with [`leave_scope`].
+    ///
+    /// [`leave_scope`]: #method.leave_scope
+    #[inline]
+    pub fn enter_scope(&mut self) -> usize {
+        std::mem::replace(&mut self.scope_start, self.items.len())
+    }
+
+    /// Pops the items that have been pushed since the matching [`enter_scope`] call and
+    /// restores the `prev_scope`.
+    ///
+    /// [`enter_scope`]: #method.enter_scope
+    #[inline]
+    pub fn leave_scope(
+        &mut self,
+        prev_scope: usize,
+        popped_element_data_handler: impl FnMut(E),
+    ) {
+        self.pop_to(self.scope_start, popped_element_data_handler);
+        self.scope_start = prev_scope;
     }
 
     #[inline]
@@ -345,6 +493,25 @@ mod tests {
         );
     }
 
+    #[test]
+    fn sibling_jumps() {
+        let mut stack = Stack::new(MemoryLimiter::new_shared(2048), false);
+        let mut jumps = SiblingJumps::default();
+
+        jumps.add_next(&(0..1));
+        jumps.add_later(&(1..2));
+        stack.add_sibling_jumps(jumps);
+
+        stack.push_item(item("item1", 0)).unwrap();
+
+        assert!(stack.take_sibling_jumps().is_empty());
+
+        stack.pop_up_to(local_name("item1"), |_| ());
+
+        assert_eq!(stack.take_sibling_jumps(), [0..1, 1..2]);
+        assert_eq!(stack.take_sibling_jumps(), [1..2]);
+    }
+
     #[test]
     fn pop_up_to() {
         macro_rules! assert_pop_result {
@@ -0,0 +1,311 @@
+use super::parser::{SelectorImplDescriptor, SelectorOptions, SelectorsParser};
+use super::{Selector, SelectorBindingError, SelectorError};
+use selectors::parser::SelectorList;
+use std::borrow::Cow;
+use std::str::FromStr;
+use std::sync::Arc;
+
+// NOTE: placeholders are replaced with the markers made of the private use area characters and
+// the index of the placeholder before the template is parsed. The characters are valid both in
+// identifiers and in strings, and the index is not affected by the ASCII lowercasing the parser
+// applies to the attribute names.
+const MARKER_START: char = '\u{E000}';
+const MARKER_END: char = '\u{E001}';
+
+#[inline]
+fn is_placeholder_name_char(ch: char) -> bool {
+    ch.is_ascii_alphanumeric() || ch == '_' || ch == '-'
+}
+
+/// Values bound to the placeholders of a [`SelectorTemplate`].
+///
+/// [`SelectorTemplate`]: struct.SelectorTemplate.html
+#[derive(Clone, Debug, Default)]
+pub(crate) struct PlaceholderValues(Option<Arc<Vec<String>>>);
+
+impl PlaceholderValues {
+    /// Replaces the placeholder markers in the `text` with the bound values.
+    pub fn apply<'t>(&self, text: &'t str) -> Cow<'t, str> {
+        let values = match self.0 {
+            Some(ref values) if text.contains(MARKER_START) => values,
+            _ => return Cow::Borrowed(text),
+        };
+
+        let mut result = String::with_capacity(text.len());
+        let mut rest = text;
+
+        while let Some(start) = rest.find(MARKER_START) {
+            result.push_str(&rest[..start]);
+            rest = &rest[start..];
+
+            let marker_content = &rest[MARKER_START.len_utf8()..];
+
+            let value = marker_content.find(MARKER_END).and_then(|end| {
+                let value = marker_content[..end]
+                    .parse::<usize>()
+                    .ok()
+                    .and_then(|idx| values.get(idx))?;
+
+                Some((value, MARKER_START.len_utf8() + end + MARKER_END.len_utf8()))
+            });
+
+            match value {
+                Some((value, marker_len)) => {
+                    result.push_str(value);
+                    rest = &rest[marker_len..];
+                }
+                None => {
+                    result.push(MARKER_START);
+                    rest = marker_content;
+                }
+            }
+        }
+
+        result.push_str(rest);
+
+        Cow::Owned(result)
+    }
+}
+
+/// A CSS selector with placeholders that can be bound to values.
+///
+/// A placeholder is a name consisting of ASCII alphanumeric characters, `_` and `-` in curly
+/// braces, e.g. `{prefix}`. Placeholders can be used in place of or inside tag names, IDs, class
+/// names, attribute names and values and the strings of `:contains()`, e.g.
+/// `a[href^={prefix}]` or `a[href^="https://{host}/"]`.
+///
+/// The template is parsed once and binding values to it produces a [`Selector`] that shares
+/// the parsed template, so selectors for different sets of values can be created without
+/// formatting and re-parsing the selector text. The values are used verbatim, there is no need
+/// to escape them.
+///
+/// # Example
+/// ```
+/// use lol_html::{rewrite_str, ElementContentHandlers, RewriteStrSettings, SelectorTemplate};
+/// use std::borrow::Cow;
+///
+/// let template: SelectorTemplate = "a[href^={prefix}]".parse().unwrap();
+/// let selector = template.bind(&[("prefix", "https://tenant.example/")]).unwrap();
+///
+/// let html = rewrite_str(
+///     r#"<a href="https://tenant.example/1"></a><a href="/2"></a>"#,
+///     RewriteStrSettings {
+///         element_content_handlers: vec![(
+///             Cow::Owned(selector),
+///             ElementContentHandlers::default().element(|el| {
+///                 el.set_attribute("rel", "external")?;
+///
+///                 Ok(())
+///             }),
+///         )],
+///         ..RewriteStrSettings::default()
+///     },
+/// )
+/// .unwrap();
+///
+/// assert_eq!(
+///     html,
+///     r#"<a href="https://tenant.example/1" rel="external"></a><a href="/2"></a>"#
+/// );
+/// ```
+///
+/// [`Selector`]: struct.Selector.html
+#[derive(Clone, Debug)]
+pub struct SelectorTemplate {
+    selector: SelectorList<SelectorImplDescriptor>,
+    placeholders: Vec<String>,
+}
+
+impl FromStr for SelectorTemplate {
+    type Err = SelectorError;
+
+    fn from_str(template: &str) -> Result<Self, Self::Err> {
+        let mut placeholders: Vec<String> = Vec::new();
+        let mut selector = String::with_capacity(template.len());
+        let mut rest = template;
+
+        while let Some(start) = rest.find('{') {
+            selector.push_str(&rest[..start]);
+            rest = &rest[start + 1..];
+
+            let name_len = rest
+                .find(|ch| !is_placeholder_name_char(ch))
+                .unwrap_or(rest.len());
+
+            // NOTE: braces that don't enclose a placeholder name are left to the parser.
+            if name_len == 0 || !rest[name_len..].starts_with('}') {
+                selector.push('{');
+                continue;
+            }
+
+            let name = &rest[..name_len];
+
+            let idx = match placeholders.iter().position(|p| p == name) {
+                Some(idx) => idx,
+                None => {
+                    placeholders.push(name.to_string());
+                    placeholders.len() - 1
+                }
+            };
+
+            selector.push(MARKER_START);
+            selector.push_str(&idx.to_string());
+            selector.push(MARKER_END);
+
+            rest = &rest[name_len + 1..];
+        }
+
+        selector.push_str(rest);
+
+        Ok(SelectorTemplate {
+            selector: SelectorsParser::parse(&selector)?,
+            placeholders,
+        })
+    }
+}
+
+impl SelectorTemplate {
+    /// Returns the names of the placeholders in the order of their first occurrence in
+    /// the template.
+    #[inline]
+    pub fn placeholders(&self) -> impl Iterator<Item = &str> {
+        self.placeholders.iter().map(String::as_str)
+    }
+
+    /// Produces a selector with the placeholders of the template replaced with the `values`.
+    ///
+    /// The `values` should contain a value for each of the placeholders of the template and
+    /// nothing else.
+    pub fn bind<'v>(
+        &self,
+        values: impl IntoIterator<Item = &'v (&'v str, &'v str)>,
+    ) -> Result<Selector, SelectorBindingError> {
+        let mut bound_values = vec![None; self.placeholders.len()];
+
+        for &(name, value) in values {
+            match self.placeholders.iter().position(|p| p == name) {
+                Some(idx) => bound_values[idx] = Some(value.to_string()),
+                None => return Err(SelectorBindingError::UnknownPlaceholder(name.to_string())),
+            }
+        }
+
+        let bound_values = bound_values
+            .into_iter()
+            .zip(self.placeholders.iter())
+            .map(|(value, name)| {
+                value.ok_or_else(|| SelectorBindingError::MissingValue(name.to_owned()))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Selector(
+            self.selector.clone(),
+            PlaceholderValues(Some(Arc::new(bound_values))),
+            SelectorOptions::default(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn placeholder_values() {
+        let values = PlaceholderValues(Some(Arc::new(vec!["foo".into(), "bar".into()])));
+
+        assert_eq!(values.apply("baz"), "baz");
+        assert_eq!(values.apply("\u{E000}1\u{E001}"), "bar");
+
+        assert_eq!(
+            values.apply("a\u{E000}0\u{E001}b\u{E000}1\u{E001}\u{E000}0\u{E001}"),
+            "afoobbarfoo"
+        );
+
+        // NOTE: malformed markers are left intact.
+        assert_eq!(
+            values.apply("\u{E000}2\u{E001}\u{E000}x\u{E000}0"),
+            "\u{E000}2\u{E001}\u{E000}x\u{E000}0"
+        );
+
+        assert_eq!(
+            PlaceholderValues::default().apply("\u{E000}0\u{E001}"),
+            "\u{E000}0\u{E001}"
+        );
+    }
+
+    #[test]
+    fn placeholders() {
+        let template: SelectorTemplate = "{tag}.{cls} > [{attr}={value}], {tag}".parse().unwrap();
+
+        assert_eq!(
+            template.placeholders().collect::<Vec<_>>(),
+            ["tag", "cls", "attr", "value"]
+        );
+    }
+
+    #[test]
+    fn bound_selectors() {
+        use crate::{rewrite_str, ElementContentHandlers, RewriteStrSettings};
+
+        let template: SelectorTemplate = r#"{tag}.{cls}[data-x="[{v}]"]"#.parse().unwrap();
+
+        let rewrite = |values: &[(&str, &str)]| {
+            rewrite_str(
+                r#"<p class=a data-x='["]'></p><b class=b data-x="[a b]"></b>"#,
+                RewriteStrSettings {
+                    element_content_handlers: vec![(
+                        Cow::Owned(template.bind(values).unwrap()),
+                        ElementContentHandlers::default().element(|el| {
+                            el.set_attribute("matched", "")?;
+
+                            Ok(())
+                        }),
+                    )],
+                    ..RewriteStrSettings::default()
+                },
+            )
+            .unwrap()
+        };
+
+        assert_eq!(
+            rewrite(&[("tag", "p"), ("cls", "a"), ("v", "\"")]),
+            r#"<p class=a data-x='["]' matched=""></p><b class=b data-x="[a b]"></b>"#
+        );
+
+        assert_eq!(
+            rewrite(&[("tag", "b"), ("cls", "b"), ("v", "a b")]),
+            r#"<p class=a data-x='["]'></p><b class=b data-x="[a b]" matched=""></b>"#
+        );
+    }
+
+    #[test]
+    fn binding_errors() {
+        let template: SelectorTemplate = "a[href^={prefix}][rel={rel}]".parse().unwrap();
+
+        assert_eq!(
+            template.bind(&[("prefix", "/")]).unwrap_err(),
+            SelectorBindingError::MissingValue("rel".into())
+        );
+
+        assert_eq!(
+            template
+                .bind(&[("prefix", "/"), ("rel", "next"), ("foo", "bar")])
+                .unwrap_err(),
+            SelectorBindingError::UnknownPlaceholder("foo".into())
+        );
+
+        assert!(template.bind(&[("rel", "next"), ("prefix", "/")]).is_ok());
+    }
+
+    #[test]
+    fn template_parse_errors() {
+        ["a[href^={prefix]", "a[href^={}]", "a[href^={ prefix}]"]
+            .iter()
+            .for_each(|t| {
+                assert_eq!(
+                    t.parse::<SelectorTemplate>().unwrap_err(),
+                    SelectorError::UnexpectedTokenInAttribute
+                )
+            });
+    }
+}
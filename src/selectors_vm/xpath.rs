@@ -0,0 +1,518 @@
+//! A translator of a restricted subset of XPath 1.0 to CSS selectors.
+//!
+//! Each supported XPath construct has an exact CSS counterpart, so translated expressions are
+//! parsed and compiled to the selector matching VM program in the same way as CSS selectors.
+use super::{Selector, XPathError};
+use cssparser::{serialize_identifier, serialize_string};
+use std::fmt::Write;
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum StepCombinator {
+    Root,
+    Child,
+    Descendant,
+}
+
+#[inline]
+fn is_name_start_char(ch: char) -> bool {
+    ch.is_alphabetic() || ch == '_'
+}
+
+#[inline]
+fn is_name_char(ch: char) -> bool {
+    ch.is_alphanumeric() || matches!(ch, '-' | '_' | '.')
+}
+
+struct XPathParser<'i> {
+    input: &'i str,
+    pos: usize,
+}
+
+impl<'i> XPathParser<'i> {
+    #[inline]
+    fn rest(&self) -> &'i str {
+        &self.input[self.pos..]
+    }
+
+    #[inline]
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    #[inline]
+    fn peek(&mut self) -> Option<char> {
+        self.skip_whitespace();
+        self.rest().chars().next()
+    }
+
+    #[inline]
+    fn unexpected(&mut self) -> XPathError {
+        match self.peek() {
+            Some(ch) => XPathError::UnexpectedCharacter(ch),
+            None => XPathError::UnexpectedEnd,
+        }
+    }
+
+    fn eat(&mut self, token: &str) -> bool {
+        self.skip_whitespace();
+
+        if self.rest().starts_with(token) {
+            self.pos += token.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, token: &str) -> Result<(), XPathError> {
+        if self.eat(token) {
+            Ok(())
+        } else {
+            Err(self.unexpected())
+        }
+    }
+
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        let start = self.pos;
+
+        if self.eat(keyword) && !self.rest().starts_with(is_name_char) {
+            true
+        } else {
+            self.pos = start;
+            false
+        }
+    }
+
+    fn name(&mut self) -> Result<&'i str, XPathError> {
+        self.skip_whitespace();
+
+        let rest = self.rest();
+
+        if !rest.starts_with(is_name_start_char) {
+            return Err(self.unexpected());
+        }
+
+        let len = rest
+            .find(|ch: char| !is_name_char(ch))
+            .unwrap_or(rest.len());
+
+        self.pos += len;
+
+        // NOTE: namespace prefixes are not supported.
+        if self.rest().starts_with(':') && !self.rest().starts_with("::") {
+            return Err(XPathError::UnexpectedCharacter(':'));
+        }
+
+        Ok(&rest[..len])
+    }
+
+    fn literal(&mut self) -> Result<&'i str, XPathError> {
+        let quote = match self.peek() {
+            Some(quote @ '"') | Some(quote @ '\'') => quote,
+            _ => return Err(self.unexpected()),
+        };
+
+        let rest = &self.rest()[1..];
+        let len = rest.find(quote).ok_or(XPathError::UnexpectedEnd)?;
+
+        self.pos += len + 2;
+
+        Ok(&rest[..len])
+    }
+
+    fn number(&mut self) -> Result<usize, XPathError> {
+        self.skip_whitespace();
+
+        let rest = self.rest();
+        let len = rest
+            .find(|ch: char| !ch.is_ascii_digit())
+            .unwrap_or(rest.len());
+
+        match rest[..len].parse() {
+            Ok(number) => {
+                self.pos += len;
+                Ok(number)
+            }
+            Err(_) => Err(self.unexpected()),
+        }
+    }
+
+    fn expression(&mut self) -> Result<String, XPathError> {
+        let mut selectors = vec![self.location_path()?];
+
+        while self.eat("|") {
+            selectors.push(self.location_path()?);
+        }
+
+        match self.peek() {
+            Some(ch) => Err(XPathError::UnexpectedCharacter(ch)),
+            None => Ok(selectors.join(", ")),
+        }
+    }
+
+    fn location_path(&mut self) -> Result<String, XPathError> {
+        let mut css = String::new();
+
+        // NOTE: relative location paths are evaluated against the document root, i.e.
+        // `div/a` is equivalent to `//div/a`.
+        let mut combinator = if self.eat("//") {
+            StepCombinator::Descendant
+        } else if self.eat("/") {
+            StepCombinator::Root
+        } else {
+            StepCombinator::Descendant
+        };
+
+        loop {
+            combinator = self.axis(combinator)?;
+
+            if self.eat("text()") {
+                if combinator != StepCombinator::Descendant || css.is_empty() {
+                    return Err(XPathError::UnsupportedTextNodeTest);
+                }
+
+                css.push_str("::text");
+
+                return match self.peek() {
+                    Some('/') | Some('[') => Err(XPathError::UnsupportedTextNodeTest),
+                    _ => Ok(css),
+                };
+            }
+
+            self.step(combinator, &mut css)?;
+
+            combinator = if self.eat("//") {
+                StepCombinator::Descendant
+            } else if self.eat("/") {
+                StepCombinator::Child
+            } else {
+                return Ok(css);
+            };
+        }
+    }
+
+    fn axis(&mut self, combinator: StepCombinator) -> Result<StepCombinator, XPathError> {
+        let start = self.pos;
+
+        if let Ok(name) = self.name() {
+            if self.eat("::") {
+                return match name {
+                    "child" => Ok(combinator),
+                    "descendant" => Ok(StepCombinator::Descendant),
+                    _ => Err(XPathError::UnsupportedAxis),
+                };
+            }
+        }
+
+        self.pos = start;
+
+        Ok(combinator)
+    }
+
+    fn step(&mut self, combinator: StepCombinator, css: &mut String) -> Result<(), XPathError> {
+        let name = if self.eat("*") {
+            None
+        } else {
+            Some(self.name()?)
+        };
+
+        if self.peek() == Some('(') {
+            return Err(XPathError::UnsupportedFunction);
+        }
+
+        match combinator {
+            // NOTE: `html` is always the root element of an HTML document.
+            StepCombinator::Root if !name.map_or(false, |n| n.eq_ignore_ascii_case("html")) => {
+                return Err(XPathError::UnsupportedAbsolutePath)
+            }
+            StepCombinator::Child => css.push_str(" > "),
+            StepCombinator::Descendant if !css.is_empty() => css.push(' '),
+            _ => (),
+        }
+
+        match name {
+            Some(name) => serialize_identifier(name, css).unwrap(),
+            None => css.push('*'),
+        }
+
+        self.predicates(name.is_some(), css)
+    }
+
+    fn predicates(&mut self, has_name: bool, css: &mut String) -> Result<(), XPathError> {
+        let mut is_first = true;
+
+        while self.eat("[") {
+            if let Some(position) = self.position()? {
+                // NOTE: a positional predicate that follows another predicate selects among
+                // the elements filtered by the previous predicate, we can't express that in CSS.
+                if !is_first {
+                    return Err(XPathError::UnsupportedPredicate);
+                }
+
+                let pseudo_class = if has_name { "nth-of-type" } else { "nth-child" };
+
+                write!(css, ":{}({})", pseudo_class, position).unwrap();
+            } else {
+                self.condition(css)?;
+
+                while self.eat_keyword("and") {
+                    self.condition(css)?;
+                }
+
+                if self.eat_keyword("or") {
+                    return Err(XPathError::UnsupportedPredicate);
+                }
+            }
+
+            self.expect("]")?;
+            is_first = false;
+        }
+
+        Ok(())
+    }
+
+    fn position(&mut self) -> Result<Option<usize>, XPathError> {
+        if self.eat("position()") {
+            self.expect("=")?;
+            self.number().map(Some)
+        } else if self.peek().map_or(false, |ch| ch.is_ascii_digit()) {
+            self.number().map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn attribute_name(&mut self) -> Result<String, XPathError> {
+        self.expect("@")?;
+
+        let mut name = String::new();
+
+        serialize_identifier(self.name()?, &mut name).unwrap();
+
+        Ok(name)
+    }
+
+    fn attribute_selector(name: &str, operator: &str, value: &str, css: &mut String) {
+        write!(css, "[{}{}", name, operator).unwrap();
+        serialize_string(value, css).unwrap();
+        css.push(']');
+    }
+
+    fn condition(&mut self, css: &mut String) -> Result<(), XPathError> {
+        if self.peek() == Some('@') {
+            let name = self.attribute_name()?;
+
+            if self.eat("!=") {
+                let value = self.literal()?;
+
+                write!(css, "[{}]:not(", name).unwrap();
+                Self::attribute_selector(&name, "=", value, css);
+                css.push(')');
+            } else if self.eat("=") {
+                Self::attribute_selector(&name, "=", self.literal()?, css);
+            } else {
+                write!(css, "[{}]", name).unwrap();
+            }
+
+            return Ok(());
+        }
+
+        let function = self.name()?;
+
+        self.expect("(")?;
+
+        let operator = match function {
+            "not" => {
+                css.push_str(":not(");
+                self.condition(css)?;
+                css.push(')');
+
+                return self.expect(")");
+            }
+            "contains" => "*=",
+            "starts-with" => "^=",
+            "ends-with" => "$=",
+            _ => return Err(XPathError::UnsupportedFunction),
+        };
+
+        let name = self.attribute_name()?;
+
+        self.expect(",")?;
+
+        let value = self.literal()?;
+
+        self.expect(")")?;
+
+        // NOTE: in XPath any string contains, starts and ends with an empty string, while
+        // CSS substring attribute selectors with an empty value never match.
+        if value.is_empty() {
+            write!(css, "[{}]", name).unwrap();
+        } else {
+            Self::attribute_selector(&name, operator, value, css);
+        }
+
+        Ok(())
+    }
+}
+
+#[inline]
+fn xpath_to_css(xpath: &str) -> Result<String, XPathError> {
+    if xpath.trim().is_empty() {
+        return Err(XPathError::EmptyExpression);
+    }
+
+    XPathParser {
+        input: xpath,
+        pos: 0,
+    }
+    .expression()
+}
+
+impl Selector {
+    /// Parses a selector from an expression in a restricted subset of [XPath 1.0].
+    ///
+    /// The expression is translated to an equivalent CSS selector, so the resulting selector
+    /// can be used in the same way as a selector parsed from a CSS selector string.
+    ///
+    /// # Supported expressions
+    ///
+    /// Expression                              | Represents                                                                            |
+    /// --------------------------------------- | ------------------------------------------------------------------------------------- |
+    /// `//E`, `E`                              | any element of type `E`                                                               |
+    /// `//*`                                   | any element                                                                           |
+    /// `/html/E`                               | an `E` element child of the root `html` element                                       |
+    /// `E/F`, `E/child::F`                     | an `F` element child of an `E` element                                                |
+    /// `E//F`, `E/descendant::F`               | an `F` element descendant of an `E` element                                           |
+    /// `E[n]`, `E[position()=n]`               | an `E` element, the n-th sibling of its type                                          |
+    /// `*[n]`                                  | an element, the n-th child of its parent                                              |
+    /// `E[@foo]`                               | an `E` element with a `foo` attribute                                                 |
+    /// `E[@foo="bar"]`                         | an `E` element whose foo attribute value is exactly equal to `"bar"`                  |
+    /// `E[@foo!="bar"]`                        | an `E` element whose foo attribute value is not equal to `"bar"`                      |
+    /// `E[contains(@foo, "bar")]`              | an `E` element whose foo attribute value contains the substring `"bar"`               |
+    /// `E[starts-with(@foo, "bar")]`           | an `E` element whose foo attribute value begins with the string `"bar"`               |
+    /// `E[ends-with(@foo, "bar")]`             | an `E` element whose foo attribute value ends with the string `"bar"`                 |
+    /// `E[not(c)]`                             | an `E` element that doesn't satisfy the condition `c`                                 |
+    /// `E[c1 and c2]`, `E[c1][c2]`             | an `E` element that satisfies both conditions `c1` and `c2`                           |
+    /// `E//text()`                             | text chunks in the inner content of an `E` element                                    |
+    /// <code>P1 &#124; P2</code>               | elements matched by either of the paths `P1` and `P2`                                 |
+    ///
+    /// Relative location paths are evaluated against the document root, i.e. `div/a` is
+    /// equivalent to `//div/a`. A positional predicate is supported only as the first predicate
+    /// of a step.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use lol_html::{rewrite_str, element, RewriteStrSettings, Selector};
+    /// use std::borrow::Cow;
+    ///
+    /// let selector = Selector::from_xpath("//ul[@id='menu']/li[2]/a").unwrap();
+    ///
+    /// let html = rewrite_str(
+    ///     r#"<ul id="menu"><li><a>Home</a></li><li><a>About</a></li></ul>"#,
+    ///     RewriteStrSettings {
+    ///         element_content_handlers: vec![(
+    ///             Cow::Owned(selector),
+    ///             lol_html::ElementContentHandlers::default().element(|el| {
+    ///                 el.set_attribute("class", "active").unwrap();
+    ///
+    ///                 Ok(())
+    ///             }),
+    ///         )],
+    ///         ..RewriteStrSettings::default()
+    ///     }
+    /// ).unwrap();
+    ///
+    /// assert_eq!(
+    ///     html,
+    ///     r#"<ul id="menu"><li><a>Home</a></li><li><a class="active">About</a></li></ul>"#
+    /// );
+    /// ```
+    ///
+    /// [XPath 1.0]: https://www.w3.org/TR/1999/REC-xpath-19991116/
+    pub fn from_xpath(xpath: &str) -> Result<Self, XPathError> {
+        Ok(xpath_to_css(xpath)?.parse()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::errors::SelectorError;
+
+    macro_rules! assert_css {
+        ($xpath:expr, $css:expr) => {
+            assert_eq!(xpath_to_css($xpath), Ok($css.to_string()), "{}", $xpath);
+            assert!(Selector::from_xpath($xpath).is_ok(), "{}", $xpath);
+        };
+    }
+
+    macro_rules! assert_err {
+        ($xpath:expr, $err:expr) => {
+            assert_eq!(Selector::from_xpath($xpath).err(), Some($err), "{}", $xpath);
+        };
+    }
+
+    #[test]
+    fn location_paths() {
+        assert_css!("//div", "div");
+        assert_css!("div", "div");
+        assert_css!("//*", "*");
+        assert_css!("/html/body/div", "html > body > div");
+        assert_css!("//div/p//a", "div > p a");
+        assert_css!("//div/child::p/descendant::a", "div > p a");
+        assert_css!("//ul | //ol/li", "ul, ol > li");
+        assert_css!(" // div / span ", "div > span");
+    }
+
+    #[test]
+    fn predicates() {
+        assert_css!("//li[2]", "li:nth-of-type(2)");
+        assert_css!("//*[position() = 3]", "*:nth-child(3)");
+        assert_css!("//a[@href]", "a[href]");
+        assert_css!(r#"//a[@href="/"]"#, r#"a[href="/"]"#);
+        assert_css!("//a[@rel!='nofollow']", r#"a[rel]:not([rel="nofollow"])"#);
+        assert_css!(
+            "//a[contains(@class, 'btn')][starts-with(@href, 'http')]",
+            r#"a[class*="btn"][href^="http"]"#
+        );
+        assert_css!("//img[ends-with(@src, '.png')]", r#"img[src$=".png"]"#);
+        assert_css!("//a[contains(@class, '')]", "a[class]");
+        assert_css!(
+            "//li[1][@id and not(@hidden)]",
+            "li:nth-of-type(1)[id]:not([hidden])"
+        );
+        assert_css!(r#"//a[@title='Say "hi"']"#, r#"a[title="Say \"hi\""]"#);
+    }
+
+    #[test]
+    fn text_node_test() {
+        assert_css!("//p//text()", "p::text");
+        assert_css!("//div[@id='x']/descendant::text()", r#"div[id="x"]::text"#);
+        assert_err!("//p/text()", XPathError::UnsupportedTextNodeTest);
+        assert_err!("//text()", XPathError::UnsupportedTextNodeTest);
+        assert_err!("//p//text()/a", XPathError::UnsupportedTextNodeTest);
+    }
+
+    #[test]
+    fn errors() {
+        assert_err!("", XPathError::EmptyExpression);
+        assert_err!("//div/", XPathError::UnexpectedEnd);
+        assert_err!("//div[", XPathError::UnexpectedEnd);
+        assert_err!("//div]", XPathError::UnexpectedCharacter(']'));
+        assert_err!("/body", XPathError::UnsupportedAbsolutePath);
+        assert_err!("//div/parent::p", XPathError::UnsupportedAxis);
+        assert_err!("//div/following-sibling::p", XPathError::UnsupportedAxis);
+        assert_err!("//div/node()", XPathError::UnsupportedFunction);
+        assert_err!("//div[last()]", XPathError::UnsupportedFunction);
+        assert_err!("//div[@a or @b]", XPathError::UnsupportedPredicate);
+        assert_err!("//div[@a][2]", XPathError::UnsupportedPredicate);
+        assert_err!("//svg:rect", XPathError::UnexpectedCharacter(':'));
+        assert_err!(
+            "//div[not(not(@a))]",
+            XPathError::InvalidSelector(SelectorError::NestedNegation)
+        );
+    }
+}
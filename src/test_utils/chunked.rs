@@ -0,0 +1,192 @@
+use super::golden::{encode, rewrite};
+use super::ASCII_COMPATIBLE_ENCODINGS;
+use crate::Settings;
+use encoding_rs::Encoding;
+use std::slice::Chunks;
+
+/// An input encoded in one of the [`ASCII_COMPATIBLE_ENCODINGS`] and split into chunks of
+/// the same size (the last chunk can be shorter).
+///
+/// [`ASCII_COMPATIBLE_ENCODINGS`]: static.ASCII_COMPATIBLE_ENCODINGS.html
+#[derive(Debug)]
+pub struct ChunkedInput<'b> {
+    /// The encoding of the input.
+    pub encoding: &'static Encoding,
+    /// The size of the chunks.
+    pub chunk_size: usize,
+    /// The encoded input.
+    pub bytes: &'b [u8],
+}
+
+impl<'b> ChunkedInput<'b> {
+    /// Returns an iterator over the chunks of the input.
+    #[inline]
+    pub fn chunks(&self) -> Chunks<'b, u8> {
+        self.bytes.chunks(self.chunk_size)
+    }
+}
+
+/// Invokes `f` for each possible chunked representation of the `input`.
+///
+/// The input is encoded in each of the [`ASCII_COMPATIBLE_ENCODINGS`] that can represent it and
+/// split into chunks of every size from 1 byte to the length of the encoded input. The last of
+/// the representations for each encoding is always the input in a single chunk.
+///
+/// [`ASCII_COMPATIBLE_ENCODINGS`]: static.ASCII_COMPATIBLE_ENCODINGS.html
+pub fn for_each_chunked_input(input: &str, mut f: impl FnMut(&ChunkedInput)) {
+    for &encoding in ASCII_COMPATIBLE_ENCODINGS.iter() {
+        let bytes = match encode(input, encoding) {
+            Some(bytes) => bytes,
+            None => continue,
+        };
+
+        for chunk_size in 1..=bytes.len().max(1) {
+            f(&ChunkedInput {
+                encoding,
+                chunk_size,
+                bytes: &bytes,
+            });
+        }
+    }
+}
+
+/// Asserts that the output of the rewriter with the settings produced by `settings_factory`
+/// doesn't depend on how the `input` is split into chunks.
+///
+/// For each chunked representation of the input produced by [`for_each_chunked_input`], the output
+/// is compared with the output for the same input written in a single chunk. The `encoding` field
+/// of the produced settings is overridden for each check.
+///
+/// This is useful to verify that handlers don't rely on the boundaries of the text chunks, e.g.
+/// that a text handler accumulates the text until the last chunk of the text node.
+///
+/// # Panics
+/// If the output differs between the chunked representations, or if the rewriter fails.
+///
+/// # Example
+/// ```
+/// # #[cfg(feature = "test_support")] {
+/// use lol_html::{element, Settings};
+/// use lol_html::html_content::ContentType;
+/// use lol_html::test_utils::assert_chunk_boundary_safe;
+///
+/// assert_chunk_boundary_safe("<div><span>Hi</span></div>", || Settings {
+///     element_content_handlers: vec![element!("span", |el| {
+///         el.after("!", ContentType::Text);
+///         Ok(())
+///     })],
+///     ..Settings::default()
+/// });
+/// # }
+/// ```
+///
+/// [`for_each_chunked_input`]: fn.for_each_chunked_input.html
+pub fn assert_chunk_boundary_safe<'h, 's>(
+    input: &str,
+    mut settings_factory: impl FnMut() -> Settings<'h, 's>,
+) {
+    let mut single_chunk_output = None;
+
+    for_each_chunked_input(input, |chunked| {
+        let single_chunk_size = chunked.bytes.len().max(1);
+
+        // NOTE: the single chunk representation comes last for each encoding, so we
+        // compute its output upfront.
+        if chunked.chunk_size == 1 {
+            single_chunk_output = Some(rewrite(
+                chunked.bytes,
+                single_chunk_size,
+                chunked.encoding,
+                settings_factory(),
+            ));
+        }
+
+        if chunked.chunk_size == single_chunk_size {
+            return;
+        }
+
+        let expected = single_chunk_output.as_ref().unwrap();
+        let actual = rewrite(
+            chunked.bytes,
+            chunked.chunk_size,
+            chunked.encoding,
+            settings_factory(),
+        );
+
+        assert!(
+            actual == *expected,
+            "Chunk boundary dependent output (encoding: {}, chunk size: {}).\n\
+             Single chunk output:\n{}\n\
+             Chunked output:\n{}",
+            chunked.encoding.name(),
+            chunked.chunk_size,
+            expected,
+            actual
+        );
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::html_content::ContentType;
+    use crate::*;
+
+    #[test]
+    fn chunked_inputs() {
+        let mut utf8_chunk_sizes = Vec::new();
+        let mut encodings = 0;
+
+        for_each_chunked_input("<p>Привет</p>", |chunked| {
+            if chunked.chunk_size == 1 {
+                encodings += 1;
+            }
+
+            if chunked.encoding == encoding_rs::UTF_8 {
+                assert_eq!(chunked.chunks().collect::<Vec<_>>().concat(), chunked.bytes);
+
+                utf8_chunk_sizes.push(chunked.chunk_size);
+            }
+        });
+
+        assert_eq!(utf8_chunk_sizes, (1..=19).collect::<Vec<_>>());
+
+        // NOTE: encodings that can't represent Cyrillic are skipped.
+        assert!(encodings > 1 && encodings < ASCII_COMPATIBLE_ENCODINGS.len());
+    }
+
+    #[test]
+    fn chunk_boundary_safe_handlers() {
+        assert_chunk_boundary_safe("<p>Привет, <b>world</b></p>", || {
+            let mut text = String::new();
+
+            Settings {
+                element_content_handlers: vec![text!("p", move |t| {
+                    text += t.as_str();
+
+                    if t.last_in_text_node() {
+                        t.replace(&text.to_uppercase(), ContentType::Text);
+                        text.clear();
+                    } else {
+                        t.remove();
+                    }
+
+                    Ok(())
+                })],
+                ..Settings::default()
+            }
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "Chunk boundary dependent output")]
+    fn chunk_boundary_dependent_handlers() {
+        assert_chunk_boundary_safe("<p>Hello</p>", || Settings {
+            element_content_handlers: vec![text!("p", |t| {
+                t.replace("|", ContentType::Text);
+                Ok(())
+            })],
+            ..Settings::default()
+        });
+    }
+}
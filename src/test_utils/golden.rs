@@ -0,0 +1,250 @@
+use super::{Output, ASCII_COMPATIBLE_ENCODINGS};
+use crate::{HtmlRewriter, Settings};
+use encoding_rs::Encoding;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+const CHUNK_SIZES: &[usize] = &[1, 2, 3, 5, 8, 13, 64];
+const UPDATE_ENV_VAR: &str = "LOL_HTML_UPDATE_GOLDEN";
+const INPUT_SUFFIX: &str = ".src.html";
+const EXPECTED_SUFFIX: &str = ".expected.html";
+
+pub(super) fn rewrite<'h, 's>(
+    input: &[u8],
+    chunk_size: usize,
+    encoding: &'static Encoding,
+    settings: Settings<'h, 's>,
+) -> String {
+    let mut output = Output::new(encoding);
+
+    {
+        let mut rewriter = HtmlRewriter::try_new(
+            Settings {
+                encoding: encoding.name(),
+                ..settings
+            },
+            |c: &[u8]| output.push(c),
+        )
+        .unwrap();
+
+        for chunk in input.chunks(chunk_size) {
+            rewriter.write(chunk).unwrap();
+        }
+
+        rewriter.end().unwrap();
+    }
+
+    output.into()
+}
+
+pub(super) fn encode(text: &str, encoding: &'static Encoding) -> Option<Vec<u8>> {
+    let (bytes, _, has_unmappable_characters) = encoding.encode(text);
+
+    // NOTE: some encodings deviate from the round-trip behaviour for certain characters,
+    // so we skip such encodings for the text, as it's done for the functional tests.
+    if has_unmappable_characters || encoding.decode_without_bom_handling(&bytes).0 != text {
+        None
+    } else {
+        Some(bytes.into_owned())
+    }
+}
+
+/// Asserts that rewriting `input` with the settings produced by `settings_factory` yields the
+/// `expected` output.
+///
+/// The check is performed for each of the [`ASCII_COMPATIBLE_ENCODINGS`] that can represent both
+/// the input and the expected output, with the input split into chunks of various sizes. The
+/// `encoding` field of the produced settings is overridden for each check.
+///
+/// # Panics
+/// If the output differs from the expected one, or if the rewriter fails.
+///
+/// # Example
+/// ```
+/// # #[cfg(feature = "test_support")] {
+/// use lol_html::{element, Settings};
+/// use lol_html::test_utils::assert_golden_output;
+///
+/// assert_golden_output("<div><span>Hi</span></div>", "<div><span>Hi</span>!</div>", || {
+///     Settings {
+///         element_content_handlers: vec![element!("span", |el| {
+///             el.after("!", lol_html::html_content::ContentType::Text);
+///             Ok(())
+///         })],
+///         ..Settings::default()
+///     }
+/// });
+/// # }
+/// ```
+///
+/// [`ASCII_COMPATIBLE_ENCODINGS`]: static.ASCII_COMPATIBLE_ENCODINGS.html
+pub fn assert_golden_output<'h, 's>(
+    input: &str,
+    expected: &str,
+    mut settings_factory: impl FnMut() -> Settings<'h, 's>,
+) {
+    for &encoding in ASCII_COMPATIBLE_ENCODINGS.iter() {
+        let bytes = match (encode(input, encoding), encode(expected, encoding)) {
+            (Some(bytes), Some(_)) => bytes,
+            _ => continue,
+        };
+
+        let single_chunk_size = bytes.len().max(1);
+
+        for &chunk_size in CHUNK_SIZES.iter().chain(Some(&single_chunk_size)) {
+            let actual = rewrite(&bytes, chunk_size, encoding, settings_factory());
+
+            assert!(
+                actual == expected,
+                "Golden output mismatch (encoding: {}, chunk size: {}).\n\
+                 Expected:\n{}\n\
+                 Actual:\n{}",
+                encoding.name(),
+                chunk_size,
+                expected,
+                actual
+            );
+        }
+    }
+}
+
+/// A golden test case loaded by [`golden_fixtures`].
+///
+/// [`golden_fixtures`]: fn.golden_fixtures.html
+#[derive(Debug)]
+pub struct GoldenFixture {
+    /// The name of the fixture, i.e. the input file name without the `.src.html` suffix.
+    pub name: String,
+    /// The input of the rewriter.
+    pub input: String,
+    /// The expected output of the rewriter.
+    pub expected: String,
+    expected_path: PathBuf,
+}
+
+impl GoldenFixture {
+    /// Asserts that rewriting the fixture input with the settings produced by `settings_factory`
+    /// yields the expected output. See [`assert_golden_output`] for the details.
+    ///
+    /// If the `LOL_HTML_UPDATE_GOLDEN` environment variable is set, the expected output file is
+    /// overwritten with the actual output instead.
+    ///
+    /// [`assert_golden_output`]: fn.assert_golden_output.html
+    pub fn assert_output<'h, 's>(&self, mut settings_factory: impl FnMut() -> Settings<'h, 's>) {
+        if env::var_os(UPDATE_ENV_VAR).is_some() {
+            let input = self.input.as_bytes();
+            let output = rewrite(
+                input,
+                input.len().max(1),
+                encoding_rs::UTF_8,
+                settings_factory(),
+            );
+
+            fs::write(&self.expected_path, output).unwrap_or_else(|e| {
+                panic!("Failed to update `{}`: {}", self.expected_path.display(), e)
+            });
+        } else {
+            assert_golden_output(&self.input, &self.expected, settings_factory);
+        }
+    }
+}
+
+/// Loads golden test cases from the `dir`.
+///
+/// Each test case consists of the `<name>.src.html` input file and the `<name>.expected.html`
+/// file with the expected output, both in UTF-8. A missing expected output file is an error,
+/// unless the `LOL_HTML_UPDATE_GOLDEN` environment variable is set. Fixtures are sorted by name.
+pub fn golden_fixtures(dir: impl AsRef<Path>) -> io::Result<Vec<GoldenFixture>> {
+    let dir = dir.as_ref();
+    let update = env::var_os(UPDATE_ENV_VAR).is_some();
+    let mut fixtures = Vec::new();
+
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+
+        let name = match path.file_name().and_then(|n| n.to_str()) {
+            Some(file_name) if file_name.ends_with(INPUT_SUFFIX) => {
+                file_name[..file_name.len() - INPUT_SUFFIX.len()].to_string()
+            }
+            _ => continue,
+        };
+
+        let expected_path = dir.join(format!("{}{}", name, EXPECTED_SUFFIX));
+
+        let expected = match fs::read_to_string(&expected_path) {
+            Ok(expected) => expected,
+            Err(ref e) if update && e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e),
+        };
+
+        fixtures.push(GoldenFixture {
+            name,
+            input: fs::read_to_string(&path)?,
+            expected,
+            expected_path,
+        });
+    }
+
+    fixtures.sort_by(|a, b| a.name.cmp(&b.name));
+
+    Ok(fixtures)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::html_content::ContentType;
+    use crate::*;
+
+    fn settings() -> Settings<'static, 'static> {
+        Settings {
+            element_content_handlers: vec![element!("b", |el| {
+                el.set_tag_name("strong")?;
+                el.append("ü", ContentType::Text);
+                Ok(())
+            })],
+            ..Settings::default()
+        }
+    }
+
+    #[test]
+    fn matching_output() {
+        assert_golden_output(
+            "<p><b>Привет</b>, <b>world</b></p>",
+            "<p><strong>Приветü</strong>, <strong>worldü</strong></p>",
+            settings,
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Golden output mismatch")]
+    fn mismatching_output() {
+        assert_golden_output("<b>Hi</b>", "<b>Hi</b>", settings);
+    }
+
+    #[test]
+    fn fixtures_loading() {
+        let dir = env::temp_dir().join(format!("lol_html_golden_{}", std::process::id()));
+
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("b.src.html"), "<b>2</b>").unwrap();
+        fs::write(dir.join("b.expected.html"), "<strong>2ü</strong>").unwrap();
+        fs::write(dir.join("a.src.html"), "<b>1</b>").unwrap();
+        fs::write(dir.join("a.expected.html"), "<strong>1ü</strong>").unwrap();
+        fs::write(dir.join("readme.txt"), "").unwrap();
+
+        let fixtures = golden_fixtures(&dir).unwrap();
+
+        fs::remove_dir_all(&dir).unwrap();
+
+        let names: Vec<_> = fixtures.iter().map(|f| f.name.as_str()).collect();
+
+        assert_eq!(names, ["a", "b"]);
+
+        for fixture in fixtures {
+            fixture.assert_output(settings);
+        }
+    }
+}
@@ -0,0 +1,243 @@
+use super::OutputSink;
+
+/// The framing of the rewritten body chosen by the [`ContentLengthSink`].
+///
+/// [`ContentLengthSink`]: struct.ContentLengthSink.html
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BodyFraming {
+    /// The whole body is known upfront and should be sent with the `Content-Length` header
+    /// set to the contained length.
+    ContentLength(usize),
+    /// The body is streamed, e.g. with `Transfer-Encoding: chunked`. Its length is known only
+    /// once the body ends and is reported by [`BodyEvent::End`], so it can be sent in a trailer.
+    ///
+    /// [`BodyEvent::End`]: enum.BodyEvent.html#variant.End
+    Chunked,
+}
+
+/// An event produced by the [`ContentLengthSink`].
+///
+/// [`ContentLengthSink`]: struct.ContentLengthSink.html
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BodyEvent<'b> {
+    /// The framing of the body. Produced exactly once, before any of the body's data.
+    Framing(BodyFraming),
+    /// A chunk of the body.
+    Data(&'b [u8]),
+    /// The end of the body with the total length of the rewritten content.
+    End {
+        /// The total length of the body in bytes.
+        content_length: usize,
+    },
+}
+
+/// An [`OutputSink`] that determines the framing of the rewritten body for HTTP proxies.
+///
+/// The length of the rewritten content usually differs from the length of the input, so
+/// the `Content-Length` header of the original response can't be forwarded as is. The sink
+/// buffers up to `max_buffered_len` bytes of the output: if the rewriter's output ends within
+/// that limit, the body is framed with [`BodyFraming::ContentLength`] of the exact length.
+/// Otherwise, the body is streamed as soon as the limit is exceeded and framed with
+/// [`BodyFraming::Chunked`], and the total length is reported once the output ends, e.g. to be
+/// sent in a trailer.
+///
+/// # Example
+/// ```
+/// use lol_html::{element, BodyEvent, BodyFraming, ContentLengthSink, HtmlRewriter, Settings};
+///
+/// let mut framing = None;
+/// let mut body = vec![];
+///
+/// {
+///     let mut rewriter = HtmlRewriter::try_new(
+///         Settings {
+///             element_content_handlers: vec![
+///                 element!("img", |el| {
+///                     el.set_attribute("loading", "lazy").unwrap();
+///
+///                     Ok(())
+///                 })
+///             ],
+///             ..Settings::default()
+///         },
+///         ContentLengthSink::new(4096, |event| match event {
+///             BodyEvent::Framing(f) => framing = Some(f),
+///             BodyEvent::Data(data) => body.extend_from_slice(data),
+///             BodyEvent::End { .. } => (),
+///         })
+///     ).unwrap();
+///
+///     rewriter.write(b"<img src=a.png>").unwrap();
+///     rewriter.end().unwrap();
+/// }
+///
+/// assert_eq!(framing, Some(BodyFraming::ContentLength(30)));
+/// assert_eq!(body, br#"<img src=a.png loading="lazy">"#);
+/// ```
+///
+/// [`OutputSink`]: trait.OutputSink.html
+/// [`BodyFraming::ContentLength`]: enum.BodyFraming.html#variant.ContentLength
+/// [`BodyFraming::Chunked`]: enum.BodyFraming.html#variant.Chunked
+pub struct ContentLengthSink<F: FnMut(BodyEvent)> {
+    event_handler: F,
+    max_buffered_len: usize,
+    buffer: Vec<u8>,
+    content_length: usize,
+    streaming: bool,
+}
+
+impl<F: FnMut(BodyEvent)> ContentLengthSink<F> {
+    /// Creates a sink that buffers up to `max_buffered_len` bytes of the output to compute
+    /// the length of the body upfront and passes the produced events to the `event_handler`.
+    #[inline]
+    pub fn new(max_buffered_len: usize, event_handler: F) -> Self {
+        ContentLengthSink {
+            event_handler,
+            max_buffered_len,
+            buffer: Vec::default(),
+            content_length: 0,
+            streaming: false,
+        }
+    }
+
+    fn start_streaming(&mut self) {
+        (self.event_handler)(BodyEvent::Framing(BodyFraming::Chunked));
+
+        if !self.buffer.is_empty() {
+            (self.event_handler)(BodyEvent::Data(&self.buffer));
+        }
+
+        self.buffer = Vec::default();
+        self.streaming = true;
+    }
+
+    fn end(&mut self) {
+        if !self.streaming {
+            (self.event_handler)(BodyEvent::Framing(BodyFraming::ContentLength(
+                self.content_length,
+            )));
+
+            if !self.buffer.is_empty() {
+                (self.event_handler)(BodyEvent::Data(&self.buffer));
+            }
+
+            self.buffer = Vec::default();
+        }
+
+        (self.event_handler)(BodyEvent::End {
+            content_length: self.content_length,
+        });
+    }
+}
+
+impl<F: FnMut(BodyEvent)> OutputSink for ContentLengthSink<F> {
+    #[inline]
+    fn handle_chunk(&mut self, chunk: &[u8]) {
+        if chunk.is_empty() {
+            // NOTE: the last chunk of the output.
+            self.end();
+            return;
+        }
+
+        self.content_length += chunk.len();
+
+        if !self.streaming && self.content_length > self.max_buffered_len {
+            self.start_streaming();
+        }
+
+        if self.streaming {
+            (self.event_handler)(BodyEvent::Data(chunk));
+        } else {
+            self.buffer.extend_from_slice(chunk);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::html_content::ContentType;
+    use crate::*;
+
+    #[derive(Debug, PartialEq, Eq)]
+    enum OwnedEvent {
+        Framing(BodyFraming),
+        Data(String),
+        End(usize),
+    }
+
+    fn rewrite(input: &str, max_buffered_len: usize) -> Vec<OwnedEvent> {
+        let mut events = vec![];
+
+        {
+            let mut rewriter = HtmlRewriter::try_new(
+                Settings {
+                    element_content_handlers: vec![element!("p", |el| {
+                        el.prepend("[", ContentType::Text);
+                        el.append("]", ContentType::Text);
+                        Ok(())
+                    })],
+                    ..Settings::default()
+                },
+                ContentLengthSink::new(max_buffered_len, |event| {
+                    events.push(match event {
+                        BodyEvent::Framing(f) => OwnedEvent::Framing(f),
+                        BodyEvent::Data(d) => {
+                            OwnedEvent::Data(String::from_utf8(d.to_vec()).unwrap())
+                        }
+                        BodyEvent::End { content_length } => OwnedEvent::End(content_length),
+                    })
+                }),
+            )
+            .unwrap();
+
+            for chunk in input.as_bytes().chunks(4) {
+                rewriter.write(chunk).unwrap();
+            }
+
+            rewriter.end().unwrap();
+        }
+
+        events
+    }
+
+    #[test]
+    fn content_length() {
+        let events = rewrite("<p>foo</p><p>bar</p>", 1024);
+
+        assert_eq!(
+            events,
+            [
+                OwnedEvent::Framing(BodyFraming::ContentLength(24)),
+                OwnedEvent::Data("<p>[foo]</p><p>[bar]</p>".into()),
+                OwnedEvent::End(24)
+            ]
+        );
+
+        assert_eq!(
+            rewrite("", 1024),
+            [
+                OwnedEvent::Framing(BodyFraming::ContentLength(0)),
+                OwnedEvent::End(0)
+            ]
+        );
+    }
+
+    #[test]
+    fn chunked() {
+        let events = rewrite("<p>foo</p><p>bar</p>", 10);
+
+        assert_eq!(events[0], OwnedEvent::Framing(BodyFraming::Chunked));
+        assert_eq!(events.last(), Some(&OwnedEvent::End(24)));
+
+        let body = events[1..events.len() - 1]
+            .iter()
+            .map(|e| match e {
+                OwnedEvent::Data(d) => d.as_str(),
+                _ => panic!("Unexpected event: {:?}", e),
+            })
+            .collect::<String>();
+
+        assert_eq!(body, "<p>[foo]</p><p>[bar]</p>");
+    }
+}
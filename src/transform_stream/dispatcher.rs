@@ -1,6 +1,7 @@
 use super::*;
 use crate::base::{Bytes, Range};
 use crate::html::{LocalName, Namespace};
+use crate::memory::SharedMemoryLimiter;
 use crate::parser::{
     Lexeme, LexemeSink, NonTagContentLexeme, ParserDirective, ParserOutputSink, TagHintSink,
     TagLexeme, TagTokenOutline,
@@ -14,6 +15,9 @@ use std::rc::Rc;
 
 use TagTokenOutline::*;
 
+// NOTE: the auxiliary start tag info is only requested by the selector matching VM, but the
+// dispatcher handles the requests regardless of the enabled features.
+#[cfg_attr(not(feature = "element_handlers"), allow(dead_code))]
 pub struct AuxStartTagInfo<'i> {
     pub input: &'i Bytes<'i>,
     pub attr_buffer: SharedAttributeBuffer,
@@ -23,6 +27,7 @@ pub struct AuxStartTagInfo<'i> {
 type AuxStartTagInfoRequest<C> =
     Box<dyn FnOnce(&mut C, AuxStartTagInfo<'_>) -> Result<TokenCaptureFlags, RewritingError>>;
 
+#[cfg_attr(not(feature = "element_handlers"), allow(dead_code))]
 pub enum DispatcherError<C> {
     InfoRequest(AuxStartTagInfoRequest<C>),
     RewritingError(RewritingError),
@@ -37,12 +42,21 @@ pub trait TransformController: Sized {
     fn handle_token(&mut self, token: &mut Token) -> Result<(), RewritingError>;
     fn handle_end(&mut self, document_end: &mut DocumentEnd) -> Result<(), RewritingError>;
     fn should_emit_content(&self) -> bool;
+
+    #[inline]
+    fn capture_flags_after_start_tag(&self) -> Option<TokenCaptureFlags> {
+        None
+    }
 }
 
 /// Defines an interface for the [`HtmlRewriter`]'s output.
 ///
 /// Implemented for [`Fn`] and [`FnMut`].
 ///
+/// The rewriter is generic over the output sink and serializes the content without dynamic
+/// dispatch, so calls to the sink can be inlined. Sinks that need to be chosen at runtime can
+/// be passed as a boxed closure, e.g. `Box<dyn FnMut(&[u8])>`.
+///
 /// [`HtmlRewriter`]: struct.HtmlRewriter.html
 /// [`Fn`]: https://doc.rust-lang.org/std/ops/trait.Fn.html
 /// [`FnMut`]: https://doc.rust-lang.org/std/ops/trait.FnMut.html
@@ -52,6 +66,22 @@ pub trait OutputSink {
     /// # Note
     /// The last chunk of the output has zero length.
     fn handle_chunk(&mut self, chunk: &[u8]);
+
+    /// Handles rewriter's output chunk that is an unmodified copy of the input at the
+    /// `input_offset`.
+    ///
+    /// The default implementation passes non-empty chunks to [`handle_chunk`]. An empty chunk
+    /// is produced once the input ends, with the `input_offset` equal to the input length.
+    ///
+    /// [`handle_chunk`]: #tymethod.handle_chunk
+    #[inline]
+    fn handle_unmodified_chunk(&mut self, chunk: &[u8], input_offset: usize) {
+        let _ = input_offset;
+
+        if !chunk.is_empty() {
+            self.handle_chunk(chunk);
+        }
+    }
 }
 
 impl<F: FnMut(&[u8])> OutputSink for F {
@@ -60,6 +90,22 @@ impl<F: FnMut(&[u8])> OutputSink for F {
     }
 }
 
+#[inline]
+fn emit_token(token: &Token, lexeme_start: Option<usize>, output_sink: &mut impl OutputSink) {
+    let input_start = match token {
+        Token::TextChunk(text_chunk) => Some(text_chunk.byte_range().start),
+        _ => lexeme_start,
+    };
+
+    match input_start {
+        Some(mut input_offset) if token.is_verbatim() => token.to_bytes(&mut |c| {
+            output_sink.handle_unmodified_chunk(c, input_offset);
+            input_offset += c.len();
+        }),
+        _ => token.to_bytes(&mut |c| output_sink.handle_chunk(c)),
+    }
+}
+
 pub struct Dispatcher<C, O>
 where
     C: TransformController,
@@ -68,6 +114,7 @@ where
     transform_controller: C,
     output_sink: O,
     remaining_content_start: usize,
+    input_offset: usize,
     token_capturer: TokenCapturer,
     got_flags_from_hint: bool,
     pending_element_aux_info_req: Option<AuxStartTagInfoRequest<C>>,
@@ -80,37 +127,90 @@ where
     C: TransformController,
     O: OutputSink,
 {
-    pub fn new(transform_controller: C, output_sink: O, encoding: &'static Encoding) -> Self {
+    pub fn new(
+        transform_controller: C,
+        output_sink: O,
+        encoding: &'static Encoding,
+        memory_limiter: SharedMemoryLimiter,
+        buffer_script_and_style_text: bool,
+    ) -> Self {
         let initial_capture_flags = transform_controller.initial_capture_flags();
+        let emission_enabled = transform_controller.should_emit_content();
 
         Dispatcher {
             transform_controller,
             output_sink,
             remaining_content_start: 0,
-            token_capturer: TokenCapturer::new(initial_capture_flags, encoding),
+            input_offset: 0,
+            token_capturer: TokenCapturer::new(
+                initial_capture_flags,
+                encoding,
+                memory_limiter,
+                buffer_script_and_style_text,
+            ),
             got_flags_from_hint: false,
             pending_element_aux_info_req: None,
-            emission_enabled: true,
+            emission_enabled,
             encoding,
         }
     }
 
-    pub fn flush_remaining_input(&mut self, input: &[u8], consumed_byte_count: usize) {
-        let output = &input[self.remaining_content_start..consumed_byte_count];
+    #[inline]
+    pub fn output_sink_mut(&mut self) -> &mut O {
+        &mut self.output_sink
+    }
 
-        if self.emission_enabled && !output.is_empty() {
-            self.output_sink.handle_chunk(&output);
+    #[inline]
+    pub fn transform_controller_mut(&mut self) -> &mut C {
+        &mut self.transform_controller
+    }
+
+    /// Emits the consumed part of the `input` that hasn't been emitted yet.
+    ///
+    /// The `input` ends with the `data` written by the caller. The part of the output that
+    /// belongs to the `data` is passed to the output sink as a subslice of the `data` rather
+    /// than of the `input`, so the caller's chunk gets to the output sink without copies even
+    /// if the `input` is a buffered copy of it.
+    pub fn flush_remaining_input(&mut self, input: &[u8], data: &[u8], consumed_byte_count: usize) {
+        if self.emission_enabled {
+            let data_start = input.len() - data.len();
+            let start = self.remaining_content_start;
+
+            if start < data_start {
+                let output = &input[start..consumed_byte_count.min(data_start)];
+
+                if !output.is_empty() {
+                    self.output_sink
+                        .handle_unmodified_chunk(output, self.input_offset + start);
+                }
+            }
+
+            if consumed_byte_count > data_start {
+                let start = start.max(data_start);
+                let output = &data[start - data_start..consumed_byte_count - data_start];
+
+                if !output.is_empty() {
+                    self.output_sink
+                        .handle_unmodified_chunk(output, self.input_offset + start);
+                }
+            }
         }
 
         self.remaining_content_start = 0;
+
+        // NOTE: the next chunk starts with the bytes that haven't been consumed.
+        self.input_offset += consumed_byte_count;
     }
 
     pub fn finish(&mut self, input: &[u8]) -> Result<(), RewritingError> {
-        self.flush_remaining_input(input, input.len());
+        self.flush_remaining_input(input, &[], input.len());
+        self.output_sink
+            .handle_unmodified_chunk(&[], self.input_offset);
 
         let mut document_end = DocumentEnd::new(&mut self.output_sink, self.encoding);
 
         self.transform_controller.handle_end(&mut document_end)?;
+        document_end.flush();
 
         // NOTE: output the finalizing chunk.
         self.output_sink.handle_chunk(&[]);
@@ -125,14 +225,21 @@ where
     where
         Lexeme<'i, T>: ToToken,
     {
+        // NOTE: fast path - if nothing is captured the lexeme can't produce a token, so
+        // its raw bytes stay in the remaining content and are flushed to the output as is.
+        if self.token_capturer.is_idle() {
+            return Ok(());
+        }
+
         let transform_controller = &mut self.transform_controller;
         let output_sink = &mut self.output_sink;
         let emission_enabled = self.emission_enabled;
         let lexeme_range = lexeme.raw_range();
         let remaining_content_start = self.remaining_content_start;
+        let input_offset = self.input_offset;
         let mut lexeme_consumed = false;
 
-        self.token_capturer.feed(lexeme, |event| {
+        self.token_capturer.feed(lexeme, input_offset, |event| {
             match event {
                 TokenCapturerEvent::LexemeConsumed => {
                     let chunk = lexeme.input().slice(Range {
@@ -143,7 +250,10 @@ where
                     lexeme_consumed = true;
 
                     if emission_enabled && chunk.len() > 0 {
-                        output_sink.handle_chunk(&chunk);
+                        output_sink.handle_unmodified_chunk(
+                            &chunk,
+                            input_offset + remaining_content_start,
+                        );
                     }
                 }
                 TokenCapturerEvent::TokenProduced(mut token) => {
@@ -152,7 +262,7 @@ where
                     transform_controller.handle_token(&mut token)?;
 
                     if emission_enabled {
-                        token.to_bytes(&mut |c| output_sink.handle_chunk(c));
+                        emit_token(&token, Some(input_offset + lexeme_range.start), output_sink);
                     }
                 }
             }
@@ -266,7 +376,7 @@ where
                 transform_controller.handle_token(&mut token)?;
 
                 if emission_enabled {
-                    token.to_bytes(&mut |c| output_sink.handle_chunk(c));
+                    emit_token(&token, None, output_sink);
                 }
             }
 
@@ -311,6 +421,14 @@ where
         self.try_produce_token_from_lexeme(lexeme)?;
         self.emission_enabled = self.transform_controller.should_emit_content();
 
+        // NOTE: start tag handlers can request more content of the element
+        // to be captured, so we need to update the capture flags.
+        if let TagTokenOutline::StartTag { .. } = lexeme.token_outline() {
+            if let Some(flags) = self.transform_controller.capture_flags_after_start_tag() {
+                self.token_capturer.set_capture_flags(flags);
+            }
+        }
+
         Ok(self.get_next_parser_directive())
     }
 
@@ -1,16 +1,20 @@
+mod content_length_sink;
 mod dispatcher;
+mod patch_sink;
 
 use self::dispatcher::Dispatcher;
 use crate::memory::{Arena, SharedMemoryLimiter};
 use crate::parser::{Parser, ParserDirective, SharedAttributeBuffer};
 use crate::rewriter::RewritingError;
 use encoding_rs::Encoding;
-use std::cell::RefCell;
+use std::cell::{RefCell, RefMut};
 use std::rc::Rc;
 
-pub use self::dispatcher::{
-    AuxStartTagInfo, DispatcherError, OutputSink, StartTagHandlingResult, TransformController,
-};
+pub use self::content_length_sink::{BodyEvent, BodyFraming, ContentLengthSink};
+#[cfg(feature = "element_handlers")]
+pub use self::dispatcher::{AuxStartTagInfo, DispatcherError};
+pub use self::dispatcher::{OutputSink, StartTagHandlingResult, TransformController};
+pub use self::patch_sink::{Patch, PatchSink};
 
 pub struct TransformStreamSettings<C, O>
 where
@@ -20,9 +24,12 @@ where
     pub transform_controller: C,
     pub output_sink: O,
     pub preallocated_parsing_buffer_size: usize,
+    pub parsing_buffer_compaction_watermark: usize,
+    pub parsing_buffer_shrink_watermark: usize,
     pub memory_limiter: SharedMemoryLimiter,
     pub encoding: &'static Encoding,
     pub strict: bool,
+    pub buffer_script_and_style_text: bool,
 }
 
 pub struct TransformStream<C, O>
@@ -56,12 +63,16 @@ where
             settings.transform_controller,
             settings.output_sink,
             settings.encoding,
+            Rc::clone(&settings.memory_limiter),
+            settings.buffer_script_and_style_text,
         )));
 
         let buffer = Arena::new(
             settings.memory_limiter,
             settings.preallocated_parsing_buffer_size,
-        );
+        )
+        .with_compaction_watermark(settings.parsing_buffer_compaction_watermark)
+        .with_shrink_watermark(settings.parsing_buffer_shrink_watermark);
 
         let parser = Parser::new(&dispatcher, initial_parser_directive, settings.strict);
 
@@ -73,6 +84,16 @@ where
         }
     }
 
+    #[inline]
+    pub fn output_sink_mut(&self) -> RefMut<O> {
+        RefMut::map(self.dispatcher.borrow_mut(), |d| d.output_sink_mut())
+    }
+
+    #[inline]
+    pub fn transform_controller_mut(&self) -> RefMut<'_, C> {
+        RefMut::map(self.dispatcher.borrow_mut(), |d| d.transform_controller_mut())
+    }
+
     fn buffer_blocked_bytes(
         &mut self,
         data: &[u8],
@@ -112,11 +133,12 @@ where
 
         self.dispatcher
             .borrow_mut()
-            .flush_remaining_input(&chunk, consumed_byte_count);
+            .flush_remaining_input(chunk, data, consumed_byte_count);
 
         if consumed_byte_count < chunk.len() {
             self.buffer_blocked_bytes(data, consumed_byte_count)?;
-        } else {
+        } else if self.has_buffered_data {
+            self.buffer.reset();
             self.has_buffered_data = false;
         }
 
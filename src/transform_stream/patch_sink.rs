@@ -0,0 +1,261 @@
+use super::OutputSink;
+use std::ops::Range;
+
+/// A patch to the input of the [`HtmlRewriter`] produced by the [`PatchSink`].
+///
+/// [`HtmlRewriter`]: struct.HtmlRewriter.html
+/// [`PatchSink`]: struct.PatchSink.html
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Patch {
+    /// The byte range of the input that should be replaced. The range is empty for insertions.
+    pub input_range: Range<usize>,
+    /// The bytes that should be written in place of the input range. Empty for removals.
+    pub replacement: Vec<u8>,
+}
+
+/// An [`OutputSink`] that, instead of the rewritten document, produces a series of [`Patch`]es
+/// to the input of the [`HtmlRewriter`].
+///
+/// Patches are produced in order of their input ranges and the ranges don't overlap, so
+/// applying all the patches to the input produces the rewritten document. Unmodified parts of
+/// the input are never included in the patches, so the patches can be persisted as a compact
+/// delta of the rewriting. A patch is handed over to the `patch_handler` as soon as the
+/// rewriter reaches the next unmodified part of the input, or once the input ends.
+///
+/// # Note
+/// Text of documents in encodings other than UTF-8 is always included in the patches if it's
+/// captured by a content handler, since the text is re-encoded on serialization.
+///
+/// # Example
+/// ```
+/// use lol_html::{element, HtmlRewriter, Patch, PatchSink, Settings};
+///
+/// let mut patches = vec![];
+///
+/// {
+///     let mut rewriter = HtmlRewriter::try_new(
+///         Settings {
+///             element_content_handlers: vec![
+///                 element!("img", |el| {
+///                     el.set_attribute("loading", "lazy").unwrap();
+///
+///                     Ok(())
+///                 })
+///             ],
+///             ..Settings::default()
+///         },
+///         PatchSink::new(|patch| patches.push(patch))
+///     ).unwrap();
+///
+///     rewriter.write(b"<p>Hello</p><img src=a.png><p>Bye</p>").unwrap();
+///     rewriter.end().unwrap();
+/// }
+///
+/// assert_eq!(
+///     patches,
+///     [Patch {
+///         input_range: 12..27,
+///         replacement: br#"<img src=a.png loading="lazy">"#.to_vec(),
+///     }]
+/// );
+/// ```
+///
+/// [`OutputSink`]: trait.OutputSink.html
+/// [`Patch`]: struct.Patch.html
+/// [`HtmlRewriter`]: struct.HtmlRewriter.html
+pub struct PatchSink<F: FnMut(Patch)> {
+    patch_handler: F,
+    input_pos: usize,
+    input_len: usize,
+    replacement: Vec<u8>,
+}
+
+impl<F: FnMut(Patch)> PatchSink<F> {
+    /// Creates a sink that passes produced patches to the `patch_handler`.
+    #[inline]
+    pub fn new(patch_handler: F) -> Self {
+        PatchSink {
+            patch_handler,
+            input_pos: 0,
+            input_len: 0,
+            replacement: Vec::default(),
+        }
+    }
+
+    fn flush(&mut self, input_end: usize) {
+        if input_end > self.input_pos || !self.replacement.is_empty() {
+            (self.patch_handler)(Patch {
+                input_range: self.input_pos..input_end,
+                replacement: std::mem::take(&mut self.replacement),
+            });
+        }
+    }
+}
+
+impl<F: FnMut(Patch)> OutputSink for PatchSink<F> {
+    #[inline]
+    fn handle_chunk(&mut self, chunk: &[u8]) {
+        if chunk.is_empty() {
+            // NOTE: the last chunk of the output.
+            self.flush(self.input_len.max(self.input_pos));
+        } else {
+            self.replacement.extend_from_slice(chunk);
+        }
+    }
+
+    #[inline]
+    fn handle_unmodified_chunk(&mut self, chunk: &[u8], input_offset: usize) {
+        if chunk.is_empty() {
+            // NOTE: the input has ended, but content can still be appended to the document
+            // end, so we defer the last patch until the output ends.
+            self.input_len = input_offset;
+        } else if input_offset < self.input_pos {
+            // NOTE: the chunk can't be represented as a part of the input at this point.
+            self.replacement.extend_from_slice(chunk);
+        } else {
+            self.flush(input_offset);
+            self.input_pos = input_offset + chunk.len();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::html_content::ContentType;
+    use crate::*;
+
+    fn apply_patches(input: &[u8], patches: &[Patch]) -> Vec<u8> {
+        let mut output = Vec::default();
+        let mut input_pos = 0;
+
+        for patch in patches {
+            assert!(patch.input_range.start >= input_pos, "{:?}", patches);
+
+            output.extend_from_slice(&input[input_pos..patch.input_range.start]);
+            output.extend_from_slice(&patch.replacement);
+            input_pos = patch.input_range.end;
+        }
+
+        output.extend_from_slice(&input[input_pos..]);
+
+        output
+    }
+
+    fn rewrite_to_patches(
+        input: &str,
+        chunk_size: usize,
+        settings: impl Fn() -> Settings<'static, 'static>,
+    ) -> Vec<Patch> {
+        let mut patches = vec![];
+        let mut output = vec![];
+
+        {
+            let mut rewriter =
+                HtmlRewriter::try_new(settings(), PatchSink::new(|p| patches.push(p))).unwrap();
+
+            for chunk in input.as_bytes().chunks(chunk_size) {
+                rewriter.write(chunk).unwrap();
+            }
+
+            rewriter.end().unwrap();
+        }
+
+        {
+            let mut rewriter =
+                HtmlRewriter::try_new(settings(), |c: &[u8]| output.extend_from_slice(c)).unwrap();
+
+            rewriter.write(input.as_bytes()).unwrap();
+            rewriter.end().unwrap();
+        }
+
+        assert_eq!(
+            String::from_utf8(apply_patches(input.as_bytes(), &patches)).unwrap(),
+            String::from_utf8(output).unwrap()
+        );
+
+        patches
+    }
+
+    fn patch(input_range: Range<usize>, replacement: &str) -> Patch {
+        Patch {
+            input_range,
+            replacement: replacement.as_bytes().to_vec(),
+        }
+    }
+
+    #[test]
+    fn no_modifications() {
+        for chunk_size in 1..10 {
+            let patches = rewrite_to_patches(
+                "<!doctype html><div><!-- foo --><p>Hello &amp; bye</p></div>",
+                chunk_size,
+                || Settings {
+                    element_content_handlers: vec![
+                        text!("p", |_| Ok(())),
+                        comments!("div", |_| Ok(())),
+                    ],
+                    document_content_handlers: vec![doctype!(|_| Ok(()))],
+                    ..Settings::default()
+                },
+            );
+
+            assert_eq!(patches, []);
+        }
+    }
+
+    #[test]
+    fn modifications() {
+        for chunk_size in 1..10 {
+            let patches = rewrite_to_patches(
+                "<div><span>foo</span><p>bar</p><b>baz</b></div>",
+                chunk_size,
+                || Settings {
+                    element_content_handlers: vec![
+                        element!("span", |el| {
+                            el.remove();
+                            Ok(())
+                        }),
+                        element!("b", |el| {
+                            el.before("[", ContentType::Text);
+                            Ok(())
+                        }),
+                        text!("p", |t| {
+                            t.replace(&t.as_str().to_uppercase(), ContentType::Text);
+                            Ok(())
+                        }),
+                    ],
+                    ..Settings::default()
+                },
+            );
+
+            assert_eq!(
+                patches,
+                [
+                    patch(5..21, ""),
+                    patch(24..27, "BAR"),
+                    patch(31..34, "[<b>")
+                ],
+                "chunk size: {}",
+                chunk_size
+            );
+        }
+    }
+
+    #[test]
+    fn document_end_insertion() {
+        let patches = rewrite_to_patches("<div><p>Hi", 1024, || Settings {
+            element_content_handlers: vec![element!("p", |el| {
+                el.remove();
+                Ok(())
+            })],
+            document_content_handlers: vec![end!(|end| {
+                end.append("<!-- end -->", ContentType::Html);
+                Ok(())
+            })],
+            ..Settings::default()
+        });
+
+        assert_eq!(patches, [patch(5..10, "<!-- end -->")]);
+    }
+}
@@ -0,0 +1,291 @@
+//! Differential testing of the lexer against html5ever.
+//!
+//! Tokenizes every file of the given corpus with both html5ever (with the tree builder
+//! feedback) and lol-html's lexer and reports the first divergence for each file.
+//!
+//! Usage: `./scripts/differential.sh [--chunk-size=SIZE] PATH...`, where `PATH` is either
+//! an HTML file or a directory that is traversed recursively.
+
+#[macro_use]
+extern crate serde_derive;
+
+#[allow(dead_code, unused_macros)]
+#[macro_use]
+mod harness;
+
+use self::harness::suites::html5lib_tests::feedback_tests::expected_tokens;
+use self::harness::suites::html5lib_tests::{TestToken, TestTokenList};
+use encoding_rs::UTF_8;
+use lol_html::errors::RewritingError;
+use lol_html::html_content::DocumentEnd;
+use lol_html::{
+    LocalName, MemoryLimiter, Namespace, StartTagHandlingResult, Token, TokenCaptureFlags,
+    TransformController, TransformStream, TransformStreamSettings,
+};
+use std::fmt::{self, Display, Formatter};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::exit;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum DivergenceKind {
+    TokenBoundaries,
+    TextSplits,
+    AttributeSets,
+    TokenFields,
+}
+
+impl DivergenceKind {
+    const ALL: [DivergenceKind; 4] = [
+        DivergenceKind::TokenBoundaries,
+        DivergenceKind::TextSplits,
+        DivergenceKind::AttributeSets,
+        DivergenceKind::TokenFields,
+    ];
+}
+
+impl Display for DivergenceKind {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str(match self {
+            DivergenceKind::TokenBoundaries => "token boundaries",
+            DivergenceKind::TextSplits => "text splits",
+            DivergenceKind::AttributeSets => "attribute sets",
+            DivergenceKind::TokenFields => "token fields",
+        })
+    }
+}
+
+struct Divergence {
+    kind: DivergenceKind,
+    token_idx: usize,
+    preceding: Option<TestToken>,
+    actual: Option<TestToken>,
+    expected: Option<TestToken>,
+}
+
+impl Display for Divergence {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        writeln!(f, "{} diverge at token #{}", self.kind, self.token_idx)?;
+        writeln!(f, "    preceding: {:?}", self.preceding)?;
+        writeln!(f, "    lol-html:  {:?}", self.actual)?;
+        write!(f, "    html5ever: {:?}", self.expected)?;
+
+        if let (
+            Some(TestToken::StartTag {
+                attributes: actual, ..
+            }),
+            Some(TestToken::StartTag {
+                attributes: expected,
+                ..
+            }),
+        ) = (&self.actual, &self.expected)
+        {
+            for (name, value) in actual {
+                match expected.get(name) {
+                    Some(v) if v == value => (),
+                    Some(v) => write!(f, "\n    attribute `{}`: {:?} vs {:?}", name, value, v)?,
+                    None => write!(f, "\n    extra attribute `{}`", name)?,
+                }
+            }
+
+            for name in expected.keys().filter(|n| !actual.contains_key(*n)) {
+                write!(f, "\n    missing attribute `{}`", name)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn classify(actual: &TestToken, expected: &TestToken) -> DivergenceKind {
+    match (actual, expected) {
+        (TestToken::Text(_), TestToken::Text(_)) => DivergenceKind::TextSplits,
+        (
+            TestToken::StartTag {
+                name: actual_name,
+                self_closing: actual_self_closing,
+                ..
+            },
+            TestToken::StartTag {
+                name: expected_name,
+                self_closing: expected_self_closing,
+                ..
+            },
+        ) if actual_name == expected_name && actual_self_closing == expected_self_closing => {
+            DivergenceKind::AttributeSets
+        }
+        (TestToken::StartTag { .. }, TestToken::StartTag { .. })
+        | (TestToken::EndTag { .. }, TestToken::EndTag { .. })
+        | (TestToken::Comment(_), TestToken::Comment(_))
+        | (TestToken::Doctype { .. }, TestToken::Doctype { .. }) => DivergenceKind::TokenFields,
+        _ => DivergenceKind::TokenBoundaries,
+    }
+}
+
+// NOTE: the token streams get out of sync after the first divergence, so
+// only the first one is reported.
+fn find_divergence(actual: &[TestToken], expected: &[TestToken]) -> Option<Divergence> {
+    let len = actual.len().max(expected.len());
+
+    (0..len).find_map(|i| {
+        let (a, e) = (actual.get(i), expected.get(i));
+
+        let kind = match (a, e) {
+            (Some(a), Some(e)) if a == e => return None,
+            (Some(a), Some(e)) => classify(a, e),
+            _ => DivergenceKind::TokenBoundaries,
+        };
+
+        Some(Divergence {
+            kind,
+            token_idx: i,
+            preceding: i.checked_sub(1).and_then(|i| expected.get(i)).cloned(),
+            actual: a.cloned(),
+            expected: e.cloned(),
+        })
+    })
+}
+
+struct LexerTokensController<'t> {
+    tokens: &'t mut TestTokenList,
+}
+
+impl TransformController for LexerTokensController<'_> {
+    fn initial_capture_flags(&self) -> TokenCaptureFlags {
+        TokenCaptureFlags::all()
+    }
+
+    fn handle_start_tag(&mut self, _: LocalName, _: Namespace) -> StartTagHandlingResult<Self> {
+        Ok(TokenCaptureFlags::all())
+    }
+
+    fn handle_end_tag(&mut self, _: LocalName) -> TokenCaptureFlags {
+        TokenCaptureFlags::all()
+    }
+
+    fn handle_token(&mut self, token: &mut Token) -> Result<(), RewritingError> {
+        self.tokens.push(token);
+
+        Ok(())
+    }
+
+    fn handle_end(&mut self, _: &mut DocumentEnd) -> Result<(), RewritingError> {
+        Ok(())
+    }
+
+    fn should_emit_content(&self) -> bool {
+        false
+    }
+}
+
+fn lexer_tokens(input: &str, chunk_size: usize) -> Result<Vec<TestToken>, RewritingError> {
+    let mut tokens = TestTokenList::default();
+
+    {
+        let mut transform_stream = TransformStream::new(TransformStreamSettings {
+            transform_controller: LexerTokensController {
+                tokens: &mut tokens,
+            },
+            output_sink: |_: &[u8]| {},
+            preallocated_parsing_buffer_size: 0,
+            parsing_buffer_compaction_watermark: 0,
+            parsing_buffer_shrink_watermark: 0,
+            memory_limiter: MemoryLimiter::new_shared(usize::MAX),
+            encoding: UTF_8,
+            strict: true,
+            buffer_script_and_style_text: false,
+        });
+
+        for chunk in input.as_bytes().chunks(chunk_size.max(1)) {
+            transform_stream.write(chunk)?;
+        }
+
+        transform_stream.end()?;
+    }
+
+    Ok(tokens.into())
+}
+
+fn collect_files(path: &Path, files: &mut Vec<PathBuf>) {
+    if path.is_dir() {
+        let mut entries: Vec<_> = fs::read_dir(path)
+            .unwrap()
+            .map(|e| e.unwrap().path())
+            .collect();
+
+        entries.sort();
+
+        for entry in entries {
+            collect_files(&entry, files);
+        }
+    } else {
+        files.push(path.to_owned());
+    }
+}
+
+fn main() {
+    let mut chunk_size = None;
+    let mut files = Vec::default();
+
+    for arg in std::env::args().skip(1) {
+        if let Some(size) = arg.strip_prefix("--chunk-size=") {
+            chunk_size = Some(size.parse().expect("Chunk size should be a number"));
+        } else {
+            collect_files(Path::new(&arg), &mut files);
+        }
+    }
+
+    if files.is_empty() {
+        println!("Usage: ./scripts/differential.sh [--chunk-size=SIZE] PATH...");
+        return;
+    }
+
+    let mut counts = [0; 4];
+    let mut bailouts = 0;
+    let mut skipped = 0;
+
+    for file in &files {
+        let input = match String::from_utf8(fs::read(file).unwrap()) {
+            Ok(input) => input,
+            Err(_) => {
+                println!("{}: skipped, not a UTF-8 file", file.display());
+                skipped += 1;
+                continue;
+            }
+        };
+
+        let expected = expected_tokens::get(&input);
+
+        match lexer_tokens(&input, chunk_size.unwrap_or_else(|| input.len())) {
+            Ok(actual) => {
+                if let Some(divergence) = find_divergence(&actual, &expected) {
+                    println!("{}: {}", file.display(), divergence);
+
+                    counts[divergence.kind as usize] += 1;
+                }
+            }
+            Err(e) => {
+                println!("{}: bailout, {}", file.display(), e);
+                bailouts += 1;
+            }
+        }
+    }
+
+    let divergent: usize = counts.iter().sum();
+
+    println!(
+        "\nChecked {} files: {} divergent, {} bailouts, {} skipped.",
+        files.len(),
+        divergent,
+        bailouts,
+        skipped
+    );
+
+    for kind in DivergenceKind::ALL.iter() {
+        println!("    {}: {}", kind, counts[*kind as usize]);
+    }
+
+    if divergent > 0 {
+        exit(1);
+    }
+}
@@ -105,9 +105,12 @@ pub fn parse(
             transform_controller,
             output_sink: |chunk: &[u8]| output.push(chunk),
             preallocated_parsing_buffer_size: 0,
+            parsing_buffer_compaction_watermark: 0,
+            parsing_buffer_shrink_watermark: 0,
             memory_limiter,
             encoding,
-            strict: true
+            strict: true,
+            buffer_script_and_style_text: false
         }
     );
 
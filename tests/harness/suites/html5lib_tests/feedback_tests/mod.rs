@@ -1,4 +1,4 @@
-mod expected_tokens;
+pub mod expected_tokens;
 
 use super::super::{for_each_test_file, get_test_file_reader};
 use super::{default_initial_states, Bailout, TestCase};
@@ -1,5 +1,5 @@
 mod decoder;
-mod feedback_tests;
+pub mod feedback_tests;
 mod test_token;
 mod unescape;
 
@@ -102,9 +102,12 @@ fn main() {
         transform_controller: TraceTransformController::new(tag_hint_mode),
         output_sink: |_: &[u8]| {},
         preallocated_parsing_buffer_size: 0,
+        parsing_buffer_compaction_watermark: 0,
+        parsing_buffer_shrink_watermark: 0,
         memory_limiter: MemoryLimiter::new_shared(2048),
         encoding: UTF_8,
         strict: true,
+        buffer_script_and_style_text: false,
     });
 
     let parser = transform_stream.parser();
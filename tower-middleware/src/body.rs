@@ -0,0 +1,307 @@
+use bytes::{Buf, Bytes};
+use http::HeaderMap;
+use http_body::{Body, SizeHint};
+use lol_html::errors::{EncodingError, RewritingError};
+use lol_html::{HtmlRewriter, Settings};
+use pin_project_lite::pin_project;
+use std::fmt::{self, Display};
+use std::mem;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// An error that occurs when a [`RewritingBody`] is polled.
+///
+/// [`RewritingBody`]: struct.RewritingBody.html
+#[derive(Debug)]
+pub enum RewritingBodyError<E> {
+    /// An error of the wrapped body.
+    Body(E),
+
+    /// An error that occurred during the rewriting.
+    Rewriting(RewritingError),
+
+    /// The body exceeds the maximum size of a rewritten body.
+    TooLarge,
+
+    /// The `charset` of the response is not supported by the rewriter.
+    Encoding(EncodingError),
+}
+
+impl<E: Display> Display for RewritingBodyError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RewritingBodyError::Body(err) => err.fmt(f),
+            RewritingBodyError::Rewriting(err) => err.fmt(f),
+            RewritingBodyError::TooLarge => {
+                f.write_str("The body exceeds the maximum size of a rewritten body.")
+            }
+            RewritingBodyError::Encoding(err) => err.fmt(f),
+        }
+    }
+}
+
+impl<E: std::error::Error + 'static> std::error::Error for RewritingBodyError<E> {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            RewritingBodyError::Body(err) => Some(err),
+            RewritingBodyError::Rewriting(err) => Some(err),
+            RewritingBodyError::TooLarge => None,
+            RewritingBodyError::Encoding(err) => Some(err),
+        }
+    }
+}
+
+pub(crate) struct Rewrite<F> {
+    pub settings_factory: F,
+    pub charset: Option<String>,
+    pub sniff: bool,
+    pub max_body_size: usize,
+    pub pass_through_unrewritable: bool,
+}
+
+// NOTE: whitespace and the UTF-8 BOM are skipped before the sniffed patterns.
+const HTML_PATTERNS: &[&[u8]] = &[
+    b"<!doctype html",
+    b"<html",
+    b"<head",
+    b"<script",
+    b"<iframe",
+    b"<h1",
+    b"<div",
+    b"<font",
+    b"<table",
+    b"<a",
+    b"<style",
+    b"<title",
+    b"<b",
+    b"<body",
+    b"<br",
+    b"<p",
+];
+
+/// Determines if the content is HTML, following the [MIME sniffing] rules.
+///
+/// [MIME sniffing]: https://mimesniff.spec.whatwg.org/#identifying-a-resource-with-an-unknown-mime-type
+fn sniff_html(content: &[u8]) -> bool {
+    let content = content.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(content);
+    let start = content
+        .iter()
+        .position(|&b| !matches!(b, b'\t' | b'\n' | b'\x0C' | b'\r' | b' '))
+        .unwrap_or(content.len());
+
+    let content = &content[start..];
+
+    if content.starts_with(b"<!--") {
+        return true;
+    }
+
+    HTML_PATTERNS.iter().any(|pattern| {
+        content.len() > pattern.len()
+            && content[..pattern.len()].eq_ignore_ascii_case(pattern)
+            && matches!(content[pattern.len()], b' ' | b'>')
+    })
+}
+
+impl<F> Rewrite<F>
+where
+    F: Fn() -> Settings<'static, 'static>,
+{
+    fn rewrite<E>(&self, input: Vec<u8>) -> Result<Bytes, RewritingBodyError<E>> {
+        if self.sniff && !sniff_html(&input) {
+            return Ok(input.into());
+        }
+
+        let mut settings = (self.settings_factory)();
+        let mut output = Vec::with_capacity(input.len());
+
+        if let Some(ref charset) = self.charset {
+            settings.encoding = charset;
+        }
+
+        let mut rewriter =
+            match HtmlRewriter::try_new(settings, |c: &[u8]| output.extend_from_slice(c)) {
+                Ok(rewriter) => rewriter,
+                Err(_) if self.pass_through_unrewritable => return Ok(input.into()),
+                Err(err) => return Err(RewritingBodyError::Encoding(err)),
+            };
+
+        rewriter
+            .write(&input)
+            .map_err(RewritingBodyError::Rewriting)?;
+
+        rewriter.end().map_err(RewritingBodyError::Rewriting)?;
+
+        Ok(output.into())
+    }
+}
+
+enum State<F> {
+    Buffering {
+        buffer: Vec<u8>,
+        rewrite: Rewrite<F>,
+    },
+    PassThrough {
+        prefix: Option<Bytes>,
+    },
+    Rewritten {
+        output: Option<Bytes>,
+    },
+}
+
+pin_project! {
+    /// A response body that is rewritten by the [`HtmlRewrite`] middleware.
+    ///
+    /// The rewriter can't be sent between threads, so the body is buffered and rewritten once
+    /// it's complete. See the [crate documentation] for the bodies that can't be rewritten.
+    ///
+    /// [crate documentation]: index.html
+    ///
+    /// [`HtmlRewrite`]: struct.HtmlRewrite.html
+    pub struct RewritingBody<B, F> {
+        #[pin]
+        inner: B,
+        state: State<F>,
+    }
+}
+
+impl<B, F> RewritingBody<B, F> {
+    pub(crate) fn rewritten(inner: B, rewrite: Rewrite<F>) -> Self {
+        RewritingBody {
+            inner,
+            state: State::Buffering {
+                buffer: Vec::new(),
+                rewrite,
+            },
+        }
+    }
+
+    pub(crate) fn passed_through(inner: B) -> Self {
+        RewritingBody {
+            inner,
+            state: State::PassThrough { prefix: None },
+        }
+    }
+}
+
+impl<B, F> Body for RewritingBody<B, F>
+where
+    B: Body,
+    F: Fn() -> Settings<'static, 'static>,
+{
+    type Data = Bytes;
+    type Error = RewritingBodyError<B::Error>;
+
+    fn poll_data(
+        self: Pin<&mut Self>,
+        cx: &mut Context,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        let mut this = self.project();
+
+        loop {
+            match this.state {
+                State::Buffering { buffer, rewrite } => match this.inner.as_mut().poll_data(cx) {
+                    Poll::Ready(Some(Ok(mut data))) => {
+                        while data.has_remaining() {
+                            let chunk = data.chunk();
+                            let len = chunk.len();
+
+                            buffer.extend_from_slice(chunk);
+                            data.advance(len);
+                        }
+
+                        if buffer.len() > rewrite.max_body_size {
+                            if !rewrite.pass_through_unrewritable {
+                                *this.state = State::Rewritten { output: None };
+
+                                return Poll::Ready(Some(Err(RewritingBodyError::TooLarge)));
+                            }
+
+                            let prefix = mem::take(buffer).into();
+
+                            *this.state = State::PassThrough {
+                                prefix: Some(prefix),
+                            };
+                        }
+                    }
+                    Poll::Ready(Some(Err(err))) => {
+                        return Poll::Ready(Some(Err(RewritingBodyError::Body(err))))
+                    }
+                    Poll::Ready(None) => {
+                        let result = rewrite.rewrite(mem::take(buffer));
+
+                        *this.state = State::Rewritten {
+                            output: result.as_ref().ok().cloned(),
+                        };
+
+                        if let Err(err) = result {
+                            return Poll::Ready(Some(Err(err)));
+                        }
+                    }
+                    Poll::Pending => return Poll::Pending,
+                },
+                State::PassThrough { prefix } => {
+                    if let Some(prefix) = prefix.take() {
+                        return Poll::Ready(Some(Ok(prefix)));
+                    }
+
+                    return match this.inner.as_mut().poll_data(cx) {
+                        Poll::Ready(Some(Ok(mut data))) => {
+                            Poll::Ready(Some(Ok(data.copy_to_bytes(data.remaining()))))
+                        }
+                        Poll::Ready(Some(Err(err))) => {
+                            Poll::Ready(Some(Err(RewritingBodyError::Body(err))))
+                        }
+                        Poll::Ready(None) => Poll::Ready(None),
+                        Poll::Pending => Poll::Pending,
+                    };
+                }
+                State::Rewritten { output } => {
+                    return Poll::Ready(output.take().filter(|o| !o.is_empty()).map(Ok))
+                }
+            }
+        }
+    }
+
+    fn poll_trailers(
+        self: Pin<&mut Self>,
+        cx: &mut Context,
+    ) -> Poll<Result<Option<HeaderMap>, Self::Error>> {
+        self.project()
+            .inner
+            .poll_trailers(cx)
+            .map_err(RewritingBodyError::Body)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        match self.state {
+            State::Buffering { .. } => false,
+            State::PassThrough { ref prefix } => prefix.is_none() && self.inner.is_end_stream(),
+            State::Rewritten { ref output } => output.is_none(),
+        }
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        match self.state {
+            State::PassThrough { prefix: None } => self.inner.size_hint(),
+            _ => SizeHint::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn html_sniffing() {
+        assert!(sniff_html(b"<!DOCTYPE html><p>Hi</p>"));
+        assert!(sniff_html(b"\xEF\xBB\xBF \n<HTML>"));
+        assert!(sniff_html(b"<!-- comment -->"));
+        assert!(sniff_html(b"<p class=\"foo\">"));
+
+        assert!(!sniff_html(b"<pre>"));
+        assert!(!sniff_html(b"<p"));
+        assert!(!sniff_html(b"{\"foo\": \"<p>\"}"));
+        assert!(!sniff_html(b""));
+    }
+}
@@ -0,0 +1,507 @@
+//! [Tower] middleware that rewrites HTML responses with [LOL HTML].
+//!
+//! The [`HtmlRewriteLayer`] wraps a service and rewrites the bodies of its `text/html` responses
+//! using the [`Settings`] produced by a factory for each response. Other responses are passed
+//! through untouched.
+//!
+//! A response is rewritten if:
+//! * its `Content-Type` is `text/html`, or it has no `Content-Type` and its body is [sniffed]
+//!   as HTML;
+//! * it's not compressed, i.e. it has no `Content-Encoding` other than `identity`. Place the layer
+//!   inside the compression layer so it sees the uncompressed responses.
+//!
+//! The `Content-Length` header is removed from the rewritten responses. The `charset` parameter
+//! of the `Content-Type` is used as the encoding of the document.
+//!
+//! Bodies of the rewritten responses are buffered, and a body fails with
+//! a [`RewritingBodyError`] if:
+//! * it exceeds the [maximum size];
+//! * its `charset` is not supported by the rewriter;
+//! * the rewriting fails, e.g. a content handler returns an error.
+//!
+//! Bodies that can't be rewritten because of their size or encoding can be passed through as is
+//! instead, if enabled with [`pass_through_unrewritable`].
+//!
+//! # Example
+//! ```
+//! use lol_html::{element, Settings};
+//! use lol_html_tower::HtmlRewriteLayer;
+//!
+//! let layer = HtmlRewriteLayer::new(|| Settings {
+//!     element_content_handlers: vec![element!("a[href]", |el| {
+//!         el.set_attribute("rel", "nofollow")?;
+//!         Ok(())
+//!     })],
+//!     ..Settings::default()
+//! });
+//! # let _ = layer;
+//! ```
+//!
+//! [Tower]: https://github.com/tower-rs/tower
+//! [LOL HTML]: https://github.com/cloudflare/lol-html
+//! [`HtmlRewriteLayer`]: struct.HtmlRewriteLayer.html
+//! [`Settings`]: ../lol_html/struct.Settings.html
+//! [`RewritingBodyError`]: enum.RewritingBodyError.html
+//! [maximum size]: struct.HtmlRewriteLayer.html#method.max_body_size
+//! [`pass_through_unrewritable`]: struct.HtmlRewriteLayer.html#method.pass_through_unrewritable
+//! [sniffed]: https://mimesniff.spec.whatwg.org/#identifying-a-resource-with-an-unknown-mime-type
+
+mod body;
+
+use self::body::Rewrite;
+use http::header::{HeaderMap, CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_TYPE};
+use http::{Request, Response, StatusCode};
+use http_body::Body;
+use lol_html::Settings;
+use pin_project_lite::pin_project;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tower_layer::Layer;
+use tower_service::Service;
+
+pub use self::body::{RewritingBody, RewritingBodyError};
+
+/// The default maximum size of a response body that is rewritten.
+pub const DEFAULT_MAX_BODY_SIZE: usize = 4 * 1024 * 1024;
+
+/// A [`Layer`] that applies the [`HtmlRewrite`] middleware.
+///
+/// [`Layer`]: https://docs.rs/tower-layer/0.3/tower_layer/trait.Layer.html
+/// [`HtmlRewrite`]: struct.HtmlRewrite.html
+#[derive(Clone, Debug)]
+pub struct HtmlRewriteLayer<F> {
+    settings_factory: F,
+    max_body_size: usize,
+    pass_through_unrewritable: bool,
+}
+
+impl<F> HtmlRewriteLayer<F>
+where
+    F: Fn() -> Settings<'static, 'static> + Clone,
+{
+    /// Creates a new layer that rewrites HTML responses using the settings produced
+    /// by `settings_factory`.
+    #[inline]
+    pub fn new(settings_factory: F) -> Self {
+        HtmlRewriteLayer {
+            settings_factory,
+            max_body_size: DEFAULT_MAX_BODY_SIZE,
+            pass_through_unrewritable: false,
+        }
+    }
+
+    /// Sets the maximum size of a response body that is rewritten.
+    ///
+    /// Bodies are buffered before they are rewritten. Bodies that exceed the maximum size fail
+    /// with [`RewritingBodyError::TooLarge`], unless [`pass_through_unrewritable`] is enabled.
+    ///
+    /// ### Default
+    ///
+    /// [`DEFAULT_MAX_BODY_SIZE`].
+    ///
+    /// [`DEFAULT_MAX_BODY_SIZE`]: constant.DEFAULT_MAX_BODY_SIZE.html
+    /// [`RewritingBodyError::TooLarge`]: enum.RewritingBodyError.html#variant.TooLarge
+    /// [`pass_through_unrewritable`]: #method.pass_through_unrewritable
+    #[inline]
+    pub fn max_body_size(mut self, max_body_size: usize) -> Self {
+        self.max_body_size = max_body_size;
+        self
+    }
+
+    /// If set to `true`, bodies that exceed the [maximum size] and bodies in encodings that are
+    /// not supported by the rewriter are passed through as is instead of failing with
+    /// a [`RewritingBodyError`].
+    ///
+    /// Note that such responses reach the client without the modifications made by the content
+    /// handlers, so this shouldn't be enabled if the handlers are relied on, e.g. to sanitize
+    /// the content.
+    ///
+    /// ### Default
+    ///
+    /// `false`.
+    ///
+    /// [maximum size]: #method.max_body_size
+    /// [`RewritingBodyError`]: enum.RewritingBodyError.html
+    #[inline]
+    pub fn pass_through_unrewritable(mut self, pass_through_unrewritable: bool) -> Self {
+        self.pass_through_unrewritable = pass_through_unrewritable;
+        self
+    }
+}
+
+impl<S, F: Clone> Layer<S> for HtmlRewriteLayer<F> {
+    type Service = HtmlRewrite<S, F>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        HtmlRewrite {
+            inner,
+            settings_factory: self.settings_factory.clone(),
+            max_body_size: self.max_body_size,
+            pass_through_unrewritable: self.pass_through_unrewritable,
+        }
+    }
+}
+
+/// Middleware that rewrites the HTML responses of the inner service.
+///
+/// See the [crate documentation] for the rules that decide which responses are rewritten.
+///
+/// [crate documentation]: index.html
+#[derive(Clone, Debug)]
+pub struct HtmlRewrite<S, F> {
+    inner: S,
+    settings_factory: F,
+    max_body_size: usize,
+    pass_through_unrewritable: bool,
+}
+
+impl<S, F> HtmlRewrite<S, F>
+where
+    F: Fn() -> Settings<'static, 'static> + Clone,
+{
+    /// Wraps the `inner` service, rewriting its HTML responses using the settings produced
+    /// by `settings_factory`.
+    #[inline]
+    pub fn new(inner: S, settings_factory: F) -> Self {
+        HtmlRewriteLayer::new(settings_factory).layer(inner)
+    }
+
+    /// Returns a reference to the inner service.
+    #[inline]
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Consumes the middleware, returning the inner service.
+    #[inline]
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S, F, ReqBody, ResBody> Service<Request<ReqBody>> for HtmlRewrite<S, F>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    ResBody: Body,
+    F: Fn() -> Settings<'static, 'static> + Clone,
+{
+    type Response = Response<RewritingBody<ResBody, F>>;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future, F>;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        ResponseFuture {
+            inner: self.inner.call(req),
+            settings_factory: Some(self.settings_factory.clone()),
+            max_body_size: self.max_body_size,
+            pass_through_unrewritable: self.pass_through_unrewritable,
+        }
+    }
+}
+
+pin_project! {
+    /// The response future of the [`HtmlRewrite`] middleware.
+    ///
+    /// [`HtmlRewrite`]: struct.HtmlRewrite.html
+    pub struct ResponseFuture<Fut, F> {
+        #[pin]
+        inner: Fut,
+        settings_factory: Option<F>,
+        max_body_size: usize,
+        pass_through_unrewritable: bool,
+    }
+}
+
+impl<Fut, F, B, E> Future for ResponseFuture<Fut, F>
+where
+    Fut: Future<Output = Result<Response<B>, E>>,
+{
+    type Output = Result<Response<RewritingBody<B, F>>, E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let this = self.project();
+
+        let response = match this.inner.poll(cx) {
+            Poll::Ready(response) => response?,
+            Poll::Pending => return Poll::Pending,
+        };
+
+        let settings_factory = this
+            .settings_factory
+            .take()
+            .expect("Response future polled after completion");
+
+        let (mut parts, body) = response.into_parts();
+
+        let body = match rewrite_mode(parts.status, &parts.headers) {
+            Some((sniff, charset)) => {
+                parts.headers.remove(CONTENT_LENGTH);
+
+                RewritingBody::rewritten(
+                    body,
+                    Rewrite {
+                        settings_factory,
+                        charset,
+                        sniff,
+                        max_body_size: *this.max_body_size,
+                        pass_through_unrewritable: *this.pass_through_unrewritable,
+                    },
+                )
+            }
+            None => RewritingBody::passed_through(body),
+        };
+
+        Poll::Ready(Ok(Response::from_parts(parts, body)))
+    }
+}
+
+/// Decides if the response should be rewritten. Returns whether the body needs to be sniffed
+/// and the charset of the body.
+fn rewrite_mode(status: StatusCode, headers: &HeaderMap) -> Option<(bool, Option<String>)> {
+    if status.is_informational()
+        || status == StatusCode::NO_CONTENT
+        || status == StatusCode::NOT_MODIFIED
+    {
+        return None;
+    }
+
+    if let Some(encoding) = headers.get(CONTENT_ENCODING) {
+        if !encoding.as_bytes().eq_ignore_ascii_case(b"identity") {
+            return None;
+        }
+    }
+
+    let content_type = match headers.get(CONTENT_TYPE) {
+        Some(content_type) => content_type.to_str().ok()?,
+        None => return Some((true, None)),
+    };
+
+    let mut params = content_type.split(';');
+    let essence = params.next().unwrap_or_default().trim();
+
+    if !essence.eq_ignore_ascii_case("text/html") {
+        return None;
+    }
+
+    let charset = params.find_map(|param| {
+        let (name, value) = param.split_once('=')?;
+
+        if name.trim().eq_ignore_ascii_case("charset") {
+            Some(value.trim().trim_matches('"').to_owned())
+        } else {
+            None
+        }
+    });
+
+    Some((false, charset))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::body::to_bytes;
+    use hyper::Body as HyperBody;
+    use lol_html::element;
+    use lol_html::errors::EncodingError;
+    use std::convert::Infallible;
+    use tower::{service_fn, ServiceExt};
+
+    fn settings() -> Settings<'static, 'static> {
+        Settings {
+            element_content_handlers: vec![element!("p", |el| {
+                el.set_attribute("class", "rewritten")?;
+                Ok(())
+            })],
+            ..Settings::default()
+        }
+    }
+
+    type SettingsFactory = fn() -> Settings<'static, 'static>;
+
+    async fn call(
+        layer: HtmlRewriteLayer<SettingsFactory>,
+        response: Response<HyperBody>,
+    ) -> Response<RewritingBody<HyperBody, SettingsFactory>> {
+        let mut response = Some(response);
+
+        let service = layer.layer(service_fn(move |_: Request<()>| {
+            let response = response.take().unwrap();
+
+            async move { Ok::<_, Infallible>(response) }
+        }));
+
+        service.oneshot(Request::new(())).await.unwrap()
+    }
+
+    async fn respond(
+        layer: HtmlRewriteLayer<SettingsFactory>,
+        response: Response<HyperBody>,
+    ) -> (Response<()>, String) {
+        let (parts, body) = call(layer, response).await.into_parts();
+        let body = to_bytes(body).await.unwrap();
+
+        (
+            Response::from_parts(parts, ()),
+            String::from_utf8(body.to_vec()).unwrap(),
+        )
+    }
+
+    fn html_response(body: &'static str) -> Response<HyperBody> {
+        Response::builder()
+            .header(CONTENT_TYPE, "text/html; charset=utf-8")
+            .header(CONTENT_LENGTH, body.len())
+            .body(HyperBody::from(body))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn rewrites_html() {
+        let layer = HtmlRewriteLayer::new(settings as fn() -> _);
+        let (response, body) = respond(layer, html_response("<p>Hello</p>")).await;
+
+        assert_eq!(body, r#"<p class="rewritten">Hello</p>"#);
+        assert!(response.headers().get(CONTENT_LENGTH).is_none());
+    }
+
+    #[tokio::test]
+    async fn rewrites_streamed_html() {
+        let layer = HtmlRewriteLayer::new(settings as fn() -> _);
+        let (mut sender, body) = HyperBody::channel();
+
+        tokio::spawn(async move {
+            sender.send_data("<p>Hel".into()).await.unwrap();
+            sender.send_data("lo</p>".into()).await.unwrap();
+        });
+
+        let response = Response::builder()
+            .header(CONTENT_TYPE, "text/html")
+            .body(body)
+            .unwrap();
+
+        let (_, body) = respond(layer, response).await;
+
+        assert_eq!(body, r#"<p class="rewritten">Hello</p>"#);
+    }
+
+    #[tokio::test]
+    async fn passes_through_other_content() {
+        let layer = HtmlRewriteLayer::new(settings as fn() -> _);
+
+        let response = Response::builder()
+            .header(CONTENT_TYPE, "text/plain")
+            .header(CONTENT_LENGTH, 12)
+            .body(HyperBody::from("<p>Hello</p>"))
+            .unwrap();
+
+        let (response, body) = respond(layer, response).await;
+
+        assert_eq!(body, "<p>Hello</p>");
+        assert_eq!(response.headers()[CONTENT_LENGTH], "12");
+    }
+
+    #[tokio::test]
+    async fn passes_through_compressed_content() {
+        let layer = HtmlRewriteLayer::new(settings as fn() -> _);
+        let mut response = html_response("<p>Hello</p>");
+
+        response
+            .headers_mut()
+            .insert(CONTENT_ENCODING, "gzip".parse().unwrap());
+
+        let (response, body) = respond(layer, response).await;
+
+        assert_eq!(body, "<p>Hello</p>");
+        assert_eq!(response.headers()[CONTENT_LENGTH], "12");
+    }
+
+    #[tokio::test]
+    async fn sniffs_content_without_type() {
+        let layer = HtmlRewriteLayer::new(settings as fn() -> _);
+
+        let (_, body) = respond(layer.clone(), Response::new("<p>Hello</p>".into())).await;
+
+        assert_eq!(body, r#"<p class="rewritten">Hello</p>"#);
+
+        let (_, body) = respond(layer, Response::new("Hello <p>".into())).await;
+
+        assert_eq!(body, "Hello <p>");
+    }
+
+    async fn respond_with_error(
+        layer: HtmlRewriteLayer<SettingsFactory>,
+        response: Response<HyperBody>,
+    ) -> RewritingBodyError<hyper::Error> {
+        to_bytes(call(layer, response).await.into_body())
+            .await
+            .unwrap_err()
+    }
+
+    fn utf16_response() -> Response<HyperBody> {
+        Response::builder()
+            .header(CONTENT_TYPE, "text/html; charset=utf-16")
+            .body(HyperBody::from("<p>Hello</p>"))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn large_bodies() {
+        let layer = HtmlRewriteLayer::new(settings as fn() -> _).max_body_size(4);
+        let err = respond_with_error(layer.clone(), html_response("<p>Hello</p>")).await;
+
+        assert!(matches!(err, RewritingBodyError::TooLarge));
+
+        let layer = layer.pass_through_unrewritable(true);
+        let (_, body) = respond(layer, html_response("<p>Hello</p>")).await;
+
+        assert_eq!(body, "<p>Hello</p>");
+    }
+
+    #[tokio::test]
+    async fn unsupported_encodings() {
+        let layer = HtmlRewriteLayer::new(settings as fn() -> _);
+        let err = respond_with_error(layer.clone(), utf16_response()).await;
+
+        assert!(matches!(
+            err,
+            RewritingBodyError::Encoding(EncodingError::NonAsciiCompatibleEncoding)
+        ));
+
+        let layer = layer.pass_through_unrewritable(true);
+        let (_, body) = respond(layer, utf16_response()).await;
+
+        assert_eq!(body, "<p>Hello</p>");
+    }
+
+    #[test]
+    fn rewrite_modes() {
+        let mode = |content_type: Option<&str>| {
+            let mut headers = HeaderMap::new();
+
+            if let Some(content_type) = content_type {
+                headers.insert(CONTENT_TYPE, content_type.parse().unwrap());
+            }
+
+            rewrite_mode(StatusCode::OK, &headers)
+        };
+
+        assert_eq!(mode(None), Some((true, None)));
+        assert_eq!(mode(Some("TEXT/HTML")), Some((false, None)));
+        assert_eq!(
+            mode(Some("text/html; charset=\"ISO-8859-1\"")),
+            Some((false, Some("ISO-8859-1".into())))
+        );
+        assert_eq!(mode(Some("text/htmlx")), None);
+        assert_eq!(mode(Some("application/xhtml+xml")), None);
+
+        assert_eq!(
+            rewrite_mode(StatusCode::NOT_MODIFIED, &HeaderMap::new()),
+            None
+        );
+    }
+}